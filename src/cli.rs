@@ -0,0 +1,245 @@
+// 命令行管理
+// 直接读写数据目录中的数据库与配置文件，便于在没有浏览器的服务器上通过 SSH 管理；
+// 运行中的实例对 API Key 的变更立即生效，新添加的账号在配置重载（SIGHUP）后生效
+use std::io::{BufRead, Write};
+
+use anti_proxy::modules::{self, api_keys, audit};
+use anti_proxy::proxy::handlers::manage;
+
+const USAGE: &str = "\
+Usage: anti-proxy [command]
+
+Commands:
+  serve                               Start the proxy server (default)
+  check-config [path]                 Check a config file without starting the server
+  keys list                           List API keys
+  keys create <name> [--expires-days N]
+                                      Create an API key and print it
+  keys revoke <id|name>               Delete an API key (restorable until purged)
+  accounts list                       List accounts
+  accounts add --oauth                Add an account by pasting the OAuth callback URL
+  accounts add --device               Add an account by entering a code on any device
+  usage --key <id|name>               Show usage of an API key";
+
+/// 执行命令行子命令；返回 `None` 表示启动服务，否则为进程退出码
+pub async fn run(args: &[String]) -> Option<i32> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        [] | ["serve"] => return None,
+        ["help" | "-h" | "--help"] => {
+            println!("{}", USAGE);
+            return Some(0);
+        }
+        ["check-config", rest @ ..] if rest.len() <= 1 => return Some(check_config(rest.first().copied())),
+        ["keys", "list"] => admin(list_keys()).await,
+        ["keys", "create", name, rest @ ..] => admin(create_key(name, rest)).await,
+        ["keys", "revoke", key] => admin(revoke_key(key)).await,
+        ["accounts", "list"] => admin(list_accounts()).await,
+        ["accounts", "add", "--oauth"] => admin(add_oauth_account()).await,
+        ["accounts", "add", "--device"] => admin(add_device_account()).await,
+        ["usage", "--key", key] => admin(key_usage(key)).await,
+        _ => {
+            eprintln!("{}", USAGE);
+            return Some(2);
+        }
+    };
+    match result {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            Some(1)
+        }
+    }
+}
+
+/// 初始化数据库与加密密钥后，以 `cli` 身份执行管理操作（记入审计日志）
+async fn admin<F: std::future::Future<Output = Result<(), String>>>(command: F) -> Result<(), String> {
+    let config = modules::config::load_web_config()?;
+    modules::storage::configure(&config.storage)?;
+    modules::account::init_db()?;
+    modules::secret_store::init(config.secret_master_key.as_deref())
+        .map_err(|e| format!("failed to initialize secret encryption: {}", e))?;
+    api_keys::init_db()?;
+    audit::init_db()?;
+    let _ = modules::quota::set_budget_timezone(&config.token_budget_timezone);
+    audit::with_actor("cli".to_string(), None, command).await
+}
+
+/// 检查配置文件并输出问题，返回进程退出码
+fn check_config(path: Option<&str>) -> i32 {
+    let problems = match path {
+        Some(path) => std::fs::read_to_string(path)
+            .map(|content| modules::config::check_config(&content))
+            .map_err(|e| format!("failed to read {}: {}", path, e)),
+        None => modules::config::check_web_config(),
+    };
+    match problems {
+        Ok(problems) if problems.is_empty() => {
+            println!("config OK");
+            0
+        }
+        Ok(problems) => {
+            for problem in &problems {
+                eprintln!("{}", problem);
+            }
+            eprintln!("{} problem(s) found", problems.len());
+            1
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            2
+        }
+    }
+}
+
+fn format_time(timestamp: Option<i64>) -> String {
+    timestamp
+        .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// 按 ID 或名称查找 API Key；名称重复时要求使用 ID
+fn resolve_key(key: &str) -> Result<api_keys::ApiKey, String> {
+    if let Some(found) = api_keys::get_api_key(key)? {
+        return Ok(found);
+    }
+    let mut matches = api_keys::find_by_name(key)?;
+    match matches.len() {
+        0 => Err(format!("API key not found: {}", key)),
+        1 => Ok(matches.remove(0)),
+        _ => Err(format!(
+            "{} API keys are named {}, use an ID instead: {}",
+            matches.len(),
+            key,
+            matches.iter().map(|k| k.id.as_str()).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+async fn list_keys() -> Result<(), String> {
+    let keys = api_keys::list_api_keys()?;
+    println!("{:<36}  {:<20}  {:<8}  {:>10}  {:>12}  LAST USED", "ID", "NAME", "STATUS", "REQUESTS", "TOKENS");
+    for key in keys {
+        let status = if key.is_expired() {
+            "expired"
+        } else if key.enabled {
+            "enabled"
+        } else {
+            "disabled"
+        };
+        println!(
+            "{:<36}  {:<20}  {:<8}  {:>10}  {:>12}  {}",
+            key.id,
+            key.name,
+            status,
+            key.total_requests,
+            key.total_input_tokens + key.total_output_tokens,
+            format_time(key.last_used_at)
+        );
+    }
+    Ok(())
+}
+
+async fn create_key(name: &str, options: &[&str]) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("name must not be empty".to_string());
+    }
+    let expires_at = match options {
+        [] => None,
+        ["--expires-days", days] => {
+            let days: i64 = days
+                .parse()
+                .ok()
+                .filter(|d| *d > 0)
+                .ok_or_else(|| format!("invalid --expires-days: {}", days))?;
+            Some(chrono::Utc::now().timestamp() + days * 86400)
+        }
+        _ => return Err(format!("unexpected arguments: {}", options.join(" "))),
+    };
+    let key = api_keys::create_api_key(name, expires_at)?;
+    audit::record("api_key.created", Some(&key.id), None, audit::snapshot(&api_keys::ApiKeyResponse::from(key.clone())));
+    println!("id:  {}", key.id);
+    println!("key: {}", key.key);
+    println!("The key is only shown once, store it now.");
+    Ok(())
+}
+
+async fn revoke_key(key: &str) -> Result<(), String> {
+    let existing = resolve_key(key)?;
+    api_keys::delete_api_key(&existing.id)?;
+    println!("revoked {} ({})", existing.name, existing.id);
+    let id = existing.id.clone();
+    audit::record("api_key.deleted", Some(&id), audit::snapshot(&api_keys::ApiKeyResponse::from(existing)), None);
+    Ok(())
+}
+
+async fn list_accounts() -> Result<(), String> {
+    let accounts = modules::account::list_accounts()?;
+    println!("{:<36}  {:<32}  STATUS", "ID", "EMAIL");
+    for account in accounts {
+        let status = if account.disabled {
+            "disabled"
+        } else if account.proxy_disabled {
+            "proxy disabled"
+        } else {
+            "active"
+        };
+        println!("{:<36}  {:<32}  {}", account.id, account.email, status);
+    }
+    Ok(())
+}
+
+/// 在任意设备的浏览器中完成授权，再把跳转后的地址（页面打不开也没关系）粘贴回终端
+async fn add_oauth_account() -> Result<(), String> {
+    let port = modules::config::load_web_config()?.port;
+    let redirect_uri = manage::oauth_redirect_uri(port);
+    println!("Open this URL in a browser and sign in:\n\n{}\n", modules::oauth::get_auth_url(&redirect_uri));
+    print!("Paste the URL the browser was redirected to (or the code): ");
+    std::io::stdout().flush().map_err(|e| e.to_string())?;
+
+    let mut input = String::new();
+    std::io::stdin().lock().read_line(&mut input).map_err(|e| e.to_string())?;
+    let query = manage::parse_oauth_callback_input(&input)?;
+    if let Some(error) = query.error {
+        return Err(format!("Authorization failed: {}", error));
+    }
+    let code = query
+        .code
+        .ok_or_else(|| "Unable to parse authorization code from callback URL".to_string())?;
+
+    let account = manage::add_oauth_account(&code, &redirect_uri).await?;
+    println!("added {} ({})", account.email, account.id);
+    println!("A running server picks up the account on config reload (SIGHUP or POST /api/config/reload).");
+    Ok(())
+}
+
+/// 设备授权：在任意设备上打开验证页面并输入短码，终端轮询直到授权完成
+async fn add_device_account() -> Result<(), String> {
+    let device = modules::oauth::request_device_code().await?;
+    println!("On any device, open {} and enter the code:\n\n    {}\n", device.verification_url, device.user_code);
+    println!("Waiting for authorization (expires in {} minutes)...", device.expires_in / 60);
+
+    let token = modules::oauth::poll_device_token(&device, || false).await?;
+    let account = manage::save_oauth_account(token).await?;
+    println!("added {} ({})", account.email, account.id);
+    println!("A running server picks up the account on config reload (SIGHUP or POST /api/config/reload).");
+    Ok(())
+}
+
+async fn key_usage(key: &str) -> Result<(), String> {
+    let key = resolve_key(key)?;
+    let today = api_keys::get_daily_tokens(&key.id, &modules::quota::current_budget_day())?;
+    println!("name:           {}", key.name);
+    println!("id:             {}", key.id);
+    println!("requests:       {} ({} ok, {} failed)", key.total_requests, key.success_count, key.error_count);
+    println!("input tokens:   {}", key.total_input_tokens);
+    println!("output tokens:  {}", key.total_output_tokens);
+    match key.daily_token_budget {
+        Some(budget) => println!("tokens today:   {} / {}", today, budget),
+        None => println!("tokens today:   {}", today),
+    }
+    println!("estimated cost: {:.4}", key.total_cost);
+    println!("last used:      {}", format_time(key.last_used_at));
+    Ok(())
+}
@@ -3,11 +3,50 @@ use std::sync::Arc;
 use anti_proxy::modules;
 use anti_proxy::proxy;
 
+mod cli;
+
 #[tokio::main]
 async fn main() -> Result<(), String> {
-    modules::logger::init_logger();
+    // 子命令（check-config、keys、accounts、usage）执行后直接退出，不启动服务
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args).await {
+        std::process::exit(code);
+    }
+
+    // 在读取配置、打开任何数据库之前应用上次暂存的备份恢复，结果在日志初始化后输出
+    let restored = modules::backup::apply_staged_restore();
 
-    let mut proxy_config = match modules::config::load_web_config() {
+    // 先读取配置以确定日志格式，加载失败的警告在日志初始化后输出
+    let loaded_config = modules::config::load_web_config();
+    let log_format = loaded_config.as_ref().map(|c| c.log_format).unwrap_or_default();
+    modules::logger::init_logger(log_format);
+    match restored {
+        Ok(Some(count)) => tracing::warn!("已从备份恢复 {} 个文件", count),
+        Ok(None) => {}
+        Err(e) => tracing::error!("应用备份恢复失败: {}", e),
+    }
+    // 配置有未知字段或无效取值时默认拒绝启动，避免拼写错误的配置被静默忽略
+    let problems = match &loaded_config {
+        Ok(_) => modules::config::check_web_config().unwrap_or_default(),
+        Err(err) => vec![err.clone()],
+    };
+    if !problems.is_empty() && !modules::config::allow_invalid_config() {
+        for problem in &problems {
+            tracing::error!("配置问题: {}", problem);
+        }
+        return Err(format!(
+            "config has {} problem(s); fix them or set {}=1 to start anyway",
+            problems.len(),
+            modules::config::ALLOW_INVALID_CONFIG_ENV
+        ));
+    }
+    if loaded_config.is_ok() {
+        for problem in &problems {
+            tracing::warn!("配置问题: {}", problem);
+        }
+    }
+
+    let mut proxy_config = match loaded_config {
         Ok(cfg) => cfg,
         Err(err) => {
             tracing::warn!("failed to load web config: {}. using defaults", err);
@@ -17,43 +56,56 @@ async fn main() -> Result<(), String> {
         }
     };
 
-    if let Ok(value) = std::env::var("ANTI_PROXY_ALLOW_LAN") {
-        let enabled = matches!(value.as_str(), "1" | "true" | "yes" | "on");
-        if enabled {
-            proxy_config.allow_lan_access = true;
-        }
-    }
+    modules::config::apply_env_overrides(&mut proxy_config);
 
-    if let Ok(value) = std::env::var("ANTI_PROXY_ENABLED") {
-        let enabled = matches!(value.as_str(), "1" | "true" | "yes" | "on");
-        if enabled {
-            proxy_config.enabled = true;
-        }
-    }
+    let bind_address = std::env::var("ANTI_PROXY_BIND")
+        .unwrap_or_else(|_| proxy_config.get_bind_address().to_string());
 
-    let bind_address = if let Ok(addr) = std::env::var("ANTI_PROXY_BIND") {
-        if addr != "127.0.0.1" && addr != "localhost" {
-            proxy_config.allow_lan_access = true;
-        }
-        addr
-    } else {
-        proxy_config.get_bind_address().to_string()
-    };
+    if let Err(e) = modules::logger::set_log_level(&proxy_config.log_level) {
+        tracing::warn!("{}", e);
+    }
 
-    let data_dir = modules::account::get_data_dir()?;
     let _ = modules::account::get_accounts_dir()?;
 
+    // 选择存储后端（SQLite 或共享 PostgreSQL），需在读写账号、日志与 API Key 之前完成
+    modules::storage::configure(&proxy_config.storage)
+        .map_err(|e| format!("failed to configure storage: {}", e))?;
+    modules::account::init_db().map_err(|e| format!("failed to initialize account storage: {}", e))?;
+
+    // 初始化静态加密主密钥，并加密历史明文 token
+    modules::secret_store::init(proxy_config.secret_master_key.as_deref())
+        .map_err(|e| format!("failed to initialize secret encryption: {}", e))?;
+    match modules::account::encrypt_stored_tokens() {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("encrypted stored tokens for {} accounts", n),
+        Err(e) => tracing::warn!("failed to encrypt stored account tokens: {}", e),
+    }
+
     // 初始化 API Keys 数据库
     if let Err(e) = modules::api_keys::init_db() {
         tracing::error!("failed to initialize API keys database: {}", e);
     }
 
+    // 初始化审计日志数据库
+    if let Err(e) = modules::audit::init_db() {
+        tracing::error!("failed to initialize audit log database: {}", e);
+    }
+
+    // 初始化模型别名数据库
+    if let Err(e) = modules::model_aliases::init_db() {
+        tracing::error!("failed to initialize model aliases database: {}", e);
+    }
+
+    if let Err(e) = modules::quota::set_budget_timezone(&proxy_config.token_budget_timezone) {
+        tracing::warn!("{}; daily token budgets reset at UTC midnight", e);
+    }
+
     // 迁移旧的单一 API Key 到多 key 系统
     if let Err(e) = modules::api_keys::migrate_legacy_key(&proxy_config.api_key) {
         tracing::warn!("failed to migrate legacy API key: {}", e);
     }
 
-    let token_manager = Arc::new(proxy::TokenManager::new(data_dir));
+    let token_manager = Arc::new(proxy::TokenManager::new());
     token_manager
         .update_sticky_config(proxy_config.scheduling.clone())
         .await;
@@ -67,11 +119,108 @@ async fn main() -> Result<(), String> {
         tracing::warn!("no active accounts found; open the web console to add accounts");
     }
 
+    // 后台提前刷新 OAuth access token
+    token_manager.spawn_refresh_task();
+
+    proxy::response_cache::RESPONSE_CACHE.configure(&proxy_config.response_cache);
+    if let Err(e) = proxy::files::validate(&proxy_config.files) {
+        tracing::warn!("files 配置无效，已忽略: {}", e);
+    } else {
+        proxy::files::FILES.configure(&proxy_config.files);
+    }
+    if let Err(e) = proxy::batches::validate(&proxy_config.batch) {
+        tracing::warn!("batch 配置无效，已忽略: {}", e);
+    } else {
+        proxy::batches::BATCHES.configure(&proxy_config.batch);
+    }
+    if let Err(e) = proxy::middleware::maintenance::validate(&proxy_config.maintenance) {
+        tracing::warn!("maintenance 配置无效，已忽略: {}", e);
+    } else {
+        proxy::middleware::maintenance::MAINTENANCE.configure(&proxy_config.maintenance);
+    }
+    if let Err(e) = modules::backup::validate(&proxy_config.backup) {
+        tracing::warn!("backup 配置无效，已忽略: {}", e);
+    } else {
+        modules::backup::BACKUPS.configure(&proxy_config.backup);
+    }
+    modules::backup::BACKUPS.spawn_scheduler();
+    if let Err(e) = proxy::concurrency::validate(&proxy_config.concurrency) {
+        tracing::warn!("concurrency 配置无效，已忽略: {}", e);
+    } else {
+        proxy::concurrency::CONCURRENCY.configure(&proxy_config.concurrency);
+    }
+    proxy::model_registry::MODEL_REGISTRY.configure(&proxy_config.model_registry);
+    if let Err(e) = proxy::upstream::shadow::validate(&proxy_config.shadow) {
+        tracing::warn!("shadow 配置无效，已忽略: {}", e);
+    } else {
+        proxy::upstream::shadow::SHADOW.configure(&proxy_config.shadow);
+    }
+    modules::alerts::ALERTS.configure(&proxy_config.alerts);
+    modules::usage_buffer::USAGE_BUFFER.configure(&proxy_config.usage_flush);
+    modules::usage_buffer::USAGE_BUFFER.spawn_flush_task();
+    proxy::cluster::CLUSTER.spawn_sync_task();
+    modules::api_keys::set_deleted_key_retention_days(proxy_config.deleted_key_retention_days);
+    modules::api_keys::spawn_purge_task();
+    if let Err(e) = proxy::pricing::validate(&proxy_config.pricing) {
+        tracing::warn!("pricing 配置无效，已忽略: {}", e);
+    } else {
+        proxy::pricing::PRICING.configure(&proxy_config.pricing);
+    }
+    if let Err(e) = proxy::auth_guard::validate(&proxy_config.auth_lockout) {
+        tracing::warn!("auth_lockout 配置无效，已忽略: {}", e);
+    } else {
+        proxy::auth_guard::AUTH_GUARD.configure(&proxy_config.auth_lockout);
+    }
+    proxy::otel::TRACER.configure(&proxy_config.otel);
+    proxy::cluster::CLUSTER.configure(&proxy_config.cluster);
+    if let Err(e) = proxy::upstream::profiles::validate(&proxy_config.client_profiles) {
+        tracing::warn!("client_profiles 配置无效，已忽略: {}", e);
+    } else {
+        proxy::upstream::profiles::CLIENT_PROFILES.configure(&proxy_config.client_profiles);
+    }
+    if let Err(e) = modules::oauth::validate(&proxy_config.oauth_clients) {
+        tracing::warn!("oauth_clients 配置无效，已忽略: {}", e);
+    } else {
+        modules::oauth::OAUTH_CLIENTS.configure(&proxy_config.oauth_clients);
+    }
+    proxy::handlers::gemini::set_passthrough(proxy_config.gemini_passthrough);
+    proxy::handlers::openai::set_strict_stream(proxy_config.openai_strict_stream);
+    proxy::handlers::openai::set_json_repair(proxy_config.openai_json_repair);
+    proxy::mappers::openai::thinking::set_mode(proxy_config.openai_thinking);
+    proxy::common::sse::set_keepalive_interval(proxy_config.sse_keepalive_seconds);
+    proxy::middleware::body_limit::set_max_request_body(proxy_config.max_request_body_bytes);
+    proxy::middleware::compression::set_enabled(proxy_config.compress_responses);
+    if let Err(e) = proxy::middleware::base_path::init(&proxy_config.base_path) {
+        tracing::warn!("base_path 配置无效，已忽略: {}", e);
+    }
+
     let monitor = Arc::new(proxy::monitor::ProxyMonitor::new(1000));
     monitor.set_enabled(proxy_config.enable_logging);
+    monitor.set_redaction(&proxy_config.log_redaction);
+    monitor.set_capture(&proxy_config.monitor);
+    // 归档配置无效时不清理，避免本应归档的日志被直接删除
+    if let Err(e) = modules::log_archive::validate(&proxy_config.log_archive) {
+        tracing::warn!("log_archive 配置无效，已暂停日志清理: {}", e);
+    } else {
+        monitor.spawn_retention_task(proxy_config.log_retention.clone(), proxy_config.log_archive.clone());
+    }
+
+    let tls_acceptor = proxy::tls::init(&proxy_config.tls, &bind_address, proxy_config.port)
+        .await
+        .map_err(|e| format!("failed to set up TLS: {}", e))?;
+    let listeners = proxy::listener::bind_all(
+        &bind_address,
+        proxy_config.port,
+        tls_acceptor,
+        &proxy_config.listeners,
+        proxy_config.unix_socket_mode.as_deref(),
+    )
+    .await
+    .map_err(|e| format!("failed to start proxy server: {}", e))?;
+    let addresses: Vec<String> = listeners.iter().map(|l| l.describe()).collect();
 
     let (server, handle) = proxy::AxumServer::start(
-        bind_address.clone(),
+        listeners,
         proxy_config.port,
         token_manager,
         proxy_config.anthropic_mapping.clone(),
@@ -81,23 +230,61 @@ async fn main() -> Result<(), String> {
         proxy_config.upstream_proxy.clone(),
         proxy::ProxySecurityConfig::from_proxy_config(&proxy_config),
         monitor,
+        proxy_config.retry.clone(),
+        proxy_config.upstream_timeouts.clone(),
+        proxy_config.upstream_endpoints.clone(),
     )
     .await
     .map_err(|e| format!("failed to start proxy server: {}", e))?;
 
     tracing::info!(
-        "anti-proxy listening on http://{}:{}",
-        bind_address,
-        proxy_config.port
+        "anti-proxy listening on {}",
+        addresses.join(", ")
     );
 
-    tokio::signal::ctrl_c()
-        .await
-        .map_err(|e| format!("failed to listen for shutdown signal: {}", e))?;
+    // SIGHUP 触发配置热重载
+    proxy::reload::spawn_sighup_listener(server.app_state());
 
-    tracing::info!("shutdown requested, stopping server...");
+    shutdown_signal().await?;
+
+    // 停止接受新连接，等待在途请求（含流式响应）结束，再等待日志与用量落盘
+    tracing::info!("shutdown requested, draining in-flight requests...");
     server.stop();
-    let _ = handle.await;
+    let drain_timeout = std::time::Duration::from_secs(proxy_config.shutdown_drain_timeout_seconds);
+    if tokio::time::timeout(drain_timeout, handle).await.is_err() {
+        tracing::warn!(
+            "in-flight requests did not finish within {}s, closing them",
+            drain_timeout.as_secs()
+        );
+    }
+    if !proxy::shutdown::wait_for_background_tasks(proxy::shutdown::FLUSH_TIMEOUT).await {
+        tracing::warn!(
+            "{} pending log/usage writes did not finish before exit",
+            proxy::shutdown::pending_tasks()
+        );
+    }
+    modules::usage_buffer::USAGE_BUFFER.flush_async().await;
+    tracing::info!("shutdown complete");
 
     Ok(())
 }
+
+/// 等待 Ctrl+C 或 SIGTERM（容器 / systemd 停止服务时发送）
+async fn shutdown_signal() -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate())
+            .map_err(|e| format!("failed to listen for SIGTERM: {}", e))?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res.map_err(|e| format!("failed to listen for shutdown signal: {}", e)),
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c()
+            .await
+            .map_err(|e| format!("failed to listen for shutdown signal: {}", e))
+    }
+}
@@ -27,6 +27,14 @@ pub struct Account {
     /// Unix timestamp when the proxy was disabled.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub proxy_disabled_at: Option<i64>,
+    /// Routing pools this account belongs to (e.g. `prod`, `experiments`).
+    /// API keys bound to a pool only rotate within that pool's accounts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pools: Vec<String>,
+    /// Upstream client profile (User-Agent / client metadata) used for this account's requests.
+    /// Falls back to `client_profiles.default` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_profile: Option<String>,
     pub created_at: i64,
     pub last_used: i64,
 }
@@ -46,6 +54,8 @@ impl Account {
             proxy_disabled: false,
             proxy_disabled_reason: None,
             proxy_disabled_at: None,
+            pools: Vec::new(),
+            client_profile: None,
             created_at: now,
             last_used: now,
         }
@@ -55,7 +65,16 @@ impl Account {
         self.last_used = chrono::Utc::now().timestamp();
     }
 
-    pub fn update_quota(&mut self, quota: QuotaData) {
+    /// 替换配额快照；尚未结束的冷却记录会保留下来
+    pub fn update_quota(&mut self, mut quota: QuotaData) {
+        if let Some(previous) = self.quota.take() {
+            let now = chrono::Utc::now().timestamp();
+            for (group, cooldown) in previous.cooldowns {
+                if cooldown.until > now {
+                    quota.cooldowns.entry(group).or_insert(cooldown);
+                }
+            }
+        }
         self.quota = Some(quota);
     }
 }
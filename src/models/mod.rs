@@ -3,4 +3,4 @@ pub mod token;
 pub mod quota;
 pub use account::{Account, AccountIndex, AccountSummary};
 pub use token::TokenData;
-pub use quota::QuotaData;
+pub use quota::{QuotaCooldown, QuotaData};
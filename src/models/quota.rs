@@ -18,6 +18,22 @@ pub struct QuotaData {
     /// 订阅等级 (FREE/PRO/ULTRA)
     #[serde(default)]
     pub subscription_tier: Option<String>,
+    /// 按配额组记录的冷却状态（由上游 429 写入，重启后仍然生效）
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub cooldowns: std::collections::BTreeMap<String, QuotaCooldown>,
+}
+
+/// 配额组冷却记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuotaCooldown {
+    /// 冷却结束时间（Unix 秒）
+    pub until: i64,
+    /// 限流原因（`quota_exhausted` / `rate_limit_exceeded` / `unknown`）
+    pub reason: String,
+    /// 本轮冷却期间观察到的 429 次数
+    pub hits: u32,
+    /// 最近一次 429 的时间（Unix 秒）
+    pub last_hit_at: i64,
 }
 
 impl QuotaData {
@@ -27,6 +43,7 @@ impl QuotaData {
             last_updated: chrono::Utc::now().timestamp(),
             is_forbidden: false,
             subscription_tier: None,
+            cooldowns: std::collections::BTreeMap::new(),
         }
     }
 
@@ -13,6 +13,9 @@ pub struct TokenData {
     pub project_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub session_id: Option<String>,  // 新增：Antigravity sessionId
+    /// 签发该 refresh_token 的 OAuth 客户端名称（`oauth_clients`），为空表示内置客户端
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oauth_client: Option<String>,
 }
 
 impl TokenData {
@@ -34,6 +37,7 @@ impl TokenData {
             email,
             project_id,
             session_id,
+            oauth_client: None,
         }
     }
 }
@@ -4,11 +4,15 @@ use serde_json;
 use uuid::Uuid;
 
 use crate::models::{Account, AccountIndex, AccountSummary, TokenData, QuotaData};
+use crate::modules::secret_store;
+use crate::modules::storage::{self, params, Database};
 use once_cell::sync::Lazy;
-use std::sync::Mutex;
+use std::sync::{Mutex, MutexGuard};
 
 /// 全局账号写入锁，防止并发操作导致索引文件损坏
 static ACCOUNT_INDEX_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+/// 共享存储下账号索引的跨实例锁键
+const ACCOUNT_INDEX_LOCK_KEY: i64 = 0x4150_4163_636f_756e;
 
 // ... existing constants ...
 const DATA_DIR: &str = ".AntiProxy";
@@ -49,19 +53,135 @@ pub fn get_accounts_dir() -> Result<PathBuf, String> {
     Ok(accounts_dir)
 }
 
+/// 账号索引写入锁；共享存储下同时持有跨实例锁，避免多个实例同时改写索引
+struct IndexLock {
+    _shared: Option<storage::SharedLock>,
+    _local: MutexGuard<'static, ()>,
+}
+
+fn lock_index() -> Result<IndexLock, String> {
+    let local = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let shared = storage::lock(ACCOUNT_INDEX_LOCK_KEY).map_err(|e| format!("获取锁失败: {}", e))?;
+    Ok(IndexLock { _shared: shared, _local: local })
+}
+
+/// 初始化账号存储（共享存储下建表，本地以 JSON 文件存储无需初始化）
+pub fn init_db() -> Result<(), String> {
+    if !storage::is_shared() {
+        return Ok(());
+    }
+    let mut conn = storage::open(Database::Accounts)?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS account_files (
+            name TEXT PRIMARY KEY,
+            content TEXT NOT NULL
+        )",
+        params![],
+    )?;
+    Ok(())
+}
+
+fn account_file_name(account_id: &str) -> String {
+    format!("{}/{}.json", ACCOUNTS_DIR, account_id)
+}
+
+/// 读取账号数据文件（`name` 为数据目录下的相对路径，共享存储下为 account_files 表的键），不存在时返回 None
+fn read_data_file(name: &str) -> Result<Option<String>, String> {
+    if storage::is_shared() {
+        let mut conn = storage::open(Database::Accounts)?;
+        return conn
+            .query_row("SELECT content FROM account_files WHERE name = $1", params![name])?
+            .map(|row| row.get(0))
+            .transpose();
+    }
+    let path = get_data_dir()?.join(name);
+    if !path.exists() {
+        return Ok(None);
+    }
+    fs::read_to_string(&path).map(Some).map_err(|e| e.to_string())
+}
+
+/// 写入账号数据文件（先写临时文件再原子替换）
+fn write_data_file(name: &str, content: &str) -> Result<(), String> {
+    if storage::is_shared() {
+        let mut conn = storage::open(Database::Accounts)?;
+        conn.execute(
+            "INSERT INTO account_files (name, content) VALUES ($1, $2)
+             ON CONFLICT(name) DO UPDATE SET content = excluded.content",
+            params![name, content],
+        )?;
+        return Ok(());
+    }
+    let path = get_data_dir()?.join(name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, content).map_err(|e| format!("写入临时文件失败: {}", e))?;
+    fs::rename(&temp_path, &path).map_err(|e| format!("替换文件失败: {}", e))
+}
+
+fn remove_data_file(name: &str) -> Result<(), String> {
+    if storage::is_shared() {
+        let mut conn = storage::open(Database::Accounts)?;
+        conn.execute("DELETE FROM account_files WHERE name = $1", params![name])?;
+        return Ok(());
+    }
+    let path = get_data_dir()?.join(name);
+    if path.exists() {
+        fs::remove_file(&path).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// 列出所有已保存的账号 ID（包括未出现在索引中的账号）
+pub fn list_account_ids() -> Result<Vec<String>, String> {
+    if storage::is_shared() {
+        let mut conn = storage::open(Database::Accounts)?;
+        let prefix = format!("{}/", ACCOUNTS_DIR);
+        return conn
+            .query("SELECT name FROM account_files ORDER BY name", params![])?
+            .iter()
+            .map(|row| row.get::<String>(0))
+            .filter_map(|name| match name {
+                Ok(name) => name.strip_prefix(&prefix)?.strip_suffix(".json").map(|id| Ok(id.to_string())),
+                Err(e) => Some(Err(e)),
+            })
+            .collect();
+    }
+    let accounts_dir = get_data_dir()?.join(ACCOUNTS_DIR);
+    if !accounts_dir.exists() {
+        return Err(format!("账号目录不存在: {:?}", accounts_dir));
+    }
+    let ids = fs::read_dir(&accounts_dir)
+        .map_err(|e| format!("读取账号目录失败: {}", e))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|s| s.to_str()) == Some("json"))
+        .filter_map(|p| p.file_stem().and_then(|s| s.to_str()).map(str::to_string))
+        .collect();
+    Ok(ids)
+}
+
+/// 读取账号的原始 JSON（token 为密文），不存在时返回 None
+pub fn read_account_json(account_id: &str) -> Result<Option<String>, String> {
+    read_data_file(&account_file_name(account_id))
+}
+
+/// 直接写入账号的原始 JSON（调用方负责 token 加密）
+pub fn write_account_json(account_id: &str, content: &str) -> Result<(), String> {
+    write_data_file(&account_file_name(account_id), content)
+}
+
 /// 加载账号索引
 pub fn load_account_index() -> Result<AccountIndex, String> {
-    let data_dir = get_data_dir()?;
-    let index_path = data_dir.join(ACCOUNTS_INDEX);
-    // modules::logger::log_info(&format!("正在加载账号索引: {:?}", index_path)); // Optional: reduce noise
-    
-    if !index_path.exists() {
-        crate::modules::logger::log_warn("账号索引文件不存在");
-        return Ok(AccountIndex::new());
-    }
-    
-    let content = fs::read_to_string(&index_path)
-        .map_err(|e| format!("读取账号索引失败: {}", e))?;
+    let content = match read_data_file(ACCOUNTS_INDEX).map_err(|e| format!("读取账号索引失败: {}", e))? {
+        Some(content) => content,
+        None => {
+            crate::modules::logger::log_warn("账号索引文件不存在");
+            return Ok(AccountIndex::new());
+        }
+    };
     
     let index: AccountIndex = serde_json::from_str(&content)
         .map_err(|e| format!("解析账号索引失败: {}", e))?;
@@ -72,47 +192,35 @@ pub fn load_account_index() -> Result<AccountIndex, String> {
 
 /// 保存账号索引 (原子化写入)
 pub fn save_account_index(index: &AccountIndex) -> Result<(), String> {
-    let data_dir = get_data_dir()?;
-    let index_path = data_dir.join(ACCOUNTS_INDEX);
-    let temp_path = data_dir.join(format!("{}.tmp", ACCOUNTS_INDEX));
-    
     let content = serde_json::to_string_pretty(index)
         .map_err(|e| format!("序列化账号索引失败: {}", e))?;
     
-    // 写入临时文件
-    fs::write(&temp_path, content)
-        .map_err(|e| format!("写入临时索引文件失败: {}", e))?;
-        
-    // 原子重命名
-    fs::rename(temp_path, index_path)
-        .map_err(|e| format!("替换索引文件失败: {}", e))
+    write_data_file(ACCOUNTS_INDEX, &content)
+        .map_err(|e| format!("保存账号索引失败: {}", e))
 }
 
 /// 加载账号数据
 pub fn load_account(account_id: &str) -> Result<Account, String> {
-    let accounts_dir = get_accounts_dir()?;
-    let account_path = accounts_dir.join(format!("{}.json", account_id));
-    
-    if !account_path.exists() {
-        return Err(format!("账号不存在: {}", account_id));
-    }
-    
-    let content = fs::read_to_string(&account_path)
-        .map_err(|e| format!("读取账号数据失败: {}", e))?;
-    
-    serde_json::from_str(&content)
-        .map_err(|e| format!("解析账号数据失败: {}", e))
+    let content = read_account_json(account_id)
+        .map_err(|e| format!("读取账号数据失败: {}", e))?
+        .ok_or_else(|| format!("账号不存在: {}", account_id))?;
+    
+    let mut account: Account = serde_json::from_str(&content)
+        .map_err(|e| format!("解析账号数据失败: {}", e))?;
+    account.token.access_token = secret_store::decrypt(&account.token.access_token)?;
+    account.token.refresh_token = secret_store::decrypt(&account.token.refresh_token)?;
+    Ok(account)
 }
 
-/// 保存账号数据
+/// 保存账号数据（token 加密落盘）
 pub fn save_account(account: &Account) -> Result<(), String> {
-    let accounts_dir = get_accounts_dir()?;
-    let account_path = accounts_dir.join(format!("{}.json", account.id));
-    
-    let content = serde_json::to_string_pretty(account)
+    let mut stored = account.clone();
+    stored.token.access_token = secret_store::encrypt(&account.token.access_token)?;
+    stored.token.refresh_token = secret_store::encrypt(&account.token.refresh_token)?;
+    let content = serde_json::to_string_pretty(&stored)
         .map_err(|e| format!("序列化账号数据失败: {}", e))?;
     
-    fs::write(&account_path, content)
+    write_account_json(&account.id, &content)
         .map_err(|e| format!("保存账号数据失败: {}", e))
 }
 
@@ -164,7 +272,7 @@ pub fn list_accounts() -> Result<Vec<Account>, String> {
 
 /// 添加账号
 pub fn add_account(email: String, name: Option<String>, token: TokenData) -> Result<Account, String> {
-    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let _lock = lock_index()?;
     let mut index = load_account_index()?;
     
     // 检查是否已存在
@@ -201,7 +309,7 @@ pub fn add_account(email: String, name: Option<String>, token: TokenData) -> Res
 
 /// 添加或更新账号
 pub fn upsert_account(email: String, name: Option<String>, token: TokenData) -> Result<Account, String> {
-    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let _lock = lock_index()?;
     let mut index = load_account_index()?;
     
     // 先找到账号 ID（如果存在）
@@ -267,7 +375,7 @@ pub fn upsert_account(email: String, name: Option<String>, token: TokenData) ->
 
 /// 删除账号
 pub fn delete_account(account_id: &str) -> Result<(), String> {
-    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let _lock = lock_index()?;
     let mut index = load_account_index()?;
     
     // 从索引中移除
@@ -286,24 +394,17 @@ pub fn delete_account(account_id: &str) -> Result<(), String> {
     save_account_index(&index)?;
     
     // 删除账号文件
-    let accounts_dir = get_accounts_dir()?;
-    let account_path = accounts_dir.join(format!("{}.json", account_id));
-    
-    if account_path.exists() {
-        fs::remove_file(&account_path)
-            .map_err(|e| format!("删除账号文件失败: {}", e))?;
-    }
+    remove_data_file(&account_file_name(account_id))
+        .map_err(|e| format!("删除账号文件失败: {}", e))?;
     
     Ok(())
 }
 
 /// 批量删除账号 (原子性操作索引)
 pub fn delete_accounts(account_ids: &[String]) -> Result<(), String> {
-    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let _lock = lock_index()?;
     let mut index = load_account_index()?;
     
-    let accounts_dir = get_accounts_dir()?;
-    
     for account_id in account_ids {
         // 从索引中移除
         index.accounts.retain(|s| &s.id != account_id);
@@ -314,10 +415,7 @@ pub fn delete_accounts(account_ids: &[String]) -> Result<(), String> {
         }
         
         // 删除账号文件
-        let account_path = accounts_dir.join(format!("{}.json", account_id));
-        if account_path.exists() {
-            let _ = fs::remove_file(&account_path);
-        }
+        let _ = remove_data_file(&account_file_name(account_id));
     }
     
     // 如果当前账号为空，尝试选取第一个作为默认
@@ -331,7 +429,7 @@ pub fn delete_accounts(account_ids: &[String]) -> Result<(), String> {
 /// 重新排序账号列表
 /// 根据传入的账号ID顺序更新索引文件中的账号排列顺序
 pub fn reorder_accounts(account_ids: &[String]) -> Result<(), String> {
-    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let _lock = lock_index()?;
     let mut index = load_account_index()?;
     
     // 创建一个映射，记录每个账号ID对应的摘要信息
@@ -379,7 +477,7 @@ pub fn get_current_account() -> Result<Option<Account>, String> {
 
 /// 设置当前激活账号 ID
 pub fn set_current_account_id(account_id: &str) -> Result<(), String> {
-    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let _lock = lock_index()?;
     let mut index = load_account_index()?;
     index.current_account_id = Some(account_id.to_string());
     save_account_index(&index)
@@ -392,6 +490,54 @@ pub fn update_account_quota(account_id: &str, quota: QuotaData) -> Result<(), St
     save_account(&account)
 }
 
+/// 设置账号所属的账号池（去除空白与重复项）
+pub fn set_account_pools(account_id: &str, pools: &[String]) -> Result<Account, String> {
+    let mut account = load_account(account_id)?;
+    let mut normalized: Vec<String> = Vec::new();
+    for pool in pools.iter().map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        if !normalized.iter().any(|p| p == pool) {
+            normalized.push(pool.to_string());
+        }
+    }
+    account.pools = normalized;
+    save_account(&account)?;
+    Ok(account)
+}
+
+/// 设置账号使用的上游客户端 profile（None 表示使用默认 profile）
+pub fn set_account_client_profile(account_id: &str, profile: Option<String>) -> Result<Account, String> {
+    let mut account = load_account(account_id)?;
+    account.client_profile = profile.map(|p| p.trim().to_string()).filter(|p| !p.is_empty());
+    save_account(&account)?;
+    Ok(account)
+}
+
+/// 将仍以明文存储 token 的账号文件重新加密保存，返回迁移数量
+pub fn encrypt_stored_tokens() -> Result<usize, String> {
+    let _lock = lock_index()?;
+    let index = load_account_index()?;
+    let mut migrated = 0;
+
+    for summary in &index.accounts {
+        let Ok(Some(content)) = read_account_json(&summary.id) else {
+            continue;
+        };
+        let Ok(raw) = serde_json::from_str::<serde_json::Value>(&content) else {
+            continue;
+        };
+        let is_plaintext = ["access_token", "refresh_token"].iter().any(|field| {
+            raw["token"][field]
+                .as_str()
+                .is_some_and(|v| !secret_store::is_encrypted(v))
+        });
+        if is_plaintext {
+            save_account(&load_account(&summary.id)?)?;
+            migrated += 1;
+        }
+    }
+    Ok(migrated)
+}
+
 /// 导出所有账号的 refresh_token
 #[allow(dead_code)]
 pub fn export_accounts() -> Result<Vec<(String, String)>, String> {
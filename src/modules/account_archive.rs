@@ -0,0 +1,279 @@
+//! 账号加密导入 / 导出
+//!
+//! 将所有账号（refresh_token、project_id 与元数据）打包为口令保护的加密归档，
+//! 用于在机器之间迁移而无需重新走 OAuth 流程。
+//! 密钥由 Argon2id 从口令派生，内容使用 XChaCha20-Poly1305 加密
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::models::Account;
+
+pub const ARCHIVE_FORMAT: &str = "antiproxy-accounts";
+const ARCHIVE_VERSION: u32 = 1;
+/// 附加认证数据：防止归档头被篡改成其他格式 / 版本
+const ARCHIVE_AAD: &[u8] = b"antiproxy-accounts:v1";
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+/// 导入时允许的 KDF 参数上限（防止恶意归档耗尽内存 / CPU）
+const MAX_M_COST_KIB: u32 = 256 * 1024;
+const MAX_T_COST: u32 = 10;
+const MAX_P_COST: u32 = 8;
+
+/// 口令派生参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub algorithm: String,
+    pub salt: String,
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+/// 加密归档（导出文件内容）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountArchive {
+    pub format: String,
+    pub version: u32,
+    pub created_at: i64,
+    pub account_count: usize,
+    pub kdf: KdfParams,
+    pub cipher: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// 归档解密后的内容
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchivePayload {
+    exported_at: i64,
+    accounts: Vec<Account>,
+}
+
+/// 导入结果
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportSummary {
+    pub created: usize,
+    pub updated: usize,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], m_cost: u32, t_cost: u32, p_cost: u32) -> Result<[u8; KEY_LEN], String> {
+    let params = Params::new(m_cost, t_cost, p_cost, Some(KEY_LEN)).map_err(|e| format!("Invalid KDF parameters: {}", e))?;
+    let mut key = [0u8; KEY_LEN];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Failed to derive key: {}", e))?;
+    Ok(key)
+}
+
+fn validate_passphrase(passphrase: &str) -> Result<(), String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(format!("Passphrase must be at least {} characters", MIN_PASSPHRASE_LEN));
+    }
+    Ok(())
+}
+
+/// 口令加密的结果；账号归档与整库备份共用
+pub(crate) struct Sealed {
+    pub kdf: KdfParams,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+pub(crate) const CIPHER: &str = "xchacha20poly1305";
+
+/// 用口令加密任意内容，`aad` 绑定归档格式与版本
+pub(crate) fn seal(plaintext: &[u8], passphrase: &str, aad: &[u8], m_cost: u32, t_cost: u32, p_cost: u32) -> Result<Sealed, String> {
+    validate_passphrase(passphrase)?;
+
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let key = derive_key(passphrase, &salt, m_cost, t_cost, p_cost)?;
+    let cipher = XChaCha20Poly1305::new_from_slice(&key).map_err(|e| e.to_string())?;
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+        .map_err(|_| "Failed to encrypt archive".to_string())?;
+
+    Ok(Sealed {
+        kdf: KdfParams {
+            algorithm: "argon2id".to_string(),
+            salt: STANDARD.encode(salt),
+            m_cost,
+            t_cost,
+            p_cost,
+        },
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+}
+
+/// 解密 `seal` 的结果；口令错误或内容被篡改时返回错误
+pub(crate) fn unseal(sealed: &Sealed, cipher: &str, passphrase: &str, aad: &[u8]) -> Result<Vec<u8>, String> {
+    if sealed.kdf.algorithm != "argon2id" || cipher != CIPHER {
+        return Err("Unsupported archive cipher".to_string());
+    }
+    let kdf = &sealed.kdf;
+    if kdf.m_cost > MAX_M_COST_KIB || kdf.t_cost > MAX_T_COST || kdf.p_cost > MAX_P_COST {
+        return Err("Archive KDF parameters exceed the allowed limits".to_string());
+    }
+
+    let decode = |field: &str, value: &str| STANDARD.decode(value).map_err(|e| format!("Invalid {}: {}", field, e));
+    let salt = decode("salt", &kdf.salt)?;
+    let nonce = decode("nonce", &sealed.nonce)?;
+    let ciphertext = decode("ciphertext", &sealed.ciphertext)?;
+    if nonce.len() != NONCE_LEN {
+        return Err("Invalid nonce length".to_string());
+    }
+
+    let key = derive_key(passphrase, &salt, kdf.m_cost, kdf.t_cost, kdf.p_cost)?;
+    let cipher = XChaCha20Poly1305::new_from_slice(&key).map_err(|e| e.to_string())?;
+    cipher
+        .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ciphertext, aad })
+        .map_err(|_| "Wrong passphrase or corrupted archive".to_string())
+}
+
+fn encrypt_with_params(accounts: Vec<Account>, passphrase: &str, m_cost: u32, t_cost: u32, p_cost: u32) -> Result<AccountArchive, String> {
+    let now = chrono::Utc::now().timestamp();
+    let account_count = accounts.len();
+    let plaintext = serde_json::to_vec(&ArchivePayload {
+        exported_at: now,
+        accounts,
+    })
+    .map_err(|e| e.to_string())?;
+    let sealed = seal(&plaintext, passphrase, ARCHIVE_AAD, m_cost, t_cost, p_cost)?;
+
+    Ok(AccountArchive {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        created_at: now,
+        account_count,
+        kdf: sealed.kdf,
+        cipher: CIPHER.to_string(),
+        nonce: sealed.nonce,
+        ciphertext: sealed.ciphertext,
+    })
+}
+
+/// 加密账号列表（使用 Argon2 默认参数）
+pub fn encrypt_accounts(accounts: Vec<Account>, passphrase: &str) -> Result<AccountArchive, String> {
+    encrypt_with_params(
+        accounts,
+        passphrase,
+        Params::DEFAULT_M_COST,
+        Params::DEFAULT_T_COST,
+        Params::DEFAULT_P_COST,
+    )
+}
+
+/// 解密归档；口令错误或内容被篡改时返回错误
+pub fn decrypt_accounts(archive: &AccountArchive, passphrase: &str) -> Result<Vec<Account>, String> {
+    if archive.format != ARCHIVE_FORMAT || archive.version != ARCHIVE_VERSION {
+        return Err(format!("Unsupported archive format: {} v{}", archive.format, archive.version));
+    }
+    let sealed = Sealed {
+        kdf: archive.kdf.clone(),
+        nonce: archive.nonce.clone(),
+        ciphertext: archive.ciphertext.clone(),
+    };
+    let plaintext = unseal(&sealed, &archive.cipher, passphrase, ARCHIVE_AAD)?;
+
+    let payload: ArchivePayload =
+        serde_json::from_slice(&plaintext).map_err(|e| format!("Invalid archive content: {}", e))?;
+    Ok(payload.accounts)
+}
+
+/// 导出所有账号为加密归档
+pub fn export_accounts(passphrase: &str) -> Result<AccountArchive, String> {
+    let accounts = crate::modules::account::list_accounts()?;
+    encrypt_accounts(accounts, passphrase)
+}
+
+/// 导入加密归档：按邮箱合并，已存在的账号更新 token 与元数据
+pub fn import_accounts(archive: &AccountArchive, passphrase: &str) -> Result<ImportSummary, String> {
+    use crate::modules::account;
+
+    let accounts = decrypt_accounts(archive, passphrase)?;
+    let existing: std::collections::HashSet<String> =
+        account::load_account_index()?.accounts.into_iter().map(|s| s.email).collect();
+
+    let mut summary = ImportSummary { created: 0, updated: 0 };
+    for imported in accounts {
+        if existing.contains(&imported.email) {
+            summary.updated += 1;
+        } else {
+            summary.created += 1;
+        }
+
+        let mut saved = account::upsert_account(imported.email.clone(), imported.name.clone(), imported.token.clone())?;
+        saved.quota = imported.quota.or(saved.quota);
+        saved.disabled = imported.disabled;
+        saved.disabled_reason = imported.disabled_reason;
+        saved.disabled_at = imported.disabled_at;
+        saved.proxy_disabled = imported.proxy_disabled;
+        saved.proxy_disabled_reason = imported.proxy_disabled_reason;
+        saved.proxy_disabled_at = imported.proxy_disabled_at;
+        account::save_account(&saved)?;
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenData;
+
+    fn sample_account(email: &str) -> Account {
+        let token = TokenData::new(
+            "access".to_string(),
+            format!("refresh-{}", email),
+            3600,
+            Some(email.to_string()),
+            Some("project-1".to_string()),
+            None,
+        );
+        Account::new(uuid::Uuid::new_v4().to_string(), email.to_string(), token)
+    }
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let archive = encrypt_with_params(vec![sample_account("a@example.com")], "correct horse", 64, 1, 1).unwrap();
+        assert_eq!(archive.account_count, 1);
+        assert!(!archive.ciphertext.contains("refresh"));
+
+        let accounts = decrypt_accounts(&archive, "correct horse").unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].token.refresh_token, "refresh-a@example.com");
+        assert_eq!(accounts[0].token.project_id.as_deref(), Some("project-1"));
+
+        assert!(decrypt_accounts(&archive, "wrong horse").is_err());
+    }
+
+    #[test]
+    fn test_archive_rejects_tampering_and_weak_passphrase() {
+        assert!(encrypt_with_params(vec![], "short", 64, 1, 1).is_err());
+
+        let archive = encrypt_with_params(vec![sample_account("a@example.com")], "correct horse", 64, 1, 1).unwrap();
+        let mut tampered = archive.clone();
+        let mut bytes = STANDARD.decode(&tampered.ciphertext).unwrap();
+        bytes[0] ^= 1;
+        tampered.ciphertext = STANDARD.encode(bytes);
+        assert!(decrypt_accounts(&tampered, "correct horse").is_err());
+
+        let mut expensive = archive;
+        expensive.kdf.m_cost = MAX_M_COST_KIB + 1;
+        assert!(decrypt_accounts(&expensive, "correct horse").is_err());
+    }
+}
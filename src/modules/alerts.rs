@@ -0,0 +1,386 @@
+//! Webhook 告警
+//!
+//! 在账号认证失败、上游端点全部不可用、API Key 预算即将 / 已经用尽、错误率过高时
+//! 向配置的 Webhook (Slack / Discord / 通用 JSON) 推送通知。
+//! 投递在后台任务中进行，失败按指数退避重试；同一告警在冷却期内只发送一次
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::{AlertEvent, AlertsConfig, WebhookConfig, WebhookFormat};
+
+/// 全局告警管理器（启动 / 热重载时由配置写入）
+pub static ALERTS: Lazy<AlertManager> = Lazy::new(AlertManager::new);
+
+/// 单次投递超时（秒）
+const DELIVERY_TIMEOUT_SECS: u64 = 10;
+/// 重试退避上限
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// 一条告警
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub event: AlertEvent,
+    /// 告警对象（账号邮箱、API Key ID 等），与事件一起作为去重键
+    pub subject: String,
+    pub title: String,
+    pub message: String,
+    pub details: Value,
+    /// 为 true 时同一去重键只发送一次（如每日预算告警）
+    pub once: bool,
+}
+
+/// 测试投递结果
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookTestResult {
+    pub url: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn event_name(event: AlertEvent) -> String {
+    serde_json::to_value(event)
+        .ok()
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+/// 按 Webhook 格式构建消息体
+fn build_payload(format: WebhookFormat, alert: &Alert) -> Value {
+    match format {
+        WebhookFormat::Slack => json!({ "text": format!("*[AntiProxy] {}*\n{}", alert.title, alert.message) }),
+        WebhookFormat::Discord => json!({ "content": format!("**[AntiProxy] {}**\n{}", alert.title, alert.message) }),
+        WebhookFormat::Generic => json!({
+            "source": "anti-proxy",
+            "event": event_name(alert.event),
+            "subject": alert.subject,
+            "title": alert.title,
+            "message": alert.message,
+            "details": alert.details,
+            "timestamp": chrono::Utc::now().timestamp(),
+        }),
+    }
+}
+
+fn backoff_delay(attempt: u32) -> Duration {
+    Duration::from_secs(1u64 << attempt.min(6)).min(MAX_BACKOFF)
+}
+
+async fn post_once(client: &reqwest::Client, url: &str, payload: &Value) -> Result<(), String> {
+    let response = client
+        .post(url)
+        .json(payload)
+        .send()
+        .await
+        .map_err(|e| format!("Webhook request failed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Webhook returned HTTP {}", response.status()));
+    }
+    Ok(())
+}
+
+/// 投递到单个 Webhook，失败时按 1s、2s、4s... 退避重试
+async fn deliver(hook: WebhookConfig, payload: Value, max_retries: u32) {
+    let client = crate::utils::http::create_client(DELIVERY_TIMEOUT_SECS);
+    let mut attempt = 0;
+    loop {
+        match post_once(&client, &hook.url, &payload).await {
+            Ok(()) => return,
+            Err(e) if attempt >= max_retries => {
+                tracing::warn!("[Alerts] Giving up on webhook {} after {} attempts: {}", hook.url, attempt + 1, e);
+                return;
+            }
+            Err(e) => {
+                let delay = backoff_delay(attempt);
+                tracing::debug!("[Alerts] Webhook {} failed ({}), retrying in {:?}", hook.url, e, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// 滑动窗口内的请求结果，用于计算错误率
+#[derive(Default)]
+struct ErrorWindow {
+    outcomes: VecDeque<(Instant, bool)>,
+    errors: usize,
+}
+
+impl ErrorWindow {
+    /// 记录一次结果并返回 (窗口内请求数, 错误数)
+    fn record(&mut self, now: Instant, is_error: bool, window: Duration) -> (usize, usize) {
+        self.outcomes.push_back((now, is_error));
+        self.errors += is_error as usize;
+        while let Some(&(at, err)) = self.outcomes.front() {
+            if now.duration_since(at) <= window {
+                break;
+            }
+            self.outcomes.pop_front();
+            self.errors -= err as usize;
+        }
+        (self.outcomes.len(), self.errors)
+    }
+}
+
+pub struct AlertManager {
+    /// 快速路径：关闭时 record_request 不加锁
+    enabled: AtomicBool,
+    config: RwLock<AlertsConfig>,
+    /// (事件, 对象) -> 上次发送时间
+    last_sent: Mutex<HashMap<(AlertEvent, String), Instant>>,
+    error_window: Mutex<ErrorWindow>,
+}
+
+impl Default for AlertManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AlertManager {
+    pub fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            config: RwLock::new(AlertsConfig::default()),
+            last_sent: Mutex::new(HashMap::new()),
+            error_window: Mutex::new(ErrorWindow::default()),
+        }
+    }
+
+    pub fn configure(&self, config: &AlertsConfig) {
+        self.enabled
+            .store(config.enabled && !config.webhooks.is_empty(), Ordering::Relaxed);
+        *self.config.write().unwrap() = config.clone();
+    }
+
+    /// 去重：冷却期内（或 once 告警已发送过）返回 false
+    fn should_send(&self, alert: &Alert, now: Instant, cooldown: Duration) -> bool {
+        let mut last_sent = self.last_sent.lock().unwrap();
+        let key = (alert.event, alert.subject.clone());
+        if let Some(at) = last_sent.get(&key) {
+            if alert.once || now.duration_since(*at) < cooldown {
+                return false;
+            }
+        }
+        last_sent.insert(key, now);
+        true
+    }
+
+    /// 触发告警：投递给订阅了该事件的所有 Webhook（后台进行，不阻塞调用方）
+    pub fn fire(&self, alert: Alert) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let config = self.config.read().unwrap().clone();
+        let hooks: Vec<WebhookConfig> = config
+            .webhooks
+            .into_iter()
+            .filter(|h| h.events.is_empty() || h.events.contains(&alert.event))
+            .collect();
+        if hooks.is_empty() || !self.should_send(&alert, Instant::now(), Duration::from_secs(config.cooldown_seconds)) {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        tracing::info!("[Alerts] {}: {}", alert.title, alert.message);
+        for hook in hooks {
+            let payload = build_payload(hook.format, &alert);
+            runtime.spawn(deliver(hook, payload, config.max_retries));
+        }
+    }
+
+    /// 记录一次 API 请求结果，5xx 占比超过阈值时触发错误率告警
+    pub fn record_request(&self, status: u16) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let (threshold, window, min_requests) = {
+            let config = self.config.read().unwrap();
+            (
+                config.error_rate_threshold,
+                Duration::from_secs(config.error_rate_window_seconds),
+                config.error_rate_min_requests,
+            )
+        };
+        let (total, errors) = self
+            .error_window
+            .lock()
+            .unwrap()
+            .record(Instant::now(), status >= 500, window);
+
+        let rate = errors as f64 / total as f64;
+        if total >= min_requests.max(1) && rate >= threshold {
+            self.fire(Alert {
+                event: AlertEvent::ErrorRate,
+                subject: "global".to_string(),
+                title: "High error rate".to_string(),
+                message: format!(
+                    "{:.0}% of the last {} requests failed ({} errors in {}s window)",
+                    rate * 100.0,
+                    total,
+                    errors,
+                    window.as_secs()
+                ),
+                details: json!({ "requests": total, "errors": errors, "error_rate": rate }),
+                once: false,
+            });
+        }
+    }
+
+    /// 向所有配置的 Webhook 发送测试消息（不重试，忽略 enabled 与事件订阅）
+    pub async fn send_test(&self) -> Vec<WebhookTestResult> {
+        let hooks = self.config.read().unwrap().webhooks.clone();
+        let client = crate::utils::http::create_client(DELIVERY_TIMEOUT_SECS);
+        let alert = Alert {
+            event: AlertEvent::ErrorRate,
+            subject: "test".to_string(),
+            title: "Test alert".to_string(),
+            message: "This is a test notification from AntiProxy.".to_string(),
+            details: json!({ "test": true }),
+            once: false,
+        };
+
+        let mut results = Vec::with_capacity(hooks.len());
+        for hook in hooks {
+            let mut payload = build_payload(hook.format, &alert);
+            if hook.format == WebhookFormat::Generic {
+                payload["event"] = json!("test");
+            }
+            let outcome = post_once(&client, &hook.url, &payload).await;
+            results.push(WebhookTestResult {
+                url: hook.url,
+                ok: outcome.is_ok(),
+                error: outcome.err(),
+            });
+        }
+        results
+    }
+}
+
+/// 账号 OAuth 刷新被拒绝
+pub fn notify_account_auth_failure(email: &str, error: &str, disabled: bool) {
+    ALERTS.fire(Alert {
+        event: AlertEvent::AccountAuthFailure,
+        subject: email.to_string(),
+        title: "Account authentication failed".to_string(),
+        message: if disabled {
+            format!("Account {} was disabled: {}", email, error)
+        } else {
+            format!("Token refresh for {} failed: {}", email, error)
+        },
+        details: json!({ "email": email, "error": error, "disabled": disabled }),
+        once: false,
+    });
+}
+
+/// 同一 IP 连续认证失败达到告警阈值
+pub fn notify_auth_brute_force(ip: &str, failures: u32, kind: &str) {
+    ALERTS.fire(Alert {
+        event: AlertEvent::AuthBruteForce,
+        subject: ip.to_string(),
+        title: "Repeated authentication failures".to_string(),
+        message: format!("{} consecutive failed {} attempts from {}", failures, kind, ip),
+        details: json!({ "ip": ip, "failures": failures, "kind": kind }),
+        once: false,
+    });
+}
+
+/// 所有上游端点均请求失败
+pub fn notify_endpoints_down(error: &str) {
+    ALERTS.fire(Alert {
+        event: AlertEvent::EndpointsDown,
+        subject: "upstream".to_string(),
+        title: "All upstream endpoints are down".to_string(),
+        message: format!("Every upstream endpoint failed. Last error: {}", error),
+        details: json!({ "error": error }),
+        once: false,
+    });
+}
+
+/// API Key 每日预算用量：达到 80% / 100% 时各提醒一次（每个预算日）
+pub fn notify_budget_usage(key_id: &str, used: u64, budget: u64, day: &str) {
+    if budget == 0 {
+        return;
+    }
+    let (event, title) = if used >= budget {
+        (AlertEvent::QuotaBudgetExceeded, "Daily token budget exhausted")
+    } else if used * 5 >= budget * 4 {
+        (AlertEvent::QuotaBudgetWarning, "Daily token budget above 80%")
+    } else {
+        return;
+    };
+    ALERTS.fire(Alert {
+        event,
+        subject: format!("{}:{}", key_id, day),
+        title: title.to_string(),
+        message: format!("API key {} used {} of {} tokens on {}", key_id, used, budget, day),
+        details: json!({ "key_id": key_id, "used": used, "budget": budget, "day": day }),
+        once: true,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert(once: bool) -> Alert {
+        Alert {
+            event: AlertEvent::AccountAuthFailure,
+            subject: "a@example.com".to_string(),
+            title: "Account authentication failed".to_string(),
+            message: "invalid_grant".to_string(),
+            details: json!({ "email": "a@example.com" }),
+            once,
+        }
+    }
+
+    #[test]
+    fn test_build_payload_formats() {
+        let alert = alert(false);
+        let slack = build_payload(WebhookFormat::Slack, &alert);
+        assert!(slack["text"].as_str().unwrap().contains("Account authentication failed"));
+        let discord = build_payload(WebhookFormat::Discord, &alert);
+        assert!(discord["content"].as_str().unwrap().contains("invalid_grant"));
+
+        let generic = build_payload(WebhookFormat::Generic, &alert);
+        assert_eq!(generic["event"], "account_auth_failure");
+        assert_eq!(generic["details"]["email"], "a@example.com");
+    }
+
+    #[test]
+    fn test_cooldown_and_once() {
+        let manager = AlertManager::new();
+        let cooldown = Duration::from_secs(60);
+        let now = Instant::now();
+        assert!(manager.should_send(&alert(false), now, cooldown));
+        assert!(!manager.should_send(&alert(false), now + Duration::from_secs(30), cooldown));
+        assert!(manager.should_send(&alert(false), now + Duration::from_secs(61), cooldown));
+
+        let mut once = alert(true);
+        once.subject = "key:2025-01-01".to_string();
+        assert!(manager.should_send(&once, now, cooldown));
+        assert!(!manager.should_send(&once, now + Duration::from_secs(3600), cooldown));
+    }
+
+    #[test]
+    fn test_error_window_expires_old_outcomes() {
+        let mut window = ErrorWindow::default();
+        let start = Instant::now();
+        let span = Duration::from_secs(10);
+        assert_eq!(window.record(start, true, span), (1, 1));
+        assert_eq!(window.record(start + Duration::from_secs(5), false, span), (2, 1));
+        // 第一条已超出窗口
+        assert_eq!(window.record(start + Duration::from_secs(12), false, span), (2, 0));
+        assert_eq!(backoff_delay(0), Duration::from_secs(1));
+        assert_eq!(backoff_delay(10), MAX_BACKOFF);
+    }
+}
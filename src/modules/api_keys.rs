@@ -1,9 +1,43 @@
 //! API Keys 管理模块
 //! 支持多 key 隔离和用量追踪
 
-use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::modules::secret_store;
+use crate::modules::storage::{self, params, Database, Row, Store};
+use crate::modules::usage_buffer::UsageDelta;
+use crate::proxy::request_transform::RequestTransform;
+
+/// API Key 优先级：并发受限时高优先级请求先获得空位，低优先级请求最先被拒绝
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl KeyPriority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyPriority::Low => "low",
+            KeyPriority::Normal => "normal",
+            KeyPriority::High => "high",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "low" => KeyPriority::Low,
+            "high" => KeyPriority::High,
+            _ => KeyPriority::Normal,
+        }
+    }
+}
 
 /// API Key 结构
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +58,67 @@ pub struct ApiKey {
     pub total_input_tokens: u64,
     /// 总输出 tokens
     pub total_output_tokens: u64,
+    /// 累计估算费用（按 `pricing` 配置计价）
+    #[serde(default)]
+    pub total_cost: f64,
+    /// 每分钟请求上限（None 表示不限制）
+    pub rate_limit_rpm: Option<u32>,
+    /// 每日请求上限（None 表示不限制）
+    pub rate_limit_rpd: Option<u32>,
+    /// 允许使用的模型（None 表示不限制，支持 `gemini-2.5-*` 前缀通配）
+    pub allowed_models: Option<Vec<String>>,
+    /// 每日 token 预算（输入 + 输出，None 表示不限制）
+    pub daily_token_budget: Option<u64>,
+    /// 过期时间（Unix 秒，None 表示永不过期）
+    pub expires_at: Option<i64>,
+    /// 允许的客户端 IP（CIDR，None 表示不限制）
+    pub allowed_ips: Option<Vec<String>>,
+    /// 转发前的请求体改写规则（系统提示词 / 默认参数 / 删除字段）
+    pub request_transform: Option<RequestTransform>,
+    /// 绑定的账号池（None 表示可使用全部账号）
+    pub pool: Option<String>,
+    /// 并发排队优先级
+    #[serde(default)]
+    pub priority: KeyPriority,
+    /// 删除时间（Unix 秒）；已删除的 key 无法认证，保留期内可恢复
+    #[serde(default)]
+    pub deleted_at: Option<i64>,
+}
+
+impl ApiKey {
+    /// 检查客户端 IP 是否在该 Key 的白名单内（未配置时不限制，无法确定地址时拒绝）
+    pub fn is_ip_allowed(&self, ip: Option<std::net::IpAddr>) -> bool {
+        let Some(entries) = &self.allowed_ips else {
+            return true;
+        };
+        let Some(ip) = ip else {
+            return false;
+        };
+        entries
+            .iter()
+            .filter_map(|e| e.parse::<crate::proxy::ip_filter::IpNet>().ok())
+            .any(|net| net.contains(ip))
+    }
+
+    /// 是否已过期
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(chrono::Utc::now().timestamp())
+    }
+
+    fn is_expired_at(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|t| now >= t)
+    }
+
+    /// 检查该 Key 是否允许请求指定模型
+    pub fn is_model_allowed(&self, model: &str) -> bool {
+        match &self.allowed_models {
+            None => true,
+            Some(models) => models.iter().any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => model.starts_with(prefix),
+                None => pattern == model,
+            }),
+        }
+    }
 }
 
 /// API Key 用量统计
@@ -34,12 +129,17 @@ pub struct ApiKeyUsage {
     pub error_count: u64,
     pub total_input_tokens: u64,
     pub total_output_tokens: u64,
+    /// 累计估算费用
+    pub estimated_cost: f64,
 }
 
 /// 创建 API Key 请求
 #[derive(Debug, Deserialize)]
 pub struct CreateApiKeyRequest {
     pub name: String,
+    /// 过期时间（Unix 秒）
+    #[serde(default)]
+    pub expires_at: Option<i64>,
 }
 
 /// API Key 响应（隐藏完整 key）
@@ -52,6 +152,18 @@ pub struct ApiKeyResponse {
     pub created_at: i64,
     pub last_used_at: Option<i64>,
     pub usage: ApiKeyUsage,
+    pub rate_limit_rpm: Option<u32>,
+    pub rate_limit_rpd: Option<u32>,
+    pub allowed_models: Option<Vec<String>>,
+    pub daily_token_budget: Option<u64>,
+    pub expires_at: Option<i64>,
+    pub expired: bool,
+    pub allowed_ips: Option<Vec<String>>,
+    pub request_transform: Option<RequestTransform>,
+    pub pool: Option<String>,
+    pub priority: KeyPriority,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<i64>,
 }
 
 impl From<ApiKey> for ApiKeyResponse {
@@ -62,6 +174,7 @@ impl From<ApiKey> for ApiKeyResponse {
             key.key.clone()
         };
 
+        let expired = key.is_expired();
         Self {
             id: key.id,
             name: key.name,
@@ -75,45 +188,168 @@ impl From<ApiKey> for ApiKeyResponse {
                 error_count: key.error_count,
                 total_input_tokens: key.total_input_tokens,
                 total_output_tokens: key.total_output_tokens,
+                estimated_cost: key.total_cost,
             },
+            rate_limit_rpm: key.rate_limit_rpm,
+            rate_limit_rpd: key.rate_limit_rpd,
+            allowed_models: key.allowed_models,
+            daily_token_budget: key.daily_token_budget,
+            expires_at: key.expires_at,
+            expired,
+            allowed_ips: key.allowed_ips,
+            request_transform: key.request_transform,
+            pool: key.pool,
+            priority: key.priority,
+            deleted_at: key.deleted_at,
         }
     }
 }
 
-fn get_db_path() -> Result<PathBuf, String> {
+pub fn get_db_path() -> Result<PathBuf, String> {
     let data_dir = crate::modules::account::get_data_dir()?;
     Ok(data_dir.join("api_keys.db"))
 }
 
+/// SELECT 使用的列顺序，需与 row_to_api_key 保持一致
+const API_KEY_COLUMNS: &str = "id, name, key, enabled, created_at, last_used_at,
+    total_requests, success_count, error_count,
+    total_input_tokens, total_output_tokens,
+    rate_limit_rpm, rate_limit_rpd, allowed_models, daily_token_budget, expires_at, allowed_ips,
+    request_transform, total_cost, pool, deleted_at, priority";
+
+fn row_to_api_key(row: &Row) -> Result<ApiKey, String> {
+    Ok(ApiKey {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        key: decrypt_key(row.get(2)?),
+        enabled: row.get::<i32>(3)? == 1,
+        created_at: row.get(4)?,
+        last_used_at: row.get(5)?,
+        total_requests: row.get::<i64>(6)? as u64,
+        success_count: row.get::<i64>(7)? as u64,
+        error_count: row.get::<i64>(8)? as u64,
+        total_input_tokens: row.get::<i64>(9)? as u64,
+        total_output_tokens: row.get::<i64>(10)? as u64,
+        rate_limit_rpm: row.get::<Option<i64>>(11)?.map(|v| v as u32),
+        rate_limit_rpd: row.get::<Option<i64>>(12)?.map(|v| v as u32),
+        // 以 JSON 数组存储
+        allowed_models: row
+            .get::<Option<String>>(13)?
+            .and_then(|v| serde_json::from_str(&v).ok()),
+        daily_token_budget: row.get::<Option<i64>>(14)?.map(|v| v as u64),
+        expires_at: row.get(15)?,
+        allowed_ips: row
+            .get::<Option<String>>(16)?
+            .and_then(|v| serde_json::from_str(&v).ok()),
+        request_transform: row
+            .get::<Option<String>>(17)?
+            .and_then(|v| serde_json::from_str(&v).ok()),
+        total_cost: row.get(18)?,
+        pool: row.get(19)?,
+        deleted_at: row.get(20)?,
+        priority: row
+            .get::<Option<String>>(21)?
+            .map(|v| KeyPriority::parse(&v))
+            .unwrap_or_default(),
+    })
+}
+
+/// 解密存储的 key；主密钥不匹配时保留密文（该 key 无法通过认证）
+fn decrypt_key(stored: String) -> String {
+    match secret_store::decrypt(&stored) {
+        Ok(key) => key,
+        Err(e) => {
+            tracing::warn!("Failed to decrypt stored API key: {}", e);
+            stored
+        }
+    }
+}
+
+/// 将历史明文 key 加密存储并补充查找哈希，返回迁移条数
+fn encrypt_plaintext_keys(conn: &mut dyn Store) -> Result<usize, String> {
+    let rows = conn
+        .query("SELECT id, key FROM api_keys WHERE key_hash IS NULL OR key NOT LIKE 'enc:%'", params![])?
+        .iter()
+        .map(|row| Ok((row.get::<String>(0)?, row.get::<String>(1)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    storage::transaction(conn, |tx| {
+        for (id, stored) in &rows {
+            let key = secret_store::decrypt(stored)?;
+            tx.execute(
+                "UPDATE api_keys SET key = $1, key_hash = $2 WHERE id = $3",
+                params![secret_store::encrypt(&key)?, secret_store::lookup_hash(&key)?, id],
+            )?;
+        }
+        Ok(rows.len())
+    })
+}
+
 /// 初始化数据库
 pub fn init_db() -> Result<(), String> {
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = storage::open(Database::ApiKeys)?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS api_keys (
             id TEXT PRIMARY KEY,
             name TEXT NOT NULL,
             key TEXT NOT NULL UNIQUE,
-            enabled INTEGER NOT NULL DEFAULT 1,
-            created_at INTEGER NOT NULL,
-            last_used_at INTEGER,
-            total_requests INTEGER NOT NULL DEFAULT 0,
-            success_count INTEGER NOT NULL DEFAULT 0,
-            error_count INTEGER NOT NULL DEFAULT 0,
-            total_input_tokens INTEGER NOT NULL DEFAULT 0,
-            total_output_tokens INTEGER NOT NULL DEFAULT 0
+            enabled BIGINT NOT NULL DEFAULT 1,
+            created_at BIGINT NOT NULL,
+            last_used_at BIGINT,
+            total_requests BIGINT NOT NULL DEFAULT 0,
+            success_count BIGINT NOT NULL DEFAULT 0,
+            error_count BIGINT NOT NULL DEFAULT 0,
+            total_input_tokens BIGINT NOT NULL DEFAULT 0,
+            total_output_tokens BIGINT NOT NULL DEFAULT 0
         )",
-        [],
-    )
-    .map_err(|e| e.to_string())?;
+        params![],
+    )?;
 
     // 创建 key 索引用于快速查找
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_api_keys_key ON api_keys (key)",
-        [],
-    )
-    .map_err(|e| e.to_string())?;
+        params![],
+    )?;
+
+    // 旧库迁移：按需补充限流列（已存在时跳过）
+    conn.add_column("api_keys", "rate_limit_rpm BIGINT")?;
+    conn.add_column("api_keys", "rate_limit_rpd BIGINT")?;
+    conn.add_column("api_keys", "allowed_models TEXT")?;
+    conn.add_column("api_keys", "daily_token_budget BIGINT")?;
+    conn.add_column("api_keys", "expires_at BIGINT")?;
+    conn.add_column("api_keys", "allowed_ips TEXT")?;
+    conn.add_column("api_keys", "request_transform TEXT")?;
+    conn.add_column("api_keys", "total_cost DOUBLE PRECISION NOT NULL DEFAULT 0")?;
+    conn.add_column("api_keys", "pool TEXT")?;
+    conn.add_column("api_keys", "deleted_at BIGINT")?;
+    conn.add_column("api_keys", "priority TEXT")?;
+    // key 列存储密文，认证按 key_hash 检索
+    conn.add_column("api_keys", "key_hash TEXT")?;
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_api_keys_key_hash ON api_keys (key_hash)",
+        params![],
+    )?;
+
+    let migrated = encrypt_plaintext_keys(conn.as_mut())?;
+    if migrated > 0 {
+        tracing::info!("Encrypted {} stored API keys", migrated);
+    }
+
+    // 按自然日（预算时区）汇总的 token 用量，用于每日预算
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS api_key_daily_usage (
+            key_id TEXT NOT NULL,
+            day TEXT NOT NULL,
+            input_tokens BIGINT NOT NULL DEFAULT 0,
+            output_tokens BIGINT NOT NULL DEFAULT 0,
+            PRIMARY KEY (key_id, day)
+        )",
+        params![],
+    )?;
 
     Ok(())
 }
@@ -124,20 +360,18 @@ pub fn generate_key() -> String {
 }
 
 /// 创建新的 API Key
-pub fn create_api_key(name: &str) -> Result<ApiKey, String> {
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+pub fn create_api_key(name: &str, expires_at: Option<i64>) -> Result<ApiKey, String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
 
     let id = uuid::Uuid::new_v4().to_string();
     let key = generate_key();
     let created_at = chrono::Utc::now().timestamp();
 
     conn.execute(
-        "INSERT INTO api_keys (id, name, key, enabled, created_at, total_requests, success_count, error_count, total_input_tokens, total_output_tokens)
-         VALUES (?1, ?2, ?3, 1, ?4, 0, 0, 0, 0, 0)",
-        params![id, name, key, created_at],
-    )
-    .map_err(|e| e.to_string())?;
+        "INSERT INTO api_keys (id, name, key, key_hash, enabled, created_at, total_requests, success_count, error_count, total_input_tokens, total_output_tokens, expires_at)
+         VALUES ($1, $2, $3, $4, 1, $5, 0, 0, 0, 0, 0, $6)",
+        params![id, name, secret_store::encrypt(&key)?, secret_store::lookup_hash(&key)?, created_at, expires_at],
+    )?;
 
     Ok(ApiKey {
         id,
@@ -151,222 +385,369 @@ pub fn create_api_key(name: &str) -> Result<ApiKey, String> {
         error_count: 0,
         total_input_tokens: 0,
         total_output_tokens: 0,
+        total_cost: 0.0,
+        rate_limit_rpm: None,
+        rate_limit_rpd: None,
+        allowed_models: None,
+        daily_token_budget: None,
+        expires_at,
+        allowed_ips: None,
+        request_transform: None,
+        pool: None,
+        priority: KeyPriority::Normal,
+        deleted_at: None,
     })
 }
 
 /// 获取所有 API Keys
 pub fn list_api_keys() -> Result<Vec<ApiKey>, String> {
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare(
-            "SELECT id, name, key, enabled, created_at, last_used_at,
-                    total_requests, success_count, error_count,
-                    total_input_tokens, total_output_tokens
-             FROM api_keys ORDER BY created_at DESC",
-        )
-        .map_err(|e| e.to_string())?;
-
-    let keys_iter = stmt
-        .query_map([], |row| {
-            Ok(ApiKey {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                key: row.get(2)?,
-                enabled: row.get::<_, i32>(3)? == 1,
-                created_at: row.get(4)?,
-                last_used_at: row.get(5)?,
-                total_requests: row.get::<_, i64>(6)? as u64,
-                success_count: row.get::<_, i64>(7)? as u64,
-                error_count: row.get::<_, i64>(8)? as u64,
-                total_input_tokens: row.get::<_, i64>(9)? as u64,
-                total_output_tokens: row.get::<_, i64>(10)? as u64,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let mut keys = Vec::new();
-    for key in keys_iter {
-        keys.push(key.map_err(|e| e.to_string())?);
-    }
-    Ok(keys)
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    conn.query(
+        &format!(
+            "SELECT {} FROM api_keys WHERE deleted_at IS NULL ORDER BY created_at DESC",
+            API_KEY_COLUMNS
+        ),
+        params![],
+    )?
+    .iter()
+    .map(row_to_api_key)
+    .collect()
 }
 
 /// 根据 key 字符串查找 API Key（用于认证）
 pub fn find_by_key(key_str: &str) -> Result<Option<ApiKey>, String> {
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare(
-            "SELECT id, name, key, enabled, created_at, last_used_at,
-                    total_requests, success_count, error_count,
-                    total_input_tokens, total_output_tokens
-             FROM api_keys WHERE key = ?1",
-        )
-        .map_err(|e| e.to_string())?;
-
-    let result = stmt.query_row([key_str], |row| {
-        Ok(ApiKey {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            key: row.get(2)?,
-            enabled: row.get::<_, i32>(3)? == 1,
-            created_at: row.get(4)?,
-            last_used_at: row.get(5)?,
-            total_requests: row.get::<_, i64>(6)? as u64,
-            success_count: row.get::<_, i64>(7)? as u64,
-            error_count: row.get::<_, i64>(8)? as u64,
-            total_input_tokens: row.get::<_, i64>(9)? as u64,
-            total_output_tokens: row.get::<_, i64>(10)? as u64,
-        })
-    });
-
-    match result {
-        Ok(key) => Ok(Some(key)),
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(e.to_string()),
-    }
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    conn.query_row(
+        &format!("SELECT {} FROM api_keys WHERE key_hash = $1 AND deleted_at IS NULL", API_KEY_COLUMNS),
+        params![secret_store::lookup_hash(key_str)?],
+    )?
+    .map(|row| row_to_api_key(&row))
+    .transpose()
 }
 
 /// 获取单个 API Key
 pub fn get_api_key(id: &str) -> Result<Option<ApiKey>, String> {
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare(
-            "SELECT id, name, key, enabled, created_at, last_used_at,
-                    total_requests, success_count, error_count,
-                    total_input_tokens, total_output_tokens
-             FROM api_keys WHERE id = ?1",
-        )
-        .map_err(|e| e.to_string())?;
-
-    let result = stmt.query_row([id], |row| {
-        Ok(ApiKey {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            key: row.get(2)?,
-            enabled: row.get::<_, i32>(3)? == 1,
-            created_at: row.get(4)?,
-            last_used_at: row.get(5)?,
-            total_requests: row.get::<_, i64>(6)? as u64,
-            success_count: row.get::<_, i64>(7)? as u64,
-            error_count: row.get::<_, i64>(8)? as u64,
-            total_input_tokens: row.get::<_, i64>(9)? as u64,
-            total_output_tokens: row.get::<_, i64>(10)? as u64,
-        })
-    });
+    let mut conn = storage::open(Database::ApiKeys)?;
 
-    match result {
-        Ok(key) => Ok(Some(key)),
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(e.to_string()),
-    }
+    conn.query_row(
+        &format!("SELECT {} FROM api_keys WHERE id = $1 AND deleted_at IS NULL", API_KEY_COLUMNS),
+        params![id],
+    )?
+    .map(|row| row_to_api_key(&row))
+    .transpose()
+}
+
+/// 按名称查找 API Key（名称不唯一，返回所有同名 key）
+pub fn find_by_name(name: &str) -> Result<Vec<ApiKey>, String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    conn.query(
+        &format!("SELECT {} FROM api_keys WHERE name = $1 AND deleted_at IS NULL ORDER BY created_at", API_KEY_COLUMNS),
+        params![name],
+    )?
+    .iter()
+    .map(row_to_api_key)
+    .collect()
 }
 
 /// 更新 API Key 名称
 pub fn update_api_key_name(id: &str, name: &str) -> Result<(), String> {
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = storage::open(Database::ApiKeys)?;
 
     conn.execute(
-        "UPDATE api_keys SET name = ?1 WHERE id = ?2",
+        "UPDATE api_keys SET name = $1 WHERE id = $2",
         params![name, id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     Ok(())
 }
 
 /// 启用/禁用 API Key
 pub fn set_api_key_enabled(id: &str, enabled: bool) -> Result<(), String> {
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = storage::open(Database::ApiKeys)?;
 
     conn.execute(
-        "UPDATE api_keys SET enabled = ?1 WHERE id = ?2",
+        "UPDATE api_keys SET enabled = $1 WHERE id = $2",
         params![if enabled { 1 } else { 0 }, id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+
+    Ok(())
+}
+
+/// 设置 API Key 限流（None 表示不限制）
+pub fn set_api_key_rate_limits(
+    id: &str,
+    rpm: Option<u32>,
+    rpd: Option<u32>,
+) -> Result<(), String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    conn.execute(
+        "UPDATE api_keys SET rate_limit_rpm = $1, rate_limit_rpd = $2 WHERE id = $3",
+        params![rpm, rpd, id],
+    )?;
+
+    Ok(())
+}
+
+/// 设置 API Key 模型白名单（None 表示不限制）
+pub fn set_api_key_allowed_models(id: &str, models: Option<&[String]>) -> Result<(), String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    let value = match models {
+        Some(list) => Some(serde_json::to_string(list).map_err(|e| e.to_string())?),
+        None => None,
+    };
+
+    conn.execute(
+        "UPDATE api_keys SET allowed_models = $1 WHERE id = $2",
+        params![value, id],
+    )?;
 
     Ok(())
 }
 
-/// 删除 API Key
+/// 设置 API Key 每日 token 预算（None 表示不限制）
+pub fn set_api_key_daily_token_budget(id: &str, budget: Option<u64>) -> Result<(), String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    conn.execute(
+        "UPDATE api_keys SET daily_token_budget = $1 WHERE id = $2",
+        params![budget.map(|v| v as i64), id],
+    )?;
+
+    Ok(())
+}
+
+/// 设置 API Key 允许的客户端 IP（None 表示不限制）
+pub fn set_api_key_allowed_ips(id: &str, ips: Option<&[String]>) -> Result<(), String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    let value = match ips {
+        Some(list) => Some(serde_json::to_string(list).map_err(|e| e.to_string())?),
+        None => None,
+    };
+
+    conn.execute(
+        "UPDATE api_keys SET allowed_ips = $1 WHERE id = $2",
+        params![value, id],
+    )?;
+
+    Ok(())
+}
+
+/// 设置 API Key 请求改写规则（None 表示不改写）
+pub fn set_api_key_request_transform(id: &str, transform: Option<&RequestTransform>) -> Result<(), String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    let value = match transform {
+        Some(t) => Some(serde_json::to_string(t).map_err(|e| e.to_string())?),
+        None => None,
+    };
+
+    conn.execute(
+        "UPDATE api_keys SET request_transform = $1 WHERE id = $2",
+        params![value, id],
+    )?;
+
+    Ok(())
+}
+
+/// 设置 API Key 绑定的账号池（None 表示不限制）
+pub fn set_api_key_pool(id: &str, pool: Option<&str>) -> Result<(), String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    conn.execute(
+        "UPDATE api_keys SET pool = $1 WHERE id = $2",
+        params![pool, id],
+    )?;
+
+    Ok(())
+}
+
+/// 设置 API Key 优先级（normal 存为 NULL）
+pub fn set_api_key_priority(id: &str, priority: KeyPriority) -> Result<(), String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    let value = (priority != KeyPriority::Normal).then(|| priority.as_str());
+    conn.execute(
+        "UPDATE api_keys SET priority = $1 WHERE id = $2",
+        params![value, id],
+    )?;
+
+    Ok(())
+}
+
+/// 设置 API Key 过期时间（None 表示永不过期）
+pub fn set_api_key_expires_at(id: &str, expires_at: Option<i64>) -> Result<(), String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    conn.execute(
+        "UPDATE api_keys SET expires_at = $1 WHERE id = $2",
+        params![expires_at, id],
+    )?;
+
+    Ok(())
+}
+
+/// 删除 API Key（软删除：保留用量历史，保留期内可恢复）
 pub fn delete_api_key(id: &str) -> Result<(), String> {
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = storage::open(Database::ApiKeys)?;
 
-    conn.execute("DELETE FROM api_keys WHERE id = ?1", params![id])
-        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "UPDATE api_keys SET deleted_at = $1 WHERE id = $2 AND deleted_at IS NULL",
+        params![chrono::Utc::now().timestamp(), id],
+    )?;
 
     Ok(())
 }
 
+/// 列出已删除（尚未清除）的 API Keys
+pub fn list_deleted_api_keys() -> Result<Vec<ApiKey>, String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    conn.query(
+        &format!(
+            "SELECT {} FROM api_keys WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+            API_KEY_COLUMNS
+        ),
+        params![],
+    )?
+    .iter()
+    .map(row_to_api_key)
+    .collect()
+}
+
+/// 恢复已删除的 API Key，返回是否恢复（key 不存在或未删除时为 false）
+pub fn restore_api_key(id: &str) -> Result<bool, String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    let changed = conn.execute(
+        "UPDATE api_keys SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL",
+        params![id],
+    )?;
+
+    Ok(changed > 0)
+}
+
+/// 永久清除删除时间早于 cutoff 的 key 及其每日用量，返回清除数量
+fn purge_deleted_before(conn: &mut dyn Store, cutoff: i64) -> Result<Vec<String>, String> {
+    let ids = conn
+        .query("SELECT id FROM api_keys WHERE deleted_at IS NOT NULL AND deleted_at <= $1", params![cutoff])?
+        .iter()
+        .map(|row| row.get::<String>(0))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for id in &ids {
+        conn.execute("DELETE FROM api_keys WHERE id = $1", params![id])?;
+        conn.execute("DELETE FROM api_key_daily_usage WHERE key_id = $1", params![id])?;
+    }
+    Ok(ids)
+}
+
+/// 已删除 key 的保留天数，0 表示不自动清除；启动 / 热重载时由配置写入
+static DELETED_KEY_RETENTION_DAYS: AtomicU32 = AtomicU32::new(crate::proxy::config::DEFAULT_DELETED_KEY_RETENTION_DAYS);
+/// 清除任务的检查间隔
+const PURGE_INTERVAL_SECS: u64 = 3600;
+
+pub fn set_deleted_key_retention_days(days: u32) {
+    DELETED_KEY_RETENTION_DAYS.store(days, Ordering::Relaxed);
+}
+
+/// 启动后台任务，定期清除超过保留期的已删除 key
+pub fn spawn_purge_task() {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(PURGE_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            let days = DELETED_KEY_RETENTION_DAYS.load(Ordering::Relaxed);
+            if days == 0 {
+                continue;
+            }
+            match tokio::task::spawn_blocking(move || purge_deleted_keys(days)).await {
+                Ok(Ok(0)) => {}
+                Ok(Ok(purged)) => tracing::info!("[ApiKeys] Purged {} deleted API keys", purged),
+                Ok(Err(e)) => tracing::error!("Failed to purge deleted API keys: {}", e),
+                Err(e) => tracing::error!("API key purge task failed: {}", e),
+            }
+        }
+    });
+}
+
+/// 清除超过保留期的已删除 key
+pub fn purge_deleted_keys(retention_days: u32) -> Result<usize, String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    let cutoff = chrono::Utc::now().timestamp() - retention_days as i64 * 86_400;
+    let ids = purge_deleted_before(conn.as_mut(), cutoff)?;
+    for id in &ids {
+        crate::modules::usage_buffer::USAGE_BUFFER.discard(id);
+    }
+    Ok(ids.len())
+}
+
 /// 重新生成 API Key
 pub fn regenerate_api_key(id: &str) -> Result<String, String> {
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = storage::open(Database::ApiKeys)?;
 
     let new_key = generate_key();
 
     conn.execute(
-        "UPDATE api_keys SET key = ?1 WHERE id = ?2",
-        params![new_key, id],
-    )
-    .map_err(|e| e.to_string())?;
+        "UPDATE api_keys SET key = $1, key_hash = $2 WHERE id = $3",
+        params![secret_store::encrypt(&new_key)?, secret_store::lookup_hash(&new_key)?, id],
+    )?;
 
     Ok(new_key)
 }
 
-/// 记录 API Key 使用（用于统计）
-pub fn record_usage(
-    key_str: &str,
-    success: bool,
-    input_tokens: Option<u32>,
-    output_tokens: Option<u32>,
-) -> Result<(), String> {
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let now = chrono::Utc::now().timestamp();
-    let input = input_tokens.unwrap_or(0) as i64;
-    let output = output_tokens.unwrap_or(0) as i64;
-
-    if success {
-        conn.execute(
-            "UPDATE api_keys SET
-                last_used_at = ?1,
-                total_requests = total_requests + 1,
-                success_count = success_count + 1,
-                total_input_tokens = total_input_tokens + ?2,
-                total_output_tokens = total_output_tokens + ?3
-             WHERE key = ?4",
-            params![now, input, output, key_str],
-        )
-        .map_err(|e| e.to_string())?;
-    } else {
-        conn.execute(
-            "UPDATE api_keys SET
-                last_used_at = ?1,
-                total_requests = total_requests + 1,
-                error_count = error_count + 1
-             WHERE key = ?2",
-            params![now, key_str],
-        )
-        .map_err(|e| e.to_string())?;
-    }
-
-    Ok(())
+/// 批量写入 API Key 用量增量（由 usage_buffer 定期调用），所有 Key 在同一事务中更新
+pub fn apply_usage_deltas(deltas: &HashMap<String, UsageDelta>) -> Result<(), String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+    storage::transaction(conn.as_mut(), |tx| {
+        for (key_id, delta) in deltas {
+            tx.execute(
+                "UPDATE api_keys SET
+                    last_used_at = $1,
+                    total_requests = total_requests + $2,
+                    success_count = success_count + $3,
+                    error_count = error_count + $4,
+                    total_input_tokens = total_input_tokens + $5,
+                    total_output_tokens = total_output_tokens + $6,
+                    total_cost = total_cost + $7
+                 WHERE id = $8",
+                params![
+                    delta.last_used_at,
+                    delta.requests as i64,
+                    delta.success as i64,
+                    delta.errors as i64,
+                    delta.input_tokens as i64,
+                    delta.output_tokens as i64,
+                    delta.cost,
+                    key_id
+                ],
+            )?;
+
+            // 冲突更新中的原值需带表名限定（PostgreSQL 不允许省略）
+            for (day, (input, output)) in &delta.daily {
+                tx.execute(
+                    "INSERT INTO api_key_daily_usage (key_id, day, input_tokens, output_tokens)
+                     SELECT id, $1, $2, $3 FROM api_keys WHERE id = $4
+                     ON CONFLICT(key_id, day) DO UPDATE SET
+                        input_tokens = api_key_daily_usage.input_tokens + excluded.input_tokens,
+                        output_tokens = api_key_daily_usage.output_tokens + excluded.output_tokens",
+                    params![day, *input as i64, *output as i64, key_id],
+                )?;
+            }
+        }
+        Ok(())
+    })
 }
 
 /// 重置 API Key 用量统计
 pub fn reset_usage(id: &str) -> Result<(), String> {
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = storage::open(Database::ApiKeys)?;
 
     conn.execute(
         "UPDATE api_keys SET
@@ -374,19 +755,34 @@ pub fn reset_usage(id: &str) -> Result<(), String> {
             success_count = 0,
             error_count = 0,
             total_input_tokens = 0,
-            total_output_tokens = 0
-         WHERE id = ?1",
+            total_output_tokens = 0,
+            total_cost = 0
+         WHERE id = $1",
         params![id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+    conn.execute("DELETE FROM api_key_daily_usage WHERE key_id = $1", params![id])?;
 
     Ok(())
 }
 
+/// 获取 API Key 某一天（预算时区）的 token 用量（输入 + 输出）
+pub fn get_daily_tokens(key_id: &str, day: &str) -> Result<u64, String> {
+    let mut conn = storage::open(Database::ApiKeys)?;
+
+    let total: i64 = conn
+        .query_row(
+            "SELECT COALESCE(SUM(input_tokens + output_tokens), 0)
+             FROM api_key_daily_usage WHERE key_id = $1 AND day = $2",
+            params![key_id, day],
+        )?
+        .map_or(Ok(0), |row| row.get(0))?;
+
+    Ok(total as u64 + crate::modules::usage_buffer::USAGE_BUFFER.pending_daily_tokens(key_id, day))
+}
+
 /// 获取所有 API Keys 的总用量
 pub fn get_total_usage() -> Result<ApiKeyUsage, String> {
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = storage::open(Database::ApiKeys)?;
 
     let result = conn.query_row(
         "SELECT
@@ -394,34 +790,35 @@ pub fn get_total_usage() -> Result<ApiKeyUsage, String> {
             COALESCE(SUM(success_count), 0),
             COALESCE(SUM(error_count), 0),
             COALESCE(SUM(total_input_tokens), 0),
-            COALESCE(SUM(total_output_tokens), 0)
-         FROM api_keys",
-        [],
-        |row| {
-            Ok(ApiKeyUsage {
-                total_requests: row.get::<_, i64>(0)? as u64,
-                success_count: row.get::<_, i64>(1)? as u64,
-                error_count: row.get::<_, i64>(2)? as u64,
-                total_input_tokens: row.get::<_, i64>(3)? as u64,
-                total_output_tokens: row.get::<_, i64>(4)? as u64,
-            })
-        },
+            COALESCE(SUM(total_output_tokens), 0),
+            COALESCE(SUM(total_cost), 0)
+         FROM api_keys WHERE deleted_at IS NULL",
+        params![],
     );
+    let usage = |row: Row| -> Result<ApiKeyUsage, String> {
+        Ok(ApiKeyUsage {
+            total_requests: row.get::<i64>(0)? as u64,
+            success_count: row.get::<i64>(1)? as u64,
+            error_count: row.get::<i64>(2)? as u64,
+            total_input_tokens: row.get::<i64>(3)? as u64,
+            total_output_tokens: row.get::<i64>(4)? as u64,
+            estimated_cost: row.get(5)?,
+        })
+    };
 
-    match result {
-        Ok(usage) => Ok(usage),
-        Err(_) => Ok(ApiKeyUsage::default()),
+    match result.map(|row| row.map(usage)) {
+        Ok(Some(Ok(usage))) => Ok(usage),
+        _ => Ok(ApiKeyUsage::default()),
     }
 }
 
 /// 检查是否有任何 API Key 存在
 pub fn has_any_keys() -> Result<bool, String> {
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = storage::open(Database::ApiKeys)?;
 
     let count: i64 = conn
-        .query_row("SELECT COUNT(*) FROM api_keys", [], |row| row.get(0))
-        .map_err(|e| e.to_string())?;
+        .query_row("SELECT COUNT(*) FROM api_keys", params![])?
+        .map_or(Ok(0), |row| row.get(0))?;
 
     Ok(count > 0)
 }
@@ -443,19 +840,114 @@ pub fn migrate_legacy_key(legacy_key: &str) -> Result<(), String> {
     }
 
     // 创建迁移的 key
-    let db_path = get_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = storage::open(Database::ApiKeys)?;
 
     let id = uuid::Uuid::new_v4().to_string();
     let created_at = chrono::Utc::now().timestamp();
 
     conn.execute(
-        "INSERT INTO api_keys (id, name, key, enabled, created_at, total_requests, success_count, error_count, total_input_tokens, total_output_tokens)
-         VALUES (?1, ?2, ?3, 1, ?4, 0, 0, 0, 0, 0)",
-        params![id, "Default (Migrated)", legacy_key, created_at],
-    )
-    .map_err(|e| e.to_string())?;
+        "INSERT INTO api_keys (id, name, key, key_hash, enabled, created_at, total_requests, success_count, error_count, total_input_tokens, total_output_tokens)
+         VALUES ($1, $2, $3, $4, 1, $5, 0, 0, 0, 0, 0)",
+        params![
+            id,
+            "Default (Migrated)",
+            secret_store::encrypt(legacy_key)?,
+            secret_store::lookup_hash(legacy_key)?,
+            created_at
+        ],
+    )?;
 
     tracing::info!("Migrated legacy API key to new multi-key system");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_with_models(models: Option<Vec<&str>>) -> ApiKey {
+        ApiKey {
+            id: "id".to_string(),
+            name: "test".to_string(),
+            key: generate_key(),
+            enabled: true,
+            created_at: 0,
+            last_used_at: None,
+            total_requests: 0,
+            success_count: 0,
+            error_count: 0,
+            total_input_tokens: 0,
+            total_output_tokens: 0,
+            total_cost: 0.0,
+            rate_limit_rpm: None,
+            rate_limit_rpd: None,
+            allowed_models: models.map(|m| m.into_iter().map(String::from).collect()),
+            daily_token_budget: None,
+            expires_at: None,
+            allowed_ips: None,
+            request_transform: None,
+            pool: None,
+            priority: KeyPriority::Normal,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn test_model_allowlist() {
+        assert!(key_with_models(None).is_model_allowed("gemini-3-pro-high"));
+
+        let key = key_with_models(Some(vec!["gemini-2.0-flash", "claude-sonnet-*"]));
+        assert!(key.is_model_allowed("gemini-2.0-flash"));
+        assert!(key.is_model_allowed("claude-sonnet-4-5-thinking"));
+        assert!(!key.is_model_allowed("gemini-2.0-flash-exp"));
+        assert!(!key.is_model_allowed("claude-opus-4-5"));
+
+        assert!(!key_with_models(Some(vec![])).is_model_allowed("gemini-2.0-flash"));
+    }
+
+    #[test]
+    fn test_ip_allowlist() {
+        let mut key = key_with_models(None);
+        assert!(key.is_ip_allowed(None));
+
+        key.allowed_ips = Some(vec!["203.0.113.0/24".to_string()]);
+        assert!(key.is_ip_allowed(Some("203.0.113.10".parse().unwrap())));
+        assert!(!key.is_ip_allowed(Some("198.51.100.1".parse().unwrap())));
+        assert!(!key.is_ip_allowed(None));
+    }
+
+    #[test]
+    fn test_expiration() {
+        let mut key = key_with_models(None);
+        assert!(!key.is_expired_at(i64::MAX));
+
+        key.expires_at = Some(1_000);
+        assert!(!key.is_expired_at(999));
+        assert!(key.is_expired_at(1_000));
+        assert!(key.is_expired_at(2_000));
+    }
+
+    #[test]
+    fn test_purge_deleted_before() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE api_keys (id TEXT PRIMARY KEY, deleted_at INTEGER);
+             CREATE TABLE api_key_daily_usage (key_id TEXT, day TEXT);
+             INSERT INTO api_keys VALUES ('live', NULL), ('old', 100), ('recent', 900);
+             INSERT INTO api_key_daily_usage VALUES ('old', '2026-01-01'), ('recent', '2026-01-01');",
+        )
+        .unwrap();
+
+        assert_eq!(purge_deleted_before(&mut conn, 500).unwrap(), vec!["old".to_string()]);
+        let remaining: Vec<String> = conn
+            .prepare("SELECT id FROM api_keys ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(remaining, vec!["live", "recent"]);
+        let usage: i64 = conn.query_row("SELECT COUNT(*) FROM api_key_daily_usage", [], |row| row.get(0)).unwrap();
+        assert_eq!(usage, 1);
+    }
+}
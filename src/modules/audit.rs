@@ -0,0 +1,313 @@
+//! 管理操作审计日志
+//! 记录管理后台的每一次变更（API Key / 账号 / 配置 / Passkey），只追加不修改，供合规审查浏览与导出
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+
+/// 未登录（如首次设置 Passkey / 密码）时记录的操作者
+pub const ANONYMOUS_ACTOR: &str = "anonymous";
+
+/// SELECT 使用的列顺序，需与 row_to_entry 保持一致
+const AUDIT_COLUMNS: &str = "id, timestamp, actor, client_ip, action, target, before, after";
+
+const DEFAULT_QUERY_LIMIT: usize = 100;
+const MAX_QUERY_LIMIT: usize = 1000;
+
+/// 单条审计记录
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// 毫秒时间戳
+    pub timestamp: i64,
+    /// 操作者（`password` / `passkey:<名称>` / `anonymous`）
+    pub actor: String,
+    pub client_ip: Option<String>,
+    /// 操作类型，如 `api_key.created`
+    pub action: String,
+    /// 操作对象 ID
+    pub target: Option<String>,
+    /// 变更前快照
+    pub before: Option<Value>,
+    /// 变更后快照
+    pub after: Option<Value>,
+}
+
+/// 审计日志查询条件 (/api/audit)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditQuery {
+    /// 起始时间 (毫秒时间戳，含)
+    pub from: Option<i64>,
+    /// 结束时间 (毫秒时间戳，含)
+    pub to: Option<i64>,
+    pub actor: Option<String>,
+    /// 精确匹配，或以 `.` 结尾按前缀匹配（如 `api_key.`）
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+tokio::task_local! {
+    /// 当前管理请求的操作者与来源 IP（web_auth 中间件设置作用域）
+    static CURRENT_ACTOR: (String, Option<String>);
+}
+
+/// 在操作者作用域内执行请求
+pub async fn with_actor<F: std::future::Future>(actor: String, client_ip: Option<String>, fut: F) -> F::Output {
+    CURRENT_ACTOR.scope((actor, client_ip), fut).await
+}
+
+/// 当前操作者与来源 IP（不在管理请求作用域内时为匿名）
+pub fn current_actor() -> (String, Option<String>) {
+    CURRENT_ACTOR
+        .try_with(|a| a.clone())
+        .unwrap_or_else(|_| (ANONYMOUS_ACTOR.to_string(), None))
+}
+
+fn get_db_path() -> Result<PathBuf, String> {
+    let data_dir = crate::modules::account::get_data_dir()?;
+    Ok(data_dir.join("audit_log.db"))
+}
+
+fn open_db() -> Result<crate::modules::db_pool::PooledConnection, String> {
+    let conn = crate::modules::db_pool::open(&get_db_path()?)?;
+    ensure_schema(&conn)?;
+    Ok(conn)
+}
+
+pub fn init_db() -> Result<(), String> {
+    open_db().map(|_| ())
+}
+
+fn ensure_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp INTEGER NOT NULL,
+            actor TEXT NOT NULL,
+            client_ip TEXT,
+            action TEXT NOT NULL,
+            target TEXT,
+            before TEXT,
+            after TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_audit_timestamp ON audit_log (timestamp DESC);
+        CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'audit log is append-only'); END;
+        CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'audit log is append-only'); END;",
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn insert_entry(
+    conn: &Connection,
+    actor: &str,
+    client_ip: Option<&str>,
+    action: &str,
+    target: Option<&str>,
+    before: Option<&Value>,
+    after: Option<&Value>,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO audit_log (timestamp, actor, client_ip, action, target, before, after)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            chrono::Utc::now().timestamp_millis(),
+            actor,
+            client_ip,
+            action,
+            target,
+            before.map(|v| v.to_string()),
+            after.map(|v| v.to_string()),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// 将对象序列化为快照
+pub fn snapshot<T: Serialize>(value: &T) -> Option<Value> {
+    serde_json::to_value(value).ok()
+}
+
+/// 记录一次管理操作；写入失败只记录日志，不影响操作本身
+pub fn record(action: &str, target: Option<&str>, before: Option<Value>, after: Option<Value>) {
+    let (actor, client_ip) = current_actor();
+    let result = open_db().and_then(|conn| {
+        insert_entry(&conn, &actor, client_ip.as_deref(), action, target, before.as_ref(), after.as_ref())
+    });
+    if let Err(e) = result {
+        tracing::error!("[Audit] Failed to record {} by {}: {}", action, actor, e);
+    }
+}
+
+fn row_to_entry(row: &rusqlite::Row) -> rusqlite::Result<AuditEntry> {
+    let parse = |raw: Option<String>| raw.and_then(|s| serde_json::from_str(&s).ok());
+    Ok(AuditEntry {
+        id: row.get(0)?,
+        timestamp: row.get(1)?,
+        actor: row.get(2)?,
+        client_ip: row.get(3)?,
+        action: row.get(4)?,
+        target: row.get(5)?,
+        before: parse(row.get(6)?),
+        after: parse(row.get(7)?),
+    })
+}
+
+/// 构建 WHERE 子句及参数
+fn build_filter(query: &AuditQuery) -> (String, Vec<Box<dyn rusqlite::ToSql>>) {
+    let mut clauses: Vec<&str> = Vec::new();
+    let mut values: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+    if let Some(from) = query.from {
+        clauses.push("timestamp >= ?");
+        values.push(Box::new(from));
+    }
+    if let Some(to) = query.to {
+        clauses.push("timestamp <= ?");
+        values.push(Box::new(to));
+    }
+    if let Some(actor) = query.actor.as_ref().filter(|v| !v.is_empty()) {
+        clauses.push("actor = ?");
+        values.push(Box::new(actor.clone()));
+    }
+    if let Some(action) = query.action.as_ref().filter(|v| !v.is_empty()) {
+        if action.ends_with('.') {
+            clauses.push("substr(action, 1, ?) = ?");
+            values.push(Box::new(action.len() as i64));
+        } else {
+            clauses.push("action = ?");
+        }
+        values.push(Box::new(action.clone()));
+    }
+    if let Some(target) = query.target.as_ref().filter(|v| !v.is_empty()) {
+        clauses.push("target = ?");
+        values.push(Box::new(target.clone()));
+    }
+
+    let where_sql = if clauses.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", clauses.join(" AND "))
+    };
+    (where_sql, values)
+}
+
+fn query_entries(conn: &Connection, query: &AuditQuery, paginate: bool) -> Result<(u64, Vec<AuditEntry>), String> {
+    let (where_sql, mut values) = build_filter(query);
+
+    let total: u64 = conn
+        .query_row(
+            &format!("SELECT COUNT(*) FROM audit_log{}", where_sql),
+            rusqlite::params_from_iter(values.iter()),
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut sql = format!("SELECT {} FROM audit_log{} ORDER BY id DESC", AUDIT_COLUMNS, where_sql);
+    if paginate {
+        let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT).clamp(1, MAX_QUERY_LIMIT);
+        values.push(Box::new(limit as i64));
+        values.push(Box::new(query.offset.unwrap_or(0) as i64));
+        sql.push_str(" LIMIT ? OFFSET ?");
+    }
+
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(rusqlite::params_from_iter(values.iter()), row_to_entry)
+        .map_err(|e| e.to_string())?;
+
+    let mut entries = Vec::new();
+    for entry in rows {
+        entries.push(entry.map_err(|e| e.to_string())?);
+    }
+    Ok((total, entries))
+}
+
+/// 按条件分页查询，返回 (匹配总数, 当前页)
+pub fn query(query: &AuditQuery) -> Result<(u64, Vec<AuditEntry>), String> {
+    query_entries(&*open_db()?, query, true)
+}
+
+/// 导出所有匹配记录为 CSV（忽略分页参数）
+pub fn export_csv(query: &AuditQuery) -> Result<String, String> {
+    let (_, entries) = query_entries(&*open_db()?, query, false)?;
+    Ok(to_csv(&entries))
+}
+
+fn to_csv(entries: &[AuditEntry]) -> String {
+    let mut out = String::from("id,timestamp,actor,client_ip,action,target,before,after\n");
+    for entry in entries {
+        let timestamp = chrono::DateTime::from_timestamp_millis(entry.timestamp)
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_default();
+        let json = |v: &Option<Value>| v.as_ref().map(|v| v.to_string()).unwrap_or_default();
+        let fields = [
+            entry.id.to_string(),
+            timestamp,
+            entry.actor.clone(),
+            entry.client_ip.clone().unwrap_or_default(),
+            entry.action.clone(),
+            entry.target.clone().unwrap_or_default(),
+            json(&entry.before),
+            json(&entry.after),
+        ];
+        crate::utils::csv::push_row(&mut out, &fields);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn test_conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+        insert_entry(&conn, "password", Some("127.0.0.1"), "api_key.created", Some("k1"), None, Some(&json!({"name": "ci"}))).unwrap();
+        insert_entry(&conn, "passkey:laptop", None, "api_key.deleted", Some("k1"), Some(&json!({"name": "ci"})), None).unwrap();
+        insert_entry(&conn, "password", None, "account.removed", Some("a1"), None, None).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_audit_log_is_append_only() {
+        let conn = test_conn();
+        assert!(conn.execute("UPDATE audit_log SET actor = 'x'", []).is_err());
+        assert!(conn.execute("DELETE FROM audit_log", []).is_err());
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM audit_log", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let conn = test_conn();
+        let (total, entries) = query_entries(&conn, &AuditQuery::default(), true).unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(entries[0].action, "account.removed");
+
+        let by_prefix = AuditQuery { action: Some("api_key.".to_string()), ..Default::default() };
+        let (total, entries) = query_entries(&conn, &by_prefix, true).unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(entries[1].after, Some(json!({"name": "ci"})));
+
+        let by_actor = AuditQuery { actor: Some("password".to_string()), limit: Some(1), ..Default::default() };
+        let (total, entries) = query_entries(&conn, &by_actor, true).unwrap();
+        assert_eq!((total, entries.len()), (2, 1));
+    }
+
+    #[test]
+    fn test_to_csv_escapes_fields() {
+        let conn = test_conn();
+        let (_, entries) = query_entries(&conn, &AuditQuery { target: Some("k1".to_string()), ..Default::default() }, false).unwrap();
+        let csv = to_csv(&entries);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].ends_with(",api_key.created,k1,,\"{\"\"name\"\":\"\"ci\"\"}\""));
+    }
+}
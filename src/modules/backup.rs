@@ -0,0 +1,495 @@
+//! 整库备份与恢复
+//!
+//! 将数据目录中的 SQLite 数据库、配置、账号与密钥文件打包为一个口令加密的归档
+//! （加密方式同账号导出）。恢复时先解密写入暂存目录，下次启动、打开任何数据库之前再替换到位，
+//! 避免覆盖运行中进程已打开的数据库文件。定时备份写入本地目录或 S3 兼容存储，并按数量保留
+
+use argon2::Params;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use crate::modules::account_archive::{self, KdfParams, Sealed};
+use crate::modules::s3::S3Client;
+use crate::proxy::config::BackupConfig;
+
+pub const BACKUP_FORMAT: &str = "antiproxy-backup";
+const BACKUP_VERSION: u32 = 1;
+const BACKUP_AAD: &[u8] = b"antiproxy-backup:v1";
+
+/// 待恢复内容的暂存目录（位于数据目录下）
+const STAGING_DIR: &str = "restore-pending";
+/// 暂存完成的标记，列出需要替换的文件
+const STAGING_MANIFEST: &str = "manifest.json";
+const DEFAULT_BACKUP_DIR: &str = "backups";
+const FILE_PREFIX: &str = "antiproxy-backup-";
+const FILE_SUFFIX: &str = ".json";
+const FILE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const LOGS_DB: &str = "proxy_logs.db";
+/// 随备份打包的子目录（只包含其中的文件，不递归）
+const INCLUDED_DIRS: &[&str] = &["accounts", "tls"];
+/// 定时任务检查间隔
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 加密备份归档（下载 / 写入备份目录的文件内容）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupArchive {
+    pub format: String,
+    pub version: u32,
+    pub created_at: i64,
+    pub file_count: usize,
+    pub kdf: KdfParams,
+    pub cipher: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupFile {
+    /// 相对数据目录的路径
+    path: String,
+    /// base64 编码的文件内容
+    data: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupPayload {
+    created_at: i64,
+    files: Vec<BackupFile>,
+}
+
+/// 恢复结果
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreSummary {
+    /// 归档的创建时间
+    pub created_at: i64,
+    pub files: Vec<String>,
+}
+
+pub fn validate(config: &BackupConfig) -> Result<(), String> {
+    if config.interval_hours > 0 && config.passphrase.chars().count() < account_archive::MIN_PASSPHRASE_LEN {
+        return Err(format!(
+            "passphrase of at least {} characters is required for scheduled backups",
+            account_archive::MIN_PASSPHRASE_LEN
+        ));
+    }
+    if config.retention == 0 {
+        return Err("retention must be at least 1".to_string());
+    }
+    if let Some(s3) = &config.s3 {
+        crate::modules::s3::validate(s3).map_err(|e| format!("s3: {}", e))?;
+    }
+    Ok(())
+}
+
+/// 不打包的文件：SQLite 的 WAL / 共享内存文件（数据库以快照方式导出）与写入中的临时文件
+fn is_skipped_file(name: &str) -> bool {
+    name.ends_with("-wal") || name.ends_with("-shm") || name.ends_with(".tmp")
+}
+
+/// 导出数据库的一致快照（VACUUM INTO 在读事务中完成，不阻塞写入）
+fn snapshot_db(path: &Path) -> Result<Vec<u8>, String> {
+    let temp = path.with_extension("db.backup.tmp");
+    let _ = fs::remove_file(&temp);
+    let conn = crate::modules::db_pool::open(path)?;
+    conn.execute("VACUUM INTO ?1", [temp.to_string_lossy()])
+        .map_err(|e| format!("Failed to snapshot {}: {}", path.display(), e))?;
+    drop(conn);
+    let data = fs::read(&temp).map_err(|e| e.to_string());
+    let _ = fs::remove_file(&temp);
+    data
+}
+
+fn collect_files(data_dir: &Path, include_logs: bool) -> Result<Vec<BackupFile>, String> {
+    let mut files = Vec::new();
+    let mut push = |path: String, data: Vec<u8>| files.push(BackupFile { path, data: STANDARD.encode(data) });
+
+    let mut entries: Vec<_> = fs::read_dir(data_dir).map_err(|e| e.to_string())?.flatten().collect();
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path();
+        if path.is_dir() {
+            if !INCLUDED_DIRS.contains(&name.as_str()) {
+                continue;
+            }
+            let mut children: Vec<_> = fs::read_dir(&path).map_err(|e| e.to_string())?.flatten().collect();
+            children.sort_by_key(|e| e.file_name());
+            for child in children {
+                let child_name = child.file_name().to_string_lossy().to_string();
+                if child.path().is_file() && !is_skipped_file(&child_name) {
+                    let data = fs::read(child.path()).map_err(|e| e.to_string())?;
+                    push(format!("{}/{}", name, child_name), data);
+                }
+            }
+        } else if is_skipped_file(&name) || (name == LOGS_DB && !include_logs) {
+            continue;
+        } else if name.ends_with(".db") {
+            push(name, snapshot_db(&path)?);
+        } else {
+            push(name, fs::read(&path).map_err(|e| e.to_string())?);
+        }
+    }
+    Ok(files)
+}
+
+fn create_with_params(data_dir: &Path, passphrase: &str, include_logs: bool, m_cost: u32, t_cost: u32, p_cost: u32) -> Result<BackupArchive, String> {
+    let now = chrono::Utc::now().timestamp();
+    let files = collect_files(data_dir, include_logs)?;
+    let file_count = files.len();
+    let plaintext = serde_json::to_vec(&BackupPayload { created_at: now, files }).map_err(|e| e.to_string())?;
+    let sealed = account_archive::seal(&plaintext, passphrase, BACKUP_AAD, m_cost, t_cost, p_cost)?;
+    Ok(BackupArchive {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        created_at: now,
+        file_count,
+        kdf: sealed.kdf,
+        cipher: account_archive::CIPHER.to_string(),
+        nonce: sealed.nonce,
+        ciphertext: sealed.ciphertext,
+    })
+}
+
+/// 创建加密备份
+pub fn create_backup(passphrase: &str, include_logs: bool) -> Result<BackupArchive, String> {
+    let data_dir = crate::modules::account::get_data_dir()?;
+    create_with_params(&data_dir, passphrase, include_logs, Params::DEFAULT_M_COST, Params::DEFAULT_T_COST, Params::DEFAULT_P_COST)
+}
+
+/// 归档中的路径只能是数据目录下的文件或允许的子目录中的文件
+fn is_valid_restore_path(path: &str) -> bool {
+    let components: Vec<_> = Path::new(path).components().collect();
+    let names: Vec<_> = components
+        .iter()
+        .filter_map(|c| match c {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect();
+    if names.len() != components.len() || is_skipped_file(path) {
+        return false;
+    }
+    match names.as_slice() {
+        [name] => *name != STAGING_DIR && *name != DEFAULT_BACKUP_DIR,
+        [dir, _] => INCLUDED_DIRS.contains(dir),
+        _ => false,
+    }
+}
+
+fn decrypt_backup(archive: &BackupArchive, passphrase: &str) -> Result<BackupPayload, String> {
+    if archive.format != BACKUP_FORMAT || archive.version != BACKUP_VERSION {
+        return Err(format!("Unsupported backup format: {} v{}", archive.format, archive.version));
+    }
+    let sealed = Sealed {
+        kdf: archive.kdf.clone(),
+        nonce: archive.nonce.clone(),
+        ciphertext: archive.ciphertext.clone(),
+    };
+    let plaintext = account_archive::unseal(&sealed, &archive.cipher, passphrase, BACKUP_AAD)?;
+    serde_json::from_slice(&plaintext).map_err(|e| format!("Invalid backup content: {}", e))
+}
+
+fn stage_in(data_dir: &Path, archive: &BackupArchive, passphrase: &str) -> Result<RestoreSummary, String> {
+    let payload = decrypt_backup(archive, passphrase)?;
+    if let Some(bad) = payload.files.iter().find(|f| !is_valid_restore_path(&f.path)) {
+        return Err(format!("Backup contains an invalid path: {}", bad.path));
+    }
+
+    let staging = data_dir.join(STAGING_DIR);
+    if staging.exists() {
+        fs::remove_dir_all(&staging).map_err(|e| format!("Failed to clear previous restore: {}", e))?;
+    }
+    let mut paths = Vec::with_capacity(payload.files.len());
+    for file in &payload.files {
+        let data = STANDARD.decode(&file.data).map_err(|e| format!("Invalid data for {}: {}", file.path, e))?;
+        let target = staging.join(&file.path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        fs::write(&target, data).map_err(|e| format!("Failed to stage {}: {}", file.path, e))?;
+        paths.push(file.path.clone());
+    }
+    // 清单最后写入：只有完整暂存的恢复才会在启动时生效
+    let manifest = serde_json::to_vec(&paths).map_err(|e| e.to_string())?;
+    fs::write(staging.join(STAGING_MANIFEST), manifest).map_err(|e| e.to_string())?;
+    Ok(RestoreSummary { created_at: payload.created_at, files: paths })
+}
+
+/// 解密备份并暂存，下次启动时生效
+pub fn stage_restore(archive: &BackupArchive, passphrase: &str) -> Result<RestoreSummary, String> {
+    let data_dir = crate::modules::account::get_data_dir()?;
+    stage_in(&data_dir, archive, passphrase)
+}
+
+fn apply_in(data_dir: &Path) -> Result<Option<usize>, String> {
+    let staging = data_dir.join(STAGING_DIR);
+    let manifest_path = staging.join(STAGING_MANIFEST);
+    if !manifest_path.exists() {
+        // 暂存未完成（如写入中途退出），丢弃
+        if staging.exists() {
+            fs::remove_dir_all(&staging).map_err(|e| e.to_string())?;
+        }
+        return Ok(None);
+    }
+    let content = fs::read(&manifest_path).map_err(|e| e.to_string())?;
+    let paths: Vec<String> = serde_json::from_slice(&content).map_err(|e| format!("Invalid restore manifest: {}", e))?;
+    for path in paths.iter().filter(|p| is_valid_restore_path(p)) {
+        let target = data_dir.join(path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        // 旧数据库的 WAL 不能留给恢复后的数据库
+        if path.ends_with(".db") {
+            for suffix in ["-wal", "-shm"] {
+                let _ = fs::remove_file(data_dir.join(format!("{}{}", path, suffix)));
+            }
+        }
+        fs::rename(staging.join(path), &target).map_err(|e| format!("Failed to restore {}: {}", path, e))?;
+    }
+    fs::remove_dir_all(&staging).map_err(|e| e.to_string())?;
+    Ok(Some(paths.len()))
+}
+
+/// 启动时应用暂存的恢复，返回恢复的文件数
+pub fn apply_staged_restore() -> Result<Option<usize>, String> {
+    let data_dir = crate::modules::account::get_data_dir()?;
+    apply_in(&data_dir)
+}
+
+fn backup_file_name(timestamp: i64) -> String {
+    let time = chrono::DateTime::from_timestamp(timestamp, 0).unwrap_or_default();
+    format!("{}{}{}", FILE_PREFIX, time.format(FILE_TIME_FORMAT), FILE_SUFFIX)
+}
+
+fn parse_backup_time(name: &str) -> Option<i64> {
+    let stamp = name.strip_prefix(FILE_PREFIX)?.strip_suffix(FILE_SUFFIX)?;
+    chrono::NaiveDateTime::parse_from_str(stamp, FILE_TIME_FORMAT)
+        .ok()
+        .map(|t| t.and_utc().timestamp())
+}
+
+/// 定时备份的写入位置
+enum Destination {
+    Local(PathBuf),
+    S3(S3Client),
+}
+
+impl Destination {
+    fn from_config(config: &BackupConfig) -> Result<Self, String> {
+        if let Some(s3) = &config.s3 {
+            return Ok(Self::S3(S3Client::new(s3.clone())));
+        }
+        let dir = match config.directory.trim() {
+            "" => crate::modules::account::get_data_dir()?.join(DEFAULT_BACKUP_DIR),
+            dir => PathBuf::from(dir),
+        };
+        Ok(Self::Local(dir))
+    }
+
+    /// 已有的备份文件名，按时间从旧到新
+    async fn list(&self) -> Result<Vec<String>, String> {
+        let mut names: Vec<String> = match self {
+            Self::Local(dir) if !dir.exists() => Vec::new(),
+            Self::Local(dir) => fs::read_dir(dir)
+                .map_err(|e| e.to_string())?
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .collect(),
+            Self::S3(client) => {
+                let prefix = client.object_key(FILE_PREFIX);
+                let strip = client.object_key("");
+                client
+                    .list_objects(&prefix)
+                    .await?
+                    .into_iter()
+                    .filter_map(|key| key.strip_prefix(&strip).map(str::to_string))
+                    .collect()
+            }
+        };
+        names.retain(|name| parse_backup_time(name).is_some());
+        names.sort();
+        Ok(names)
+    }
+
+    async fn write(&self, name: &str, data: Vec<u8>) -> Result<(), String> {
+        match self {
+            Self::Local(dir) => {
+                fs::create_dir_all(dir).map_err(|e| format!("Failed to create backup directory: {}", e))?;
+                let temp = dir.join(format!("{}.tmp", name));
+                fs::write(&temp, data).map_err(|e| e.to_string())?;
+                fs::rename(&temp, dir.join(name)).map_err(|e| e.to_string())
+            }
+            Self::S3(client) => client.put_object(&client.object_key(name), data).await,
+        }
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), String> {
+        match self {
+            Self::Local(dir) => fs::remove_file(dir.join(name)).map_err(|e| e.to_string()),
+            Self::S3(client) => client.delete_object(&client.object_key(name)).await,
+        }
+    }
+}
+
+pub static BACKUPS: Lazy<BackupScheduler> = Lazy::new(BackupScheduler::default);
+
+#[derive(Default)]
+pub struct BackupScheduler {
+    config: RwLock<BackupConfig>,
+    /// 最近一次定时备份的时间；None 表示尚未从备份位置读取
+    last_backup: Mutex<Option<i64>>,
+}
+
+impl BackupScheduler {
+    /// 启动 / 热重载时写入配置（调用前应已通过 validate）
+    pub fn configure(&self, config: &BackupConfig) {
+        *self.config.write().unwrap() = config.clone();
+        // 备份位置可能已变更，重新读取最近一次备份时间
+        *self.last_backup.lock().unwrap() = None;
+    }
+
+    pub fn config(&self) -> BackupConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// 立即执行一次定时备份：写入备份位置并清理超出保留数量的旧备份，返回文件名
+    pub async fn run_once(&self) -> Result<String, String> {
+        let config = self.config();
+        if config.passphrase.is_empty() {
+            return Err("backup.passphrase is not configured".to_string());
+        }
+        let destination = Destination::from_config(&config)?;
+        let (passphrase, include_logs) = (config.passphrase.clone(), config.include_logs);
+        let archive = tokio::task::spawn_blocking(move || create_backup(&passphrase, include_logs))
+            .await
+            .map_err(|e| e.to_string())??;
+        let name = backup_file_name(archive.created_at);
+        let data = serde_json::to_vec(&archive).map_err(|e| e.to_string())?;
+        destination.write(&name, data).await?;
+        *self.last_backup.lock().unwrap() = Some(archive.created_at);
+
+        let names = destination.list().await?;
+        let excess = names.len().saturating_sub(config.retention);
+        for old in &names[..excess] {
+            if let Err(e) = destination.delete(old).await {
+                tracing::warn!("[Backup] Failed to delete old backup {}: {}", old, e);
+            }
+        }
+        Ok(name)
+    }
+
+    async fn last_backup_time(&self, config: &BackupConfig) -> Result<i64, String> {
+        if let Some(last) = *self.last_backup.lock().unwrap() {
+            return Ok(last);
+        }
+        let names = Destination::from_config(config)?.list().await?;
+        let last = names.last().and_then(|name| parse_backup_time(name)).unwrap_or(0);
+        *self.last_backup.lock().unwrap() = Some(last);
+        Ok(last)
+    }
+
+    async fn run_if_due(&self) -> Result<(), String> {
+        let config = self.config();
+        if config.interval_hours == 0 {
+            return Ok(());
+        }
+        let last = self.last_backup_time(&config).await?;
+        let due_at = last.saturating_add(config.interval_hours.saturating_mul(3600) as i64);
+        if chrono::Utc::now().timestamp() < due_at {
+            return Ok(());
+        }
+        let name = self.run_once().await?;
+        tracing::info!("[Backup] Scheduled backup written: {}", name);
+        Ok(())
+    }
+
+    /// 后台定时备份
+    pub fn spawn_scheduler(&'static self) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(SCHEDULE_CHECK_INTERVAL).await;
+                if let Err(e) = self.run_if_due().await {
+                    tracing::error!("[Backup] Scheduled backup failed: {}", e);
+                    // 失败后等待下一个间隔再重试，避免每分钟重复失败
+                    *self.last_backup.lock().unwrap() = Some(chrono::Utc::now().timestamp());
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("antiproxy-backup-test-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_backup_and_staged_restore() {
+        let source = temp_dir("source");
+        fs::write(source.join("web_config.json"), b"{\"port\": 8045}").unwrap();
+        fs::write(source.join("master.key"), b"secret").unwrap();
+        fs::write(source.join("accounts.json.tmp"), b"partial").unwrap();
+        fs::create_dir_all(source.join("accounts")).unwrap();
+        fs::write(source.join("accounts").join("a1.json"), b"{\"id\": \"a1\"}").unwrap();
+        fs::create_dir_all(source.join("logs")).unwrap();
+        fs::write(source.join("logs").join("app.log"), b"log").unwrap();
+        {
+            let conn = rusqlite::Connection::open(source.join("api_keys.db")).unwrap();
+            conn.execute_batch("PRAGMA journal_mode = WAL; CREATE TABLE t (v TEXT); INSERT INTO t VALUES ('kept');")
+                .unwrap();
+        }
+        {
+            let conn = rusqlite::Connection::open(source.join(LOGS_DB)).unwrap();
+            conn.execute_batch("CREATE TABLE logs (v TEXT);").unwrap();
+        }
+
+        let archive = create_with_params(&source, "correct horse", false, 64, 1, 1).unwrap();
+        assert_eq!(archive.file_count, 4);
+        assert!(decrypt_backup(&archive, "wrong horse").is_err());
+
+        let target = temp_dir("target");
+        fs::write(target.join("api_keys.db-wal"), b"stale").unwrap();
+        let summary = stage_in(&target, &archive, "correct horse").unwrap();
+        assert_eq!(summary.files, vec!["accounts/a1.json", "api_keys.db", "master.key", "web_config.json"]);
+        // 暂存后原文件尚未改动
+        assert!(!target.join("web_config.json").exists());
+
+        assert_eq!(apply_in(&target).unwrap(), Some(4));
+        assert!(!target.join(STAGING_DIR).exists());
+        assert!(!target.join("api_keys.db-wal").exists());
+        assert_eq!(fs::read(target.join("accounts").join("a1.json")).unwrap(), b"{\"id\": \"a1\"}");
+        let conn = rusqlite::Connection::open(target.join("api_keys.db")).unwrap();
+        let value: String = conn.query_row("SELECT v FROM t", [], |row| row.get(0)).unwrap();
+        assert_eq!(value, "kept");
+        assert_eq!(apply_in(&target).unwrap(), None);
+
+        let _ = fs::remove_dir_all(source);
+        let _ = fs::remove_dir_all(target);
+    }
+
+    #[test]
+    fn test_restore_paths_and_names() {
+        assert!(is_valid_restore_path("api_keys.db"));
+        assert!(is_valid_restore_path("accounts/a1.json"));
+        assert!(!is_valid_restore_path("../etc/passwd"));
+        assert!(!is_valid_restore_path("/etc/passwd"));
+        assert!(!is_valid_restore_path("logs/app.log"));
+        assert!(!is_valid_restore_path("restore-pending"));
+        assert!(!is_valid_restore_path("accounts/nested/a.json"));
+
+        let name = backup_file_name(1_767_323_045);
+        assert_eq!(name, "antiproxy-backup-20260102T030405Z.json");
+        assert_eq!(parse_backup_time(&name), Some(1_767_323_045));
+        assert_eq!(parse_backup_time("notes.txt"), None);
+    }
+}
@@ -1,5 +1,4 @@
 use std::fs;
-use serde_json;
 
 use crate::proxy::ProxyConfig;
 use super::account::get_data_dir;
@@ -20,8 +19,133 @@ pub fn load_web_config() -> Result<ProxyConfig, String> {
     let content = fs::read_to_string(&config_path)
         .map_err(|e| format!("读取配置文件失败: {}", e))?;
     
-    serde_json::from_str(&content)
-        .map_err(|e| format!("解析配置文件失败: {}", e))
+    parse_config(&content).map_err(|e| format!("解析配置文件失败: {}", e))
+}
+
+/// 解析配置，错误信息带出错字段的路径（如 `retry.max_attempts: invalid type ...`）
+fn parse_config(content: &str) -> Result<ProxyConfig, String> {
+    let deserializer = &mut serde_json::Deserializer::from_str(content);
+    serde_path_to_error::deserialize(deserializer).map_err(|e| match e.path().to_string().as_str() {
+        "." => e.inner().to_string(),
+        path => format!("{}: {}", path, e.inner()),
+    })
+}
+
+/// 检查配置文本，返回发现的问题（为空表示配置有效）
+///
+/// 除解析错误外，还会报告拼写错误等未知字段（反序列化时会被静默忽略）以及取值无效的字段
+pub fn check_config(content: &str) -> Vec<String> {
+    let raw: serde_json::Value = match serde_json::from_str(content) {
+        Ok(raw) => raw,
+        Err(e) => return vec![format!("invalid JSON: {}", e)],
+    };
+    let config = match parse_config(content) {
+        Ok(config) => config,
+        Err(e) => return vec![e],
+    };
+
+    let mut problems = Vec::new();
+    // 反序列化后再序列化，原文中存在而结果中没有的字段即为未知字段
+    if let Ok(known) = serde_json::to_value(&config) {
+        let mut unknown = Vec::new();
+        collect_unknown_fields(&raw, &known, "", &mut unknown);
+        problems.extend(unknown.into_iter().map(|path| format!("{}: unknown field", path)));
+    }
+    if let Err(e) = super::logger::validate_log_level(&config.log_level) {
+        problems.push(format!("log_level: {}", e));
+    }
+    if let Err(e) = super::quota::parse_budget_timezone(&config.token_budget_timezone) {
+        problems.push(format!("token_budget_timezone: {}", e));
+    }
+    if let Err(e) = crate::proxy::pricing::validate(&config.pricing) {
+        problems.push(format!("pricing: {}", e));
+    }
+    if let Err(e) = crate::modules::storage::validate(&config.storage) {
+        problems.push(format!("storage: {}", e));
+    }
+    if let Err(e) = crate::proxy::upstream::circuit_breaker::validate(&config.upstream_timeouts) {
+        problems.push(format!("upstream_timeouts: {}", e));
+    }
+    if let Err(e) = crate::proxy::upstream::profiles::validate(&config.client_profiles) {
+        problems.push(format!("client_profiles: {}", e));
+    }
+    if let Err(e) = super::oauth::validate(&config.oauth_clients) {
+        problems.push(format!("oauth_clients: {}", e));
+    }
+    if let Err(e) = crate::proxy::security::validate_session(&config.session) {
+        problems.push(format!("session: {}", e));
+    }
+    if let Err(e) = crate::proxy::auth_guard::validate(&config.auth_lockout) {
+        problems.push(format!("auth_lockout: {}", e));
+    }
+    if let Err(e) = crate::proxy::files::validate(&config.files) {
+        problems.push(format!("files: {}", e));
+    }
+    if let Err(e) = crate::proxy::batches::validate(&config.batch) {
+        problems.push(format!("batch: {}", e));
+    }
+    if let Err(e) = crate::proxy::middleware::maintenance::validate(&config.maintenance) {
+        problems.push(format!("maintenance: {}", e));
+    }
+    if let Err(e) = crate::modules::backup::validate(&config.backup) {
+        problems.push(format!("backup: {}", e));
+    }
+    if let Err(e) = crate::proxy::concurrency::validate(&config.concurrency) {
+        problems.push(format!("concurrency: {}", e));
+    }
+    if let Err(e) = crate::proxy::upstream::shadow::validate(&config.shadow) {
+        problems.push(format!("shadow: {}", e));
+    }
+    if let Err(e) = crate::modules::log_archive::validate(&config.log_archive) {
+        problems.push(format!("log_archive: {}", e));
+    }
+    if let Err(e) = crate::proxy::cluster::validate(&config.cluster) {
+        problems.push(format!("cluster: {}", e));
+    }
+    if let Err(e) = crate::proxy::middleware::base_path::normalize(&config.base_path) {
+        problems.push(format!("base_path: {}", e));
+    }
+    problems
+}
+
+/// 检查数据目录中的配置文件
+pub fn check_web_config() -> Result<Vec<String>, String> {
+    let config_path = get_data_dir()?.join(CONFIG_FILE);
+    let content = fs::read_to_string(&config_path)
+        .map_err(|e| format!("读取配置文件失败: {}", e))?;
+    Ok(check_config(&content))
+}
+
+fn collect_unknown_fields(raw: &serde_json::Value, known: &serde_json::Value, path: &str, out: &mut Vec<String>) {
+    use serde_json::Value;
+    match (raw, known) {
+        (Value::Object(raw), Value::Object(known)) => {
+            for (key, value) in raw {
+                let field_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                match known.get(key) {
+                    Some(known) => collect_unknown_fields(value, known, &field_path, out),
+                    // 值为空的可选字段序列化时会被省略
+                    None if is_empty(value) => {}
+                    None => out.push(field_path),
+                }
+            }
+        }
+        (Value::Array(raw), Value::Array(known)) => {
+            for (i, (value, known)) in raw.iter().zip(known).enumerate() {
+                collect_unknown_fields(value, known, &format!("{}[{}]", path, i), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_empty(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null => true,
+        serde_json::Value::Object(map) => map.is_empty(),
+        serde_json::Value::Array(items) => items.is_empty(),
+        _ => false,
+    }
 }
 
 /// 保存 Web 服务配置
@@ -35,3 +159,73 @@ pub fn save_web_config(config: &ProxyConfig) -> Result<(), String> {
     fs::write(&config_path, content)
         .map_err(|e| format!("保存配置失败: {}", e))
 }
+
+/// 设置后即使配置有问题也照常启动与热重载的环境变量
+pub const ALLOW_INVALID_CONFIG_ENV: &str = "ANTI_PROXY_ALLOW_INVALID_CONFIG";
+
+/// 是否放行有问题的配置（默认发现未知字段或无效取值时拒绝启动与热重载）
+pub fn allow_invalid_config() -> bool {
+    std::env::var(ALLOW_INVALID_CONFIG_ENV)
+        .is_ok_and(|value| matches!(value.as_str(), "1" | "true" | "yes" | "on"))
+}
+
+/// 应用环境变量覆盖（启动与热重载时均需调用，保证两者行为一致）
+pub fn apply_env_overrides(config: &mut ProxyConfig) {
+    if let Ok(value) = std::env::var("ANTI_PROXY_ALLOW_LAN") {
+        if matches!(value.as_str(), "1" | "true" | "yes" | "on") {
+            config.allow_lan_access = true;
+        }
+    }
+
+    if let Ok(value) = std::env::var("ANTI_PROXY_ENABLED") {
+        if matches!(value.as_str(), "1" | "true" | "yes" | "on") {
+            config.enabled = true;
+        }
+    }
+
+    // 绑定到非本机地址意味着允许局域网访问
+    if let Ok(addr) = std::env::var("ANTI_PROXY_BIND") {
+        if addr != "127.0.0.1" && addr != "localhost" {
+            config.allow_lan_access = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with(patch: serde_json::Value) -> String {
+        let mut config = serde_json::to_value(ProxyConfig::default()).unwrap();
+        for (key, value) in patch.as_object().unwrap() {
+            match (config.get_mut(key), value) {
+                (Some(serde_json::Value::Object(target)), serde_json::Value::Object(fields)) => {
+                    target.extend(fields.clone());
+                }
+                _ => {
+                    config[key] = value.clone();
+                }
+            }
+        }
+        config.to_string()
+    }
+
+    #[test]
+    fn test_check_config() {
+        assert_eq!(check_config(&config_with(serde_json::json!({}))), Vec::<String>::new());
+        let config = config_with(serde_json::json!({"client_profiles": {"profiles": {}, "default": null}}));
+        assert!(check_config(&config).is_empty());
+
+        let problems = check_config(&config_with(serde_json::json!({"prot": 8045, "retry": {"max_atempts": 3}})));
+        assert_eq!(problems, vec!["prot: unknown field", "retry.max_atempts: unknown field"]);
+
+        let problems = check_config(&config_with(serde_json::json!({"retry": {"max_attempts": "3"}})));
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("retry.max_attempts: invalid type"), "{}", problems[0]);
+
+        let problems = check_config(&config_with(serde_json::json!({"token_budget_timezone": "Mars/Olympus"})));
+        assert_eq!(problems, vec!["token_budget_timezone: Invalid timezone: Mars/Olympus"]);
+        assert!(check_config(r#"{"enabled": true"#)[0].starts_with("invalid JSON"));
+        assert!(check_config("{}")[0].contains("missing field"));
+    }
+}
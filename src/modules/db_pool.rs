@@ -0,0 +1,116 @@
+// SQLite 连接池
+// 每个数据库文件复用一组已打开的连接，避免热路径上反复打开文件；
+// 连接统一使用 WAL 日志模式（读写互不阻塞）并设置 busy_timeout（写入冲突时等待而不是立即返回 SQLITE_BUSY）
+use once_cell::sync::Lazy;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// 每个数据库最多保留的空闲连接
+const MAX_IDLE_PER_DB: usize = 8;
+/// 等待其他连接释放写锁的最长时间
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+static IDLE: Lazy<Mutex<HashMap<PathBuf, Vec<Connection>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 借出的连接，离开作用域时归还连接池
+pub struct PooledConnection {
+    conn: Option<Connection>,
+    path: PathBuf,
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection is present until drop")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().expect("connection is present until drop")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let Some(conn) = self.conn.take() else {
+            return;
+        };
+        // 未提交的事务（如出错提前返回）不能带回连接池
+        if !conn.is_autocommit() {
+            return;
+        }
+        let mut idle = IDLE.lock().unwrap_or_else(|e| e.into_inner());
+        let conns = idle.entry(std::mem::take(&mut self.path)).or_default();
+        if conns.len() < MAX_IDLE_PER_DB {
+            conns.push(conn);
+        }
+    }
+}
+
+fn connect(path: &Path) -> Result<Connection, String> {
+    let conn = Connection::open(path).map_err(|e| e.to_string())?;
+    conn.busy_timeout(BUSY_TIMEOUT).map_err(|e| e.to_string())?;
+    // journal_mode 会返回一行结果，不能用 execute
+    conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?;
+    // WAL 模式下 NORMAL 只在检查点时 fsync，断电最多丢失最近的提交，不会损坏数据库
+    conn.pragma_update(None, "synchronous", "NORMAL").map_err(|e| e.to_string())?;
+    Ok(conn)
+}
+
+/// 从连接池取出（或新建）指定数据库的连接
+pub fn open(path: &Path) -> Result<PooledConnection, String> {
+    let idle = IDLE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_mut(path)
+        .and_then(|conns| conns.pop());
+    let conn = match idle {
+        Some(conn) => conn,
+        None => connect(path)?,
+    };
+    Ok(PooledConnection { conn: Some(conn), path: path.to_path_buf() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn idle_count(path: &Path) -> usize {
+        IDLE.lock().unwrap().get(path).map_or(0, |conns| conns.len())
+    }
+
+    #[test]
+    fn test_pool_reuses_wal_connections() {
+        let path = std::env::temp_dir().join(format!("anti-proxy-pool-{}.db", uuid::Uuid::new_v4()));
+        {
+            let conn = open(&path).unwrap();
+            conn.execute("CREATE TABLE t (v INTEGER)", []).unwrap();
+            let mode: String = conn.query_row("PRAGMA journal_mode", [], |r| r.get(0)).unwrap();
+            assert_eq!(mode, "wal");
+            // 同时借出的第二个连接是新建的
+            let other = open(&path).unwrap();
+            other.execute("INSERT INTO t VALUES (1)", []).unwrap();
+        }
+        assert_eq!(idle_count(&path), 2);
+        {
+            let mut conn = open(&path).unwrap();
+            assert_eq!(idle_count(&path), 1);
+            // 未提交的事务不归还
+            let tx = conn.transaction().unwrap();
+            std::mem::forget(tx);
+        }
+        assert_eq!(idle_count(&path), 1);
+
+        IDLE.lock().unwrap().remove(&path);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+}
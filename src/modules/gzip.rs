@@ -0,0 +1,559 @@
+//! gzip / deflate 压缩与解压（RFC 1950 / 1951 / 1952）
+//!
+//! 编码：LZ77 匹配 + 固定 Huffman 编码的单个 deflate 块。压缩率不及 zlib，
+//! 但对日志、JSON 等重复度高的文本已足够，且任何标准 gzip 工具都能解压。
+//! 解码支持存储、固定 Huffman 与动态 Huffman 三种块，用于解析上游返回的压缩响应体
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const WINDOW_MASK: usize = WINDOW_SIZE - 1;
+const HASH_BITS: u32 = 15;
+const HASH_SIZE: usize = 1 << HASH_BITS;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// 每个位置最多比较的候选数，在速度与压缩率之间折中
+const MAX_CHAIN: usize = 64;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data
+        .iter()
+        .fold(!0u32, |crc, &b| CRC_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+/// deflate 按 LSB 优先写入比特流
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write_bits(&mut self, value: u32, bits: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman 码按 MSB 优先写入
+    fn write_code(&mut self, code: u32, bits: u32) {
+        self.write_bits(code.reverse_bits() >> (32 - bits), bits);
+    }
+
+    /// 固定 Huffman 表中的字面量 / 长度符号
+    fn write_symbol(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xC0 + symbol - 280, 8),
+        }
+    }
+
+    fn write_match(&mut self, length: usize, distance: usize) {
+        let index = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap_or(0);
+        self.write_symbol(257 + index as u32);
+        self.write_bits((length - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index] as u32);
+        let index = DIST_BASE.iter().rposition(|&base| base as usize <= distance).unwrap_or(0);
+        self.write_code(index as u32, 5);
+        self.write_bits((distance - DIST_BASE[index] as usize) as u32, DIST_EXTRA[index] as u32);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let value = (data[pos] as usize) << 10 ^ (data[pos + 1] as usize) << 5 ^ data[pos + 2] as usize;
+    value & (HASH_SIZE - 1)
+}
+
+/// 把 `pos` 加入哈希链
+fn insert(data: &[u8], pos: usize, head: &mut [usize], prev: &mut [usize]) {
+    if pos + MIN_MATCH <= data.len() {
+        let h = hash(data, pos);
+        prev[pos & WINDOW_MASK] = head[h];
+        head[h] = pos;
+    }
+}
+
+/// raw deflate 数据流
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter { out: Vec::with_capacity(data.len() / 4 + 16), buffer: 0, count: 0 };
+    // BFINAL = 1，BTYPE = 01（固定 Huffman）
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    let mut head = vec![usize::MAX; HASH_SIZE];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (mut best_len, mut best_dist) = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let max_len = MAX_MATCH.min(data.len() - pos);
+            let mut candidate = head[hash(data, pos)];
+            let mut chain = MAX_CHAIN;
+            while candidate < pos && pos - candidate <= WINDOW_SIZE && chain > 0 {
+                let len = data[candidate..candidate + max_len]
+                    .iter()
+                    .zip(&data[pos..pos + max_len])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    (best_len, best_dist) = (len, pos - candidate);
+                    if len == max_len {
+                        break;
+                    }
+                }
+                candidate = prev[candidate & WINDOW_MASK];
+                chain -= 1;
+            }
+        }
+
+        if best_len >= MIN_MATCH {
+            writer.write_match(best_len, best_dist);
+            for p in pos..pos + best_len {
+                insert(data, p, &mut head, &mut prev);
+            }
+            pos += best_len;
+        } else {
+            writer.write_symbol(data[pos] as u32);
+            insert(data, pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+    writer.write_symbol(256);
+    writer.finish()
+}
+
+/// gzip 格式压缩
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    // 魔数、deflate 方法、无标志位与修改时间、OS 未知
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    out.extend(deflate(data));
+    out.extend(crc32(data).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
+
+/// zlib 格式（`Content-Encoding: deflate`）的校验和
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// deflate 按 LSB 优先读取比特流
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0, buffer: 0, count: 0 }
+    }
+
+    fn bits(&mut self, bits: u32) -> Result<u32, String> {
+        while self.count < bits {
+            let byte = *self.data.get(self.pos).ok_or("unexpected end of deflate stream")?;
+            self.pos += 1;
+            self.buffer |= (byte as u64) << self.count;
+            self.count += 8;
+        }
+        let value = (self.buffer & ((1u64 << bits) - 1)) as u32;
+        self.buffer >>= bits;
+        self.count -= bits;
+        Ok(value)
+    }
+
+    /// 丢弃当前字节剩余的比特（存储块从字节边界开始）
+    fn align(&mut self) {
+        let rest = self.count % 8;
+        self.buffer >>= rest;
+        self.count -= rest;
+    }
+
+    /// 已消耗的完整字节数
+    fn consumed(&self) -> usize {
+        self.pos - (self.count / 8) as usize
+    }
+}
+
+/// 规范 Huffman 码表：各码长的符号数，以及按码值排序的符号
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, String> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err("over-subscribed Huffman code".to_string());
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    /// 逐比特读取，直到落在某个码长的码值区间内
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code".to_string())
+    }
+}
+
+/// 动态 Huffman 块中码长序列的存放顺序
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn fixed_tables() -> Result<(Huffman, Huffman), String> {
+    let mut lengths = [8u8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5u8; 30])?))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literals = reader.bits(5)? as usize + 257;
+    let distances = reader.bits(5)? as usize + 1;
+    let code_lengths = reader.bits(4)? as usize + 4;
+    if literals > 286 || distances > 30 {
+        return Err("too many Huffman codes".to_string());
+    }
+    let mut lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_table = Huffman::new(&lengths)?;
+
+    let mut lengths = vec![0u8; literals + distances];
+    let mut index = 0;
+    while index < lengths.len() {
+        let (value, repeat) = match code_length_table.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths[..index].last().ok_or("repeat with no previous length")?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        let end = index + repeat;
+        lengths.get_mut(index..end).ok_or("too many code lengths")?.fill(value);
+        index = end;
+    }
+    if lengths[256] == 0 {
+        return Err("missing end-of-block code".to_string());
+    }
+    Ok((Huffman::new(&lengths[..literals])?, Huffman::new(&lengths[literals..])?))
+}
+
+fn check_limit(out: &[u8], limit: usize) -> Result<(), String> {
+    if out.len() > limit {
+        return Err(format!("decompressed size exceeds {} bytes", limit));
+    }
+    Ok(())
+}
+
+/// 解压 raw deflate 数据流，返回解压结果与消耗的输入字节数；输出超过 `limit` 时报错
+fn inflate(data: &[u8], limit: usize) -> Result<(Vec<u8>, usize), String> {
+    let mut reader = BitReader::new(data);
+    let mut out = Vec::with_capacity(data.len().saturating_mul(4).min(limit));
+    loop {
+        let last = reader.bits(1)? == 1;
+        let (literals, distances) = match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let len = reader.bits(16)?;
+                if len != !reader.bits(16)? & 0xFFFF {
+                    return Err("stored block length mismatch".to_string());
+                }
+                for _ in 0..len {
+                    out.push(reader.bits(8)? as u8);
+                }
+                check_limit(&out, limit)?;
+                if last {
+                    break;
+                }
+                continue;
+            }
+            1 => fixed_tables()?,
+            2 => dynamic_tables(&mut reader)?,
+            _ => return Err("invalid deflate block type".to_string()),
+        };
+        loop {
+            let symbol = literals.decode(&mut reader)? as usize;
+            match symbol {
+                0..=255 => out.push(symbol as u8),
+                256 => break,
+                _ => {
+                    let index = symbol - 257;
+                    if index >= LENGTH_BASE.len() {
+                        return Err("invalid length symbol".to_string());
+                    }
+                    let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                    let index = distances.decode(&mut reader)? as usize;
+                    if index >= DIST_BASE.len() {
+                        return Err("invalid distance symbol".to_string());
+                    }
+                    let distance = DIST_BASE[index] as usize + reader.bits(DIST_EXTRA[index] as u32)? as usize;
+                    if distance > out.len() {
+                        return Err("distance too far back".to_string());
+                    }
+                    let start = out.len() - distance;
+                    for i in 0..length {
+                        out.push(out[start + i]);
+                    }
+                }
+            }
+            check_limit(&out, limit)?;
+        }
+        if last {
+            break;
+        }
+    }
+    Ok((out, reader.consumed()))
+}
+
+/// 跳过 `\0` 结尾的头部字段
+fn skip_zero_terminated(data: &[u8], pos: usize) -> Result<usize, String> {
+    data.get(pos..)
+        .and_then(|rest| rest.iter().position(|&b| b == 0))
+        .map(|end| pos + end + 1)
+        .ok_or_else(|| "truncated gzip header".to_string())
+}
+
+/// gzip 格式解压（支持多个成员拼接），校验 CRC 与长度；输出超过 `limit` 时报错
+pub fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        if rest.len() < 18 || rest[..3] != [0x1f, 0x8b, 8] {
+            return Err("not a gzip stream".to_string());
+        }
+        let flags = rest[3];
+        let mut pos = 10;
+        if flags & 0x04 != 0 {
+            let extra = u16::from_le_bytes([rest[10], rest[11]]) as usize;
+            pos += 2 + extra;
+        }
+        if flags & 0x08 != 0 {
+            pos = skip_zero_terminated(rest, pos)?;
+        }
+        if flags & 0x10 != 0 {
+            pos = skip_zero_terminated(rest, pos)?;
+        }
+        if flags & 0x02 != 0 {
+            pos += 2;
+        }
+        let body = rest.get(pos..).ok_or("truncated gzip header")?;
+        let (member, used) = inflate(body, limit - out.len())?;
+        let trailer = body.get(used..used + 8).ok_or("truncated gzip trailer")?;
+        if crc32(&member).to_le_bytes() != trailer[..4] {
+            return Err("gzip CRC mismatch".to_string());
+        }
+        if (member.len() as u32).to_le_bytes() != trailer[4..] {
+            return Err("gzip length mismatch".to_string());
+        }
+        out.extend(member);
+        rest = &body[used + 8..];
+    }
+    Ok(out)
+}
+
+/// 按 `Content-Encoding` 解码响应体：`gzip` / `x-gzip`，以及 zlib 封装或 raw 的 `deflate`
+pub fn decode(encoding: &str, data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    match encoding {
+        "gzip" | "x-gzip" => decompress(data, limit),
+        "deflate" => {
+            // RFC 1950 头部：方法为 deflate、无预设字典、校验位可被 31 整除；否则按 raw deflate 处理
+            let zlib = data.len() >= 6
+                && data[0] & 0x0F == 8
+                && data[1] & 0x20 == 0
+                && (u16::from_be_bytes([data[0], data[1]])).is_multiple_of(31);
+            if !zlib {
+                return inflate(data, limit).map(|(out, _)| out);
+            }
+            let (out, used) = inflate(&data[2..], limit)?;
+            let checksum = data.get(2 + used..6 + used).ok_or("truncated zlib trailer")?;
+            if adler32(&out).to_be_bytes() != checksum {
+                return Err("zlib checksum mismatch".to_string());
+            }
+            Ok(out)
+        }
+        other => Err(format!("unsupported Content-Encoding: {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc_and_compress() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        // 空输入：固定 Huffman 块只含结束符
+        assert_eq!(
+            compress(b""),
+            vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255, 0x03, 0x00, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        // 单个字面量 'a' 与 zlib 固定 Huffman 输出一致
+        assert_eq!(deflate(b"a"), vec![0x4b, 0x04, 0x00]);
+
+        let line = b"{\"model\":\"gemini-2.5-flash\",\"status\":200}\n";
+        let data: Vec<u8> = line.iter().cycle().take(line.len() * 500).copied().collect();
+        let compressed = compress(&data);
+        assert!(compressed.len() < data.len() / 20);
+        assert_eq!(&compressed[compressed.len() - 4..], (data.len() as u32).to_le_bytes());
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_deflate_known_vectors() {
+        // 期望值均已用 zlib（Python `zlib.decompress(data, -15)`）解压验证；
+        // 没有可选匹配时与 zlib 固定 Huffman 输出逐字节一致，其余因 zlib 的惰性匹配而略有不同
+        let vectors: [(&[u8], &str); 4] = [
+            (b"a", "4b0400"),
+            (b"abcabcabcabc", "4b4c4a862300"),
+            (b"hello hello hello world", "cb48cdc9c9574026cbf38b725200"),
+            (
+                b"{\"model\":\"gemini-2.5-flash\",\"status\":200}\n{\"model\":\"gemini-2.5-flash\",\"status\":429}\n",
+                "ab56cacd4f49cd51b2524a4fcdcdcccbd435d233d54dcb492cce50d2512a2e492c292d56b2323230a8e5224aa58991652d1700",
+            ),
+        ];
+        for (data, expected) in vectors {
+            assert_eq!(deflate(data), hex(expected));
+            assert_eq!(inflate(&hex(expected), usize::MAX).unwrap().0, data);
+        }
+        // zlib 自身的固定 Huffman 输出（惰性匹配）同样能解压
+        assert_eq!(inflate(&hex("4b4c4a4e842100"), usize::MAX).unwrap().0, b"abcabcabcabc");
+    }
+
+    #[test]
+    fn test_decode_reference_streams() {
+        // Python gzip / zlib 生成：存储块、带文件名的成员、动态 Huffman 块
+        let stored = hex("1f8b0800000000000403010c00f3ff73746f72656420626c6f636b94a3243d0c000000");
+        assert_eq!(decompress(&stored, usize::MAX).unwrap(), b"stored block");
+        let named = hex("1f8b08080000000002ff612e74787400cb4bcc4d4d51c84dcd4d4a2d0200acb73b430c000000");
+        assert_eq!(decode("gzip", &named, usize::MAX).unwrap(), b"named member");
+        // 两个成员拼接
+        assert_eq!(decompress(&[stored.clone(), named].concat(), usize::MAX).unwrap(), b"stored blocknamed member");
+
+        let text = br#"{"candidates":[{"content":{"parts":[{"text":"Hello! How can I help you today?"}]}}],"usageMetadata":{"promptTokenCount":12,"candidatesTokenCount":9}}"#;
+        let zlib = hex(concat!(
+            "789c4d8db10ec2300c057fc578ee021b5d1858da818d0d75b01a8b560d71d43a2a55e47f27c0d2f54eef5ec69e821b1d29",
+            "2f583f32f6129483629d31d2ac7fa8fc2e041bf65e0ed0c80a65052d0cec236c9240c5d17641ebccba0ad3424fbeb152c9",
+            "d2af34cb2bea5d260e5749dffaf154ed9ef7e66cf601519234cd"
+        ));
+        assert_eq!((zlib[2] >> 1) & 3, 2);
+        assert_eq!(decode("deflate", &zlib, usize::MAX).unwrap(), text);
+        // raw deflate 同样接受
+        assert_eq!(decode("deflate", &deflate(text), usize::MAX).unwrap(), text);
+
+        let mut corrupt = stored.clone();
+        corrupt[20] ^= 1;
+        assert!(decompress(&corrupt, usize::MAX).unwrap_err().contains("CRC"));
+        assert!(decompress(&stored, 4).unwrap_err().contains("exceeds"));
+        assert!(decode("br", &stored, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        // 简单的线性同余序列作为不可压缩数据
+        let mut seed = 12345u32;
+        let noise: Vec<u8> = (0..70_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let log_line = b"{\"model\":\"gemini-2.5-flash\",\"status\":200,\"duration\":1234}\n";
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            b"x".to_vec(),
+            vec![0u8; 100_000],
+            log_line.iter().cycle().take(log_line.len() * 2_000).copied().collect(),
+            noise.clone(),
+            // 超出 32KB 窗口后重复出现的内容
+            [noise[..40_000].to_vec(), noise[..40_000].to_vec()].concat(),
+        ];
+        for input in inputs {
+            assert_eq!(decompress(&compress(&input), usize::MAX).unwrap(), input);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! 请求日志归档
+//!
+//! 保留策略清理前，把将被删除的日志按 UTC 日期分区写成 gzip 压缩的 JSONL 上传到 S3 兼容存储
+//! （`<prefix>logs/dt=YYYY-MM-DD/proxy-logs-<首条时间戳>-<首条 ID>.jsonl.gz`），上传成功后才删除本地记录
+
+use std::collections::BTreeMap;
+
+use crate::modules::s3::S3Client;
+use crate::proxy::config::{LogArchiveConfig, LogRetentionConfig};
+use crate::proxy::monitor::ProxyRequestLog;
+
+/// 每轮从数据库读取的最大条数，控制单个归档对象与内存占用
+const ARCHIVE_BATCH: usize = 2000;
+
+pub fn validate(config: &LogArchiveConfig) -> Result<(), String> {
+    match &config.s3 {
+        Some(s3) => crate::modules::s3::validate(s3).map_err(|e| format!("s3: {}", e)),
+        None => Ok(()),
+    }
+}
+
+/// 日志所在的 UTC 日期分区
+fn partition(log: &ProxyRequestLog) -> String {
+    chrono::DateTime::from_timestamp_millis(log.timestamp)
+        .unwrap_or_default()
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+/// 同一批日志生成的对象名固定，上传后删除失败时重试会覆盖而不是重复
+fn object_name(day: &str, logs: &[ProxyRequestLog]) -> String {
+    let first = &logs[0];
+    format!("logs/dt={}/proxy-logs-{}-{}.jsonl.gz", day, first.timestamp, first.id)
+}
+
+fn encode(logs: &[ProxyRequestLog]) -> Result<Vec<u8>, String> {
+    let mut jsonl = Vec::new();
+    for log in logs {
+        serde_json::to_writer(&mut jsonl, log).map_err(|e| e.to_string())?;
+        jsonl.push(b'\n');
+    }
+    Ok(crate::modules::gzip::compress(&jsonl))
+}
+
+fn group_by_day(logs: Vec<ProxyRequestLog>) -> BTreeMap<String, Vec<ProxyRequestLog>> {
+    let mut groups: BTreeMap<String, Vec<ProxyRequestLog>> = BTreeMap::new();
+    for log in logs {
+        groups.entry(partition(&log)).or_default().push(log);
+    }
+    groups
+}
+
+/// 归档并删除超出保留策略的日志，返回删除条数；上传失败的日志留在本地等待下次重试
+pub async fn archive_expired(client: &S3Client, retention: &LogRetentionConfig) -> Result<usize, String> {
+    let mut total = 0;
+    loop {
+        let (max_days, max_rows) = (retention.max_days, retention.max_rows);
+        let logs = tokio::task::spawn_blocking(move || {
+            crate::modules::proxy_db::prunable_logs(max_days, max_rows, ARCHIVE_BATCH)
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+        let exhausted = logs.len() < ARCHIVE_BATCH;
+
+        let mut archived = Vec::new();
+        let mut failure = None;
+        for (day, group) in group_by_day(logs) {
+            let result = match encode(&group) {
+                Ok(data) => client.put_object(&client.object_key(&object_name(&day, &group)), data).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => archived.extend(group.into_iter().map(|log| log.id)),
+                Err(e) => {
+                    failure = Some(format!("{}: {}", day, e));
+                    break;
+                }
+            }
+        }
+
+        let deleted = tokio::task::spawn_blocking(move || crate::modules::proxy_db::delete_logs(&archived))
+            .await
+            .map_err(|e| e.to_string())??;
+        total += deleted;
+        if let Some(e) = failure {
+            return Err(format!("archived {} logs before failing at {}", total, e));
+        }
+        if exhausted || deleted == 0 {
+            return Ok(total);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(id: &str, timestamp: i64) -> ProxyRequestLog {
+        ProxyRequestLog { id: id.to_string(), timestamp, ..Default::default() }
+    }
+
+    #[test]
+    fn test_daily_partitions() {
+        // 2026-01-01T23:59:59Z 与 2026-01-02T00:00:00Z
+        let logs = vec![log("a", 1_767_311_999_000), log("b", 1_767_312_000_000), log("c", 1_767_312_060_000)];
+        let groups = group_by_day(logs);
+        let days: Vec<_> = groups.keys().cloned().collect();
+        assert_eq!(days, vec!["2026-01-01", "2026-01-02"]);
+        assert_eq!(groups["2026-01-02"].len(), 2);
+        assert_eq!(
+            object_name("2026-01-02", &groups["2026-01-02"]),
+            "logs/dt=2026-01-02/proxy-logs-1767312000000-b.jsonl.gz"
+        );
+
+        let data = encode(&groups["2026-01-02"]).unwrap();
+        assert_eq!(&data[..2], &[0x1f, 0x8b]);
+        assert!(validate(&LogArchiveConfig::default()).is_ok());
+    }
+}
@@ -1,9 +1,22 @@
 use tracing::{info, warn, error};
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::{fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Layer, Registry};
 use std::fs;
 use std::io::Write;
 use std::path::PathBuf;
 use crate::modules::account::get_data_dir;
+use crate::proxy::config::LogFormat;
+
+/// 日志级别过滤器的热更新句柄
+static FILTER_HANDLE: once_cell::sync::OnceCell<reload::Handle<EnvFilter, Registry>> =
+    once_cell::sync::OnceCell::new();
+
+/// 当前生效的日志级别指令
+static CURRENT_LEVEL: once_cell::sync::Lazy<std::sync::RwLock<String>> =
+    once_cell::sync::Lazy::new(|| std::sync::RwLock::new("info".to_string()));
+
+/// 本 crate 的模块前缀，允许在指令中省略（`proxy::upstream=debug`）
+const CRATE_TARGET: &str = "anti_proxy";
+const CRATE_MODULES: [&str; 3] = ["proxy", "modules", "utils"];
 
 // 自定义本地时区时间格式化器
 struct LocalTimer;
@@ -27,7 +40,9 @@ pub fn get_log_dir() -> Result<PathBuf, String> {
 }
 
 /// 初始化日志系统
-pub fn init_logger() {
+///
+/// 输出格式在启动时确定，JSON 模式下控制台与文件均输出单行 JSON
+pub fn init_logger(format: LogFormat) {
     // 捕获 log 宏日志
     let _ = tracing_log::LogTracer::init();
 
@@ -47,27 +62,41 @@ pub fn init_logger() {
             let file_appender = tracing_appender::rolling::daily(dir, "app.log");
             let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
             file_guard = Some(guard);
-            file_layer = Some(
-                fmt::Layer::new()
-                    .with_writer(non_blocking)
-                    .with_ansi(false)
-                    .with_target(true)
-                    .with_level(true)
-                    .with_timer(LocalTimer),
-            );
+            let layer = fmt::Layer::new()
+                .with_writer(non_blocking)
+                .with_ansi(false)
+                .with_target(true)
+                .with_level(true)
+                .with_timer(LocalTimer);
+            file_layer = Some(match format {
+                LogFormat::Json => layer.json().flatten_event(true).boxed(),
+                LogFormat::Text => layer.boxed(),
+            });
         } else {
             eprintln!("日志目录不可写，已降级为控制台输出");
         }
     }
 
-    let console_layer = fmt::Layer::new()
-        .with_target(false)
-        .with_thread_ids(false)
-        .with_level(true)
-        .with_timer(LocalTimer);
+    let console_layer = match format {
+        LogFormat::Json => fmt::Layer::new()
+            .with_target(true)
+            .with_level(true)
+            .with_timer(LocalTimer)
+            .json()
+            .flatten_event(true)
+            .boxed(),
+        LogFormat::Text => fmt::Layer::new()
+            .with_target(false)
+            .with_thread_ids(false)
+            .with_level(true)
+            .with_timer(LocalTimer)
+            .boxed(),
+    };
 
     let filter_layer =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let (filter_layer, filter_handle) = reload::Layer::new(filter_layer);
+    let _ = FILTER_HANDLE.set(filter_handle);
 
     let _ = tracing_subscriber::registry()
         .with(filter_layer)
@@ -83,6 +112,63 @@ pub fn init_logger() {
     }
 }
 
+/// 设置日志级别（EnvFilter 语法，如 `debug` 或 `info,proxy::upstream=debug,axum=warn`）
+///
+/// 设置了 RUST_LOG 环境变量时以环境变量为准，不做修改
+pub fn set_log_level(directives: &str) -> Result<(), String> {
+    if env_override_active() {
+        return Ok(());
+    }
+    let (normalized, filter) = parse_directives(directives)?;
+    let handle = FILTER_HANDLE.get().ok_or_else(|| "日志系统尚未初始化".to_string())?;
+    handle.reload(filter).map_err(|e| e.to_string())?;
+    *CURRENT_LEVEL.write().unwrap_or_else(|e| e.into_inner()) = normalized;
+    Ok(())
+}
+
+fn parse_directives(directives: &str) -> Result<(String, EnvFilter), String> {
+    let normalized = normalize_directives(directives);
+    let filter = EnvFilter::try_new(&normalized).map_err(|e| format!("无效的日志级别 '{}': {}", directives, e))?;
+    Ok((normalized, filter))
+}
+
+/// 校验日志级别指令（不修改当前级别）
+pub fn validate_log_level(directives: &str) -> Result<(), String> {
+    parse_directives(directives).map(|_| ())
+}
+
+/// 当前生效的日志级别指令
+pub fn current_log_level() -> String {
+    if env_override_active() {
+        return std::env::var("RUST_LOG").unwrap_or_default();
+    }
+    CURRENT_LEVEL.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// 是否由 RUST_LOG 环境变量接管日志级别
+pub fn env_override_active() -> bool {
+    std::env::var("RUST_LOG").is_ok()
+}
+
+/// 为省略 crate 名的目标补全前缀：`proxy::upstream=debug` -> `anti_proxy::proxy::upstream=debug`
+fn normalize_directives(directives: &str) -> String {
+    directives
+        .split(',')
+        .map(|d| d.trim())
+        .filter(|d| !d.is_empty())
+        .map(|d| {
+            let target = d.split(['=', '[']).next().unwrap_or("");
+            let first = target.split("::").next().unwrap_or("");
+            if d.contains('=') && CRATE_MODULES.contains(&first) {
+                format!("{}::{}", CRATE_TARGET, d)
+            } else {
+                d.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 fn is_log_dir_writable(dir: &PathBuf) -> bool {
     let probe = dir.join(".write_test");
     let result = fs::OpenOptions::new()
@@ -135,3 +221,20 @@ pub fn log_warn(message: &str) {
 pub fn log_error(message: &str) {
     error!("{}", message);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_directives() {
+        assert_eq!(
+            normalize_directives("info, proxy::upstream=debug,axum=warn"),
+            "info,anti_proxy::proxy::upstream=debug,axum=warn"
+        );
+        assert_eq!(normalize_directives("modules=trace"), "anti_proxy::modules=trace");
+        assert_eq!(normalize_directives("anti_proxy::proxy=debug"), "anti_proxy::proxy=debug");
+        // 不带目标的全局级别保持不变
+        assert_eq!(normalize_directives("debug"), "debug");
+    }
+}
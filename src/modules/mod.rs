@@ -1,10 +1,25 @@
 pub mod account;
+pub mod account_archive;
+pub mod alerts;
 pub mod api_keys;
+pub mod audit;
+pub mod backup;
 pub mod config;
+pub mod db_pool;
+pub mod gzip;
+pub mod log_archive;
 pub mod logger;
+pub mod model_aliases;
 pub mod oauth;
+pub mod postgres;
 pub mod proxy_db;
 pub mod quota;
+pub mod s3;
+pub mod secret_store;
+pub mod setup;
+pub mod storage;
+pub mod totp;
+pub mod usage_buffer;
 pub mod webauthn;
 
 pub use account::*;
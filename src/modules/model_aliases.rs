@@ -0,0 +1,232 @@
+//! 模型别名管理模块
+//! 将客户端请求的模型名映射到实际上游模型，并可按别名覆盖 temperature / max_tokens
+
+use once_cell::sync::Lazy;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// 模型别名
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ModelAlias {
+    /// 客户端请求的模型名
+    pub alias: String,
+    /// 实际使用的模型（仍会经过常规模型映射）
+    pub target: String,
+    /// 强制使用的 temperature（None 表示沿用请求值）
+    pub temperature: Option<f32>,
+    /// 强制使用的 max_tokens（None 表示沿用请求值）
+    pub max_tokens: Option<u32>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl ModelAlias {
+    /// 将别名应用到请求参数
+    pub fn apply(&self, model: &mut String, temperature: &mut Option<f32>, max_tokens: &mut Option<u32>) {
+        *model = self.target.clone();
+        if self.temperature.is_some() {
+            *temperature = self.temperature;
+        }
+        if self.max_tokens.is_some() {
+            *max_tokens = self.max_tokens;
+        }
+    }
+}
+
+/// 创建 / 更新别名请求
+#[derive(Debug, Deserialize)]
+pub struct ModelAliasRequest {
+    pub alias: Option<String>,
+    pub target: String,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+/// 内存缓存，避免每个请求都查询数据库（写操作后刷新）
+static ALIAS_CACHE: Lazy<RwLock<HashMap<String, ModelAlias>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+fn get_db_path() -> Result<PathBuf, String> {
+    let data_dir = crate::modules::account::get_data_dir()?;
+    Ok(data_dir.join("model_aliases.db"))
+}
+
+/// SELECT 使用的列顺序，需与 row_to_alias 保持一致
+const ALIAS_COLUMNS: &str = "alias, target, temperature, max_tokens, created_at, updated_at";
+
+fn row_to_alias(row: &rusqlite::Row) -> rusqlite::Result<ModelAlias> {
+    Ok(ModelAlias {
+        alias: row.get(0)?,
+        target: row.get(1)?,
+        temperature: row.get::<_, Option<f64>>(2)?.map(|v| v as f32),
+        max_tokens: row.get::<_, Option<i64>>(3)?.map(|v| v as u32),
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+    })
+}
+
+/// 初始化数据库并加载缓存
+pub fn init_db() -> Result<(), String> {
+    let db_path = get_db_path()?;
+    let conn = crate::modules::db_pool::open(&db_path)?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS model_aliases (
+            alias TEXT PRIMARY KEY,
+            target TEXT NOT NULL,
+            temperature REAL,
+            max_tokens INTEGER,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+
+    reload_cache()
+}
+
+fn reload_cache() -> Result<(), String> {
+    let aliases = list_aliases()?;
+    let mut cache = ALIAS_CACHE.write().unwrap_or_else(|e| e.into_inner());
+    *cache = aliases.into_iter().map(|a| (a.alias.clone(), a)).collect();
+    Ok(())
+}
+
+/// 获取所有别名
+pub fn list_aliases() -> Result<Vec<ModelAlias>, String> {
+    let db_path = get_db_path()?;
+    let conn = crate::modules::db_pool::open(&db_path)?;
+
+    let mut stmt = conn
+        .prepare(&format!("SELECT {} FROM model_aliases ORDER BY alias", ALIAS_COLUMNS))
+        .map_err(|e| e.to_string())?;
+
+    let rows = stmt.query_map([], row_to_alias).map_err(|e| e.to_string())?;
+
+    let mut aliases = Vec::new();
+    for alias in rows {
+        aliases.push(alias.map_err(|e| e.to_string())?);
+    }
+    Ok(aliases)
+}
+
+/// 获取单个别名
+pub fn get_alias(alias: &str) -> Result<Option<ModelAlias>, String> {
+    let db_path = get_db_path()?;
+    let conn = crate::modules::db_pool::open(&db_path)?;
+
+    let result = conn.query_row(
+        &format!("SELECT {} FROM model_aliases WHERE alias = ?1", ALIAS_COLUMNS),
+        [alias],
+        row_to_alias,
+    );
+
+    match result {
+        Ok(a) => Ok(Some(a)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// 校验别名参数
+pub fn validate(alias: &str, target: &str) -> Result<(), String> {
+    if alias.trim().is_empty() || target.trim().is_empty() {
+        return Err("alias and target are required".to_string());
+    }
+    if alias.trim() == target.trim() {
+        return Err("alias must differ from target".to_string());
+    }
+    Ok(())
+}
+
+/// 创建或更新别名
+pub fn upsert_alias(
+    alias: &str,
+    target: &str,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+) -> Result<ModelAlias, String> {
+    let alias = alias.trim();
+    let target = target.trim();
+    validate(alias, target)?;
+
+    let db_path = get_db_path()?;
+    let conn = crate::modules::db_pool::open(&db_path)?;
+
+    let now = chrono::Utc::now().timestamp();
+    conn.execute(
+        "INSERT INTO model_aliases (alias, target, temperature, max_tokens, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+         ON CONFLICT(alias) DO UPDATE SET
+            target = excluded.target,
+            temperature = excluded.temperature,
+            max_tokens = excluded.max_tokens,
+            updated_at = excluded.updated_at",
+        params![alias, target, temperature.map(|v| v as f64), max_tokens, now],
+    )
+    .map_err(|e| e.to_string())?;
+
+    reload_cache()?;
+    get_alias(alias)?.ok_or_else(|| "alias not found after upsert".to_string())
+}
+
+/// 删除别名，返回是否存在
+pub fn delete_alias(alias: &str) -> Result<bool, String> {
+    let db_path = get_db_path()?;
+    let conn = crate::modules::db_pool::open(&db_path)?;
+
+    let affected = conn
+        .execute("DELETE FROM model_aliases WHERE alias = ?1", params![alias])
+        .map_err(|e| e.to_string())?;
+
+    reload_cache()?;
+    Ok(affected > 0)
+}
+
+/// 查找请求模型对应的别名（读缓存）
+pub fn resolve(model: &str) -> Option<ModelAlias> {
+    ALIAS_CACHE
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(model)
+        .cloned()
+}
+
+/// 所有别名名称（用于 /v1/models）
+pub fn alias_names() -> Vec<String> {
+    ALIAS_CACHE
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .keys()
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_overrides_only_when_set() {
+        let alias = ModelAlias {
+            alias: "fast".to_string(),
+            target: "gemini-2.5-flash".to_string(),
+            temperature: Some(0.2),
+            max_tokens: None,
+            created_at: 0,
+            updated_at: 0,
+        };
+
+        let mut model = "fast".to_string();
+        let mut temperature = Some(1.0);
+        let mut max_tokens = Some(512);
+        alias.apply(&mut model, &mut temperature, &mut max_tokens);
+
+        assert_eq!(model, "gemini-2.5-flash");
+        assert_eq!(temperature, Some(0.2));
+        assert_eq!(max_tokens, Some(512));
+    }
+}
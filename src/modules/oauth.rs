@@ -1,5 +1,10 @@
 use serde::{Deserialize, Serialize};
-use std::sync::LazyLock;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::OAuthClientConfig;
 
 // Google OAuth 配置
 // 敏感凭证从环境变量读取，提供默认值作为 fallback（仅用于开发环境）
@@ -17,10 +22,145 @@ static CLIENT_SECRET: LazyLock<String> = LazyLock::new(|| {
     })
 });
 
+/// 内置客户端（`GOOGLE_CLIENT_ID` / `GOOGLE_CLIENT_SECRET`）的名称，未记录客户端的账号使用它
+pub const DEFAULT_CLIENT: &str = "default";
+/// 客户端被限流或停用后，新授权跳过它的时长
+const CLIENT_COOLDOWN: Duration = Duration::from_secs(15 * 60);
+
+/// 全局 OAuth 客户端配置（启动 / 热重载时写入）
+pub static OAUTH_CLIENTS: LazyLock<OAuthClients> = LazyLock::new(OAuthClients::default);
+
+/// 一组 OAuth 客户端凭证
+#[derive(Debug, Clone)]
+pub struct OAuthClient {
+    pub name: String,
+    client_id: String,
+    client_secret: String,
+}
+
+impl OAuthClient {
+    fn builtin() -> Self {
+        Self {
+            name: DEFAULT_CLIENT.to_string(),
+            client_id: CLIENT_ID.clone(),
+            client_secret: CLIENT_SECRET.clone(),
+        }
+    }
+}
+
+impl From<&OAuthClientConfig> for OAuthClient {
+    fn from(config: &OAuthClientConfig) -> Self {
+        Self {
+            name: config.name.clone(),
+            client_id: config.client_id.clone(),
+            client_secret: config.client_secret.clone(),
+        }
+    }
+}
+
+/// 校验 OAuth 客户端配置
+pub fn validate(clients: &[OAuthClientConfig]) -> Result<(), String> {
+    let mut names = std::collections::HashSet::new();
+    for client in clients {
+        if client.name.trim().is_empty() {
+            return Err("Invalid oauth client: name must not be empty".to_string());
+        }
+        if client.client_id.trim().is_empty() || client.client_secret.trim().is_empty() {
+            return Err(format!("Invalid oauth client {}: client_id and client_secret are required", client.name));
+        }
+        if !names.insert(client.name.as_str()) {
+            return Err(format!("Invalid oauth clients: duplicate name `{}`", client.name));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct OAuthClients {
+    clients: RwLock<Vec<OAuthClientConfig>>,
+    next: AtomicUsize,
+    /// 客户端名 -> 冷却结束时间
+    cooldowns: Mutex<HashMap<String, Instant>>,
+    /// 当前授权码流程使用的客户端（授权 URL 与换取 token 必须使用同一客户端）
+    login: RwLock<Option<String>>,
+}
+
+impl OAuthClients {
+    pub fn configure(&self, clients: &[OAuthClientConfig]) {
+        *self.clients.write().unwrap_or_else(|e| e.into_inner()) = clients.to_vec();
+    }
+
+    /// 账号使用的客户端；未记录或已从配置中删除时使用内置客户端
+    pub fn get(&self, name: Option<&str>) -> OAuthClient {
+        let clients = self.clients.read().unwrap_or_else(|e| e.into_inner());
+        let name = name.unwrap_or(DEFAULT_CLIENT);
+        match clients.iter().find(|c| c.name == name) {
+            Some(client) => client.into(),
+            None => {
+                if name != DEFAULT_CLIENT {
+                    tracing::warn!("OAuth client `{}` is not configured, using the built-in client", name);
+                }
+                OAuthClient::builtin()
+            }
+        }
+    }
+
+    /// 为新授权选择客户端：在配置的客户端间轮换，跳过冷却中的客户端（全部冷却时仍按顺序选择）
+    pub fn next_for_login(&self) -> OAuthClient {
+        let clients = self.clients.read().unwrap_or_else(|e| e.into_inner());
+        if clients.is_empty() {
+            return OAuthClient::builtin();
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % clients.len();
+        let now = Instant::now();
+        let cooldowns = self.cooldowns.lock().unwrap_or_else(|e| e.into_inner());
+        let available = (0..clients.len())
+            .map(|i| &clients[(start + i) % clients.len()])
+            .find(|c| cooldowns.get(&c.name).is_none_or(|until| *until <= now));
+        available.unwrap_or(&clients[start]).into()
+    }
+
+    /// 客户端被限流或被上游停用，冷却期内新授权不再选择它
+    pub fn report_throttled(&self, name: &str) {
+        tracing::warn!("OAuth client `{}` was throttled or rejected, skipping it for new logins", name);
+        self.cooldowns
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string(), Instant::now() + CLIENT_COOLDOWN);
+    }
+
+    fn begin_login(&self) -> OAuthClient {
+        let client = self.next_for_login();
+        *self.login.write().unwrap_or_else(|e| e.into_inner()) = Some(client.name.clone());
+        client
+    }
+
+    fn login_client(&self) -> OAuthClient {
+        let name = self.login.read().unwrap_or_else(|e| e.into_inner()).clone();
+        self.get(name.as_deref())
+    }
+}
+
+/// token 端点的错误是否表示客户端被限流或停用
+fn is_client_throttled(status: reqwest::StatusCode, body: &str) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || ["invalid_client", "unauthorized_client", "rate_limit"].iter().any(|e| body.contains(e))
+}
+
 const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
 const USERINFO_URL: &str = "https://www.googleapis.com/oauth2/v2/userinfo";
 
 const AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+const DEVICE_CODE_URL: &str = "https://oauth2.googleapis.com/device/code";
+const DEVICE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+const SCOPES: [&str; 5] = [
+    "https://www.googleapis.com/auth/cloud-platform",
+    "https://www.googleapis.com/auth/userinfo.email",
+    "https://www.googleapis.com/auth/userinfo.profile",
+    "https://www.googleapis.com/auth/cclog",
+    "https://www.googleapis.com/auth/experimentsandconfigs",
+];
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenResponse {
@@ -30,6 +170,9 @@ pub struct TokenResponse {
     pub token_type: String,
     #[serde(default)]
     pub refresh_token: Option<String>,
+    /// 签发 token 的客户端名称
+    #[serde(skip)]
+    pub oauth_client: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -62,18 +205,13 @@ impl UserInfo {
 }
 
 
-/// 生成 OAuth 授权 URL
+/// 生成 OAuth 授权 URL（同时选定本次授权使用的客户端）
 pub fn get_auth_url(redirect_uri: &str) -> String {
-    let scopes = vec![
-        "https://www.googleapis.com/auth/cloud-platform",
-        "https://www.googleapis.com/auth/userinfo.email",
-        "https://www.googleapis.com/auth/userinfo.profile",
-        "https://www.googleapis.com/auth/cclog",
-        "https://www.googleapis.com/auth/experimentsandconfigs"
-    ].join(" ");
+    let scopes = SCOPES.join(" ");
+    let client = OAUTH_CLIENTS.begin_login();
 
     let params = vec![
-        ("client_id", CLIENT_ID.as_str()),
+        ("client_id", client.client_id.as_str()),
         ("redirect_uri", redirect_uri),
         ("response_type", "code"),
         ("scope", &scopes),
@@ -89,10 +227,11 @@ pub fn get_auth_url(redirect_uri: &str) -> String {
 /// 使用 Authorization Code 交换 Token
 pub async fn exchange_code(code: &str, redirect_uri: &str) -> Result<TokenResponse, String> {
     let client = crate::utils::http::create_client(15);
+    let oauth_client = OAUTH_CLIENTS.login_client();
     
     let params = [
-        ("client_id", CLIENT_ID.as_str()),
-        ("client_secret", CLIENT_SECRET.as_str()),
+        ("client_id", oauth_client.client_id.as_str()),
+        ("client_secret", oauth_client.client_secret.as_str()),
         ("code", code),
         ("redirect_uri", redirect_uri),
         ("grant_type", "authorization_code"),
@@ -106,9 +245,10 @@ pub async fn exchange_code(code: &str, redirect_uri: &str) -> Result<TokenRespon
         .map_err(|e| format!("Token 交换请求失败: {}", e))?;
 
     if response.status().is_success() {
-        let token_res = response.json::<TokenResponse>()
+        let mut token_res = response.json::<TokenResponse>()
             .await
             .map_err(|e| format!("Token 解析失败: {}", e))?;
+        token_res.oauth_client = Some(oauth_client.name);
         
         // 添加详细日志
         crate::modules::logger::log_info(&format!(
@@ -129,18 +269,148 @@ pub async fn exchange_code(code: &str, redirect_uri: &str) -> Result<TokenRespon
         
         Ok(token_res)
     } else {
+        let status = response.status();
         let error_text = response.text().await.unwrap_or_default();
+        if is_client_throttled(status, &error_text) {
+            OAUTH_CLIENTS.report_throttled(&oauth_client.name);
+        }
         Err(format!("Token 交换失败: {}", error_text))
     }
 }
 
-/// 使用 refresh_token 刷新 access_token
-pub async fn refresh_access_token(refresh_token: &str) -> Result<TokenResponse, String> {
+/// 设备授权码（device authorization flow）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceCode {
+    pub device_code: String,
+    /// 用户在验证页面输入的短码
+    pub user_code: String,
+    #[serde(alias = "verification_uri")]
+    pub verification_url: String,
+    pub expires_in: u64,
+    /// 轮询间隔（秒）
+    #[serde(default = "default_device_interval")]
+    pub interval: u64,
+    /// 申请设备码的客户端，轮询时必须使用同一客户端
+    #[serde(skip)]
+    pub oauth_client: String,
+}
+
+fn default_device_interval() -> u64 {
+    5
+}
+
+/// 设备授权轮询的单次结果
+#[derive(Debug)]
+enum DevicePoll {
+    Token(TokenResponse),
+    Pending,
+    SlowDown,
+    Failed(String),
+}
+
+fn parse_device_poll(success: bool, body: &str) -> DevicePoll {
+    if success {
+        return match serde_json::from_str::<TokenResponse>(body) {
+            Ok(token) => DevicePoll::Token(token),
+            Err(e) => DevicePoll::Failed(format!("Token 解析失败: {}", e)),
+        };
+    }
+    let error = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v["error"].as_str().map(str::to_string))
+        .unwrap_or_default();
+    match error.as_str() {
+        "authorization_pending" => DevicePoll::Pending,
+        "slow_down" => DevicePoll::SlowDown,
+        "access_denied" => DevicePoll::Failed("Authorization was denied".to_string()),
+        "expired_token" => DevicePoll::Failed("Device code expired, start again".to_string()),
+        _ => DevicePoll::Failed(format!("Token 交换失败: {}", body)),
+    }
+}
+
+/// 申请设备授权码，用户在任意设备上打开验证页面并输入短码完成授权
+pub async fn request_device_code() -> Result<DeviceCode, String> {
     let client = crate::utils::http::create_client(15);
+    let oauth_client = OAUTH_CLIENTS.next_for_login();
+    let scopes = SCOPES.join(" ");
+    let params = [("client_id", oauth_client.client_id.as_str()), ("scope", scopes.as_str())];
+
+    let response = client
+        .post(DEVICE_CODE_URL)
+        .form(&params)
+        .send()
+        .await
+        .map_err(|e| format!("设备授权请求失败: {}", e))?;
+
+    if response.status().is_success() {
+        let mut device = response
+            .json::<DeviceCode>()
+            .await
+            .map_err(|e| format!("设备授权码解析失败: {}", e))?;
+        device.oauth_client = oauth_client.name;
+        Ok(device)
+    } else {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        if is_client_throttled(status, &error_text) {
+            OAUTH_CLIENTS.report_throttled(&oauth_client.name);
+        }
+        Err(format!("设备授权请求失败: {}", error_text))
+    }
+}
+
+/// 轮询直到用户完成设备授权；`cancelled` 返回 true 或授权码过期时结束
+pub async fn poll_device_token(device: &DeviceCode, cancelled: impl Fn() -> bool) -> Result<TokenResponse, String> {
+    let client = crate::utils::http::create_client(15);
+    let oauth_client = OAUTH_CLIENTS.get(Some(&device.oauth_client));
+    let params = [
+        ("client_id", oauth_client.client_id.as_str()),
+        ("client_secret", oauth_client.client_secret.as_str()),
+        ("device_code", device.device_code.as_str()),
+        ("grant_type", DEVICE_GRANT_TYPE),
+    ];
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(device.expires_in);
+    let mut interval = device.interval.max(1);
+
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+        if cancelled() {
+            return Err("Device authorization cancelled".to_string());
+        }
+        if std::time::Instant::now() >= deadline {
+            return Err("Device code expired, start again".to_string());
+        }
+
+        let response = match client.post(TOKEN_URL).form(&params).send().await {
+            Ok(response) => response,
+            // 网络抖动时继续轮询，直到授权码过期
+            Err(e) => {
+                tracing::warn!("Device token poll failed: {}", e);
+                continue;
+            }
+        };
+        let success = response.status().is_success();
+        let body = response.text().await.unwrap_or_default();
+        match parse_device_poll(success, &body) {
+            DevicePoll::Token(mut token) => {
+                token.oauth_client = Some(oauth_client.name);
+                return Ok(token);
+            }
+            DevicePoll::Pending => {}
+            DevicePoll::SlowDown => interval += 5,
+            DevicePoll::Failed(e) => return Err(e),
+        }
+    }
+}
+
+/// 使用 refresh_token 刷新 access_token（必须使用签发该 refresh_token 的客户端）
+pub async fn refresh_access_token(refresh_token: &str, oauth_client: Option<&str>) -> Result<TokenResponse, String> {
+    let client = crate::utils::http::create_client(15);
+    let oauth_client = OAUTH_CLIENTS.get(oauth_client);
     
     let params = [
-        ("client_id", CLIENT_ID.as_str()),
-        ("client_secret", CLIENT_SECRET.as_str()),
+        ("client_id", oauth_client.client_id.as_str()),
+        ("client_secret", oauth_client.client_secret.as_str()),
         ("refresh_token", refresh_token),
         ("grant_type", "refresh_token"),
     ];
@@ -163,7 +433,11 @@ pub async fn refresh_access_token(refresh_token: &str) -> Result<TokenResponse,
         crate::modules::logger::log_info(&format!("Token 刷新成功！有效期: {} 秒", token_data.expires_in));
         Ok(token_data)
     } else {
+        let status = response.status();
         let error_text = response.text().await.unwrap_or_default();
+        if is_client_throttled(status, &error_text) {
+            OAUTH_CLIENTS.report_throttled(&oauth_client.name);
+        }
         Err(format!("刷新失败: {}", error_text))
     }
 }
@@ -203,15 +477,66 @@ pub async fn ensure_fresh_token(
     
     // 需要刷新
     crate::modules::logger::log_info("Token 即将过期，正在刷新...");
-    let response = refresh_access_token(&current_token.refresh_token).await?;
+    let response = refresh_access_token(&current_token.refresh_token, current_token.oauth_client.as_deref()).await?;
     
     // 构造新 TokenData
-    Ok(crate::models::TokenData::new(
+    let mut token = crate::models::TokenData::new(
         response.access_token,
         current_token.refresh_token.clone(), // 刷新时不一定会返回新的 refresh_token
         response.expires_in,
         current_token.email.clone(),
         current_token.project_id.clone(), // 保留原有 project_id
         None,  // session_id 会在 token_manager 中生成
-    ))
+    );
+    token.oauth_client = current_token.oauth_client.clone();
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_device_poll() {
+        let token = parse_device_poll(true, r#"{"access_token": "ya29", "expires_in": 3599, "refresh_token": "1//r"}"#);
+        assert!(matches!(token, DevicePoll::Token(t) if t.refresh_token.as_deref() == Some("1//r")));
+        assert!(matches!(parse_device_poll(false, r#"{"error": "authorization_pending"}"#), DevicePoll::Pending));
+        assert!(matches!(parse_device_poll(false, r#"{"error": "slow_down"}"#), DevicePoll::SlowDown));
+        assert!(matches!(parse_device_poll(false, r#"{"error": "access_denied"}"#), DevicePoll::Failed(_)));
+        assert!(matches!(parse_device_poll(false, "bad gateway"), DevicePoll::Failed(_)));
+
+        let device: DeviceCode = serde_json::from_str(
+            r#"{"device_code": "d", "user_code": "ABCD-EFGH", "verification_uri": "https://www.google.com/device", "expires_in": 1800}"#,
+        )
+        .unwrap();
+        assert_eq!(device.verification_url, "https://www.google.com/device");
+        assert_eq!(device.interval, 5);
+    }
+
+    #[test]
+    fn test_oauth_client_rotation() {
+        let client = |name: &str| OAuthClientConfig {
+            name: name.to_string(),
+            client_id: format!("{}.apps.googleusercontent.com", name),
+            client_secret: "secret".to_string(),
+        };
+        let clients = OAuthClients::default();
+        assert_eq!(clients.next_for_login().name, DEFAULT_CLIENT);
+
+        let config = vec![client("a"), client("b")];
+        assert!(validate(&config).is_ok());
+        assert!(validate(&[client("a"), client("a")]).is_err());
+        clients.configure(&config);
+        assert_eq!(clients.next_for_login().name, "a");
+        assert_eq!(clients.next_for_login().name, "b");
+
+        clients.report_throttled("a");
+        assert_eq!(clients.next_for_login().name, "b");
+        assert_eq!(clients.next_for_login().name, "b");
+
+        // 账号记录的客户端已删除时回退到内置客户端
+        assert_eq!(clients.get(Some("b")).client_id, "b.apps.googleusercontent.com");
+        assert_eq!(clients.get(Some("removed")).name, DEFAULT_CLIENT);
+        assert_eq!(clients.get(None).name, DEFAULT_CLIENT);
+    }
 }
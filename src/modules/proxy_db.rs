@@ -1,51 +1,304 @@
-use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use crate::modules::storage::{self, params, Database, Dialect, Row, Store, Value};
 use crate::proxy::monitor::ProxyRequestLog;
 
+/// SELECT 使用的列顺序，需与 row_to_log 保持一致
+const LOG_COLUMNS: &str = "id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, key_id, account_email, estimated_cost, finish_reason, replay_of, tags, client_ip, request_id, usage_estimated, request_body_modified, sample_weight";
+/// 每行日志代表的请求数（采样保留的成功请求带权重，其余为 1）
+const SAMPLE_WEIGHT: &str = "COALESCE(sample_weight, 1)";
+
+/// 历史日志查询条件 (/api/logs)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LogQuery {
+    /// 起始时间 (毫秒时间戳，含)
+    pub from: Option<i64>,
+    /// 结束时间 (毫秒时间戳，含)
+    pub to: Option<i64>,
+    pub key_id: Option<String>,
+    pub model: Option<String>,
+    /// 上游账号邮箱
+    pub account: Option<String>,
+    /// 精确状态码 (如 "429") 或状态类别 (如 "5xx")
+    pub status: Option<String>,
+    /// 标签过滤，`key=value`
+    pub tag: Option<String>,
+    /// 客户端 IP
+    pub client_ip: Option<String>,
+    /// 请求 ID（`x-request-id`）
+    pub request_id: Option<String>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+const DEFAULT_QUERY_LIMIT: usize = 100;
+const MAX_QUERY_LIMIT: usize = 1000;
+
+/// 用量统计的时间粒度
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageBucket {
+    #[default]
+    Hour,
+    Day,
+}
+
+impl UsageBucket {
+    fn millis(self) -> i64 {
+        match self {
+            Self::Hour => 3_600_000,
+            Self::Day => 86_400_000,
+        }
+    }
+
+    /// 未指定起始时间时的默认统计范围
+    fn default_range_millis(self) -> i64 {
+        match self {
+            Self::Hour => 86_400_000,
+            Self::Day => 30 * 86_400_000,
+        }
+    }
+}
+
+/// 用量统计的分组维度
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageGroupBy {
+    #[default]
+    None,
+    Key,
+    Model,
+    Account,
+    /// 按 `tag_key` 指定的标签值分组
+    Tag,
+}
+
+impl UsageGroupBy {
+    /// 分组表达式（固定白名单，不拼接用户输入）；标签路径作为参数绑定，`tag_path` 为其占位符
+    fn column(self, dialect: Dialect, tag_path: &str) -> String {
+        match self {
+            Self::None => "NULL".to_string(),
+            Self::Key => "key_id".to_string(),
+            Self::Model => "model".to_string(),
+            Self::Account => "account_email".to_string(),
+            Self::Tag => dialect.json_text("tags", tag_path),
+        }
+    }
+}
+
+/// 用量统计查询条件 (/api/stats/usage)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UsageQuery {
+    /// 起始时间 (毫秒时间戳，含)，默认按粒度回溯 24 小时 / 30 天
+    pub from: Option<i64>,
+    /// 结束时间 (毫秒时间戳，含)
+    pub to: Option<i64>,
+    #[serde(default)]
+    pub bucket: UsageBucket,
+    #[serde(default)]
+    pub group_by: UsageGroupBy,
+    pub key_id: Option<String>,
+    pub model: Option<String>,
+    pub account: Option<String>,
+    /// group_by=tag 时的标签名
+    pub tag_key: Option<String>,
+    /// 标签过滤，`key=value`
+    pub tag: Option<String>,
+}
+
+/// 单个时间桶 + 分组的聚合结果
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageRow {
+    /// 时间桶起点 (毫秒时间戳，UTC 对齐)
+    pub bucket_start: i64,
+    /// 分组值 (group_by=none 时为 null)
+    pub group: Option<String>,
+    pub requests: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// 估算费用合计（未配置价格的模型不计入）
+    pub estimated_cost: f64,
+    pub avg_duration_ms: f64,
+}
+
+fn row_to_log(row: &Row) -> Result<ProxyRequestLog, String> {
+    Ok(ProxyRequestLog {
+        id: row.get(0)?,
+        timestamp: row.get(1)?,
+        method: row.get(2)?,
+        url: row.get(3)?,
+        status: row.get(4)?,
+        duration: row.get(5)?,
+        model: row.get(6)?,
+        error: row.get(7)?,
+        request_body: row.get(8).unwrap_or(None),
+        response_body: row.get(9).unwrap_or(None),
+        input_tokens: row.get(10).unwrap_or(None),
+        output_tokens: row.get(11).unwrap_or(None),
+        key_id: row.get(12).unwrap_or(None),
+        account_email: row.get(13).unwrap_or(None),
+        estimated_cost: row.get(14).unwrap_or(None),
+        finish_reason: row.get(15).unwrap_or(None),
+        replay_of: row.get(16).unwrap_or(None),
+        tags: row
+            .get::<Option<String>>(17)
+            .unwrap_or(None)
+            .and_then(|json| serde_json::from_str(&json).ok()),
+        client_ip: row.get(18).unwrap_or(None),
+        request_id: row.get(19).unwrap_or(None),
+        usage_estimated: row.get::<Option<bool>>(20).unwrap_or(None).unwrap_or(false),
+        request_body_modified: row.get::<Option<bool>>(21).unwrap_or(None).unwrap_or(false),
+        sample_weight: row.get(22).unwrap_or(None),
+    })
+}
+
+/// 日志查询与用量统计的错误
+#[derive(Debug, thiserror::Error)]
+pub enum QueryError {
+    /// 查询参数不合法
+    #[error("{0}")]
+    BadRequest(String),
+    /// 数据库读取失败
+    #[error("{0}")]
+    Storage(String),
+}
+
+impl From<String> for QueryError {
+    fn from(e: String) -> Self {
+        QueryError::Storage(e)
+    }
+}
+
+/// 标签名对应的 JSON 路径；标签名不合法时返回错误
+fn tag_path(key: &str) -> Result<String, QueryError> {
+    let key = key.trim().to_lowercase();
+    if !crate::proxy::monitor::is_valid_tag_key(&key) {
+        return Err(QueryError::BadRequest(format!("Invalid tag key: {}", key)));
+    }
+    Ok(format!("$.\"{}\"", key))
+}
+
+/// 解析状态过滤条件，返回 [min, max] 闭区间
+pub(crate) fn parse_status_filter(status: &str) -> Option<(u16, u16)> {
+    let status = status.trim().to_lowercase();
+    if let Some(class) = status.strip_suffix("xx") {
+        let digit: u16 = class.parse().ok()?;
+        if (1..=5).contains(&digit) {
+            return Some((digit * 100, digit * 100 + 99));
+        }
+        return None;
+    }
+    let code: u16 = status.parse().ok()?;
+    Some((code, code))
+}
+
+/// 构建 WHERE 子句，参数追加到 `values`（占位符按已有参数个数继续编号）
+fn build_filter(query: &LogQuery, dialect: Dialect, values: &mut Vec<Value>) -> Result<String, QueryError> {
+    let mut clauses: Vec<String> = Vec::new();
+
+    if let Some(from) = query.from {
+        clauses.push(format!("timestamp >= {}", storage::bind(values, from)));
+    }
+    if let Some(to) = query.to {
+        clauses.push(format!("timestamp <= {}", storage::bind(values, to)));
+    }
+    if let Some(key_id) = query.key_id.as_ref().filter(|v| !v.is_empty()) {
+        clauses.push(format!("key_id = {}", storage::bind(values, key_id)));
+    }
+    if let Some(model) = query.model.as_ref().filter(|v| !v.is_empty()) {
+        clauses.push(format!("model = {}", storage::bind(values, model)));
+    }
+    if let Some(account) = query.account.as_ref().filter(|v| !v.is_empty()) {
+        clauses.push(format!("account_email = {}", storage::bind(values, account)));
+    }
+    if let Some(client_ip) = query.client_ip.as_ref().filter(|v| !v.is_empty()) {
+        clauses.push(format!("client_ip = {}", storage::bind(values, client_ip)));
+    }
+    if let Some(request_id) = query.request_id.as_ref().filter(|v| !v.is_empty()) {
+        clauses.push(format!("request_id = {}", storage::bind(values, request_id)));
+    }
+    if let Some(status) = query.status.as_ref().filter(|v| !v.is_empty()) {
+        let (min, max) = parse_status_filter(status)
+            .ok_or_else(|| QueryError::BadRequest(format!("Invalid status filter: {}", status)))?;
+        clauses.push(format!(
+            "status BETWEEN {} AND {}",
+            storage::bind(values, min),
+            storage::bind(values, max)
+        ));
+    }
+    if let Some(tag) = query.tag.as_ref().filter(|v| !v.is_empty()) {
+        let (key, value) = tag
+            .split_once('=')
+            .ok_or_else(|| QueryError::BadRequest(format!("Invalid tag filter: {}, expected key=value", tag)))?;
+        let path = storage::bind(values, tag_path(key)?);
+        clauses.push(format!("{} = {}", dialect.json_text("tags", &path), storage::bind(values, value.trim())));
+    }
+
+    if clauses.is_empty() {
+        Ok(String::new())
+    } else {
+        Ok(format!(" WHERE {}", clauses.join(" AND ")))
+    }
+}
+
 pub fn get_proxy_db_path() -> Result<PathBuf, String> {
     let data_dir = crate::modules::account::get_data_dir()?;
     Ok(data_dir.join("proxy_logs.db"))
 }
 
 pub fn init_db() -> Result<(), String> {
-    let db_path = get_proxy_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-    
+    let mut conn = storage::open(Database::Logs)?;
+    ensure_schema(conn.as_mut())
+}
+
+fn ensure_schema(conn: &mut dyn Store) -> Result<(), String> {
     conn.execute(
         "CREATE TABLE IF NOT EXISTS request_logs (
             id TEXT PRIMARY KEY,
-            timestamp INTEGER,
+            timestamp BIGINT,
             method TEXT,
             url TEXT,
-            status INTEGER,
-            duration INTEGER,
+            status BIGINT,
+            duration BIGINT,
             model TEXT,
             error TEXT
         )",
-        [],
-    ).map_err(|e| e.to_string())?;
+        params![],
+    )?;
 
-    // Try to add new columns (ignore errors if they exist)
-    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN request_body TEXT", []);
-    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN response_body TEXT", []);
-    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN input_tokens INTEGER", []);
-    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN output_tokens INTEGER", []);
+    // Add columns introduced by later versions (skipped if they exist)
+    conn.add_column("request_logs", "request_body TEXT")?;
+    conn.add_column("request_logs", "response_body TEXT")?;
+    conn.add_column("request_logs", "input_tokens BIGINT")?;
+    conn.add_column("request_logs", "output_tokens BIGINT")?;
+    conn.add_column("request_logs", "key_id TEXT")?;
+    conn.add_column("request_logs", "account_email TEXT")?;
+    conn.add_column("request_logs", "estimated_cost DOUBLE PRECISION")?;
+    conn.add_column("request_logs", "finish_reason TEXT")?;
+    conn.add_column("request_logs", "replay_of TEXT")?;
+    conn.add_column("request_logs", "tags TEXT")?;
+    conn.add_column("request_logs", "client_ip TEXT")?;
+    conn.add_column("request_logs", "request_id TEXT")?;
+    conn.add_column("request_logs", "usage_estimated BIGINT")?;
+    conn.add_column("request_logs", "request_body_modified BIGINT")?;
+    conn.add_column("request_logs", "sample_weight BIGINT")?;
 
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_timestamp ON request_logs (timestamp DESC)",
-        [],
-    ).map_err(|e| e.to_string())?;
+        params![],
+    )?;
 
     Ok(())
 }
 
 pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
-    let db_path = get_proxy_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = storage::open(Database::Logs)?;
 
     conn.execute(
-        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, key_id, account_email, estimated_cost, finish_reason, replay_of, tags, client_ip, request_id, usage_estimated, request_body_modified, sample_weight)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23)",
         params![
             log.id,
             log.timestamp,
@@ -59,79 +312,401 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
             log.response_body,
             log.input_tokens,
             log.output_tokens,
+            log.key_id,
+            log.account_email,
+            log.estimated_cost,
+            log.finish_reason,
+            log.replay_of,
+            log.tags.as_ref().and_then(|tags| serde_json::to_string(tags).ok()),
+            log.client_ip,
+            log.request_id,
+            log.usage_estimated,
+            log.request_body_modified,
+            log.sample_weight,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     Ok(())
 }
 
+/// 按 ID 读取单条日志（含请求 / 响应体）
+pub fn get_log(id: &str) -> Result<Option<ProxyRequestLog>, String> {
+    let mut conn = storage::open(Database::Logs)?;
+    conn.query_row(&format!("SELECT {} FROM request_logs WHERE id = $1", LOG_COLUMNS), params![id])?
+        .map(|row| row_to_log(&row))
+        .transpose()
+}
+
 pub fn get_logs(limit: usize) -> Result<Vec<ProxyRequestLog>, String> {
-    let db_path = get_proxy_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn.prepare(
-        "SELECT id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens
-         FROM request_logs 
-         ORDER BY timestamp DESC 
-         LIMIT ?1"
-    ).map_err(|e| e.to_string())?;
-
-    let logs_iter = stmt.query_map([limit], |row| {
-        Ok(ProxyRequestLog {
-            id: row.get(0)?,
-            timestamp: row.get(1)?,
-            method: row.get(2)?,
-            url: row.get(3)?,
-            status: row.get(4)?,
-            duration: row.get(5)?,
-            model: row.get(6)?,
-            error: row.get(7)?,
-            request_body: row.get(8).unwrap_or(None),
-            response_body: row.get(9).unwrap_or(None),
-            input_tokens: row.get(10).unwrap_or(None),
-            output_tokens: row.get(11).unwrap_or(None),
+    let mut conn = storage::open(Database::Logs)?;
+
+    conn.query(
+        &format!("SELECT {} FROM request_logs ORDER BY timestamp DESC LIMIT $1", LOG_COLUMNS),
+        params![limit],
+    )?
+    .iter()
+    .map(row_to_log)
+    .collect()
+}
+
+/// 按条件查询历史日志，返回 (匹配总数, 当前页)
+pub fn query_logs(query: &LogQuery) -> Result<(u64, Vec<ProxyRequestLog>), QueryError> {
+    let mut conn = storage::open(Database::Logs)?;
+
+    let mut values = Vec::new();
+    let where_sql = build_filter(query, conn.dialect(), &mut values)?;
+
+    let total: u64 = conn
+        .query_row(&format!("SELECT COUNT(*) FROM request_logs{}", where_sql), &values)?
+        .map_or(Ok(0), |row| row.get(0))?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT).clamp(1, MAX_QUERY_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    let sql = format!(
+        "SELECT {} FROM request_logs{} ORDER BY timestamp DESC LIMIT {} OFFSET {}",
+        LOG_COLUMNS,
+        where_sql,
+        storage::bind(&mut values, limit),
+        storage::bind(&mut values, offset)
+    );
+
+    let logs = conn
+        .query(&sql, &values)?
+        .iter()
+        .map(row_to_log)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((total, logs))
+}
+
+/// 校验查询条件（流式导出在开始输出前调用，避免响应头发出后才报错）
+pub fn validate_query(query: &LogQuery) -> Result<(), QueryError> {
+    build_filter(query, storage::dialect(), &mut Vec::new()).map(|_| ())
+}
+
+/// 按时间正序逐条遍历匹配的日志（不含请求 / 响应体，忽略分页参数）
+///
+/// 逐行读取，不会把结果集整体载入内存；`visit` 返回 false 时提前结束
+pub fn for_each_log(query: &LogQuery, visit: impl FnMut(ProxyRequestLog) -> bool) -> Result<(), String> {
+    let mut conn = storage::open(Database::Logs)?;
+    visit_logs(conn.as_mut(), query, visit)
+}
+
+fn visit_logs(
+    conn: &mut dyn Store,
+    query: &LogQuery,
+    mut visit: impl FnMut(ProxyRequestLog) -> bool,
+) -> Result<(), String> {
+    let mut values = Vec::new();
+    let where_sql = build_filter(query, conn.dialect(), &mut values).map_err(|e| e.to_string())?;
+    // 请求 / 响应体以 NULL 占位，保持与 row_to_log 的列顺序一致
+    conn.for_each_row(
+        &format!(
+            "SELECT id, timestamp, method, url, status, duration, model, error, NULL, NULL,
+                    input_tokens, output_tokens, key_id, account_email, estimated_cost, finish_reason, replay_of, tags, client_ip, request_id, usage_estimated, request_body_modified, sample_weight
+             FROM request_logs{} ORDER BY timestamp ASC",
+            where_sql
+        ),
+        &values,
+        &mut |row| Ok(visit(row_to_log(&row)?)),
+    )
+}
+
+/// 按时间桶与分组聚合请求日志
+pub fn usage_stats(query: &UsageQuery) -> Result<Vec<UsageRow>, QueryError> {
+    let mut conn = storage::open(Database::Logs)?;
+    aggregate_usage(conn.as_mut(), query, chrono::Utc::now().timestamp_millis())
+}
+
+fn aggregate_usage(conn: &mut dyn Store, query: &UsageQuery, now_ms: i64) -> Result<Vec<UsageRow>, QueryError> {
+    let to = query.to.unwrap_or(now_ms);
+    let from = query.from.unwrap_or(to - query.bucket.default_range_millis());
+    let filter = LogQuery {
+        from: Some(from),
+        to: Some(to),
+        key_id: query.key_id.clone(),
+        model: query.model.clone(),
+        account: query.account.clone(),
+        tag: query.tag.clone(),
+        ..Default::default()
+    };
+    let dialect = conn.dialect();
+    // 分组表达式位于 WHERE 之前，其参数先绑定
+    let mut values: Vec<Value> = Vec::new();
+    let mut group_tag = String::new();
+    if query.group_by == UsageGroupBy::Tag {
+        let key = query
+            .tag_key
+            .as_deref()
+            .filter(|k| !k.is_empty())
+            .ok_or_else(|| QueryError::BadRequest("Invalid group_by=tag: tag_key is required".to_string()))?;
+        group_tag = storage::bind(&mut values, tag_path(key)?);
+    }
+    let where_sql = build_filter(&filter, dialect, &mut values)?;
+
+    let bucket_ms = query.bucket.millis();
+    let rows = conn.query(
+        &format!(
+            "SELECT (timestamp / {bucket}) * {bucket} AS bucket_start, {group} AS grp,
+                    SUM({weight}),
+                    SUM(CASE WHEN status < 200 OR status >= 400 THEN {weight} ELSE 0 END),
+                    COALESCE(SUM(input_tokens * {weight}), 0),
+                    COALESCE(SUM(output_tokens * {weight}), 0),
+                    COALESCE(SUM(duration * {weight}) * 1.0 / SUM({weight}), 0),
+                    COALESCE(SUM(estimated_cost * {weight}), 0)
+             FROM request_logs{where_sql}
+             GROUP BY bucket_start, grp
+             ORDER BY bucket_start, grp",
+            bucket = bucket_ms,
+            group = query.group_by.column(dialect, &group_tag),
+            weight = SAMPLE_WEIGHT,
+            where_sql = where_sql,
+        ),
+        &values,
+    )?;
+
+    rows.iter()
+        .map(|row| {
+            let requests: u64 = row.get(2)?;
+            let errors: u64 = row.get(3)?;
+            Ok(UsageRow {
+                bucket_start: row.get(0)?,
+                group: row.get(1)?,
+                requests,
+                errors,
+                error_rate: if requests == 0 { 0.0 } else { errors as f64 / requests as f64 },
+                input_tokens: row.get(4)?,
+                output_tokens: row.get(5)?,
+                avg_duration_ms: row.get(6)?,
+                estimated_cost: row.get(7)?,
+            })
         })
-    }).map_err(|e| e.to_string())?;
+        .collect()
+}
+
+/// 按保留策略清理旧日志，返回删除的行数
+pub fn prune_logs(max_days: u32, max_rows: u64) -> Result<usize, String> {
+    let mut conn = storage::open(Database::Logs)?;
 
-    let mut logs = Vec::new();
-    for log in logs_iter {
-        logs.push(log.map_err(|e| e.to_string())?);
+    let mut deleted = 0;
+    if max_days > 0 {
+        let cutoff = chrono::Utc::now().timestamp_millis() - max_days as i64 * 86_400_000;
+        deleted += conn.execute("DELETE FROM request_logs WHERE timestamp < $1", params![cutoff])?;
+    }
+    if max_rows > 0 {
+        deleted += conn.execute(
+            "DELETE FROM request_logs WHERE id NOT IN (
+                SELECT id FROM request_logs ORDER BY timestamp DESC LIMIT $1
+            )",
+            params![max_rows as i64],
+        )?;
     }
-    Ok(logs)
+    Ok(deleted)
+}
+
+/// 超出保留策略、下次清理会删除的日志（含请求 / 响应体），按时间正序，最多 `limit` 条
+pub fn prunable_logs(max_days: u32, max_rows: u64, limit: usize) -> Result<Vec<ProxyRequestLog>, String> {
+    let mut conn = storage::open(Database::Logs)?;
+
+    let mut conditions = Vec::new();
+    let mut values: Vec<Value> = Vec::new();
+    if max_days > 0 {
+        let cutoff = chrono::Utc::now().timestamp_millis() - max_days as i64 * 86_400_000;
+        conditions.push(format!("timestamp < {}", storage::bind(&mut values, cutoff)));
+    }
+    if max_rows > 0 {
+        conditions.push(format!(
+            "id NOT IN (SELECT id FROM request_logs ORDER BY timestamp DESC LIMIT {})",
+            storage::bind(&mut values, max_rows)
+        ));
+    }
+    if conditions.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sql = format!(
+        "SELECT {} FROM request_logs WHERE {} ORDER BY timestamp ASC LIMIT {}",
+        LOG_COLUMNS,
+        conditions.join(" OR "),
+        storage::bind(&mut values, limit)
+    );
+
+    conn.query(&sql, &values)?.iter().map(row_to_log).collect()
+}
+
+/// 按 ID 删除日志（归档上传成功后调用）
+pub fn delete_logs(ids: &[String]) -> Result<usize, String> {
+    let mut conn = storage::open(Database::Logs)?;
+    storage::transaction(conn.as_mut(), |tx| {
+        let mut deleted = 0;
+        for id in ids {
+            deleted += tx.execute("DELETE FROM request_logs WHERE id = $1", params![id])?;
+        }
+        Ok(deleted)
+    })
 }
 
 pub fn get_stats() -> Result<crate::proxy::monitor::ProxyStats, String> {
-    let db_path = get_proxy_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let total_requests: u64 = conn.query_row(
-        "SELECT COUNT(*) FROM request_logs",
-        [],
-        |row| row.get(0),
-    ).map_err(|e| e.to_string())?;
-
-    let success_count: u64 = conn.query_row(
-        "SELECT COUNT(*) FROM request_logs WHERE status >= 200 AND status < 400",
-        [],
-        |row| row.get(0),
-    ).map_err(|e| e.to_string())?;
-
-    let error_count: u64 = conn.query_row(
-        "SELECT COUNT(*) FROM request_logs WHERE status < 200 OR status >= 400",
-        [],
-        |row| row.get(0),
-    ).map_err(|e| e.to_string())?;
+    let mut conn = storage::open(Database::Logs)?;
+    let mut sum = |filter: &str| -> Result<u64, String> {
+        conn.query_row(&format!("SELECT COALESCE(SUM({}), 0) FROM request_logs{}", SAMPLE_WEIGHT, filter), params![])?
+            .map_or(Ok(0), |row| row.get(0))
+    };
 
     Ok(crate::proxy::monitor::ProxyStats {
-        total_requests,
-        success_count,
-        error_count,
+        total_requests: sum("")?,
+        success_count: sum(" WHERE status >= 200 AND status < 400")?,
+        error_count: sum(" WHERE status < 200 OR status >= 400")?,
     })
 }
 
 pub fn clear_logs() -> Result<(), String> {
-    let db_path = get_proxy_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-    conn.execute("DELETE FROM request_logs", []).map_err(|e| e.to_string())?;
+    let mut conn = storage::open(Database::Logs)?;
+    conn.execute("DELETE FROM request_logs", params![])?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::{params, Connection};
+
+    #[test]
+    fn test_parse_status_filter() {
+        assert_eq!(parse_status_filter("429"), Some((429, 429)));
+        assert_eq!(parse_status_filter("5xx"), Some((500, 599)));
+        assert_eq!(parse_status_filter("2XX"), Some((200, 299)));
+        assert_eq!(parse_status_filter("9xx"), None);
+        assert_eq!(parse_status_filter("abc"), None);
+    }
+
+    #[test]
+    fn test_build_filter() {
+        let query = LogQuery {
+            from: Some(1),
+            key_id: Some("key-1".to_string()),
+            model: Some(String::new()),
+            status: Some("4xx".to_string()),
+            ..Default::default()
+        };
+        let mut values = Vec::new();
+        let where_sql = build_filter(&query, Dialect::Sqlite, &mut values).unwrap();
+        assert_eq!(where_sql, " WHERE timestamp >= $1 AND key_id = $2 AND status BETWEEN $3 AND $4");
+        assert_eq!(values.len(), 4);
+
+        let bad = LogQuery { status: Some("oops".to_string()), ..Default::default() };
+        assert!(matches!(build_filter(&bad, Dialect::Sqlite, &mut Vec::new()), Err(QueryError::BadRequest(_))));
+
+        // 占位符接着已有参数编号
+        let tagged = LogQuery { tag: Some("Project=alpha".to_string()), ..Default::default() };
+        let mut values = vec![Value::Null];
+        let where_sql = build_filter(&tagged, Dialect::Sqlite, &mut values).unwrap();
+        assert_eq!(where_sql, " WHERE json_extract(tags, $2) = $3");
+        assert_eq!(values.len(), 3);
+        let where_sql = build_filter(&tagged, Dialect::Postgres, &mut Vec::new()).unwrap();
+        assert_eq!(
+            where_sql,
+            " WHERE (jsonb_path_query_first(CAST(tags AS jsonb), CAST($1 AS jsonpath)) #>> '{}') = $2"
+        );
+        for tag in ["project", "bad key=x", "x\"y=z"] {
+            let bad = LogQuery { tag: Some(tag.to_string()), ..Default::default() };
+            assert!(matches!(build_filter(&bad, Dialect::Sqlite, &mut Vec::new()), Err(QueryError::BadRequest(_))));
+        }
+    }
+
+    #[test]
+    fn test_visit_logs_in_order_and_stops_early() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&mut conn).unwrap();
+        for (id, ts, status) in [("b", 20, 500), ("a", 10, 200), ("c", 30, 200)] {
+            conn.execute(
+                "INSERT INTO request_logs (id, timestamp, method, url, status, duration, request_body)
+                 VALUES (?1, ?2, 'POST', '/v1/messages', ?3, 100, 'body')",
+                params![id, ts, status],
+            )
+            .unwrap();
+        }
+
+        let mut seen = Vec::new();
+        let query = LogQuery { status: Some("2xx".to_string()), ..Default::default() };
+        visit_logs(&mut conn, &query, |log| {
+            assert!(log.request_body.is_none());
+            seen.push(log.id);
+            true
+        })
+        .unwrap();
+        assert_eq!(seen, vec!["a", "c"]);
+
+        let mut count = 0;
+        visit_logs(&mut conn, &LogQuery::default(), |_| {
+            count += 1;
+            false
+        })
+        .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_aggregate_usage() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&mut conn).unwrap();
+        let hour = 3_600_000;
+        let rows = [
+            ("a", 10, 200, "m1", "k1", "x@example.com", 10, 5),
+            ("b", 20, 429, "m1", "k1", "y@example.com", 0, 0),
+            ("c", hour + 1, 200, "m2", "k2", "x@example.com", 1, 2),
+        ];
+        for (id, ts, status, model, key, account, input, output) in rows {
+            conn.execute(
+                "INSERT INTO request_logs (id, timestamp, status, duration, model, key_id, account_email, input_tokens, output_tokens)
+                 VALUES (?1, ?2, ?3, 100, ?4, ?5, ?6, ?7, ?8)",
+                params![id, ts, status, model, key, account, input, output],
+            )
+            .unwrap();
+        }
+
+        let query = UsageQuery { from: Some(0), ..Default::default() };
+        let result = aggregate_usage(&mut conn, &query, 2 * hour).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].bucket_start, 0);
+        assert_eq!((result[0].requests, result[0].errors, result[0].input_tokens), (2, 1, 10));
+        assert_eq!(result[0].error_rate, 0.5);
+        assert_eq!(result[1].bucket_start, hour);
+
+        let query = UsageQuery {
+            from: Some(0),
+            bucket: UsageBucket::Day,
+            group_by: UsageGroupBy::Account,
+            ..Default::default()
+        };
+        let result = aggregate_usage(&mut conn, &query, 2 * hour).unwrap();
+        let groups: Vec<_> = result.iter().map(|r| (r.group.as_deref(), r.requests, r.output_tokens)).collect();
+        assert_eq!(groups, vec![(Some("x@example.com"), 2, 7), (Some("y@example.com"), 1, 0)]);
+
+        for (id, tags) in [("a", r#"{"project":"alpha","team":"ml"}"#), ("c", r#"{"project":"beta"}"#)] {
+            conn.execute("UPDATE request_logs SET tags = ?1 WHERE id = ?2", params![tags, id]).unwrap();
+        }
+        let query = UsageQuery {
+            from: Some(0),
+            bucket: UsageBucket::Day,
+            group_by: UsageGroupBy::Tag,
+            tag_key: Some("project".to_string()),
+            model: Some("m1".to_string()),
+            ..Default::default()
+        };
+        let result = aggregate_usage(&mut conn, &query, 2 * hour).unwrap();
+        let groups: Vec<_> = result.iter().map(|r| (r.group.as_deref(), r.requests)).collect();
+        assert_eq!(groups, vec![(None, 1), (Some("alpha"), 1)]);
+
+        let query = UsageQuery { from: Some(0), tag: Some("team=ml".to_string()), ..Default::default() };
+        let result = aggregate_usage(&mut conn, &query, 2 * hour).unwrap();
+        assert_eq!((result.len(), result[0].input_tokens), (1, 10));
+
+        let query = UsageQuery { group_by: UsageGroupBy::Tag, ..Default::default() };
+        assert!(matches!(aggregate_usage(&mut conn, &query, 2 * hour), Err(QueryError::BadRequest(_))));
+
+        // 采样保留的成功请求按权重计入请求数、token 与费用
+        conn.execute("UPDATE request_logs SET sample_weight = 10, estimated_cost = 0.5 WHERE id = 'c'", []).unwrap();
+        let result = aggregate_usage(&mut conn, &UsageQuery { from: Some(0), ..Default::default() }, 2 * hour).unwrap();
+        assert_eq!((result[1].requests, result[1].errors, result[1].input_tokens, result[1].output_tokens), (10, 0, 10, 20));
+        assert_eq!((result[1].estimated_cost, result[1].avg_duration_ms), (5.0, 100.0));
+    }
+}
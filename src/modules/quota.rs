@@ -230,3 +230,273 @@ pub async fn fetch_all_quotas(
 
     results
 }
+
+// ===== API Key 每日 token 预算 =====
+// 用量由 usage_buffer 批量按自然日写入，这里负责计算预算日与拦截超额请求
+
+/// 预算日切换所用的时区（默认 UTC）
+static BUDGET_TIMEZONE: once_cell::sync::Lazy<std::sync::RwLock<chrono_tz::Tz>> =
+    once_cell::sync::Lazy::new(|| std::sync::RwLock::new(chrono_tz::UTC));
+
+/// 每日预算超额信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenBudgetExceeded {
+    pub used: u64,
+    pub budget: u64,
+    /// 下一次重置时间（Unix 秒）
+    pub resets_at: i64,
+    /// 本次请求的预估输入 token 数（预检拒绝时使用）
+    pub requested: u64,
+}
+
+/// 当日 token 预算用量（供 /v1/usage 展示）
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenBudgetStatus {
+    pub budget: u64,
+    pub used: u64,
+    pub remaining: u64,
+    /// 下一次重置时间（Unix 秒）
+    pub resets_at: i64,
+    pub timezone: String,
+}
+
+/// 查询 API Key 当日预算用量（只读，不触发告警）
+pub fn key_token_budget_status(key_id: &str, budget: u64) -> Result<TokenBudgetStatus, String> {
+    let now = chrono::Utc::now();
+    let tz = budget_timezone();
+    let used = crate::modules::api_keys::get_daily_tokens(key_id, &budget_day_at(now, tz))?;
+    Ok(TokenBudgetStatus {
+        budget,
+        used,
+        remaining: budget.saturating_sub(used),
+        resets_at: next_reset_at(now, tz).timestamp(),
+        timezone: tz.name().to_string(),
+    })
+}
+
+/// 设置预算重置时区（IANA 名称，如 `Asia/Shanghai`）
+pub fn set_budget_timezone(name: &str) -> Result<(), String> {
+    let tz = parse_budget_timezone(name)?;
+    *BUDGET_TIMEZONE.write().unwrap_or_else(|e| e.into_inner()) = tz;
+    Ok(())
+}
+
+pub fn parse_budget_timezone(name: &str) -> Result<chrono_tz::Tz, String> {
+    name.parse().map_err(|_| format!("Invalid timezone: {}", name))
+}
+
+fn budget_timezone() -> chrono_tz::Tz {
+    *BUDGET_TIMEZONE.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 当前预算日（预算时区下的 `YYYY-MM-DD`）
+pub fn current_budget_day() -> String {
+    budget_day_at(chrono::Utc::now(), budget_timezone())
+}
+
+fn budget_day_at(now: chrono::DateTime<chrono::Utc>, tz: chrono_tz::Tz) -> String {
+    now.with_timezone(&tz).format("%Y-%m-%d").to_string()
+}
+
+/// 预算时区下一个午夜对应的 UTC 时间
+fn next_reset_at(now: chrono::DateTime<chrono::Utc>, tz: chrono_tz::Tz) -> chrono::DateTime<chrono::Utc> {
+    use chrono::TimeZone;
+    let tomorrow = now.with_timezone(&tz).date_naive() + chrono::Duration::days(1);
+    let midnight = tomorrow.and_hms_opt(0, 0, 0).unwrap_or_default();
+    // 夏令时切换恰好跳过午夜时，取之后最早的有效时刻
+    match tz.from_local_datetime(&midnight) {
+        chrono::LocalResult::Single(t) | chrono::LocalResult::Ambiguous(t, _) => t.with_timezone(&chrono::Utc),
+        chrono::LocalResult::None => tz
+            .from_local_datetime(&(midnight + chrono::Duration::hours(1)))
+            .earliest()
+            .map(|t| t.with_timezone(&chrono::Utc))
+            .unwrap_or(now + chrono::Duration::days(1)),
+    }
+}
+
+/// 预算已用尽，或剩余部分装不下本次请求
+fn exceeds_budget(used: u64, budget: u64, estimated_tokens: u64) -> bool {
+    used >= budget || used.saturating_add(estimated_tokens) > budget
+}
+
+/// 检查 API Key 当日 token 用量是否已达到预算
+///
+/// `estimated_tokens` 为本次请求的预估输入 token 数：剩余预算不足以容纳时提前拒绝，避免消耗上游配额
+pub fn check_key_token_budget(
+    key_id: &str,
+    budget: Option<u64>,
+    estimated_tokens: u64,
+) -> Result<(), TokenBudgetExceeded> {
+    let Some(budget) = budget else {
+        return Ok(());
+    };
+    let now = chrono::Utc::now();
+    let tz = budget_timezone();
+    let day = budget_day_at(now, tz);
+    let used = match crate::modules::api_keys::get_daily_tokens(key_id, &day) {
+        Ok(used) => used,
+        Err(e) => {
+            // 统计不可用时放行，避免数据库故障导致全部请求被拒
+            tracing::warn!("[Quota] Failed to read daily token usage for {}: {}", key_id, e);
+            return Ok(());
+        }
+    };
+    crate::modules::alerts::notify_budget_usage(key_id, used, budget, &day);
+    if exceeds_budget(used, budget, estimated_tokens) {
+        return Err(TokenBudgetExceeded {
+            used,
+            budget,
+            resets_at: next_reset_at(now, tz).timestamp(),
+            requested: estimated_tokens,
+        });
+    }
+    Ok(())
+}
+
+// ===== 账号冷却 =====
+// 记录上游 429 与配额接口报告的重置时间；冷却中的账号不参与轮换，直到重置时间到达
+
+/// 解析配额接口返回的 resetTime（RFC 3339）为 Unix 秒
+pub fn parse_reset_time(value: &str) -> Option<i64> {
+    chrono::DateTime::parse_from_rfc3339(value).ok().map(|t| t.timestamp())
+}
+
+/// 配额组内最早的未来重置时间（Unix 秒）
+pub fn group_reset_at(quota: &QuotaData, quota_group: &str, now: i64) -> Option<i64> {
+    quota
+        .models
+        .iter()
+        .filter(|m| m.name.contains(quota_group))
+        .filter_map(|m| parse_reset_time(&m.reset_time))
+        .filter(|t| *t > now)
+        .min()
+}
+
+/// 配额组内所有模型都已耗尽时，返回冷却结束时间（最早的重置时间）
+pub fn group_exhausted_until(quota: &QuotaData, quota_group: &str, now: i64) -> Option<i64> {
+    let mut models = quota.models.iter().filter(|m| m.name.contains(quota_group)).peekable();
+    models.peek()?;
+    if models.any(|m| m.percentage > 0) {
+        return None;
+    }
+    group_reset_at(quota, quota_group, now)
+}
+
+/// 当前生效的冷却（配额组 -> 结束时间）：429 写入的记录与配额耗尽推导的冷却取较晚者
+pub fn active_cooldowns(quota: &QuotaData, quota_groups: &[&str], now: i64) -> std::collections::BTreeMap<String, i64> {
+    let mut cooldowns: std::collections::BTreeMap<String, i64> = quota
+        .cooldowns
+        .iter()
+        .filter(|(_, c)| c.until > now)
+        .map(|(group, c)| (group.clone(), c.until))
+        .collect();
+    for group in quota_groups {
+        if let Some(until) = group_exhausted_until(quota, group, now) {
+            let entry = cooldowns.entry(group.to_string()).or_insert(until);
+            *entry = (*entry).max(until);
+        }
+    }
+    cooldowns
+}
+
+/// 记录一次 429 并写回账号文件，使冷却在重启后仍然生效
+pub fn record_cooldown(account_id: &str, quota_group: &str, until: i64, reason: &str) -> Result<(), String> {
+    let mut account = crate::modules::account::load_account(account_id)?;
+    let now = chrono::Utc::now().timestamp();
+    let quota = account.quota.get_or_insert_with(|| QuotaData { last_updated: 0, ..QuotaData::new() });
+    quota.cooldowns.retain(|_, c| c.until > now);
+    let cooldown = quota
+        .cooldowns
+        .entry(quota_group.to_string())
+        .or_insert_with(|| crate::models::QuotaCooldown { until, reason: reason.to_string(), hits: 0, last_hit_at: now });
+    cooldown.until = cooldown.until.max(until);
+    cooldown.reason = reason.to_string();
+    cooldown.hits += 1;
+    cooldown.last_hit_at = now;
+    crate::modules::account::save_account(&account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_budget_day_uses_timezone() {
+        let now = chrono::Utc.with_ymd_and_hms(2025, 3, 1, 20, 0, 0).unwrap();
+        assert_eq!(budget_day_at(now, chrono_tz::UTC), "2025-03-01");
+        assert_eq!(budget_day_at(now, chrono_tz::Asia::Shanghai), "2025-03-02");
+    }
+
+    #[test]
+    fn test_next_reset_at_local_midnight() {
+        let now = chrono::Utc.with_ymd_and_hms(2025, 3, 1, 20, 0, 0).unwrap();
+        // 上海 3/2 04:00，下一次重置是 3/3 00:00 (+08:00)
+        let reset = next_reset_at(now, chrono_tz::Asia::Shanghai);
+        assert_eq!(reset, chrono::Utc.with_ymd_and_hms(2025, 3, 2, 16, 0, 0).unwrap());
+
+        // 夏令时当天（纽约 3/9 凌晨 2 点跳到 3 点）午夜仍然有效
+        let now = chrono::Utc.with_ymd_and_hms(2025, 3, 9, 12, 0, 0).unwrap();
+        let reset = next_reset_at(now, chrono_tz::America::New_York);
+        assert_eq!(reset, chrono::Utc.with_ymd_and_hms(2025, 3, 10, 4, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_unlimited_budget_passes() {
+        assert!(check_key_token_budget("missing", None, 1_000_000).is_ok());
+        assert!(set_budget_timezone("Not/AZone").is_err());
+    }
+
+    #[test]
+    fn test_exceeds_budget_with_estimate() {
+        assert!(!exceeds_budget(900, 1000, 100));
+        assert!(exceeds_budget(900, 1000, 101));
+        assert!(exceeds_budget(1000, 1000, 0));
+        assert!(!exceeds_budget(0, 1000, 0));
+    }
+
+    fn quota_with(models: &[(&str, i32, &str)]) -> QuotaData {
+        let mut quota = QuotaData::new();
+        for (name, percentage, reset) in models {
+            quota.add_model(name.to_string(), *percentage, reset.to_string());
+        }
+        quota
+    }
+
+    #[test]
+    fn test_group_exhausted_until() {
+        let now = parse_reset_time("2025-03-01T12:00:00Z").unwrap();
+        let quota = quota_with(&[
+            ("claude-sonnet-4-5", 0, "2025-03-01T17:00:00Z"),
+            ("claude-opus-4-5", 0, "2025-03-01T15:30:00Z"),
+            ("gemini-3-pro-high", 40, "2025-03-01T13:00:00Z"),
+        ]);
+        assert_eq!(group_exhausted_until(&quota, "claude", now), parse_reset_time("2025-03-01T15:30:00Z"));
+        assert_eq!(group_exhausted_until(&quota, "gemini", now), None);
+        assert_eq!(group_reset_at(&quota, "gemini", now), parse_reset_time("2025-03-01T13:00:00Z"));
+        // 重置时间已过或未知时不冷却
+        let later = parse_reset_time("2025-03-01T18:00:00Z").unwrap();
+        assert_eq!(group_exhausted_until(&quota, "claude", later), None);
+        assert_eq!(group_exhausted_until(&quota_with(&[]), "claude", now), None);
+    }
+
+    #[test]
+    fn test_active_cooldowns_merges_429_records() {
+        let now = parse_reset_time("2025-03-01T12:00:00Z").unwrap();
+        let mut quota = quota_with(&[("claude-sonnet-4-5", 0, "2025-03-01T15:00:00Z")]);
+        let cooldown = |until: i64| crate::models::QuotaCooldown {
+            until,
+            reason: "rate_limit_exceeded".to_string(),
+            hits: 1,
+            last_hit_at: now,
+        };
+        quota.cooldowns.insert("claude".to_string(), cooldown(now + 60));
+        quota.cooldowns.insert("gemini".to_string(), cooldown(now + 120));
+        quota.cooldowns.insert("gemini::image_gen".to_string(), cooldown(now - 1));
+
+        let active = active_cooldowns(&quota, &["claude", "gemini"], now);
+        assert_eq!(active.get("claude"), parse_reset_time("2025-03-01T15:00:00Z").as_ref());
+        assert_eq!(active.get("gemini"), Some(&(now + 120)));
+        assert!(!active.contains_key("gemini::image_gen"));
+    }
+}
@@ -0,0 +1,211 @@
+//! S3 兼容对象存储客户端
+//!
+//! 只实现备份需要的上传 / 列出 / 删除，请求使用 AWS Signature V4 签名，
+//! 兼容 AWS S3、MinIO、Cloudflare R2 等服务
+
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+use crate::proxy::config::S3Config;
+
+const SERVICE: &str = "s3";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+});
+
+static LIST_KEY: Lazy<regex::Regex> = Lazy::new(|| regex::Regex::new(r"<Key>([^<]*)</Key>").expect("valid regex"));
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// SigV4 签名密钥
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let k_date = hmac_sha256(format!("AWS4{}", secret).as_bytes(), date.as_bytes());
+    let k_region = hmac_sha256(&k_date, region.as_bytes());
+    let k_service = hmac_sha256(&k_region, service.as_bytes());
+    hmac_sha256(&k_service, b"aws4_request")
+}
+
+/// RFC 3986 编码；`keep_slash` 用于对象路径
+fn uri_encode(value: &str, keep_slash: bool) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            b'/' if keep_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+pub fn validate(config: &S3Config) -> Result<(), String> {
+    let endpoint = url::Url::parse(&config.endpoint).map_err(|e| format!("invalid endpoint: {}", e))?;
+    if !matches!(endpoint.scheme(), "http" | "https") || endpoint.host_str().is_none() {
+        return Err("endpoint must be an http(s) URL".to_string());
+    }
+    for (field, value) in [
+        ("bucket", &config.bucket),
+        ("region", &config.region),
+        ("access_key_id", &config.access_key_id),
+        ("secret_access_key", &config.secret_access_key),
+    ] {
+        if value.trim().is_empty() {
+            return Err(format!("{} is required", field));
+        }
+    }
+    Ok(())
+}
+
+pub struct S3Client {
+    config: S3Config,
+}
+
+/// 已签名的请求目标
+struct Signed {
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+impl S3Client {
+    pub fn new(config: S3Config) -> Self {
+        Self { config }
+    }
+
+    /// 对象的完整名称（加上配置的前缀）
+    pub fn object_key(&self, name: &str) -> String {
+        format!("{}{}", self.config.prefix, name)
+    }
+
+    fn sign(&self, method: &str, key: &str, query: &[(&str, &str)], payload: &[u8], now: chrono::DateTime<chrono::Utc>) -> Result<Signed, String> {
+        let endpoint = url::Url::parse(&self.config.endpoint).map_err(|e| e.to_string())?;
+        let scheme = endpoint.scheme();
+        let base_host = match endpoint.port() {
+            Some(port) => format!("{}:{}", endpoint.host_str().unwrap_or_default(), port),
+            None => endpoint.host_str().unwrap_or_default().to_string(),
+        };
+        let (host, path) = if self.config.path_style {
+            (base_host, format!("/{}/{}", self.config.bucket, uri_encode(key, true)))
+        } else {
+            (format!("{}.{}", self.config.bucket, base_host), format!("/{}", uri_encode(key, true)))
+        };
+
+        let mut params: Vec<(String, String)> = query.iter().map(|(k, v)| (uri_encode(k, false), uri_encode(v, false))).collect();
+        params.sort();
+        let query_string = params.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("&");
+
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = sha256_hex(payload);
+        let canonical_headers = format!("host:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n", host, payload_hash, amz_date);
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, path, query_string, canonical_headers, signed_headers, payload_hash
+        );
+
+        let scope = format!("{}/{}/{}/aws4_request", date, self.config.region, SERVICE);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, sha256_hex(canonical_request.as_bytes()));
+        let key = signing_key(&self.config.secret_access_key, &date, &self.config.region, SERVICE);
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.config.access_key_id, scope, signed_headers, signature
+        );
+
+        let url = if query_string.is_empty() {
+            format!("{}://{}{}", scheme, host, path)
+        } else {
+            format!("{}://{}{}?{}", scheme, host, path, query_string)
+        };
+        Ok(Signed {
+            url,
+            headers: vec![
+                ("x-amz-content-sha256".to_string(), payload_hash),
+                ("x-amz-date".to_string(), amz_date),
+                ("authorization".to_string(), authorization),
+            ],
+        })
+    }
+
+    async fn send(&self, method: reqwest::Method, key: &str, query: &[(&str, &str)], body: Vec<u8>) -> Result<String, String> {
+        let signed = self.sign(method.as_str(), key, query, &body, chrono::Utc::now())?;
+        let mut request = CLIENT.request(method.clone(), &signed.url);
+        for (name, value) in signed.headers {
+            request = request.header(name, value);
+        }
+        let response = request.body(body).send().await.map_err(|e| format!("S3 request failed: {}", e))?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            return Err(format!("S3 {} {} returned {}: {}", method, key, status, text.chars().take(300).collect::<String>()));
+        }
+        Ok(text)
+    }
+
+    pub async fn put_object(&self, key: &str, data: Vec<u8>) -> Result<(), String> {
+        self.send(reqwest::Method::PUT, key, &[], data).await.map(|_| ())
+    }
+
+    pub async fn delete_object(&self, key: &str) -> Result<(), String> {
+        self.send(reqwest::Method::DELETE, key, &[], Vec::new()).await.map(|_| ())
+    }
+
+    /// 列出指定前缀下的对象名（最多 1000 个）
+    pub async fn list_objects(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let body = self
+            .send(reqwest::Method::GET, "", &[("list-type", "2"), ("prefix", prefix)], Vec::new())
+            .await?;
+        Ok(LIST_KEY
+            .captures_iter(&body)
+            .map(|c| c[1].replace("&amp;", "&"))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signing_key_and_request() {
+        // AWS 文档中的 SigV4 签名密钥示例
+        let key = signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam");
+        assert_eq!(hex(&key), "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+        assert_eq!(uri_encode("backups/a b+c.json", true), "backups/a%20b%2Bc.json");
+
+        let client = S3Client::new(S3Config {
+            endpoint: "http://localhost:9000".to_string(),
+            bucket: "bak".to_string(),
+            region: "us-east-1".to_string(),
+            access_key_id: "AKID".to_string(),
+            secret_access_key: "secret".to_string(),
+            prefix: "antiproxy/".to_string(),
+            path_style: true,
+        });
+        let now = chrono::DateTime::parse_from_rfc3339("2026-01-02T03:04:05Z").unwrap().with_timezone(&chrono::Utc);
+        let signed = client.sign("GET", "", &[("prefix", "antiproxy/"), ("list-type", "2")], b"", now).unwrap();
+        assert_eq!(signed.url, "http://localhost:9000/bak/?list-type=2&prefix=antiproxy%2F");
+        let authorization = &signed.headers[2].1;
+        assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKID/20260102/us-east-1/s3/aws4_request, "));
+        assert_eq!(client.object_key("x.json"), "antiproxy/x.json");
+    }
+}
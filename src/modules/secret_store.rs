@@ -0,0 +1,184 @@
+//! 敏感字段静态加密
+//!
+//! API Key 与账号的 refresh_token / access_token 落盘前使用 XChaCha20-Poly1305 加密，
+//! 加密密钥由主密钥派生。主密钥来源优先级：
+//! 环境变量 `ANTI_PROXY_MASTER_KEY` > 配置 `secret_master_key` > 数据目录下自动生成的 `master.key`
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    XChaCha20Poly1305, XNonce,
+};
+use hmac::{Hmac, Mac};
+use once_cell::sync::OnceCell;
+use rand::RngCore;
+use sha2::Sha256;
+
+/// 已加密字段的前缀；不带前缀的值视为旧版明文
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const MASTER_KEY_ENV: &str = "ANTI_PROXY_MASTER_KEY";
+const MASTER_KEY_FILE: &str = "master.key";
+const NONCE_LEN: usize = 24;
+
+static KEYS: OnceCell<SecretKeys> = OnceCell::new();
+
+/// 由主密钥派生的子密钥
+struct SecretKeys {
+    /// 字段加密密钥
+    cipher_key: [u8; 32],
+    /// 查找哈希密钥（API Key 按哈希检索，无需解密整表）
+    lookup_key: [u8; 32],
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+impl SecretKeys {
+    fn derive(master_secret: &str) -> Self {
+        let secret = master_secret.trim().as_bytes();
+        Self {
+            cipher_key: hmac_sha256(secret, b"antiproxy-secret-store:cipher:v1"),
+            lookup_key: hmac_sha256(secret, b"antiproxy-secret-store:lookup:v1"),
+        }
+    }
+
+    fn encrypt(&self, plaintext: &str) -> Result<String, String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let cipher = XChaCha20Poly1305::new_from_slice(&self.cipher_key).map_err(|e| e.to_string())?;
+        let ciphertext = cipher
+            .encrypt(XNonce::from_slice(&nonce), plaintext.as_bytes())
+            .map_err(|_| "Failed to encrypt secret".to_string())?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(sealed)))
+    }
+
+    fn decrypt(&self, stored: &str) -> Result<String, String> {
+        let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let sealed = STANDARD.decode(encoded).map_err(|e| format!("Invalid encrypted secret: {}", e))?;
+        if sealed.len() < NONCE_LEN {
+            return Err("Invalid encrypted secret: too short".to_string());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let cipher = XChaCha20Poly1305::new_from_slice(&self.cipher_key).map_err(|e| e.to_string())?;
+        let plaintext = cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Failed to decrypt secret (master key changed?)".to_string())?;
+        String::from_utf8(plaintext).map_err(|e| e.to_string())
+    }
+
+    fn lookup_hash(&self, plaintext: &str) -> String {
+        hmac_sha256(&self.lookup_key, plaintext.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// 读取或生成数据目录下的主密钥文件
+fn load_or_create_key_file() -> Result<String, String> {
+    let path = crate::modules::account::get_data_dir()?.join(MASTER_KEY_FILE);
+    if path.exists() {
+        return std::fs::read_to_string(&path).map_err(|e| format!("读取主密钥失败: {}", e));
+    }
+
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let encoded = STANDARD.encode(secret);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&path).map_err(|e| format!("创建主密钥失败: {}", e))?;
+    std::io::Write::write_all(&mut file, encoded.as_bytes()).map_err(|e| format!("写入主密钥失败: {}", e))?;
+    tracing::info!("Generated master key for secret encryption: {:?}", path);
+    Ok(encoded)
+}
+
+fn resolve_master_secret(config_master_key: Option<&str>) -> Result<String, String> {
+    if let Ok(value) = std::env::var(MASTER_KEY_ENV) {
+        if !value.trim().is_empty() {
+            return Ok(value);
+        }
+    }
+    if let Some(value) = config_master_key.filter(|v| !v.trim().is_empty()) {
+        return Ok(value.to_string());
+    }
+    load_or_create_key_file()
+}
+
+fn keys() -> Result<&'static SecretKeys, String> {
+    KEYS.get_or_try_init(|| resolve_master_secret(None).map(|s| SecretKeys::derive(&s)))
+}
+
+/// 启动时初始化主密钥（需在读写 API Key / 账号之前调用）
+pub fn init(config_master_key: Option<&str>) -> Result<(), String> {
+    KEYS.get_or_try_init(|| resolve_master_secret(config_master_key).map(|s| SecretKeys::derive(&s)))
+        .map(|_| ())
+}
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// 加密字段值（已加密的值原样返回）
+pub fn encrypt(plaintext: &str) -> Result<String, String> {
+    if is_encrypted(plaintext) {
+        return Ok(plaintext.to_string());
+    }
+    keys()?.encrypt(plaintext)
+}
+
+/// 解密字段值；旧版明文原样返回
+pub fn decrypt(stored: &str) -> Result<String, String> {
+    if !is_encrypted(stored) {
+        return Ok(stored.to_string());
+    }
+    keys()?.decrypt(stored)
+}
+
+/// 计算用于检索的密钥哈希 (HMAC-SHA256)
+pub fn lookup_hash(plaintext: &str) -> Result<String, String> {
+    Ok(keys()?.lookup_hash(plaintext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_roundtrip_and_plaintext_passthrough() {
+        let keys = SecretKeys::derive("test-master-secret");
+        let sealed = keys.encrypt("1//refresh-token").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.contains("refresh"));
+        assert_ne!(sealed, keys.encrypt("1//refresh-token").unwrap());
+        assert_eq!(keys.decrypt(&sealed).unwrap(), "1//refresh-token");
+
+        // 旧版明文原样返回
+        assert_eq!(keys.decrypt("sk-legacy").unwrap(), "sk-legacy");
+
+        // 主密钥不同无法解密
+        assert!(SecretKeys::derive("other-secret").decrypt(&sealed).is_err());
+    }
+
+    #[test]
+    fn test_lookup_hash() {
+        let keys = SecretKeys::derive("test-master-secret");
+        assert_eq!(keys.lookup_hash("sk-abc"), keys.lookup_hash("sk-abc"));
+        assert_ne!(keys.lookup_hash("sk-abc"), keys.lookup_hash("sk-abd"));
+        assert_ne!(keys.lookup_hash("sk-abc"), SecretKeys::derive("other").lookup_hash("sk-abc"));
+        assert_eq!(keys.lookup_hash("sk-abc").len(), 64);
+    }
+}
@@ -0,0 +1,122 @@
+//! 首次运行引导
+//! 全新安装（未配置认证且没有账号）时按顺序完成：设置管理员登录 → 添加第一个账号 → 生成第一个 API Key，
+//! 进度记录在 `setup.json`，完成后引导接口锁定
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+const SETUP_FILE: &str = "setup.json";
+
+/// 引导进度
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SetupProgress {
+    /// 通过引导设置管理员登录的时间
+    #[serde(default)]
+    pub started_at: Option<i64>,
+    /// 引导完成（生成第一个 API Key）的时间
+    #[serde(default)]
+    pub completed_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SetupStatus {
+    /// 全新安装，尚未开始引导
+    Pending,
+    /// 已设置管理员登录，尚未完成后续步骤
+    InProgress,
+    /// 已完成，或安装未经引导直接配置
+    Completed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SetupStep {
+    Admin,
+    Account,
+    ApiKey,
+}
+
+impl SetupStep {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::Account => "account",
+            Self::ApiKey => "api_key",
+        }
+    }
+}
+
+impl SetupProgress {
+    /// 当前状态；`auth_configured` / `has_accounts` 为当前安装的实际情况
+    pub fn status(&self, auth_configured: bool, has_accounts: bool) -> SetupStatus {
+        if self.completed_at.is_some() {
+            SetupStatus::Completed
+        } else if self.started_at.is_some() {
+            SetupStatus::InProgress
+        } else if !auth_configured && !has_accounts {
+            SetupStatus::Pending
+        } else {
+            SetupStatus::Completed
+        }
+    }
+
+    /// 下一个待完成的步骤，引导已结束时为 None
+    pub fn next_step(&self, auth_configured: bool, has_accounts: bool) -> Option<SetupStep> {
+        match self.status(auth_configured, has_accounts) {
+            SetupStatus::Completed => None,
+            SetupStatus::Pending => Some(SetupStep::Admin),
+            // 设置管理员登录前记录了开始，但登录未设置成功
+            SetupStatus::InProgress if !auth_configured => Some(SetupStep::Admin),
+            SetupStatus::InProgress if !has_accounts => Some(SetupStep::Account),
+            SetupStatus::InProgress => Some(SetupStep::ApiKey),
+        }
+    }
+}
+
+fn setup_path() -> Result<PathBuf, String> {
+    Ok(crate::modules::account::get_data_dir()?.join(SETUP_FILE))
+}
+
+pub fn load_progress() -> Result<SetupProgress, String> {
+    let path = setup_path()?;
+    if !path.exists() {
+        return Ok(SetupProgress::default());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("读取引导进度失败: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("解析引导进度失败: {}", e))
+}
+
+pub fn save_progress(progress: &SetupProgress) -> Result<(), String> {
+    let path = setup_path()?;
+    let temp_path = path.with_extension("json.tmp");
+    let content = serde_json::to_string_pretty(progress).map_err(|e| format!("序列化引导进度失败: {}", e))?;
+    fs::write(&temp_path, content).map_err(|e| format!("写入引导进度失败: {}", e))?;
+    fs::rename(temp_path, path).map_err(|e| format!("替换引导进度文件失败: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setup_status_and_steps() {
+        let fresh = SetupProgress::default();
+        assert_eq!(fresh.status(false, false), SetupStatus::Pending);
+        assert_eq!(fresh.next_step(false, false), Some(SetupStep::Admin));
+        // 未经引导配置过的安装不再开放引导
+        assert_eq!(fresh.status(true, false), SetupStatus::Completed);
+        assert_eq!(fresh.status(false, true), SetupStatus::Completed);
+        assert_eq!(fresh.next_step(false, true), None);
+
+        let started = SetupProgress { started_at: Some(1), completed_at: None };
+        assert_eq!(started.next_step(false, false), Some(SetupStep::Admin));
+        assert_eq!(started.next_step(true, false), Some(SetupStep::Account));
+        assert_eq!(started.next_step(true, true), Some(SetupStep::ApiKey));
+
+        let done = SetupProgress { started_at: Some(1), completed_at: Some(2) };
+        assert_eq!(done.status(true, true), SetupStatus::Completed);
+        assert_eq!(done.next_step(true, true), None);
+    }
+}
@@ -0,0 +1,599 @@
+//! 存储后端
+//!
+//! 请求日志、API Key 及用量、账号数据通过 [`Store`] 读写。默认每类数据一个本地 SQLite 文件；
+//! `storage.backend = "postgres"` 时多个实例共享同一个 PostgreSQL 数据库。
+//! 占位符统一写作 `$N`（两种数据库都支持），列类型使用两者通用的 BIGINT / DOUBLE PRECISION；
+//! 方言不同的部分（JSON 取值、补充列）由 [`Dialect`] 与 [`Store::add_column`] 按后端生成
+
+use once_cell::sync::{Lazy, OnceCell};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::modules::postgres::{self, PgConfig, PgConnection};
+use crate::proxy::config::{StorageBackend, StorageConfig};
+
+/// 覆盖 `storage.postgres_url` 的环境变量（避免把数据库密码写进配置文件）
+pub const POSTGRES_URL_ENV: &str = "ANTI_PROXY_POSTGRES_URL";
+
+/// 每个进程最多保留的空闲 PostgreSQL 连接
+const MAX_IDLE_PG: usize = 8;
+
+/// 启动时写入；未设置表示使用本地 SQLite
+static POSTGRES: OnceCell<PgConfig> = OnceCell::new();
+static PG_IDLE: Lazy<Mutex<Vec<PgConnection>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// 数据分类；SQLite 后端下各自对应数据目录中的一个文件，PostgreSQL 后端下共用一个数据库
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Database {
+    Logs,
+    ApiKeys,
+    /// 账号数据；SQLite 后端下仍以 JSON 文件存储，只有共享后端使用该库
+    Accounts,
+}
+
+impl Database {
+    fn sqlite_path(self) -> Result<PathBuf, String> {
+        match self {
+            Database::Logs => crate::modules::proxy_db::get_proxy_db_path(),
+            Database::ApiKeys => crate::modules::api_keys::get_db_path(),
+            Database::Accounts => Err("accounts are stored as JSON files with the sqlite backend".to_string()),
+        }
+    }
+}
+
+/// SQL 方言
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Sqlite,
+    Postgres,
+}
+
+impl Dialect {
+    /// 读取 JSON 文本列 `column` 中 `path`（`$."key"` 形式，通常为参数占位符）处的值
+    pub fn json_text(self, column: &str, path: &str) -> String {
+        match self {
+            Dialect::Sqlite => format!("json_extract({}, {})", column, path),
+            Dialect::Postgres => format!(
+                "(jsonb_path_query_first(CAST({} AS jsonb), CAST({} AS jsonpath)) #>> '{{}}')",
+                column, path
+            ),
+        }
+    }
+}
+
+/// 参数 / 结果值
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+
+/// 可绑定为 SQL 参数的类型
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl ToValue for str {
+    fn to_value(&self) -> Value {
+        Value::Text(self.to_string())
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::Text(self.clone())
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::Integer(*self as i64)
+    }
+}
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Value {
+        Value::Real(*self)
+    }
+}
+
+macro_rules! integer_to_value {
+    ($($t:ty),*) => {
+        $(impl ToValue for $t {
+            fn to_value(&self) -> Value {
+                Value::Integer(*self as i64)
+            }
+        })*
+    };
+}
+
+integer_to_value!(i32, i64, u16, u32, u64, usize);
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Value {
+        self.as_ref().map_or(Value::Null, ToValue::to_value)
+    }
+}
+
+impl<T: ToValue + ?Sized> ToValue for &T {
+    fn to_value(&self) -> Value {
+        (**self).to_value()
+    }
+}
+
+/// 按引用收集参数，用法同 `rusqlite::params!`
+macro_rules! params {
+    () => {
+        &[] as &[$crate::modules::storage::Value]
+    };
+    ($($value:expr),+ $(,)?) => {
+        &[$($crate::modules::storage::ToValue::to_value(&$value)),+] as &[$crate::modules::storage::Value]
+    };
+}
+pub(crate) use params;
+
+/// 追加一个参数，返回其占位符 `$N`（用于动态拼接的条件）
+pub fn bind(values: &mut Vec<Value>, value: impl ToValue) -> String {
+    values.push(value.to_value());
+    format!("${}", values.len())
+}
+
+/// 可从结果列读取的类型
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self, String>;
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Integer(v) => Ok(*v),
+            Value::Real(v) => Ok(*v as i64),
+            // PostgreSQL 以文本返回结果，bigint 的 SUM 为 numeric
+            Value::Text(v) => v
+                .parse::<i64>()
+                .or_else(|_| v.parse::<f64>().map(|f| f as i64))
+                .map_err(|_| format!("invalid integer {:?}", v)),
+            Value::Null => Err("unexpected NULL".to_string()),
+        }
+    }
+}
+
+macro_rules! integer_from_value {
+    ($($t:ty),*) => {
+        $(impl FromValue for $t {
+            fn from_value(value: &Value) -> Result<Self, String> {
+                let v = i64::from_value(value)?;
+                <$t>::try_from(v).map_err(|_| format!("integer {} out of range", v))
+            }
+        })*
+    };
+}
+
+integer_from_value!(i32, u16, u32, u64, usize);
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Integer(v) => Ok(*v as f64),
+            Value::Real(v) => Ok(*v),
+            Value::Text(v) => v.parse().map_err(|_| format!("invalid number {:?}", v)),
+            Value::Null => Err("unexpected NULL".to_string()),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Text(v) if matches!(v.as_str(), "t" | "true") => Ok(true),
+            Value::Text(v) if matches!(v.as_str(), "f" | "false") => Ok(false),
+            _ => i64::from_value(value).map(|v| v != 0),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Text(v) => Ok(v.clone()),
+            Value::Integer(v) => Ok(v.to_string()),
+            Value::Real(v) => Ok(v.to_string()),
+            Value::Null => Err("unexpected NULL".to_string()),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+/// 一行查询结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row(Vec<Value>);
+
+impl Row {
+    pub fn get<T: FromValue>(&self, index: usize) -> Result<T, String> {
+        let value = self.0.get(index).ok_or_else(|| format!("column {} out of range", index))?;
+        T::from_value(value).map_err(|e| format!("column {}: {}", index, e))
+    }
+}
+
+/// 数据库连接
+pub trait Store {
+    fn dialect(&self) -> Dialect;
+
+    /// 执行语句，返回受影响的行数
+    fn execute(&mut self, sql: &str, params: &[Value]) -> Result<usize, String>;
+
+    /// 逐行读取查询结果，`visit` 返回 false 时提前结束
+    fn for_each_row(&mut self, sql: &str, params: &[Value], visit: &mut dyn FnMut(Row) -> Result<bool, String>) -> Result<(), String>;
+
+    fn query(&mut self, sql: &str, params: &[Value]) -> Result<Vec<Row>, String> {
+        let mut rows = Vec::new();
+        self.for_each_row(sql, params, &mut |row| {
+            rows.push(row);
+            Ok(true)
+        })?;
+        Ok(rows)
+    }
+
+    /// 读取第一行（没有结果时为 None）
+    fn query_row(&mut self, sql: &str, params: &[Value]) -> Result<Option<Row>, String> {
+        let mut first = None;
+        self.for_each_row(sql, params, &mut |row| {
+            first = Some(row);
+            Ok(false)
+        })?;
+        Ok(first)
+    }
+
+    /// 为已有表补充列（旧库迁移），列已存在时忽略
+    fn add_column(&mut self, table: &str, column: &str) -> Result<(), String>;
+}
+
+/// 在事务中执行 `f`，出错时回滚
+pub fn transaction<T>(store: &mut dyn Store, f: impl FnOnce(&mut dyn Store) -> Result<T, String>) -> Result<T, String> {
+    store.execute("BEGIN", params![])?;
+    match f(store) {
+        Ok(value) => {
+            store.execute("COMMIT", params![])?;
+            Ok(value)
+        }
+        Err(e) => {
+            let _ = store.execute("ROLLBACK", params![]);
+            Err(e)
+        }
+    }
+}
+
+impl rusqlite::ToSql for Value {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        use rusqlite::types::ToSqlOutput;
+        Ok(match self {
+            Value::Null => ToSqlOutput::from(rusqlite::types::Null),
+            Value::Integer(v) => ToSqlOutput::from(*v),
+            Value::Real(v) => ToSqlOutput::from(*v),
+            Value::Text(v) => ToSqlOutput::from(v.as_str()),
+        })
+    }
+}
+
+impl From<rusqlite::types::ValueRef<'_>> for Value {
+    fn from(value: rusqlite::types::ValueRef<'_>) -> Self {
+        use rusqlite::types::ValueRef;
+        match value {
+            ValueRef::Null => Value::Null,
+            ValueRef::Integer(v) => Value::Integer(v),
+            ValueRef::Real(v) => Value::Real(v),
+            ValueRef::Text(v) | ValueRef::Blob(v) => Value::Text(String::from_utf8_lossy(v).into_owned()),
+        }
+    }
+}
+
+/// 按名称绑定 `$N` 参数（SQLite 按首次出现的顺序给命名参数编号，不能按位置绑定）
+fn bind_sqlite(stmt: &mut rusqlite::Statement<'_>, params: &[Value]) -> Result<(), String> {
+    for index in 1..=stmt.parameter_count() {
+        let name = stmt.parameter_name(index).unwrap_or("?").to_string();
+        let value = name
+            .strip_prefix('$')
+            .and_then(|n| n.parse::<usize>().ok())
+            .and_then(|n| params.get(n.checked_sub(1)?))
+            .ok_or_else(|| format!("no value for SQL parameter {}", name))?;
+        stmt.raw_bind_parameter(index, value).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+impl Store for rusqlite::Connection {
+    fn dialect(&self) -> Dialect {
+        Dialect::Sqlite
+    }
+
+    fn execute(&mut self, sql: &str, params: &[Value]) -> Result<usize, String> {
+        let mut stmt = self.prepare(sql).map_err(|e| e.to_string())?;
+        bind_sqlite(&mut stmt, params)?;
+        stmt.raw_execute().map_err(|e| e.to_string())
+    }
+
+    fn for_each_row(&mut self, sql: &str, params: &[Value], visit: &mut dyn FnMut(Row) -> Result<bool, String>) -> Result<(), String> {
+        let mut stmt = self.prepare(sql).map_err(|e| e.to_string())?;
+        let columns = stmt.column_count();
+        bind_sqlite(&mut stmt, params)?;
+        let mut rows = stmt.raw_query();
+        while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            let values = (0..columns)
+                .map(|i| row.get_ref(i).map(Value::from))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            if !visit(Row(values))? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_column(&mut self, table: &str, column: &str) -> Result<(), String> {
+        // SQLite 不支持 ADD COLUMN IF NOT EXISTS
+        match Store::execute(self, &format!("ALTER TABLE {} ADD COLUMN {}", table, column), params![]) {
+            Err(e) if e.contains("duplicate column name") => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+}
+
+impl Store for crate::modules::db_pool::PooledConnection {
+    fn dialect(&self) -> Dialect {
+        Dialect::Sqlite
+    }
+
+    fn execute(&mut self, sql: &str, params: &[Value]) -> Result<usize, String> {
+        Store::execute(&mut **self, sql, params)
+    }
+
+    fn for_each_row(&mut self, sql: &str, params: &[Value], visit: &mut dyn FnMut(Row) -> Result<bool, String>) -> Result<(), String> {
+        (**self).for_each_row(sql, params, visit)
+    }
+
+    fn add_column(&mut self, table: &str, column: &str) -> Result<(), String> {
+        (**self).add_column(table, column)
+    }
+}
+
+fn pg_param(value: &Value) -> (u32, Option<String>) {
+    match value {
+        Value::Null => (postgres::UNKNOWN, None),
+        Value::Integer(v) => (postgres::INT8, Some(v.to_string())),
+        Value::Real(v) if v.is_nan() => (postgres::FLOAT8, Some("NaN".to_string())),
+        Value::Real(v) if v.is_infinite() => (postgres::FLOAT8, Some(if *v > 0.0 { "Infinity" } else { "-Infinity" }.to_string())),
+        Value::Real(v) => (postgres::FLOAT8, Some(v.to_string())),
+        Value::Text(v) => (postgres::UNKNOWN, Some(v.clone())),
+    }
+}
+
+/// 从连接池借出的 PostgreSQL 连接，离开作用域时归还
+struct PgStore {
+    conn: Option<PgConnection>,
+}
+
+impl PgStore {
+    fn run(&mut self, sql: &str, params: &[Value], on_row: &mut dyn FnMut(Vec<Option<String>>) -> Result<bool, String>) -> Result<u64, String> {
+        let params: Vec<_> = params.iter().map(pg_param).collect();
+        self.conn
+            .as_mut()
+            .expect("connection is present until drop")
+            .run(sql, &params, on_row)
+    }
+}
+
+impl Drop for PgStore {
+    fn drop(&mut self) {
+        let Some(conn) = self.conn.take() else {
+            return;
+        };
+        // 未结束的事务或出错的连接不能带回连接池
+        if !conn.is_reusable() {
+            return;
+        }
+        let mut idle = PG_IDLE.lock().unwrap_or_else(|e| e.into_inner());
+        if idle.len() < MAX_IDLE_PG {
+            idle.push(conn);
+        }
+    }
+}
+
+impl Store for PgStore {
+    fn dialect(&self) -> Dialect {
+        Dialect::Postgres
+    }
+
+    fn execute(&mut self, sql: &str, params: &[Value]) -> Result<usize, String> {
+        self.run(sql, params, &mut |_| Ok(true)).map(|n| n as usize)
+    }
+
+    fn for_each_row(&mut self, sql: &str, params: &[Value], visit: &mut dyn FnMut(Row) -> Result<bool, String>) -> Result<(), String> {
+        self.run(sql, params, &mut |values| {
+            visit(Row(values.into_iter().map(|v| v.map_or(Value::Null, Value::Text)).collect()))
+        })
+        .map(|_| ())
+    }
+
+    fn add_column(&mut self, table: &str, column: &str) -> Result<(), String> {
+        self.execute(&format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS {}", table, column), params![])
+            .map(|_| ())
+    }
+}
+
+fn open_pg(config: &PgConfig) -> Result<PgStore, String> {
+    let idle = PG_IDLE.lock().unwrap_or_else(|e| e.into_inner()).pop();
+    let conn = match idle {
+        Some(conn) => conn,
+        None => PgConnection::connect(config).map_err(|e| format!("PostgreSQL: {}", e))?,
+    };
+    Ok(PgStore { conn: Some(conn) })
+}
+
+/// 实际使用的 PostgreSQL 地址：环境变量优先于配置
+fn postgres_url(config: &StorageConfig) -> Option<String> {
+    std::env::var(POSTGRES_URL_ENV)
+        .ok()
+        .filter(|url| !url.is_empty())
+        .or_else(|| config.postgres_url.clone().filter(|url| !url.is_empty()))
+}
+
+/// 校验存储配置
+pub fn validate(config: &StorageConfig) -> Result<(), String> {
+    if config.backend == StorageBackend::Postgres {
+        let url = postgres_url(config).ok_or_else(|| format!("postgres_url (or {}) is required for the postgres backend", POSTGRES_URL_ENV))?;
+        PgConfig::parse(&url).map_err(|e| format!("postgres_url: {}", e))?;
+    }
+    Ok(())
+}
+
+/// 启动时选择存储后端（修改需重启）；PostgreSQL 后端会先连接一次，数据库不可用时启动失败
+pub fn configure(config: &StorageConfig) -> Result<(), String> {
+    validate(config)?;
+    if config.backend != StorageBackend::Postgres {
+        return Ok(());
+    }
+    let pg = PgConfig::parse(&postgres_url(config).unwrap_or_default())?;
+    let mut store = open_pg(&pg)?;
+    store.execute("SELECT 1", params![])?;
+    tracing::info!("Using shared PostgreSQL storage at {}:{}/{}", pg.host, pg.port, pg.database);
+    POSTGRES.set(pg).map_err(|_| "storage backend is already configured".to_string())
+}
+
+/// 是否使用多个实例共享的后端（PostgreSQL）
+pub fn is_shared() -> bool {
+    POSTGRES.get().is_some()
+}
+
+/// 当前后端的 SQL 方言
+pub fn dialect() -> Dialect {
+    if is_shared() {
+        Dialect::Postgres
+    } else {
+        Dialect::Sqlite
+    }
+}
+
+/// 打开指定数据的连接
+pub fn open(database: Database) -> Result<Box<dyn Store>, String> {
+    match POSTGRES.get() {
+        Some(config) => Ok(Box::new(open_pg(config)?)),
+        None => Ok(Box::new(crate::modules::db_pool::open(&database.sqlite_path()?)?)),
+    }
+}
+
+/// 跨实例互斥锁（PostgreSQL advisory lock），离开作用域时释放
+pub struct SharedLock {
+    store: PgStore,
+    key: i64,
+}
+
+impl Drop for SharedLock {
+    fn drop(&mut self) {
+        // 解锁失败时丢弃连接，会话结束后服务端自动释放锁
+        if self.store.query_row("SELECT pg_advisory_unlock($1)", params![self.key]).is_err() {
+            self.store.conn = None;
+        }
+    }
+}
+
+/// 获取跨实例互斥锁，其他实例持有时等待；非共享后端下返回 None（进程内加锁即可）
+pub fn lock(key: i64) -> Result<Option<SharedLock>, String> {
+    let Some(config) = POSTGRES.get() else {
+        return Ok(None);
+    };
+    let mut store = open_pg(config)?;
+    store.query_row("SELECT pg_advisory_lock($1)", params![key])?;
+    Ok(Some(SharedLock { store, key }))
+}
+
+/// 检查数据库能否连接并执行查询（就绪检查使用）
+pub fn ping(database: Database) -> Result<(), String> {
+    open(database)?.query_row("SELECT 1", params![]).map(|_| ())
+}
+
+/// 在阻塞线程池中执行存储调用；PostgreSQL 后端下每次查询都是一次网络往返，不能占用异步工作线程。
+/// 闭包 panic 时在调用方继续 panic，与直接调用一致
+pub async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_round_trip() {
+        let row = Row(vec![
+            Value::Text("42".to_string()),
+            Value::Text("1.50".to_string()),
+            Value::Null,
+            Value::Integer(1),
+            Value::Text("t".to_string()),
+        ]);
+        assert_eq!(row.get::<u64>(0).unwrap(), 42);
+        assert_eq!(row.get::<f64>(1).unwrap(), 1.5);
+        assert_eq!(row.get::<i64>(1).unwrap(), 1);
+        assert_eq!(row.get::<Option<String>>(2).unwrap(), None);
+        assert!(row.get::<String>(2).is_err());
+        assert!(row.get::<bool>(3).unwrap() && row.get::<bool>(4).unwrap());
+        assert!(row.get::<u16>(5).is_err());
+        assert_eq!(Row(vec![Value::Integer(-1)]).get::<u32>(0).unwrap_err(), "column 0: integer -1 out of range");
+
+        let name = "k".to_string();
+        let missing: Option<u32> = None;
+        assert_eq!(
+            params![name, 7u32, missing, true, 0.5],
+            &[Value::Text("k".to_string()), Value::Integer(7), Value::Null, Value::Integer(1), Value::Real(0.5)]
+        );
+    }
+
+    #[test]
+    fn test_sqlite_store_and_transaction() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        let store: &mut dyn Store = &mut conn;
+        store.execute("CREATE TABLE t (id TEXT PRIMARY KEY, n BIGINT)", params![]).unwrap();
+        // 占位符按编号绑定，与出现顺序无关
+        assert_eq!(store.execute("INSERT INTO t VALUES ($3, NULL), ($1, $2)", params!["a", 1, "b"]).unwrap(), 2);
+        assert!(store.execute("INSERT INTO t VALUES ($1, $2)", params!["c"]).is_err());
+        store.add_column("t", "tags TEXT").unwrap();
+        store.add_column("t", "tags TEXT").unwrap();
+
+        let failed: Result<(), String> = transaction(store, |tx| {
+            tx.execute("UPDATE t SET n = 5", params![])?;
+            Err("abort".to_string())
+        });
+        assert!(failed.is_err());
+        let rows = store.query("SELECT id, n FROM t ORDER BY id", params![]).unwrap();
+        assert_eq!(rows.iter().map(|r| (r.get::<String>(0).unwrap(), r.get::<Option<i64>>(1).unwrap())).collect::<Vec<_>>(), vec![("a".to_string(), Some(1)), ("b".to_string(), None)]);
+
+        transaction(store, |tx| tx.execute("UPDATE t SET n = 5", params![]).map(|_| ())).unwrap();
+        assert_eq!(store.query_row("SELECT SUM(n) FROM t", params![]).unwrap().unwrap().get::<i64>(0).unwrap(), 10);
+        assert!(store.query_row("SELECT id FROM t WHERE id = $1", params!["zzz"]).unwrap().is_none());
+
+        store.execute("UPDATE t SET tags = $1 WHERE id = $2", params![r#"{"project":"x"}"#, "a"]).unwrap();
+        let mut values = Vec::new();
+        let path = bind(&mut values, r#"$."project""#);
+        let sql = format!("SELECT id FROM t WHERE {} = {}", store.dialect().json_text("tags", &path), bind(&mut values, "x"));
+        assert_eq!(sql, "SELECT id FROM t WHERE json_extract(tags, $1) = $2");
+        assert_eq!(store.query(&sql, &values).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,156 @@
+//! TOTP (RFC 6238) 认证模块
+//!
+//! 为无法使用 WebAuthn 的环境提供基于验证器 App 的登录方式：
+//! 生成密钥与绑定二维码，并校验 6 位动态码
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+/// 时间步长 (秒)
+pub const PERIOD_SECS: u64 = 30;
+/// 动态码位数
+pub const DIGITS: u32 = 6;
+/// 允许的前后时间步偏差 (应对时钟误差)
+const ALLOWED_SKEW: u64 = 1;
+/// 密钥长度 (字节)，与 RFC 4226 推荐的 160 bit 一致
+const SECRET_LEN: usize = 20;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// 生成新的 TOTP 密钥 (Base32 编码)
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut secret);
+    base32_encode(&secret)
+}
+
+/// Base32 编码 (RFC 4648，无填充)
+pub fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+/// Base32 解码，忽略大小写、空格与填充
+pub fn base32_decode(input: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(input.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in input.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a as char == c.to_ascii_uppercase())
+            .ok_or_else(|| format!("Invalid base32 character: {}", c))?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+/// HOTP (RFC 4226)
+fn hotp(secret: &[u8], counter: u64, digits: u32) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let code = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+    code % 10u32.pow(digits)
+}
+
+/// 计算指定时间的动态码
+pub fn code_at(secret: &[u8], unix_secs: u64) -> String {
+    format!("{:0width$}", hotp(secret, unix_secs / PERIOD_SECS, DIGITS), width = DIGITS as usize)
+}
+
+/// 校验动态码，成功时返回匹配的时间步（用于防止同一动态码被重复使用）
+///
+/// `last_used_step` 之前（含）的时间步一律拒绝
+pub fn verify(secret_b32: &str, code: &str, unix_secs: u64, last_used_step: Option<u64>) -> Option<u64> {
+    let code = code.trim().replace(' ', "");
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let secret = base32_decode(secret_b32).ok()?;
+    let current = unix_secs / PERIOD_SECS;
+
+    (current.saturating_sub(ALLOWED_SKEW)..=current + ALLOWED_SKEW)
+        .filter(|step| last_used_step.is_none_or(|last| *step > last))
+        .find(|step| code_at(&secret, step * PERIOD_SECS) == code)
+}
+
+/// 生成验证器 App 使用的 otpauth:// 绑定地址
+pub fn provisioning_uri(secret_b32: &str, account: &str, issuer: &str) -> String {
+    let label: String = url::form_urlencoded::byte_serialize(format!("{}:{}", issuer, account).as_bytes()).collect();
+    let issuer_param: String = url::form_urlencoded::byte_serialize(issuer.as_bytes()).collect();
+    format!(
+        "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        label, secret_b32, issuer_param, DIGITS, PERIOD_SECS
+    )
+}
+
+/// 将绑定地址渲染为 SVG 二维码
+pub fn qr_svg(data: &str) -> Result<String, String> {
+    let code = qrcode::QrCode::new(data.as_bytes()).map_err(|e| format!("Failed to generate QR code: {}", e))?;
+    Ok(code
+        .render::<qrcode::render::svg::Color>()
+        .min_dimensions(200, 200)
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base32_roundtrip() {
+        // RFC 4648 测试向量
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_decode("mzxw6ytboi======").unwrap(), b"foobar");
+        assert!(base32_decode("MZ1W").is_err());
+
+        let secret = generate_secret();
+        assert_eq!(secret.len(), 32);
+        assert_eq!(base32_decode(&secret).unwrap().len(), SECRET_LEN);
+    }
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        // RFC 6238 附录 B (SHA1)，取 8 位动态码的后 6 位
+        let secret = b"12345678901234567890";
+        assert_eq!(code_at(secret, 59), "287082");
+        assert_eq!(code_at(secret, 1111111109), "081804");
+        assert_eq!(code_at(secret, 1234567890), "005924");
+    }
+
+    #[test]
+    fn test_verify_skew_and_replay() {
+        let secret_b32 = base32_encode(b"12345678901234567890");
+        let now = 1234567890;
+        let step = now / PERIOD_SECS;
+
+        assert_eq!(verify(&secret_b32, "005924", now, None), Some(step));
+        assert_eq!(verify(&secret_b32, "005 924", now + PERIOD_SECS, None), Some(step));
+        assert_eq!(verify(&secret_b32, "005924", now + 3 * PERIOD_SECS, None), None);
+        // 已使用过的时间步不能再次使用
+        assert_eq!(verify(&secret_b32, "005924", now, Some(step)), None);
+        assert_eq!(verify(&secret_b32, "12345", now, None), None);
+    }
+}
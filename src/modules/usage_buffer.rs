@@ -0,0 +1,209 @@
+// API Key 用量批量写入
+// 请求结束时只把用量增量累加到内存，按间隔 / 累计条数在一个事务中落盘，退出前强制刷新；
+// 预算检查会加上尚未落盘的用量，批量写入不会放宽每日 token 预算
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+use crate::proxy::config::UsageFlushConfig;
+
+/// 全局用量缓冲（monitor 中间件写入，后台任务落盘）
+pub static USAGE_BUFFER: Lazy<UsageBuffer> = Lazy::new(UsageBuffer::new);
+
+/// 单个 API Key 尚未落盘的用量增量（启用集群时落盘后也推送给其他实例）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageDelta {
+    pub requests: u64,
+    pub success: u64,
+    pub errors: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost: f64,
+    pub last_used_at: i64,
+    /// 预算日 -> (输入, 输出) token
+    pub daily: HashMap<String, (u64, u64)>,
+}
+
+impl UsageDelta {
+    fn merge(&mut self, other: UsageDelta) {
+        self.requests += other.requests;
+        self.success += other.success;
+        self.errors += other.errors;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cost += other.cost;
+        self.last_used_at = self.last_used_at.max(other.last_used_at);
+        for (day, (input, output)) in other.daily {
+            let entry = self.daily.entry(day).or_default();
+            entry.0 += input;
+            entry.1 += output;
+        }
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    /// key_id -> 增量
+    deltas: HashMap<String, UsageDelta>,
+    /// 自上次落盘以来记录的请求数
+    records: usize,
+}
+
+pub struct UsageBuffer {
+    pending: Mutex<Pending>,
+    interval_secs: AtomicU64,
+    max_pending: AtomicUsize,
+    /// 达到 max_pending 或配置变化时唤醒落盘任务
+    wake: Notify,
+}
+
+impl UsageBuffer {
+    fn new() -> Self {
+        let defaults = UsageFlushConfig::default();
+        Self {
+            pending: Mutex::new(Pending::default()),
+            interval_secs: AtomicU64::new(defaults.interval_seconds),
+            max_pending: AtomicUsize::new(defaults.max_pending),
+            wake: Notify::new(),
+        }
+    }
+
+    /// 应用配置（启动 / 热重载时调用）
+    pub fn configure(&self, config: &UsageFlushConfig) {
+        self.interval_secs.store(config.interval_seconds, Ordering::Relaxed);
+        self.max_pending.store(config.max_pending.max(1), Ordering::Relaxed);
+        self.wake.notify_one();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Pending> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 累加一次请求的用量；token 只在成功时计入
+    pub fn record(&self, key_id: &str, success: bool, input_tokens: Option<u32>, output_tokens: Option<u32>, cost: Option<f64>) {
+        let mut delta = UsageDelta {
+            requests: 1,
+            last_used_at: chrono::Utc::now().timestamp(),
+            ..Default::default()
+        };
+        if success {
+            let (input, output) = (input_tokens.unwrap_or(0) as u64, output_tokens.unwrap_or(0) as u64);
+            delta.success = 1;
+            delta.input_tokens = input;
+            delta.output_tokens = output;
+            delta.cost = cost.unwrap_or(0.0);
+            if input > 0 || output > 0 {
+                delta.daily.insert(crate::modules::quota::current_budget_day(), (input, output));
+            }
+        } else {
+            delta.errors = 1;
+        }
+
+        let records = {
+            let mut pending = self.lock();
+            pending.deltas.entry(key_id.to_string()).or_default().merge(delta);
+            pending.records += 1;
+            pending.records
+        };
+        if self.interval_secs.load(Ordering::Relaxed) == 0 || records >= self.max_pending.load(Ordering::Relaxed) {
+            self.wake.notify_one();
+        }
+    }
+
+    /// 尚未落盘的某一预算日 token 用量（输入 + 输出）
+    pub fn pending_daily_tokens(&self, key_id: &str, day: &str) -> u64 {
+        self.lock()
+            .deltas
+            .get(key_id)
+            .and_then(|d| d.daily.get(day))
+            .map_or(0, |(input, output)| input + output)
+    }
+
+    /// 丢弃某个 Key 尚未落盘的用量（重置统计时调用）
+    pub fn discard(&self, key_id: &str) {
+        self.lock().deltas.remove(key_id);
+    }
+
+    /// 取出全部增量并写入数据库；写入失败时放回缓冲，下次重试
+    pub fn flush(&self) -> Result<usize, String> {
+        let deltas = {
+            let mut pending = self.lock();
+            pending.records = 0;
+            std::mem::take(&mut pending.deltas)
+        };
+        if deltas.is_empty() {
+            return Ok(0);
+        }
+        let count = deltas.len();
+        match crate::modules::api_keys::apply_usage_deltas(&deltas) {
+            Ok(()) => {
+                crate::proxy::cluster::CLUSTER.broadcast_usage(&deltas);
+                Ok(count)
+            }
+            Err(e) => {
+                let mut pending = self.lock();
+                for (key_id, delta) in deltas {
+                    pending.deltas.entry(key_id).or_default().merge(delta);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// 在阻塞线程池中落盘
+    pub async fn flush_async(&'static self) {
+        match tokio::task::spawn_blocking(move || self.flush()).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => tracing::error!("Failed to flush API key usage: {}", e),
+            Err(e) => tracing::error!("API key usage flush task failed: {}", e),
+        }
+    }
+
+    /// 启动后台落盘任务：每隔 interval_seconds，或累计请求数达到 max_pending 时落盘
+    pub fn spawn_flush_task(&'static self) {
+        tokio::spawn(async move {
+            loop {
+                let interval = self.interval_secs.load(Ordering::Relaxed);
+                if interval == 0 {
+                    self.wake.notified().await;
+                } else {
+                    let _ = tokio::time::timeout(Duration::from_secs(interval), self.wake.notified()).await;
+                }
+                self.flush_async().await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_merges_deltas() {
+        let buffer = UsageBuffer::new();
+        buffer.record("k1", true, Some(10), Some(5), Some(0.5));
+        buffer.record("k1", true, Some(1), None, None);
+        buffer.record("k1", false, Some(100), Some(100), None);
+        buffer.record("k2", false, None, None, None);
+
+        let pending = buffer.lock();
+        let k1 = &pending.deltas["k1"];
+        assert_eq!((k1.requests, k1.success, k1.errors), (3, 2, 1));
+        assert_eq!((k1.input_tokens, k1.output_tokens), (11, 5));
+        assert_eq!(k1.cost, 0.5);
+        assert_eq!(pending.deltas["k2"].errors, 1);
+        assert_eq!(pending.records, 4);
+        drop(pending);
+
+        let day = crate::modules::quota::current_budget_day();
+        assert_eq!(buffer.pending_daily_tokens("k1", &day), 16);
+        assert_eq!(buffer.pending_daily_tokens("k2", &day), 0);
+        buffer.discard("k1");
+        assert_eq!(buffer.pending_daily_tokens("k1", &day), 0);
+    }
+}
@@ -9,6 +9,8 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use webauthn_rs::prelude::*;
 
+use crate::proxy::config::SessionConfig;
+
 /// 认证模式
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -27,6 +29,111 @@ impl Default for AuthMode {
     }
 }
 
+/// 管理员角色（按权限从低到高排序）
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminRole {
+    /// 只读统计
+    Viewer,
+    /// 管理 API Key、查看日志
+    Operator,
+    /// 全部权限（含管理员与认证设置）；旧版本注册的 Passkey 没有角色字段，视为 owner
+    #[default]
+    Owner,
+}
+
+impl AdminRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Operator => "operator",
+            Self::Owner => "owner",
+        }
+    }
+}
+
+/// 密码模式下的附加管理员（主密码始终为 owner）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminUser {
+    pub name: String,
+    pub password_hash: String,
+    pub role: AdminRole,
+    pub created_at: i64,
+}
+
+/// 附加管理员信息 (公开)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminUserInfo {
+    pub name: String,
+    pub role: AdminRole,
+    pub created_at: i64,
+}
+
+impl From<&AdminUser> for AdminUserInfo {
+    fn from(u: &AdminUser) -> Self {
+        Self {
+            name: u.name.clone(),
+            role: u.role,
+            created_at: u.created_at,
+        }
+    }
+}
+
+/// Admin API token 前缀（与代理 API Key 的 `sk-` 区分）
+pub const ADMIN_TOKEN_PREFIX: &str = "apt_";
+
+/// 长期有效的 Admin API token（供脚本 / Terraform 调用 /api/*），只保存哈希
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminToken {
+    pub id: String,
+    pub name: String,
+    /// token 的 SHA-256（hex）；token 为随机生成，无需加盐慢哈希
+    pub token_hash: String,
+    pub role: AdminRole,
+    pub created_at: i64,
+}
+
+/// Admin API token 信息 (公开)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminTokenInfo {
+    pub id: String,
+    pub name: String,
+    pub role: AdminRole,
+    pub created_at: i64,
+}
+
+impl From<&AdminToken> for AdminTokenInfo {
+    fn from(t: &AdminToken) -> Self {
+        Self {
+            id: t.id.clone(),
+            name: t.name.clone(),
+            role: t.role,
+            created_at: t.created_at,
+        }
+    }
+}
+
+fn hash_admin_token(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 登录身份（session 只记录身份，角色在每次请求时实时解析）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionIdentity {
+    /// 主密码 / TOTP 备用登录
+    Owner,
+    /// Passkey 登录（凭据 ID）
+    Passkey(String),
+    /// 附加管理员（用户名）
+    User(String),
+}
+
+/// 最低密码长度
+const MIN_PASSWORD_LEN: usize = 6;
+/// 管理员用户名最大长度
+const MAX_USER_NAME_LEN: usize = 64;
+
 /// 认证配置存储
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct AuthConfig {
@@ -35,6 +142,18 @@ pub struct AuthConfig {
     /// 密码哈希 (仅密码模式)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub password_hash: Option<String>,
+    /// TOTP 密钥 (Base32，确认绑定后写入)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp_secret: Option<String>,
+    /// 最近一次成功使用的 TOTP 时间步 (防止动态码重放)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp_last_step: Option<u64>,
+    /// 附加管理员 (仅密码模式)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<AdminUser>,
+    /// Admin API token（任意认证模式）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<AdminToken>,
 }
 
 impl AuthConfig {
@@ -44,6 +163,7 @@ impl AuthConfig {
         Ok(Self {
             mode: AuthMode::Password,
             password_hash: Some(hash),
+            ..Default::default()
         })
     }
 
@@ -153,6 +273,9 @@ pub struct StoredCredential {
     pub created_at: i64,
     /// 最后使用时间
     pub last_used_at: Option<i64>,
+    /// 管理员角色
+    #[serde(default)]
+    pub role: AdminRole,
 }
 
 #[derive(Debug, Clone)]
@@ -161,6 +284,25 @@ struct AuthStateEntry {
     created_at: i64,
 }
 
+/// TOTP 连续失败次数上限，超过后暂时锁定
+const TOTP_MAX_FAILURES: u32 = 5;
+/// TOTP 锁定时长 (秒)
+const TOTP_LOCKOUT_SECS: i64 = 60;
+
+#[derive(Debug, Default)]
+struct TotpThrottle {
+    failures: u32,
+    locked_until: i64,
+}
+
+/// TOTP 绑定信息 (仅在绑定时返回一次)
+#[derive(Debug, Clone, Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+    pub qr_svg: String,
+}
+
 /// WebAuthn 管理器
 pub struct WebAuthnManager {
     /// 凭据存储路径
@@ -175,6 +317,10 @@ pub struct WebAuthnManager {
     reg_states: Arc<RwLock<std::collections::HashMap<String, AuthStateEntry>>>,
     /// 认证状态缓存 (challenge -> state, with TTL)
     auth_states: Arc<RwLock<std::collections::HashMap<String, AuthStateEntry>>>,
+    /// 待确认的 TOTP 密钥
+    pending_totp: Arc<RwLock<Option<AuthStateEntry>>>,
+    /// TOTP 失败计数
+    totp_throttle: Arc<RwLock<TotpThrottle>>,
 }
 
 impl WebAuthnManager {
@@ -190,6 +336,8 @@ impl WebAuthnManager {
             auth_config: Arc::new(RwLock::new(AuthConfig::default())),
             reg_states: Arc::new(RwLock::new(std::collections::HashMap::new())),
             auth_states: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            pending_totp: Arc::new(RwLock::new(None)),
+            totp_throttle: Arc::new(RwLock::new(TotpThrottle::default())),
         }
     }
 
@@ -247,7 +395,7 @@ impl WebAuthnManager {
         }
 
         // 验证密码强度
-        if password.len() < 6 {
+        if password.len() < MIN_PASSWORD_LEN {
             return Err("Password must be at least 6 characters".to_string());
         }
 
@@ -278,15 +426,16 @@ impl WebAuthnManager {
         }
 
         // 验证新密码强度
-        if new_password.len() < 6 {
+        if new_password.len() < MIN_PASSWORD_LEN {
             return Err("New password must be at least 6 characters".to_string());
         }
 
         let new_config = AuthConfig::with_password(new_password)?;
 
         {
+            // 只替换密码哈希，保留 TOTP 绑定
             let mut auth_config = self.auth_config.write().await;
-            *auth_config = new_config;
+            auth_config.password_hash = new_config.password_hash;
         }
 
         self.save_auth_config().await?;
@@ -295,6 +444,279 @@ impl WebAuthnManager {
         Ok(())
     }
 
+    /// 列出附加管理员
+    pub async fn list_admin_users(&self) -> Vec<AdminUserInfo> {
+        let config = self.auth_config.read().await;
+        config.users.iter().map(AdminUserInfo::from).collect()
+    }
+
+    /// 添加附加管理员 (仅密码模式)
+    pub async fn add_admin_user(&self, name: &str, password: &str, role: AdminRole) -> Result<AdminUserInfo, String> {
+        let name = validate_user_name(name)?;
+        if password.len() < MIN_PASSWORD_LEN {
+            return Err("Password must be at least 6 characters".to_string());
+        }
+        let password_hash = hash_password(password)?;
+
+        let info = {
+            let mut config = self.auth_config.write().await;
+            if config.mode != AuthMode::Password {
+                return Err("Additional admin users require password authentication".to_string());
+            }
+            if config.users.iter().any(|u| u.name == name) {
+                return Err(format!("User '{}' already exists", name));
+            }
+            let user = AdminUser {
+                name,
+                password_hash,
+                role,
+                created_at: chrono::Utc::now().timestamp(),
+            };
+            let info = AdminUserInfo::from(&user);
+            config.users.push(user);
+            info
+        };
+
+        self.save_auth_config().await?;
+        tracing::info!("Admin user added: {} ({})", info.name, info.role.as_str());
+        Ok(info)
+    }
+
+    /// 修改附加管理员的角色 / 密码
+    pub async fn update_admin_user(
+        &self,
+        name: &str,
+        role: Option<AdminRole>,
+        password: Option<&str>,
+    ) -> Result<AdminUserInfo, String> {
+        let password_hash = match password {
+            Some(p) if p.len() < MIN_PASSWORD_LEN => {
+                return Err("Password must be at least 6 characters".to_string());
+            }
+            Some(p) => Some(hash_password(p)?),
+            None => None,
+        };
+
+        let info = {
+            let mut config = self.auth_config.write().await;
+            let user = config
+                .users
+                .iter_mut()
+                .find(|u| u.name == name)
+                .ok_or("User not found")?;
+            if let Some(role) = role {
+                user.role = role;
+            }
+            if let Some(hash) = password_hash {
+                user.password_hash = hash;
+            }
+            AdminUserInfo::from(&*user)
+        };
+
+        self.save_auth_config().await?;
+        Ok(info)
+    }
+
+    /// 删除附加管理员
+    pub async fn remove_admin_user(&self, name: &str) -> Result<AdminUserInfo, String> {
+        let info = {
+            let mut config = self.auth_config.write().await;
+            let index = config
+                .users
+                .iter()
+                .position(|u| u.name == name)
+                .ok_or("User not found")?;
+            AdminUserInfo::from(&config.users.remove(index))
+        };
+
+        self.save_auth_config().await?;
+        tracing::info!("Admin user removed: {}", name);
+        Ok(info)
+    }
+
+    /// 验证附加管理员的密码，成功时返回其角色
+    pub async fn verify_user_password(&self, name: &str, password: &str) -> Option<AdminRole> {
+        let config = self.auth_config.read().await;
+        if config.mode != AuthMode::Password {
+            return None;
+        }
+        let user = config.users.iter().find(|u| u.name == name)?;
+        verify_password(password, &user.password_hash).then_some(user.role)
+    }
+
+    /// 列出 Admin API token
+    pub async fn list_admin_tokens(&self) -> Vec<AdminTokenInfo> {
+        let config = self.auth_config.read().await;
+        config.tokens.iter().map(AdminTokenInfo::from).collect()
+    }
+
+    /// 创建 Admin API token，返回 token 信息与明文（明文只在此时返回一次）
+    pub async fn create_admin_token(&self, name: &str, role: AdminRole) -> Result<(AdminTokenInfo, String), String> {
+        let name = validate_user_name(name)?;
+        let token = format!("{}{}", ADMIN_TOKEN_PREFIX, uuid::Uuid::new_v4().simple());
+
+        let info = {
+            let mut config = self.auth_config.write().await;
+            if config.tokens.iter().any(|t| t.name == name) {
+                return Err(format!("Token '{}' already exists", name));
+            }
+            let admin_token = AdminToken {
+                id: uuid::Uuid::new_v4().to_string(),
+                name,
+                token_hash: hash_admin_token(&token),
+                role,
+                created_at: chrono::Utc::now().timestamp(),
+            };
+            let info = AdminTokenInfo::from(&admin_token);
+            config.tokens.push(admin_token);
+            info
+        };
+
+        self.save_auth_config().await?;
+        tracing::info!("Admin API token created: {} ({})", info.name, info.role.as_str());
+        Ok((info, token))
+    }
+
+    /// 吊销 Admin API token
+    pub async fn remove_admin_token(&self, id: &str) -> Result<AdminTokenInfo, String> {
+        let info = {
+            let mut config = self.auth_config.write().await;
+            let index = config
+                .tokens
+                .iter()
+                .position(|t| t.id == id)
+                .ok_or("Token not found")?;
+            AdminTokenInfo::from(&config.tokens.remove(index))
+        };
+
+        self.save_auth_config().await?;
+        tracing::info!("Admin API token revoked: {}", info.name);
+        Ok(info)
+    }
+
+    /// 校验 Admin API token，成功时返回其信息
+    pub async fn verify_admin_token(&self, token: &str) -> Option<AdminTokenInfo> {
+        let hash = hash_admin_token(token);
+        let config = self.auth_config.read().await;
+        config.tokens.iter().find(|t| t.token_hash == hash).map(AdminTokenInfo::from)
+    }
+
+    /// 解析登录身份当前的角色；身份已被删除时返回 None
+    pub async fn identity_role(&self, identity: &SessionIdentity) -> Option<AdminRole> {
+        match identity {
+            SessionIdentity::Owner => Some(AdminRole::Owner),
+            SessionIdentity::Passkey(id) => {
+                let credentials = self.credentials.read().await;
+                credentials.iter().find(|c| &c.credential_id == id).map(|c| c.role)
+            }
+            SessionIdentity::User(name) => {
+                let config = self.auth_config.read().await;
+                config.users.iter().find(|u| &u.name == name).map(|u| u.role)
+            }
+        }
+    }
+
+    /// 是否已绑定 TOTP
+    pub async fn totp_enabled(&self) -> bool {
+        self.auth_config.read().await.totp_secret.is_some()
+    }
+
+    /// 开始绑定 TOTP：生成新密钥，确认前不生效
+    pub async fn start_totp_enrollment(&self, account: &str) -> Result<TotpEnrollment, String> {
+        if self.get_auth_mode().await == AuthMode::None {
+            return Err("Set up a password or passkey before enabling TOTP".to_string());
+        }
+
+        let secret = crate::modules::totp::generate_secret();
+        let otpauth_uri = crate::modules::totp::provisioning_uri(&secret, account, "AntiProxy");
+        let qr_svg = crate::modules::totp::qr_svg(&otpauth_uri)?;
+
+        *self.pending_totp.write().await = Some(AuthStateEntry {
+            state_json: secret.clone(),
+            created_at: chrono::Utc::now().timestamp(),
+        });
+
+        Ok(TotpEnrollment {
+            secret,
+            otpauth_uri,
+            qr_svg,
+        })
+    }
+
+    /// 使用验证器 App 中的动态码确认绑定
+    pub async fn confirm_totp_enrollment(&self, code: &str) -> Result<(), String> {
+        let now = chrono::Utc::now().timestamp();
+        let pending = self
+            .pending_totp
+            .read()
+            .await
+            .clone()
+            .filter(|p| now - p.created_at <= 300)
+            .ok_or("TOTP enrollment not started or expired")?;
+
+        let step = crate::modules::totp::verify(&pending.state_json, code, now as u64, None)
+            .ok_or("Invalid TOTP code")?;
+
+        {
+            let mut auth_config = self.auth_config.write().await;
+            auth_config.totp_secret = Some(pending.state_json);
+            auth_config.totp_last_step = Some(step);
+        }
+        *self.pending_totp.write().await = None;
+
+        self.save_auth_config().await?;
+        tracing::info!("TOTP authentication enabled");
+        Ok(())
+    }
+
+    /// 校验 TOTP 动态码（连续失败会被暂时锁定）
+    pub async fn verify_totp(&self, code: &str) -> Result<(), String> {
+        let now = chrono::Utc::now().timestamp();
+        {
+            let throttle = self.totp_throttle.read().await;
+            if throttle.locked_until > now {
+                return Err("Too many failed attempts. Try again later.".to_string());
+            }
+        }
+
+        // 校验与记录已使用的时间步在同一把写锁下完成，并发登录不能重复使用同一个动态码
+        let step = {
+            let mut auth_config = self.auth_config.write().await;
+            let secret = auth_config.totp_secret.as_deref().ok_or("TOTP is not enabled")?;
+            let step = crate::modules::totp::verify(secret, code, now as u64, auth_config.totp_last_step);
+            if step.is_some() {
+                auth_config.totp_last_step = step;
+            }
+            step
+        };
+
+        if step.is_none() {
+            let mut throttle = self.totp_throttle.write().await;
+            throttle.failures += 1;
+            if throttle.failures >= TOTP_MAX_FAILURES {
+                throttle.failures = 0;
+                throttle.locked_until = now + TOTP_LOCKOUT_SECS;
+                tracing::warn!("TOTP locked for {}s after repeated failures", TOTP_LOCKOUT_SECS);
+            }
+            return Err("Invalid TOTP code".to_string());
+        }
+
+        *self.totp_throttle.write().await = TotpThrottle::default();
+        self.save_auth_config().await
+    }
+
+    /// 解除 TOTP 绑定
+    pub async fn disable_totp(&self) -> Result<(), String> {
+        {
+            let mut auth_config = self.auth_config.write().await;
+            auth_config.totp_secret = None;
+            auth_config.totp_last_step = None;
+        }
+        self.save_auth_config().await?;
+        tracing::info!("TOTP authentication disabled");
+        Ok(())
+    }
+
     /// 重置认证 (危险操作，需要当前认证)
     pub async fn reset_auth(&self) -> Result<(), String> {
         // 清除 passkeys
@@ -422,14 +844,15 @@ impl WebAuthnManager {
         Ok((ccr, challenge_b64))
     }
 
-    /// 完成注册流程
+    /// 完成注册流程，返回新凭据的 ID
     pub async fn finish_registration(
         &self,
         config: &WebAuthnConfig,
         challenge: &str,
         response: RegisterPublicKeyCredential,
         user_name: &str,
-    ) -> Result<(), String> {
+        role: AdminRole,
+    ) -> Result<String, String> {
         let webauthn = WebauthnBuilder::new(&config.rp_id, &config.rp_origin)
             .map_err(|e| format!("Failed to create WebAuthn builder: {}", e))?
             .rp_name(&config.rp_name)
@@ -465,13 +888,18 @@ impl WebAuthnManager {
             passkey.cred_id().as_ref(),
         );
 
+        // 第一个 Passkey 始终为 owner
+        let current_mode = self.get_auth_mode().await;
+        let role = if current_mode == AuthMode::None { AdminRole::Owner } else { role };
+
         let stored = StoredCredential {
-            credential_id,
+            credential_id: credential_id.clone(),
             user_id: uuid::Uuid::new_v4().to_string(),
             user_name: user_name.to_string(),
             passkey_json,
             created_at: chrono::Utc::now().timestamp(),
             last_used_at: None,
+            role,
         };
 
         {
@@ -482,7 +910,6 @@ impl WebAuthnManager {
         self.save_credentials().await?;
 
         // 设置认证模式为 Passkey (如果是首次注册)
-        let current_mode = self.get_auth_mode().await;
         if current_mode == AuthMode::None {
             let mut auth_config = self.auth_config.write().await;
             auth_config.mode = AuthMode::Passkey;
@@ -491,7 +918,7 @@ impl WebAuthnManager {
             tracing::info!("Authentication mode set to Passkey");
         }
 
-        Ok(())
+        Ok(credential_id)
     }
 
     /// 开始认证流程
@@ -544,7 +971,7 @@ impl WebAuthnManager {
         Ok((rcr, challenge_b64))
     }
 
-    /// 完成认证流程
+    /// 完成认证流程，返回本次使用的凭据 ID
     pub async fn finish_authentication(
         &self,
         config: &WebAuthnConfig,
@@ -600,39 +1027,71 @@ impl WebAuthnManager {
 
         self.save_credentials().await?;
 
-        // 生成 session token
-        let session_token = uuid::Uuid::new_v4().to_string();
-
-        Ok(session_token)
+        Ok(cred_id_b64)
     }
 
     /// 删除凭据
     pub async fn delete_credential(&self, credential_id: &str) -> Result<(), String> {
         {
             let mut credentials = self.credentials.write().await;
-            let original_len = credentials.len();
-            credentials.retain(|c| c.credential_id != credential_id);
-
-            if credentials.len() == original_len {
-                return Err("Credential not found".to_string());
+            let cred = credentials
+                .iter()
+                .find(|c| c.credential_id == credential_id)
+                .ok_or("Credential not found")?;
+            if cred.role == AdminRole::Owner && owner_count(&credentials) <= 1 {
+                return Err("Cannot delete the last owner passkey".to_string());
             }
+            credentials.retain(|c| c.credential_id != credential_id);
         }
 
         self.save_credentials().await
     }
 
+    /// 重命名凭据（设备名称）
+    pub async fn rename_credential(&self, credential_id: &str, name: &str) -> Result<CredentialInfo, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Name is required".to_string());
+        }
+
+        let info = {
+            let mut credentials = self.credentials.write().await;
+            let cred = credentials
+                .iter_mut()
+                .find(|c| c.credential_id == credential_id)
+                .ok_or("Credential not found")?;
+            cred.user_name = name.to_string();
+            CredentialInfo::from(&*cred)
+        };
+
+        self.save_credentials().await?;
+        Ok(info)
+    }
+
+    /// 修改 Passkey 的角色（至少保留一个 owner）
+    pub async fn set_credential_role(&self, credential_id: &str, role: AdminRole) -> Result<CredentialInfo, String> {
+        let info = {
+            let mut credentials = self.credentials.write().await;
+            let owners = owner_count(&credentials);
+            let cred = credentials
+                .iter_mut()
+                .find(|c| c.credential_id == credential_id)
+                .ok_or("Credential not found")?;
+            if cred.role == AdminRole::Owner && role != AdminRole::Owner && owners <= 1 {
+                return Err("Cannot demote the last owner passkey".to_string());
+            }
+            cred.role = role;
+            CredentialInfo::from(&*cred)
+        };
+
+        self.save_credentials().await?;
+        Ok(info)
+    }
+
     /// 列出所有凭据 (不包含敏感数据)
     pub async fn list_credentials(&self) -> Vec<CredentialInfo> {
         let credentials = self.credentials.read().await;
-        credentials
-            .iter()
-            .map(|c| CredentialInfo {
-                credential_id: c.credential_id.clone(),
-                user_name: c.user_name.clone(),
-                created_at: c.created_at,
-                last_used_at: c.last_used_at,
-            })
-            .collect()
+        credentials.iter().map(CredentialInfo::from).collect()
     }
 
     /// 清理过期的注册/认证状态 (5分钟过期)
@@ -646,6 +1105,22 @@ impl WebAuthnManager {
     }
 }
 
+fn owner_count(credentials: &[StoredCredential]) -> usize {
+    credentials.iter().filter(|c| c.role == AdminRole::Owner).count()
+}
+
+/// 校验并规范化管理员用户名
+fn validate_user_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Name is required".to_string());
+    }
+    if name.chars().count() > MAX_USER_NAME_LEN {
+        return Err(format!("Name must be at most {} characters", MAX_USER_NAME_LEN));
+    }
+    Ok(name.to_string())
+}
+
 /// 凭据信息 (公开)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CredentialInfo {
@@ -653,31 +1128,119 @@ pub struct CredentialInfo {
     pub user_name: String,
     pub created_at: i64,
     pub last_used_at: Option<i64>,
+    pub role: AdminRole,
+}
+
+impl From<&StoredCredential> for CredentialInfo {
+    fn from(c: &StoredCredential) -> Self {
+        Self {
+            credential_id: c.credential_id.clone(),
+            user_name: c.user_name.clone(),
+            created_at: c.created_at,
+            last_used_at: c.last_used_at,
+            role: c.role,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SessionEntry {
+    /// 对外展示的 session ID（与 cookie 中的 token 不同，可安全返回给前端）
+    id: String,
+    /// 登录身份，吊销凭据 / 删除管理员时一并使其 session 失效
+    identity: SessionIdentity,
+    created_at: i64,
+    last_seen_at: i64,
+    client: SessionClient,
+}
+
+/// 登录时的客户端信息
+#[derive(Debug, Clone, Default)]
+pub struct SessionClient {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// 活跃 session 信息 (GET /api/auth/sessions)
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    #[serde(skip)]
+    pub identity: SessionIdentity,
+    pub created_at: i64,
+    pub last_seen_at: i64,
+    pub expires_at: i64,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// 是否为发起请求的 session
+    pub current: bool,
 }
 
 /// Session 管理器
 pub struct SessionManager {
-    /// 活跃的 sessions (token -> expiry timestamp)
-    sessions: Arc<RwLock<std::collections::HashMap<String, i64>>>,
-    /// Session 有效期 (秒)
-    session_ttl: i64,
+    /// 活跃的 sessions (token -> entry)
+    sessions: Arc<RwLock<std::collections::HashMap<String, SessionEntry>>>,
+    /// 有效期与 cookie 属性（启动 / 热重载时写入）
+    config: std::sync::RwLock<SessionConfig>,
 }
 
 impl SessionManager {
-    pub fn new(session_ttl_hours: i64) -> Self {
+    pub fn new(session_ttl_hours: u64) -> Self {
         Self {
             sessions: Arc::new(RwLock::new(std::collections::HashMap::new())),
-            session_ttl: session_ttl_hours * 3600,
+            config: std::sync::RwLock::new(SessionConfig {
+                ttl_hours: session_ttl_hours,
+                ..Default::default()
+            }),
         }
     }
 
-    /// 创建新 session
-    pub async fn create_session(&self) -> String {
+    /// 更新有效期与 cookie 配置，对已有 session 同样生效
+    pub fn configure(&self, config: &SessionConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config.clone();
+    }
+
+    pub fn config(&self) -> SessionConfig {
+        self.config.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// session 失效时间：登录时间加最长有效期，设置了无操作超时时取两者中较早者
+    fn expires_at(config: &SessionConfig, entry: &SessionEntry) -> i64 {
+        let expiry = entry.created_at + (config.ttl_hours * 3600) as i64;
+        match config.idle_timeout_minutes {
+            0 => expiry,
+            idle => expiry.min(entry.last_seen_at + (idle * 60) as i64),
+        }
+    }
+
+    /// 创建新 session（主密码 / TOTP 登录）
+    pub async fn create_session(&self, client: SessionClient) -> String {
+        self.insert_session(SessionIdentity::Owner, client).await
+    }
+
+    /// 创建通过 Passkey 登录的 session
+    pub async fn create_passkey_session(&self, credential_id: &str, client: SessionClient) -> String {
+        self.insert_session(SessionIdentity::Passkey(credential_id.to_string()), client).await
+    }
+
+    /// 创建附加管理员登录的 session
+    pub async fn create_user_session(&self, name: &str, client: SessionClient) -> String {
+        self.insert_session(SessionIdentity::User(name.to_string()), client).await
+    }
+
+    async fn insert_session(&self, identity: SessionIdentity, client: SessionClient) -> String {
         let token = uuid::Uuid::new_v4().to_string();
-        let expiry = chrono::Utc::now().timestamp() + self.session_ttl;
+        let now = chrono::Utc::now().timestamp();
+        let entry = SessionEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            identity,
+            created_at: now,
+            last_seen_at: now,
+            client,
+        };
 
         let mut sessions = self.sessions.write().await;
-        sessions.insert(token.clone(), expiry);
+        sessions.insert(token.clone(), entry);
 
         token
     }
@@ -686,27 +1249,100 @@ impl SessionManager {
     pub async fn validate_session(&self, token: &str) -> bool {
         let sessions = self.sessions.read().await;
 
-        if let Some(&expiry) = sessions.get(token) {
+        if let Some(entry) = sessions.get(token) {
             let now = chrono::Utc::now().timestamp();
-            expiry > now
+            Self::expires_at(&self.config(), entry) > now
         } else {
             false
         }
     }
 
-    /// 刷新 session
+    /// 获取 session 登录所用的凭据 ID
+    pub async fn session_credential(&self, token: &str) -> Option<String> {
+        match self.session_identity(token).await? {
+            SessionIdentity::Passkey(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// 获取 session 的登录身份
+    pub async fn session_identity(&self, token: &str) -> Option<SessionIdentity> {
+        let sessions = self.sessions.read().await;
+        sessions.get(token).map(|e| e.identity.clone())
+    }
+
+    /// 记录 session 活动时间（重置无操作超时）
     pub async fn refresh_session(&self, token: &str) -> bool {
         let mut sessions = self.sessions.write().await;
 
-        if sessions.contains_key(token) {
-            let expiry = chrono::Utc::now().timestamp() + self.session_ttl;
-            sessions.insert(token.to_string(), expiry);
+        if let Some(entry) = sessions.get_mut(token) {
+            entry.last_seen_at = chrono::Utc::now().timestamp();
             true
         } else {
             false
         }
     }
 
+    /// 删除某个凭据登录产生的所有 session，返回删除数量
+    pub async fn revoke_credential_sessions(&self, credential_id: &str) -> usize {
+        self.revoke_identity_sessions(&SessionIdentity::Passkey(credential_id.to_string()))
+            .await
+    }
+
+    /// 删除某个附加管理员的所有 session，返回删除数量
+    pub async fn revoke_user_sessions(&self, name: &str) -> usize {
+        self.revoke_identity_sessions(&SessionIdentity::User(name.to_string())).await
+    }
+
+    async fn revoke_identity_sessions(&self, identity: &SessionIdentity) -> usize {
+        let mut sessions = self.sessions.write().await;
+        let before = sessions.len();
+        sessions.retain(|_, entry| &entry.identity != identity);
+        before - sessions.len()
+    }
+
+    /// 列出未过期的 session，按最近活动时间倒序
+    pub async fn list_sessions(&self, current_token: &str) -> Vec<SessionInfo> {
+        let now = chrono::Utc::now().timestamp();
+        let config = self.config();
+        let sessions = self.sessions.read().await;
+        let mut list: Vec<SessionInfo> = sessions
+            .iter()
+            .filter(|(_, entry)| Self::expires_at(&config, entry) > now)
+            .map(|(token, entry)| SessionInfo {
+                id: entry.id.clone(),
+                identity: entry.identity.clone(),
+                created_at: entry.created_at,
+                last_seen_at: entry.last_seen_at,
+                expires_at: Self::expires_at(&config, entry),
+                ip: entry.client.ip.clone(),
+                user_agent: entry.client.user_agent.clone(),
+                current: token == current_token,
+            })
+            .collect();
+        list.sort_by_key(|s| std::cmp::Reverse(s.last_seen_at));
+        list
+    }
+
+    /// 按 session ID 吊销，返回被吊销 session 的登录身份
+    pub async fn revoke_session(&self, id: &str) -> Option<SessionIdentity> {
+        let mut sessions = self.sessions.write().await;
+        let token = sessions.iter().find(|(_, entry)| entry.id == id)?.0.clone();
+        sessions.remove(&token).map(|entry| entry.identity)
+    }
+
+    /// 吊销除 `current_token` 外满足条件的所有 session，返回删除数量
+    pub async fn revoke_other_sessions(
+        &self,
+        current_token: &str,
+        filter: impl Fn(&SessionIdentity) -> bool,
+    ) -> usize {
+        let mut sessions = self.sessions.write().await;
+        let before = sessions.len();
+        sessions.retain(|token, entry| token == current_token || !filter(&entry.identity));
+        before - sessions.len()
+    }
+
     /// 删除 session
     pub async fn delete_session(&self, token: &str) {
         let mut sessions = self.sessions.write().await;
@@ -716,7 +1352,167 @@ impl SessionManager {
     /// 清理过期 sessions
     pub async fn cleanup_expired(&self) {
         let now = chrono::Utc::now().timestamp();
+        let config = self.config();
         let mut sessions = self.sessions.write().await;
-        sessions.retain(|_, &mut expiry| expiry > now);
+        sessions.retain(|_, entry| Self::expires_at(&config, entry) > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_totp_code_single_use_under_concurrency() {
+        let dir = std::env::temp_dir().join(format!("anti-proxy-totp-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manager = Arc::new(WebAuthnManager::new(dir.clone()));
+        let secret = crate::modules::totp::generate_secret();
+        manager.auth_config.write().await.totp_secret = Some(secret.clone());
+        let key = crate::modules::totp::base32_decode(&secret).unwrap();
+        let code = crate::modules::totp::code_at(&key, chrono::Utc::now().timestamp() as u64);
+
+        let attempts: Vec<_> = (0..8)
+            .map(|_| {
+                let (manager, code) = (manager.clone(), code.clone());
+                tokio::spawn(async move { manager.verify_totp(&code).await.is_ok() })
+            })
+            .collect();
+        let mut accepted = 0;
+        for attempt in attempts {
+            accepted += attempt.await.unwrap() as usize;
+        }
+        assert_eq!(accepted, 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_revoke_credential_sessions() {
+        let sessions = SessionManager::new(1);
+        let laptop = sessions.create_passkey_session("laptop", SessionClient::default()).await;
+        let yubikey = sessions.create_passkey_session("yubikey", SessionClient::default()).await;
+        let password = sessions.create_session(SessionClient::default()).await;
+
+        assert_eq!(sessions.session_credential(&laptop).await.as_deref(), Some("laptop"));
+        assert_eq!(sessions.session_credential(&password).await, None);
+
+        assert_eq!(sessions.revoke_credential_sessions("laptop").await, 1);
+        assert!(!sessions.validate_session(&laptop).await);
+        assert!(sessions.validate_session(&yubikey).await);
+        assert!(sessions.validate_session(&password).await);
+
+        let alice = sessions.create_user_session("alice", SessionClient::default()).await;
+        assert_eq!(sessions.session_identity(&alice).await, Some(SessionIdentity::User("alice".to_string())));
+        assert_eq!(sessions.revoke_user_sessions("alice").await, 1);
+        assert!(!sessions.validate_session(&alice).await);
+    }
+
+    #[tokio::test]
+    async fn test_list_and_revoke_sessions() {
+        let sessions = SessionManager::new(1);
+        let client = SessionClient {
+            ip: Some("10.0.0.5".to_string()),
+            user_agent: Some("Firefox".to_string()),
+        };
+        let laptop = sessions.create_session(client).await;
+        let phone = sessions.create_session(SessionClient::default()).await;
+        let bob = sessions.create_user_session("bob", SessionClient::default()).await;
+
+        let list = sessions.list_sessions(&phone).await;
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.iter().filter(|s| s.current).count(), 1);
+        let laptop_info = list.iter().find(|s| s.ip.as_deref() == Some("10.0.0.5")).unwrap();
+        assert_eq!(laptop_info.user_agent.as_deref(), Some("Firefox"));
+        assert_ne!(laptop_info.id, laptop);
+
+        assert_eq!(sessions.revoke_session(&laptop_info.id).await, Some(SessionIdentity::Owner));
+        assert!(!sessions.validate_session(&laptop).await);
+        assert_eq!(sessions.revoke_session(&laptop_info.id).await, None);
+
+        sessions.create_session(SessionClient::default()).await;
+        assert_eq!(sessions.revoke_other_sessions(&phone, |i| *i == SessionIdentity::Owner).await, 1);
+        assert!(sessions.validate_session(&phone).await);
+        assert!(sessions.validate_session(&bob).await);
+        assert_eq!(sessions.revoke_other_sessions(&phone, |_| true).await, 1);
+        assert_eq!(sessions.list_sessions(&phone).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_session_idle_timeout() {
+        let sessions = SessionManager::new(1);
+        let token = sessions.create_session(SessionClient::default()).await;
+        sessions.sessions.write().await.get_mut(&token).unwrap().last_seen_at -= 600;
+        assert!(sessions.validate_session(&token).await);
+
+        sessions.configure(&SessionConfig { ttl_hours: 1, idle_timeout_minutes: 5, ..Default::default() });
+        assert!(!sessions.validate_session(&token).await);
+        assert!(sessions.refresh_session(&token).await);
+        assert!(sessions.validate_session(&token).await);
+
+        sessions.sessions.write().await.get_mut(&token).unwrap().created_at -= 3600;
+        assert!(!sessions.validate_session(&token).await);
+        sessions.cleanup_expired().await;
+        assert!(sessions.list_sessions(&token).await.is_empty());
+    }
+
+    fn test_credential(id: &str, role: AdminRole) -> StoredCredential {
+        StoredCredential {
+            credential_id: id.to_string(),
+            user_id: id.to_string(),
+            user_name: id.to_string(),
+            passkey_json: String::new(),
+            created_at: 0,
+            last_used_at: None,
+            role,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_roles_keep_last_owner() {
+        let dir = std::env::temp_dir().join(format!("antiproxy-webauthn-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manager = WebAuthnManager::new(dir.clone());
+        *manager.credentials.write().await =
+            vec![test_credential("laptop", AdminRole::Owner), test_credential("ops", AdminRole::Viewer)];
+
+        assert!(manager.set_credential_role("laptop", AdminRole::Operator).await.is_err());
+        assert!(manager.delete_credential("laptop").await.is_err());
+        assert_eq!(manager.set_credential_role("ops", AdminRole::Operator).await.unwrap().role, AdminRole::Operator);
+        assert_eq!(
+            manager.identity_role(&SessionIdentity::Passkey("ops".to_string())).await,
+            Some(AdminRole::Operator)
+        );
+        assert_eq!(manager.identity_role(&SessionIdentity::User("bob".to_string())).await, None);
+
+        // 旧版本凭据没有 role 字段
+        let legacy: StoredCredential = serde_json::from_str(
+            r#"{"credential_id":"a","user_id":"b","user_name":"c","passkey_json":"","created_at":0,"last_used_at":null}"#,
+        )
+        .unwrap();
+        assert_eq!(legacy.role, AdminRole::Owner);
+        assert!(AdminRole::Owner > AdminRole::Operator && AdminRole::Operator > AdminRole::Viewer);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_admin_tokens() {
+        let dir = std::env::temp_dir().join(format!("antiproxy-webauthn-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manager = WebAuthnManager::new(dir.clone());
+
+        let (info, token) = manager.create_admin_token("terraform", AdminRole::Operator).await.unwrap();
+        assert!(token.starts_with(ADMIN_TOKEN_PREFIX));
+        assert!(manager.create_admin_token("terraform", AdminRole::Viewer).await.is_err());
+        assert_eq!(manager.verify_admin_token(&token).await.unwrap().role, AdminRole::Operator);
+        assert!(manager.verify_admin_token("apt_wrong").await.is_none());
+        // 配置文件中只有哈希
+        assert!(!std::fs::read_to_string(dir.join("auth_config.json")).unwrap().contains(&token));
+
+        manager.remove_admin_token(&info.id).await.unwrap();
+        assert!(manager.verify_admin_token(&token).await.is_none());
+        assert!(manager.remove_admin_token(&info.id).await.is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
     }
 }
@@ -0,0 +1,211 @@
+// 账号请求结果统计
+// 记录每个账号最近一段时间内的上游响应结果，供 /api/accounts/health 计算 429 比例与最近成功时间
+use dashmap::DashMap;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+tokio::task_local! {
+    /// 当前请求实际使用的上游账号（monitor 中间件设置作用域，handler 选定账号后写入）
+    static SERVED_ACCOUNT: RefCell<Option<String>>;
+}
+
+/// 在作用域内执行请求，返回结果及处理该请求的账号邮箱
+pub async fn track_served_account<F: Future>(fut: F) -> (F::Output, Option<String>) {
+    SERVED_ACCOUNT
+        .scope(RefCell::new(None), async move {
+            let output = fut.await;
+            let account = SERVED_ACCOUNT.with(|a| a.borrow_mut().take());
+            (output, account)
+        })
+        .await
+}
+
+/// 记录当前请求使用的账号（不在作用域内时忽略）
+pub fn note_served_account(email: &str) {
+    let _ = SERVED_ACCOUNT.try_with(|a| *a.borrow_mut() = Some(email.to_string()));
+}
+
+/// 统计窗口
+pub const STATS_WINDOW: Duration = Duration::from_secs(3600);
+/// 每个账号最多保留的结果数
+const MAX_OUTCOMES_PER_ACCOUNT: usize = 1000;
+
+#[derive(Default)]
+struct AccountOutcomes {
+    /// (时间, 是否为 429)
+    recent: VecDeque<(Instant, bool)>,
+    /// 最近一次成功响应（Unix 秒）
+    last_success: Option<i64>,
+}
+
+impl AccountOutcomes {
+    fn push(&mut self, now: Instant, is_429: bool) {
+        self.recent.push_back((now, is_429));
+        while self.recent.len() > MAX_OUTCOMES_PER_ACCOUNT {
+            self.recent.pop_front();
+        }
+        self.prune(now);
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while let Some((at, _)) = self.recent.front() {
+            if now.duration_since(*at) > STATS_WINDOW {
+                self.recent.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// 单个账号在统计窗口内的汇总
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccountStatsSnapshot {
+    pub requests: u32,
+    pub rate_limited: u32,
+    pub last_success: Option<i64>,
+}
+
+impl AccountStatsSnapshot {
+    /// 429 占比（无请求时为 0）
+    pub fn rate_limited_ratio(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.rate_limited as f64 / self.requests as f64
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AccountStats {
+    outcomes: DashMap<String, AccountOutcomes>,
+}
+
+impl AccountStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一次成功响应
+    pub fn record_success(&self, account_id: &str) {
+        self.record_at(account_id, Instant::now(), None);
+    }
+
+    /// 记录一次失败响应
+    pub fn record_failure(&self, account_id: &str, status: u16) {
+        self.record_at(account_id, Instant::now(), Some(status));
+    }
+
+    fn record_at(&self, account_id: &str, now: Instant, failure_status: Option<u16>) {
+        let mut entry = self.outcomes.entry(account_id.to_string()).or_default();
+        entry.push(now, failure_status == Some(429));
+        if failure_status.is_none() {
+            entry.last_success = Some(chrono::Utc::now().timestamp());
+        }
+    }
+
+    pub fn snapshot(&self, account_id: &str) -> AccountStatsSnapshot {
+        self.snapshot_at(account_id, Instant::now())
+    }
+
+    fn snapshot_at(&self, account_id: &str, now: Instant) -> AccountStatsSnapshot {
+        let Some(mut entry) = self.outcomes.get_mut(account_id) else {
+            return AccountStatsSnapshot::default();
+        };
+        entry.prune(now);
+        AccountStatsSnapshot {
+            requests: entry.recent.len() as u32,
+            rate_limited: entry.recent.iter().filter(|(_, is_429)| *is_429).count() as u32,
+            last_success: entry.last_success,
+        }
+    }
+}
+
+/// 从 fetchAvailableModels 响应中提取各模型剩余配额百分比
+///
+/// 与 quota::fetch_quota 的规则一致：只保留 gemini / claude 模型
+pub fn parse_quota_models(resp: &Value) -> Vec<(String, i32)> {
+    let mut models: Vec<(String, i32)> = resp
+        .get("models")
+        .and_then(|m| m.as_object())
+        .map(|models| {
+            models
+                .iter()
+                .filter(|(name, _)| name.contains("gemini") || name.contains("claude"))
+                .filter_map(|(name, info)| {
+                    let fraction = info.get("quotaInfo")?.get("remainingFraction");
+                    let percentage = fraction.and_then(|f| f.as_f64()).map(|f| (f * 100.0) as i32).unwrap_or(0);
+                    Some((name.clone(), percentage))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    models.sort();
+    models
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_rate_limited_ratio_and_window() {
+        let stats = AccountStats::new();
+        let start = Instant::now();
+        stats.record_at("a", start, Some(429));
+        stats.record_at("a", start, None);
+        stats.record_at("a", start, Some(500));
+        stats.record_at("a", start, Some(429));
+
+        let snap = stats.snapshot_at("a", start);
+        assert_eq!(snap.requests, 4);
+        assert_eq!(snap.rate_limited, 2);
+        assert_eq!(snap.rate_limited_ratio(), 0.5);
+        assert!(snap.last_success.is_some());
+
+        // 窗口外的结果被丢弃，但保留最近成功时间
+        let later = stats.snapshot_at("a", start + STATS_WINDOW + Duration::from_secs(1));
+        assert_eq!(later.requests, 0);
+        assert_eq!(later.rate_limited_ratio(), 0.0);
+        assert_eq!(later.last_success, snap.last_success);
+
+        assert_eq!(stats.snapshot("unknown"), AccountStatsSnapshot::default());
+    }
+
+    #[tokio::test]
+    async fn test_track_served_account() {
+        let (value, account) = track_served_account(async {
+            note_served_account("first@example.com");
+            // 重试切换账号后以最后一次为准
+            note_served_account("second@example.com");
+            42
+        })
+        .await;
+        assert_eq!(value, 42);
+        assert_eq!(account.as_deref(), Some("second@example.com"));
+
+        // 作用域外调用不会 panic
+        note_served_account("ignored@example.com");
+    }
+
+    #[test]
+    fn test_parse_quota_models() {
+        let resp = json!({
+            "models": {
+                "gemini-2.5-pro": { "quotaInfo": { "remainingFraction": 0.75 } },
+                "claude-sonnet-4-5": { "quotaInfo": {} },
+                "chat_20706": { "quotaInfo": { "remainingFraction": 1.0 } },
+                "gemini-no-quota": {}
+            }
+        });
+        assert_eq!(
+            parse_quota_models(&resp),
+            vec![("claude-sonnet-4-5".to_string(), 0), ("gemini-2.5-pro".to_string(), 75)]
+        );
+    }
+}
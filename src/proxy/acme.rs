@@ -0,0 +1,547 @@
+// ACME (RFC 8555) 证书自动签发与续期
+// HTTP-01 验证：在 `http_port` 上应答 `/.well-known/acme-challenge/<token>`，其余请求重定向到 HTTPS；
+// 账号密钥与证书保存在数据目录的 `tls/` 下，重启后直接复用，到期前自动续期
+use axum::{
+    extract::Path,
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
+};
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine as _};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, EcdsaSigningAlgorithm, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING, ECDSA_P256_SHA256_FIXED_SIGNING};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::PrivateKeyDer;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::{Path as FsPath, PathBuf};
+use std::time::Duration;
+
+use crate::proxy::config::AcmeConfig;
+use crate::proxy::tls::{certified_key_from_pem, format_expiry, CertInfo, CERTS};
+
+const ACCOUNT_KEY_FILE: &str = "acme_account.pem";
+const CERT_FILE: &str = "cert.pem";
+const KEY_FILE: &str = "key.pem";
+
+/// 证书有效时的检查间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(12 * 3600);
+/// 签发失败后的重试间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(3600);
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: usize = 60;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 待验证的 HTTP-01 token -> key authorization
+static CHALLENGES: Lazy<DashMap<String, String>> = Lazy::new(DashMap::new);
+
+// DER 编码用到的 OID
+const OID_COMMON_NAME: &[u8] = &[0x06, 0x03, 0x55, 0x04, 0x03];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_PRIME256V1: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_EXTENSION_REQUEST: &[u8] = &[0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x0e];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x06, 0x03, 0x55, 0x1d, 0x11];
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+
+fn tls_dir() -> Result<PathBuf, String> {
+    let dir = crate::modules::account::get_data_dir()?.join("tls");
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    Ok(dir)
+}
+
+/// 写入私钥类文件（Unix 下权限 0600）
+fn write_private(path: &FsPath, contents: &[u8]) -> Result<(), String> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    std::io::Write::write_all(&mut file, contents).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+fn pem_encode(label: &str, der: &[u8]) -> String {
+    let encoded = STANDARD.encode(der);
+    let mut out = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        out.push_str(std::str::from_utf8(line).unwrap_or_default());
+        out.push('\n');
+    }
+    out.push_str(&format!("-----END {}-----\n", label));
+    out
+}
+
+fn generate_pkcs8(rng: &SystemRandom) -> Result<Vec<u8>, String> {
+    EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, rng)
+        .map(|doc| doc.as_ref().to_vec())
+        .map_err(|_| "Failed to generate ECDSA key".to_string())
+}
+
+fn key_pair(alg: &'static EcdsaSigningAlgorithm, pkcs8: &[u8], rng: &SystemRandom) -> Result<EcdsaKeyPair, String> {
+    EcdsaKeyPair::from_pkcs8(alg, pkcs8, rng).map_err(|e| format!("Invalid ECDSA key: {}", e))
+}
+
+/// 读取或生成 ACME 账号密钥 (P-256, PKCS#8)
+fn load_or_create_account_key(rng: &SystemRandom) -> Result<Vec<u8>, String> {
+    let path = tls_dir()?.join(ACCOUNT_KEY_FILE);
+    if let Ok(pem) = std::fs::read(&path) {
+        return match PrivateKeyDer::from_pem_slice(&pem) {
+            Ok(PrivateKeyDer::Pkcs8(key)) => Ok(key.secret_pkcs8_der().to_vec()),
+            _ => Err(format!("{:?} is not a PKCS#8 private key", path)),
+        };
+    }
+    let pkcs8 = generate_pkcs8(rng)?;
+    write_private(&path, pem_encode("PRIVATE KEY", &pkcs8).as_bytes())?;
+    tracing::info!("[ACME] Generated account key: {:?}", path);
+    Ok(pkcs8)
+}
+
+// ===== DER 编码 =====
+
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+fn der_seq(parts: &[Vec<u8>]) -> Vec<u8> {
+    der(0x30, &parts.concat())
+}
+
+fn der_bit_string(bytes: &[u8]) -> Vec<u8> {
+    der(0x03, &[&[0u8][..], bytes].concat())
+}
+
+/// 构建 PKCS#10 证书签名请求（CN 为第一个域名，全部域名写入 SAN）
+fn build_csr(key: &EcdsaKeyPair, rng: &SystemRandom, domains: &[String]) -> Result<Vec<u8>, String> {
+    let first = domains.first().ok_or("No domain configured")?;
+    let subject = der_seq(&[der(0x31, &der_seq(&[OID_COMMON_NAME.to_vec(), der(0x0c, first.as_bytes())]))]);
+    let public_key = der_seq(&[
+        der_seq(&[OID_EC_PUBLIC_KEY.to_vec(), OID_PRIME256V1.to_vec()]),
+        der_bit_string(key.public_key().as_ref()),
+    ]);
+    let alt_names = der_seq(&domains.iter().map(|d| der(0x82, d.as_bytes())).collect::<Vec<_>>());
+    let extensions = der_seq(&[der_seq(&[OID_SUBJECT_ALT_NAME.to_vec(), der(0x04, &alt_names)])]);
+    let attributes = der(0xa0, &der_seq(&[OID_EXTENSION_REQUEST.to_vec(), der(0x31, &extensions)]));
+    let info = der_seq(&[vec![0x02, 0x01, 0x00], subject, public_key, attributes]);
+    let signature = key.sign(rng, &info).map_err(|_| "Failed to sign CSR".to_string())?;
+    Ok(der_seq(&[info, der_seq(&[OID_ECDSA_WITH_SHA256.to_vec()]), der_bit_string(signature.as_ref())]))
+}
+
+// ===== ACME 协议 =====
+
+struct AcmeResponse {
+    location: Option<String>,
+    body: String,
+}
+
+impl AcmeResponse {
+    fn json(&self) -> Result<Value, String> {
+        serde_json::from_str(&self.body).map_err(|e| format!("Invalid ACME response: {}", e))
+    }
+}
+
+/// 从 problem 文档或 authorization 的 challenge 错误中提取说明
+fn problem_detail(value: &Value) -> String {
+    value
+        .get("detail")
+        .or_else(|| value.pointer("/error/detail"))
+        .or_else(|| {
+            value
+                .get("challenges")
+                .and_then(|c| c.as_array())
+                .and_then(|c| c.iter().find_map(|ch| ch.pointer("/error/detail")))
+        })
+        .and_then(|d| d.as_str())
+        .unwrap_or("unknown error")
+        .to_string()
+}
+
+struct AcmeClient {
+    http: reqwest::Client,
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+    key: EcdsaKeyPair,
+    rng: SystemRandom,
+    /// 账号 URL，注册后用于 JWS 的 `kid`
+    kid: Option<String>,
+    nonce: Option<String>,
+}
+
+impl AcmeClient {
+    async fn connect(directory_url: &str, key: EcdsaKeyPair, rng: SystemRandom) -> Result<Self, String> {
+        let http = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build().map_err(|e| e.to_string())?;
+        let directory: Value = http
+            .get(directory_url)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to fetch ACME directory: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Invalid ACME directory: {}", e))?;
+        let url = |name: &str| {
+            directory
+                .get(name)
+                .and_then(|v| v.as_str())
+                .map(String::from)
+                .ok_or_else(|| format!("ACME directory has no {}", name))
+        };
+        Ok(Self {
+            new_nonce: url("newNonce")?,
+            new_account: url("newAccount")?,
+            new_order: url("newOrder")?,
+            http,
+            key,
+            rng,
+            kid: None,
+            nonce: None,
+        })
+    }
+
+    fn jwk_coordinates(&self) -> (String, String) {
+        // 未压缩公钥：0x04 || x || y
+        let public = self.key.public_key().as_ref();
+        (URL_SAFE_NO_PAD.encode(&public[1..33]), URL_SAFE_NO_PAD.encode(&public[33..65]))
+    }
+
+    fn jwk(&self) -> Value {
+        let (x, y) = self.jwk_coordinates();
+        json!({"crv": "P-256", "kty": "EC", "x": x, "y": y})
+    }
+
+    /// RFC 7638 JWK 指纹（字段按字典序、无空白）
+    fn thumbprint(&self) -> String {
+        let (x, y) = self.jwk_coordinates();
+        let canonical = format!(r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#, x, y);
+        URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+    }
+
+    fn sign(&self, url: &str, nonce: &str, payload: Option<&Value>) -> Result<Value, String> {
+        let mut protected = json!({"alg": "ES256", "nonce": nonce, "url": url});
+        match &self.kid {
+            Some(kid) => protected["kid"] = json!(kid),
+            None => protected["jwk"] = self.jwk(),
+        }
+        let protected = URL_SAFE_NO_PAD.encode(protected.to_string());
+        // POST-as-GET 的 payload 为空串
+        let payload = payload.map(|p| URL_SAFE_NO_PAD.encode(p.to_string())).unwrap_or_default();
+        let signature = self
+            .key
+            .sign(&self.rng, format!("{}.{}", protected, payload).as_bytes())
+            .map_err(|_| "Failed to sign ACME request".to_string())?;
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": URL_SAFE_NO_PAD.encode(signature.as_ref()),
+        }))
+    }
+
+    async fn fresh_nonce(&self) -> Result<String, String> {
+        let resp = self.http.head(&self.new_nonce).send().await.map_err(|e| format!("Failed to get ACME nonce: {}", e))?;
+        resp.headers()
+            .get("replay-nonce")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from)
+            .ok_or_else(|| "ACME server returned no nonce".to_string())
+    }
+
+    /// 发送签名请求；nonce 失效（badNonce）时重试一次
+    async fn post(&mut self, url: &str, payload: Option<&Value>) -> Result<AcmeResponse, String> {
+        let mut retried = false;
+        loop {
+            let nonce = match self.nonce.take() {
+                Some(nonce) => nonce,
+                None => self.fresh_nonce().await?,
+            };
+            let body = self.sign(url, &nonce, payload)?;
+            let resp = self
+                .http
+                .post(url)
+                .header(header::CONTENT_TYPE, "application/jose+json")
+                .body(body.to_string())
+                .send()
+                .await
+                .map_err(|e| format!("ACME request to {} failed: {}", url, e))?;
+            let header_value = |name: &str| resp.headers().get(name).and_then(|v| v.to_str().ok()).map(String::from);
+            self.nonce = header_value("replay-nonce");
+            let location = header_value("location");
+            let status = resp.status();
+            let body = resp.text().await.map_err(|e| e.to_string())?;
+            if status.is_success() {
+                return Ok(AcmeResponse { location, body });
+            }
+
+            let problem: Value = serde_json::from_str(&body).unwrap_or_default();
+            if !retried && problem.get("type").and_then(|t| t.as_str()) == Some("urn:ietf:params:acme:error:badNonce") {
+                retried = true;
+                continue;
+            }
+            return Err(format!("ACME request to {} failed ({}): {}", url, status, problem_detail(&problem)));
+        }
+    }
+
+    /// 轮询资源直到状态进入 `done` 之一
+    async fn poll(&mut self, url: &str, done: &[&str]) -> Result<Value, String> {
+        for _ in 0..POLL_ATTEMPTS {
+            let value = self.post(url, None).await?.json()?;
+            match value.get("status").and_then(|s| s.as_str()) {
+                Some(status) if done.contains(&status) => return Ok(value),
+                Some("invalid") => return Err(format!("ACME validation failed: {}", problem_detail(&value))),
+                _ => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        }
+        Err(format!("Timed out waiting for {}", url))
+    }
+
+    async fn register(&mut self, email: Option<&str>) -> Result<(), String> {
+        let mut payload = json!({"termsOfServiceAgreed": true});
+        if let Some(email) = email.filter(|e| !e.trim().is_empty()) {
+            payload["contact"] = json!([format!("mailto:{}", email.trim())]);
+        }
+        let url = self.new_account.clone();
+        let resp = self.post(&url, Some(&payload)).await?;
+        self.kid = Some(resp.location.ok_or("ACME account response has no Location")?);
+        Ok(())
+    }
+
+    /// 完成一个域名的 HTTP-01 验证
+    async fn authorize(&mut self, authz_url: &str) -> Result<(), String> {
+        let authz = self.post(authz_url, None).await?.json()?;
+        if authz.get("status").and_then(|s| s.as_str()) == Some("valid") {
+            return Ok(());
+        }
+        let domain = authz.pointer("/identifier/value").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        let challenge = authz
+            .get("challenges")
+            .and_then(|c| c.as_array())
+            .and_then(|c| c.iter().find(|ch| ch.get("type").and_then(|t| t.as_str()) == Some("http-01")))
+            .ok_or_else(|| format!("No http-01 challenge offered for {}", domain))?;
+        let (Some(token), Some(challenge_url)) = (
+            challenge.get("token").and_then(|t| t.as_str()).map(String::from),
+            challenge.get("url").and_then(|u| u.as_str()).map(String::from),
+        ) else {
+            return Err(format!("Malformed http-01 challenge for {}", domain));
+        };
+
+        CHALLENGES.insert(token.clone(), format!("{}.{}", token, self.thumbprint()));
+        let result = match self.post(&challenge_url, Some(&json!({}))).await {
+            Ok(_) => self.poll(authz_url, &["valid"]).await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        CHALLENGES.remove(&token);
+        result.map_err(|e| format!("{}: {}", domain, e))
+    }
+}
+
+/// 签发证书，返回 (证书链 PEM, 私钥 PEM)
+async fn issue(config: &AcmeConfig, domains: &[String]) -> Result<(String, String), String> {
+    let rng = SystemRandom::new();
+    let account_key = key_pair(&ECDSA_P256_SHA256_FIXED_SIGNING, &load_or_create_account_key(&rng)?, &rng)?;
+    let mut client = AcmeClient::connect(&config.directory_url, account_key, rng.clone()).await?;
+    client.register(config.email.as_deref()).await?;
+
+    let identifiers: Vec<Value> = domains.iter().map(|d| json!({"type": "dns", "value": d})).collect();
+    let new_order = client.new_order.clone();
+    let resp = client.post(&new_order, Some(&json!({"identifiers": identifiers}))).await?;
+    let order_url = resp.location.clone().ok_or("ACME order response has no Location")?;
+    let order = resp.json()?;
+
+    let authorizations: Vec<String> = order
+        .get("authorizations")
+        .and_then(|a| a.as_array())
+        .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+        .unwrap_or_default();
+    for authz_url in &authorizations {
+        client.authorize(authz_url).await?;
+    }
+
+    let order = client.poll(&order_url, &["ready"]).await?;
+    let finalize = order.get("finalize").and_then(|f| f.as_str()).ok_or("ACME order has no finalize URL")?.to_string();
+    let cert_pkcs8 = generate_pkcs8(&rng)?;
+    let csr = build_csr(&key_pair(&ECDSA_P256_SHA256_ASN1_SIGNING, &cert_pkcs8, &rng)?, &rng, domains)?;
+    client.post(&finalize, Some(&json!({"csr": URL_SAFE_NO_PAD.encode(csr)}))).await?;
+    let order = client.poll(&order_url, &["valid"]).await?;
+
+    let cert_url = order.get("certificate").and_then(|c| c.as_str()).ok_or("ACME order has no certificate URL")?.to_string();
+    let cert_pem = client.post(&cert_url, None).await?.body;
+    Ok((cert_pem, pem_encode("PRIVATE KEY", &cert_pkcs8)))
+}
+
+async fn issue_and_install(config: &AcmeConfig, domains: &[String]) -> Result<CertInfo, String> {
+    let (cert_pem, key_pem) = issue(config, domains).await?;
+    let (key, info) = certified_key_from_pem(cert_pem.as_bytes(), key_pem.as_bytes())?;
+    let dir = tls_dir()?;
+    write_private(&dir.join(KEY_FILE), key_pem.as_bytes())?;
+    std::fs::write(dir.join(CERT_FILE), &cert_pem).map_err(|e| format!("Failed to save certificate: {}", e))?;
+    CERTS.install(key);
+    Ok(info)
+}
+
+/// 加载上次签发并保存的证书
+fn load_stored_cert() -> Option<CertInfo> {
+    let dir = tls_dir().ok()?;
+    let cert_pem = std::fs::read(dir.join(CERT_FILE)).ok()?;
+    let key_pem = std::fs::read(dir.join(KEY_FILE)).ok()?;
+    match certified_key_from_pem(&cert_pem, &key_pem) {
+        Ok((key, info)) => {
+            CERTS.install(key);
+            Some(info)
+        }
+        Err(e) => {
+            tracing::warn!("[ACME] Ignoring stored certificate: {}", e);
+            None
+        }
+    }
+}
+
+async fn renewal_loop(config: AcmeConfig, domains: Vec<String>, mut current: Option<CertInfo>) {
+    let renew_before = i64::from(config.renew_before_days) * 86400;
+    loop {
+        let renew_at = current
+            .as_ref()
+            .filter(|info| info.covers(&domains))
+            .map(|info| info.not_after - renew_before);
+        let now = chrono::Utc::now().timestamp();
+        if let Some(renew_at) = renew_at.filter(|&at| at > now) {
+            tokio::time::sleep(CHECK_INTERVAL.min(Duration::from_secs((renew_at - now) as u64))).await;
+            continue;
+        }
+
+        tracing::info!("[ACME] Requesting certificate for {:?}", domains);
+        match issue_and_install(&config, &domains).await {
+            Ok(info) => {
+                tracing::info!("[ACME] Certificate issued, expires at {}", format_expiry(info.not_after));
+                current = Some(info);
+            }
+            Err(e) => {
+                tracing::error!("[ACME] Certificate issuance failed, retrying in {}s: {}", RETRY_INTERVAL.as_secs(), e);
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+        }
+    }
+}
+
+async fn challenge_handler(Path(token): Path<String>) -> Response {
+    match CHALLENGES.get(&token) {
+        Some(key_authorization) => ([(header::CONTENT_TYPE, "text/plain")], key_authorization.clone()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+fn https_redirect_target(headers: &HeaderMap, uri: &Uri, https_port: u16) -> Option<String> {
+    let host = headers.get(header::HOST)?.to_str().ok()?;
+    // 去掉端口（兼容 IPv6 字面量）
+    let host = match host.rfind(':') {
+        Some(idx) if !host[idx..].contains(']') => &host[..idx],
+        _ => host,
+    };
+    let port = if https_port == 443 { String::new() } else { format!(":{}", https_port) };
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    Some(format!("https://{}{}{}", host, port, path))
+}
+
+/// 启动 ACME：加载已保存的证书，监听 HTTP-01 端口，并在后台签发 / 续期
+pub async fn start(config: AcmeConfig, host: &str, https_port: u16) -> Result<(), String> {
+    if config.http_port == https_port {
+        return Err("tls.acme.http_port must differ from the HTTPS port".to_string());
+    }
+    let domains: Vec<String> = config
+        .domains
+        .iter()
+        .map(|d| d.trim().to_ascii_lowercase())
+        .filter(|d| !d.is_empty())
+        .collect();
+
+    let current = load_stored_cert();
+    if let Some(info) = &current {
+        tracing::info!("[ACME] Loaded stored certificate for {:?}, expires at {}", info.dns_names, format_expiry(info.not_after));
+    }
+
+    let addr = format!("{}:{}", host, config.http_port);
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .map_err(|e| format!("Failed to bind ACME challenge listener on {}: {}", addr, e))?;
+    let app = Router::new()
+        .route("/.well-known/acme-challenge/:token", get(challenge_handler))
+        .fallback(move |headers: HeaderMap, uri: Uri| async move {
+            match https_redirect_target(&headers, &uri, https_port) {
+                Some(target) => Redirect::permanent(&target).into_response(),
+                None => StatusCode::BAD_REQUEST.into_response(),
+            }
+        });
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("[ACME] Challenge listener stopped: {}", e);
+        }
+    });
+    tracing::info!("[ACME] Answering HTTP-01 challenges on http://{}", addr);
+
+    tokio::spawn(renewal_loop(config, domains, current));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x509_parser::prelude::FromDer;
+
+    #[test]
+    fn test_der_long_length() {
+        assert_eq!(der(0x04, &[1, 2]), vec![0x04, 2, 1, 2]);
+        let long = der(0x04, &[0u8; 300]);
+        assert_eq!(&long[..4], &[0x04, 0x82, 0x01, 0x2c]);
+        assert_eq!(long.len(), 304);
+    }
+
+    #[test]
+    fn test_csr_contains_all_domains() {
+        let rng = SystemRandom::new();
+        let pkcs8 = generate_pkcs8(&rng).unwrap();
+        let key = key_pair(&ECDSA_P256_SHA256_ASN1_SIGNING, &pkcs8, &rng).unwrap();
+        let domains = vec!["proxy.example.com".to_string(), "api.example.com".to_string()];
+        let csr = build_csr(&key, &rng, &domains).unwrap();
+
+        let (rest, parsed) = x509_parser::certification_request::X509CertificationRequest::from_der(&csr).unwrap();
+        assert!(rest.is_empty());
+        let names: Vec<String> = parsed
+            .requested_extensions()
+            .into_iter()
+            .flatten()
+            .filter_map(|ext| match ext {
+                x509_parser::extensions::ParsedExtension::SubjectAlternativeName(san) => Some(san),
+                _ => None,
+            })
+            .flat_map(|san| san.general_names.iter())
+            .filter_map(|name| match name {
+                x509_parser::extensions::GeneralName::DNSName(dns) => Some(dns.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, domains);
+    }
+
+    #[test]
+    fn test_https_redirect_target() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, "proxy.example.com:80".parse().unwrap());
+        let uri: Uri = "/v1/models?x=1".parse().unwrap();
+        assert_eq!(https_redirect_target(&headers, &uri, 443).as_deref(), Some("https://proxy.example.com/v1/models?x=1"));
+        headers.insert(header::HOST, "[::1]".parse().unwrap());
+        assert_eq!(https_redirect_target(&headers, &uri, 8443).as_deref(), Some("https://[::1]:8443/v1/models?x=1"));
+    }
+}
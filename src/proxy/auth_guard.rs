@@ -0,0 +1,238 @@
+// 认证暴力破解防护
+// 按客户端 IP 统计登录接口 (/api/auth/*)、API Key 与管理 API token 的校验失败：窗口内失败达到上限后锁定该 IP，
+// 锁定时长逐次翻倍；失败与锁定记入审计日志，连续失败达到阈值时发送告警。状态只保存在内存中，重启后清空
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::sync::RwLock;
+
+use crate::proxy::config::AuthLockoutConfig;
+
+/// 无法确定客户端地址时使用的统计键
+pub const UNKNOWN_IP: &str = "unknown";
+
+/// IP 记录超过该数量时清理已过期的条目
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// 全局认证防护（auth / web_auth 中间件与登录接口使用）
+pub static AUTH_GUARD: Lazy<AuthGuard> = Lazy::new(AuthGuard::default);
+
+/// 单个 IP 的失败计数
+#[derive(Debug, Clone, Default)]
+struct IpState {
+    /// 当前计数窗口的起点（Unix 秒）
+    window_start: i64,
+    /// 窗口内的失败次数
+    failures: u32,
+    /// 已锁定的次数，决定下次锁定时长
+    lockouts: u32,
+    /// 锁定截止时间（Unix 秒），0 表示未锁定过
+    locked_until: i64,
+    /// 自上次认证成功以来的连续失败次数（用于告警）
+    consecutive: u32,
+}
+
+/// 一次失败记录的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Failure {
+    consecutive: u32,
+    /// 本次失败触发的锁定时长（秒）
+    locked_for: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct AuthGuard {
+    config: RwLock<AuthLockoutConfig>,
+    states: DashMap<String, IpState>,
+}
+
+/// 校验锁定配置
+pub fn validate(config: &AuthLockoutConfig) -> Result<(), String> {
+    if config.max_failures == 0 {
+        return Ok(());
+    }
+    if config.window_seconds == 0 {
+        return Err("window_seconds must be greater than 0".to_string());
+    }
+    if config.lockout_seconds == 0 {
+        return Err("lockout_seconds must be greater than 0".to_string());
+    }
+    if config.max_lockout_seconds < config.lockout_seconds {
+        return Err("max_lockout_seconds must not be less than lockout_seconds".to_string());
+    }
+    Ok(())
+}
+
+/// 第 `lockouts + 1` 次锁定的时长：首次为 `lockout_seconds`，之后翻倍，不超过上限
+fn lockout_duration(config: &AuthLockoutConfig, lockouts: u32) -> u64 {
+    config
+        .lockout_seconds
+        .saturating_mul(1u64 << lockouts.min(32))
+        .min(config.max_lockout_seconds)
+}
+
+impl AuthGuard {
+    pub fn configure(&self, config: &AuthLockoutConfig) {
+        *self.config.write().unwrap() = config.clone();
+    }
+
+    /// IP 是否处于锁定中；锁定时返回剩余秒数（用于 Retry-After）
+    pub fn check(&self, ip: &str) -> Result<(), u64> {
+        self.check_at(ip, chrono::Utc::now().timestamp())
+    }
+
+    fn check_at(&self, ip: &str, now: i64) -> Result<(), u64> {
+        if self.config.read().unwrap().max_failures == 0 {
+            return Ok(());
+        }
+        match self.states.get(ip) {
+            Some(state) if state.locked_until > now => Err((state.locked_until - now) as u64),
+            _ => Ok(()),
+        }
+    }
+
+    /// 记录一次认证失败：写入审计日志，达到上限时锁定该 IP，连续失败达到阈值时告警
+    ///
+    /// `kind` 为失败的认证方式：`password`、`passkey`、`totp`、`api_key`、`admin_token`
+    pub fn record_failure(&self, ip: &str, kind: &str) {
+        let config = self.config.read().unwrap().clone();
+        let failure = self.failure_at(ip, &config, chrono::Utc::now().timestamp());
+
+        crate::modules::audit::record(
+            "auth.failed",
+            Some(ip),
+            None,
+            Some(json!({ "kind": kind, "failures": failure.consecutive })),
+        );
+        if let Some(seconds) = failure.locked_for {
+            tracing::warn!(
+                "[AuthGuard] {} locked out for {}s after {} consecutive failed attempts ({})",
+                ip,
+                seconds,
+                failure.consecutive,
+                kind
+            );
+            crate::modules::audit::record(
+                "auth.locked_out",
+                Some(ip),
+                None,
+                Some(json!({ "kind": kind, "failures": failure.consecutive, "lockout_seconds": seconds })),
+            );
+        }
+        if config.alert_after_failures > 0 && failure.consecutive.is_multiple_of(config.alert_after_failures) {
+            crate::modules::alerts::notify_auth_brute_force(ip, failure.consecutive, kind);
+        }
+    }
+
+    fn failure_at(&self, ip: &str, config: &AuthLockoutConfig, now: i64) -> Failure {
+        let window = config.window_seconds.max(1) as i64;
+        let failure = {
+            let mut state = self.states.entry(ip.to_string()).or_default();
+            // 上次锁定结束后足够久没有再被锁定，锁定时长从头计算
+            if state.locked_until > 0 && now - state.locked_until >= config.max_lockout_seconds as i64 {
+                state.lockouts = 0;
+            }
+            if now - state.window_start >= window {
+                state.window_start = now;
+                state.failures = 0;
+            }
+            state.failures += 1;
+            state.consecutive = state.consecutive.saturating_add(1);
+
+            let mut locked_for = None;
+            if config.max_failures > 0 && state.failures >= config.max_failures {
+                let seconds = lockout_duration(config, state.lockouts);
+                state.lockouts = state.lockouts.saturating_add(1);
+                state.locked_until = now + seconds as i64;
+                state.window_start = now;
+                state.failures = 0;
+                locked_for = Some(seconds);
+            }
+            Failure {
+                consecutive: state.consecutive,
+                locked_for,
+            }
+        };
+
+        if self.states.len() > PRUNE_THRESHOLD {
+            self.states
+                .retain(|_, state| state.locked_until > now || now - state.window_start < window);
+        }
+        failure
+    }
+
+    /// 认证成功后清除该 IP 的失败记录
+    pub fn record_success(&self, ip: &str) {
+        if self.states.contains_key(ip) {
+            self.states.remove(ip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> AuthLockoutConfig {
+        AuthLockoutConfig {
+            max_failures: 3,
+            window_seconds: 60,
+            lockout_seconds: 10,
+            max_lockout_seconds: 25,
+            alert_after_failures: 0,
+        }
+    }
+
+    #[test]
+    fn test_progressive_lockout() {
+        let guard = AuthGuard::default();
+        let config = test_config();
+        guard.configure(&config);
+        let ip = "203.0.113.7";
+
+        assert_eq!(guard.failure_at(ip, &config, 1000).locked_for, None);
+        assert_eq!(guard.failure_at(ip, &config, 1001).locked_for, None);
+        assert!(guard.check_at(ip, 1001).is_ok());
+        assert_eq!(guard.failure_at(ip, &config, 1002).locked_for, Some(10));
+        assert_eq!(guard.check_at(ip, 1005), Err(7));
+        assert!(guard.check_at(ip, 1012).is_ok());
+        assert!(guard.check_at("198.51.100.1", 1005).is_ok());
+
+        // 第二次锁定翻倍，第三次受上限限制
+        for t in 1012..1014 {
+            guard.failure_at(ip, &config, t);
+        }
+        assert_eq!(guard.failure_at(ip, &config, 1014).locked_for, Some(20));
+        for t in 1040..1042 {
+            guard.failure_at(ip, &config, t);
+        }
+        let failure = guard.failure_at(ip, &config, 1042);
+        assert_eq!(failure.locked_for, Some(25));
+        assert_eq!(failure.consecutive, 9);
+
+        guard.record_success(ip);
+        assert!(guard.check_at(ip, 1043).is_ok());
+        assert_eq!(guard.failure_at(ip, &config, 1043).consecutive, 1);
+    }
+
+    #[test]
+    fn test_failures_outside_window_reset() {
+        let guard = AuthGuard::default();
+        let config = test_config();
+        guard.configure(&config);
+        let ip = "203.0.113.8";
+
+        guard.failure_at(ip, &config, 0);
+        guard.failure_at(ip, &config, 10);
+        assert_eq!(guard.failure_at(ip, &config, 70).locked_for, None);
+        assert!(guard.check_at(ip, 70).is_ok());
+
+        let disabled = AuthLockoutConfig { max_failures: 0, ..config };
+        guard.configure(&disabled);
+        for t in 100..110 {
+            assert_eq!(guard.failure_at(ip, &disabled, t).locked_for, None);
+        }
+        assert!(validate(&disabled).is_ok());
+        assert!(validate(&AuthLockoutConfig { max_lockout_seconds: 5, ..test_config() }).is_err());
+    }
+}
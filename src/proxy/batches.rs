@@ -0,0 +1,492 @@
+// 批处理任务 (/v1/batches)
+// 兼容 OpenAI Batch API：客户端先通过 /v1/files 上传 JSONL（每行一个请求），创建批处理后由后台以低优先级逐条执行，
+// 成功与失败的结果分别写入新文件供下载。任务元数据与执行进度保存在数据目录的 batches/ 下，重启后继续执行未完成的任务
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+
+use crate::proxy::config::BatchConfig;
+
+/// 数据目录下的存储子目录
+const BATCHES_DIR: &str = "batches";
+/// 批处理 ID 前缀
+const ID_PREFIX: &str = "batch_";
+/// 目前唯一支持的完成时限
+pub const COMPLETION_WINDOW: &str = "24h";
+const COMPLETION_WINDOW_SECS: i64 = 24 * 3600;
+
+/// 可以批量执行的端点
+pub const SUPPORTED_ENDPOINTS: [&str; 3] = ["/v1/chat/completions", "/v1/completions", "/v1/embeddings"];
+
+/// 全局批处理存储
+pub static BATCHES: Lazy<BatchStore> = Lazy::new(BatchStore::default);
+
+/// 标记内部发起的批处理请求（auth 中间件据此按低优先级排队）
+#[derive(Debug, Clone, Copy)]
+pub struct BatchRequest;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    Failed,
+    InProgress,
+    Finalizing,
+    Completed,
+    Expired,
+    Cancelling,
+    Cancelled,
+}
+
+impl BatchStatus {
+    /// 仍需后台继续执行
+    pub fn is_active(&self) -> bool {
+        matches!(self, BatchStatus::InProgress | BatchStatus::Finalizing | BatchStatus::Cancelling)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RequestCounts {
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+}
+
+/// 输入文件中的格式错误，或导致批处理失败的原因
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchError {
+    pub code: String,
+    pub message: String,
+    /// 出错的行号（从 1 开始）
+    pub line: Option<usize>,
+}
+
+/// 批处理元数据
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Batch {
+    pub id: String,
+    pub endpoint: String,
+    pub input_file_id: String,
+    pub status: BatchStatus,
+    #[serde(default)]
+    pub output_file_id: Option<String>,
+    #[serde(default)]
+    pub error_file_id: Option<String>,
+    #[serde(default)]
+    pub errors: Vec<BatchError>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    #[serde(default)]
+    pub in_progress_at: Option<i64>,
+    #[serde(default)]
+    pub finalizing_at: Option<i64>,
+    #[serde(default)]
+    pub completed_at: Option<i64>,
+    #[serde(default)]
+    pub failed_at: Option<i64>,
+    #[serde(default)]
+    pub expired_at: Option<i64>,
+    #[serde(default)]
+    pub cancelling_at: Option<i64>,
+    #[serde(default)]
+    pub cancelled_at: Option<i64>,
+    pub request_counts: RequestCounts,
+    #[serde(default)]
+    pub metadata: Option<Value>,
+    /// 创建时使用的 API Key ID；未启用鉴权时为空
+    pub owner: Option<String>,
+    /// 创建请求的客户端地址，执行时沿用以通过 IP 规则
+    #[serde(default)]
+    pub client_ip: Option<std::net::IpAddr>,
+}
+
+impl Batch {
+    /// OpenAI Batch 对象
+    pub fn to_object(&self) -> Value {
+        let errors = (!self.errors.is_empty()).then(|| json!({ "object": "list", "data": self.errors }));
+        json!({
+            "id": self.id,
+            "object": "batch",
+            "endpoint": self.endpoint,
+            "errors": errors,
+            "input_file_id": self.input_file_id,
+            "completion_window": COMPLETION_WINDOW,
+            "status": self.status,
+            "output_file_id": self.output_file_id,
+            "error_file_id": self.error_file_id,
+            "created_at": self.created_at,
+            "in_progress_at": self.in_progress_at,
+            "expires_at": self.expires_at,
+            "finalizing_at": self.finalizing_at,
+            "completed_at": self.completed_at,
+            "failed_at": self.failed_at,
+            "expired_at": self.expired_at,
+            "cancelling_at": self.cancelling_at,
+            "cancelled_at": self.cancelled_at,
+            "request_counts": self.request_counts,
+            "metadata": self.metadata,
+        })
+    }
+}
+
+/// 输入文件中的一行请求
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchLine {
+    pub custom_id: String,
+    pub body: Value,
+}
+
+/// 校验批处理配置
+pub fn validate(config: &BatchConfig) -> Result<(), String> {
+    if config.max_requests == 0 {
+        return Err("max_requests must be greater than 0".to_string());
+    }
+    Ok(())
+}
+
+/// 批处理 ID 只能是 `batch_` 加 32 位十六进制，防止路径穿越
+fn is_valid_id(id: &str) -> bool {
+    id.strip_prefix(ID_PREFIX)
+        .is_some_and(|rest| rest.len() == 32 && rest.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn line_error(line: usize, message: impl Into<String>) -> BatchError {
+    BatchError {
+        code: "invalid_request".to_string(),
+        message: message.into(),
+        line: Some(line),
+    }
+}
+
+/// 解析 JSONL 输入：每行 `{"custom_id", "method": "POST", "url", "body"}`，`url` 必须与批处理的端点一致
+pub fn parse_input(data: &[u8], endpoint: &str, max_requests: usize) -> Result<Vec<BatchLine>, Vec<BatchError>> {
+    let text = std::str::from_utf8(data).map_err(|_| vec![line_error(1, "Input file is not valid UTF-8")])?;
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+    let mut seen = HashSet::new();
+    for (index, raw) in text.lines().enumerate().filter(|(_, raw)| !raw.trim().is_empty()) {
+        let line = index + 1;
+        let value: Value = match serde_json::from_str(raw) {
+            Ok(v) => v,
+            Err(e) => {
+                errors.push(line_error(line, format!("Invalid JSON: {}", e)));
+                continue;
+            }
+        };
+        let Some(custom_id) = value.get("custom_id").and_then(Value::as_str).filter(|id| !id.is_empty()) else {
+            errors.push(line_error(line, "Missing custom_id"));
+            continue;
+        };
+        if !seen.insert(custom_id.to_string()) {
+            errors.push(line_error(line, format!("Duplicate custom_id: {}", custom_id)));
+            continue;
+        }
+        if !value.get("method").and_then(Value::as_str).is_some_and(|m| m.eq_ignore_ascii_case("POST")) {
+            errors.push(line_error(line, "method must be POST"));
+            continue;
+        }
+        if value.get("url").and_then(Value::as_str) != Some(endpoint) {
+            errors.push(line_error(line, format!("url must match the batch endpoint {}", endpoint)));
+            continue;
+        }
+        let Some(body) = value.get("body").filter(|b| b.is_object()) else {
+            errors.push(line_error(line, "body must be a JSON object"));
+            continue;
+        };
+        lines.push(BatchLine { custom_id: custom_id.to_string(), body: body.clone() });
+    }
+    if lines.is_empty() && errors.is_empty() {
+        errors.push(line_error(1, "Input file contains no requests"));
+    }
+    if lines.len() > max_requests {
+        errors.push(BatchError {
+            code: "too_many_requests".to_string(),
+            message: format!("A batch can contain at most {} requests", max_requests),
+            line: None,
+        });
+    }
+    if errors.is_empty() {
+        Ok(lines)
+    } else {
+        Err(errors)
+    }
+}
+
+#[derive(Default)]
+pub struct BatchStore {
+    config: RwLock<BatchConfig>,
+    /// 串行化元数据的读改写（后台执行与取消请求可能同时更新）
+    write_lock: Mutex<()>,
+    /// 存储目录；为 None 时使用数据目录下的 batches/
+    dir: Option<PathBuf>,
+}
+
+impl BatchStore {
+    #[cfg(test)]
+    fn with_dir(dir: PathBuf) -> Self {
+        Self {
+            dir: Some(dir),
+            ..Self::default()
+        }
+    }
+
+    pub fn configure(&self, config: &BatchConfig) {
+        *self.config.write().unwrap() = config.clone();
+    }
+
+    pub fn config(&self) -> BatchConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    fn dir(&self) -> Result<PathBuf, String> {
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => crate::modules::account::get_data_dir()?.join(BATCHES_DIR),
+        };
+        std::fs::create_dir_all(&dir).map_err(|e| format!("failed to create batches directory: {}", e))?;
+        Ok(dir)
+    }
+
+    fn write(&self, batch: &Batch) -> Result<(), String> {
+        let meta = serde_json::to_vec(batch).map_err(|e| e.to_string())?;
+        std::fs::write(self.dir()?.join(format!("{}.json", batch.id)), meta)
+            .map_err(|e| format!("failed to store batch: {}", e))
+    }
+
+    /// 创建批处理；输入有格式错误时直接以 failed 状态保存
+    pub fn create(
+        &self,
+        owner: Option<&str>,
+        client_ip: Option<std::net::IpAddr>,
+        endpoint: &str,
+        input_file_id: &str,
+        metadata: Option<Value>,
+        parsed: &Result<Vec<BatchLine>, Vec<BatchError>>,
+    ) -> Result<Batch, String> {
+        let now = chrono::Utc::now().timestamp();
+        let mut batch = Batch {
+            id: format!("{}{}", ID_PREFIX, uuid::Uuid::new_v4().simple()),
+            endpoint: endpoint.to_string(),
+            input_file_id: input_file_id.to_string(),
+            status: BatchStatus::InProgress,
+            output_file_id: None,
+            error_file_id: None,
+            errors: Vec::new(),
+            created_at: now,
+            expires_at: now + COMPLETION_WINDOW_SECS,
+            in_progress_at: Some(now),
+            finalizing_at: None,
+            completed_at: None,
+            failed_at: None,
+            expired_at: None,
+            cancelling_at: None,
+            cancelled_at: None,
+            request_counts: RequestCounts::default(),
+            metadata,
+            owner: owner.map(str::to_string),
+            client_ip,
+        };
+        match parsed {
+            Ok(lines) => batch.request_counts.total = lines.len(),
+            Err(errors) => {
+                batch.status = BatchStatus::Failed;
+                batch.errors = errors.clone();
+                batch.in_progress_at = None;
+                batch.failed_at = Some(now);
+            }
+        }
+        self.write(&batch)?;
+        Ok(batch)
+    }
+
+    /// 按 ID 读取（不检查归属，供后台执行使用）
+    pub fn load(&self, id: &str) -> Result<Option<Batch>, String> {
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+        let meta = match std::fs::read(self.dir()?.join(format!("{}.json", id))) {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("failed to read batch: {}", e)),
+        };
+        serde_json::from_slice(&meta).map(Some).map_err(|e| format!("invalid batch metadata: {}", e))
+    }
+
+    /// 查询批处理；其他 API Key 创建的视为不存在
+    pub fn get(&self, owner: Option<&str>, id: &str) -> Result<Option<Batch>, String> {
+        Ok(self.load(id)?.filter(|b| b.owner.as_deref() == owner))
+    }
+
+    /// 列出该 API Key 的批处理，新创建的在前
+    pub fn list(&self, owner: Option<&str>) -> Result<Vec<Batch>, String> {
+        let mut batches: Vec<Batch> = self.all()?.into_iter().filter(|b| b.owner.as_deref() == owner).collect();
+        batches.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.id.cmp(&b.id)));
+        Ok(batches)
+    }
+
+    /// 需要继续执行的批处理（启动时恢复）
+    pub fn active(&self) -> Result<Vec<Batch>, String> {
+        Ok(self.all()?.into_iter().filter(|b| b.status.is_active()).collect())
+    }
+
+    fn all(&self) -> Result<Vec<Batch>, String> {
+        let entries = std::fs::read_dir(self.dir()?).map_err(|e| format!("failed to list batches: {}", e))?;
+        Ok(entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".json").map(str::to_string))
+            .filter_map(|id| self.load(&id).ok().flatten())
+            .collect())
+    }
+
+    /// 读取、修改并保存元数据
+    pub fn update(&self, id: &str, apply: impl FnOnce(&mut Batch)) -> Result<Option<Batch>, String> {
+        let _guard = self.write_lock.lock().unwrap();
+        let Some(mut batch) = self.load(id)? else {
+            return Ok(None);
+        };
+        apply(&mut batch);
+        self.write(&batch)?;
+        Ok(Some(batch))
+    }
+
+    /// 请求取消；进行中的批处理先进入 cancelling，由后台在当前请求结束后完成取消
+    pub fn cancel(&self, owner: Option<&str>, id: &str) -> Result<Option<Batch>, String> {
+        if self.get(owner, id)?.is_none() {
+            return Ok(None);
+        }
+        self.update(id, |batch| {
+            if batch.status == BatchStatus::InProgress {
+                batch.status = BatchStatus::Cancelling;
+                batch.cancelling_at = Some(chrono::Utc::now().timestamp());
+            }
+        })
+    }
+
+    fn progress_path(&self, id: &str, succeeded: bool) -> Result<PathBuf, String> {
+        let suffix = if succeeded { "output" } else { "errors" };
+        Ok(self.dir()?.join(format!("{}.{}.jsonl", id, suffix)))
+    }
+
+    /// 追加一条执行结果并更新计数
+    pub fn record_result(&self, id: &str, result: &Value, succeeded: bool) -> Result<(), String> {
+        let path = self.progress_path(id, succeeded)?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("failed to write batch results: {}", e))?;
+        writeln!(file, "{}", result).map_err(|e| format!("failed to write batch results: {}", e))?;
+        self.update(id, |batch| {
+            if succeeded {
+                batch.request_counts.completed += 1;
+            } else {
+                batch.request_counts.failed += 1;
+            }
+        })?;
+        Ok(())
+    }
+
+    /// 已有结果的 custom_id（重启后跳过）
+    pub fn finished_ids(&self, id: &str) -> Result<HashSet<String>, String> {
+        let (output, errors) = self.read_results(id)?;
+        Ok([output, errors]
+            .iter()
+            .flat_map(|data| String::from_utf8_lossy(data).lines().map(str::to_string).collect::<Vec<_>>())
+            .filter_map(|line| serde_json::from_str::<Value>(&line).ok())
+            .filter_map(|v| v.get("custom_id").and_then(Value::as_str).map(str::to_string))
+            .collect())
+    }
+
+    /// 累积的成功 / 失败结果
+    pub fn read_results(&self, id: &str) -> Result<(Vec<u8>, Vec<u8>), String> {
+        let read = |succeeded| -> Result<Vec<u8>, String> {
+            match std::fs::read(self.progress_path(id, succeeded)?) {
+                Ok(data) => Ok(data),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+                Err(e) => Err(format!("failed to read batch results: {}", e)),
+            }
+        };
+        Ok((read(true)?, read(false)?))
+    }
+
+    /// 结果已转存为文件后删除进度文件
+    pub fn clear_results(&self, id: &str) {
+        for succeeded in [true, false] {
+            if let Ok(path) = self.progress_path(id, succeeded) {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENDPOINT: &str = "/v1/chat/completions";
+
+    fn request_line(custom_id: &str) -> String {
+        json!({
+            "custom_id": custom_id,
+            "method": "POST",
+            "url": ENDPOINT,
+            "body": { "model": "gemini-2.5-flash", "messages": [{ "role": "user", "content": "hi" }] }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_parse_input() {
+        let input = format!("{}\n\n{}\n", request_line("a"), request_line("b"));
+        let lines = parse_input(input.as_bytes(), ENDPOINT, 10).unwrap();
+        assert_eq!(lines.iter().map(|l| l.custom_id.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+
+        let input = [
+            request_line("a"),
+            request_line("a"),
+            "not json".to_string(),
+            request_line("c").replace(ENDPOINT, "/v1/embeddings"),
+        ]
+        .join("\n");
+        let errors = parse_input(input.as_bytes(), ENDPOINT, 10).unwrap_err();
+        assert_eq!(errors.iter().map(|e| e.line).collect::<Vec<_>>(), vec![Some(2), Some(3), Some(4)]);
+
+        let errors = parse_input(request_line("a").as_bytes(), ENDPOINT, 0).unwrap_err();
+        assert_eq!(errors[0].code, "too_many_requests");
+        assert!(parse_input(b"", ENDPOINT, 10).is_err());
+    }
+
+    #[test]
+    fn test_batch_lifecycle() {
+        let dir = std::env::temp_dir().join(format!("antiproxy-batches-{}", uuid::Uuid::new_v4().simple()));
+        let store = BatchStore::with_dir(dir.clone());
+        let parsed = parse_input(request_line("a").as_bytes(), ENDPOINT, 10);
+        let batch = store.create(Some("key-a"), None, ENDPOINT, "file-x", None, &parsed).unwrap();
+        assert!(is_valid_id(&batch.id));
+        assert_eq!(batch.request_counts.total, 1);
+        assert!(store.get(Some("key-b"), &batch.id).unwrap().is_none());
+        assert_eq!(store.active().unwrap().len(), 1);
+
+        store.record_result(&batch.id, &json!({ "custom_id": "a" }), true).unwrap();
+        assert!(store.finished_ids(&batch.id).unwrap().contains("a"));
+        let cancelled = store.cancel(Some("key-a"), &batch.id).unwrap().unwrap();
+        assert_eq!(cancelled.status, BatchStatus::Cancelling);
+        assert_eq!(cancelled.request_counts.completed, 1);
+
+        let (output, errors) = store.read_results(&batch.id).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "{\"custom_id\":\"a\"}\n");
+        assert!(errors.is_empty());
+        store.clear_results(&batch.id);
+        assert!(store.finished_ids(&batch.id).unwrap().is_empty());
+
+        // 格式错误的输入直接失败
+        let failed = store.create(None, None, ENDPOINT, "file-x", None, &parse_input(b"{}", ENDPOINT, 10)).unwrap();
+        assert_eq!(failed.status, BatchStatus::Failed);
+        assert_eq!(failed.to_object()["errors"]["data"][0]["line"], 1);
+        assert_eq!(store.list(None).unwrap().len(), 1);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
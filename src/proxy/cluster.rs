@@ -0,0 +1,284 @@
+// 多实例协调
+// 负载均衡后的多个实例各自维护账号状态；这里让它们按账号分片错开使用同一账号，
+// 并在账号触发 429 时把冷却状态推送给其他实例（POST /cluster/cooldown，共享密钥鉴权）；
+// API Key 的 RPM / RPD 计数增量每秒、用量增量每次落盘后推送给其他实例（POST /cluster/sync），
+// 限流与每日 token 预算按所有实例的合计计算。各实例须使用相同的 API Key（按 Key ID 对应）
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use crate::modules::usage_buffer::UsageDelta;
+use crate::proxy::config::ClusterConfig;
+use crate::proxy::key_rate_limit::{KeyCounts, KEY_RATE_LIMITER};
+use crate::proxy::rate_limit::RateLimitReason;
+use crate::proxy::server::AppState;
+
+/// 实例间通知的请求路径
+pub const COOLDOWN_PATH: &str = "/cluster/cooldown";
+/// 实例间同步 API Key 计数与用量的请求路径
+pub const SYNC_PATH: &str = "/cluster/sync";
+
+/// 单次推送超时
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Key 限流计数的推送间隔
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 全局集群配置（启动 / 热重载时由配置写入）
+pub static CLUSTER: Lazy<Cluster> = Lazy::new(Cluster::default);
+
+/// 推送给其他实例的冷却通知
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CooldownNotice {
+    pub quota_group: String,
+    pub account_id: String,
+    /// 冷却结束时间（Unix 秒）
+    pub until: i64,
+    pub reason: String,
+}
+
+/// 推送给其他实例的 API Key 计数与用量增量
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncNotice {
+    /// RPM / RPD 计数增量
+    #[serde(default)]
+    pub rate_limits: Vec<KeyCounts>,
+    /// 已在发送方落盘的用量增量（key_id -> 增量），接收方直接写入本地数据库
+    #[serde(default)]
+    pub usage: HashMap<String, UsageDelta>,
+}
+
+/// 是否为实例间通知的路径（由共享密钥鉴权，不走 API Key / Web 登录）
+pub fn is_peer_path(path: &str) -> bool {
+    path == COOLDOWN_PATH || path == SYNC_PATH
+}
+
+/// 校验集群配置：`node_index` 必须在 0 到 peers 数量之间
+pub fn validate(config: &ClusterConfig) -> Result<(), String> {
+    if config.node_index > config.peers.len() {
+        return Err(format!(
+            "node_index {} is out of range: with {} peer(s) it must be between 0 and {}",
+            config.node_index,
+            config.peers.len(),
+            config.peers.len()
+        ));
+    }
+    if let Some(peer) = config.peers.iter().find(|p| !p.starts_with("http://") && !p.starts_with("https://")) {
+        return Err(format!("peer {:?} must start with http:// or https://", peer));
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct Cluster {
+    config: RwLock<ClusterConfig>,
+}
+
+/// 账号所属分片：对账号 ID 做稳定哈希，各实例结果一致
+fn shard_of(account_id: &str, nodes: usize) -> usize {
+    let digest = Sha256::digest(account_id.as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(bytes) % nodes as u64) as usize
+}
+
+impl Cluster {
+    pub fn configure(&self, config: &ClusterConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config.clone();
+    }
+
+    fn config(&self) -> ClusterConfig {
+        self.config.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn enabled(config: &ClusterConfig) -> bool {
+        !config.peers.is_empty() && config.secret.as_deref().is_some_and(|s| !s.is_empty())
+    }
+
+    /// 账号是否分给本实例；未启用集群时所有账号都属于本实例
+    pub fn owns(&self, account_id: &str) -> bool {
+        let config = self.config.read().unwrap_or_else(|e| e.into_inner());
+        if !Self::enabled(&config) {
+            return true;
+        }
+        shard_of(account_id, config.peers.len() + 1) == config.node_index
+    }
+
+    /// 在后台把冷却状态推送给所有其他实例，失败只记录日志
+    pub fn broadcast_cooldown(&self, notice: CooldownNotice) {
+        self.broadcast(COOLDOWN_PATH, notice);
+    }
+
+    /// 把已落盘的 API Key 用量增量推送给所有其他实例
+    pub fn broadcast_usage(&self, usage: &HashMap<String, UsageDelta>) {
+        // 共享 PostgreSQL 存储时各实例已写入同一份用量，再广播会重复计数
+        if crate::modules::storage::is_shared()
+            || !Self::enabled(&self.config.read().unwrap_or_else(|e| e.into_inner()))
+        {
+            return;
+        }
+        self.broadcast(SYNC_PATH, SyncNotice { usage: usage.clone(), ..Default::default() });
+    }
+
+    /// 启动后台同步任务：定期把本实例新增的 Key 限流计数推送给其他实例
+    pub fn spawn_sync_task(&'static self) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(SYNC_INTERVAL);
+            loop {
+                ticker.tick().await;
+                // 未启用集群时也取出计数，避免增量无限累积
+                let rate_limits = KEY_RATE_LIMITER.take_unsynced();
+                if !rate_limits.is_empty() {
+                    self.broadcast(SYNC_PATH, SyncNotice { rate_limits, ..Default::default() });
+                }
+            }
+        });
+    }
+
+    /// 在后台把通知推送给所有其他实例，失败只记录日志
+    fn broadcast<T: Serialize + Clone + Send + 'static>(&self, path: &'static str, notice: T) {
+        let config = self.config();
+        if !Self::enabled(&config) {
+            return;
+        }
+        let secret = config.secret.unwrap_or_default();
+        let client = match reqwest::Client::builder().timeout(NOTIFY_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("[Cluster] Failed to build HTTP client: {}", e);
+                return;
+            }
+        };
+        for peer in config.peers {
+            let (client, secret, notice) = (client.clone(), secret.clone(), notice.clone());
+            tokio::spawn(async move {
+                let url = format!("{}{}", peer.trim_end_matches('/'), path);
+                let result = client.post(&url).bearer_auth(secret).json(&notice).send().await;
+                match result {
+                    Ok(response) if response.status().is_success() => {}
+                    Ok(response) => tracing::warn!("[Cluster] Peer {} rejected {}: HTTP {}", peer, path, response.status()),
+                    Err(e) => tracing::warn!("[Cluster] Failed to notify peer {}: {}", peer, e),
+                }
+            });
+        }
+    }
+
+    /// 校验实例间请求携带的共享密钥（比较两者的摘要，耗时与内容无关）
+    fn verify(&self, headers: &HeaderMap) -> bool {
+        let config = self.config.read().unwrap_or_else(|e| e.into_inner());
+        let Some(secret) = config.secret.as_deref().filter(|s| !s.is_empty()) else {
+            return false;
+        };
+        let presented = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        let (presented, expected) = (Sha256::digest(presented.as_bytes()), Sha256::digest(secret.as_bytes()));
+        presented.iter().zip(expected.iter()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+/// 接收其他实例推送的冷却通知
+///
+/// POST /cluster/cooldown
+pub async fn handle_cooldown(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(notice): Json<CooldownNotice>,
+) -> Response {
+    if !CLUSTER.verify(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let applied = state.token_manager.apply_peer_cooldown(
+        &notice.quota_group,
+        &notice.account_id,
+        notice.until,
+        RateLimitReason::parse(&notice.reason),
+    );
+    Json(serde_json::json!({ "applied": applied })).into_response()
+}
+
+/// 接收其他实例推送的 API Key 计数与用量增量
+///
+/// POST /cluster/sync
+pub async fn handle_sync(headers: HeaderMap, Json(notice): Json<SyncNotice>) -> Response {
+    if !CLUSTER.verify(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    for counts in &notice.rate_limits {
+        KEY_RATE_LIMITER.apply_peer(counts);
+    }
+    if !notice.usage.is_empty() {
+        let usage = notice.usage.clone();
+        let result = tokio::task::spawn_blocking(move || crate::modules::api_keys::apply_usage_deltas(&usage))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r);
+        if let Err(e) = result {
+            tracing::error!("[Cluster] Failed to apply peer usage: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+    }
+    Json(serde_json::json!({
+        "rate_limits": notice.rate_limits.len(),
+        "usage": notice.usage.len(),
+    }))
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shards_partition_accounts() {
+        let cluster = Cluster::default();
+        let ids: Vec<String> = (0..50).map(|i| format!("account-{}", i)).collect();
+        assert!(ids.iter().all(|id| cluster.owns(id)));
+
+        let mut owners = vec![0; ids.len()];
+        for node_index in 0..3 {
+            cluster.configure(&ClusterConfig {
+                node_index,
+                peers: vec!["http://a".to_string(), "http://b".to_string()],
+                secret: Some("s3cret".to_string()),
+            });
+            for (i, id) in ids.iter().enumerate() {
+                owners[i] += cluster.owns(id) as usize;
+            }
+        }
+        // 每个账号恰好属于一个实例
+        assert!(owners.iter().all(|&n| n == 1));
+
+        let mut headers = HeaderMap::new();
+        assert!(!cluster.verify(&headers));
+        headers.insert(header::AUTHORIZATION, "Bearer s3cret".parse().unwrap());
+        assert!(cluster.verify(&headers));
+    }
+
+    #[test]
+    fn test_validate_node_index() {
+        let mut config = ClusterConfig {
+            node_index: 2,
+            peers: vec!["http://a".to_string(), "https://b".to_string()],
+            secret: Some("s3cret".to_string()),
+        };
+        assert!(validate(&config).is_ok());
+        config.node_index = 3;
+        assert!(validate(&config).unwrap_err().contains("node_index 3"));
+        config.node_index = 0;
+        config.peers.push("10.0.0.3:8045".to_string());
+        assert!(validate(&config).is_err());
+        assert!(validate(&ClusterConfig::default()).is_ok());
+    }
+}
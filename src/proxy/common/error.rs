@@ -1,6 +1,58 @@
 // 错误处理
+// 代理自身产生的错误统一使用 OpenAI 错误格式：`{"error": {"message", "type", "code", "param"}}`，
+// `type` 由状态码决定，`code` 标识具体原因
 use thiserror::Error;
-use axum::{http::StatusCode, Json, response::IntoResponse};
+use axum::{http::StatusCode, Json, response::{IntoResponse, Response}};
+use serde_json::{json, Value};
+
+/// 状态码对应的 OpenAI 错误类型
+pub fn error_type(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::UNAUTHORIZED => "authentication_error",
+        StatusCode::FORBIDDEN => "permission_error",
+        StatusCode::NOT_FOUND => "not_found_error",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+        StatusCode::GATEWAY_TIMEOUT => "timeout_error",
+        s if s.is_server_error() => "server_error",
+        _ => "invalid_request_error",
+    }
+}
+
+/// 处理器未指明原因时按状态码使用的错误代码
+pub fn default_code(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => "invalid_request",
+        StatusCode::UNAUTHORIZED => "invalid_api_key",
+        StatusCode::FORBIDDEN => "permission_denied",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::PAYLOAD_TOO_LARGE => "request_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limit_exceeded",
+        StatusCode::BAD_GATEWAY => "upstream_error",
+        StatusCode::SERVICE_UNAVAILABLE => "service_unavailable",
+        StatusCode::GATEWAY_TIMEOUT => "upstream_timeout",
+        s if s.is_server_error() => "internal_error",
+        _ => "request_failed",
+    }
+}
+
+/// OpenAI 格式的错误体；需要附加字段（如预算详情）时在 `error` 对象上补充
+pub fn error_body(status: StatusCode, code: &str, message: impl Into<String>) -> Value {
+    json!({
+        "error": {
+            "message": message.into(),
+            "type": error_type(status),
+            "code": code,
+            "param": null
+        }
+    })
+}
+
+/// OpenAI 格式的错误响应
+pub fn error_response(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    (status, Json(error_body(status, code, message))).into_response()
+}
 
 #[derive(Debug, Error)]
 pub enum ProxyError {
@@ -29,13 +81,6 @@ impl IntoResponse for ProxyError {
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
-        let body = serde_json::json!({
-            "error": {
-                "message": self.to_string(),
-                "type": format!("{:?}", self)
-            }
-        });
-
-        (status, Json(body)).into_response()
+        error_response(status, default_code(status), self.to_string())
     }
 }
@@ -0,0 +1,138 @@
+// 图片输入处理
+// 校验 data URL / 下载远程图片，按文件头识别 MIME 类型，并限制单张图片大小
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures::StreamExt;
+
+/// 单张图片大小上限（与上游 inlineData 限制一致）
+pub const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+/// 下载远程图片的超时时间（秒）
+const FETCH_TIMEOUT_SECS: u64 = 30;
+
+/// 根据文件头识别图片 MIME 类型
+pub fn sniff_mime(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" && matches!(&bytes[8..12], b"heic" | b"heix" | b"mif1") {
+        Some("image/heic")
+    } else {
+        None
+    }
+}
+
+/// 校验图片数据并确定 MIME 类型：优先使用文件头识别结果，其次使用声明的类型
+fn resolve_mime(bytes: &[u8], declared: Option<&str>) -> Result<String, String> {
+    if bytes.len() > MAX_IMAGE_BYTES {
+        return Err(format!(
+            "Image is {} bytes, exceeds the {} byte limit",
+            bytes.len(),
+            MAX_IMAGE_BYTES
+        ));
+    }
+    if let Some(mime) = sniff_mime(bytes) {
+        return Ok(mime.to_string());
+    }
+    match declared.map(|m| m.trim().to_ascii_lowercase()) {
+        Some(mime) if mime.starts_with("image/") => Ok(mime),
+        _ => Err("Unsupported image format".to_string()),
+    }
+}
+
+/// 解析 `data:<mime>;base64,<data>`，返回校验后的 (MIME, base64 数据)
+pub fn parse_data_url(url: &str) -> Result<(String, String), String> {
+    let rest = url
+        .strip_prefix("data:")
+        .ok_or_else(|| "Not a data URL".to_string())?;
+    let (meta, data) = rest
+        .split_once(',')
+        .ok_or_else(|| "Invalid data URL: missing ','".to_string())?;
+    if !meta.split(';').any(|p| p.eq_ignore_ascii_case("base64")) {
+        return Err("Invalid data URL: only base64 encoded images are supported".to_string());
+    }
+    let declared = meta.split(';').next().filter(|m| !m.is_empty());
+
+    // 部分客户端会在 base64 中插入换行
+    let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+    if data.len() / 4 * 3 > MAX_IMAGE_BYTES + 3 {
+        return Err(format!("Image exceeds the {} byte limit", MAX_IMAGE_BYTES));
+    }
+    let bytes = STANDARD
+        .decode(&data)
+        .map_err(|e| format!("Invalid base64 image data: {}", e))?;
+    let mime = resolve_mime(&bytes, declared)?;
+    Ok((mime, data))
+}
+
+/// 下载远程图片，返回 (MIME, base64 数据)
+pub async fn fetch_image(url: &str) -> Result<(String, String), String> {
+    let client = crate::utils::http::create_client(FETCH_TIMEOUT_SECS);
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch image {}: {}", url, e))?;
+    if !response.status().is_success() {
+        return Err(format!("Failed to fetch image {}: HTTP {}", url, response.status()));
+    }
+    if response.content_length().is_some_and(|len| len as usize > MAX_IMAGE_BYTES) {
+        return Err(format!("Image {} exceeds the {} byte limit", url, MAX_IMAGE_BYTES));
+    }
+    let declared = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or(v).to_string());
+
+    // 边下载边计数，避免未声明 Content-Length 的超大响应占满内存
+    let mut bytes = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| format!("Failed to fetch image {}: {}", url, e))?;
+        if bytes.len() + chunk.len() > MAX_IMAGE_BYTES {
+            return Err(format!("Image {} exceeds the {} byte limit", url, MAX_IMAGE_BYTES));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    let mime = resolve_mime(&bytes, declared.as_deref())?;
+    Ok((mime, STANDARD.encode(&bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_1X1: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mP8z8BQDwAEhQGAhKmMIQAAAABJRU5ErkJggg==";
+
+    #[test]
+    fn test_sniff_mime() {
+        assert_eq!(sniff_mime(&STANDARD.decode(PNG_1X1).unwrap()), Some("image/png"));
+        assert_eq!(sniff_mime(b"\xff\xd8\xff\xe0rest"), Some("image/jpeg"));
+        assert_eq!(sniff_mime(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff_mime(b"plain text"), None);
+    }
+
+    #[test]
+    fn test_parse_data_url() {
+        // 声明类型错误时以文件头为准
+        let (mime, data) = parse_data_url(&format!("data:image/jpeg;base64,{}", PNG_1X1)).unwrap();
+        assert_eq!(mime, "image/png");
+        assert_eq!(data, PNG_1X1);
+
+        // 无法识别文件头时回退到声明的图片类型
+        let avif = STANDARD.encode(b"not-sniffable");
+        assert_eq!(parse_data_url(&format!("data:image/avif;base64,{}", avif)).unwrap().0, "image/avif");
+        assert!(parse_data_url(&format!("data:text/plain;base64,{}", avif)).is_err());
+
+        assert!(parse_data_url("data:image/png,rawdata").is_err());
+        assert!(parse_data_url("data:image/png;base64,@@@").is_err());
+
+        let oversized = STANDARD.encode(vec![0u8; MAX_IMAGE_BYTES + 1]);
+        assert!(parse_data_url(&format!("data:image/png;base64,{}", oversized)).is_err());
+    }
+}
@@ -1,7 +1,11 @@
 // Common 模块 - 公共工具
 
-// pub mod error;
+pub mod error;
 // pub mod rate_limiter;
 pub mod model_mapping;
 pub mod utils;
 pub mod json_schema;
+pub mod sse;
+pub mod image;
+pub mod token_count;
+pub mod usage;
@@ -134,6 +134,9 @@ pub async fn get_all_dynamic_models(
         }
     }
 
+    // 7. Add model aliases
+    model_ids.extend(crate::modules::model_aliases::alias_names());
+
     let mut sorted_ids: Vec<_> = model_ids.into_iter().collect();
     sorted_ids.sort();
     sorted_ids
@@ -0,0 +1,88 @@
+// SSE 心跳
+// 上游长时间无输出（如模型思考阶段）时向客户端发送 `: ping` 注释帧，避免中间代理 / 客户端因空闲超时断开连接
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// 心跳帧（SSE 注释行，客户端会忽略）
+pub const KEEPALIVE_FRAME: &[u8] = b": ping\n\n";
+
+/// 心跳间隔（秒），0 表示关闭；启动 / 热重载时由配置写入
+static KEEPALIVE_INTERVAL_SECS: AtomicU64 = AtomicU64::new(crate::proxy::config::DEFAULT_SSE_KEEPALIVE_SECONDS);
+
+pub fn set_keepalive_interval(seconds: u64) {
+    KEEPALIVE_INTERVAL_SECS.store(seconds, Ordering::Relaxed);
+}
+
+/// 为 SSE 流注入心跳：距上一个数据块超过间隔时插入一个 `: ping` 帧
+///
+/// 心跳只会插在两个数据块之间，转换器输出的每个数据块都是完整事件，不会截断事件
+pub fn with_keepalive<S, E>(stream: S) -> impl Stream<Item = Result<Bytes, E>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    with_keepalive_interval(stream, Duration::from_secs(KEEPALIVE_INTERVAL_SECS.load(Ordering::Relaxed)))
+}
+
+fn with_keepalive_interval<S, E>(stream: S, interval: Duration) -> impl Stream<Item = Result<Bytes, E>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    async_stream::stream! {
+        let mut stream = Box::pin(stream);
+        loop {
+            let next = if interval.is_zero() {
+                stream.next().await
+            } else {
+                match tokio::time::timeout(interval, stream.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        yield Ok(Bytes::from_static(KEEPALIVE_FRAME));
+                        continue;
+                    }
+                }
+            };
+            match next {
+                Some(item) => yield item,
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keepalive_injected_between_chunks() {
+        let upstream = async_stream::stream! {
+            yield Ok::<_, String>(Bytes::from_static(b"data: 1\n\n"));
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            yield Ok(Bytes::from_static(b"data: 2\n\n"));
+        };
+        let chunks: Vec<Bytes> = with_keepalive_interval(upstream, Duration::from_millis(100))
+            .map(|c| c.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(chunks.first().unwrap(), "data: 1\n\n");
+        assert_eq!(chunks.last().unwrap(), "data: 2\n\n");
+        let pings = &chunks[1..chunks.len() - 1];
+        assert!(!pings.is_empty() && pings.iter().all(|c| c.as_ref() == KEEPALIVE_FRAME));
+        assert_ne!(chunks.last().unwrap().as_ref(), KEEPALIVE_FRAME);
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_disabled() {
+        let upstream = async_stream::stream! {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            yield Ok::<_, String>(Bytes::from_static(b"data: 1\n\n"));
+        };
+        let chunks: Vec<_> = with_keepalive_interval(upstream, Duration::ZERO).collect().await;
+        assert_eq!(chunks.len(), 1);
+    }
+}
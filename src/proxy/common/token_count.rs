@@ -0,0 +1,159 @@
+// 本地 token 估算
+// 上游 countTokens 不可用时的兜底，以及转发前的每日预算预检（不消耗上游配额）
+use serde_json::{Map, Value};
+
+/// 每条消息的固定开销
+pub const MESSAGE_OVERHEAD_TOKENS: u32 = 3;
+/// 单张图片的估算值
+pub const IMAGE_BLOCK_TOKENS: u32 = 256;
+/// 单个文档的估算值
+pub const DOCUMENT_BLOCK_TOKENS: u32 = 1024;
+
+/// 不参与估算的字段（标识符 / 枚举值 / 生成参数）
+const SKIPPED_KEYS: &[&str] = &[
+    "model",
+    "role",
+    "type",
+    "id",
+    "call_id",
+    "tool_call_id",
+    "tool_use_id",
+    "mimeType",
+    "mime_type",
+    "media_type",
+    "user",
+    "metadata",
+    "stream",
+    "stream_options",
+    "tool_choice",
+    "toolConfig",
+    "generationConfig",
+    "generation_config",
+    "safetySettings",
+    "response_format",
+    "cache_control",
+];
+
+/// 按字符粗略估算：ASCII 约 4 字符 1 token，非 ASCII（中日韩等）按 1 字符 1 token
+pub fn estimate_text_tokens(text: &str) -> u32 {
+    let mut ascii_chars = 0u32;
+    let mut non_ascii_chars = 0u32;
+
+    for ch in text.chars() {
+        if ch.is_ascii() {
+            ascii_chars += 1;
+        } else {
+            non_ascii_chars += 1;
+        }
+    }
+
+    ascii_chars.div_ceil(4) + non_ascii_chars
+}
+
+/// 估算任意协议（OpenAI / Anthropic / Gemini）请求体的输入 token 数
+pub fn estimate_body_tokens(body: &Value) -> u32 {
+    estimate_value(body, None)
+}
+
+fn estimate_value(value: &Value, key: Option<&str>) -> u32 {
+    match value {
+        Value::String(text) => estimate_text_tokens(text),
+        Value::Array(items) => {
+            let overhead = if matches!(key, Some("messages" | "contents" | "input")) {
+                MESSAGE_OVERHEAD_TOKENS.saturating_mul(items.len() as u32)
+            } else {
+                0
+            };
+            items
+                .iter()
+                .map(|item| estimate_value(item, None))
+                .fold(overhead, u32::saturating_add)
+        }
+        Value::Object(map) => estimate_object(map),
+        _ => 0,
+    }
+}
+
+fn estimate_object(map: &Map<String, Value>) -> u32 {
+    // 图片 / 文档按固定值计，避免把 base64 数据当作文本
+    if ["inlineData", "inline_data", "fileData", "file_data", "image_url"]
+        .iter()
+        .any(|k| map.contains_key(*k))
+    {
+        return IMAGE_BLOCK_TOKENS;
+    }
+    match map.get("type").and_then(|t| t.as_str()) {
+        Some("image" | "input_image") => return IMAGE_BLOCK_TOKENS,
+        Some("document") => return DOCUMENT_BLOCK_TOKENS,
+        _ => {}
+    }
+    map.iter()
+        .filter(|(k, _)| !SKIPPED_KEYS.contains(&k.as_str()))
+        .map(|(k, v)| estimate_value(v, Some(k)))
+        .fold(0, u32::saturating_add)
+}
+
+/// 从上游 countTokens 响应中提取 token 数
+pub fn extract_total_tokens(value: &Value) -> Option<u32> {
+    let raw = value.get("response").unwrap_or(value);
+
+    let total = raw
+        .get("totalTokens")
+        .or_else(|| raw.get("total_tokens"))
+        .or_else(|| raw.get("tokenCount"))
+        .and_then(|v| v.as_u64())
+        .and_then(|v| u32::try_from(v).ok());
+
+    if total.is_some() {
+        return total;
+    }
+
+    let usage = raw.get("usageMetadata")?;
+    usage
+        .get("promptTokenCount")
+        .or_else(|| usage.get("totalTokenCount"))
+        .and_then(|v| v.as_u64())
+        .and_then(|v| u32::try_from(v).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_estimate_text_tokens() {
+        assert_eq!(estimate_text_tokens(""), 0);
+        assert_eq!(estimate_text_tokens("abcd"), 1);
+        assert_eq!(estimate_text_tokens("abcde"), 2);
+        assert_eq!(estimate_text_tokens("你好"), 2);
+    }
+
+    #[test]
+    fn test_estimate_body_tokens_across_protocols() {
+        let openai = json!({
+            "model": "gpt-4o-with-a-very-long-model-name",
+            "messages": [
+                {"role": "system", "content": "abcdefgh"},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "abcd"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+                ]}
+            ]
+        });
+        assert_eq!(estimate_body_tokens(&openai), 2 * MESSAGE_OVERHEAD_TOKENS + 2 + 1 + IMAGE_BLOCK_TOKENS);
+
+        let gemini = json!({
+            "contents": [{"role": "user", "parts": [{"text": "abcd"}, {"inlineData": {"mimeType": "image/png", "data": "QUFB".repeat(1000)}}]}],
+            "generationConfig": {"responseMimeType": "application/json"}
+        });
+        assert_eq!(estimate_body_tokens(&gemini), MESSAGE_OVERHEAD_TOKENS + 1 + IMAGE_BLOCK_TOKENS);
+    }
+
+    #[test]
+    fn test_extract_total_tokens() {
+        assert_eq!(extract_total_tokens(&json!({"response": {"totalTokens": 42}})), Some(42));
+        assert_eq!(extract_total_tokens(&json!({"usageMetadata": {"promptTokenCount": 7}})), Some(7));
+        assert_eq!(extract_total_tokens(&json!({})), None);
+    }
+}
@@ -0,0 +1,343 @@
+// 响应用量提取
+// 增量解析 SSE：每个 `data:` 事件经过时即解析，跨数据块边界拼接，不依赖流末尾的缓冲区；
+// 兼容 OpenAI Chat / Responses、Anthropic Messages 与 Gemini 的用量和结束原因字段；
+// 同时按字符估算生成内容的 token 数，上游流未返回用量时用于补齐
+use serde_json::Value;
+
+use crate::proxy::common::token_count::{estimate_body_tokens, estimate_text_tokens};
+
+/// 单个事件的最大长度，超出的事件直接丢弃（如内联图片的超长 data 行）
+const MAX_EVENT_BYTES: usize = 8 * 1024 * 1024;
+
+/// 承载生成内容的字段（各协议的文本 / 思考 / 工具参数增量）
+const OUTPUT_TEXT_KEYS: &[&str] = &["content", "text", "thinking", "reasoning_content", "partial_json", "arguments", "delta", "refusal"];
+
+/// 从响应中提取的用量
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResponseUsage {
+    pub input_tokens: Option<u32>,
+    pub output_tokens: Option<u32>,
+    /// 结束原因（`stop` / `end_turn` / `STOP` / `length` 等，按上游原样保留）
+    pub finish_reason: Option<String>,
+    /// token 数中有本地估算值（上游未返回用量）
+    pub estimated: bool,
+    /// 仅有总量时的兜底值
+    total_tokens: Option<u32>,
+    /// 流中生成内容的估算 token 数
+    output_estimate: u32,
+}
+
+fn as_u32(value: Option<&Value>) -> Option<u32> {
+    value.and_then(|v| v.as_u64()).and_then(|v| u32::try_from(v).ok())
+}
+
+fn first_str(value: Option<&Value>) -> Option<String> {
+    value.and_then(|v| v.as_str()).filter(|s| !s.is_empty()).map(String::from)
+}
+
+impl ResponseUsage {
+    /// 从非流式 JSON 响应提取
+    pub fn from_json(json: &Value) -> Self {
+        let mut usage = Self::default();
+        usage.merge_json(json);
+        usage.finish()
+    }
+
+    /// 合并一个 JSON 对象（完整响应或一个 SSE 事件）中的用量；后出现的字段覆盖先前的值
+    pub fn merge_json(&mut self, json: &Value) {
+        // 上游 v1internal 响应包裹在 `response` 中；Responses API 的 response.* 事件同样如此
+        let roots = [Some(json), json.get("response"), json.get("message")];
+        for root in roots.into_iter().flatten() {
+            if let Some(usage) = root.get("usage").filter(|u| u.is_object()) {
+                self.merge_counts(
+                    as_u32(usage.get("prompt_tokens").or(usage.get("input_tokens"))),
+                    as_u32(usage.get("completion_tokens").or(usage.get("output_tokens"))),
+                    as_u32(usage.get("total_tokens")),
+                );
+            }
+            if let Some(usage) = root.get("usageMetadata") {
+                self.merge_counts(
+                    as_u32(usage.get("promptTokenCount")),
+                    as_u32(usage.get("candidatesTokenCount")),
+                    as_u32(usage.get("totalTokenCount")),
+                );
+            }
+            self.merge_finish_reason(root);
+        }
+    }
+
+    fn merge_counts(&mut self, input: Option<u32>, output: Option<u32>, total: Option<u32>) {
+        self.input_tokens = input.or(self.input_tokens);
+        self.output_tokens = output.or(self.output_tokens);
+        self.total_tokens = total.or(self.total_tokens);
+    }
+
+    fn merge_finish_reason(&mut self, root: &Value) {
+        let reason = root
+            .get("choices")
+            .and_then(|c| c.get(0))
+            .and_then(|c| first_str(c.get("finish_reason")))
+            .or_else(|| root.get("candidates").and_then(|c| c.get(0)).and_then(|c| first_str(c.get("finishReason"))))
+            // Anthropic: message_delta.delta.stop_reason / 非流式 stop_reason
+            .or_else(|| root.get("delta").and_then(|d| first_str(d.get("stop_reason"))))
+            .or_else(|| first_str(root.get("stop_reason")))
+            // Responses API: response.completed / response.incomplete
+            .or_else(|| {
+                root.get("incomplete_details")
+                    .and_then(|d| first_str(d.get("reason")))
+                    .or_else(|| first_str(root.get("status")).filter(|s| s != "in_progress" && s != "queued"))
+                    .filter(|_| root.get("object").and_then(|o| o.as_str()) == Some("response"))
+            });
+        if reason.is_some() {
+            self.finish_reason = reason;
+        }
+    }
+
+    /// 没有分项计数时使用总量作为输出 token
+    fn finish(mut self) -> Self {
+        if self.input_tokens.is_none() && self.output_tokens.is_none() {
+            self.output_tokens = self.total_tokens;
+        }
+        self
+    }
+
+    /// 用本地估算补齐上游没有返回的计数：输入按请求体估算，输出按流中的生成内容估算
+    pub fn fill_estimates(mut self, request_body: Option<&[u8]>) -> Self {
+        if self.input_tokens.is_none() {
+            let estimate = request_body
+                .and_then(|body| serde_json::from_slice::<Value>(body).ok())
+                .map(|body| estimate_body_tokens(&body));
+            if estimate.is_some() {
+                self.input_tokens = estimate;
+                self.estimated = true;
+            }
+        }
+        if self.output_tokens.is_none() {
+            self.output_tokens = Some(self.output_estimate);
+            self.estimated = true;
+        }
+        self
+    }
+}
+
+/// 单个 SSE 事件中生成内容的估算 token 数
+fn estimate_event_output(json: &Value) -> u32 {
+    // Responses API 的 *.done / response.completed 等事件重复完整输出，只统计增量事件
+    if let Some(kind) = json.get("type").and_then(|t| t.as_str()) {
+        if kind.starts_with("response.") && !kind.ends_with(".delta") {
+            return 0;
+        }
+    }
+    estimate_output_value(json, None)
+}
+
+fn estimate_output_value(value: &Value, key: Option<&str>) -> u32 {
+    match value {
+        Value::String(text) if key.is_some_and(|k| OUTPUT_TEXT_KEYS.contains(&k)) => estimate_text_tokens(text),
+        // Gemini functionCall 的参数是对象
+        Value::Object(_) if key == Some("args") => estimate_body_tokens(value),
+        Value::Array(items) => items.iter().map(|item| estimate_output_value(item, key)).fold(0, u32::saturating_add),
+        Value::Object(map) => map
+            .iter()
+            .map(|(k, v)| estimate_output_value(v, Some(k)))
+            .fold(0, u32::saturating_add),
+        _ => 0,
+    }
+}
+
+/// 增量 SSE 用量解析器
+#[derive(Debug, Default)]
+pub struct SseUsageParser {
+    /// 尚未遇到换行的半行（按字节保存，避免截断 UTF-8）
+    line: Vec<u8>,
+    /// 当前事件已累积的 data 内容
+    data: String,
+    /// 当前行超出上限，丢弃至行尾
+    line_overflow: bool,
+    /// 当前事件超出上限，丢弃至事件结束
+    event_overflow: bool,
+    usage: ResponseUsage,
+}
+
+impl SseUsageParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 处理一个数据块（可能包含多个事件，也可能只是某个事件的一部分）
+    pub fn feed(&mut self, chunk: &[u8]) {
+        let mut rest = chunk;
+        while let Some(pos) = rest.iter().position(|&b| b == b'\n') {
+            self.push_line_bytes(&rest[..pos]);
+            if std::mem::take(&mut self.line_overflow) {
+                self.event_overflow = true;
+            } else {
+                let line = std::mem::take(&mut self.line);
+                self.process_line(&line);
+            }
+            rest = &rest[pos + 1..];
+        }
+        self.push_line_bytes(rest);
+    }
+
+    fn push_line_bytes(&mut self, bytes: &[u8]) {
+        if self.line_overflow {
+            return;
+        }
+        if self.line.len() + self.data.len() + bytes.len() > MAX_EVENT_BYTES {
+            self.line_overflow = true;
+            self.line.clear();
+            self.data.clear();
+            return;
+        }
+        self.line.extend_from_slice(bytes);
+    }
+
+    fn process_line(&mut self, line: &[u8]) {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            self.dispatch();
+            return;
+        }
+        if self.event_overflow {
+            return;
+        }
+        // 只关心 data 字段；注释行（`: ping`）与 event / id 字段忽略
+        let Some(value) = line.strip_prefix(b"data:") else {
+            return;
+        };
+        let value = value.strip_prefix(b" ").unwrap_or(value);
+        if !self.data.is_empty() {
+            self.data.push('\n');
+        }
+        self.data.push_str(&String::from_utf8_lossy(value));
+    }
+
+    fn dispatch(&mut self) {
+        let data = std::mem::take(&mut self.data);
+        if std::mem::take(&mut self.event_overflow) {
+            return;
+        }
+        let data = data.trim();
+        if data.is_empty() || data == "[DONE]" {
+            return;
+        }
+        match serde_json::from_str::<Value>(data) {
+            Ok(json) => {
+                self.usage.output_estimate = self.usage.output_estimate.saturating_add(estimate_event_output(&json));
+                if may_carry_usage(data) {
+                    self.usage.merge_json(&json);
+                }
+            }
+            Err(e) => tracing::debug!("[Usage] Skipping unparsable SSE event: {}", e),
+        }
+    }
+
+    /// 流结束：处理未以空行结尾的最后一个事件
+    pub fn finish(mut self) -> ResponseUsage {
+        if !self.line.is_empty() && !self.line_overflow {
+            let line = std::mem::take(&mut self.line);
+            self.process_line(&line);
+        }
+        self.dispatch();
+        self.usage.finish()
+    }
+}
+
+/// 快速过滤不含用量 / 结束原因的事件，内容增量不需要合并用量
+fn may_carry_usage(data: &str) -> bool {
+    data.contains("sage") || data.contains("inish") || data.contains("stop_reason") || data.contains("\"response\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse_chunked(stream: &str, chunk_size: usize) -> ResponseUsage {
+        let mut parser = SseUsageParser::new();
+        for chunk in stream.as_bytes().chunks(chunk_size) {
+            parser.feed(chunk);
+        }
+        parser.finish()
+    }
+
+    #[test]
+    fn test_openai_stream_any_chunking() {
+        let stream = concat!(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"你好\"},\"finish_reason\":null}]}\n\n",
+            ": ping\n\n",
+            "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"stop\"}]}\r\n\r\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":34}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let expected = ResponseUsage {
+            input_tokens: Some(12),
+            output_tokens: Some(34),
+            finish_reason: Some("stop".to_string()),
+            estimated: false,
+            total_tokens: None,
+            output_estimate: 2,
+        };
+        for size in [1, 3, 7, 64, stream.len()] {
+            assert_eq!(parse_chunked(stream, size), expected, "chunk size {}", size);
+        }
+        // 上游返回了用量时不做估算
+        assert_eq!(parse_chunked(stream, 64).fill_estimates(Some(b"{}")), expected);
+    }
+
+    #[test]
+    fn test_anthropic_stream_merges_start_and_delta() {
+        let stream = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":25,\"output_tokens\":1}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"delta\":{\"text\":\"hi\"}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":15}}\n\n",
+        );
+        let usage = parse_chunked(stream, 5);
+        assert_eq!((usage.input_tokens, usage.output_tokens), (Some(25), Some(15)));
+        assert_eq!(usage.finish_reason.as_deref(), Some("end_turn"));
+    }
+
+    #[test]
+    fn test_gemini_and_responses_without_trailing_blank_line() {
+        let gemini = "data: {\"candidates\":[{\"finishReason\":\"STOP\"}],\"usageMetadata\":{\"promptTokenCount\":3,\"candidatesTokenCount\":4}}";
+        let usage = parse_chunked(gemini, 10);
+        assert_eq!((usage.input_tokens, usage.output_tokens), (Some(3), Some(4)));
+        assert_eq!(usage.finish_reason.as_deref(), Some("STOP"));
+
+        let responses = json!({
+            "type": "response.completed",
+            "response": {"object": "response", "status": "completed", "usage": {"input_tokens": 8, "output_tokens": 9}}
+        });
+        let usage = ResponseUsage::from_json(&responses);
+        assert_eq!((usage.input_tokens, usage.output_tokens), (Some(8), Some(9)));
+        assert_eq!(usage.finish_reason.as_deref(), Some("completed"));
+
+        let total_only = ResponseUsage::from_json(&json!({"usage": {"total_tokens": 42}}));
+        assert_eq!((total_only.input_tokens, total_only.output_tokens), (None, Some(42)));
+    }
+
+    #[test]
+    fn test_estimates_when_stream_has_no_usage() {
+        let stream = concat!(
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"abcdefgh\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"function\":{\"name\":\"f\",\"arguments\":\"{\\\"q\\\":1}\"}}]},\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "data: {\"type\":\"response.output_text.done\",\"text\":\"abcdefgh\"}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let request = br#"{"model":"gpt-4o","messages":[{"role":"user","content":"abcd"}]}"#;
+        let usage = parse_chunked(stream, 9).fill_estimates(Some(request));
+        assert!(usage.estimated);
+        assert_eq!(usage.input_tokens, Some(1 + 3));
+        assert_eq!(usage.output_tokens, Some(2 + 2));
+        assert_eq!(usage.finish_reason.as_deref(), Some("tool_calls"));
+
+        // 上游只返回了输入计数时只补齐输出
+        let gemini = "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"你好\"}]}}],\"usageMetadata\":{\"promptTokenCount\":9}}\n\n";
+        let usage = parse_chunked(gemini, 64).fill_estimates(None);
+        assert_eq!((usage.input_tokens, usage.output_tokens, usage.estimated), (Some(9), Some(2), true));
+    }
+}
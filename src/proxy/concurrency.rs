@@ -0,0 +1,726 @@
+// 上游并发限制
+// 全局与单账号的在途请求上限：超过上限的请求排队等待空位，队列已满或等待超时时返回错误。
+// 排队按 API Key 优先级调度：有高优先级请求在等待全局空位时，低优先级请求让出空位；
+// 低优先级请求只能占用一半队列，负载高时最先被拒绝。
+// 同一优先级内按 API Key 公平分配：空出的全局名额优先给在途请求最少、其次最久未获得名额的等待中 Key，
+// 单个 Key 的突发请求无法挤占其他 Key。
+// 按上游模型的并发 / RPM 上限在选择账号之前检查，避免某个模型的突发请求触发上游限流
+use futures::{Stream, StreamExt};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::modules::api_keys::KeyPriority;
+use crate::proxy::config::ConcurrencyConfig;
+
+/// 模型 RPM 的滑动窗口
+const RPM_WINDOW: Duration = Duration::from_secs(60);
+
+const PRIORITIES: [KeyPriority; 3] = [KeyPriority::Low, KeyPriority::Normal, KeyPriority::High];
+
+fn tier(priority: KeyPriority) -> usize {
+    PRIORITIES.iter().position(|p| *p == priority).unwrap_or(1)
+}
+
+/// 全局并发限制器（各协议 handler 调用上游前获取许可）
+pub static CONCURRENCY: Lazy<ConcurrencyLimiter> = Lazy::new(ConcurrencyLimiter::new);
+
+/// 校验模型上限：模型名非空
+pub fn validate(config: &ConcurrencyConfig) -> Result<(), String> {
+    if config.models.keys().any(|model| model.trim().is_empty()) {
+        return Err("Model name in models must not be empty".to_string());
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct AccountSlots {
+    email: String,
+    in_flight: usize,
+}
+
+#[derive(Default)]
+struct ModelSlots {
+    in_flight: usize,
+    /// 窗口内放行请求的时间（从旧到新）
+    started: VecDeque<Instant>,
+}
+
+impl ModelSlots {
+    fn prune(&mut self, now: Instant) {
+        while self.started.front().is_some_and(|t| now.duration_since(*t) >= RPM_WINDOW) {
+            self.started.pop_front();
+        }
+    }
+}
+
+fn decrement(map: &mut HashMap<String, usize>, key: &str) {
+    if let Some(count) = map.get_mut(key) {
+        *count = count.saturating_sub(1);
+        if *count == 0 {
+            map.remove(key);
+        }
+    }
+}
+
+impl Counters {
+    /// 公平分配的排序：在途数少的优先，相同时最久未获得名额的优先
+    fn fair_rank(&self, key_id: &str) -> (usize, u64) {
+        (
+            self.key_in_flight.get(key_id).copied().unwrap_or(0),
+            self.last_grant.get(key_id).copied().unwrap_or(0),
+        )
+    }
+
+    /// Key 既无在途也不在等待时丢弃其记录
+    fn forget_idle_key(&mut self, key_id: &str) {
+        let busy = self.key_in_flight.contains_key(key_id) || self.waiting_keys.iter().any(|w| w.contains_key(key_id));
+        if !busy {
+            self.last_grant.remove(key_id);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    in_flight: usize,
+    queued: usize,
+    /// 各优先级的排队数（按 PRIORITIES 顺序）
+    queued_by_tier: [usize; 3],
+    /// 各优先级中因全局上限而等待的请求数
+    waiting_global: [usize; 3],
+    /// 各优先级中因全局上限而等待的请求数，按 API Key 统计
+    waiting_keys: [HashMap<String, usize>; 3],
+    /// 各 API Key 的在途请求数
+    key_in_flight: HashMap<String, usize>,
+    /// 各 API Key 最近一次获得名额的序号（只保留在途或等待中的 Key）
+    last_grant: HashMap<String, u64>,
+    grants: u64,
+    accounts: HashMap<String, AccountSlots>,
+    /// 按命中的模型模式计数
+    models: HashMap<String, ModelSlots>,
+}
+
+enum Capacity {
+    Available,
+    /// 全局已满，或需要让给更高优先级 / 在途更少的 Key 的请求
+    GlobalFull,
+    AccountFull,
+}
+
+struct Inner {
+    config: RwLock<ConcurrencyConfig>,
+    counters: Mutex<Counters>,
+    /// 许可释放或配置变更时唤醒排队中的请求
+    released: Notify,
+}
+
+impl Inner {
+    fn capacity(&self, counters: &Counters, account_id: &str, key_id: &str, tier: usize) -> Capacity {
+        let config = self.config.read().unwrap();
+        let higher_waiting = counters.waiting_global[tier + 1..].iter().any(|n| *n > 0);
+        let own_rank = counters.fair_rank(key_id);
+        let fairer_waiting = config.fair_share
+            && counters.waiting_keys[tier]
+                .keys()
+                .any(|key| key != key_id && counters.fair_rank(key) < own_rank);
+        let global_ok = config.max_in_flight == 0
+            || (counters.in_flight < config.max_in_flight && !higher_waiting && !fairer_waiting);
+        let account_ok = config.max_per_account == 0
+            || counters.accounts.get(account_id).map_or(0, |a| a.in_flight) < config.max_per_account;
+        match (global_ok, account_ok) {
+            (true, true) => Capacity::Available,
+            (false, _) => Capacity::GlobalFull,
+            (true, false) => Capacity::AccountFull,
+        }
+    }
+}
+
+/// 在途请求许可，drop 时释放（流式响应需持有到流结束）
+pub struct ConcurrencyPermit {
+    inner: Arc<Inner>,
+    account_id: String,
+    key_id: String,
+    model: Option<ModelPermit>,
+}
+
+impl ConcurrencyPermit {
+    /// 合并模型额度许可，随在途许可一起释放
+    pub fn with_model(mut self, model: ModelPermit) -> Self {
+        self.model = Some(model);
+        self
+    }
+}
+
+/// 模型额度许可，drop 时释放在途名额（RPM 计数保留到滑出窗口）
+pub struct ModelPermit {
+    inner: Arc<Inner>,
+    /// 命中的模型模式；该模型未配置上限时为 None
+    pattern: Option<String>,
+}
+
+impl Drop for ModelPermit {
+    fn drop(&mut self) {
+        let Some(pattern) = &self.pattern else {
+            return;
+        };
+        if let Some(slots) = self.inner.counters.lock().unwrap().models.get_mut(pattern) {
+            slots.in_flight = slots.in_flight.saturating_sub(1);
+        }
+        self.inner.released.notify_waiters();
+    }
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        {
+            let mut counters = self.inner.counters.lock().unwrap();
+            counters.in_flight = counters.in_flight.saturating_sub(1);
+            decrement(&mut counters.key_in_flight, &self.key_id);
+            counters.forget_idle_key(&self.key_id);
+            if let Some(slots) = counters.accounts.get_mut(&self.account_id) {
+                slots.in_flight = slots.in_flight.saturating_sub(1);
+                if slots.in_flight == 0 {
+                    counters.accounts.remove(&self.account_id);
+                }
+            }
+        }
+        self.inner.released.notify_waiters();
+    }
+}
+
+/// 排队占位，drop 时出队（请求被取消时同样生效）
+struct QueueSlot {
+    inner: Arc<Inner>,
+    key_id: String,
+    tier: usize,
+    /// 是否计入 waiting_global
+    global: bool,
+}
+
+impl QueueSlot {
+    /// 更新等待原因；高优先级请求不再等待全局空位时唤醒让位中的低优先级请求
+    fn set_global(&mut self, counters: &mut Counters, global: bool) {
+        if self.global == global {
+            return;
+        }
+        self.global = global;
+        if global {
+            counters.waiting_global[self.tier] += 1;
+            *counters.waiting_keys[self.tier].entry(self.key_id.clone()).or_default() += 1;
+        } else {
+            counters.waiting_global[self.tier] = counters.waiting_global[self.tier].saturating_sub(1);
+            decrement(&mut counters.waiting_keys[self.tier], &self.key_id);
+            self.inner.released.notify_waiters();
+        }
+    }
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        {
+            let mut counters = self.inner.counters.lock().unwrap();
+            counters.queued = counters.queued.saturating_sub(1);
+            counters.queued_by_tier[self.tier] = counters.queued_by_tier[self.tier].saturating_sub(1);
+            if self.global {
+                counters.waiting_global[self.tier] = counters.waiting_global[self.tier].saturating_sub(1);
+                decrement(&mut counters.waiting_keys[self.tier], &self.key_id);
+                counters.forget_idle_key(&self.key_id);
+            }
+        }
+        if self.global {
+            self.inner.released.notify_waiters();
+        }
+    }
+}
+
+/// 让流式响应体持有许可，流结束（或客户端断开）时释放
+pub fn hold_permit<S: Stream>(stream: S, permit: ConcurrencyPermit) -> impl Stream<Item = S::Item> {
+    stream.map(move |item| {
+        let _ = &permit;
+        item
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountConcurrency {
+    pub account_id: String,
+    pub email: String,
+    pub in_flight: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyConcurrency {
+    /// API Key ID，未鉴权的请求为空
+    pub key_id: String,
+    pub in_flight: usize,
+    /// 等待全局名额的请求数
+    pub waiting: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelConcurrency {
+    /// 配置中的模型名或通配模式
+    pub pattern: String,
+    pub in_flight: usize,
+    pub max_in_flight: usize,
+    /// 最近一分钟放行的请求数
+    pub requests_last_minute: usize,
+    pub rpm: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedByPriority {
+    pub high: usize,
+    pub normal: usize,
+    pub low: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConcurrencyStatus {
+    pub in_flight: usize,
+    pub queued: usize,
+    pub queued_by_priority: QueuedByPriority,
+    pub max_in_flight: usize,
+    pub max_per_account: usize,
+    pub max_queue: usize,
+    pub fair_share: bool,
+    pub accounts: Vec<AccountConcurrency>,
+    pub keys: Vec<KeyConcurrency>,
+    pub models: Vec<ModelConcurrency>,
+}
+
+pub struct ConcurrencyLimiter {
+    inner: Arc<Inner>,
+}
+
+impl Default for ConcurrencyLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                config: RwLock::new(ConcurrencyConfig::default()),
+                counters: Mutex::new(Counters::default()),
+                released: Notify::new(),
+            }),
+        }
+    }
+
+    /// 应用配置（启动 / 热重载时调用）；上限调高后立即唤醒排队请求
+    pub fn configure(&self, config: &ConcurrencyConfig) {
+        *self.inner.config.write().unwrap() = config.clone();
+        self.inner.released.notify_waiters();
+    }
+
+    /// 获取在途许可，没有空位时排队等待；优先级与公平分配取自当前请求的 API Key
+    pub async fn acquire(&self, account_id: &str, email: &str) -> Result<ConcurrencyPermit, String> {
+        let priority = crate::proxy::middleware::auth::current_key_priority();
+        self.acquire_with_priority(account_id, email, priority).await
+    }
+
+    /// 以指定优先级获取在途许可
+    pub async fn acquire_with_priority(
+        &self,
+        account_id: &str,
+        email: &str,
+        priority: KeyPriority,
+    ) -> Result<ConcurrencyPermit, String> {
+        let key_id = crate::proxy::middleware::auth::current_key_id().unwrap_or_default();
+        self.acquire_for_key(account_id, email, &key_id, priority).await
+    }
+
+    /// 以指定 API Key 与优先级获取在途许可（未鉴权的请求共用空 Key）
+    pub async fn acquire_for_key(
+        &self,
+        account_id: &str,
+        email: &str,
+        key_id: &str,
+        priority: KeyPriority,
+    ) -> Result<ConcurrencyPermit, String> {
+        let tier = tier(priority);
+        let (max_queue, timeout_ms) = {
+            let config = self.inner.config.read().unwrap();
+            // 低优先级只能使用一半队列
+            let max_queue = if priority == KeyPriority::Low { config.max_queue / 2 } else { config.max_queue };
+            (max_queue, config.queue_timeout_ms)
+        };
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(timeout_ms);
+        let mut queue_slot: Option<QueueSlot> = None;
+
+        loop {
+            // 先注册唤醒再检查计数，避免检查与等待之间的释放被错过
+            let released = self.inner.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            {
+                let mut counters = self.inner.counters.lock().unwrap();
+                let capacity = self.inner.capacity(&counters, account_id, key_id, tier);
+                if matches!(capacity, Capacity::Available) {
+                    counters.in_flight += 1;
+                    *counters.key_in_flight.entry(key_id.to_string()).or_default() += 1;
+                    counters.grants += 1;
+                    let grant = counters.grants;
+                    counters.last_grant.insert(key_id.to_string(), grant);
+                    let slots = counters.accounts.entry(account_id.to_string()).or_default();
+                    slots.in_flight += 1;
+                    slots.email = email.to_string();
+                    drop(counters);
+                    drop(queue_slot);
+                    return Ok(ConcurrencyPermit {
+                        inner: self.inner.clone(),
+                        account_id: account_id.to_string(),
+                        key_id: key_id.to_string(),
+                        model: None,
+                    });
+                }
+                if queue_slot.is_none() {
+                    if counters.queued >= max_queue {
+                        return Err(format!(
+                            "Too many concurrent requests ({} already queued, {} priority)",
+                            counters.queued,
+                            priority.as_str()
+                        ));
+                    }
+                    counters.queued += 1;
+                    counters.queued_by_tier[tier] += 1;
+                    queue_slot = Some(QueueSlot {
+                        inner: self.inner.clone(),
+                        key_id: key_id.to_string(),
+                        tier,
+                        global: false,
+                    });
+                }
+                if let Some(slot) = queue_slot.as_mut() {
+                    slot.set_global(&mut counters, matches!(capacity, Capacity::GlobalFull));
+                }
+            }
+
+            if tokio::time::timeout_at(deadline, released).await.is_err() {
+                return Err(format!("Timed out after {}ms waiting for a free upstream slot", timeout_ms));
+            }
+        }
+    }
+
+    /// 按映射后的上游模型获取额度（在选择账号之前调用）
+    ///
+    /// 超过模型并发上限时排队等待空位；超过 RPM 时等待最早的请求滑出窗口，
+    /// 窗口恢复晚于排队超时则立即返回错误
+    pub async fn acquire_model(&self, model: &str) -> Result<ModelPermit, String> {
+        let timeout_ms = self.inner.config.read().unwrap().queue_timeout_ms;
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+
+        loop {
+            let released = self.inner.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            let wake_at = {
+                // 每轮重新读取配置，热重载调整的上限对排队中的请求立即生效
+                let config = self.inner.config.read().unwrap();
+                let Some((pattern, limit)) = crate::proxy::pricing::lookup_pattern(&config.models, model)
+                    .filter(|(_, limit)| limit.max_in_flight > 0 || limit.rpm > 0)
+                else {
+                    return Ok(ModelPermit { inner: self.inner.clone(), pattern: None });
+                };
+
+                let now = Instant::now();
+                let mut counters = self.inner.counters.lock().unwrap();
+                let slots = counters.models.entry(pattern.to_string()).or_default();
+                slots.prune(now);
+                let in_flight_ok = limit.max_in_flight == 0 || slots.in_flight < limit.max_in_flight;
+                let rpm_ok = limit.rpm == 0 || slots.started.len() < limit.rpm as usize;
+                if in_flight_ok && rpm_ok {
+                    slots.in_flight += 1;
+                    slots.started.push_back(now);
+                    return Ok(ModelPermit {
+                        inner: self.inner.clone(),
+                        pattern: Some(pattern.to_string()),
+                    });
+                }
+                if rpm_ok {
+                    deadline
+                } else {
+                    let window_frees = slots.started[0] + RPM_WINDOW;
+                    if window_frees > deadline {
+                        return Err(format!(
+                            "Rate limit for model {} reached ({} requests per minute)",
+                            model, limit.rpm
+                        ));
+                    }
+                    window_frees
+                }
+            };
+
+            tokio::select! {
+                _ = released => {}
+                _ = tokio::time::sleep_until(wake_at) => {}
+            }
+            if Instant::now() >= deadline {
+                return Err(format!(
+                    "Timed out after {}ms waiting for a free slot for model {}",
+                    timeout_ms, model
+                ));
+            }
+        }
+    }
+
+    pub fn status(&self) -> ConcurrencyStatus {
+        let config = self.inner.config.read().unwrap().clone();
+        let counters = self.inner.counters.lock().unwrap();
+        let mut accounts: Vec<AccountConcurrency> = counters
+            .accounts
+            .iter()
+            .map(|(id, slots)| AccountConcurrency {
+                account_id: id.clone(),
+                email: slots.email.clone(),
+                in_flight: slots.in_flight,
+            })
+            .collect();
+        accounts.sort_by(|a, b| b.in_flight.cmp(&a.in_flight).then_with(|| a.email.cmp(&b.email)));
+        let mut keys: Vec<KeyConcurrency> = counters
+            .last_grant
+            .keys()
+            .chain(counters.waiting_keys.iter().flat_map(|w| w.keys()))
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .map(|key| KeyConcurrency {
+                key_id: key.clone(),
+                in_flight: counters.key_in_flight.get(key).copied().unwrap_or(0),
+                waiting: counters.waiting_keys.iter().filter_map(|w| w.get(key)).sum(),
+            })
+            .collect();
+        keys.sort_by(|a, b| b.in_flight.cmp(&a.in_flight).then_with(|| b.waiting.cmp(&a.waiting)));
+        let now = Instant::now();
+        let mut models: Vec<ModelConcurrency> = config
+            .models
+            .iter()
+            .map(|(pattern, limit)| {
+                let slots = counters.models.get(pattern);
+                ModelConcurrency {
+                    pattern: pattern.clone(),
+                    in_flight: slots.map_or(0, |s| s.in_flight),
+                    max_in_flight: limit.max_in_flight,
+                    requests_last_minute: slots.map_or(0, |s| {
+                        s.started.iter().filter(|t| now.duration_since(**t) < RPM_WINDOW).count()
+                    }),
+                    rpm: limit.rpm,
+                }
+            })
+            .collect();
+        models.sort_by(|a, b| a.pattern.cmp(&b.pattern));
+        ConcurrencyStatus {
+            in_flight: counters.in_flight,
+            queued: counters.queued,
+            queued_by_priority: QueuedByPriority {
+                high: counters.queued_by_tier[tier(KeyPriority::High)],
+                normal: counters.queued_by_tier[tier(KeyPriority::Normal)],
+                low: counters.queued_by_tier[tier(KeyPriority::Low)],
+            },
+            max_in_flight: config.max_in_flight,
+            max_per_account: config.max_per_account,
+            max_queue: config.max_queue,
+            fair_share: config.fair_share,
+            accounts,
+            keys,
+            models,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::config::ModelConcurrencyLimit;
+
+    fn limiter(max_in_flight: usize, max_per_account: usize, max_queue: usize, queue_timeout_ms: u64) -> Arc<ConcurrencyLimiter> {
+        let limiter = ConcurrencyLimiter::new();
+        limiter.configure(&ConcurrencyConfig {
+            max_in_flight,
+            max_per_account,
+            max_queue,
+            queue_timeout_ms,
+            fair_share: true,
+            models: HashMap::new(),
+        });
+        Arc::new(limiter)
+    }
+
+    #[tokio::test]
+    async fn test_queue_until_released() {
+        let limiter = limiter(1, 0, 10, 5_000);
+        let first = limiter.acquire("a", "a@example.com").await.unwrap();
+
+        let waiter = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire("b", "b@example.com").await.map(|_| ()) })
+        };
+        while limiter.status().queued == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(limiter.status().in_flight, 1);
+
+        drop(first);
+        waiter.await.unwrap().unwrap();
+        let status = limiter.status();
+        assert_eq!((status.in_flight, status.queued), (0, 0));
+        assert!(status.accounts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_per_account_limit_queue_full_and_timeout() {
+        let limiter = limiter(0, 1, 0, 50);
+        let _a = limiter.acquire("a", "a@example.com").await.unwrap();
+        // 其他账号不受单账号上限影响
+        let _b = limiter.acquire("b", "b@example.com").await.unwrap();
+        assert_eq!(limiter.status().accounts.len(), 2);
+
+        // 队列长度为 0：直接拒绝
+        assert!(limiter.acquire("a", "a@example.com").await.err().unwrap().contains("queued"));
+
+        // 排队超时后出队
+        limiter.configure(&ConcurrencyConfig {
+            max_in_flight: 0,
+            max_per_account: 1,
+            max_queue: 1,
+            queue_timeout_ms: 50,
+            fair_share: true,
+            models: HashMap::new(),
+        });
+        assert!(limiter.acquire("a", "a@example.com").await.err().unwrap().contains("Timed out"));
+        assert_eq!(limiter.status().queued, 0);
+    }
+
+    #[tokio::test]
+    async fn test_priority_order_and_low_priority_shedding() {
+        let limiter = limiter(1, 0, 4, 5_000);
+        let first = limiter.acquire("a", "a@example.com").await.unwrap();
+
+        let spawn_waiter = |priority| {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire_with_priority("b", "b@example.com", priority).await })
+        };
+        let low = spawn_waiter(KeyPriority::Low);
+        while limiter.status().queued == 0 {
+            tokio::task::yield_now().await;
+        }
+        let high = spawn_waiter(KeyPriority::High);
+        while limiter.status().queued < 2 {
+            tokio::task::yield_now().await;
+        }
+        let status = limiter.status();
+        assert_eq!((status.queued_by_priority.high, status.queued_by_priority.low), (1, 1));
+
+        // 后到的高优先级请求先获得空位
+        drop(first);
+        let high_permit = high.await.unwrap().unwrap();
+        assert!(!low.is_finished());
+        assert_eq!(limiter.status().queued_by_priority.low, 1);
+        drop(high_permit);
+        low.await.unwrap().unwrap();
+
+        // 低优先级只能占用一半队列（此处为 2）
+        let _held = limiter.acquire("a", "a@example.com").await.unwrap();
+        let _queued = (spawn_waiter(KeyPriority::Low), spawn_waiter(KeyPriority::Low));
+        while limiter.status().queued < 2 {
+            tokio::task::yield_now().await;
+        }
+        let err = limiter
+            .acquire_with_priority("c", "c@example.com", KeyPriority::Low)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.contains("low priority"));
+        let normal = spawn_waiter(KeyPriority::Normal);
+        while limiter.status().queued < 3 {
+            tokio::task::yield_now().await;
+        }
+        normal.abort();
+    }
+
+    #[tokio::test]
+    async fn test_model_limits() {
+        let limiter = Arc::new(ConcurrencyLimiter::new());
+        let models = HashMap::from([
+            ("gemini-2.5-pro*".to_string(), ModelConcurrencyLimit { max_in_flight: 1, rpm: 0 }),
+            ("claude-opus-4-5".to_string(), ModelConcurrencyLimit { max_in_flight: 0, rpm: 2 }),
+        ]);
+        limiter.configure(&ConcurrencyConfig { queue_timeout_ms: 5_000, models, ..Default::default() });
+
+        // 未配置的模型不受限制
+        let _flash = (limiter.acquire_model("gemini-2.5-flash").await.unwrap(), limiter.acquire_model("gemini-2.5-flash").await.unwrap());
+
+        // 同一模式匹配的模型共享并发额度，释放后排队请求获得空位
+        let first = limiter.acquire_model("gemini-2.5-pro").await.unwrap();
+        let waiter = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire_model("gemini-2.5-pro-high").await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+        drop(first);
+        waiter.await.unwrap().unwrap();
+
+        // RPM 额度要到一分钟后才恢复，超过排队超时则立即拒绝；在途请求结束不会归还 RPM 额度
+        drop(limiter.acquire_model("claude-opus-4-5").await.unwrap());
+        let _second = limiter.acquire_model("claude-opus-4-5").await.unwrap();
+        let started = Instant::now();
+        let err = limiter.acquire_model("claude-opus-4-5").await.err().unwrap();
+        assert!(err.contains("2 requests per minute"));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let status = limiter.status();
+        let opus = status.models.iter().find(|m| m.pattern == "claude-opus-4-5").unwrap();
+        assert_eq!((opus.in_flight, opus.requests_last_minute), (1, 2));
+        assert!(validate(&ConcurrencyConfig {
+            models: HashMap::from([(" ".to_string(), ModelConcurrencyLimit::default())]),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fair_share_across_keys() {
+        let limiter = limiter(1, 0, 10, 5_000);
+        let first = limiter.acquire_for_key("a", "a@example.com", "busy", KeyPriority::Normal).await.unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let spawn_waiter = |key: &'static str| {
+            let (limiter, order) = (limiter.clone(), order.clone());
+            tokio::spawn(async move {
+                let _permit = limiter.acquire_for_key("a", "a@example.com", key, KeyPriority::Normal).await.unwrap();
+                order.lock().unwrap().push(key);
+            })
+        };
+        // 繁忙 Key 先排了多个请求，后到的安静 Key 仍先获得空位
+        let mut waiters = vec![spawn_waiter("busy"), spawn_waiter("busy"), spawn_waiter("busy")];
+        while limiter.status().queued < 3 {
+            tokio::task::yield_now().await;
+        }
+        waiters.push(spawn_waiter("quiet"));
+        while limiter.status().queued < 4 {
+            tokio::task::yield_now().await;
+        }
+        let status = limiter.status();
+        let busy = status.keys.iter().find(|k| k.key_id == "busy").unwrap();
+        assert_eq!((busy.in_flight, busy.waiting), (1, 3));
+
+        drop(first);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert_eq!(order.lock().unwrap()[0], "quiet");
+        let status = limiter.status();
+        assert_eq!((status.in_flight, status.queued), (0, 0));
+        assert!(status.keys.is_empty());
+    }
+}
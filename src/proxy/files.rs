@@ -0,0 +1,311 @@
+// 上传文件 (/v1/files)
+// 文件保存在数据目录的 files/ 下（内容与元数据各一个文件），按上传时使用的 API Key 隔离，过期后删除。
+// 上游 v1internal 没有独立的文件上传接口：chat 请求引用文件 ID 时，由代理读取文件内容并作为 inlineData 发送，
+// 客户端无需在每次请求中内联大段 base64
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use crate::proxy::config::FilesConfig;
+
+/// 数据目录下的存储子目录
+const FILES_DIR: &str = "files";
+/// 文件 ID 前缀
+const ID_PREFIX: &str = "file-";
+
+/// 全局文件存储（/v1/files 与 chat 请求的文件引用使用）
+pub static FILES: Lazy<FileStore> = Lazy::new(FileStore::default);
+
+/// 文件元数据
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredFile {
+    pub id: String,
+    pub filename: String,
+    pub purpose: String,
+    pub mime_type: String,
+    pub bytes: usize,
+    pub created_at: i64,
+    pub expires_at: i64,
+    /// 上传时使用的 API Key ID；未启用鉴权时为空
+    pub owner: Option<String>,
+}
+
+impl StoredFile {
+    /// OpenAI File 对象
+    pub fn to_object(&self) -> Value {
+        json!({
+            "id": self.id,
+            "object": "file",
+            "bytes": self.bytes,
+            "created_at": self.created_at,
+            "expires_at": self.expires_at,
+            "filename": self.filename,
+            "purpose": self.purpose,
+            "status": "processed"
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct FileStore {
+    config: RwLock<FilesConfig>,
+    /// 存储目录；为 None 时使用数据目录下的 files/
+    dir: Option<PathBuf>,
+}
+
+/// 校验文件配置
+pub fn validate(config: &FilesConfig) -> Result<(), String> {
+    if config.ttl_hours == 0 {
+        return Err("ttl_hours must be greater than 0".to_string());
+    }
+    Ok(())
+}
+
+/// 文件 ID 只能是 `file-` 加 32 位十六进制，防止路径穿越
+fn is_valid_id(id: &str) -> bool {
+    id.strip_prefix(ID_PREFIX)
+        .is_some_and(|rest| rest.len() == 32 && rest.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// 确定文件的 MIME 类型：优先使用上传时声明的类型，其次按文件头与扩展名推断
+pub fn detect_mime(declared: Option<&str>, filename: &str, data: &[u8]) -> Option<String> {
+    if let Some(mime) = declared
+        .map(|m| m.split(';').next().unwrap_or(m).trim().to_ascii_lowercase())
+        .filter(|m| !m.is_empty() && m != "application/octet-stream")
+    {
+        return Some(mime);
+    }
+    if let Some(mime) = crate::proxy::common::image::sniff_mime(data) {
+        return Some(mime.to_string());
+    }
+    if data.starts_with(b"%PDF-") {
+        return Some("application/pdf".to_string());
+    }
+    let extension = filename.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+    let by_extension = match extension.as_deref() {
+        Some("pdf") => Some("application/pdf"),
+        Some("md" | "markdown") => Some("text/markdown"),
+        Some("csv") => Some("text/csv"),
+        Some("html" | "htm") => Some("text/html"),
+        Some("xml") => Some("text/xml"),
+        Some("json") => Some("application/json"),
+        Some("mp3") => Some("audio/mpeg"),
+        Some("wav") => Some("audio/wav"),
+        Some("mp4") => Some("video/mp4"),
+        _ => None,
+    };
+    if let Some(mime) = by_extension {
+        return Some(mime.to_string());
+    }
+    // 其余能按 UTF-8 解码的内容作为纯文本（源代码、日志等）
+    std::str::from_utf8(data).is_ok().then(|| "text/plain".to_string())
+}
+
+impl FileStore {
+    #[cfg(test)]
+    fn with_dir(dir: PathBuf) -> Self {
+        Self {
+            config: RwLock::new(FilesConfig::default()),
+            dir: Some(dir),
+        }
+    }
+
+    pub fn configure(&self, config: &FilesConfig) {
+        *self.config.write().unwrap() = config.clone();
+    }
+
+    /// 单个文件上限（字节），0 表示禁止上传
+    pub fn max_file_bytes(&self) -> usize {
+        self.config.read().unwrap().max_file_bytes
+    }
+
+    fn dir(&self) -> Result<PathBuf, String> {
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => crate::modules::account::get_data_dir()?.join(FILES_DIR),
+        };
+        std::fs::create_dir_all(&dir).map_err(|e| format!("failed to create files directory: {}", e))?;
+        Ok(dir)
+    }
+
+    /// 保存上传的文件
+    pub fn save(
+        &self,
+        owner: Option<&str>,
+        filename: &str,
+        purpose: &str,
+        mime_type: &str,
+        data: &[u8],
+    ) -> Result<StoredFile, String> {
+        let max_file_bytes = self.max_file_bytes();
+        if data.len() > max_file_bytes {
+            return Err(format!("File exceeds the {} byte limit", max_file_bytes));
+        }
+        self.save_generated(owner, filename, purpose, mime_type, data)
+    }
+
+    /// 保存代理生成的文件（如批处理结果），不受上传大小限制
+    pub fn save_generated(
+        &self,
+        owner: Option<&str>,
+        filename: &str,
+        purpose: &str,
+        mime_type: &str,
+        data: &[u8],
+    ) -> Result<StoredFile, String> {
+        let ttl_hours = self.config.read().unwrap().ttl_hours;
+        self.prune_expired();
+
+        let now = chrono::Utc::now().timestamp();
+        let file = StoredFile {
+            id: format!("{}{}", ID_PREFIX, uuid::Uuid::new_v4().simple()),
+            filename: filename.to_string(),
+            purpose: purpose.to_string(),
+            mime_type: mime_type.to_string(),
+            bytes: data.len(),
+            created_at: now,
+            expires_at: now + (ttl_hours as i64) * 3600,
+            owner: owner.map(str::to_string),
+        };
+        let dir = self.dir()?;
+        std::fs::write(dir.join(&file.id), data).map_err(|e| format!("failed to store file: {}", e))?;
+        let meta = serde_json::to_vec(&file).map_err(|e| e.to_string())?;
+        std::fs::write(dir.join(format!("{}.json", file.id)), meta).map_err(|e| format!("failed to store file: {}", e))?;
+        Ok(file)
+    }
+
+    fn read_meta(&self, id: &str) -> Result<Option<StoredFile>, String> {
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+        let path = self.dir()?.join(format!("{}.json", id));
+        let meta = match std::fs::read(&path) {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("failed to read file metadata: {}", e)),
+        };
+        serde_json::from_slice(&meta).map(Some).map_err(|e| format!("invalid file metadata: {}", e))
+    }
+
+    /// 查询文件元数据；其他 API Key 上传的和已过期的文件视为不存在
+    pub fn get(&self, owner: Option<&str>, id: &str) -> Result<Option<StoredFile>, String> {
+        let Some(file) = self.read_meta(id)? else {
+            return Ok(None);
+        };
+        if file.owner.as_deref() != owner {
+            return Ok(None);
+        }
+        if file.expires_at <= chrono::Utc::now().timestamp() {
+            self.remove(id);
+            return Ok(None);
+        }
+        Ok(Some(file))
+    }
+
+    /// 读取文件内容
+    pub fn read(&self, owner: Option<&str>, id: &str) -> Result<Option<(StoredFile, Vec<u8>)>, String> {
+        let Some(file) = self.get(owner, id)? else {
+            return Ok(None);
+        };
+        let data = std::fs::read(self.dir()?.join(id)).map_err(|e| format!("failed to read file: {}", e))?;
+        Ok(Some((file, data)))
+    }
+
+    /// 列出该 API Key 的未过期文件，新上传的在前
+    pub fn list(&self, owner: Option<&str>) -> Result<Vec<StoredFile>, String> {
+        let now = chrono::Utc::now().timestamp();
+        let mut files: Vec<StoredFile> = self
+            .all()?
+            .into_iter()
+            .filter(|f| f.owner.as_deref() == owner && f.expires_at > now)
+            .collect();
+        files.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.id.cmp(&b.id)));
+        Ok(files)
+    }
+
+    /// 删除文件；返回是否存在
+    pub fn delete(&self, owner: Option<&str>, id: &str) -> Result<bool, String> {
+        if self.get(owner, id)?.is_none() {
+            return Ok(false);
+        }
+        self.remove(id);
+        Ok(true)
+    }
+
+    fn all(&self) -> Result<Vec<StoredFile>, String> {
+        let entries = std::fs::read_dir(self.dir()?).map_err(|e| format!("failed to list files: {}", e))?;
+        Ok(entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".json").map(str::to_string))
+            .filter_map(|id| self.read_meta(&id).ok().flatten())
+            .collect())
+    }
+
+    fn remove(&self, id: &str) {
+        if let Ok(dir) = self.dir() {
+            let _ = std::fs::remove_file(dir.join(id));
+            let _ = std::fs::remove_file(dir.join(format!("{}.json", id)));
+        }
+    }
+
+    /// 删除所有已过期的文件
+    pub fn prune_expired(&self) {
+        let now = chrono::Utc::now().timestamp();
+        match self.all() {
+            Ok(files) => {
+                for file in files.iter().filter(|f| f.expires_at <= now) {
+                    tracing::debug!("[Files] Removing expired file {}", file.id);
+                    self.remove(&file.id);
+                }
+            }
+            Err(e) => tracing::warn!("[Files] Failed to prune expired files: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> (FileStore, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("antiproxy-files-{}", uuid::Uuid::new_v4().simple()));
+        (FileStore::with_dir(dir.clone()), dir)
+    }
+
+    #[test]
+    fn test_save_read_and_isolation() {
+        let (store, dir) = temp_store();
+        let file = store.save(Some("key-a"), "notes.md", "user_data", "text/markdown", b"# hi").unwrap();
+        assert!(is_valid_id(&file.id));
+        assert_eq!(file.bytes, 4);
+
+        let (meta, data) = store.read(Some("key-a"), &file.id).unwrap().unwrap();
+        assert_eq!(meta, file);
+        assert_eq!(data, b"# hi");
+        assert!(store.get(Some("key-b"), &file.id).unwrap().is_none());
+        assert!(store.get(None, &file.id).unwrap().is_none());
+        assert!(store.get(Some("key-a"), "../web_config.json").unwrap().is_none());
+        assert_eq!(store.list(Some("key-a")).unwrap().len(), 1);
+        assert!(store.list(Some("key-b")).unwrap().is_empty());
+
+        assert!(!store.delete(Some("key-b"), &file.id).unwrap());
+        assert!(store.delete(Some("key-a"), &file.id).unwrap());
+        assert!(store.get(Some("key-a"), &file.id).unwrap().is_none());
+
+        store.configure(&FilesConfig { max_file_bytes: 2, ..FilesConfig::default() });
+        assert!(store.save(None, "big.txt", "user_data", "text/plain", b"abc").is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_detect_mime() {
+        assert_eq!(detect_mime(Some("application/pdf"), "a", b"x").as_deref(), Some("application/pdf"));
+        assert_eq!(detect_mime(Some("application/octet-stream"), "a.bin", b"%PDF-1.7").as_deref(), Some("application/pdf"));
+        assert_eq!(detect_mime(None, "data.csv", b"a,b").as_deref(), Some("text/csv"));
+        assert_eq!(detect_mime(None, "main.rs", b"fn main() {}").as_deref(), Some("text/plain"));
+        assert_eq!(detect_mime(None, "blob", &[0xff, 0xfe, 0x00, 0x81]), None);
+        assert!(validate(&FilesConfig { ttl_hours: 0, ..FilesConfig::default() }).is_err());
+    }
+}
@@ -11,13 +11,15 @@ use serde::{Deserialize, Serialize};
 use crate::modules::api_keys::{
     self, ApiKeyResponse, CreateApiKeyRequest,
 };
+use crate::modules::audit;
+use crate::modules::storage;
 use crate::proxy::server::AppState;
 
 /// 列出所有 API Keys
 pub async fn list_api_keys(
     State(_state): State<AppState>,
 ) -> Result<impl IntoResponse, StatusCode> {
-    match api_keys::list_api_keys() {
+    match storage::blocking(api_keys::list_api_keys).await {
         Ok(keys) => {
             let responses: Vec<ApiKeyResponse> = keys.into_iter().map(|k| k.into()).collect();
             Ok(Json(responses))
@@ -38,14 +40,20 @@ pub async fn create_api_key(
         return Err(StatusCode::BAD_REQUEST);
     }
 
-    match api_keys::create_api_key(&req.name) {
+    if req.expires_at.is_some_and(|t| t <= 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match storage::blocking(move || api_keys::create_api_key(&req.name, req.expires_at)).await {
         Ok(key) => {
+            audit::record("api_key.created", Some(&key.id), None, audit::snapshot(&ApiKeyResponse::from(key.clone())));
             // 创建时返回完整的 key（只有这一次机会看到完整 key）
             Ok((StatusCode::CREATED, Json(CreatedApiKeyResponse {
                 id: key.id,
                 name: key.name,
                 key: key.key, // 完整 key，只在创建时返回
                 created_at: key.created_at,
+                expires_at: key.expires_at,
             })))
         }
         Err(e) => {
@@ -61,6 +69,7 @@ pub struct CreatedApiKeyResponse {
     pub name: String,
     pub key: String,
     pub created_at: i64,
+    pub expires_at: Option<i64>,
 }
 
 /// 获取单个 API Key
@@ -68,8 +77,14 @@ pub async fn get_api_key(
     State(_state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<impl IntoResponse, StatusCode> {
-    match api_keys::get_api_key(&id) {
-        Ok(Some(key)) => Ok(Json(ApiKeyResponse::from(key))),
+    fetch_key(&id).await.map(|key| Json(ApiKeyResponse::from(key)))
+}
+
+/// 读取 API Key，不存在时返回 404
+async fn fetch_key(id: &str) -> Result<api_keys::ApiKey, StatusCode> {
+    let id = id.to_string();
+    match storage::blocking(move || api_keys::get_api_key(&id)).await {
+        Ok(Some(key)) => Ok(key),
         Ok(None) => Err(StatusCode::NOT_FOUND),
         Err(e) => {
             tracing::error!("Failed to get API key: {}", e);
@@ -83,6 +98,24 @@ pub async fn get_api_key(
 pub struct UpdateApiKeyRequest {
     pub name: Option<String>,
     pub enabled: Option<bool>,
+    /// 每分钟请求上限，0 表示取消限制
+    pub rate_limit_rpm: Option<u32>,
+    /// 每日请求上限，0 表示取消限制
+    pub rate_limit_rpd: Option<u32>,
+    /// 模型白名单，空数组表示取消限制
+    pub allowed_models: Option<Vec<String>>,
+    /// 每日 token 预算（输入 + 输出），0 表示取消限制
+    pub daily_token_budget: Option<u64>,
+    /// 过期时间（Unix 秒），0 表示永不过期
+    pub expires_at: Option<i64>,
+    /// 允许的客户端 IP（CIDR），空数组表示取消限制
+    pub allowed_ips: Option<Vec<String>>,
+    /// 请求改写规则，空对象表示取消
+    pub request_transform: Option<crate::proxy::request_transform::RequestTransform>,
+    /// 绑定的账号池，空字符串表示取消绑定
+    pub pool: Option<String>,
+    /// 并发排队优先级：high / normal / low
+    pub priority: Option<api_keys::KeyPriority>,
 }
 
 /// 更新 API Key
@@ -92,19 +125,57 @@ pub async fn update_api_key(
     Json(req): Json<UpdateApiKeyRequest>,
 ) -> Result<impl IntoResponse, StatusCode> {
     // 检查 key 是否存在
-    match api_keys::get_api_key(&id) {
-        Ok(Some(_)) => {}
-        Ok(None) => return Err(StatusCode::NOT_FOUND),
+    let existing = fetch_key(&id).await?;
+
+    // 先校验，避免部分字段已写入后才返回 400
+    validate_update(&req)?;
+    let updated = {
+        let (id, existing) = (id.clone(), existing.clone());
+        storage::blocking(move || {
+            apply_update(&id, &existing, req)?;
+            // 返回更新后的 key
+            reload_key(&id)
+        })
+        .await?
+    };
+    audit::record(
+        "api_key.updated",
+        Some(&id),
+        audit::snapshot(&ApiKeyResponse::from(existing)),
+        audit::snapshot(&updated),
+    );
+    Ok(Json(updated))
+}
+
+fn validate_update(req: &UpdateApiKeyRequest) -> Result<(), StatusCode> {
+    if req.expires_at.is_some_and(|t| t < 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Some(ips) = &req.allowed_ips {
+        if crate::proxy::ip_filter::parse_list(ips).is_err() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    Ok(())
+}
+
+fn reload_key(id: &str) -> Result<ApiKeyResponse, StatusCode> {
+    match api_keys::get_api_key(id) {
+        Ok(Some(key)) => Ok(ApiKeyResponse::from(key)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
         Err(e) => {
-            tracing::error!("Failed to get API key: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            tracing::error!("Failed to get updated API key: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
+}
 
+/// 写入请求中提供的字段（未提供的字段保持不变）
+fn apply_update(id: &str, existing: &api_keys::ApiKey, req: UpdateApiKeyRequest) -> Result<(), StatusCode> {
     // 更新名称
     if let Some(name) = req.name {
         if !name.trim().is_empty() {
-            if let Err(e) = api_keys::update_api_key_name(&id, &name) {
+            if let Err(e) = api_keys::update_api_key_name(id, &name) {
                 tracing::error!("Failed to update API key name: {}", e);
                 return Err(StatusCode::INTERNAL_SERVER_ERROR);
             }
@@ -113,64 +184,260 @@ pub async fn update_api_key(
 
     // 更新启用状态
     if let Some(enabled) = req.enabled {
-        if let Err(e) = api_keys::set_api_key_enabled(&id, enabled) {
+        if let Err(e) = api_keys::set_api_key_enabled(id, enabled) {
             tracing::error!("Failed to update API key enabled state: {}", e);
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     }
 
-    // 返回更新后的 key
-    match api_keys::get_api_key(&id) {
-        Ok(Some(key)) => Ok(Json(ApiKeyResponse::from(key))),
-        Ok(None) => Err(StatusCode::NOT_FOUND),
-        Err(e) => {
-            tracing::error!("Failed to get updated API key: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+    // 更新限流配置（未提供的字段保持不变）
+    if req.rate_limit_rpm.is_some() || req.rate_limit_rpd.is_some() {
+        let rpm = match req.rate_limit_rpm {
+            Some(v) => Some(v).filter(|v| *v > 0),
+            None => existing.rate_limit_rpm,
+        };
+        let rpd = match req.rate_limit_rpd {
+            Some(v) => Some(v).filter(|v| *v > 0),
+            None => existing.rate_limit_rpd,
+        };
+        if let Err(e) = api_keys::set_api_key_rate_limits(id, rpm, rpd) {
+            tracing::error!("Failed to update API key rate limits: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        // 重复提交相同的限额（如 upsert）不清空当前窗口的计数
+        if (rpm, rpd) != (existing.rate_limit_rpm, existing.rate_limit_rpd) {
+            crate::proxy::key_rate_limit::KEY_RATE_LIMITER.reset(id);
+        }
+    }
+
+    // 更新模型白名单
+    if let Some(models) = req.allowed_models {
+        let models: Vec<String> = models
+            .into_iter()
+            .map(|m| m.trim().to_string())
+            .filter(|m| !m.is_empty())
+            .collect();
+        let value = if models.is_empty() { None } else { Some(models.as_slice()) };
+        if let Err(e) = api_keys::set_api_key_allowed_models(id, value) {
+            tracing::error!("Failed to update API key allowed models: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    // 更新每日 token 预算
+    if let Some(budget) = req.daily_token_budget {
+        if let Err(e) = api_keys::set_api_key_daily_token_budget(id, Some(budget).filter(|v| *v > 0)) {
+            tracing::error!("Failed to update API key token budget: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    // 更新 IP 白名单
+    if let Some(ips) = req.allowed_ips {
+        let ips: Vec<String> = ips
+            .into_iter()
+            .map(|ip| ip.trim().to_string())
+            .filter(|ip| !ip.is_empty())
+            .collect();
+        let value = if ips.is_empty() { None } else { Some(ips.as_slice()) };
+        if let Err(e) = api_keys::set_api_key_allowed_ips(id, value) {
+            tracing::error!("Failed to update API key allowed IPs: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    // 更新请求改写规则
+    if let Some(transform) = req.request_transform {
+        let value = if transform.is_empty() { None } else { Some(&transform) };
+        if let Err(e) = api_keys::set_api_key_request_transform(id, value) {
+            tracing::error!("Failed to update API key request transform: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    // 更新账号池绑定
+    if let Some(pool) = req.pool {
+        let pool = pool.trim();
+        if let Err(e) = api_keys::set_api_key_pool(id, Some(pool).filter(|p| !p.is_empty())) {
+            tracing::error!("Failed to update API key pool: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    // 更新优先级
+    if let Some(priority) = req.priority {
+        if let Err(e) = api_keys::set_api_key_priority(id, priority) {
+            tracing::error!("Failed to update API key priority: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    // 更新过期时间
+    if let Some(expires_at) = req.expires_at {
+        if let Err(e) = api_keys::set_api_key_expires_at(id, Some(expires_at).filter(|v| *v > 0)) {
+            tracing::error!("Failed to update API key expiration: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     }
+
+    Ok(())
 }
 
-/// 删除 API Key
+/// 按名称创建或更新 API Key 的响应
+#[derive(Serialize)]
+pub struct UpsertApiKeyResponse {
+    /// 本次请求是否新建了 key
+    pub created: bool,
+    /// 完整 key，只在新建时返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(flatten)]
+    pub api_key: ApiKeyResponse,
+}
+
+/// 同名 upsert 串行执行，避免并发请求各自新建一个 key
+static UPSERT_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// 按名称幂等地创建或更新 API Key (PUT /api/keys/by-name/:name)
+///
+/// 请求体与 PUT /api/keys/:id 相同（忽略 name）；不存在时新建（201），存在时更新（200），
+/// 多个 key 同名时返回 409
+pub async fn upsert_api_key_by_name(
+    State(_state): State<AppState>,
+    Path(name): Path<String>,
+    Json(mut req): Json<UpdateApiKeyRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    req.name = None;
+    validate_update(&req)?;
+
+    let _guard = UPSERT_LOCK.lock().await;
+    let lookup = name.clone();
+    let mut matches = storage::blocking(move || api_keys::find_by_name(&lookup)).await.map_err(|e| {
+        tracing::error!("Failed to look up API key by name: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if matches.len() > 1 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    match matches.pop() {
+        Some(existing) => {
+            let id = existing.id.clone();
+            let updated = {
+                let (id, existing) = (id.clone(), existing.clone());
+                storage::blocking(move || {
+                    apply_update(&id, &existing, req)?;
+                    reload_key(&id)
+                })
+                .await?
+            };
+            let before = ApiKeyResponse::from(existing);
+            // 没有变化时不记录审计，重复执行同一配置不会刷屏
+            if audit::snapshot(&before) != audit::snapshot(&updated) {
+                audit::record("api_key.updated", Some(&id), audit::snapshot(&before), audit::snapshot(&updated));
+            }
+            Ok((StatusCode::OK, Json(UpsertApiKeyResponse { created: false, key: None, api_key: updated })))
+        }
+        None => {
+            let expires_at = req.expires_at.filter(|t| *t > 0);
+            let (key, created) = storage::blocking(move || {
+                let key = api_keys::create_api_key(&name, expires_at).map_err(|e| {
+                    tracing::error!("Failed to create API key: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+                apply_update(&key.id, &key, req)?;
+                let created = reload_key(&key.id)?;
+                Ok::<_, StatusCode>((key, created))
+            })
+            .await?;
+            audit::record("api_key.created", Some(&key.id), None, audit::snapshot(&created));
+            Ok((StatusCode::CREATED, Json(UpsertApiKeyResponse { created: true, key: Some(key.key), api_key: created })))
+        }
+    }
+}
+
+/// 删除 API Key（软删除，`deleted_key_retention_days` 内可通过 restore 恢复）
 pub async fn delete_api_key(
     State(_state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<impl IntoResponse, StatusCode> {
     // 检查 key 是否存在
-    match api_keys::get_api_key(&id) {
-        Ok(Some(_)) => {}
-        Ok(None) => return Err(StatusCode::NOT_FOUND),
+    let existing = fetch_key(&id).await?;
+
+    let target = id.clone();
+    match storage::blocking(move || api_keys::delete_api_key(&target)).await {
+        Ok(()) => {
+            audit::record("api_key.deleted", Some(&id), audit::snapshot(&ApiKeyResponse::from(existing)), None);
+            Ok(StatusCode::NO_CONTENT)
+        }
         Err(e) => {
-            tracing::error!("Failed to get API key: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            tracing::error!("Failed to delete API key: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
+}
 
-    match api_keys::delete_api_key(&id) {
-        Ok(()) => Ok(StatusCode::NO_CONTENT),
+/// 列出已删除、尚未清除的 API Keys (GET /api/keys/deleted)
+pub async fn list_deleted_api_keys(
+    State(_state): State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    match storage::blocking(api_keys::list_deleted_api_keys).await {
+        Ok(keys) => Ok(Json(keys.into_iter().map(ApiKeyResponse::from).collect::<Vec<_>>())),
         Err(e) => {
-            tracing::error!("Failed to delete API key: {}", e);
+            tracing::error!("Failed to list deleted API keys: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
 }
 
+/// 恢复已删除的 API Key (POST /api/keys/:id/restore)
+pub async fn restore_api_key(
+    State(_state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let target = id.clone();
+    let restored = storage::blocking(move || match api_keys::restore_api_key(&target) {
+        Ok(true) => reload_key(&target),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to restore API key: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    })
+    .await?;
+    audit::record("api_key.restored", Some(&id), None, audit::snapshot(&restored));
+    Ok(Json(restored))
+}
+
 /// 重新生成 API Key
 pub async fn regenerate_api_key(
     State(_state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<impl IntoResponse, StatusCode> {
     // 检查 key 是否存在
-    match api_keys::get_api_key(&id) {
-        Ok(Some(_)) => {}
-        Ok(None) => return Err(StatusCode::NOT_FOUND),
-        Err(e) => {
-            tracing::error!("Failed to get API key: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    }
+    let existing = fetch_key(&id).await?;
 
-    match api_keys::regenerate_api_key(&id) {
-        Ok(new_key) => Ok(Json(RegeneratedKeyResponse { key: new_key })),
+    let target = id.clone();
+    let regenerated = storage::blocking(move || {
+        let new_key = api_keys::regenerate_api_key(&target)?;
+        let after = api_keys::get_api_key(&target).ok().flatten().map(ApiKeyResponse::from);
+        Ok::<_, String>((new_key, after))
+    })
+    .await;
+    match regenerated {
+        Ok((new_key, after)) => {
+            audit::record(
+                "api_key.regenerated",
+                Some(&id),
+                audit::snapshot(&ApiKeyResponse::from(existing)),
+                after.as_ref().and_then(audit::snapshot),
+            );
+            Ok(Json(RegeneratedKeyResponse { key: new_key }))
+        }
         Err(e) => {
             tracing::error!("Failed to regenerate API key: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -189,20 +456,28 @@ pub async fn reset_api_key_usage(
     Path(id): Path<String>,
 ) -> Result<impl IntoResponse, StatusCode> {
     // 检查 key 是否存在
-    match api_keys::get_api_key(&id) {
-        Ok(Some(_)) => {}
-        Ok(None) => return Err(StatusCode::NOT_FOUND),
-        Err(e) => {
-            tracing::error!("Failed to get API key: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    }
+    let existing = fetch_key(&id).await?;
 
-    match api_keys::reset_usage(&id) {
-        Ok(()) => {
-            // 返回更新后的 key
-            match api_keys::get_api_key(&id) {
-                Ok(Some(key)) => Ok(Json(ApiKeyResponse::from(key))),
+    let target = id.clone();
+    let reset = storage::blocking(move || {
+        api_keys::reset_usage(&target)?;
+        // 返回更新后的 key
+        Ok::<_, String>(api_keys::get_api_key(&target))
+    })
+    .await;
+    match reset {
+        Ok(reloaded) => {
+            match reloaded {
+                Ok(Some(key)) => {
+                    let updated = ApiKeyResponse::from(key);
+                    audit::record(
+                        "api_key.usage_reset",
+                        Some(&id),
+                        audit::snapshot(&ApiKeyResponse::from(existing)),
+                        audit::snapshot(&updated),
+                    );
+                    Ok(Json(updated))
+                }
                 Ok(None) => Err(StatusCode::NOT_FOUND),
                 Err(e) => {
                     tracing::error!("Failed to get API key after reset: {}", e);
@@ -221,7 +496,7 @@ pub async fn reset_api_key_usage(
 pub async fn get_total_usage(
     State(_state): State<AppState>,
 ) -> Result<impl IntoResponse, StatusCode> {
-    match api_keys::get_total_usage() {
+    match storage::blocking(api_keys::get_total_usage).await {
         Ok(usage) => Ok(Json(usage)),
         Err(e) => {
             tracing::error!("Failed to get total usage: {}", e);
@@ -0,0 +1,54 @@
+//! 管理操作审计日志端点
+
+use axum::{
+    extract::Query,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use super::manage::error_response;
+use crate::modules::audit::{self, AuditEntry, AuditQuery};
+
+#[derive(Serialize)]
+pub struct AuditQueryResponse {
+    pub total: u64,
+    pub entries: Vec<AuditEntry>,
+}
+
+/// 分页浏览审计日志（最新在前）
+///
+/// GET /api/audit?from=&to=&actor=&action=&target=&limit=&offset=
+pub async fn list_audit(Query(query): Query<AuditQuery>) -> Response {
+    match audit::query(&query) {
+        Ok((total, entries)) => Json(AuditQueryResponse { total, entries }).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to query audit log: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, e)
+        }
+    }
+}
+
+/// 导出匹配条件的全部审计记录为 CSV
+///
+/// GET /api/audit/export?from=&to=&actor=&action=&target=
+pub async fn export_audit(Query(query): Query<AuditQuery>) -> Response {
+    let csv = match audit::export_csv(&query) {
+        Ok(csv) => csv,
+        Err(e) => {
+            tracing::error!("Failed to export audit log: {}", e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, e);
+        }
+    };
+
+    let filename = format!("antiproxy-audit-{}.csv", chrono::Utc::now().format("%Y%m%d"));
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        csv,
+    )
+        .into_response()
+}
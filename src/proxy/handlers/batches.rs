@@ -0,0 +1,378 @@
+// 批处理 API (/v1/batches)
+// 创建 / 查询 / 列表 / 取消。请求在后台逐条经内部路由执行，与 HTTP 请求走同一套 auth / monitor 管线
+// （限流、模型白名单、用量统计一致），并以低优先级排队；所有批处理共享 `batch.request_interval_ms` 的发送间隔，
+// 遇到 429 / 5xx 时退避重试
+use axum::{
+    body::Body,
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    response::IntoResponse,
+    routing::post,
+    Json, Router,
+};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tower::ServiceExt;
+
+use crate::proxy::batches::{
+    parse_input, Batch, BatchError, BatchLine, BatchRequest, BatchStatus, BATCHES, COMPLETION_WINDOW, SUPPORTED_ENDPOINTS,
+};
+use crate::proxy::files::FILES;
+use crate::proxy::ip_filter::ClientIp;
+use crate::proxy::middleware::auth::current_key_id;
+use crate::proxy::middleware::maintenance::MAINTENANCE;
+use crate::proxy::middleware::request_id::REQUEST_ID_HEADER;
+use crate::proxy::server::AppState;
+
+pub const BATCHES_PATH: &str = "/v1/batches";
+
+/// 单条结果响应体的读取上限
+const MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+/// 首次重试前的等待时间，之后每次翻倍
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(5);
+/// 维护期间检查是否已恢复的间隔
+const MAINTENANCE_POLL: Duration = Duration::from_secs(5);
+/// 列表默认 / 最大条数
+const DEFAULT_LIST_LIMIT: usize = 20;
+const MAX_LIST_LIMIT: usize = 100;
+
+/// 所有批处理共享的下一次可发送时间
+static NEXT_SEND: Lazy<tokio::sync::Mutex<tokio::time::Instant>> =
+    Lazy::new(|| tokio::sync::Mutex::new(tokio::time::Instant::now()));
+
+fn internal_error(e: String) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e)
+}
+
+fn not_found(id: &str) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, format!("No such batch: {}", id))
+}
+
+#[derive(Deserialize)]
+pub struct CreateBatchRequest {
+    pub input_file_id: String,
+    pub endpoint: String,
+    #[serde(default)]
+    pub completion_window: Option<String>,
+    #[serde(default)]
+    pub metadata: Option<Value>,
+}
+
+/// POST /v1/batches
+pub async fn handle_create(
+    State(state): State<AppState>,
+    client_ip: Option<axum::Extension<ClientIp>>,
+    Json(req): Json<CreateBatchRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    if !SUPPORTED_ENDPOINTS.contains(&req.endpoint.as_str()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Unsupported endpoint {}; supported: {}", req.endpoint, SUPPORTED_ENDPOINTS.join(", ")),
+        ));
+    }
+    if req.completion_window.as_deref().is_some_and(|w| w != COMPLETION_WINDOW) {
+        return Err((StatusCode::BAD_REQUEST, format!("completion_window must be {}", COMPLETION_WINDOW)));
+    }
+
+    let owner = current_key_id();
+    let client_ip = client_ip.map(|axum::Extension(ClientIp(ip))| ip);
+    let batch = tokio::task::spawn_blocking(move || -> Result<Batch, (StatusCode, String)> {
+        let (_, data) = FILES
+            .read(owner.as_deref(), &req.input_file_id)
+            .map_err(internal_error)?
+            .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("No such file: {}", req.input_file_id)))?;
+        let parsed = parse_input(&data, &req.endpoint, BATCHES.config().max_requests);
+        BATCHES
+            .create(owner.as_deref(), client_ip, &req.endpoint, &req.input_file_id, req.metadata, &parsed)
+            .map_err(internal_error)
+    })
+    .await
+    .map_err(|e| internal_error(e.to_string()))??;
+
+    if batch.status == BatchStatus::InProgress {
+        tracing::info!("[Batch] Created {} ({} requests to {})", batch.id, batch.request_counts.total, batch.endpoint);
+        spawn_runner(state, batch.id.clone());
+    } else {
+        tracing::info!("[Batch] Rejected input file {} for {}: {} problems", batch.input_file_id, batch.id, batch.errors.len());
+    }
+    Ok(Json(batch.to_object()))
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    pub after: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// GET /v1/batches?after=&limit=
+pub async fn handle_list(Query(query): Query<ListQuery>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let batches = BATCHES.list(current_key_id().as_deref()).map_err(internal_error)?;
+    let start = match query.after.as_deref() {
+        Some(after) => batches.iter().position(|b| b.id == after).map_or(batches.len(), |i| i + 1),
+        None => 0,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, MAX_LIST_LIMIT);
+    let page: Vec<&Batch> = batches.iter().skip(start).take(limit).collect();
+    Ok(Json(json!({
+        "object": "list",
+        "data": page.iter().map(|b| b.to_object()).collect::<Vec<_>>(),
+        "first_id": page.first().map(|b| &b.id),
+        "last_id": page.last().map(|b| &b.id),
+        "has_more": start + page.len() < batches.len(),
+    })))
+}
+
+/// GET /v1/batches/:batch_id
+pub async fn handle_get(Path(id): Path<String>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    match BATCHES.get(current_key_id().as_deref(), &id).map_err(internal_error)? {
+        Some(batch) => Ok(Json(batch.to_object())),
+        None => Err(not_found(&id)),
+    }
+}
+
+/// POST /v1/batches/:batch_id/cancel
+pub async fn handle_cancel(Path(id): Path<String>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    match BATCHES.cancel(current_key_id().as_deref(), &id).map_err(internal_error)? {
+        Some(batch) => Ok(Json(batch.to_object())),
+        None => Err(not_found(&id)),
+    }
+}
+
+/// 继续执行重启前未完成的批处理
+pub fn resume(state: AppState) {
+    match BATCHES.active() {
+        Ok(batches) => {
+            if !batches.is_empty() {
+                tracing::info!("[Batch] Resuming {} unfinished batches", batches.len());
+            }
+            for batch in batches {
+                spawn_runner(state.clone(), batch.id);
+            }
+        }
+        Err(e) => tracing::warn!("[Batch] Failed to load unfinished batches: {}", e),
+    }
+}
+
+fn spawn_runner(state: AppState, id: String) {
+    tokio::spawn(async move {
+        let outcome = match run(&state, &id).await {
+            Ok(expired) => Outcome::Finished { expired },
+            Err(e) => {
+                tracing::warn!("[Batch] {} failed: {}", id, e);
+                Outcome::Failed(e)
+            }
+        };
+        if let Err(e) = finalize(&id, outcome) {
+            tracing::error!("[Batch] Failed to finalize {}: {}", id, e);
+        }
+    });
+}
+
+/// 批处理内部请求经过与主路由相同的 request_id / auth / monitor 中间件（不经过响应缓存）
+fn batch_router(state: AppState) -> Router {
+    Router::new()
+        .route("/v1/chat/completions", post(super::openai::handle_chat_completions))
+        .route("/v1/completions", post(super::openai::handle_completions))
+        .route("/v1/embeddings", post(super::openai::handle_embeddings))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            crate::proxy::middleware::monitor::monitor_middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.security.clone(),
+            crate::proxy::middleware::auth_middleware,
+        ))
+        .layer(axum::middleware::from_fn(crate::proxy::middleware::request_id::request_id_middleware))
+        .with_state(state)
+}
+
+/// 创建批处理时使用的 API Key，执行时沿用其限流、模型白名单与用量统计
+async fn batch_credentials(state: &AppState, key_id: Option<&str>) -> Result<Option<String>, String> {
+    match key_id {
+        None => Ok(None),
+        Some("legacy") => Ok(Some(state.security.read().await.api_key.clone())),
+        Some(id) => crate::modules::storage::blocking({
+            let id = id.to_string();
+            move || crate::modules::api_keys::get_api_key(&id)
+        })
+        .await?
+        .map(|key| Some(key.key))
+        .ok_or_else(|| format!("API key {} used to create the batch no longer exists", id)),
+    }
+}
+
+/// 逐条执行尚无结果的请求；返回是否因超过完成时限而停止
+async fn run(state: &AppState, id: &str) -> Result<bool, String> {
+    let batch = BATCHES.load(id)?.ok_or_else(|| format!("batch {} not found", id))?;
+    let (_, data) = FILES
+        .read(batch.owner.as_deref(), &batch.input_file_id)?
+        .ok_or_else(|| format!("input file {} is no longer available", batch.input_file_id))?;
+    let lines = parse_input(&data, &batch.endpoint, usize::MAX)
+        .map_err(|_| format!("input file {} is no longer valid", batch.input_file_id))?;
+    let finished = BATCHES.finished_ids(id)?;
+    let api_key = batch_credentials(state, batch.owner.as_deref()).await?;
+    let router = batch_router(state.clone());
+
+    let mut expired = false;
+    for line in lines.iter().filter(|l| !finished.contains(&l.custom_id)) {
+        let current = BATCHES.load(id)?.ok_or_else(|| format!("batch {} not found", id))?;
+        if current.status == BatchStatus::Cancelling {
+            break;
+        }
+        if expired || chrono::Utc::now().timestamp() >= current.expires_at {
+            expired = true;
+            let error = json!({ "code": "batch_expired", "message": "This request could not be executed before the completion window expired" });
+            BATCHES.record_result(id, &result_line(line, None, Some(error)), false)?;
+            continue;
+        }
+        let (succeeded, response) = execute(&router, &batch, api_key.as_deref(), line).await;
+        BATCHES.record_result(id, &result_line(line, Some(response), None), succeeded)?;
+    }
+    Ok(expired)
+}
+
+fn result_line(line: &BatchLine, response: Option<Value>, error: Option<Value>) -> Value {
+    json!({
+        "id": format!("batch_req_{}", uuid::Uuid::new_v4().simple()),
+        "custom_id": line.custom_id,
+        "response": response,
+        "error": error,
+    })
+}
+
+/// 等待维护结束与全局发送间隔
+async fn pace() {
+    while MAINTENANCE.active().is_some() {
+        tokio::time::sleep(MAINTENANCE_POLL).await;
+    }
+    let interval = Duration::from_millis(BATCHES.config().request_interval_ms);
+    let mut next = NEXT_SEND.lock().await;
+    tokio::time::sleep_until(*next).await;
+    *next = tokio::time::Instant::now() + interval;
+}
+
+/// 执行一条请求，429 / 5xx 时退避重试；返回是否成功与结果中的 response 对象
+async fn execute(router: &Router, batch: &Batch, api_key: Option<&str>, line: &BatchLine) -> (bool, Value) {
+    let mut body = line.body.clone();
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert("stream".to_string(), Value::Bool(false));
+        obj.remove("stream_options");
+    }
+    let body = body.to_string();
+    let max_retries = BATCHES.config().max_retries;
+
+    let mut attempt = 0;
+    loop {
+        pace().await;
+        let mut request = Request::builder()
+            .method("POST")
+            .uri(&batch.endpoint)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.clone()))
+            .expect("static request parts are valid");
+        if let Some(value) = api_key.and_then(|k| HeaderValue::from_str(&format!("Bearer {}", k)).ok()) {
+            request.headers_mut().insert(header::AUTHORIZATION, value);
+        }
+        if let Some(ip) = batch.client_ip {
+            request.extensions_mut().insert(ClientIp(ip));
+        }
+        request.extensions_mut().insert(BatchRequest);
+
+        let response = match router.clone().oneshot(request).await {
+            Ok(r) => r,
+            Err(never) => match never {},
+        };
+        let status = response.status();
+        let request_id = response
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        let bytes = axum::body::to_bytes(response.into_body(), MAX_RESPONSE_BYTES).await.unwrap_or_default();
+
+        let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+        if retryable && attempt < max_retries {
+            let delay = RETRY_BASE_DELAY * 2u32.saturating_pow(attempt);
+            tracing::debug!("[Batch] {} {} got {}, retrying in {}s", batch.id, line.custom_id, status, delay.as_secs());
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+            continue;
+        }
+
+        let text = String::from_utf8_lossy(&bytes).to_string();
+        let body = serde_json::from_str::<Value>(&text).unwrap_or(Value::String(text));
+        let response = json!({ "status_code": status.as_u16(), "request_id": request_id, "body": body });
+        return (status.is_success(), response);
+    }
+}
+
+enum Outcome {
+    Finished { expired: bool },
+    Failed(String),
+}
+
+/// 把累积的结果转存为文件并写入最终状态
+fn finalize(id: &str, outcome: Outcome) -> Result<(), String> {
+    let mut was_cancelling = false;
+    let Some(batch) = BATCHES.update(id, |batch| {
+        batch.finalizing_at = Some(chrono::Utc::now().timestamp());
+        was_cancelling = batch.status == BatchStatus::Cancelling;
+        if !was_cancelling {
+            batch.status = BatchStatus::Finalizing;
+        }
+    })?
+    else {
+        return Ok(());
+    };
+
+    let (output, errors) = BATCHES.read_results(id)?;
+    let save = |data: &[u8], suffix: &str| -> Result<Option<String>, String> {
+        if data.is_empty() {
+            return Ok(None);
+        }
+        let filename = format!("{}_{}.jsonl", id, suffix);
+        let purpose = format!("batch_{}", suffix);
+        let file = FILES.save_generated(batch.owner.as_deref(), &filename, &purpose, "application/jsonl", data)?;
+        Ok(Some(file.id))
+    };
+    let output_file_id = save(&output, "output")?;
+    let error_file_id = save(&errors, "errors")?;
+
+    let finished = BATCHES.update(id, |batch| {
+        let now = chrono::Utc::now().timestamp();
+        batch.output_file_id = output_file_id;
+        batch.error_file_id = error_file_id;
+        match outcome {
+            _ if was_cancelling => {
+                batch.status = BatchStatus::Cancelled;
+                batch.cancelled_at = Some(now);
+            }
+            Outcome::Failed(message) => {
+                batch.status = BatchStatus::Failed;
+                batch.failed_at = Some(now);
+                batch.errors.push(BatchError { code: "batch_failed".to_string(), message, line: None });
+            }
+            Outcome::Finished { expired: true } => {
+                batch.status = BatchStatus::Expired;
+                batch.expired_at = Some(now);
+            }
+            Outcome::Finished { expired: false } => {
+                batch.status = BatchStatus::Completed;
+                batch.completed_at = Some(now);
+            }
+        }
+    })?;
+    BATCHES.clear_results(id);
+    if let Some(batch) = finished {
+        tracing::info!(
+            "[Batch] {} {:?}: {} completed, {} failed",
+            batch.id,
+            batch.status,
+            batch.request_counts.completed,
+            batch.request_counts.failed
+        );
+    }
+    Ok(())
+}
@@ -17,9 +17,11 @@ use crate::proxy::mappers::claude::{
 };
 use crate::proxy::session_manager::SessionManager;
 use crate::proxy::server::AppState;
+use crate::proxy::upstream::retry::{apply_jitter, RetryBudget};
+use crate::proxy::upstream::errors as upstream_errors;
+use crate::proxy::upstream::timeout::{is_timeout_error, timeout_response};
 use axum::http::HeaderMap;
 
-const MAX_RETRY_ATTEMPTS: usize = 3;
 const MIN_SIGNATURE_LENGTH: usize = 10;  // 最小有效签名长度
 
 // ===== Model Constants for Background Tasks =====
@@ -27,13 +29,13 @@ const MIN_SIGNATURE_LENGTH: usize = 10;  // 最小有效签名长度
 const BACKGROUND_MODEL_LITE: &str = "gemini-2.5-flash-lite";  // For simple/lightweight tasks
 const BACKGROUND_MODEL_STANDARD: &str = "gemini-2.5-flash";   // For complex background tasks
 
-// ===== Jitter Configuration =====
-// Jitter helps prevent thundering herd problem in retry scenarios
-const JITTER_FACTOR: f64 = 0.2;  // ±20% jitter
-
 // ===== Thinking 块处理辅助函数 =====
 
-use crate::proxy::mappers::claude::models::{ContentBlock, Message, MessageContent, SystemPrompt, UsageMetadata};
+use crate::proxy::mappers::claude::models::{ContentBlock, Message, MessageContent, SystemPrompt};
+use crate::proxy::common::token_count::{
+    estimate_text_tokens as estimate_tokens_from_text, extract_total_tokens, DOCUMENT_BLOCK_TOKENS,
+    IMAGE_BLOCK_TOKENS, MESSAGE_OVERHEAD_TOKENS,
+};
 
 /// 检查 thinking 块是否有有效签名
 fn has_valid_signature(block: &ContentBlock) -> bool {
@@ -161,15 +163,6 @@ fn remove_trailing_unsigned_thinking(blocks: &mut Vec<ContentBlock>) {
 
 // ===== 统一退避策略模块 =====
 
-/// Apply jitter to a delay value to prevent thundering herd
-/// Returns delay ± JITTER_FACTOR (e.g., 1000ms ± 20% = 800-1200ms)
-fn apply_jitter(delay_ms: u64) -> u64 {
-    use rand::Rng;
-    let jitter_range = (delay_ms as f64 * JITTER_FACTOR) as i64;
-    let jitter: i64 = rand::thread_rng().gen_range(-jitter_range..=jitter_range);
-    ((delay_ms as i64) + jitter).max(1) as u64
-}
-
 /// 重试策略枚举
 #[derive(Debug, Clone)]
 enum RetryStrategy {
@@ -186,6 +179,7 @@ enum RetryStrategy {
 /// 根据错误状态码和错误信息确定重试策略
 fn determine_retry_strategy(
     status_code: u16,
+    retry_after: Option<&str>,
     error_text: &str,
     retried_without_thinking: bool,
 ) -> RetryStrategy {
@@ -202,13 +196,15 @@ fn determine_retry_strategy(
 
         // 429 限流错误
         429 => {
-            // 优先使用服务端返回的 Retry-After
-            if let Some(delay_ms) = crate::proxy::upstream::retry::parse_retry_delay(error_text) {
-                let actual_delay = delay_ms.saturating_add(200).min(10_000);
-                RetryStrategy::FixedDelay(Duration::from_millis(actual_delay))
+            // 优先使用服务端返回的 Retry-After / retryDelay（总等待受重试预算限制）
+            if let Some(delay_ms) = crate::proxy::upstream::retry::retry_hint_ms(retry_after, error_text) {
+                RetryStrategy::FixedDelay(Duration::from_millis(delay_ms.saturating_add(200)))
             } else {
-                // 否则使用线性退避：1s, 2s, 3s
-                RetryStrategy::LinearBackoff { base_ms: 1000 }
+                // 否则使用指数退避：1s, 2s, 4s, 8s
+                RetryStrategy::ExponentialBackoff {
+                    base_ms: 1000,
+                    max_ms: 8000,
+                }
             }
         }
 
@@ -239,6 +235,7 @@ fn determine_retry_strategy(
 async fn apply_retry_strategy(
     strategy: RetryStrategy,
     attempt: usize,
+    budget: &mut RetryBudget,
     status_code: u16,
     trace_id: &str,
 ) -> bool {
@@ -257,12 +254,11 @@ async fn apply_retry_strategy(
                 trace_id,
                 status_code,
                 attempt + 1,
-                MAX_RETRY_ATTEMPTS,
+                budget.max_attempts(),
                 base_ms,
                 jittered_ms
             );
-            sleep(Duration::from_millis(jittered_ms)).await;
-            true
+            wait_within_budget(budget, jittered_ms, trace_id).await
         }
 
         RetryStrategy::LinearBackoff { base_ms } => {
@@ -273,12 +269,11 @@ async fn apply_retry_strategy(
                 trace_id,
                 status_code,
                 attempt + 1,
-                MAX_RETRY_ATTEMPTS,
+                budget.max_attempts(),
                 calculated_ms,
                 jittered_ms
             );
-            sleep(Duration::from_millis(jittered_ms)).await;
-            true
+            wait_within_budget(budget, jittered_ms, trace_id).await
         }
 
         RetryStrategy::ExponentialBackoff { base_ms, max_ms } => {
@@ -289,13 +284,26 @@ async fn apply_retry_strategy(
                 trace_id,
                 status_code,
                 attempt + 1,
-                MAX_RETRY_ATTEMPTS,
+                budget.max_attempts(),
                 calculated_ms,
                 jittered_ms
             );
-            sleep(Duration::from_millis(jittered_ms)).await;
+            wait_within_budget(budget, jittered_ms, trace_id).await
+        }
+    }
+}
+
+/// 在重试预算内等待；预算耗尽返回 false
+async fn wait_within_budget(budget: &mut RetryBudget, delay_ms: u64, trace_id: &str) -> bool {
+    match budget.reserve(delay_ms) {
+        Some(delay) => {
+            sleep(delay).await;
             true
         }
+        None => {
+            info!("[{}] Retry budget exhausted, stopping", trace_id);
+            false
+        }
     }
 }
 
@@ -321,7 +329,7 @@ pub async fn handle_messages(
     _headers: HeaderMap,
     Json(body): Json<Value>,
 ) -> Response {
-    tracing::error!(">>> [RED ALERT] handle_messages called! Body JSON len: {}", body.to_string().len());
+    debug!("handle_messages called, body JSON len: {}", body.to_string().len());
     
     // 生成随机 Trace ID 用户追踪
     let trace_id: String = rand::Rng::sample_iter(rand::thread_rng(), &rand::distributions::Alphanumeric)
@@ -348,6 +356,12 @@ pub async fn handle_messages(
 
     // [CRITICAL FIX] 过滤并修复 Thinking 块签名
     filter_invalid_thinking_blocks(&mut request.messages);
+
+    // 模型别名 (/api/model-aliases)
+    if let Some(alias) = crate::modules::model_aliases::resolve(&request.model) {
+        debug!("[{}] [Alias] {} -> {}", trace_id, alias.alias, alias.target);
+        alias.apply(&mut request.model, &mut request.temperature, &mut request.max_tokens);
+    }
     
     // Google Flow 继续使用 request 对象
     // (后续代码不需要再次 filter_invalid_thinking_blocks)
@@ -455,7 +469,8 @@ pub async fn handle_messages(
     let stable_session_id = crate::proxy::session_manager::SessionManager::extract_session_id(&request);
     
     let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    let mut retry_budget = upstream.retry_budget(pool_size).await;
+    let max_attempts = retry_budget.max_attempts();
 
     let mut last_error = String::new();
     let mut retried_without_thinking = false;
@@ -501,6 +516,28 @@ pub async fn handle_messages(
         // 0. 使用预计算的 session_id (在循环外部已计算，确保重试时不会改变)
         let session_id = Some(stable_session_id.as_str());
 
+        // 后台任务会降级到 Flash 模型，模型额度按实际发往上游的模型在选择账号之前检查
+        let background_task_type = detect_background_task_type(&request_for_body);
+        let upstream_model = match background_task_type {
+            Some(task_type) => select_background_model(task_type).to_string(),
+            None => mapped_model.clone(),
+        };
+        let model_permit = match crate::proxy::concurrency::CONCURRENCY.acquire_model(&upstream_model).await {
+            Ok(p) => p,
+            Err(e) => {
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    Json(json!({
+                        "type": "error",
+                        "error": {
+                            "type": "overloaded_error",
+                            "message": e
+                        }
+                    }))
+                ).into_response();
+            }
+        };
+
         let quota_group = "claude";
         // 使用 force_rotate_next 而不是 attempt > 0，这样只有在确定需要轮换时才轮换账号
         let force_rotate_token = force_rotate_next;
@@ -532,13 +569,13 @@ pub async fn handle_messages(
         let project_id = selected.project_id;
         let email = selected.email;
         let account_id = selected.account_id;
+        let client_profile = selected.client_profile;
 
         info!("✓ Using account: {} (type: {})", email, config.request_type);
         
         
         // ===== 【优化】后台任务智能检测与降级 =====
-        // 使用新的检测系统，支持 5 大类关键词和多 Flash 模型策略
-        let background_task_type = detect_background_task_type(&request_for_body);
+        // 使用新的检测系统，支持 5 大类关键词和多 Flash 模型策略（background_task_type 已在选号前检测）
         
         // 传递映射后的模型名
         let mut request_with_mapped = request_for_body.clone();
@@ -623,13 +660,34 @@ pub async fn handle_messages(
     let method = if is_stream { "streamGenerateContent" } else { "generateContent" };
     let query = if is_stream { Some("alt=sse") } else { None };
 
+    let permit = match crate::proxy::concurrency::CONCURRENCY.acquire(&account_id, &email).await {
+        Ok(p) => p.with_model(model_permit),
+        Err(e) => {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({
+                    "type": "error",
+                    "error": {
+                        "type": "overloaded_error",
+                        "message": e
+                    }
+                }))
+            ).into_response();
+        }
+    };
+
     let response = match upstream.call_v1_internal(
         method,
         &access_token,
         gemini_body,
-        query
+        query,
+        client_profile.as_deref(),
     ).await {
             Ok(r) => r,
+            Err(e) if is_timeout_error(&e) => {
+                error!("[{}] {}", trace_id, e);
+                return timeout_response(&e);
+            }
             Err(e) => {
                 last_error = e.clone();
                 debug!("Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
@@ -641,6 +699,7 @@ pub async fn handle_messages(
         
         // 成功
         if status.is_success() {
+            token_manager.record_success(&account_id);
             // 处理流式响应
             if request.stream {
                 let stream = response.bytes_stream();
@@ -660,7 +719,9 @@ pub async fn handle_messages(
                     .header(header::CONTENT_TYPE, "text/event-stream")
                     .header(header::CACHE_CONTROL, "no-cache")
                     .header(header::CONNECTION, "keep-alive")
-                    .body(Body::from_stream(sse_stream))
+                    .body(Body::from_stream(crate::proxy::common::sse::with_keepalive(
+                        crate::proxy::concurrency::hold_permit(sse_stream, permit),
+                    )))
                     .unwrap();
             } else {
                 // 处理非流式响应
@@ -720,7 +781,9 @@ pub async fn handle_messages(
         
         // 2. 获取错误文本并转移 Response 所有权
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status));
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        last_error = upstream_errors::describe(status_code, &error_text);
+        // 退避等待期间不占用并发名额
+        drop(permit);
         debug!("[{}] Upstream Error Response: {}", trace_id, error_text);
         
         // 3. 标记限流状态（用于 UI 显示）
@@ -780,8 +843,8 @@ pub async fn handle_messages(
             }
             
             // 使用统一退避策略
-            let strategy = determine_retry_strategy(status_code, &error_text, retried_without_thinking);
-            if apply_retry_strategy(strategy, attempt, status_code, &trace_id).await {
+            let strategy = determine_retry_strategy(status_code, retry_after.as_deref(), &error_text, retried_without_thinking);
+            if apply_retry_strategy(strategy, attempt, &mut retry_budget, status_code, &trace_id).await {
                 continue;
             }
         }
@@ -792,10 +855,10 @@ pub async fn handle_messages(
 
 
         // 确定重试策略
-        let strategy = determine_retry_strategy(status_code, &error_text, retried_without_thinking);
+        let strategy = determine_retry_strategy(status_code, retry_after.as_deref(), &error_text, retried_without_thinking);
 
         // 执行退避
-        if apply_retry_strategy(strategy, attempt, status_code, &trace_id).await {
+        if apply_retry_strategy(strategy, attempt, &mut retry_budget, status_code, &trace_id).await {
             // 判断是否需要轮换账号，并设置下一次循环的轮换标志
             if should_rotate_account(status_code) {
                 force_rotate_next = true;
@@ -808,7 +871,7 @@ pub async fn handle_messages(
         } else {
             // 不可重试的错误，直接返回
             error!("[{}] Non-retryable error {}: {}", trace_id, status_code, error_text);
-            return (status, error_text).into_response();
+            return upstream_errors::claude_error(status, &error_text);
         }
     }
     
@@ -816,7 +879,7 @@ pub async fn handle_messages(
         "type": "error",
         "error": {
             "type": "overloaded_error",
-            "message": format!("All {} attempts failed. Last error: {}", max_attempts, last_error)
+            "message": format!("All {} attempts failed. Last error: {}", max_attempts, upstream_errors::sanitize(&last_error))
         }
     }))).into_response()
 }
@@ -846,26 +909,6 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
     }))
 }
 
-const MESSAGE_OVERHEAD_TOKENS: u32 = 3;
-const IMAGE_BLOCK_TOKENS: u32 = 256;
-const DOCUMENT_BLOCK_TOKENS: u32 = 1024;
-
-fn estimate_tokens_from_text(text: &str) -> u32 {
-    let mut ascii_chars = 0u32;
-    let mut non_ascii_chars = 0u32;
-
-    for ch in text.chars() {
-        if ch.is_ascii() {
-            ascii_chars += 1;
-        } else {
-            non_ascii_chars += 1;
-        }
-    }
-
-    let ascii_tokens = (ascii_chars + 3) / 4;
-    ascii_tokens + non_ascii_chars
-}
-
 fn estimate_tokens_from_value(value: &Value) -> u32 {
     estimate_tokens_from_text(&value.to_string())
 }
@@ -936,28 +979,6 @@ fn estimate_tokens_from_gemini_body(body: &Value) -> u32 {
     }
 }
 
-fn extract_total_tokens(value: &Value) -> Option<u32> {
-    let raw = value.get("response").unwrap_or(value);
-
-    let total = raw
-        .get("totalTokens")
-        .or_else(|| raw.get("total_tokens"))
-        .or_else(|| raw.get("tokenCount"))
-        .and_then(|v| v.as_u64())
-        .and_then(|v| u32::try_from(v).ok());
-
-    if total.is_some() {
-        return total;
-    }
-
-    let usage = raw
-        .get("usageMetadata")
-        .and_then(|u| serde_json::from_value::<UsageMetadata>(u.clone()).ok());
-
-    usage
-        .and_then(|u| u.prompt_token_count.or(u.total_token_count))
-}
-
 /// 计算 tokens
 pub async fn handle_count_tokens(
     State(state): State<AppState>,
@@ -1031,7 +1052,7 @@ pub async fn handle_count_tokens(
                     estimate_tokens_from_gemini_body(&gemini_body).max(estimated_tokens);
                 let upstream = state.upstream.clone();
                 match upstream
-                    .call_v1_internal("countTokens", &selected.access_token, gemini_body, None)
+                    .call_v1_internal("countTokens", &selected.access_token, gemini_body, None, selected.client_profile.as_deref())
                     .await
                 {
                     Ok(resp) => {
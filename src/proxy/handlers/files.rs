@@ -0,0 +1,117 @@
+// 文件 API (/v1/files)
+// 兼容 OpenAI Files API 的上传 / 列表 / 查询 / 删除；上传的文件可在 chat 请求中以
+// `{"type": "file", "file": {"file_id": "file-..."}}` 引用
+use axum::{
+    extract::{Multipart, Path},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+
+use crate::proxy::files::{detect_mime, FILES};
+use crate::proxy::middleware::auth::current_key_id;
+
+pub const FILES_PATH: &str = "/v1/files";
+
+const DEFAULT_PURPOSE: &str = "user_data";
+
+fn internal_error(e: String) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e)
+}
+
+fn not_found(id: &str) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, format!("No such file: {}", id))
+}
+
+/// POST /v1/files (multipart: `file`, 可选 `purpose`)
+pub async fn handle_upload(mut multipart: Multipart) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let max_bytes = FILES.max_file_bytes();
+    if max_bytes == 0 {
+        return Err((StatusCode::FORBIDDEN, "File uploads are disabled".to_string()));
+    }
+
+    let mut upload = None;
+    let mut purpose = DEFAULT_PURPOSE.to_string();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Multipart error: {}", e)))?
+    {
+        match field.name().unwrap_or("") {
+            "file" => {
+                let filename = field.file_name().unwrap_or("upload").to_string();
+                let declared = field.content_type().map(str::to_string);
+                let data = field
+                    .bytes()
+                    .await
+                    .map_err(|e| (StatusCode::BAD_REQUEST, format!("File read error: {}", e)))?;
+                upload = Some((filename, declared, data));
+            }
+            "purpose" => {
+                if let Ok(value) = field.text().await {
+                    if !value.trim().is_empty() {
+                        purpose = value.trim().to_string();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let (filename, declared, data) = upload.ok_or_else(|| (StatusCode::BAD_REQUEST, "Missing file".to_string()))?;
+    if data.len() > max_bytes {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("File exceeds the {} byte limit", max_bytes),
+        ));
+    }
+    let mime_type = detect_mime(declared.as_deref(), &filename, &data).ok_or_else(|| {
+        (StatusCode::BAD_REQUEST, format!("Unsupported file type: {}", filename))
+    })?;
+
+    let owner = current_key_id();
+    let file = tokio::task::spawn_blocking(move || {
+        FILES.save(owner.as_deref(), &filename, &purpose, &mime_type, &data)
+    })
+    .await
+    .map_err(|e| internal_error(e.to_string()))?
+    .map_err(internal_error)?;
+    tracing::info!("[Files] Stored {} ({}, {} bytes)", file.id, file.mime_type, file.bytes);
+    Ok(Json(file.to_object()))
+}
+
+/// GET /v1/files
+pub async fn handle_list() -> Result<impl IntoResponse, (StatusCode, String)> {
+    let files = FILES.list(current_key_id().as_deref()).map_err(internal_error)?;
+    let data: Vec<_> = files.iter().map(|f| f.to_object()).collect();
+    Ok(Json(json!({ "object": "list", "data": data })))
+}
+
+/// GET /v1/files/:file_id
+pub async fn handle_get(Path(id): Path<String>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    match FILES.get(current_key_id().as_deref(), &id).map_err(internal_error)? {
+        Some(file) => Ok(Json(file.to_object())),
+        None => Err(not_found(&id)),
+    }
+}
+
+/// GET /v1/files/:file_id/content
+pub async fn handle_content(Path(id): Path<String>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let owner = current_key_id();
+    let lookup_id = id.clone();
+    let found = tokio::task::spawn_blocking(move || FILES.read(owner.as_deref(), &lookup_id))
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .map_err(internal_error)?;
+    let (file, data) = found.ok_or_else(|| not_found(&id))?;
+    Ok(([(header::CONTENT_TYPE, file.mime_type)], data))
+}
+
+/// DELETE /v1/files/:file_id
+pub async fn handle_delete(Path(id): Path<String>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    if !FILES.delete(current_key_id().as_deref(), &id).map_err(internal_error)? {
+        return Err(not_found(&id));
+    }
+    Ok(Json(json!({ "id": id, "object": "file", "deleted": true })))
+}
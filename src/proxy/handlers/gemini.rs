@@ -3,21 +3,29 @@ use axum::{extract::State, extract::{Json, Path}, http::StatusCode, response::In
 use serde_json::{json, Value};
 use tracing::{debug, error, info};
 
-use crate::proxy::mappers::gemini::{wrap_request, unwrap_response};
+use crate::proxy::common::error::error_response;
+use crate::proxy::mappers::gemini::{wrap_request, wrap_request_passthrough, unwrap_response};
 use crate::proxy::server::AppState;
 use crate::proxy::session_manager::SessionManager;
- 
-const MAX_RETRY_ATTEMPTS: usize = 3;
+use crate::proxy::upstream::errors as upstream_errors;
+use crate::proxy::upstream::timeout::{is_timeout_error, timeout_response};
+
+/// 原生透传模式开关（启动 / 热重载时由配置 gemini_passthrough 写入）
+static PASSTHROUGH: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+pub fn set_passthrough(enabled: bool) {
+    PASSTHROUGH.store(enabled, std::sync::atomic::Ordering::Relaxed);
+}
  
 /// 处理 generateContent 和 streamGenerateContent
 /// 路径参数: model_name, method (e.g. "gemini-pro", "generateContent")
 pub async fn handle_generate(
     State(state): State<AppState>,
     Path(model_action): Path<String>,
-    Json(body): Json<Value>
+    Json(mut body): Json<Value>
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     // 解析 model:method
-    let (model_name, method) = if let Some((m, action)) = model_action.rsplit_once(':') {
+    let (mut model_name, method) = if let Some((m, action)) = model_action.rsplit_once(':') {
         (m.to_string(), action.to_string())
     } else {
         (model_action, "generateContent".to_string())
@@ -25,17 +33,28 @@ pub async fn handle_generate(
 
     crate::modules::logger::log_info(&format!("Received Gemini request: {}/{}", model_name, method));
 
+    if method == "countTokens" {
+        let (total, _) = crate::proxy::handlers::token_count::count_tokens(&state, &model_name, &body, true).await;
+        return Ok(Json(json!({ "totalTokens": total })).into_response());
+    }
+
     // 1. 验证方法
     if method != "generateContent" && method != "streamGenerateContent" {
         return Err((StatusCode::BAD_REQUEST, format!("Unsupported method: {}", method)));
     }
     let is_stream = method == "streamGenerateContent";
 
+    // 模型别名 (/api/model-aliases)
+    if let Some(alias) = crate::modules::model_aliases::resolve(&model_name) {
+        apply_alias_to_body(&alias, &mut model_name, &mut body);
+    }
+
     // 2. 获取 UpstreamClient 和 TokenManager
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
     let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    let mut retry_budget = upstream.retry_budget(pool_size).await;
+    let max_attempts = retry_budget.max_attempts();
 
     // [CRITICAL FIX] 提前计算 session_id，确保重试时不会改变
     let stable_session_id = SessionManager::extract_gemini_session_id(&body, &model_name);
@@ -67,6 +86,12 @@ pub async fn handle_generate(
 
         let config = crate::proxy::mappers::common_utils::resolve_request_config(&model_name, &mapped_model, &tools_val);
 
+        // 模型额度在选择账号之前检查，等待期间不占用账号
+        let model_permit = crate::proxy::concurrency::CONCURRENCY
+            .acquire_model(&mapped_model)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e))?;
+
         // 4. 获取 Token (使用预计算的 session_id 和 force_rotate_next)
         let quota_group = "gemini";
         let selected = match token_manager
@@ -83,20 +108,35 @@ pub async fn handle_generate(
         let project_id = selected.project_id;
         let email = selected.email;
         let account_id = selected.account_id;
+        let client_profile = selected.client_profile;
 
         info!("✓ Using account: {} (type: {})", email, config.request_type);
 
         // 5. 包装请求 (project injection)
-        let wrapped_body = wrap_request(&body, &project_id, &mapped_model);
+        let wrapped_body = if PASSTHROUGH.load(std::sync::atomic::Ordering::Relaxed) {
+            wrap_request_passthrough(&body, &project_id, &mapped_model)
+        } else {
+            wrap_request(&body, &project_id, &mapped_model)
+        };
 
         // 5. 上游调用
         let query_string = if is_stream { Some("alt=sse") } else { None };
         let upstream_method = if is_stream { "streamGenerateContent" } else { "generateContent" };
 
+        let permit = crate::proxy::concurrency::CONCURRENCY
+            .acquire(&account_id, &email)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e))?
+            .with_model(model_permit);
+
         let response = match upstream
-            .call_v1_internal(upstream_method, &access_token, wrapped_body, query_string)
+            .call_v1_internal(upstream_method, &access_token, wrapped_body, query_string, client_profile.as_deref())
             .await {
                 Ok(r) => r,
+                Err(e) if is_timeout_error(&e) => {
+                    error!("Gemini {}", e);
+                    return Ok(timeout_response(&e));
+                }
                 Err(e) => {
                     last_error = e.clone();
                     debug!("Gemini Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
@@ -106,6 +146,7 @@ pub async fn handle_generate(
 
         let status = response.status();
         if status.is_success() {
+            token_manager.record_success(&account_id);
             // 6. 响应处理
             if is_stream {
                 use axum::body::Body;
@@ -169,7 +210,8 @@ pub async fn handle_generate(
                     }
                 };
                 
-                let body = Body::from_stream(stream);
+                let stream = crate::proxy::concurrency::hold_permit(stream, permit);
+                let body = Body::from_stream(crate::proxy::common::sse::with_keepalive(stream));
                 return Ok(Response::builder()
                     .header("Content-Type", "text/event-stream")
                     .header("Cache-Control", "no-cache")
@@ -192,7 +234,9 @@ pub async fn handle_generate(
         let status_code = status.as_u16();
         let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        last_error = upstream_errors::describe(status_code, &error_text);
+        // 退避等待期间不占用并发名额
+        drop(permit);
 
         // 判断是否应该轮换账号
         fn should_rotate_account(status_code: u16) -> bool {
@@ -224,15 +268,30 @@ pub async fn handle_generate(
                 "Gemini Upstream {} on account {}, will rotate: {}",
                 status_code, email, force_rotate_next
             );
+
+            // 401/403 换号即可，限流与服务端错误需要退避
+            if status_code != 401 && status_code != 403 && attempt + 1 < max_attempts {
+                match retry_budget.next_delay(attempt, retry_after.as_deref(), &error_text) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => {
+                        tracing::warn!("Gemini retry budget exhausted after {} attempts", attempt + 1);
+                        break;
+                    }
+                }
+            }
             continue;
         }
 
         // 404 等由于模型配置或路径错误的 HTTP 异常，直接报错，不进行无效轮换
         error!("Gemini Upstream non-retryable error {}: {}", status_code, error_text);
-        return Err((status, error_text));
+        return Ok(upstream_errors::gemini_error(status, &error_text));
     }
 
-    Ok((StatusCode::TOO_MANY_REQUESTS, format!("All accounts exhausted. Last error: {}", last_error)).into_response())
+    Ok(error_response(
+        StatusCode::TOO_MANY_REQUESTS,
+        "accounts_exhausted",
+        format!("All accounts exhausted. Last error: {}", upstream_errors::sanitize(&last_error)),
+    ))
 }
 
 pub async fn handle_list_models(State(state): State<AppState>) -> Result<impl IntoResponse, (StatusCode, String)> {
@@ -271,12 +330,63 @@ pub async fn handle_get_model(Path(model_name): Path<String>) -> impl IntoRespon
     }))
 }
 
-pub async fn handle_count_tokens(State(state): State<AppState>, Path(_model_name): Path<String>, Json(_body): Json<Value>) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let _ = state
-        .token_manager
-        .get_token("gemini", "agent", false, None)
-        .await
-        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)))?;
-    
-    Ok(Json(json!({"totalTokens": 0})))
+pub async fn handle_count_tokens(State(state): State<AppState>, Path(model_name): Path<String>, Json(body): Json<Value>) -> impl IntoResponse {
+    let (total, _) = crate::proxy::handlers::token_count::count_tokens(&state, &model_name, &body, true).await;
+    Json(json!({ "totalTokens": total }))
+}
+
+/// 将模型别名应用到 Gemini 原生请求（覆盖 generationConfig 中的 temperature / maxOutputTokens）
+fn apply_alias_to_body(alias: &crate::modules::model_aliases::ModelAlias, model_name: &mut String, body: &mut Value) {
+    let gen_config = body.get("generationConfig");
+    let mut temperature = gen_config
+        .and_then(|c| c.get("temperature"))
+        .and_then(|v| v.as_f64())
+        .map(|v| v as f32);
+    let mut max_tokens = gen_config
+        .and_then(|c| c.get("maxOutputTokens"))
+        .and_then(|v| v.as_u64())
+        .map(|v| v as u32);
+
+    debug!("[Alias] {} -> {}", alias.alias, alias.target);
+    alias.apply(model_name, &mut temperature, &mut max_tokens);
+
+    if alias.temperature.is_none() && alias.max_tokens.is_none() {
+        return;
+    }
+    let Some(obj) = body.as_object_mut() else {
+        return;
+    };
+    let gen_config = obj
+        .entry("generationConfig")
+        .or_insert_with(|| json!({}));
+    if let Some(t) = temperature {
+        gen_config["temperature"] = json!(t);
+    }
+    if let Some(m) = max_tokens {
+        gen_config["maxOutputTokens"] = json!(m);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_alias_to_body() {
+        let alias = crate::modules::model_aliases::ModelAlias {
+            alias: "writer".to_string(),
+            target: "gemini-2.5-pro".to_string(),
+            temperature: None,
+            max_tokens: Some(2048),
+            created_at: 0,
+            updated_at: 0,
+        };
+        let mut model = "writer".to_string();
+        let mut body = json!({ "contents": [], "generationConfig": { "temperature": 0.5 } });
+        apply_alias_to_body(&alias, &mut model, &mut body);
+
+        assert_eq!(model, "gemini-2.5-pro");
+        assert_eq!(body["generationConfig"]["temperature"], 0.5);
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 2048);
+    }
 }
@@ -0,0 +1,675 @@
+//! 请求日志查询与用量统计端点
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::post,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+use super::manage::error_response;
+use crate::modules::proxy_db::{self, LogQuery, QueryError, UsageBucket, UsageGroupBy, UsageQuery, UsageRow};
+use crate::modules::storage;
+use crate::proxy::monitor::{LogSummary, ProxyRequestLog, ReplayOf, TAGS_HEADER};
+use crate::proxy::server::AppState;
+
+#[derive(Serialize)]
+pub struct LogQueryResponse {
+    pub total: u64,
+    pub logs: Vec<ProxyRequestLog>,
+}
+
+/// 查询历史请求日志
+///
+/// GET /api/logs?from=&to=&key_id=&model=&status=&tag=key=value&client_ip=&request_id=&limit=&offset=
+pub async fn query_logs(Query(query): Query<LogQuery>) -> Response {
+    match storage::blocking(move || proxy_db::query_logs(&query)).await {
+        Ok((total, logs)) => Json(LogQueryResponse { total, logs }).into_response(),
+        Err(QueryError::BadRequest(e)) => error_response(StatusCode::BAD_REQUEST, e),
+        Err(QueryError::Storage(e)) => {
+            tracing::error!("Failed to query proxy logs: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, e)
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct UsageRowResponse {
+    #[serde(flatten)]
+    pub row: UsageRow,
+    /// 分组的显示名称（group_by=key 时为 API Key 名称）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub estimated_cost: f64,
+}
+
+#[derive(Serialize)]
+pub struct UsageStatsResponse {
+    pub bucket: UsageBucket,
+    pub group_by: UsageGroupBy,
+    pub rows: Vec<UsageRowResponse>,
+    pub totals: UsageTotals,
+}
+
+/// 按小时 / 天聚合的用量统计，可按 API Key、模型、账号或标签分组
+///
+/// GET /api/stats/usage?bucket=hour|day&group_by=none|key|model|account|tag&tag_key=&from=&to=&key_id=&model=&account=&tag=
+pub async fn usage_stats(Query(query): Query<UsageQuery>) -> Response {
+    let (bucket, group_by) = (query.bucket, query.group_by);
+    let rows = match storage::blocking(move || proxy_db::usage_stats(&query)).await {
+        Ok(rows) => rows,
+        Err(QueryError::BadRequest(e)) => return error_response(StatusCode::BAD_REQUEST, e),
+        Err(QueryError::Storage(e)) => {
+            tracing::error!("Failed to aggregate usage stats: {}", e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, e);
+        }
+    };
+
+    let key_names: HashMap<String, String> = if group_by == UsageGroupBy::Key {
+        storage::blocking(crate::modules::api_keys::list_api_keys)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|k| (k.id, k.name))
+            .collect()
+    } else {
+        Default::default()
+    };
+
+    let mut totals = UsageTotals::default();
+    for row in &rows {
+        totals.requests += row.requests;
+        totals.errors += row.errors;
+        totals.input_tokens += row.input_tokens;
+        totals.output_tokens += row.output_tokens;
+        totals.estimated_cost += row.estimated_cost;
+    }
+    if totals.requests > 0 {
+        totals.error_rate = totals.errors as f64 / totals.requests as f64;
+    }
+
+    let rows = rows
+        .into_iter()
+        .map(|row| UsageRowResponse {
+            label: row.group.as_ref().and_then(|g| key_names.get(g).cloned()),
+            row,
+        })
+        .collect();
+
+    Json(UsageStatsResponse {
+        bucket,
+        group_by,
+        rows,
+        totals,
+    })
+    .into_response()
+}
+
+/// 导出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportParams {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// 导出的单行日志（不含请求 / 响应体，附带 API Key 名称）
+#[derive(Serialize)]
+struct ExportRow {
+    id: String,
+    request_id: Option<String>,
+    timestamp: i64,
+    method: String,
+    url: String,
+    status: u16,
+    duration_ms: u64,
+    model: Option<String>,
+    key_id: Option<String>,
+    key_name: Option<String>,
+    client_ip: Option<String>,
+    account_email: Option<String>,
+    input_tokens: Option<u32>,
+    output_tokens: Option<u32>,
+    estimated_cost: Option<f64>,
+    finish_reason: Option<String>,
+    error: Option<String>,
+    tags: Option<BTreeMap<String, String>>,
+    /// 该行代表的请求数（日志采样时成功请求大于 1）
+    sample_weight: u64,
+}
+
+const CSV_HEADER: &str = "id,request_id,timestamp,time,method,url,status,duration_ms,model,key_id,key_name,client_ip,account_email,input_tokens,output_tokens,estimated_cost,finish_reason,error,tags,sample_weight\n";
+
+/// 标签还原为请求头格式 `key=value,key=value`
+fn format_tags(tags: &BTreeMap<String, String>) -> String {
+    tags.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join(",")
+}
+
+/// 攒够该大小再发送一个分块
+const EXPORT_CHUNK_BYTES: usize = 64 * 1024;
+
+impl ExportRow {
+    fn new(log: ProxyRequestLog, key_names: &HashMap<String, String>) -> Self {
+        Self {
+            key_name: log.key_id.as_ref().and_then(|id| key_names.get(id).cloned()),
+            id: log.id,
+            request_id: log.request_id,
+            timestamp: log.timestamp,
+            method: log.method,
+            url: log.url,
+            status: log.status,
+            duration_ms: log.duration,
+            model: log.model,
+            key_id: log.key_id,
+            client_ip: log.client_ip,
+            account_email: log.account_email,
+            input_tokens: log.input_tokens,
+            output_tokens: log.output_tokens,
+            estimated_cost: log.estimated_cost,
+            finish_reason: log.finish_reason,
+            error: log.error,
+            tags: log.tags,
+            sample_weight: log.sample_weight.unwrap_or(1),
+        }
+    }
+
+    fn write(&self, format: ExportFormat, out: &mut String) {
+        match format {
+            ExportFormat::Jsonl => {
+                if let Ok(line) = serde_json::to_string(self) {
+                    out.push_str(&line);
+                    out.push('\n');
+                }
+            }
+            ExportFormat::Csv => {
+                let opt = |v: &Option<String>| v.clone().unwrap_or_default();
+                let num = |v: Option<u32>| v.map(|n| n.to_string()).unwrap_or_default();
+                let time = chrono::DateTime::from_timestamp_millis(self.timestamp)
+                    .map(|t| t.to_rfc3339())
+                    .unwrap_or_default();
+                crate::utils::csv::push_row(
+                    out,
+                    &[
+                        self.id.clone(),
+                        opt(&self.request_id),
+                        self.timestamp.to_string(),
+                        time,
+                        self.method.clone(),
+                        self.url.clone(),
+                        self.status.to_string(),
+                        self.duration_ms.to_string(),
+                        opt(&self.model),
+                        opt(&self.key_id),
+                        opt(&self.key_name),
+                        opt(&self.client_ip),
+                        opt(&self.account_email),
+                        num(self.input_tokens),
+                        num(self.output_tokens),
+                        self.estimated_cost.map(|c| c.to_string()).unwrap_or_default(),
+                        opt(&self.finish_reason),
+                        opt(&self.error),
+                        self.tags.as_ref().map(format_tags).unwrap_or_default(),
+                        self.sample_weight.to_string(),
+                    ],
+                );
+            }
+        }
+    }
+}
+
+/// 流式导出历史请求日志（按时间正序）
+///
+/// GET /api/logs/export?format=csv|jsonl&from=&to=&key_id=&model=&account=&status=
+pub async fn export_logs(Query(params): Query<ExportParams>, Query(query): Query<LogQuery>) -> Response {
+    if let Err(e) = proxy_db::validate_query(&query) {
+        return error_response(StatusCode::BAD_REQUEST, e.to_string());
+    }
+
+    let format = params.format;
+    let key_names: HashMap<String, String> = storage::blocking(crate::modules::api_keys::list_api_keys)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|k| (k.id, k.name))
+        .collect();
+
+    // 在阻塞线程中逐行读取 SQLite，经有界通道分块发送；客户端断开后发送失败即停止读取
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<bytes::Bytes, std::io::Error>>(8);
+    tokio::task::spawn_blocking(move || {
+        let mut buf = String::new();
+        if format == ExportFormat::Csv {
+            buf.push_str(CSV_HEADER);
+        }
+        let mut client_gone = false;
+        let result = proxy_db::for_each_log(&query, |log| {
+            ExportRow::new(log, &key_names).write(format, &mut buf);
+            if buf.len() >= EXPORT_CHUNK_BYTES {
+                client_gone = tx.blocking_send(Ok(std::mem::take(&mut buf).into())).is_err();
+            }
+            !client_gone
+        });
+        match result {
+            Ok(()) if !buf.is_empty() => {
+                let _ = tx.blocking_send(Ok(buf.into()));
+            }
+            Ok(()) => {}
+            Err(e) => {
+                tracing::error!("Failed to export proxy logs: {}", e);
+                let _ = tx.blocking_send(Err(std::io::Error::other(e)));
+            }
+        }
+    });
+
+    let (content_type, extension) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+        ExportFormat::Jsonl => ("application/x-ndjson", "jsonl"),
+    };
+    let filename = format!("antiproxy-logs-{}.{}", chrono::Utc::now().format("%Y%m%d"), extension);
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)),
+    )
+        .into_response()
+}
+
+/// 实时日志最多回放的历史条数
+const MAX_STREAM_BACKLOG: usize = 200;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LogStreamQuery {
+    pub key_id: Option<String>,
+    pub model: Option<String>,
+    /// 精确状态码 (如 "429") 或状态类别 (如 "5xx")
+    pub status: Option<String>,
+    /// 连接后先推送内存中最近的 N 条匹配日志
+    #[serde(default)]
+    pub backlog: usize,
+}
+
+/// 实时日志的过滤条件
+#[derive(Debug, Default)]
+struct LogStreamFilter {
+    key_id: Option<String>,
+    model: Option<String>,
+    status: Option<(u16, u16)>,
+}
+
+impl LogStreamFilter {
+    fn new(query: &LogStreamQuery) -> Result<Self, String> {
+        let non_empty = |v: &Option<String>| v.clone().filter(|v| !v.is_empty());
+        let status = match query.status.as_deref().filter(|v| !v.is_empty()) {
+            Some(status) => Some(
+                proxy_db::parse_status_filter(status).ok_or_else(|| format!("Invalid status filter: {}", status))?,
+            ),
+            None => None,
+        };
+        Ok(Self { key_id: non_empty(&query.key_id), model: non_empty(&query.model), status })
+    }
+
+    fn matches(&self, log: &LogSummary) -> bool {
+        self.key_id.as_ref().is_none_or(|id| log.key_id.as_ref() == Some(id))
+            && self.model.as_ref().is_none_or(|model| log.model.as_ref() == Some(model))
+            && self.status.is_none_or(|(min, max)| (min..=max).contains(&log.status))
+    }
+}
+
+fn log_event(log: &LogSummary) -> Result<Event, axum::Error> {
+    Event::default().event("log").id(log.id.clone()).json_data(log)
+}
+
+/// 实时推送新的请求日志摘要（SSE，事件名 `log`）；
+/// 连接落后太多时推送 `lagged` 事件，数据为丢弃的条数
+///
+/// GET /api/logs/stream?key_id=&model=&status=&backlog=
+pub async fn stream_logs(State(state): State<AppState>, Query(query): Query<LogStreamQuery>) -> Response {
+    let filter = match LogStreamFilter::new(&query) {
+        Ok(filter) => filter,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+
+    let (recent, mut receiver) = state.monitor.subscribe().await;
+    let mut backlog: Vec<LogSummary> = recent
+        .into_iter()
+        .filter(|log| filter.matches(log))
+        .take(query.backlog.min(MAX_STREAM_BACKLOG))
+        .collect();
+    backlog.reverse();
+    let mut closed = state.monitor.tails_closed();
+
+    let stream = async_stream::stream! {
+        for log in &backlog {
+            yield log_event(log);
+        }
+        loop {
+            tokio::select! {
+                received = receiver.recv() => match received {
+                    Ok(log) if filter.matches(&log) => yield log_event(&log),
+                    Ok(_) => {}
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        yield Ok(Event::default().event("lagged").data(skipped.to_string()));
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                },
+                _ = async { closed.wait_for(|closed| *closed).await.is_ok() } => break,
+            }
+        }
+    };
+    Sse::new(stream).keep_alive(KeepAlive::default()).into_response()
+}
+
+/// 重放响应的收集上限
+const MAX_REPLAY_RESPONSE_BYTES: usize = 16 * 1024 * 1024;
+
+/// 可重放的生成类端点
+const REPLAYABLE_PATHS: [&str; 5] = [
+    "/v1/chat/completions",
+    "/v1/completions",
+    "/v1/responses",
+    "/v1/messages",
+    "/v1/embeddings",
+];
+
+#[derive(Deserialize, Default)]
+pub struct ReplayRequest {
+    /// 指定执行的账号（默认按正常调度选择）
+    pub account_id: Option<String>,
+    /// 替换原请求的模型
+    pub model: Option<String>,
+}
+
+fn is_replayable(path: &str) -> bool {
+    REPLAYABLE_PATHS.contains(&path) || (path.starts_with("/v1beta/models/") && path.contains(':'))
+}
+
+/// 替换模型：OpenAI / Claude 改写请求体的 `model`，Gemini 改写路径中的模型名
+fn apply_model_override(url: &str, body: &mut Value, model: &str) -> String {
+    if let Some(rest) = url.strip_prefix("/v1beta/models/") {
+        if let Some((_, action)) = rest.split_once(':') {
+            return format!("/v1beta/models/{}:{}", model, action);
+        }
+    }
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert("model".to_string(), Value::String(model.to_string()));
+    }
+    url.to_string()
+}
+
+/// 重放请求经过与主路由相同的 auth / monitor 中间件（不经过响应缓存）
+fn replay_router(state: AppState) -> Router {
+    Router::new()
+        .route("/v1/chat/completions", post(super::openai::handle_chat_completions))
+        .route("/v1/completions", post(super::openai::handle_completions))
+        .route("/v1/responses", post(super::openai::handle_completions))
+        .route("/v1/embeddings", post(super::openai::handle_embeddings))
+        .route("/v1/messages", post(super::claude::handle_messages))
+        .route("/v1beta/models/:model", post(super::gemini::handle_generate))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            crate::proxy::middleware::monitor::monitor_middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.security.clone(),
+            crate::proxy::middleware::auth_middleware,
+        ))
+        .with_state(state)
+}
+
+/// 原请求使用的 API Key，重放时沿用其限流、模型白名单与 IP 规则
+async fn replay_credentials(state: &AppState, key_id: Option<&str>) -> Result<Option<String>, Response> {
+    match key_id {
+        None => Ok(None),
+        Some("legacy") => Ok(Some(state.security.read().await.api_key.clone())),
+        Some(id) => match storage::blocking({
+            let id = id.to_string();
+            move || crate::modules::api_keys::get_api_key(&id)
+        })
+        .await
+        {
+            Ok(Some(key)) => Ok(Some(key.key)),
+            Ok(None) => Err(error_response(
+                StatusCode::CONFLICT,
+                format!("API key {} used by the original request no longer exists", id),
+            )),
+            Err(e) => Err(error_response(StatusCode::INTERNAL_SERVER_ERROR, e)),
+        },
+    }
+}
+
+/// 重放一条历史请求
+///
+/// POST /api/logs/:id/replay  body: {"account_id"?: "...", "model"?: "..."}
+///
+/// 原请求体经正常管线重新发送，新日志通过 `replay_of` 关联原日志；
+/// 记录时经过脱敏或截断的请求体与原请求不同，拒绝重放
+pub async fn replay_log(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    body: Option<Json<ReplayRequest>>,
+) -> Response {
+    let options = body.map(|Json(b)| b).unwrap_or_default();
+    let lookup = id.clone();
+    let original = match storage::blocking(move || proxy_db::get_log(&lookup)).await {
+        Ok(Some(log)) => log,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, format!("Log {} not found", id)),
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    if original.method != "POST" || !is_replayable(original.url.split('?').next().unwrap_or_default()) {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            format!("{} {} cannot be replayed", original.method, original.url),
+        );
+    }
+    if original.request_body_modified {
+        return error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The recorded request body was redacted or truncated and no longer matches the original request",
+        );
+    }
+    let Some(mut request_body) = original.request_body.as_deref().and_then(|b| serde_json::from_str::<Value>(b).ok()) else {
+        return error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The original request body was not recorded or is not valid JSON (it may have been truncated)",
+        );
+    };
+
+    let url = match options.model.as_deref().map(str::trim).filter(|m| !m.is_empty()) {
+        Some(model) => apply_model_override(&original.url, &mut request_body, model),
+        None => original.url.clone(),
+    };
+    let api_key = match replay_credentials(&state, original.key_id.as_deref()).await {
+        Ok(key) => key,
+        Err(response) => return response,
+    };
+
+    let log_id = uuid::Uuid::new_v4().to_string();
+    let mut request = match Request::builder()
+        .method("POST")
+        .uri(&url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(request_body.to_string()))
+    {
+        Ok(r) => r,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    if let Some(value) = api_key.and_then(|k| HeaderValue::from_str(&format!("Bearer {}", k)).ok()) {
+        request.headers_mut().insert(header::AUTHORIZATION, value);
+    }
+    for name in ["x-forwarded-for", "x-real-ip"] {
+        if let Some(value) = headers.get(name) {
+            request.headers_mut().insert(name, value.clone());
+        }
+    }
+    // 重放沿用原请求的标签，费用归属保持一致
+    if let Some(value) = original.tags.as_ref().and_then(|tags| HeaderValue::from_str(&format_tags(tags)).ok()) {
+        request.headers_mut().insert(TAGS_HEADER, value);
+    }
+    if let Some(peer) = peer {
+        request.extensions_mut().insert(peer);
+    }
+    request.extensions_mut().insert(ReplayOf { original_id: id.clone(), log_id: log_id.clone() });
+
+    let router = replay_router(state.clone());
+    let account_id = options.account_id.clone().filter(|a| !a.trim().is_empty());
+    // 在指定账号的作用域内读完响应体，流式响应中的重试同样只用该账号
+    let (status, content_type, bytes) = crate::proxy::token_manager::with_pinned_account(account_id, async move {
+        let response = match router.oneshot(request).await {
+            Ok(r) => r,
+            Err(never) => match never {},
+        };
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        let bytes = axum::body::to_bytes(response.into_body(), MAX_REPLAY_RESPONSE_BYTES).await;
+        (status, content_type, bytes)
+    })
+    .await;
+    let bytes = match bytes {
+        Ok(b) => b,
+        Err(e) => return error_response(StatusCode::BAD_GATEWAY, format!("Failed to read replay response: {}", e)),
+    };
+
+    crate::modules::audit::record(
+        "log.replayed",
+        Some(&id),
+        None,
+        Some(json!({ "url": url, "account_id": options.account_id, "model": options.model, "status": status.as_u16() })),
+    );
+
+    let text = String::from_utf8_lossy(&bytes).to_string();
+    let body = serde_json::from_str::<Value>(&text).unwrap_or(Value::String(text));
+    Json(json!({
+        "original_id": id,
+        "log_id": state.monitor.is_enabled().then_some(log_id),
+        "status": status.as_u16(),
+        "content_type": content_type,
+        "body": body,
+    }))
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_stream_filter() {
+        let log = |key: &str, model: &str, status: u16| {
+            LogSummary::from(&ProxyRequestLog {
+                key_id: Some(key.to_string()),
+                model: Some(model.to_string()),
+                status,
+                ..Default::default()
+            })
+        };
+        let query = |status: &str| LogStreamQuery {
+            key_id: Some("k1".to_string()),
+            model: Some(String::new()),
+            status: Some(status.to_string()),
+            backlog: 0,
+        };
+
+        let filter = LogStreamFilter::new(&query("5xx")).unwrap();
+        assert!(filter.matches(&log("k1", "gemini-2.5-pro", 503)));
+        assert!(!filter.matches(&log("k1", "gemini-2.5-pro", 200)));
+        assert!(!filter.matches(&log("k2", "gemini-2.5-pro", 500)));
+        assert!(LogStreamFilter::new(&query("")).unwrap().matches(&log("k1", "any", 200)));
+        assert!(LogStreamFilter::new(&query("9xx")).is_err());
+    }
+
+    #[test]
+    fn test_replay_model_override() {
+        let mut body = json!({"model": "gpt-4o", "messages": []});
+        assert_eq!(apply_model_override("/v1/chat/completions", &mut body, "gemini-2.5-pro"), "/v1/chat/completions");
+        assert_eq!(body["model"], "gemini-2.5-pro");
+
+        let mut body = json!({"contents": []});
+        let url = apply_model_override("/v1beta/models/gemini-2.5-flash:streamGenerateContent?alt=sse", &mut body, "gemini-2.5-pro");
+        assert_eq!(url, "/v1beta/models/gemini-2.5-pro:streamGenerateContent?alt=sse");
+        assert!(body.get("model").is_none());
+
+        assert!(is_replayable("/v1/messages"));
+        assert!(is_replayable("/v1beta/models/gemini-2.5-pro:generateContent"));
+        assert!(!is_replayable("/v1/models"));
+    }
+
+    #[test]
+    fn test_export_row_formats() {
+        let log = ProxyRequestLog {
+            id: "log-1".to_string(),
+            timestamp: 0,
+            method: "POST".to_string(),
+            url: "/v1/messages".to_string(),
+            status: 200,
+            duration: 42,
+            model: Some("claude-sonnet-4-5".to_string()),
+            error: None,
+            request_body: Some("{}".to_string()),
+            response_body: None,
+            input_tokens: Some(10),
+            output_tokens: Some(5),
+            key_id: Some("k1".to_string()),
+            account_email: Some("a@example.com".to_string()),
+            estimated_cost: Some(0.25),
+            finish_reason: Some("end_turn".to_string()),
+            replay_of: None,
+            tags: Some(BTreeMap::from([("project".to_string(), "alpha".to_string())])),
+            client_ip: Some("203.0.113.9".to_string()),
+            request_id: Some("req-1".to_string()),
+            usage_estimated: false,
+            request_body_modified: false,
+            sample_weight: None,
+        };
+        let key_names = HashMap::from([("k1".to_string(), "team, eu".to_string())]);
+        let row = ExportRow::new(log, &key_names);
+
+        let mut csv = String::new();
+        row.write(ExportFormat::Csv, &mut csv);
+        assert_eq!(
+            csv,
+            "log-1,req-1,0,1970-01-01T00:00:00+00:00,POST,/v1/messages,200,42,claude-sonnet-4-5,k1,\"team, eu\",203.0.113.9,a@example.com,10,5,0.25,end_turn,,project=alpha,1\n"
+        );
+        assert_eq!(CSV_HEADER.split(',').count(), csv.split(',').count() - 1);
+
+        let mut jsonl = String::new();
+        row.write(ExportFormat::Jsonl, &mut jsonl);
+        let value: serde_json::Value = serde_json::from_str(jsonl.trim_end()).unwrap();
+        assert_eq!(value["key_name"], "team, eu");
+        assert_eq!(value["input_tokens"], 10);
+        assert_eq!(value["tags"]["project"], "alpha");
+        assert!(value.get("request_body").is_none());
+    }
+}
@@ -7,11 +7,17 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use url::{form_urlencoded, Url};
 
 use crate::models::{Account, QuotaData, TokenData};
+use crate::proxy::config::ClientProfilesConfig;
+use crate::proxy::upstream::profiles::CLIENT_PROFILES;
+use crate::proxy::upstream::shadow::SHADOW;
+use crate::proxy::model_registry::MODEL_REGISTRY;
 use crate::proxy::server::AppState;
 use crate::proxy::server::OAuthStatus;
+use crate::modules::{audit, storage};
 use crate::modules::config as config_store;
 
 #[derive(Serialize)]
@@ -23,6 +29,20 @@ struct AccountsResponse {
 #[derive(Deserialize)]
 pub struct CreateAccountRequest {
     refresh_token: String,
+    /// 签发该 refresh_token 的 OAuth 客户端（`oauth_clients` 中的名称），默认内置客户端
+    #[serde(default)]
+    oauth_client: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ExportAccountsRequest {
+    passphrase: String,
+}
+
+#[derive(Deserialize)]
+pub struct ImportAccountsRequest {
+    passphrase: String,
+    archive: crate::modules::account_archive::AccountArchive,
 }
 
 #[derive(Deserialize)]
@@ -30,6 +50,17 @@ pub struct SetCurrentAccountRequest {
     account_id: String,
 }
 
+#[derive(Deserialize)]
+pub struct SetAccountPoolsRequest {
+    pools: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SetAccountClientProfileRequest {
+    /// profile 名，null 表示使用默认 profile
+    profile: Option<String>,
+}
+
 #[derive(Serialize)]
 struct RefreshQuotaResponse {
     account: Account,
@@ -61,8 +92,8 @@ pub struct MappingUpdateRequest {
 
 #[derive(Deserialize)]
 pub struct OAuthCallbackQuery {
-    code: Option<String>,
-    error: Option<String>,
+    pub code: Option<String>,
+    pub error: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -79,20 +110,52 @@ struct OAuthStatusResponse {
     auth_url: Option<String>,
 }
 
-fn error_response(status: StatusCode, message: impl Into<String>) -> Response {
+pub(crate) fn error_response(status: StatusCode, message: impl Into<String>) -> Response {
     (status, Json(json!({ "error": message.into() }))).into_response()
 }
 
+/// 审计快照只保留账号标识与状态，不含 token
+fn account_snapshot(account: &Account) -> Option<serde_json::Value> {
+    Some(json!({
+        "id": account.id,
+        "email": account.email,
+        "name": account.name,
+        "disabled": account.disabled,
+        "proxy_disabled": account.proxy_disabled,
+        "pools": account.pools,
+        "client_profile": account.client_profile,
+    }))
+}
+
+/// 读取账号；共享存储后端下是一次网络往返，放到阻塞线程池执行
+async fn load_account(account_id: &str) -> Result<Account, String> {
+    let account_id = account_id.to_string();
+    storage::blocking(move || crate::modules::account::load_account(&account_id)).await
+}
+
+/// 保存账号，同 [`load_account`] 在阻塞线程池执行
+async fn save_account(account: &Account) -> Result<(), String> {
+    let account = account.clone();
+    storage::blocking(move || crate::modules::account::save_account(&account)).await
+}
+
+/// 返回给管理接口的账号信息，去掉 OAuth token（导出请使用加密归档）
+fn without_tokens(mut account: Account) -> Account {
+    account.token.access_token.clear();
+    account.token.refresh_token.clear();
+    account
+}
+
 pub async fn list_accounts(State(_state): State<AppState>) -> Response {
-    let current_account_id = match crate::modules::account::get_current_account_id() {
+    let current_account_id = match storage::blocking(crate::modules::account::get_current_account_id).await {
         Ok(id) => id,
         Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
     };
 
-    match crate::modules::account::list_accounts() {
+    match storage::blocking(crate::modules::account::list_accounts).await {
         Ok(accounts) => Json(AccountsResponse {
             current_account_id,
-            accounts,
+            accounts: accounts.into_iter().map(without_tokens).collect(),
         })
         .into_response(),
         Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
@@ -100,15 +163,15 @@ pub async fn list_accounts(State(_state): State<AppState>) -> Response {
 }
 
 pub async fn get_account(Path(account_id): Path<String>) -> Response {
-    match crate::modules::account::load_account(&account_id) {
-        Ok(account) => Json(account).into_response(),
+    match load_account(&account_id).await {
+        Ok(account) => Json(without_tokens(account)).into_response(),
         Err(e) => error_response(StatusCode::NOT_FOUND, e),
     }
 }
 
 pub async fn get_current_account() -> Response {
-    match crate::modules::account::get_current_account() {
-        Ok(account) => Json(account).into_response(),
+    match storage::blocking(crate::modules::account::get_current_account).await {
+        Ok(account) => Json(account.map(without_tokens)).into_response(),
         Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
     }
 }
@@ -121,8 +184,18 @@ pub async fn set_current_account(
         return error_response(StatusCode::BAD_REQUEST, "account_id is required");
     }
 
-    match crate::modules::account::set_current_account_id(&payload.account_id) {
-        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+    let before = storage::blocking(crate::modules::account::get_current_account_id).await.ok().flatten();
+    let account_id = payload.account_id.clone();
+    match storage::blocking(move || crate::modules::account::set_current_account_id(&account_id)).await {
+        Ok(()) => {
+            audit::record(
+                "account.current_changed",
+                Some(&payload.account_id),
+                before.map(|id| json!({ "current_account_id": id })),
+                Some(json!({ "current_account_id": payload.account_id })),
+            );
+            StatusCode::NO_CONTENT.into_response()
+        }
         Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
     }
 }
@@ -135,7 +208,17 @@ pub async fn create_account(
         return error_response(StatusCode::BAD_REQUEST, "refresh_token is required");
     }
 
-    let token_res = match crate::modules::oauth::refresh_access_token(&payload.refresh_token).await
+    let oauth_client = payload.oauth_client.as_deref().map(str::trim).filter(|c| !c.is_empty());
+    if let Some(name) = oauth_client.filter(|n| *n != crate::modules::oauth::DEFAULT_CLIENT) {
+        let configured = config_store::load_web_config()
+            .map(|c| c.oauth_clients.iter().any(|client| client.name == name))
+            .unwrap_or(false);
+        if !configured {
+            return error_response(StatusCode::BAD_REQUEST, format!("Unknown oauth client: {}", name));
+        }
+    }
+
+    let token_res = match crate::modules::oauth::refresh_access_token(&payload.refresh_token, oauth_client).await
     {
         Ok(token) => token,
         Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
@@ -146,35 +229,40 @@ pub async fn create_account(
         Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
     };
 
-    let token = TokenData::new(
+    let project_id =
+        crate::proxy::project_resolver::discover_project_id(&token_res.access_token, &user_info.email).await;
+
+    let mut token = TokenData::new(
         token_res.access_token,
         payload.refresh_token,
         token_res.expires_in,
         Some(user_info.email.clone()),
-        None,
+        project_id,
         None,
     );
+    token.oauth_client = oauth_client.map(str::to_string);
 
-    let account = match crate::modules::account::upsert_account(
-        user_info.email.clone(),
-        user_info.get_display_name(),
-        token,
-    ) {
+    let (email, name) = (user_info.email.clone(), user_info.get_display_name());
+    let account = match storage::blocking(move || crate::modules::account::upsert_account(email, name, token)).await {
         Ok(account) => account,
         Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
     };
+    audit::record("account.added", Some(&account.id), None, account_snapshot(&account));
 
     let _ = state.token_manager.load_accounts().await;
 
-    Json(account).into_response()
+    Json(without_tokens(account)).into_response()
 }
 
 pub async fn delete_account(
     State(state): State<AppState>,
     Path(account_id): Path<String>,
 ) -> Response {
-    match crate::modules::account::delete_account(&account_id) {
+    let before = load_account(&account_id).await.ok();
+    let target = account_id.clone();
+    match storage::blocking(move || crate::modules::account::delete_account(&target)).await {
         Ok(()) => {
+            audit::record("account.removed", Some(&account_id), before.as_ref().and_then(account_snapshot), None);
             let _ = state.token_manager.load_accounts().await;
             StatusCode::NO_CONTENT.into_response()
         }
@@ -182,11 +270,66 @@ pub async fn delete_account(
     }
 }
 
+pub async fn set_account_pools(
+    State(state): State<AppState>,
+    Path(account_id): Path<String>,
+    Json(payload): Json<SetAccountPoolsRequest>,
+) -> Response {
+    let before = match load_account(&account_id).await {
+        Ok(account) => account,
+        Err(e) => return error_response(StatusCode::NOT_FOUND, e),
+    };
+    let target = account_id.clone();
+    match storage::blocking(move || crate::modules::account::set_account_pools(&target, &payload.pools)).await {
+        Ok(account) => {
+            audit::record(
+                "account.pools_changed",
+                Some(&account_id),
+                Some(json!({ "pools": before.pools })),
+                Some(json!({ "pools": account.pools })),
+            );
+            let _ = state.token_manager.load_accounts().await;
+            Json(without_tokens(account)).into_response()
+        }
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// 指定账号使用的上游客户端 profile (PUT /api/accounts/:id/client-profile)
+pub async fn set_account_client_profile(
+    State(state): State<AppState>,
+    Path(account_id): Path<String>,
+    Json(payload): Json<SetAccountClientProfileRequest>,
+) -> Response {
+    let before = match load_account(&account_id).await {
+        Ok(account) => account,
+        Err(e) => return error_response(StatusCode::NOT_FOUND, e),
+    };
+    let profile = payload.profile.map(|p| p.trim().to_string()).filter(|p| !p.is_empty());
+    if let Some(name) = profile.as_deref().filter(|name| !CLIENT_PROFILES.contains(name)) {
+        return error_response(StatusCode::BAD_REQUEST, format!("Unknown client profile: {}", name));
+    }
+    let target = account_id.clone();
+    match storage::blocking(move || crate::modules::account::set_account_client_profile(&target, profile)).await {
+        Ok(account) => {
+            audit::record(
+                "account.client_profile_changed",
+                Some(&account_id),
+                Some(json!({ "client_profile": before.client_profile })),
+                Some(json!({ "client_profile": account.client_profile })),
+            );
+            let _ = state.token_manager.load_accounts().await;
+            Json(without_tokens(account)).into_response()
+        }
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
 pub async fn refresh_account_quota(
     State(state): State<AppState>,
     Path(account_id): Path<String>,
 ) -> Response {
-    let mut account = match crate::modules::account::load_account(&account_id) {
+    let mut account = match load_account(&account_id).await {
         Ok(account) => account,
         Err(e) => return error_response(StatusCode::NOT_FOUND, e),
     };
@@ -214,7 +357,7 @@ pub async fn refresh_account_quota(
 
     account.update_quota(quota.clone());
 
-    if let Err(e) = crate::modules::account::save_account(&account) {
+    if let Err(e) = save_account(&account).await {
         return error_response(StatusCode::INTERNAL_SERVER_ERROR, e);
     }
 
@@ -223,8 +366,277 @@ pub async fn refresh_account_quota(
     Json(RefreshQuotaResponse { account, quota }).into_response()
 }
 
+/// 重新获取并保存账号的 Cloud Code 项目 ID (POST /api/accounts/:id/refresh-project)
+pub async fn refresh_account_project(
+    State(state): State<AppState>,
+    Path(account_id): Path<String>,
+) -> Response {
+    let mut account = match load_account(&account_id).await {
+        Ok(account) => account,
+        Err(e) => return error_response(StatusCode::NOT_FOUND, e),
+    };
+
+    let token = match crate::modules::oauth::ensure_fresh_token(&account.token).await {
+        Ok(token) => token,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+
+    let project_id = match crate::proxy::project_resolver::fetch_project_id(&token.access_token).await {
+        Ok(project_id) => project_id,
+        Err(e) => {
+            return error_response(
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to discover the Cloud Code project for {}: {}", account.email, e),
+            )
+        }
+    };
+
+    let before = account.token.project_id.clone();
+    account.token = token;
+    account.token.project_id = Some(project_id.clone());
+    if let Err(e) = save_account(&account).await {
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR, e);
+    }
+    if before.as_deref() != Some(project_id.as_str()) {
+        audit::record(
+            "account.project_changed",
+            Some(&account.id),
+            Some(json!({ "project_id": before })),
+            Some(json!({ "project_id": project_id })),
+        );
+    }
+
+    let _ = state.token_manager.load_accounts().await;
+
+    Json(json!({ "account_id": account.id, "project_id": project_id })).into_response()
+}
+
+/// 主动探测 token 池中的所有账号 (GET /api/accounts/health)
+pub async fn accounts_health(State(state): State<AppState>) -> Response {
+    let accounts = state.token_manager.probe_health(&state.upstream).await;
+    let healthy = accounts.iter().filter(|a| a.token_valid && !a.rate_limited).count();
+    Json(json!({
+        "total": accounts.len(),
+        "healthy": healthy,
+        "accounts": accounts,
+    }))
+    .into_response()
+}
+
+/// 导出所有账号为口令加密的归档文件
+pub async fn export_accounts(Json(payload): Json<ExportAccountsRequest>) -> Response {
+    let passphrase = payload.passphrase;
+    let archive = match tokio::task::spawn_blocking(move || crate::modules::account_archive::export_accounts(&passphrase)).await {
+        Ok(Ok(archive)) => archive,
+        Ok(Err(e)) => return error_response(StatusCode::BAD_REQUEST, e),
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+
+    let filename = format!("antiproxy-accounts-{}.json", chrono::Utc::now().format("%Y%m%d"));
+    (
+        [(
+            axum::http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )],
+        Json(archive),
+    )
+        .into_response()
+}
+
+/// 从加密归档导入账号（按邮箱合并）
+pub async fn import_accounts(State(state): State<AppState>, Json(payload): Json<ImportAccountsRequest>) -> Response {
+    let ImportAccountsRequest { passphrase, archive } = payload;
+    let summary = match tokio::task::spawn_blocking(move || {
+        crate::modules::account_archive::import_accounts(&archive, &passphrase)
+    })
+    .await
+    {
+        Ok(Ok(summary)) => summary,
+        Ok(Err(e)) => return error_response(StatusCode::BAD_REQUEST, e),
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    audit::record("account.imported", None, None, audit::snapshot(&summary));
+
+    let _ = state.token_manager.load_accounts().await;
+
+    Json(summary).into_response()
+}
+
+/// 上游模型列表缓存 (GET /api/models)
+pub async fn models_status() -> Response {
+    Json(MODEL_REGISTRY.status()).into_response()
+}
+
+/// 立即用所有账号重新获取上游模型列表 (POST /api/models/refresh)
+pub async fn refresh_models(State(state): State<AppState>) -> Response {
+    Json(MODEL_REGISTRY.refresh(&state.token_manager, &state.upstream).await).into_response()
+}
+
+/// 当前上游在途 / 排队请求数
+pub async fn concurrency_status() -> Response {
+    Json(crate::proxy::concurrency::CONCURRENCY.status()).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct UpdateEndpointsRequest {
+    endpoints: Vec<String>,
+}
+
+/// 影子流量对比统计 (GET /api/upstream/shadow)
+pub async fn shadow_status() -> Response {
+    Json(SHADOW.status()).into_response()
+}
+
+/// 清空影子流量统计 (DELETE /api/upstream/shadow)
+pub async fn reset_shadow_stats() -> Response {
+    SHADOW.reset();
+    audit::record("upstream.shadow_stats_reset", None, None, None);
+    Json(SHADOW.status()).into_response()
+}
+
+/// 上游端点配置与当前优先级 (GET /api/upstream/endpoints)
+pub async fn get_upstream_endpoints(State(state): State<AppState>) -> Response {
+    Json(state.upstream.endpoints_status().await).into_response()
+}
+
+/// 替换上游端点列表（新增 / 删除 / 调整顺序），写入配置并立即生效
+pub async fn update_upstream_endpoints(
+    State(state): State<AppState>,
+    Json(req): Json<UpdateEndpointsRequest>,
+) -> Response {
+    let endpoints = match crate::proxy::upstream::client::normalize_endpoints(&req.endpoints) {
+        Ok(endpoints) => endpoints,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+
+    let result = config_store::load_web_config().and_then(|mut config| {
+        let before = std::mem::replace(&mut config.upstream_endpoints, endpoints.clone());
+        config_store::save_web_config(&config).map(|_| before)
+    });
+    match result {
+        Ok(before) => audit::record(
+            "config.upstream_endpoints_changed",
+            None,
+            audit::snapshot(&before),
+            audit::snapshot(&endpoints),
+        ),
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+    // 显式调整顺序时丢弃之前自动提升的结果
+    if let Err(e) = state.upstream.update_endpoints(&endpoints, true).await {
+        return error_response(StatusCode::BAD_REQUEST, e);
+    }
+
+    Json(state.upstream.endpoints_status().await).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct PinEndpointsRequest {
+    /// 固定为首选的端点，null 表示取消固定
+    #[serde(default)]
+    url: Option<String>,
+    /// 禁用的端点，省略表示全部启用
+    #[serde(default)]
+    disabled: Vec<String>,
+}
+
+/// 运行时固定首选端点 / 禁用端点，替换当前设置，不写入配置，重启后清除
+pub async fn pin_upstream_endpoint(
+    State(state): State<AppState>,
+    Json(req): Json<PinEndpointsRequest>,
+) -> Response {
+    let before = state.upstream.endpoints_status().await;
+    if let Err(e) = state.upstream.set_endpoint_overrides(req.url.as_deref(), &req.disabled).await {
+        return error_response(StatusCode::BAD_REQUEST, e);
+    }
+    let after = state.upstream.endpoints_status().await;
+    audit::record(
+        "upstream.endpoints_pinned",
+        None,
+        Some(json!({ "pinned": before.pinned, "disabled": before.disabled })),
+        Some(json!({ "pinned": after.pinned, "disabled": after.disabled })),
+    );
+
+    Json(after).into_response()
+}
+
+/// 上游客户端 profile 配置 (GET /api/upstream/client-profiles)
+pub async fn get_client_profiles() -> Response {
+    match config_store::load_web_config() {
+        Ok(config) => Json(config.client_profiles).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// 替换上游客户端 profile 配置，写入配置并立即生效
+pub async fn update_client_profiles(Json(profiles): Json<ClientProfilesConfig>) -> Response {
+    if let Err(e) = crate::proxy::upstream::profiles::validate(&profiles) {
+        return error_response(StatusCode::BAD_REQUEST, e);
+    }
+
+    let result = config_store::load_web_config().and_then(|mut config| {
+        let before = std::mem::replace(&mut config.client_profiles, profiles.clone());
+        config_store::save_web_config(&config).map(|_| before)
+    });
+    match result {
+        Ok(before) => audit::record(
+            "config.client_profiles_changed",
+            None,
+            audit::snapshot(&before),
+            audit::snapshot(&profiles),
+        ),
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+    CLIENT_PROFILES.configure(&profiles);
+
+    Json(profiles).into_response()
+}
+
+/// 当前按模型计价配置 (GET /api/pricing)
+pub async fn get_pricing() -> Response {
+    match config_store::load_web_config() {
+        Ok(config) => Json(config.pricing).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// 替换按模型计价配置，写入配置并立即生效（只影响之后的请求）
+pub async fn update_pricing(
+    Json(pricing): Json<std::collections::HashMap<String, crate::proxy::config::ModelPricing>>,
+) -> Response {
+    if let Err(e) = crate::proxy::pricing::validate(&pricing) {
+        return error_response(StatusCode::BAD_REQUEST, e);
+    }
+
+    let result = config_store::load_web_config().and_then(|mut config| {
+        let before = std::mem::replace(&mut config.pricing, pricing.clone());
+        config_store::save_web_config(&config).map(|_| before)
+    });
+    match result {
+        Ok(before) => audit::record(
+            "config.pricing_changed",
+            None,
+            audit::snapshot(&before),
+            audit::snapshot(&pricing),
+        ),
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+    crate::proxy::pricing::PRICING.configure(&pricing);
+
+    Json(pricing).into_response()
+}
+
+/// 向所有配置的告警 Webhook 发送测试消息
+pub async fn test_alerts() -> Response {
+    let results = crate::modules::alerts::ALERTS.send_test().await;
+    if results.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "No alert webhooks configured");
+    }
+    Json(json!({ "results": results })).into_response()
+}
+
 pub async fn refresh_all_quotas(State(state): State<AppState>) -> Response {
-    let accounts = match crate::modules::account::list_accounts() {
+    let accounts = match storage::blocking(crate::modules::account::list_accounts).await {
         Ok(accounts) => accounts,
         Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
     };
@@ -245,7 +657,7 @@ pub async fn refresh_all_quotas(State(state): State<AppState>) -> Response {
                             }
                         }
                         account.update_quota(quota.clone());
-                        if let Err(e) = crate::modules::account::save_account(&account) {
+                        if let Err(e) = save_account(&account).await {
                             Err(e)
                         } else {
                             Ok(quota)
@@ -324,7 +736,8 @@ fn parse_oauth_query(query: &str) -> OAuthCallbackQuery {
     OAuthCallbackQuery { code, error }
 }
 
-fn parse_oauth_callback_input(raw: &str) -> Result<OAuthCallbackQuery, String> {
+/// 从粘贴的回调 URL（或授权码本身）中解析授权结果
+pub fn parse_oauth_callback_input(raw: &str) -> Result<OAuthCallbackQuery, String> {
     let trimmed = raw.trim();
     if trimmed.is_empty() {
         return Err("Callback URL cannot be empty".to_string());
@@ -352,25 +765,77 @@ fn parse_oauth_callback_input(raw: &str) -> Result<OAuthCallbackQuery, String> {
     })
 }
 
-async fn process_oauth_code(state: &AppState, code: &str) -> Result<String, String> {
-    let redirect_uri = if let Ok(value) = std::env::var("ANTI_PROXY_PUBLIC_URL") {
+/// OAuth 回调地址（设置 `ANTI_PROXY_PUBLIC_URL` 时使用公开地址）
+pub fn oauth_redirect_uri(port: u16) -> String {
+    if let Ok(value) = std::env::var("ANTI_PROXY_PUBLIC_URL") {
         format!("{}/oauth-callback", value.trim_end_matches('/'))
     } else {
-        format!("http://127.0.0.1:{}/oauth-callback", state.bind_port)
+        format!(
+            "http://127.0.0.1:{}{}",
+            port,
+            crate::proxy::middleware::base_path::url("/oauth-callback")
+        )
+    }
+}
+
+async fn process_oauth_code(state: &AppState, code: &str) -> Result<String, String> {
+    let redirect_uri = oauth_redirect_uri(state.bind_port);
+    let target = REAUTH_TARGET.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let account = match target {
+        Some(account_id) => {
+            let account = reauth_oauth_account(&account_id, code, &redirect_uri).await?;
+            *REAUTH_TARGET.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            account
+        }
+        None => add_oauth_account(code, &redirect_uri).await?,
     };
-    let token_res = crate::modules::oauth::exchange_code(code, &redirect_uri).await?;
+    let _ = state.token_manager.load_accounts().await;
+    Ok(account.email)
+}
+
+/// 重新授权已有账号：必须登录同一 Google 账号，更新 token 并恢复启用，账号 ID、账号池与用量历史保持不变
+async fn reauth_oauth_account(account_id: &str, code: &str, redirect_uri: &str) -> Result<Account, String> {
+    let before = load_account(account_id).await?;
+    let token_res = crate::modules::oauth::exchange_code(code, redirect_uri).await?;
+    let account = store_oauth_account(token_res, Some(&before.email)).await?;
+    audit::record("account.reauthorized", Some(&account.id), account_snapshot(&before), account_snapshot(&account));
+    Ok(account)
+}
+
+/// 用授权码换取 token 并保存账号（已存在的账号更新 token）
+pub async fn add_oauth_account(code: &str, redirect_uri: &str) -> Result<Account, String> {
+    let token_res = crate::modules::oauth::exchange_code(code, redirect_uri).await?;
+    save_oauth_account(token_res).await
+}
 
+/// 保存 OAuth 授权得到的账号（授权码与设备授权流程共用）
+pub async fn save_oauth_account(token_res: crate::modules::oauth::TokenResponse) -> Result<Account, String> {
+    let account = store_oauth_account(token_res, None).await?;
+    audit::record("account.added", Some(&account.id), None, account_snapshot(&account));
+    Ok(account)
+}
+
+/// 按邮箱保存授权得到的账号；`expected_email` 指定时登录的账号必须与之一致
+async fn store_oauth_account(
+    token_res: crate::modules::oauth::TokenResponse,
+    expected_email: Option<&str>,
+) -> Result<Account, String> {
     let refresh_token = token_res
         .refresh_token
         .ok_or_else(oauth_missing_refresh_message)?;
 
     let user_info = crate::modules::oauth::get_user_info(&token_res.access_token).await?;
+    if let Some(expected) = expected_email.filter(|e| !e.eq_ignore_ascii_case(&user_info.email)) {
+        return Err(format!(
+            "Signed in as {}, but this account is {}. Sign in with the same Google account to re-authorize it",
+            user_info.email, expected
+        ));
+    }
 
-    let project_id = crate::proxy::project_resolver::fetch_project_id(&token_res.access_token)
-        .await
-        .ok();
+    let project_id =
+        crate::proxy::project_resolver::discover_project_id(&token_res.access_token, &user_info.email).await;
 
-    let token_data = TokenData::new(
+    let mut token_data = TokenData::new(
         token_res.access_token,
         refresh_token,
         token_res.expires_in,
@@ -378,15 +843,11 @@ async fn process_oauth_code(state: &AppState, code: &str) -> Result<String, Stri
         project_id,
         None,
     );
+    token_data.oauth_client = token_res.oauth_client;
 
-    let account = crate::modules::account::upsert_account(
-        user_info.email.clone(),
-        user_info.get_display_name(),
-        token_data,
-    )?;
-
-    let _ = state.token_manager.load_accounts().await;
-    Ok(account.email)
+    let email = expected_email.map(str::to_string).unwrap_or(user_info.email.clone());
+    let name = user_info.get_display_name();
+    storage::blocking(move || crate::modules::account::upsert_account(email, name, token_data)).await
 }
 
 async fn update_oauth_state(
@@ -405,12 +866,17 @@ async fn update_oauth_state(
     };
 }
 
+/// 回调不需要登录，只接受管理员通过 prepare / reauth 发起、仍在等待中的授权
+async fn oauth_flow_waiting(state: &AppState) -> bool {
+    state.oauth_state.lock().await.status == "waiting"
+}
+
+const NO_PENDING_OAUTH: &str = "No OAuth authorization is in progress";
+
 pub async fn prepare_oauth(State(state): State<AppState>) -> Response {
-    let redirect_uri = if let Ok(value) = std::env::var("ANTI_PROXY_PUBLIC_URL") {
-        format!("{}/oauth-callback", value.trim_end_matches('/'))
-    } else {
-        format!("http://127.0.0.1:{}/oauth-callback", state.bind_port)
-    };
+    DEVICE_FLOW.fetch_add(1, Ordering::SeqCst);
+    *REAUTH_TARGET.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    let redirect_uri = oauth_redirect_uri(state.bind_port);
     let auth_url = crate::modules::oauth::get_auth_url(&redirect_uri);
     update_oauth_state(
         &state,
@@ -428,6 +894,93 @@ pub async fn prepare_oauth(State(state): State<AppState>) -> Response {
     .into_response()
 }
 
+/// 设备授权流程的代次：开始新的授权或取消时递增，旧的轮询任务随之结束
+static DEVICE_FLOW: AtomicU64 = AtomicU64::new(0);
+
+/// 正在重新授权的账号 ID（重新授权成功、开始其他授权或取消时清除）
+static REAUTH_TARGET: std::sync::Mutex<Option<String>> = std::sync::Mutex::new(None);
+
+/// 重新授权账号 (POST /api/accounts/:id/reauth)
+///
+/// 返回授权链接；用同一 Google 账号完成授权后（回调或粘贴回调 URL）原地更新 token 并恢复启用
+pub async fn reauth_account(State(state): State<AppState>, Path(account_id): Path<String>) -> Response {
+    let account = match load_account(&account_id).await {
+        Ok(account) => account,
+        Err(_) => return error_response(StatusCode::NOT_FOUND, "Account not found"),
+    };
+    DEVICE_FLOW.fetch_add(1, Ordering::SeqCst);
+    *REAUTH_TARGET.lock().unwrap_or_else(|e| e.into_inner()) = Some(account.id.clone());
+
+    let redirect_uri = oauth_redirect_uri(state.bind_port);
+    let auth_url = crate::modules::oauth::get_auth_url(&redirect_uri);
+    update_oauth_state(
+        &state,
+        "waiting",
+        Some(format!("Sign in as {} to re-authorize the account", account.email)),
+        None,
+        Some(auth_url.clone()),
+    )
+    .await;
+
+    Json(json!({
+        "auth_url": auth_url,
+        "redirect_uri": redirect_uri,
+        "email": account.email,
+    }))
+    .into_response()
+}
+
+/// 开始设备授权 (POST /api/oauth/device)
+///
+/// 返回验证地址与短码，在任意设备上输入短码即可完成授权；后台轮询结果，进度通过 /api/oauth/status 查询
+pub async fn start_device_oauth(State(state): State<AppState>) -> Response {
+    let device = match crate::modules::oauth::request_device_code().await {
+        Ok(device) => device,
+        Err(e) => {
+            update_oauth_state(&state, "error", Some(e.clone()), None, None).await;
+            return error_response(StatusCode::BAD_GATEWAY, e);
+        }
+    };
+    let generation = DEVICE_FLOW.fetch_add(1, Ordering::SeqCst) + 1;
+    *REAUTH_TARGET.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    update_oauth_state(
+        &state,
+        "waiting",
+        Some(format!("Enter code {} at {}", device.user_code, device.verification_url)),
+        None,
+        Some(device.verification_url.clone()),
+    )
+    .await;
+
+    let response = Json(json!({
+        "user_code": device.user_code,
+        "verification_url": device.verification_url,
+        "expires_in": device.expires_in,
+    }))
+    .into_response();
+
+    // 后台任务不在请求作用域内，沿用当前操作者记录审计日志
+    let (actor, client_ip) = audit::current_actor();
+    tokio::spawn(audit::with_actor(actor, client_ip, async move {
+        let superseded = || DEVICE_FLOW.load(Ordering::SeqCst) != generation;
+        let result = match crate::modules::oauth::poll_device_token(&device, superseded).await {
+            Ok(token) => save_oauth_account(token).await,
+            Err(e) => Err(e),
+        };
+        if superseded() {
+            return;
+        }
+        match result {
+            Ok(account) => {
+                let _ = state.token_manager.load_accounts().await;
+                update_oauth_state(&state, "success", Some("Account added".to_string()), Some(account.email), None).await;
+            }
+            Err(message) => update_oauth_state(&state, "error", Some(message), None, None).await,
+        }
+    }));
+    response
+}
+
 pub async fn oauth_status(State(state): State<AppState>) -> Response {
     let lock = state.oauth_state.lock().await.clone();
     let status = if lock.status.is_empty() {
@@ -445,6 +998,8 @@ pub async fn oauth_status(State(state): State<AppState>) -> Response {
 }
 
 pub async fn cancel_oauth(State(state): State<AppState>) -> Response {
+    DEVICE_FLOW.fetch_add(1, Ordering::SeqCst);
+    *REAUTH_TARGET.lock().unwrap_or_else(|e| e.into_inner()) = None;
     update_oauth_state(&state, "idle", None, None, None).await;
     StatusCode::NO_CONTENT.into_response()
 }
@@ -453,6 +1008,9 @@ pub async fn submit_oauth_callback(
     State(state): State<AppState>,
     Json(payload): Json<OAuthCallbackPayload>,
 ) -> Response {
+    if !oauth_flow_waiting(&state).await {
+        return error_response(StatusCode::CONFLICT, NO_PENDING_OAUTH);
+    }
     let query = if let Some(code) = payload.code.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
         OAuthCallbackQuery {
             code: Some(code.to_string()),
@@ -508,6 +1066,9 @@ pub async fn oauth_callback(
     State(state): State<AppState>,
     Query(query): Query<OAuthCallbackQuery>,
 ) -> Response {
+    if !oauth_flow_waiting(&state).await {
+        return Html(oauth_fail_html().to_string()).into_response();
+    }
     if let Some(error) = query.error {
         update_oauth_state(
             &state,
@@ -574,6 +1135,11 @@ pub async fn update_mappings(
         Ok(config) => config,
         Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
     };
+    let before = MappingResponse {
+        anthropic_mapping: config.anthropic_mapping.clone(),
+        openai_mapping: config.openai_mapping.clone(),
+        custom_mapping: config.custom_mapping.clone(),
+    };
 
     if let Some(mapping) = payload.anthropic_mapping {
         config.anthropic_mapping = mapping;
@@ -602,10 +1168,199 @@ pub async fn update_mappings(
         *m = config.custom_mapping.clone();
     }
 
-    Json(MappingResponse {
+    let after = MappingResponse {
         anthropic_mapping: config.anthropic_mapping,
         openai_mapping: config.openai_mapping,
         custom_mapping: config.custom_mapping,
-    })
+    };
+    audit::record("config.mappings_changed", None, audit::snapshot(&before), audit::snapshot(&after));
+    Json(after).into_response()
+}
+
+/// 重新加载配置文件 (POST /api/config/reload)
+pub async fn reload_config(State(state): State<AppState>) -> Response {
+    match crate::proxy::reload::reload_config(&state).await {
+        Ok(_) => {
+            // 配置文件由外部修改，无法得知变更前内容，只记录重载动作
+            audit::record("config.reloaded", None, None, None);
+            Json(json!({ "success": true })).into_response()
+        }
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// 检查配置 (POST /api/config/validate)
+///
+/// 请求体为待检查的完整配置 JSON；请求体为空时检查当前配置文件，不会修改或重载配置
+pub async fn validate_config(body: String) -> Response {
+    let problems = if body.trim().is_empty() {
+        match config_store::check_web_config() {
+            Ok(problems) => problems,
+            Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+        }
+    } else {
+        config_store::check_config(&body)
+    };
+    Json(json!({ "valid": problems.is_empty(), "problems": problems })).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct UpdateLogLevelRequest {
+    level: String,
+    /// 同时写入配置文件（默认仅在运行时生效）
+    #[serde(default)]
+    persist: bool,
+}
+
+fn log_level_response() -> Response {
+    let format = config_store::load_web_config().map(|c| c.log_format).unwrap_or_default();
+    Json(json!({
+        "level": crate::modules::logger::current_log_level(),
+        "format": format,
+        "env_override": crate::modules::logger::env_override_active(),
+    }))
+    .into_response()
+}
+
+/// 获取当前日志级别 (GET /api/logging/level)
+pub async fn get_log_level() -> Response {
+    log_level_response()
+}
+
+/// 运行时修改日志级别 (PUT /api/logging/level)
+pub async fn update_log_level(Json(req): Json<UpdateLogLevelRequest>) -> Response {
+    if crate::modules::logger::env_override_active() {
+        return error_response(StatusCode::CONFLICT, "Log level is controlled by the RUST_LOG environment variable");
+    }
+    let level = req.level.trim();
+    if level.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "level is required");
+    }
+    let before = crate::modules::logger::current_log_level();
+    if let Err(e) = crate::modules::logger::set_log_level(level) {
+        return error_response(StatusCode::BAD_REQUEST, e);
+    }
+
+    if req.persist {
+        let result = config_store::load_web_config().and_then(|mut config| {
+            config.log_level = level.to_string();
+            config_store::save_web_config(&config)
+        });
+        if let Err(e) = result {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, e);
+        }
+    }
+
+    audit::record(
+        "config.log_level_changed",
+        None,
+        Some(json!({ "level": before })),
+        Some(json!({ "level": level, "persist": req.persist })),
+    );
+    tracing::info!("Log level changed to '{}' (persist: {})", level, req.persist);
+    log_level_response()
+}
+
+#[derive(Deserialize, Default)]
+pub struct PauseRequest {
+    /// 返回给客户端的维护说明
+    #[serde(default)]
+    reason: Option<String>,
+    /// 暂停时长（秒），到期自动恢复；不填则一直暂停到手动恢复
+    #[serde(default)]
+    duration_seconds: Option<u64>,
+}
+
+/// 当前维护状态与尚未结束的计划窗口 (GET /api/proxy/maintenance)
+pub async fn maintenance_status() -> Response {
+    Json(crate::proxy::middleware::maintenance::MAINTENANCE.status()).into_response()
+}
+
+/// 暂停代理：模型请求返回 503，管理接口不受影响 (POST /api/proxy/pause)
+pub async fn pause_proxy(body: Option<Json<PauseRequest>>) -> Response {
+    let req = body.map(|Json(req)| req).unwrap_or_default();
+    if req.duration_seconds == Some(0) {
+        return error_response(StatusCode::BAD_REQUEST, "duration_seconds must be greater than 0");
+    }
+    let reason = req.reason.map(|r| r.trim().to_string()).filter(|r| !r.is_empty());
+    let active = crate::proxy::middleware::maintenance::MAINTENANCE.pause(reason, req.duration_seconds);
+    audit::record("proxy.paused", None, None, audit::snapshot(&active));
+    tracing::warn!("Proxy paused for maintenance (until: {:?})", active.until);
+    maintenance_status().await
+}
+
+/// 结束手动暂停并跳过当前的计划维护窗口 (POST /api/proxy/resume)
+pub async fn resume_proxy() -> Response {
+    let maintenance = &crate::proxy::middleware::maintenance::MAINTENANCE;
+    let before = maintenance.active();
+    maintenance.resume();
+    audit::record("proxy.resumed", None, before.as_ref().and_then(audit::snapshot), None);
+    tracing::info!("Proxy resumed");
+    maintenance_status().await
+}
+
+#[derive(Deserialize, Default)]
+pub struct CreateBackupRequest {
+    /// 加密口令，不填则使用配置的 `backup.passphrase`
+    #[serde(default)]
+    passphrase: Option<String>,
+    /// 是否包含请求日志库，不填则使用配置的 `backup.include_logs`
+    #[serde(default)]
+    include_logs: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreBackupRequest {
+    passphrase: String,
+    archive: crate::modules::backup::BackupArchive,
+}
+
+/// 导出数据库、配置与账号文件为口令加密的备份 (POST /api/backup)
+pub async fn create_backup(body: Option<Json<CreateBackupRequest>>) -> Response {
+    let req = body.map(|Json(req)| req).unwrap_or_default();
+    let config = crate::modules::backup::BACKUPS.config();
+    let passphrase = req.passphrase.filter(|p| !p.is_empty()).unwrap_or(config.passphrase);
+    let include_logs = req.include_logs.unwrap_or(config.include_logs);
+    let archive = match tokio::task::spawn_blocking(move || crate::modules::backup::create_backup(&passphrase, include_logs)).await {
+        Ok(Ok(archive)) => archive,
+        Ok(Err(e)) => return error_response(StatusCode::BAD_REQUEST, e),
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    audit::record(
+        "backup.created",
+        None,
+        None,
+        Some(json!({ "file_count": archive.file_count, "include_logs": include_logs })),
+    );
+
+    let filename = format!("antiproxy-backup-{}.json", chrono::Utc::now().format("%Y%m%d"));
+    (
+        [(
+            axum::http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )],
+        Json(archive),
+    )
+        .into_response()
+}
+
+/// 从加密备份恢复：校验并暂存，重启后生效 (POST /api/restore)
+pub async fn restore_backup(Json(payload): Json<RestoreBackupRequest>) -> Response {
+    let RestoreBackupRequest { passphrase, archive } = payload;
+    let summary = match tokio::task::spawn_blocking(move || crate::modules::backup::stage_restore(&archive, &passphrase)).await {
+        Ok(Ok(summary)) => summary,
+        Ok(Err(e)) => return error_response(StatusCode::BAD_REQUEST, e),
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    audit::record("backup.restored", None, None, audit::snapshot(&summary));
+    tracing::warn!("Backup from {} staged for restore; restart to apply", summary.created_at);
+
+    Json(json!({
+        "staged": true,
+        "created_at": summary.created_at,
+        "file_count": summary.files.len(),
+        "files": summary.files,
+        "restart_required": true,
+    }))
     .into_response()
 }
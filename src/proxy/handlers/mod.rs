@@ -2,9 +2,19 @@
 // 核心端点处理器模块
 
 pub mod api_keys;
+pub mod audit;
+pub mod batches;
 pub mod claude;
 pub mod openai;
 pub mod gemini;
 pub mod common;
+pub mod files;
 pub mod manage;
+pub mod logs;
+pub mod model_aliases;
+pub mod ollama;
+pub mod realtime;
+pub mod setup;
+pub mod token_count;
+pub mod usage;
 pub mod webauthn;
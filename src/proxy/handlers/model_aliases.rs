@@ -0,0 +1,98 @@
+//! 模型别名管理端点
+
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use super::manage::error_response;
+use crate::modules::audit;
+use crate::modules::model_aliases::{self, ModelAliasRequest};
+
+/// 列出所有模型别名
+///
+/// GET /api/model-aliases
+pub async fn list_model_aliases() -> Response {
+    match model_aliases::list_aliases() {
+        Ok(aliases) => Json(aliases).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list model aliases: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, e)
+        }
+    }
+}
+
+/// 获取单个模型别名
+///
+/// GET /api/model-aliases/:alias
+pub async fn get_model_alias(Path(alias): Path<String>) -> Response {
+    match model_aliases::get_alias(&alias) {
+        Ok(Some(a)) => Json(a).into_response(),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, format!("Model alias not found: {}", alias)),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// 创建模型别名（已存在时覆盖）
+///
+/// POST /api/model-aliases
+pub async fn create_model_alias(Json(req): Json<ModelAliasRequest>) -> Response {
+    let alias = req.alias.clone().unwrap_or_default();
+    save_alias(&alias, req, StatusCode::CREATED)
+}
+
+/// 更新模型别名
+///
+/// PUT /api/model-aliases/:alias
+pub async fn update_model_alias(Path(alias): Path<String>, Json(req): Json<ModelAliasRequest>) -> Response {
+    match model_aliases::get_alias(&alias) {
+        Ok(Some(_)) => save_alias(&alias, req, StatusCode::OK),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, format!("Model alias not found: {}", alias)),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+fn save_alias(alias: &str, req: ModelAliasRequest, status: StatusCode) -> Response {
+    if let Err(e) = model_aliases::validate(alias, &req.target) {
+        return error_response(StatusCode::BAD_REQUEST, e);
+    }
+    if let Some(t) = req.temperature {
+        if !(0.0..=2.0).contains(&t) {
+            return error_response(StatusCode::BAD_REQUEST, "temperature must be between 0 and 2");
+        }
+    }
+
+    let before = model_aliases::get_alias(alias).ok().flatten();
+    match model_aliases::upsert_alias(alias, &req.target, req.temperature, req.max_tokens.filter(|v| *v > 0)) {
+        Ok(saved) => {
+            audit::record(
+                "model_alias.saved",
+                Some(alias),
+                before.as_ref().and_then(audit::snapshot),
+                audit::snapshot(&saved),
+            );
+            (status, Json(saved)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to save model alias: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, e)
+        }
+    }
+}
+
+/// 删除模型别名
+///
+/// DELETE /api/model-aliases/:alias
+pub async fn delete_model_alias(Path(alias): Path<String>) -> Response {
+    let before = model_aliases::get_alias(&alias).ok().flatten();
+    match model_aliases::delete_alias(&alias) {
+        Ok(true) => {
+            audit::record("model_alias.deleted", Some(&alias), before.as_ref().and_then(audit::snapshot), None);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => error_response(StatusCode::NOT_FOUND, format!("Model alias not found: {}", alias)),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
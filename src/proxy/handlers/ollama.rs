@@ -0,0 +1,745 @@
+// Ollama API 兼容 (POST /api/chat, POST /api/generate, GET /api/tags)
+// 只支持 Ollama 协议的工具可以直接把 AntiProxy 当作 Ollama 后端使用
+//
+// - 请求转换为 OpenAI chat completions 请求体，经内部路由执行，auth + monitor 中间件与 /v1/chat/completions 一致
+//   （限流 / 模型白名单 / 用量统计 / 请求日志都按一次 chat completions 请求计算）
+// - 响应转换回 Ollama 格式：非流式为单个 JSON，流式（Ollama 默认）为逐行 JSON (application/x-ndjson)，最后一行 `done: true`
+// - 模型名末尾的 `:latest` 标签会被去掉，/api/tags 列出与 /v1/models 相同的模型
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use futures::StreamExt;
+use serde_json::{json, Map, Value};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::Instant;
+use tower::ServiceExt;
+
+use crate::proxy::ip_filter::ClientIp;
+use crate::proxy::server::AppState;
+
+pub const CHAT_PATH: &str = "/api/chat";
+pub const GENERATE_PATH: &str = "/api/generate";
+pub const TAGS_PATH: &str = "/api/tags";
+
+/// 内部转发的路径
+const CHAT_COMPLETIONS_PATH: &str = "/v1/chat/completions";
+
+/// /api/tags 中的模型修改时间（与 /v1/models 的 created 一致）
+const MODELS_MODIFIED_AT: &str = "2024-02-01T00:00:00Z";
+
+/// 转发到内部请求时不保留的请求头（请求体已被改写）
+const DROPPED_HEADERS: [header::HeaderName; 4] =
+    [header::HOST, header::CONTENT_LENGTH, header::CONTENT_TYPE, header::ACCEPT_ENCODING];
+
+/// 原始连接的对端地址
+type PeerInfo = Option<ConnectInfo<SocketAddr>>;
+
+/// Ollama 协议端点（不含 /api/tags）：由处理器转发给内部路由，在内部路由中鉴权与记录日志
+pub fn is_forwarded_path(path: &str) -> bool {
+    matches!(path, CHAT_PATH | GENERATE_PATH)
+}
+
+/// Ollama 协议端点：使用 API Key 鉴权，不属于管理接口
+pub fn is_ollama_path(path: &str) -> bool {
+    is_forwarded_path(path) || path == TAGS_PATH
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endpoint {
+    Chat,
+    Generate,
+}
+
+/// 转换后的请求
+#[derive(Debug)]
+struct Converted {
+    /// 客户端请求的模型名（原样写回响应）
+    model: String,
+    stream: bool,
+    /// OpenAI chat completions 请求体；为 None 表示只是预加载模型（空 prompt / messages）
+    body: Option<Value>,
+}
+
+pub async fn handle_chat(
+    State(state): State<AppState>,
+    peer: PeerInfo,
+    client_ip: Option<axum::Extension<ClientIp>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    handle(Endpoint::Chat, state, peer, client_ip, headers, body).await
+}
+
+pub async fn handle_generate(
+    State(state): State<AppState>,
+    peer: PeerInfo,
+    client_ip: Option<axum::Extension<ClientIp>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    handle(Endpoint::Generate, state, peer, client_ip, headers, body).await
+}
+
+/// GET /api/tags
+pub async fn handle_tags(State(state): State<AppState>) -> Response {
+    use crate::proxy::common::model_mapping::get_all_dynamic_models;
+
+    let model_ids = crate::proxy::model_registry::visible_models(
+        get_all_dynamic_models(&state.openai_mapping, &state.custom_mapping, &state.anthropic_mapping).await,
+    )
+    .await;
+    let models: Vec<Value> = model_ids.iter().map(|id| tag_entry(id)).collect();
+    Json(json!({ "models": models })).into_response()
+}
+
+fn tag_entry(id: &str) -> Value {
+    let family = id.split(['-', '/']).next().unwrap_or(id);
+    json!({
+        "name": id,
+        "model": id,
+        "modified_at": MODELS_MODIFIED_AT,
+        "size": 0,
+        "digest": "",
+        "details": {
+            "parent_model": "",
+            "format": "",
+            "family": family,
+            "families": [family],
+            "parameter_size": "",
+            "quantization_level": ""
+        }
+    })
+}
+
+fn ollama_error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(json!({ "error": message.into() }))).into_response()
+}
+
+fn created_at() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+}
+
+fn internal_router(state: AppState) -> Router {
+    Router::new()
+        .route(CHAT_COMPLETIONS_PATH, post(super::openai::handle_chat_completions))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            crate::proxy::middleware::monitor::monitor_middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.security.clone(),
+            crate::proxy::middleware::auth_middleware,
+        ))
+        .with_state(state)
+}
+
+async fn handle(
+    endpoint: Endpoint,
+    state: AppState,
+    peer: PeerInfo,
+    client_ip: Option<axum::Extension<ClientIp>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let started = Instant::now();
+    let request: Value = match serde_json::from_slice(&body) {
+        Ok(value) => value,
+        Err(e) => return ollama_error(StatusCode::BAD_REQUEST, format!("invalid request body: {}", e)),
+    };
+    let converted = match endpoint {
+        Endpoint::Chat => convert_chat_request(&request),
+        Endpoint::Generate => convert_generate_request(&request),
+    };
+    let converted = match converted {
+        Ok(c) => c,
+        Err(e) => return ollama_error(StatusCode::BAD_REQUEST, e),
+    };
+
+    let Some(openai_body) = converted.body else {
+        // Ollama 客户端用空请求预加载模型，直接返回完成
+        let mut done = final_line(endpoint, &converted.model, "load", None, started);
+        if endpoint == Endpoint::Chat {
+            done["message"] = json!({ "role": "assistant", "content": "" });
+        }
+        return Json(done).into_response();
+    };
+
+    let mut inner = Request::builder()
+        .method("POST")
+        .uri(CHAT_COMPLETIONS_PATH)
+        .body(Body::from(openai_body.to_string()))
+        .expect("static request parts are valid");
+    let inner_headers = inner.headers_mut();
+    inner_headers.extend(headers);
+    for name in &DROPPED_HEADERS {
+        inner_headers.remove(name);
+    }
+    inner_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    if let Some(peer) = peer {
+        inner.extensions_mut().insert(peer);
+    }
+    if let Some(axum::Extension(ip)) = client_ip {
+        inner.extensions_mut().insert(ip);
+    }
+
+    let response = match internal_router(state).oneshot(inner).await {
+        Ok(r) => r,
+        Err(never) => match never {},
+    };
+
+    let status = response.status();
+    if !status.is_success() {
+        let bytes = axum::body::to_bytes(response.into_body(), 1024 * 1024).await.unwrap_or_default();
+        return ollama_error(status, error_message(&bytes));
+    }
+
+    let is_sse = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("text/event-stream"));
+    if is_sse {
+        return ndjson_response(ndjson_stream(endpoint, converted.model, response.into_body(), started));
+    }
+
+    let bytes = match axum::body::to_bytes(response.into_body(), usize::MAX).await {
+        Ok(b) => b,
+        Err(e) => return ollama_error(StatusCode::BAD_GATEWAY, e.to_string()),
+    };
+    let completion: Value = match serde_json::from_slice(&bytes) {
+        Ok(v) => v,
+        Err(e) => return ollama_error(StatusCode::BAD_GATEWAY, format!("invalid upstream response: {}", e)),
+    };
+    let converted_response = convert_completion(endpoint, &converted.model, &completion, started);
+    if converted.stream {
+        // 流式请求命中非流式响应（如响应缓存）时，以单行 NDJSON 返回
+        let line = Bytes::from(format!("{}\n", converted_response));
+        return ndjson_response(Box::pin(futures::stream::once(async move { Ok::<_, String>(line) })));
+    }
+    Json(converted_response).into_response()
+}
+
+fn ndjson_response(stream: std::pin::Pin<Box<dyn futures::Stream<Item = Result<Bytes, String>> + Send>>) -> Response {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from_stream(stream))
+        .expect("static response parts are valid")
+}
+
+/// 错误响应体中的错误信息（OpenAI `{"error":{"message"}}`、`{"error":"..."}` 或纯文本）
+fn error_message(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes).trim().to_string();
+    let Ok(value) = serde_json::from_str::<Value>(&text) else {
+        return text;
+    };
+    let error = value.get("error").unwrap_or(&value);
+    error
+        .get("message")
+        .and_then(Value::as_str)
+        .or_else(|| error.as_str())
+        .map(str::to_string)
+        .unwrap_or(text)
+}
+
+/// 去掉 Ollama 默认的 `:latest` 标签
+fn upstream_model(model: &str) -> &str {
+    model.strip_suffix(":latest").unwrap_or(model)
+}
+
+/// Ollama 的图片是不带 MIME 类型的 base64，按文件头推断
+fn image_data_url(data: &str) -> String {
+    let mime = if data.starts_with("/9j/") {
+        "image/jpeg"
+    } else if data.starts_with("R0lGOD") {
+        "image/gif"
+    } else if data.starts_with("UklGR") {
+        "image/webp"
+    } else {
+        "image/png"
+    };
+    format!("data:{};base64,{}", mime, data)
+}
+
+/// 文本与图片合成 OpenAI 消息内容
+fn message_content(text: &str, images: Option<&Value>) -> Value {
+    let images: Vec<&str> = images
+        .and_then(Value::as_array)
+        .map(|a| a.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    if images.is_empty() {
+        return json!(text);
+    }
+    let mut blocks = Vec::new();
+    if !text.is_empty() {
+        blocks.push(json!({ "type": "text", "text": text }));
+    }
+    for image in images {
+        blocks.push(json!({ "type": "image_url", "image_url": { "url": image_data_url(image) } }));
+    }
+    Value::Array(blocks)
+}
+
+/// `options` / `format` / `tools` 等公共字段
+fn apply_common_fields(request: &Value, body: &mut Map<String, Value>) {
+    if let Some(options) = request.get("options") {
+        for key in ["temperature", "top_p", "top_k", "seed", "presence_penalty", "frequency_penalty"] {
+            if let Some(v) = options.get(key).filter(|v| v.is_number()) {
+                body.insert(key.to_string(), v.clone());
+            }
+        }
+        // num_predict 为 -1 / -2 表示不限制
+        if let Some(v) = options.get("num_predict").and_then(Value::as_i64).filter(|v| *v > 0) {
+            body.insert("max_tokens".to_string(), json!(v));
+        }
+        if let Some(v) = options.get("stop").filter(|v| v.is_array() || v.is_string()) {
+            body.insert("stop".to_string(), v.clone());
+        }
+    }
+    // `format` 为 "json" 或 JSON Schema
+    match request.get("format") {
+        Some(format) if format == "json" => {
+            body.insert("response_format".to_string(), json!({ "type": "json_object" }));
+        }
+        Some(schema) if schema.is_object() => {
+            body.insert(
+                "response_format".to_string(),
+                json!({ "type": "json_schema", "json_schema": { "name": "response", "schema": schema } }),
+            );
+        }
+        _ => {}
+    }
+    if let Some(tools) = request.get("tools").filter(|t| t.as_array().is_some_and(|a| !a.is_empty())) {
+        body.insert("tools".to_string(), tools.clone());
+    }
+}
+
+fn request_header(request: &Value) -> Result<(String, bool), String> {
+    let model = request
+        .get("model")
+        .and_then(Value::as_str)
+        .filter(|m| !m.trim().is_empty())
+        .ok_or_else(|| "model is required".to_string())?;
+    let stream = request.get("stream").and_then(Value::as_bool).unwrap_or(true);
+    Ok((model.to_string(), stream))
+}
+
+fn openai_body(model: &str, stream: bool, messages: Vec<Value>, request: &Value) -> Value {
+    let mut body = Map::new();
+    body.insert("model".to_string(), json!(upstream_model(model)));
+    body.insert("stream".to_string(), json!(stream));
+    body.insert("messages".to_string(), Value::Array(messages));
+    apply_common_fields(request, &mut body);
+    Value::Object(body)
+}
+
+/// POST /api/chat 请求转换
+fn convert_chat_request(request: &Value) -> Result<Converted, String> {
+    let (model, stream) = request_header(request)?;
+    let messages = request.get("messages").and_then(Value::as_array).cloned().unwrap_or_default();
+    if messages.is_empty() {
+        return Ok(Converted { model, stream, body: None });
+    }
+
+    // Ollama 的工具调用没有 ID：为 assistant 的调用依次分配 ID，后续 tool 消息按顺序对应
+    let mut pending_calls: VecDeque<(String, String)> = VecDeque::new();
+    let mut next_call = 0usize;
+    let mut converted = Vec::with_capacity(messages.len());
+    for message in &messages {
+        let role = message.get("role").and_then(Value::as_str).unwrap_or("user");
+        let text = message.get("content").and_then(Value::as_str).unwrap_or("");
+        let mut out = Map::new();
+        out.insert("role".to_string(), json!(role));
+        out.insert("content".to_string(), message_content(text, message.get("images")));
+
+        if let Some(calls) = message.get("tool_calls").and_then(Value::as_array) {
+            let calls: Vec<Value> = calls
+                .iter()
+                .filter_map(|call| call.get("function"))
+                .map(|function| {
+                    let id = format!("call_{}", next_call);
+                    next_call += 1;
+                    let name = function.get("name").and_then(Value::as_str).unwrap_or("").to_string();
+                    pending_calls.push_back((id.clone(), name.clone()));
+                    let arguments = match function.get("arguments") {
+                        Some(Value::String(s)) => s.clone(),
+                        Some(args) => args.to_string(),
+                        None => "{}".to_string(),
+                    };
+                    json!({ "id": id, "type": "function", "function": { "name": name, "arguments": arguments } })
+                })
+                .collect();
+            if !calls.is_empty() {
+                out.insert("tool_calls".to_string(), Value::Array(calls));
+            }
+        }
+
+        if role == "tool" {
+            let tool_name = message.get("tool_name").or_else(|| message.get("name")).and_then(Value::as_str);
+            let call = match tool_name {
+                Some(name) => pending_calls
+                    .iter()
+                    .position(|(_, n)| n == name)
+                    .and_then(|i| pending_calls.remove(i))
+                    .or_else(|| Some((String::new(), name.to_string()))),
+                None => pending_calls.pop_front(),
+            };
+            if let Some((id, name)) = call {
+                if !id.is_empty() {
+                    out.insert("tool_call_id".to_string(), json!(id));
+                }
+                out.insert("name".to_string(), json!(name));
+            }
+            out.insert("content".to_string(), json!(text));
+        }
+        converted.push(Value::Object(out));
+    }
+
+    let body = openai_body(&model, stream, converted, request);
+    Ok(Converted { model, stream, body: Some(body) })
+}
+
+/// POST /api/generate 请求转换
+fn convert_generate_request(request: &Value) -> Result<Converted, String> {
+    let (model, stream) = request_header(request)?;
+    let prompt = request.get("prompt").and_then(Value::as_str).unwrap_or("");
+    let has_images = request.get("images").and_then(Value::as_array).is_some_and(|a| !a.is_empty());
+    if prompt.is_empty() && !has_images {
+        return Ok(Converted { model, stream, body: None });
+    }
+
+    let mut messages = Vec::new();
+    if let Some(system) = request.get("system").and_then(Value::as_str).filter(|s| !s.is_empty()) {
+        messages.push(json!({ "role": "system", "content": system }));
+    }
+    messages.push(json!({ "role": "user", "content": message_content(prompt, request.get("images")) }));
+
+    let body = openai_body(&model, stream, messages, request);
+    Ok(Converted { model, stream, body: Some(body) })
+}
+
+/// Ollama 的 done_reason：截断为 `length`，其余为 `stop`
+fn done_reason(finish_reason: Option<&str>) -> &'static str {
+    match finish_reason.map(str::to_ascii_lowercase).as_deref() {
+        Some("length" | "max_tokens") => "length",
+        _ => "stop",
+    }
+}
+
+/// OpenAI tool_calls 转为 Ollama 格式（arguments 为对象）
+fn ollama_tool_calls(calls: &[Value]) -> Vec<Value> {
+    calls
+        .iter()
+        .filter_map(|call| call.get("function"))
+        .map(|function| {
+            let arguments = match function.get("arguments") {
+                Some(Value::String(s)) => serde_json::from_str(s).unwrap_or_else(|_| json!({})),
+                Some(args) => args.clone(),
+                None => json!({}),
+            };
+            json!({ "function": { "name": function.get("name").cloned().unwrap_or(json!("")), "arguments": arguments } })
+        })
+        .collect()
+}
+
+/// 一行 Ollama 响应（不含 message / response 内容）
+fn base_line(model: &str, done: bool) -> Value {
+    json!({ "model": model, "created_at": created_at(), "done": done })
+}
+
+/// 结束行：done_reason、耗时与 token 数
+fn final_line(endpoint: Endpoint, model: &str, reason: &str, usage: Option<&Value>, started: Instant) -> Value {
+    let mut line = base_line(model, true);
+    line["done_reason"] = json!(reason);
+    if endpoint == Endpoint::Generate {
+        line["response"] = json!("");
+    }
+    line["total_duration"] = json!(started.elapsed().as_nanos() as u64);
+    if let Some(usage) = usage {
+        if let Some(n) = usage.get("prompt_tokens").and_then(Value::as_u64) {
+            line["prompt_eval_count"] = json!(n);
+        }
+        if let Some(n) = usage.get("completion_tokens").and_then(Value::as_u64) {
+            line["eval_count"] = json!(n);
+        }
+    }
+    line
+}
+
+/// 写入 message（/api/chat）或 response（/api/generate）
+fn set_output(endpoint: Endpoint, line: &mut Value, content: &str, tool_calls: Vec<Value>) {
+    match endpoint {
+        Endpoint::Chat => {
+            let mut message = json!({ "role": "assistant", "content": content });
+            if !tool_calls.is_empty() {
+                message["tool_calls"] = Value::Array(tool_calls);
+            }
+            line["message"] = message;
+        }
+        Endpoint::Generate => line["response"] = json!(content),
+    }
+}
+
+/// 非流式 chat.completion 响应转换
+fn convert_completion(endpoint: Endpoint, model: &str, completion: &Value, started: Instant) -> Value {
+    let choice = completion.get("choices").and_then(|c| c.get(0));
+    let message = choice.and_then(|c| c.get("message"));
+    let content = message.and_then(|m| m.get("content")).and_then(Value::as_str).unwrap_or("");
+    let tool_calls = message
+        .and_then(|m| m.get("tool_calls"))
+        .and_then(Value::as_array)
+        .map(|calls| ollama_tool_calls(calls))
+        .unwrap_or_default();
+    let reason = done_reason(choice.and_then(|c| c.get("finish_reason")).and_then(Value::as_str));
+
+    let mut line = final_line(endpoint, model, reason, completion.get("usage"), started);
+    set_output(endpoint, &mut line, content, tool_calls);
+    line
+}
+
+/// 流式转换状态
+struct StreamState {
+    endpoint: Endpoint,
+    model: String,
+    started: Instant,
+    finish_reason: Option<String>,
+    usage: Option<Value>,
+    /// 累积的工具调用，结束前一次性下发（参数可能分多个 chunk 传输）
+    tool_calls: Vec<Value>,
+    done: bool,
+}
+
+impl StreamState {
+    fn new(endpoint: Endpoint, model: String, started: Instant) -> Self {
+        Self { endpoint, model, started, finish_reason: None, usage: None, tool_calls: Vec::new(), done: false }
+    }
+
+    fn line(&self, content: &str, tool_calls: Vec<Value>) -> Value {
+        let mut line = base_line(&self.model, false);
+        set_output(self.endpoint, &mut line, content, tool_calls);
+        line
+    }
+
+    /// 处理一个 chat.completion.chunk，返回应下发的行
+    fn process(&mut self, chunk: &Value) -> Vec<Value> {
+        if self.done {
+            return Vec::new();
+        }
+        if let Some(error) = chunk.get("error") {
+            self.done = true;
+            let message = error.get("message").and_then(Value::as_str).map(str::to_string).unwrap_or_else(|| error.to_string());
+            return vec![json!({ "error": message })];
+        }
+        if let Some(usage) = chunk.get("usage").filter(|u| u.is_object()) {
+            self.usage = Some(usage.clone());
+        }
+        let Some(choice) = chunk.get("choices").and_then(|c| c.get(0)) else {
+            return Vec::new();
+        };
+        if let Some(reason) = choice.get("finish_reason").and_then(Value::as_str) {
+            self.finish_reason = Some(reason.to_string());
+        }
+        let delta = choice.get("delta");
+        if let Some(calls) = delta.and_then(|d| d.get("tool_calls")).and_then(Value::as_array) {
+            for call in calls {
+                self.push_tool_call(call);
+            }
+        }
+        match delta.and_then(|d| d.get("content")).and_then(Value::as_str).filter(|c| !c.is_empty()) {
+            Some(content) => vec![self.line(content, Vec::new())],
+            None => Vec::new(),
+        }
+    }
+
+    fn push_tool_call(&mut self, call: &Value) {
+        let fragment = call.pointer("/function/arguments").and_then(Value::as_str).unwrap_or("");
+        match (call.get("id"), self.tool_calls.last_mut()) {
+            (None, Some(last)) => {
+                let arguments = last["function"]["arguments"].as_str().unwrap_or("").to_string() + fragment;
+                last["function"]["arguments"] = json!(arguments);
+            }
+            _ => self.tool_calls.push(json!({
+                "function": {
+                    "name": call.pointer("/function/name").cloned().unwrap_or(json!("")),
+                    "arguments": fragment
+                }
+            })),
+        }
+    }
+
+    /// 流结束时下发工具调用与结束行
+    fn finish(&mut self) -> Vec<Value> {
+        if self.done {
+            return Vec::new();
+        }
+        self.done = true;
+        let mut out = Vec::new();
+        if !self.tool_calls.is_empty() {
+            let calls = ollama_tool_calls(&std::mem::take(&mut self.tool_calls));
+            out.push(self.line("", calls));
+        }
+        let reason = done_reason(self.finish_reason.as_deref());
+        let mut done = final_line(self.endpoint, &self.model, reason, self.usage.as_ref(), self.started);
+        if self.endpoint == Endpoint::Chat {
+            done["message"] = json!({ "role": "assistant", "content": "" });
+        }
+        out.push(done);
+        out
+    }
+}
+
+fn ndjson_line(line: &Value) -> Bytes {
+    Bytes::from(format!("{}\n", line))
+}
+
+/// OpenAI SSE 流转换为 Ollama NDJSON 流
+fn ndjson_stream(
+    endpoint: Endpoint,
+    model: String,
+    body: Body,
+    started: Instant,
+) -> std::pin::Pin<Box<dyn futures::Stream<Item = Result<Bytes, String>> + Send>> {
+    Box::pin(async_stream::stream! {
+        let mut state = StreamState::new(endpoint, model, started);
+        let mut stream = body.into_data_stream();
+        let mut buffer = String::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(c) => c,
+                Err(e) => {
+                    yield Ok(ndjson_line(&json!({ "error": e.to_string() })));
+                    return;
+                }
+            };
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+            while let Some(pos) = buffer.find('\n') {
+                let line: String = buffer.drain(..=pos).collect();
+                let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else {
+                    continue;
+                };
+                let lines = if data == "[DONE]" {
+                    state.finish()
+                } else {
+                    match serde_json::from_str::<Value>(data) {
+                        Ok(chunk) => state.process(&chunk),
+                        Err(_) => Vec::new(),
+                    }
+                };
+                for line in lines {
+                    yield Ok(ndjson_line(&line));
+                }
+            }
+        }
+        for line in state.finish() {
+            yield Ok(ndjson_line(&line));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_chat_request() {
+        let converted = convert_chat_request(&json!({
+            "model": "gemini-2.5-flash:latest",
+            "messages": [
+                { "role": "system", "content": "be brief" },
+                { "role": "user", "content": "what is this?", "images": ["iVBORw0KGgo="] },
+                { "role": "assistant", "content": "", "tool_calls": [{ "function": { "name": "lookup", "arguments": { "q": "x" } } }] },
+                { "role": "tool", "content": "{\"ok\":true}" }
+            ],
+            "options": { "temperature": 0.2, "num_predict": 128, "stop": ["\n\n"] },
+            "format": "json"
+        }))
+        .unwrap();
+
+        assert_eq!(converted.model, "gemini-2.5-flash:latest");
+        assert!(converted.stream);
+        let body = converted.body.unwrap();
+        assert_eq!(body["model"], "gemini-2.5-flash");
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["max_tokens"], 128);
+        assert_eq!(body["temperature"], 0.2);
+        assert_eq!(body["stop"], json!(["\n\n"]));
+        assert_eq!(body["response_format"]["type"], "json_object");
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages[1]["content"][1]["image_url"]["url"], "data:image/png;base64,iVBORw0KGgo=");
+        assert_eq!(messages[2]["tool_calls"][0]["function"]["arguments"], "{\"q\":\"x\"}");
+        assert_eq!(messages[3]["tool_call_id"], messages[2]["tool_calls"][0]["id"]);
+        assert_eq!(messages[3]["name"], "lookup");
+
+        // 预加载模型
+        assert!(convert_chat_request(&json!({ "model": "m", "messages": [] })).unwrap().body.is_none());
+        assert!(convert_chat_request(&json!({ "messages": [] })).is_err());
+    }
+
+    #[test]
+    fn test_convert_generate_request_and_completion() {
+        let converted = convert_generate_request(&json!({
+            "model": "gemini-2.5-pro", "prompt": "hi", "system": "sys", "stream": false
+        }))
+        .unwrap();
+        assert!(!converted.stream);
+        let body = converted.body.unwrap();
+        assert_eq!(body["messages"][0], json!({ "role": "system", "content": "sys" }));
+        assert_eq!(body["messages"][1], json!({ "role": "user", "content": "hi" }));
+
+        let completion = json!({
+            "choices": [{ "index": 0, "message": { "role": "assistant", "content": "hello" }, "finish_reason": "length" }],
+            "usage": { "prompt_tokens": 3, "completion_tokens": 5 }
+        });
+        let generated = convert_completion(Endpoint::Generate, "gemini-2.5-pro", &completion, Instant::now());
+        assert_eq!(generated["response"], "hello");
+        assert_eq!(generated["done"], true);
+        assert_eq!(generated["done_reason"], "length");
+        assert_eq!(generated["prompt_eval_count"], 3);
+        assert_eq!(generated["eval_count"], 5);
+
+        let chat = convert_completion(Endpoint::Chat, "m", &completion, Instant::now());
+        assert_eq!(chat["message"], json!({ "role": "assistant", "content": "hello" }));
+        assert!(chat.get("response").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_stream() {
+        let sse = [
+            json!({ "choices": [{ "delta": { "role": "assistant", "content": "Hel" } }] }),
+            json!({ "choices": [{ "delta": { "content": "lo" } }] }),
+            json!({ "choices": [{ "delta": { "tool_calls": [{ "index": 0, "id": "c1", "function": { "name": "f", "arguments": "{\"a\"" } }] } }] }),
+            json!({ "choices": [{ "delta": { "tool_calls": [{ "index": 0, "function": { "arguments": ":1}" } }] }, "finish_reason": "stop" }] }),
+        ]
+        .iter()
+        .map(|c| format!("data: {}\n\n", c))
+        .collect::<String>()
+            + ": ping\n\ndata: [DONE]\n\n";
+
+        let lines: Vec<Value> = ndjson_stream(Endpoint::Chat, "m:latest".to_string(), Body::from(sse), Instant::now())
+            .map(|item| serde_json::from_slice::<Value>(&item.unwrap()).unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["message"]["content"], "Hel");
+        assert_eq!(lines[0]["model"], "m:latest");
+        assert_eq!(lines[0]["done"], false);
+        assert_eq!(lines[1]["message"]["content"], "lo");
+        assert_eq!(lines[2]["message"]["tool_calls"][0]["function"]["arguments"], json!({ "a": 1 }));
+        assert_eq!(lines[3]["done"], true);
+        assert_eq!(lines[3]["done_reason"], "stop");
+    }
+
+    #[test]
+    fn test_error_message() {
+        assert_eq!(error_message(br#"{"error":{"message":"bad key","type":"x"}}"#), "bad key");
+        assert_eq!(error_message(br#"{"error":"nope"}"#), "nope");
+        assert_eq!(error_message(b"plain text"), "plain text");
+    }
+}
@@ -8,15 +8,35 @@ use std::sync::Arc;
 use tracing::{debug, error, info}; // Import Engine trait for encode method
 
 use crate::proxy::mappers::openai::{
-    transform_openai_request, transform_openai_response, OpenAIRequest,
+    transform_openai_request, transform_openai_response, unsupported_params, OpenAIRequest,
 };
+use crate::proxy::common::error::error_response;
+use crate::proxy::concurrency::{hold_permit, CONCURRENCY};
 use crate::proxy::server::AppState;
 use crate::proxy::TokenManager;
 use crate::proxy::upstream::client::UpstreamClient;
+use crate::proxy::upstream::errors as upstream_errors;
+use crate::proxy::upstream::timeout::{is_timeout_error, timeout_response};
 
-const MAX_RETRY_ATTEMPTS: usize = 3;
 use crate::proxy::session_manager::SessionManager;
 
+/// Chat 流式响应是否按严格的 OpenAI chunk 格式输出
+static STRICT_STREAM: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+pub fn set_strict_stream(enabled: bool) {
+    STRICT_STREAM.store(enabled, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// 请求 JSON 输出时是否修复非流式响应中的 JSON
+static JSON_REPAIR: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+pub fn set_json_repair(enabled: bool) {
+    JSON_REPAIR.store(enabled, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// 列出请求中不会生效的参数（逗号分隔）
+const UNSUPPORTED_PARAMS_HEADER: &str = "x-antiproxy-unsupported-params";
+
 /// 响应格式类型
 #[derive(Clone, Copy)]
 enum ResponseFormat {
@@ -35,23 +55,25 @@ enum ExecuteResult {
     /// 成功的非流式响应 (Gemini 原始 JSON)
     JsonResponse(Value),
     /// 需要重试
-    Retry { error: String, should_rotate: bool },
+    /// - backoff: 是否需要退避等待（限流 / 服务端错误）
+    /// - hint_ms: 上游给出的重试等待提示 (Retry-After / retryDelay)
+    Retry {
+        error: String,
+        should_rotate: bool,
+        backoff: bool,
+        hint_ms: Option<u64>,
+    },
     /// 不可重试的错误
     FatalError { status: StatusCode, message: String },
+    /// 上游超时，直接返回 504
+    Timeout(String),
 }
 
-/// 核心请求执行函数 V2 - 接受预计算的 session_id 和 force_rotate 参数
-/// 解决了原版本中 session_id 在函数内部计算导致重试时账号切换的问题
-async fn execute_openai_request_v2(
+/// 模型路由与请求配置：返回 (映射后的模型, 请求配置, 配额组)
+async fn resolve_openai_route(
     state: &AppState,
     openai_req: &OpenAIRequest,
-    upstream: Arc<UpstreamClient>,
-    token_manager: Arc<TokenManager>,
-    force_rotate: bool,
-    session_id: &str,
-    response_format: ResponseFormat,
-) -> ExecuteResult {
-    // 1. 模型路由与配置解析
+) -> (String, crate::proxy::mappers::common_utils::RequestConfig, &'static str) {
     let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
         &openai_req.model,
         &*state.custom_mapping.read().await,
@@ -77,8 +99,35 @@ async fn execute_openai_request_v2(
     } else {
         "gemini"
     };
+    (mapped_model, config, quota_group)
+}
 
-    // 2. 获取 Token (使用传入的 session_id 和 force_rotate)
+/// 核心请求执行函数 V2 - 接受预计算的 session_id 和 force_rotate 参数
+/// 解决了原版本中 session_id 在函数内部计算导致重试时账号切换的问题
+async fn execute_openai_request_v2(
+    state: &AppState,
+    openai_req: &OpenAIRequest,
+    upstream: Arc<UpstreamClient>,
+    token_manager: Arc<TokenManager>,
+    force_rotate: bool,
+    session_id: &str,
+    response_format: ResponseFormat,
+) -> ExecuteResult {
+    // 1. 模型路由与配置解析
+    let (mapped_model, config, quota_group) = resolve_openai_route(state, openai_req).await;
+
+    // 2. 模型额度在选择账号之前检查，等待期间不占用账号
+    let model_permit = match CONCURRENCY.acquire_model(&mapped_model).await {
+        Ok(p) => p,
+        Err(e) => {
+            return ExecuteResult::FatalError {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                message: e,
+            };
+        }
+    };
+
+    // 获取 Token (使用传入的 session_id 和 force_rotate)
     let selected = match token_manager
         .get_token(quota_group, &config.request_type, force_rotate, Some(session_id))
         .await
@@ -96,6 +145,7 @@ async fn execute_openai_request_v2(
     let project_id = selected.project_id;
     let email = selected.email;
     let account_id = selected.account_id;
+    let client_profile = selected.client_profile;
 
     info!("✓ Using account: {} (type: {})", email, config.request_type);
 
@@ -115,15 +165,30 @@ async fn execute_openai_request_v2(
     };
     let query_string = if is_stream { Some("alt=sse") } else { None };
 
+    let permit = match CONCURRENCY.acquire(&account_id, &email).await {
+        Ok(p) => p.with_model(model_permit),
+        Err(e) => {
+            return ExecuteResult::FatalError {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                message: e,
+            };
+        }
+    };
     let response = match upstream
-        .call_v1_internal(method, &access_token, gemini_body, query_string)
+        .call_v1_internal(method, &access_token, gemini_body, query_string, client_profile.as_deref())
         .await
     {
         Ok(r) => r,
+        Err(e) if is_timeout_error(&e) => return ExecuteResult::Timeout(e),
         Err(e) => {
             debug!("OpenAI Request failed: {}", e);
             // 网络错误不需要轮换账号，可能是临时问题
-            return ExecuteResult::Retry { error: e, should_rotate: false };
+            return ExecuteResult::Retry {
+                error: e,
+                should_rotate: false,
+                backoff: false,
+                hint_ms: None,
+            };
         }
     };
 
@@ -131,26 +196,31 @@ async fn execute_openai_request_v2(
 
     // 5. 处理成功响应
     if status.is_success() {
+        token_manager.record_success(&account_id);
         if is_stream {
             let gemini_stream = response.bytes_stream();
             let model_clone = openai_req.model.clone();
 
+            use crate::proxy::common::sse::with_keepalive;
             // 根据响应格式选择不同的 SSE 流转换器
             let body = match response_format {
                 ResponseFormat::Chat => {
                     use crate::proxy::mappers::openai::streaming::create_openai_sse_stream;
-                    let stream = create_openai_sse_stream(Box::pin(gemini_stream), model_clone);
-                    Body::from_stream(stream)
+                    let mut stream = create_openai_sse_stream(Box::pin(gemini_stream), model_clone);
+                    if STRICT_STREAM.load(std::sync::atomic::Ordering::Relaxed) {
+                        stream = crate::proxy::mappers::openai::strict::strict_chat_stream(stream);
+                    }
+                    Body::from_stream(with_keepalive(hold_permit(stream, permit)))
                 }
                 ResponseFormat::Codex => {
                     use crate::proxy::mappers::openai::streaming::create_codex_sse_stream;
                     let stream = create_codex_sse_stream(Box::pin(gemini_stream), model_clone);
-                    Body::from_stream(stream)
+                    Body::from_stream(with_keepalive(hold_permit(stream, permit)))
                 }
                 ResponseFormat::LegacyCompletion => {
                     use crate::proxy::mappers::openai::streaming::create_legacy_sse_stream;
                     let stream = create_legacy_sse_stream(Box::pin(gemini_stream), model_clone);
-                    Body::from_stream(stream)
+                    Body::from_stream(with_keepalive(hold_permit(stream, permit)))
                 }
             };
 
@@ -216,21 +286,6 @@ async fn execute_openai_request_v2(
             &error_text,
         );
 
-        if let Some(delay_ms) = crate::proxy::upstream::retry::parse_retry_delay(&error_text) {
-            let actual_delay = delay_ms.saturating_add(200).min(10_000);
-            tracing::warn!(
-                "OpenAI Upstream {} on {}, waiting {}ms then retrying",
-                status_code,
-                email,
-                actual_delay
-            );
-            tokio::time::sleep(tokio::time::Duration::from_millis(actual_delay)).await;
-            return ExecuteResult::Retry {
-                error: format!("HTTP {}: {}", status_code, error_text),
-                should_rotate: should_rotate_account(status_code),
-            };
-        }
-
         tracing::warn!(
             "OpenAI Upstream {} on {}, will rotate: {}",
             status_code,
@@ -238,8 +293,10 @@ async fn execute_openai_request_v2(
             should_rotate_account(status_code)
         );
         return ExecuteResult::Retry {
-            error: format!("HTTP {}: {}", status_code, error_text),
+            error: upstream_errors::describe(status_code, &error_text),
             should_rotate: should_rotate_account(status_code),
+            backoff: true,
+            hint_ms: crate::proxy::upstream::retry::retry_hint_ms(retry_after.as_deref(), &error_text),
         };
     }
 
@@ -251,8 +308,10 @@ async fn execute_openai_request_v2(
             email
         );
         return ExecuteResult::Retry {
-            error: format!("HTTP {}: {}", status_code, error_text),
+            error: upstream_errors::describe(status_code, &error_text),
             should_rotate: true,
+            backoff: false,
+            hint_ms: None,
         };
     }
 
@@ -263,7 +322,7 @@ async fn execute_openai_request_v2(
     );
     ExecuteResult::FatalError {
         status,
-        message: error_text,
+        message: upstream_errors::upstream_message(&error_text),
     }
 }
 
@@ -300,7 +359,8 @@ async fn execute_with_retry(
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager.clone();
     let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    let mut retry_budget = upstream.retry_budget(pool_size).await;
+    let max_attempts = retry_budget.max_attempts();
 
     // [CRITICAL FIX] 提前计算 session_id，确保重试时不会改变
     let stable_session_id = SessionManager::extract_openai_session_id(openai_req);
@@ -308,7 +368,7 @@ async fn execute_with_retry(
     let mut last_error = String::new();
     let mut force_rotate_next = false;  // 控制下一次循环是否轮换账号
 
-    for _attempt in 0..max_attempts {
+    for attempt in 0..max_attempts {
         match execute_openai_request_v2(
             state,
             openai_req,
@@ -328,7 +388,12 @@ async fn execute_with_retry(
                 // 非流式响应 - 根据格式转换
                 let response = match response_format {
                     ResponseFormat::Chat => {
-                        let openai_response = transform_openai_response(&gemini_resp);
+                        let mut openai_response = transform_openai_response(&gemini_resp);
+                        if JSON_REPAIR.load(std::sync::atomic::Ordering::Relaxed)
+                            && openai_req.response_format.as_ref().is_some_and(|f| f.wants_json())
+                        {
+                            repair_json_content(&mut openai_response);
+                        }
                         Json(openai_response).into_response()
                     }
                     ResponseFormat::LegacyCompletion | ResponseFormat::Codex => {
@@ -361,20 +426,37 @@ async fn execute_with_retry(
                 };
                 return Ok(response);
             }
-            ExecuteResult::Retry { error, should_rotate } => {
+            ExecuteResult::Retry { error, should_rotate, backoff, hint_ms } => {
                 last_error = error;
                 force_rotate_next = should_rotate;
+                if backoff && attempt + 1 < max_attempts {
+                    match retry_budget.backoff(attempt, hint_ms) {
+                        Some(delay) => {
+                            debug!("OpenAI retry in {}ms (attempt {}/{})", delay.as_millis(), attempt + 1, max_attempts);
+                            tokio::time::sleep(delay).await;
+                        }
+                        None => {
+                            tracing::warn!("OpenAI retry budget exhausted after {} attempts", attempt + 1);
+                            break;
+                        }
+                    }
+                }
                 continue;
             }
             ExecuteResult::FatalError { status, message } => {
                 return Err((status, message));
             }
+            ExecuteResult::Timeout(error) => {
+                error!("OpenAI {}", error);
+                return Ok(timeout_response(&error));
+            }
         }
     }
 
-    Err((
+    Ok(error_response(
         StatusCode::TOO_MANY_REQUESTS,
-        format!("All accounts exhausted. Last error: {}", last_error),
+        "accounts_exhausted",
+        format!("All accounts exhausted. Last error: {}", upstream_errors::sanitize(&last_error)),
     ))
 }
 
@@ -382,6 +464,42 @@ pub async fn handle_chat_completions(
     State(state): State<AppState>,
     Json(body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let openai_req = prepare_chat_request(body).await?;
+
+    // 使用公共执行函数
+    let response = execute_with_retry(&state, &openai_req, ResponseFormat::Chat).await?;
+    Ok(mark_unsupported_params(response, &openai_req))
+}
+
+/// 在响应头中提示被忽略的参数，避免客户端误以为已生效
+fn mark_unsupported_params(mut response: Response, openai_req: &OpenAIRequest) -> Response {
+    let params = unsupported_params(openai_req);
+    if params.is_empty() {
+        return response;
+    }
+    debug!("[OpenAI] Unsupported parameters ignored: {}", params.join(", "));
+    if let Ok(value) = axum::http::HeaderValue::from_str(&params.join(", ")) {
+        response.headers_mut().insert(UNSUPPORTED_PARAMS_HEADER, value);
+    }
+    response
+}
+
+/// 把响应中被代码块或说明文字包裹的 JSON 还原
+fn repair_json_content(response: &mut crate::proxy::mappers::openai::OpenAIResponse) {
+    use crate::proxy::mappers::openai::OpenAIContent;
+
+    for choice in response.choices.iter_mut() {
+        if let Some(OpenAIContent::String(content)) = choice.message.content.as_mut() {
+            if let Some(repaired) = crate::proxy::mappers::openai::json_output::repair_json(content) {
+                debug!("[OpenAI] Repaired JSON output ({} -> {} bytes)", content.len(), repaired.len());
+                *content = repaired;
+            }
+        }
+    }
+}
+
+/// Chat 请求预处理：解析、补全空消息、应用模型别名并内联图片
+async fn prepare_chat_request(body: Value) -> Result<OpenAIRequest, (StatusCode, String)> {
     let mut openai_req: OpenAIRequest = serde_json::from_value(body)
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
 
@@ -398,13 +516,148 @@ pub async fn handle_chat_completions(
                 tool_calls: None,
                 tool_call_id: None,
                 name: None,
+                reasoning_content: None,
             });
     }
 
+    apply_model_alias(&mut openai_req);
     debug!("Received OpenAI request for model: {}", openai_req.model);
+    inline_remote_images(&mut openai_req)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid image input: {}", e)))?;
+    inline_uploaded_files(&mut openai_req).await?;
+    Ok(openai_req)
+}
 
-    // 使用公共执行函数
-    execute_with_retry(&state, &openai_req, ResponseFormat::Chat).await
+/// 调试：返回 Chat 请求转换后的上游请求（账号、端点、请求头与 v1internal 请求体），不发送到上游
+///
+/// 与真实请求走同一套路由与选号逻辑，因此会像真实请求一样建立会话绑定
+pub async fn handle_debug_transform(
+    State(state): State<AppState>,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let openai_req = prepare_chat_request(body).await?;
+    let (mapped_model, config, quota_group) = resolve_openai_route(&state, &openai_req).await;
+    let session_id = SessionManager::extract_openai_session_id(&openai_req);
+
+    let selected = state
+        .token_manager
+        .get_token(quota_group, &config.request_type, false, Some(&session_id))
+        .await
+        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)))?;
+    let gemini_body = transform_openai_request(&openai_req, &selected.project_id, &mapped_model);
+
+    let (method, query_string) = if openai_req.stream {
+        ("streamGenerateContent", Some("alt=sse"))
+    } else {
+        ("generateContent", None)
+    };
+    let upstream = state
+        .upstream
+        .preview_v1_internal(method, &selected.access_token, query_string, selected.client_profile.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(json!({
+        "model": openai_req.model,
+        "mapped_model": mapped_model,
+        "request_type": config.request_type,
+        "quota_group": quota_group,
+        "session_id": session_id,
+        "account": {
+            "id": selected.account_id,
+            "email": selected.email,
+            "project_id": selected.project_id,
+        },
+        "endpoint": upstream.url,
+        "headers": upstream.headers,
+        "body": gemini_body,
+    })))
+}
+
+/// 应用模型别名 (/api/model-aliases)
+fn apply_model_alias(openai_req: &mut OpenAIRequest) {
+    if let Some(alias) = crate::modules::model_aliases::resolve(&openai_req.model) {
+        debug!("[Alias] {} -> {}", alias.alias, alias.target);
+        // 别名设置的输出上限同样覆盖 max_completion_tokens
+        if alias.max_tokens.is_some() {
+            openai_req.max_completion_tokens = None;
+        }
+        alias.apply(&mut openai_req.model, &mut openai_req.temperature, &mut openai_req.max_tokens);
+    }
+}
+
+/// 预处理图片输入：下载 HTTP 图片并转为 data URL，校验 data URL 的大小与格式
+///
+/// 上游无法直接读取任意 HTTP 地址，因此在转换请求前统一内联为 inlineData
+async fn inline_remote_images(openai_req: &mut OpenAIRequest) -> Result<(), String> {
+    use crate::proxy::common::image;
+    use crate::proxy::mappers::openai::{OpenAIContent, OpenAIContentBlock, OpenAIImageUrl};
+
+    for msg in openai_req.messages.iter_mut() {
+        let Some(OpenAIContent::Array(blocks)) = msg.content.as_mut() else {
+            continue;
+        };
+        for block in blocks.iter_mut() {
+            let url = match block {
+                OpenAIContentBlock::ImageUrl { image_url } => image_url.url.clone(),
+                OpenAIContentBlock::Image { source } => match source.to_url() {
+                    Some(url) => url,
+                    None => return Err("image block has neither data nor url".to_string()),
+                },
+                _ => continue,
+            };
+            let (mime_type, data) = if url.starts_with("data:") {
+                image::parse_data_url(&url)?
+            } else if url.starts_with("http://") || url.starts_with("https://") {
+                image::fetch_image(&url).await?
+            } else {
+                continue;
+            };
+            *block = OpenAIContentBlock::ImageUrl {
+                image_url: OpenAIImageUrl {
+                    url: format!("data:{};base64,{}", mime_type, data),
+                    detail: None,
+                },
+            };
+        }
+    }
+    Ok(())
+}
+
+/// 把引用 /v1/files 上传文件的文件块替换为文件内容（data URL），只能引用当前 API Key 上传的文件
+async fn inline_uploaded_files(openai_req: &mut OpenAIRequest) -> Result<(), (StatusCode, String)> {
+    use base64::engine::general_purpose::STANDARD;
+    use crate::proxy::mappers::openai::{OpenAIContent, OpenAIContentBlock};
+
+    let owner = crate::proxy::middleware::auth::current_key_id();
+    for msg in openai_req.messages.iter_mut() {
+        let Some(OpenAIContent::Array(blocks)) = msg.content.as_mut() else {
+            continue;
+        };
+        for block in blocks.iter_mut() {
+            let OpenAIContentBlock::File { file } = block else {
+                continue;
+            };
+            let Some(id) = file.file_id.clone().filter(|_| file.file_data.is_none()) else {
+                continue;
+            };
+            let owner = owner.clone();
+            let stored = tokio::task::spawn_blocking(move || crate::proxy::files::FILES.read(owner.as_deref(), &id))
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            let Some((meta, data)) = stored else {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid file input: no such file {}", file.file_id.as_deref().unwrap_or_default()),
+                ));
+            };
+            file.file_data = Some(format!("data:{};base64,{}", meta.mime_type, STANDARD.encode(data)));
+            file.filename.get_or_insert(meta.filename);
+        }
+    }
+    Ok(())
 }
 
 /// 处理 Legacy Completions API (/v1/completions)
@@ -679,10 +932,16 @@ pub async fn handle_completions(
                 tool_calls: None,
                 tool_call_id: None,
                 name: None,
+                reasoning_content: None,
             });
     }
 
+    apply_model_alias(&mut openai_req);
     debug!("Received Completions request for model: {}", openai_req.model);
+    inline_remote_images(&mut openai_req)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid image input: {}", e)))?;
+    inline_uploaded_files(&mut openai_req).await?;
 
     // 根据请求类型选择响应格式
     let response_format = if is_codex_style {
@@ -692,17 +951,17 @@ pub async fn handle_completions(
     };
 
     // 使用公共执行函数
-    execute_with_retry(&state, &openai_req, response_format).await
+    let response = execute_with_retry(&state, &openai_req, response_format).await?;
+    Ok(mark_unsupported_params(response, &openai_req))
 }
 
 pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoResponse {
     use crate::proxy::common::model_mapping::get_all_dynamic_models;
 
-    let model_ids = get_all_dynamic_models(
-        &state.openai_mapping,
-        &state.custom_mapping,
-        &state.anthropic_mapping,
-    ).await;
+    let model_ids = crate::proxy::model_registry::visible_models(
+        get_all_dynamic_models(&state.openai_mapping, &state.custom_mapping, &state.anthropic_mapping).await,
+    )
+    .await;
 
     let data: Vec<_> = model_ids.into_iter().map(|id| {
         json!({
@@ -719,6 +978,130 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
     }))
 }
 
+/// OpenAI Embeddings API: POST /v1/embeddings
+/// 支持单条/批量 input，映射到上游 batchEmbedContents
+pub async fn handle_embeddings(
+    State(state): State<AppState>,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    use crate::proxy::mappers::openai::embeddings::{
+        build_embedding_request, parse_embedding_inputs, resolve_embedding_model,
+        transform_embedding_response,
+    };
+
+    let input = body.get("input").ok_or((
+        StatusCode::BAD_REQUEST,
+        "Missing 'input' field".to_string(),
+    ))?;
+    let inputs = parse_embedding_inputs(input).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let model = body
+        .get("model")
+        .and_then(|v| v.as_str())
+        .unwrap_or(crate::proxy::mappers::openai::embeddings::DEFAULT_EMBEDDING_MODEL)
+        .to_string();
+    let dimensions = body.get("dimensions").and_then(|v| v.as_u64());
+    let base64_format = body.get("encoding_format").and_then(|v| v.as_str()) == Some("base64");
+
+    let mapped_model = resolve_embedding_model(&model, &*state.custom_mapping.read().await);
+    info!(
+        "[Embeddings] Received request: model={} -> {}, inputs={}",
+        model,
+        mapped_model,
+        inputs.len()
+    );
+
+    let upstream = state.upstream.clone();
+    let token_manager = state.token_manager.clone();
+    let mut retry_budget = upstream.retry_budget(token_manager.len()).await;
+    let max_attempts = retry_budget.max_attempts();
+    let mut last_error = String::new();
+
+    for attempt in 0..max_attempts {
+        let selected = token_manager
+            .get_token("gemini", "embedding", attempt > 0, None)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)))?;
+
+        let gemini_body = build_embedding_request(&inputs, &selected.project_id, &mapped_model, dimensions);
+        let _permit = CONCURRENCY
+            .acquire(&selected.account_id, &selected.email)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e))?;
+        let response = match upstream
+            .call_v1_internal("batchEmbedContents", &selected.access_token, gemini_body, None, selected.client_profile.as_deref())
+            .await
+        {
+            Ok(r) => r,
+            Err(e) if is_timeout_error(&e) => return Ok(timeout_response(&e)),
+            Err(e) => {
+                debug!("Embeddings request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
+                last_error = e;
+                continue;
+            }
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            token_manager.record_success(&selected.account_id);
+            let gemini_resp: Value = response
+                .json()
+                .await
+                .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+            let openai_resp = transform_embedding_response(&gemini_resp, &model, &inputs, base64_format)
+                .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+            return Ok(Json(openai_resp).into_response());
+        }
+
+        let status_code = status.as_u16();
+        let retry_after = response
+            .headers()
+            .get("Retry-After")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.to_string());
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| format!("HTTP {}", status_code));
+        last_error = upstream_errors::describe(status_code, &error_text);
+
+        match status_code {
+            429 | 500 | 503 | 529 => {
+                token_manager.mark_rate_limited(
+                    "gemini",
+                    "embedding",
+                    &selected.account_id,
+                    status_code,
+                    retry_after.as_deref(),
+                    &error_text,
+                );
+                if attempt + 1 < max_attempts {
+                    match retry_budget.next_delay(attempt, retry_after.as_deref(), &error_text) {
+                        Some(delay) => tokio::time::sleep(delay).await,
+                        None => break,
+                    }
+                }
+            }
+            // 账号认证/权限问题，换号重试
+            401 | 403 => {}
+            _ => {
+                error!("[Embeddings] Upstream non-retryable error {}: {}", status_code, error_text);
+                return Err((status, upstream_errors::upstream_message(&error_text)));
+            }
+        }
+        tracing::warn!(
+            "[Embeddings] Upstream {} on account {}, rotating",
+            status_code,
+            selected.email
+        );
+    }
+
+    Ok(error_response(
+        StatusCode::TOO_MANY_REQUESTS,
+        "accounts_exhausted",
+        format!("All accounts exhausted. Last error: {}", upstream_errors::sanitize(&last_error)),
+    ))
+}
+
 /// OpenAI Images API: POST /v1/images/generations
 /// 处理图像生成请求，转换为 Gemini API 格式
 pub async fn handle_images_generations(
@@ -808,6 +1191,7 @@ pub async fn handle_images_generations(
     let access_token = selected.access_token;
     let project_id = selected.project_id;
     let email = selected.email;
+    let client_profile = selected.client_profile;
 
     info!("✓ Using account: {} for image generation", email);
 
@@ -817,6 +1201,7 @@ pub async fn handle_images_generations(
     for _ in 0..n {
         let upstream = upstream.clone();
         let access_token = access_token.clone();
+        let client_profile = client_profile.clone();
         let project_id = project_id.clone();
         let final_prompt = final_prompt.clone();
         let aspect_ratio = aspect_ratio.to_string();
@@ -851,21 +1236,21 @@ pub async fn handle_images_generations(
             });
 
             match upstream
-                .call_v1_internal("generateContent", &access_token, gemini_body, None)
+                .call_v1_internal("generateContent", &access_token, gemini_body, None, client_profile.as_deref())
                 .await
             {
                 Ok(response) => {
                     let status = response.status();
                     if !status.is_success() {
                         let err_text = response.text().await.unwrap_or_default();
-                        return Err(format!("Upstream error {}: {}", status, err_text));
+                        return Err(format!("Upstream error {}: {}", status, upstream_errors::upstream_message(&err_text)));
                     }
                     match response.json::<Value>().await {
                         Ok(json) => Ok(json),
                         Err(e) => Err(format!("Parse error: {}", e)),
                     }
                 }
-                Err(e) => Err(format!("Network error: {}", e)),
+                Err(e) => Err(format!("Network error: {}", upstream_errors::sanitize(&e))),
             }
         }));
     }
@@ -1062,6 +1447,7 @@ pub async fn handle_images_edits(
     };
     let access_token = selected.access_token;
     let project_id = selected.project_id;
+    let client_profile = selected.client_profile;
 
     // 2. 映射配置
     let mut contents_parts = Vec::new();
@@ -1122,25 +1508,26 @@ pub async fn handle_images_edits(
     for _ in 0..n {
         let upstream = upstream.clone();
         let access_token = access_token.clone();
+        let client_profile = client_profile.clone();
         let body = gemini_body.clone();
 
         tasks.push(tokio::spawn(async move {
             match upstream
-                .call_v1_internal("generateContent", &access_token, body, None)
+                .call_v1_internal("generateContent", &access_token, body, None, client_profile.as_deref())
                 .await
             {
                 Ok(response) => {
                     let status = response.status();
                     if !status.is_success() {
                         let err_text = response.text().await.unwrap_or_default();
-                        return Err(format!("Upstream error {}: {}", status, err_text));
+                        return Err(format!("Upstream error {}: {}", status, upstream_errors::upstream_message(&err_text)));
                     }
                     match response.json::<Value>().await {
                         Ok(json) => Ok(json),
                         Err(e) => Err(format!("Parse error: {}", e)),
                     }
                 }
-                Err(e) => Err(format!("Network error: {}", e)),
+                Err(e) => Err(format!("Network error: {}", upstream_errors::sanitize(&e))),
             }
         }));
     }
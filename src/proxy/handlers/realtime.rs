@@ -0,0 +1,212 @@
+// WebSocket 流式传输 (GET /v1/realtime)
+// 部分企业代理会缓冲/截断 SSE，这里通过 WebSocket 承载同样的 chat completions 流
+//
+// 协议:
+// - 客户端每条文本消息是一个 OpenAI chat completions 请求体，可带 `request_id` 字段用于关联
+// - 服务端按顺序返回 {"type":"delta","data":<chunk>} ... {"type":"done"}，失败时返回 {"type":"error"}
+// - 每条消息都经过 auth + maintenance + monitor 中间件，限流 / 模型白名单 / 维护模式 / 用量统计与 HTTP 请求一致
+use axum::{
+    body::Body,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Request, State,
+    },
+    http::{header, HeaderMap, HeaderValue},
+    response::Response,
+    routing::post,
+    Router,
+};
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+use crate::proxy::server::AppState;
+
+/// 单条 WS 消息内部转发的路径
+const CHAT_COMPLETIONS_PATH: &str = "/v1/chat/completions";
+
+/// 转发到内部请求时保留的认证头（以及 IP 访问控制所需的 X-Forwarded-For / X-Real-IP）
+const FORWARDED_HEADERS: [&str; 4] = ["authorization", "x-api-key", "x-forwarded-for", "x-real-ip"];
+
+/// 原始连接的对端地址
+type PeerInfo = Option<ConnectInfo<SocketAddr>>;
+
+pub async fn handle_realtime(
+    State(state): State<AppState>,
+    peer: PeerInfo,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let mut auth_headers = HeaderMap::new();
+    for name in FORWARDED_HEADERS {
+        if let Some(value) = headers.get(name) {
+            auth_headers.insert(header::HeaderName::from_static(name), value.clone());
+        }
+    }
+
+    ws.on_upgrade(move |socket| handle_socket(socket, state, auth_headers, peer))
+}
+
+/// 每条消息走一遍与主路由相同的 request_id / auth / maintenance / monitor 中间件；
+/// 维护模式开始后，已建立的连接上的新消息同样返回 503
+fn message_router(state: AppState) -> Router {
+    Router::new()
+        .route(CHAT_COMPLETIONS_PATH, post(super::openai::handle_chat_completions))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            crate::proxy::middleware::monitor::monitor_middleware,
+        ))
+        .layer(axum::middleware::from_fn(crate::proxy::middleware::maintenance::maintenance_middleware))
+        .layer(axum::middleware::from_fn_with_state(
+            state.security.clone(),
+            crate::proxy::middleware::auth_middleware,
+        ))
+        .layer(axum::middleware::from_fn(crate::proxy::middleware::request_id::request_id_middleware))
+        .with_state(state)
+}
+
+async fn handle_socket(mut socket: WebSocket, state: AppState, auth_headers: HeaderMap, peer: PeerInfo) {
+    let router = message_router(state);
+    tracing::info!("[Realtime] WebSocket connected");
+
+    while let Some(msg) = socket.recv().await {
+        let text = match msg {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) | Err(_) => break,
+            // Ping/Pong 由底层自动处理
+            Ok(_) => continue,
+        };
+
+        let (request_id, body) = match parse_client_message(&text) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                if send_json(&mut socket, error_frame(None, 400, &e)).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        if forward_message(&mut socket, &router, &auth_headers, peer, request_id, body)
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    tracing::info!("[Realtime] WebSocket closed");
+}
+
+/// 解析客户端消息，取出 request_id 并强制开启流式
+fn parse_client_message(text: &str) -> Result<(Option<Value>, Value), String> {
+    let mut body: Value =
+        serde_json::from_str(text).map_err(|e| format!("Invalid JSON message: {}", e))?;
+    let obj = body
+        .as_object_mut()
+        .ok_or_else(|| "Message must be a JSON object".to_string())?;
+    let request_id = obj.remove("request_id");
+    obj.insert("stream".to_string(), Value::Bool(true));
+    Ok((request_id, body))
+}
+
+/// 将一条消息作为内部 HTTP 请求执行，并把 SSE 转成 WS 帧
+///
+/// 仅在 socket 写入失败时返回 Err
+async fn forward_message(
+    socket: &mut WebSocket,
+    router: &Router,
+    auth_headers: &HeaderMap,
+    peer: PeerInfo,
+    request_id: Option<Value>,
+    body: Value,
+) -> Result<(), axum::Error> {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri(CHAT_COMPLETIONS_PATH)
+        .body(Body::from(body.to_string()))
+        .expect("static request parts are valid");
+    let headers = request.headers_mut();
+    headers.extend(auth_headers.clone());
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    // 消息自带的字符串 request_id 同时作为该次请求的请求 ID
+    if let Some(value) = request_id.as_ref().and_then(Value::as_str).and_then(|id| HeaderValue::from_str(id).ok()) {
+        headers.insert(crate::proxy::middleware::request_id::REQUEST_ID_HEADER, value);
+    }
+    if let Some(peer) = peer {
+        request.extensions_mut().insert(peer);
+    }
+
+    let response = match router.clone().oneshot(request).await {
+        Ok(r) => r,
+        Err(never) => match never {},
+    };
+
+    let status = response.status();
+    if !status.is_success() {
+        let bytes = axum::body::to_bytes(response.into_body(), 1024 * 1024)
+            .await
+            .unwrap_or_default();
+        let message = String::from_utf8_lossy(&bytes).to_string();
+        return send_json(socket, error_frame(request_id, status.as_u16(), &message)).await;
+    }
+
+    let mut stream = response.into_body().into_data_stream();
+    let mut buffer = String::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(c) => c,
+            Err(e) => {
+                return send_json(socket, error_frame(request_id, 502, &e.to_string())).await;
+            }
+        };
+        buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+        while let Some(pos) = buffer.find('\n') {
+            let line: String = buffer.drain(..=pos).collect();
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let data = data.trim();
+            if data == "[DONE]" {
+                continue;
+            }
+            if let Ok(chunk_json) = serde_json::from_str::<Value>(data) {
+                send_json(socket, json!({ "type": "delta", "request_id": request_id, "data": chunk_json })).await?;
+            }
+        }
+    }
+
+    send_json(socket, json!({ "type": "done", "request_id": request_id })).await
+}
+
+fn error_frame(request_id: Option<Value>, status: u16, message: &str) -> Value {
+    json!({
+        "type": "error",
+        "request_id": request_id,
+        "status": status,
+        "message": message
+    })
+}
+
+async fn send_json(socket: &mut WebSocket, frame: Value) -> Result<(), axum::Error> {
+    socket.send(Message::Text(frame.to_string())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_client_message_forces_stream() {
+        let (id, body) =
+            parse_client_message(r#"{"request_id":"r1","model":"gpt-4o","stream":false,"messages":[]}"#).unwrap();
+        assert_eq!(id, Some(json!("r1")));
+        assert_eq!(body["stream"], true);
+        assert!(body.get("request_id").is_none());
+
+        assert!(parse_client_message("[1,2]").is_err());
+        assert!(parse_client_message("not json").is_err());
+    }
+}
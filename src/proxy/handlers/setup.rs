@@ -0,0 +1,277 @@
+//! 首次运行引导 API (/api/setup)
+//!
+//! 设置管理员登录的步骤无需登录，完成后直接下发 owner session；
+//! 添加账号与生成 API Key 两步走常规管理员鉴权。生成第一个 API Key 后引导锁定
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::modules::audit;
+use crate::modules::setup::{self, SetupProgress, SetupStep};
+use crate::modules::webauthn::{AdminRole, AuthMode};
+use crate::proxy::handlers::api_keys::CreatedApiKeyResponse;
+use crate::proxy::handlers::manage::CreateAccountRequest;
+use crate::proxy::handlers::webauthn::{resolve_webauthn_config, session_client, session_cookie};
+use crate::proxy::server::AppState;
+
+pub const STATE_PATH: &str = "/api/setup/state";
+pub const PASSWORD_PATH: &str = "/api/setup/password";
+pub const PASSKEY_START_PATH: &str = "/api/setup/passkey/start";
+pub const PASSKEY_FINISH_PATH: &str = "/api/setup/passkey/finish";
+
+const DEFAULT_KEY_NAME: &str = "default";
+
+/// 引导步骤串行执行，避免并发请求同时完成同一步
+static SETUP_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, message: impl Into<String>) -> ApiError {
+    (status, Json(json!({ "error": message.into() })))
+}
+
+fn internal_error(e: String) -> ApiError {
+    api_error(StatusCode::INTERNAL_SERVER_ERROR, e)
+}
+
+/// 无需管理员登录的引导路径（设置第一个管理员之前调用）
+pub fn is_open_path(path: &str) -> bool {
+    matches!(path, STATE_PATH | PASSWORD_PATH | PASSKEY_START_PATH | PASSKEY_FINISH_PATH)
+}
+
+struct Snapshot {
+    progress: SetupProgress,
+    auth_configured: bool,
+    has_accounts: bool,
+}
+
+impl Snapshot {
+    async fn load(state: &AppState) -> Result<Self, ApiError> {
+        let progress = setup::load_progress().map_err(internal_error)?;
+        let auth_configured = state.webauthn_manager.get_auth_mode().await != AuthMode::None;
+        let has_accounts = !crate::modules::storage::blocking(crate::modules::account::load_account_index)
+            .await
+            .map_err(internal_error)?
+            .accounts
+            .is_empty();
+        Ok(Self { progress, auth_configured, has_accounts })
+    }
+
+    fn next_step(&self) -> Option<SetupStep> {
+        self.progress.next_step(self.auth_configured, self.has_accounts)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "status": self.progress.status(self.auth_configured, self.has_accounts),
+            "next_step": self.next_step(),
+            "steps": {
+                "admin": self.auth_configured,
+                "account": self.has_accounts,
+                "api_key": self.progress.completed_at.is_some(),
+            },
+            "completed_at": self.progress.completed_at,
+        })
+    }
+}
+
+/// 确认 `step` 是当前应执行的步骤
+async fn require_step(state: &AppState, step: SetupStep) -> Result<Snapshot, ApiError> {
+    let snapshot = Snapshot::load(state).await?;
+    match snapshot.next_step() {
+        Some(next) if next == step => Ok(snapshot),
+        Some(next) => Err(api_error(
+            StatusCode::CONFLICT,
+            format!("Setup is waiting for the {} step", next.as_str()),
+        )),
+        None => Err(api_error(StatusCode::CONFLICT, "Setup is already complete")),
+    }
+}
+
+/// 在配置管理员登录之前记录引导开始；之后的步骤即使失败也能从这里继续
+fn mark_started(mut progress: SetupProgress) -> Result<(), ApiError> {
+    if progress.started_at.is_none() {
+        progress.started_at = Some(chrono::Utc::now().timestamp());
+        setup::save_progress(&progress).map_err(internal_error)?;
+    }
+    Ok(())
+}
+
+/// 引导状态
+///
+/// GET /api/setup/state
+pub async fn get_state(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(Snapshot::load(&state).await?.to_json()))
+}
+
+#[derive(Deserialize)]
+pub struct SetupPasswordRequest {
+    pub password: String,
+}
+
+/// 设置管理员密码并登录
+///
+/// POST /api/setup/password
+pub async fn setup_password(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(req): Json<SetupPasswordRequest>,
+) -> Result<(CookieJar, impl IntoResponse), ApiError> {
+    let _guard = SETUP_LOCK.lock().await;
+    let snapshot = require_step(&state, SetupStep::Admin).await?;
+    mark_started(snapshot.progress)?;
+
+    state
+        .webauthn_manager
+        .setup_password(&req.password)
+        .await
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, e))?;
+    audit::record("auth.password_configured", None, None, None);
+    tracing::info!("[Setup] Password authentication configured");
+
+    let token = state.session_manager.create_session(session_client(&headers)).await;
+    let jar = jar.add(session_cookie(&state, &headers, token));
+    Ok((jar, Json(Snapshot::load(&state).await?.to_json())))
+}
+
+#[derive(Deserialize)]
+pub struct StartPasskeyRequest {
+    /// 设备名称
+    pub name: String,
+}
+
+/// 开始注册第一个 Passkey
+///
+/// POST /api/setup/passkey/start
+pub async fn start_passkey(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<StartPasskeyRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    require_step(&state, SetupStep::Admin).await?;
+    let config = resolve_webauthn_config(&headers, &state);
+    let (options, challenge) = state
+        .webauthn_manager
+        .start_registration(&config, &req.name)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(json!({ "challenge": challenge, "options": options })))
+}
+
+#[derive(Deserialize)]
+pub struct FinishPasskeyRequest {
+    pub challenge: String,
+    pub name: String,
+    /// WebAuthn 响应
+    pub response: Value,
+}
+
+/// 完成注册第一个 Passkey 并登录
+///
+/// POST /api/setup/passkey/finish
+pub async fn finish_passkey(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(req): Json<FinishPasskeyRequest>,
+) -> Result<(CookieJar, impl IntoResponse), ApiError> {
+    let credential: webauthn_rs::prelude::RegisterPublicKeyCredential = serde_json::from_value(req.response)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, format!("Invalid response: {}", e)))?;
+
+    let _guard = SETUP_LOCK.lock().await;
+    let snapshot = require_step(&state, SetupStep::Admin).await?;
+    mark_started(snapshot.progress)?;
+
+    let config = resolve_webauthn_config(&headers, &state);
+    let credential_id = state
+        .webauthn_manager
+        .finish_registration(&config, &req.challenge, credential, &req.name, AdminRole::Owner)
+        .await
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, e))?;
+    audit::record(
+        "passkey.registered",
+        None,
+        None,
+        Some(json!({ "name": req.name, "role": AdminRole::Owner })),
+    );
+    tracing::info!("[Setup] First passkey registered: {}", req.name);
+
+    let token = state
+        .session_manager
+        .create_passkey_session(&credential_id, session_client(&headers))
+        .await;
+    let jar = jar.add(session_cookie(&state, &headers, token));
+    Ok((jar, Json(Snapshot::load(&state).await?.to_json())))
+}
+
+/// 添加第一个账号（请求体同 POST /api/accounts）
+///
+/// POST /api/setup/account
+pub async fn add_account(State(state): State<AppState>, Json(req): Json<CreateAccountRequest>) -> Response {
+    let _guard = SETUP_LOCK.lock().await;
+    if let Err(e) = require_step(&state, SetupStep::Account).await {
+        return e.into_response();
+    }
+    crate::proxy::handlers::manage::create_account(State(state), Json(req)).await
+}
+
+#[derive(Deserialize, Default)]
+pub struct SetupApiKeyRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// 生成第一个 API Key 并锁定引导；记录完成失败时撤销该 Key
+///
+/// POST /api/setup/api-key
+pub async fn create_api_key(
+    State(state): State<AppState>,
+    body: Option<Json<SetupApiKeyRequest>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let req = body.map(|Json(req)| req).unwrap_or_default();
+    let name = req.name.as_deref().map(str::trim).filter(|n| !n.is_empty()).unwrap_or(DEFAULT_KEY_NAME);
+
+    let _guard = SETUP_LOCK.lock().await;
+    let mut snapshot = require_step(&state, SetupStep::ApiKey).await?;
+
+    let name = name.to_string();
+    let key = crate::modules::storage::blocking(move || crate::modules::api_keys::create_api_key(&name, None))
+        .await
+        .map_err(internal_error)?;
+    snapshot.progress.completed_at = Some(chrono::Utc::now().timestamp());
+    if let Err(e) = setup::save_progress(&snapshot.progress) {
+        let key_id = key.id.clone();
+        if let Err(undo) = crate::modules::storage::blocking(move || crate::modules::api_keys::delete_api_key(&key_id)).await {
+            tracing::error!("[Setup] Failed to remove API key {} after setup error: {}", key.id, undo);
+        }
+        return Err(internal_error(e));
+    }
+    audit::record(
+        "api_key.created",
+        Some(&key.id),
+        None,
+        audit::snapshot(&crate::modules::api_keys::ApiKeyResponse::from(key.clone())),
+    );
+    audit::record("setup.completed", None, None, None);
+    tracing::info!("[Setup] First-run setup completed");
+
+    let created = CreatedApiKeyResponse {
+        id: key.id,
+        name: key.name,
+        key: key.key,
+        created_at: key.created_at,
+        expires_at: key.expires_at,
+    };
+    let mut body = snapshot.to_json();
+    body["api_key"] = json!(created);
+    Ok((StatusCode::CREATED, Json(body)))
+}
@@ -0,0 +1,136 @@
+//! Token 计数端点
+//!
+//! `POST /v1/token-count`（OpenAI 或 Gemini 请求体）与 Gemini 原生 `:countTokens`。
+//! 优先调用上游 countTokens，上游不可用或指定 `?source=estimate` 时使用本地估算
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::proxy::common::token_count::{estimate_body_tokens, extract_total_tokens};
+use crate::proxy::mappers::openai::{transform_openai_request, OpenAIRequest};
+use crate::proxy::server::AppState;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TokenCountQuery {
+    /// `upstream`（默认）或 `estimate`
+    source: Option<String>,
+}
+
+/// 计数结果来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountSource {
+    Upstream,
+    Estimate,
+}
+
+impl CountSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Upstream => "upstream",
+            Self::Estimate => "estimate",
+        }
+    }
+}
+
+/// 统计请求体的输入 token 数；上游失败时回退到本地估算
+pub async fn count_tokens(state: &AppState, model: &str, body: &Value, use_upstream: bool) -> (u32, CountSource) {
+    let estimate = estimate_body_tokens(body);
+    if !use_upstream {
+        return (estimate, CountSource::Estimate);
+    }
+    match count_tokens_upstream(state, model, body).await {
+        Ok(tokens) => (tokens, CountSource::Upstream),
+        Err(e) => {
+            tracing::warn!("[TokenCount] {}. Falling back to estimate.", e);
+            (estimate, CountSource::Estimate)
+        }
+    }
+}
+
+async fn count_tokens_upstream(state: &AppState, model: &str, body: &Value) -> Result<u32, String> {
+    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        model,
+        &*state.custom_mapping.read().await,
+        &*state.openai_mapping.read().await,
+        &*state.anthropic_mapping.read().await,
+        false,
+    );
+    let quota_group = if mapped_model.to_ascii_lowercase().contains("claude") { "claude" } else { "gemini" };
+    let selected = state
+        .token_manager
+        .get_token(quota_group, "agent", false, None)
+        .await
+        .map_err(|e| format!("Token error: {}", e))?;
+
+    let wrapped = if body.get("contents").is_some() {
+        crate::proxy::mappers::gemini::wrap_request(body, &selected.project_id, &mapped_model)
+    } else {
+        let request: OpenAIRequest =
+            serde_json::from_value(body.clone()).map_err(|e| format!("Unsupported request body: {}", e))?;
+        transform_openai_request(&request, &selected.project_id, &mapped_model)
+    };
+
+    let resp = state
+        .upstream
+        .call_v1_internal("countTokens", &selected.access_token, wrapped, None, selected.client_profile.as_deref())
+        .await?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(format!("Upstream error {}", status));
+    }
+    let value: Value = resp.json().await.map_err(|e| format!("Parse error: {}", e))?;
+    extract_total_tokens(&value).ok_or_else(|| "Upstream response has no token count".to_string())
+}
+
+fn use_upstream(query: &TokenCountQuery) -> Result<bool, String> {
+    match query.source.as_deref() {
+        None | Some("upstream") => Ok(true),
+        Some("estimate") => Ok(false),
+        Some(other) => Err(format!("Unknown source '{}', expected 'upstream' or 'estimate'", other)),
+    }
+}
+
+/// POST /v1/token-count
+pub async fn handle_token_count(
+    State(state): State<AppState>,
+    Query(query): Query<TokenCountQuery>,
+    Json(body): Json<Value>,
+) -> Response {
+    let use_upstream = match use_upstream(&query) {
+        Ok(v) => v,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let Some(model) = body.get("model").and_then(|m| m.as_str()).map(|m| m.to_string()) else {
+        return (StatusCode::BAD_REQUEST, "model is required".to_string()).into_response();
+    };
+    if body.get("messages").is_none() && body.get("contents").is_none() {
+        return (StatusCode::BAD_REQUEST, "Either messages or contents is required".to_string()).into_response();
+    }
+
+    let (input_tokens, source) = count_tokens(&state, &model, &body, use_upstream).await;
+    Json(json!({
+        "object": "token_count",
+        "model": model,
+        "input_tokens": input_tokens,
+        "source": source.as_str(),
+    }))
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_use_upstream_query() {
+        assert!(use_upstream(&TokenCountQuery::default()).unwrap());
+        assert!(!use_upstream(&TokenCountQuery { source: Some("estimate".to_string()) }).unwrap());
+        assert!(use_upstream(&TokenCountQuery { source: Some("tiktoken".to_string()) }).is_err());
+    }
+}
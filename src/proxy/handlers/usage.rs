@@ -0,0 +1,86 @@
+//! 终端用户用量查询端点 (GET /v1/usage)
+//!
+//! 使用 API Key 本身认证（而不是管理后台会话），只返回该 Key 自己的用量、
+//! 当日 token 预算余量与限流窗口状态
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde_json::json;
+
+use crate::modules::{api_keys, storage};
+use crate::proxy::common::error::error_response;
+use crate::proxy::key_rate_limit::KEY_RATE_LIMITER;
+use crate::proxy::middleware::AuthenticatedKey;
+
+/// 返回当前 API Key 的请求数、token 用量、剩余每日预算与限流状态
+pub async fn handle_key_usage(auth_key: Option<Extension<AuthenticatedKey>>) -> Response {
+    let Some(Extension(auth_key)) = auth_key else {
+        return error_response(
+            StatusCode::UNAUTHORIZED,
+            "missing_api_key",
+            "A valid API key is required to query usage",
+        );
+    };
+    if auth_key.key_id == "legacy" {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "usage_not_tracked",
+            "Usage is only tracked for API keys created in the web console",
+        );
+    }
+
+    let key_id = auth_key.key_id.clone();
+    let key = match storage::blocking(move || api_keys::get_api_key(&key_id)).await {
+        Ok(Some(key)) => key,
+        Ok(None) => {
+            return error_response(StatusCode::UNAUTHORIZED, "invalid_api_key", "API key not found");
+        }
+        Err(e) => {
+            tracing::error!("Failed to load API key usage: {}", e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "Failed to load usage");
+        }
+    };
+
+    let daily_budget = match key.daily_token_budget {
+        Some(budget) => {
+            let key_id = key.id.clone();
+            match storage::blocking(move || crate::modules::quota::key_token_budget_status(&key_id, budget)).await {
+                Ok(status) => Some(status),
+                Err(e) => {
+                    tracing::error!("Failed to load daily token usage for {}: {}", key.name, e);
+                    return error_response(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "Failed to load usage");
+                }
+            }
+        }
+        None => None,
+    };
+    let rate_limits = KEY_RATE_LIMITER.status(&key.id, key.rate_limit_rpm, key.rate_limit_rpd);
+
+    Json(json!({
+        "object": "usage",
+        "key": {
+            "id": key.id,
+            "name": key.name,
+            "created_at": key.created_at,
+            "expires_at": key.expires_at,
+            "last_used_at": key.last_used_at,
+        },
+        "requests": {
+            "total": key.total_requests,
+            "success": key.success_count,
+            "error": key.error_count,
+        },
+        "tokens": {
+            "input": key.total_input_tokens,
+            "output": key.total_output_tokens,
+            "total": key.total_input_tokens + key.total_output_tokens,
+        },
+        "estimated_cost": key.total_cost,
+        "daily_budget": daily_budget,
+        "rate_limits": rate_limits,
+    }))
+    .into_response()
+}
@@ -3,21 +3,21 @@
 //! 提供 Passkey 和密码认证的 REST API
 
 use axum::{
-    extract::State,
+    extract::{Path, State},
     http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
     Json,
 };
-use axum_extra::extract::CookieJar;
+use axum_extra::extract::{cookie::Cookie, CookieJar};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
-use crate::modules::webauthn::AuthMode;
+use crate::modules::audit;
+use crate::modules::webauthn::{AdminRole, AuthMode, SessionClient, SessionIdentity};
+use crate::proxy::auth_guard::{AUTH_GUARD, UNKNOWN_IP};
 use crate::proxy::server::AppState;
 
-const SESSION_COOKIE_NAME: &str = "antiproxy_session";
-
-fn resolve_webauthn_config(headers: &HeaderMap, state: &AppState) -> crate::modules::webauthn::WebAuthnConfig {
+pub(crate) fn resolve_webauthn_config(headers: &HeaderMap, state: &AppState) -> crate::modules::webauthn::WebAuthnConfig {
     let host = headers
         .get(header::HOST)
         .and_then(|v| v.to_str().ok())
@@ -35,6 +35,12 @@ fn resolve_webauthn_config(headers: &HeaderMap, state: &AppState) -> crate::modu
             .unwrap_or(state.bind_port)
     };
 
+    crate::modules::webauthn::WebAuthnConfig::from_host(host, port, is_https(headers))
+        .unwrap_or_else(|_| crate::modules::webauthn::WebAuthnConfig::localhost(state.bind_port))
+}
+
+/// 请求是否经 HTTPS 访问（由反向代理通过 `X-Forwarded-Proto` / `Forwarded` 告知）
+fn is_https(headers: &HeaderMap) -> bool {
     let proto = headers
         .get("x-forwarded-proto")
         .and_then(|v| v.to_str().ok())
@@ -43,11 +49,122 @@ fn resolve_webauthn_config(headers: &HeaderMap, state: &AppState) -> crate::modu
         .get("forwarded")
         .and_then(|v| v.to_str().ok())
         .unwrap_or("");
-    let is_https = proto.eq_ignore_ascii_case("https")
-        || forwarded.to_ascii_lowercase().contains("proto=https");
+    proto.eq_ignore_ascii_case("https") || forwarded.to_ascii_lowercase().contains("proto=https")
+}
 
-    crate::modules::webauthn::WebAuthnConfig::from_host(host, port, is_https)
-        .unwrap_or_else(|_| crate::modules::webauthn::WebAuthnConfig::localhost(state.bind_port))
+/// 登录成功后下发的 session cookie（HttpOnly，有效期与属性见 `session` 配置）
+pub(crate) fn session_cookie(state: &AppState, headers: &HeaderMap, token: String) -> Cookie<'static> {
+    use crate::proxy::config::CookieSameSite;
+    use axum_extra::extract::cookie::SameSite;
+
+    let config = state.session_manager.config();
+    let same_site = match config.cookie_same_site {
+        CookieSameSite::Strict => SameSite::Strict,
+        CookieSameSite::Lax => SameSite::Lax,
+        CookieSameSite::None => SameSite::None,
+    };
+    // 浏览器拒绝没有 Secure 的 SameSite=None cookie
+    let secure = config
+        .cookie_secure
+        .unwrap_or_else(|| config.cookie_same_site == CookieSameSite::None || is_https(headers));
+    let path = session_cookie_path(&config);
+    Cookie::build((config.cookie_name, token))
+        .path(path)
+        .http_only(true)
+        .secure(secure)
+        .same_site(same_site)
+        .max_age(time::Duration::hours(config.ttl_hours as i64))
+        .build()
+}
+
+/// cookie 的 Path：未单独配置时使用 `base_path`
+fn session_cookie_path(config: &crate::proxy::config::SessionConfig) -> String {
+    match crate::proxy::middleware::base_path::get() {
+        base if config.cookie_path == "/" && !base.is_empty() => base.to_string(),
+        _ => config.cookie_path.clone(),
+    }
+}
+
+/// 清除 session cookie（名称与 Path 需与下发时一致）
+fn clear_session_cookie(state: &AppState) -> Cookie<'static> {
+    let config = state.session_manager.config();
+    let path = session_cookie_path(&config);
+    Cookie::build((config.cookie_name, ""))
+        .path(path)
+        .max_age(time::Duration::ZERO)
+        .build()
+}
+
+/// 请求携带的 session token
+fn session_token(jar: &CookieJar, state: &AppState) -> Option<String> {
+    jar.get(&state.session_manager.config().cookie_name)
+        .map(|cookie| cookie.value().to_string())
+}
+
+/// 登录请求的客户端 IP（由 web_auth 中间件解析）与 User-Agent
+pub(crate) fn session_client(headers: &HeaderMap) -> SessionClient {
+    SessionClient {
+        ip: audit::current_actor().1,
+        user_agent: headers
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(|ua| ua.chars().take(256).collect()),
+    }
+}
+
+/// 登录请求的客户端 IP，用于失败锁定计数
+fn login_ip() -> String {
+    audit::current_actor().1.unwrap_or_else(|| UNKNOWN_IP.to_string())
+}
+
+/// 返回当前有效的 session token
+///
+/// /api/auth/ 下的路由不经过 web_auth 中间件，需要登录的接口自行校验
+async fn require_session(jar: &CookieJar, state: &AppState) -> Result<String, (StatusCode, Json<Value>)> {
+    require_role(jar, state, AdminRole::Viewer).await
+}
+
+/// 返回当前有效且角色不低于 `role` 的 session token
+async fn require_role(
+    jar: &CookieJar,
+    state: &AppState,
+    role: AdminRole,
+) -> Result<String, (StatusCode, Json<Value>)> {
+    let Some(token) = session_token(jar, state) else {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "Authentication required" })),
+        ));
+    };
+    match crate::proxy::middleware::web_auth::session_role(state, &token).await {
+        Some(current) if current >= role => Ok(token),
+        Some(_) => Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({ "error": format!("This action requires the {} role", role.as_str()) })),
+        )),
+        None => Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "Authentication required" })),
+        )),
+    }
+}
+
+/// 已有 Passkey 时，只有 owner 登录后才能注册新设备
+async fn require_session_for_registration(
+    jar: &CookieJar,
+    state: &AppState,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    if !state.webauthn_manager.has_credentials().await {
+        return Ok(());
+    }
+    match require_role(jar, state, AdminRole::Owner).await {
+        Ok(_) => Ok(()),
+        Err((StatusCode::UNAUTHORIZED, _)) => Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "Sign in with an existing passkey to add another device" })),
+        )),
+        Err(e) => Err(e),
+    }
 }
 
 /// 检查认证状态
@@ -61,6 +178,10 @@ pub struct AuthStatusResponse {
     pub auth_mode: String,
     /// 已注册的凭据数量 (仅 passkey 模式)
     pub credential_count: usize,
+    /// 是否已绑定 TOTP
+    pub totp_enabled: bool,
+    /// 当前 session 的角色 (未登录时为 null)
+    pub role: Option<AdminRole>,
 }
 
 /// 获取认证状态
@@ -69,14 +190,13 @@ pub async fn get_auth_status(
     jar: CookieJar,
 ) -> impl IntoResponse {
     let webauthn = &state.webauthn_manager;
-    let sessions = &state.session_manager;
 
     // 检查 session cookie
-    let authenticated = if let Some(cookie) = jar.get(SESSION_COOKIE_NAME) {
-        sessions.validate_session(cookie.value()).await
-    } else {
-        false
+    let role = match session_token(&jar, &state) {
+        Some(token) => crate::proxy::middleware::web_auth::session_role(&state, &token).await,
+        None => None,
     };
+    let authenticated = role.is_some();
 
     let auth_mode = webauthn.get_auth_mode().await;
     let credential_count = webauthn.credential_count().await;
@@ -93,6 +213,8 @@ pub async fn get_auth_status(
         needs_setup,
         auth_mode: auth_mode_str.to_string(),
         credential_count,
+        totp_enabled: webauthn.totp_enabled().await,
+        role,
     })
 }
 
@@ -113,6 +235,7 @@ pub async fn setup_password(
 
     match webauthn.setup_password(&req.password).await {
         Ok(()) => {
+            audit::record("auth.password_configured", None, None, None);
             tracing::info!("Password authentication configured");
             Ok(Json(json!({ "success": true })))
         }
@@ -129,12 +252,19 @@ pub async fn setup_password(
 /// 密码登录请求
 #[derive(Deserialize)]
 pub struct PasswordLoginRequest {
+    /// 附加管理员用户名（留空使用主密码）
+    #[serde(default)]
+    pub username: Option<String>,
     pub password: String,
+    /// 已绑定 TOTP 时必填 (第二因素)
+    #[serde(default)]
+    pub totp_code: Option<String>,
 }
 
 /// 密码登录
 pub async fn password_login(
     State(state): State<AppState>,
+    headers: HeaderMap,
     jar: CookieJar,
     Json(req): Json<PasswordLoginRequest>,
 ) -> Result<(CookieJar, impl IntoResponse), (StatusCode, Json<Value>)> {
@@ -150,25 +280,57 @@ pub async fn password_login(
         ));
     }
 
+    // 附加管理员（TOTP 只绑定在主密码上）
+    if let Some(username) = req.username.as_deref().map(str::trim).filter(|u| !u.is_empty()) {
+        if webauthn.verify_user_password(username, &req.password).await.is_none() {
+            tracing::warn!("Failed password login attempt for user {}", username);
+            AUTH_GUARD.record_failure(&login_ip(), "password");
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                Json(json!({ "error": "Invalid username or password" })),
+            ));
+        }
+        AUTH_GUARD.record_success(&login_ip());
+        let token = sessions.create_user_session(username, session_client(&headers)).await;
+        let jar = jar.add(session_cookie(&state, &headers, token));
+        tracing::info!("Password authentication successful for user {}", username);
+        return Ok((jar, Json(json!({ "success": true }))));
+    }
+
     // 验证密码
     if !webauthn.verify_password(&req.password).await {
         tracing::warn!("Failed password login attempt");
+        AUTH_GUARD.record_failure(&login_ip(), "password");
         return Err((
             StatusCode::UNAUTHORIZED,
             Json(json!({ "error": "Invalid password" })),
         ));
     }
 
+    // 第二因素
+    if webauthn.totp_enabled().await {
+        let Some(code) = req.totp_code.as_deref().filter(|c| !c.trim().is_empty()) else {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                Json(json!({ "error": "TOTP code required", "totp_required": true })),
+            ));
+        };
+        if let Err(e) = webauthn.verify_totp(code).await {
+            tracing::warn!("Failed TOTP verification during password login");
+            AUTH_GUARD.record_failure(&login_ip(), "totp");
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                Json(json!({ "error": e, "totp_required": true })),
+            ));
+        }
+    }
+
     // 创建 session
-    let session_token = sessions.create_session().await;
+    AUTH_GUARD.record_success(&login_ip());
+    let session_token = sessions.create_session(session_client(&headers)).await;
 
     // 设置 cookie
-    let cookie = axum_extra::extract::cookie::Cookie::build((SESSION_COOKIE_NAME, session_token))
-        .path("/")
-        .http_only(true)
-        .same_site(axum_extra::extract::cookie::SameSite::Lax)
-        .max_age(time::Duration::days(7))
-        .build();
+    let cookie = session_cookie(&state, &headers, session_token);
 
     let jar = jar.add(cookie);
 
@@ -192,6 +354,7 @@ pub async fn change_password(
 
     match webauthn.change_password(&req.old_password, &req.new_password).await {
         Ok(()) => {
+            audit::record("auth.password_changed", None, None, None);
             tracing::info!("Password changed successfully");
             Ok(Json(json!({ "success": true })))
         }
@@ -213,34 +376,24 @@ pub async fn reset_auth(
     let webauthn = &state.webauthn_manager;
     let sessions = &state.session_manager;
 
-    // 验证当前已认证
-    let authenticated = if let Some(cookie) = jar.get(SESSION_COOKIE_NAME) {
-        sessions.validate_session(cookie.value()).await
-    } else {
-        false
-    };
-
-    if !authenticated {
-        return Err((
-            StatusCode::UNAUTHORIZED,
-            Json(json!({ "error": "Must be authenticated to reset" })),
-        ));
+    // 验证当前已认证且为 owner
+    if let Err((status, body)) = require_role(&jar, &state, AdminRole::Owner).await {
+        if status == StatusCode::UNAUTHORIZED {
+            return Err((status, Json(json!({ "error": "Must be authenticated to reset" }))));
+        }
+        return Err((status, body));
     }
 
     match webauthn.reset_auth().await {
         Ok(()) => {
             // 清除当前 session
-            if let Some(cookie) = jar.get(SESSION_COOKIE_NAME) {
-                sessions.delete_session(cookie.value()).await;
+            if let Some(token) = session_token(&jar, &state) {
+                sessions.delete_session(&token).await;
             }
 
-            let cookie = axum_extra::extract::cookie::Cookie::build((SESSION_COOKIE_NAME, ""))
-                .path("/")
-                .max_age(time::Duration::ZERO)
-                .build();
-
-            let jar = jar.add(cookie);
+            let jar = jar.add(clear_session_cookie(&state));
 
+            audit::record("auth.reset", None, None, None);
             tracing::info!("Authentication reset");
             Ok((jar, Json(json!({ "success": true }))))
         }
@@ -267,8 +420,10 @@ pub struct StartRegistrationRequest {
 pub async fn start_registration(
     State(state): State<AppState>,
     headers: HeaderMap,
+    jar: CookieJar,
     Json(req): Json<StartRegistrationRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    require_session_for_registration(&jar, &state).await?;
     let webauthn = &state.webauthn_manager;
 
     let config = resolve_webauthn_config(&headers, &state);
@@ -297,6 +452,9 @@ pub struct FinishRegistrationRequest {
     pub challenge: String,
     /// 用户名
     pub name: String,
+    /// 新设备的角色（默认 owner；首个 Passkey 始终为 owner）
+    #[serde(default)]
+    pub role: AdminRole,
     /// WebAuthn 响应
     pub response: Value,
 }
@@ -305,8 +463,10 @@ pub struct FinishRegistrationRequest {
 pub async fn finish_registration(
     State(state): State<AppState>,
     headers: HeaderMap,
+    jar: CookieJar,
     Json(req): Json<FinishRegistrationRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    require_session_for_registration(&jar, &state).await?;
     let webauthn = &state.webauthn_manager;
 
     let config = resolve_webauthn_config(&headers, &state);
@@ -322,10 +482,16 @@ pub async fn finish_registration(
         })?;
 
     match webauthn
-        .finish_registration(&config, &req.challenge, credential, &req.name)
+        .finish_registration(&config, &req.challenge, credential, &req.name, req.role)
         .await
     {
-        Ok(()) => {
+        Ok(_) => {
+            audit::record(
+                "passkey.registered",
+                None,
+                None,
+                Some(json!({ "name": req.name, "role": req.role })),
+            );
             tracing::info!("Passkey registered successfully: {}", req.name);
             Ok(Json(json!({ "success": true })))
         }
@@ -400,17 +566,13 @@ pub async fn finish_authentication(
         .finish_authentication(&config, &req.challenge, credential)
         .await
     {
-        Ok(_) => {
-            // 创建 session
-            let session_token = sessions.create_session().await;
+        Ok(credential_id) => {
+            AUTH_GUARD.record_success(&login_ip());
+            // 创建 session（记录所用凭据，吊销设备时一并失效）
+            let session_token = sessions.create_passkey_session(&credential_id, session_client(&headers)).await;
 
             // 设置 cookie (HttpOnly, 7天有效)
-            let cookie = axum_extra::extract::cookie::Cookie::build((SESSION_COOKIE_NAME, session_token))
-                .path("/")
-                .http_only(true)
-                .same_site(axum_extra::extract::cookie::SameSite::Lax)
-                .max_age(time::Duration::days(7))
-                .build();
+            let cookie = session_cookie(&state, &headers, session_token);
 
             let jar = jar.add(cookie);
 
@@ -419,6 +581,7 @@ pub async fn finish_authentication(
         }
         Err(e) => {
             tracing::error!("Failed to finish authentication: {}", e);
+            AUTH_GUARD.record_failure(&login_ip(), "passkey");
             Err((
                 StatusCode::UNAUTHORIZED,
                 Json(json!({ "error": e })),
@@ -435,17 +598,12 @@ pub async fn logout(
     let sessions = &state.session_manager;
 
     // 删除 session
-    if let Some(cookie) = jar.get(SESSION_COOKIE_NAME) {
-        sessions.delete_session(cookie.value()).await;
+    if let Some(token) = session_token(&jar, &state) {
+        sessions.delete_session(&token).await;
     }
 
     // 清除 cookie
-    let cookie = axum_extra::extract::cookie::Cookie::build((SESSION_COOKIE_NAME, ""))
-        .path("/")
-        .max_age(time::Duration::ZERO)
-        .build();
-
-    let jar = jar.add(cookie);
+    let jar = jar.add(clear_session_cookie(&state));
 
     (jar, Json(json!({ "success": true })))
 }
@@ -453,11 +611,13 @@ pub async fn logout(
 /// 列出已注册的凭据
 pub async fn list_credentials(
     State(state): State<AppState>,
-) -> impl IntoResponse {
+    jar: CookieJar,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    require_session(&jar, &state).await?;
     let webauthn = &state.webauthn_manager;
     let credentials = webauthn.list_credentials().await;
 
-    Json(json!({ "credentials": credentials }))
+    Ok(Json(json!({ "credentials": credentials })))
 }
 
 /// 删除凭据请求
@@ -469,8 +629,16 @@ pub struct DeleteCredentialRequest {
 /// 删除凭据
 pub async fn delete_credential(
     State(state): State<AppState>,
+    jar: CookieJar,
     Json(req): Json<DeleteCredentialRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    require_role(&jar, &state, AdminRole::Owner).await?;
+    revoke_credential(&state, &req.credential_id).await?;
+    Ok(Json(json!({ "success": true })))
+}
+
+/// 删除凭据并使其登录的 session 失效，返回失效的 session 数量
+async fn revoke_credential(state: &AppState, credential_id: &str) -> Result<usize, (StatusCode, Json<Value>)> {
     let webauthn = &state.webauthn_manager;
 
     // 确保至少保留一个凭据
@@ -481,11 +649,470 @@ pub async fn delete_credential(
         ));
     }
 
-    match webauthn.delete_credential(&req.credential_id).await {
-        Ok(()) => Ok(Json(json!({ "success": true }))),
+    let before = webauthn
+        .list_credentials()
+        .await
+        .into_iter()
+        .find(|c| c.credential_id == credential_id);
+    if let Err(e) = webauthn.delete_credential(credential_id).await {
+        let status = if e == "Credential not found" {
+            StatusCode::NOT_FOUND
+        } else {
+            StatusCode::BAD_REQUEST
+        };
+        return Err((status, Json(json!({ "error": e }))));
+    }
+
+    let revoked = state.session_manager.revoke_credential_sessions(credential_id).await;
+    audit::record("passkey.revoked", Some(credential_id), before.as_ref().and_then(audit::snapshot), None);
+    tracing::info!("Passkey revoked: {} ({} sessions signed out)", credential_id, revoked);
+    Ok(revoked)
+}
+
+// ===== Passkey Device Management =====
+
+/// Passkey 设备信息
+#[derive(Serialize)]
+pub struct PasskeyResponse {
+    pub id: String,
+    pub name: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+    pub role: AdminRole,
+    /// 是否为当前 session 登录所用的设备
+    pub current: bool,
+}
+
+fn passkey_response(info: crate::modules::webauthn::CredentialInfo, current: Option<&str>) -> PasskeyResponse {
+    PasskeyResponse {
+        current: current == Some(info.credential_id.as_str()),
+        id: info.credential_id,
+        name: info.user_name,
+        created_at: info.created_at,
+        last_used_at: info.last_used_at,
+        role: info.role,
+    }
+}
+
+/// 列出 Passkey 设备 (GET /api/auth/passkeys)
+pub async fn list_passkeys(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let token = require_session(&jar, &state).await?;
+    let current = state.session_manager.session_credential(&token).await;
+
+    let passkeys: Vec<PasskeyResponse> = state
+        .webauthn_manager
+        .list_credentials()
+        .await
+        .into_iter()
+        .map(|info| passkey_response(info, current.as_deref()))
+        .collect();
+
+    Ok(Json(json!({ "passkeys": passkeys })))
+}
+
+/// 重命名设备请求
+#[derive(Deserialize)]
+pub struct RenamePasskeyRequest {
+    pub name: String,
+}
+
+/// 重命名 Passkey 设备 (PATCH /api/auth/passkeys/:id)
+pub async fn rename_passkey(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Path(id): Path<String>,
+    Json(req): Json<RenamePasskeyRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let token = require_role(&jar, &state, AdminRole::Owner).await?;
+    let current = state.session_manager.session_credential(&token).await;
+
+    let before = state
+        .webauthn_manager
+        .list_credentials()
+        .await
+        .into_iter()
+        .find(|c| c.credential_id == id);
+    match state.webauthn_manager.rename_credential(&id, &req.name).await {
+        Ok(info) => {
+            audit::record(
+                "passkey.renamed",
+                Some(&id),
+                before.as_ref().and_then(audit::snapshot),
+                audit::snapshot(&info),
+            );
+            Ok(Json(passkey_response(info, current.as_deref())))
+        }
+        Err(e) => {
+            let status = if e == "Credential not found" {
+                StatusCode::NOT_FOUND
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            Err((status, Json(json!({ "error": e }))))
+        }
+    }
+}
+
+/// 吊销 Passkey 设备 (DELETE /api/auth/passkeys/:id)
+pub async fn revoke_passkey(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    require_role(&jar, &state, AdminRole::Owner).await?;
+    let revoked_sessions = revoke_credential(&state, &id).await?;
+    Ok(Json(json!({ "success": true, "revoked_sessions": revoked_sessions })))
+}
+
+// ===== Sessions =====
+//
+// owner 可管理所有 session，其他角色只能管理自己登录身份的 session
+
+/// session 列表项
+#[derive(Serialize)]
+pub struct SessionResponse {
+    #[serde(flatten)]
+    pub info: crate::modules::webauthn::SessionInfo,
+    /// 登录身份：`password`、`passkey:<name>` 或 `user:<name>`
+    pub actor: String,
+}
+
+/// 返回当前 session token 与可管理的登录身份（`None` 表示全部）
+async fn session_scope(
+    jar: &CookieJar,
+    state: &AppState,
+) -> Result<(String, Option<SessionIdentity>), (StatusCode, Json<Value>)> {
+    let token = require_session(jar, state).await?;
+    if crate::proxy::middleware::web_auth::session_role(state, &token).await == Some(AdminRole::Owner) {
+        return Ok((token, None));
+    }
+    let identity = state.session_manager.session_identity(&token).await;
+    Ok((token, identity))
+}
+
+async fn scoped_sessions(state: &AppState, token: &str, scope: Option<&SessionIdentity>) -> Vec<SessionResponse> {
+    let mut list = Vec::new();
+    for info in state.session_manager.list_sessions(token).await {
+        if scope.is_some_and(|identity| *identity != info.identity) {
+            continue;
+        }
+        let actor = crate::proxy::middleware::web_auth::identity_actor(state, Some(info.identity.clone())).await;
+        list.push(SessionResponse { info, actor });
+    }
+    list
+}
+
+/// 列出活跃的 Web UI session (GET /api/auth/sessions)
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let (token, scope) = session_scope(&jar, &state).await?;
+    Ok(Json(json!({ "sessions": scoped_sessions(&state, &token, scope.as_ref()).await })))
+}
+
+/// 吊销一个 session (DELETE /api/auth/sessions/:id)；吊销当前 session 时同时清除 cookie
+pub async fn revoke_session(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Path(id): Path<String>,
+) -> Result<(CookieJar, impl IntoResponse), (StatusCode, Json<Value>)> {
+    let (token, scope) = session_scope(&jar, &state).await?;
+    let Some(session) = scoped_sessions(&state, &token, scope.as_ref())
+        .await
+        .into_iter()
+        .find(|s| s.info.id == id)
+    else {
+        return Err((StatusCode::NOT_FOUND, Json(json!({ "error": "Session not found" }))));
+    };
+
+    state.session_manager.revoke_session(&id).await;
+    audit::record("session.revoked", Some(&id), audit::snapshot(&session), None);
+
+    let jar = if session.info.current {
+        jar.add(clear_session_cookie(&state))
+    } else {
+        jar
+    };
+    Ok((jar, Json(json!({ "success": true, "current": session.info.current }))))
+}
+
+/// 登出其他所有设备 (DELETE /api/auth/sessions)，保留当前 session
+pub async fn revoke_other_sessions(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let (token, scope) = session_scope(&jar, &state).await?;
+    let revoked = state
+        .session_manager
+        .revoke_other_sessions(&token, |identity| scope.as_ref().is_none_or(|s| s == identity))
+        .await;
+    if revoked > 0 {
+        audit::record("session.revoked_others", None, None, Some(json!({ "revoked_sessions": revoked })));
+    }
+    Ok(Json(json!({ "success": true, "revoked_sessions": revoked })))
+}
+
+// ===== Admin Management =====
+//
+// /api/admins 经过 web_auth 中间件，仅 owner 可访问
+
+fn admin_error(e: String) -> (StatusCode, Json<Value>) {
+    let status = if e.ends_with("not found") {
+        StatusCode::NOT_FOUND
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    (status, Json(json!({ "error": e })))
+}
+
+/// 列出所有管理员身份 (GET /api/admins)
+pub async fn list_admins(State(state): State<AppState>) -> impl IntoResponse {
+    let webauthn = &state.webauthn_manager;
+    let passkeys: Vec<PasskeyResponse> = webauthn
+        .list_credentials()
+        .await
+        .into_iter()
+        .map(|info| passkey_response(info, None))
+        .collect();
+
+    Json(json!({
+        "auth_mode": webauthn.get_auth_mode().await,
+        "passkeys": passkeys,
+        "users": webauthn.list_admin_users().await,
+        "tokens": webauthn.list_admin_tokens().await,
+    }))
+}
+
+/// 添加附加管理员请求
+#[derive(Deserialize)]
+pub struct CreateAdminUserRequest {
+    pub name: String,
+    pub password: String,
+    pub role: AdminRole,
+}
+
+/// 添加附加管理员 (POST /api/admins/users，仅密码模式)
+pub async fn create_admin_user(
+    State(state): State<AppState>,
+    Json(req): Json<CreateAdminUserRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let info = state
+        .webauthn_manager
+        .add_admin_user(&req.name, &req.password, req.role)
+        .await
+        .map_err(admin_error)?;
+    audit::record("admin.user_created", Some(&info.name), None, audit::snapshot(&info));
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+/// 修改附加管理员请求
+#[derive(Deserialize)]
+pub struct UpdateAdminUserRequest {
+    #[serde(default)]
+    pub role: Option<AdminRole>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// 修改附加管理员的角色 / 密码 (PATCH /api/admins/users/:name)
+pub async fn update_admin_user(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(req): Json<UpdateAdminUserRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let webauthn = &state.webauthn_manager;
+    let before = webauthn.list_admin_users().await.into_iter().find(|u| u.name == name);
+    let info = webauthn
+        .update_admin_user(&name, req.role, req.password.as_deref())
+        .await
+        .map_err(admin_error)?;
+    // 修改密码后需重新登录
+    if req.password.is_some() {
+        state.session_manager.revoke_user_sessions(&name).await;
+    }
+    audit::record(
+        "admin.user_updated",
+        Some(&name),
+        before.as_ref().and_then(audit::snapshot),
+        audit::snapshot(&info),
+    );
+    Ok(Json(info))
+}
+
+/// 删除附加管理员并使其 session 失效 (DELETE /api/admins/users/:name)
+pub async fn delete_admin_user(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let info = state
+        .webauthn_manager
+        .remove_admin_user(&name)
+        .await
+        .map_err(admin_error)?;
+    let revoked = state.session_manager.revoke_user_sessions(&name).await;
+    audit::record("admin.user_removed", Some(&name), audit::snapshot(&info), None);
+    Ok(Json(json!({ "success": true, "revoked_sessions": revoked })))
+}
+
+/// 创建 Admin API token 请求
+#[derive(Deserialize)]
+pub struct CreateAdminTokenRequest {
+    pub name: String,
+    pub role: AdminRole,
+}
+
+/// 创建 Admin API token (POST /api/admins/tokens)，明文 token 只在响应中返回一次
+pub async fn create_admin_token(
+    State(state): State<AppState>,
+    Json(req): Json<CreateAdminTokenRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let (info, token) = state
+        .webauthn_manager
+        .create_admin_token(&req.name, req.role)
+        .await
+        .map_err(admin_error)?;
+    audit::record("admin.token_created", Some(&info.id), None, audit::snapshot(&info));
+    let mut body = serde_json::to_value(&info).unwrap_or_default();
+    body["token"] = json!(token);
+    Ok((StatusCode::CREATED, Json(body)))
+}
+
+/// 吊销 Admin API token (DELETE /api/admins/tokens/:id)
+pub async fn delete_admin_token(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let info = state
+        .webauthn_manager
+        .remove_admin_token(&id)
+        .await
+        .map_err(admin_error)?;
+    audit::record("admin.token_revoked", Some(&id), audit::snapshot(&info), None);
+    Ok(Json(json!({ "success": true })))
+}
+
+/// 修改 Passkey 角色请求
+#[derive(Deserialize)]
+pub struct UpdatePasskeyRoleRequest {
+    pub role: AdminRole,
+}
+
+/// 修改 Passkey 的角色 (PATCH /api/admins/passkeys/:id)
+pub async fn update_passkey_role(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<UpdatePasskeyRoleRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let webauthn = &state.webauthn_manager;
+    let before = webauthn.list_credentials().await.into_iter().find(|c| c.credential_id == id);
+    let info = webauthn.set_credential_role(&id, req.role).await.map_err(admin_error)?;
+    audit::record(
+        "admin.passkey_role_changed",
+        Some(&id),
+        before.as_ref().and_then(audit::snapshot),
+        audit::snapshot(&info),
+    );
+    Ok(Json(passkey_response(info, None)))
+}
+
+// ===== TOTP =====
+
+/// TOTP 动态码请求
+#[derive(Deserialize)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+/// 开始绑定 TOTP (POST /api/auth/totp/enroll)，返回密钥与二维码
+pub async fn totp_enroll(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    require_role(&jar, &state, AdminRole::Owner).await?;
+
+    match state.webauthn_manager.start_totp_enrollment("admin").await {
+        Ok(enrollment) => Ok(Json(enrollment)),
         Err(e) => Err((
-            StatusCode::NOT_FOUND,
+            StatusCode::BAD_REQUEST,
             Json(json!({ "error": e })),
         )),
     }
 }
+
+/// 确认绑定 TOTP (POST /api/auth/totp/confirm)
+pub async fn totp_confirm(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(req): Json<TotpCodeRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    require_role(&jar, &state, AdminRole::Owner).await?;
+
+    match state.webauthn_manager.confirm_totp_enrollment(&req.code).await {
+        Ok(()) => {
+            audit::record("auth.totp_enabled", None, None, None);
+            Ok(Json(json!({ "success": true })))
+        }
+        Err(e) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": e })),
+        )),
+    }
+}
+
+/// 解除 TOTP 绑定 (DELETE /api/auth/totp)
+pub async fn totp_disable(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    require_role(&jar, &state, AdminRole::Owner).await?;
+
+    match state.webauthn_manager.disable_totp().await {
+        Ok(()) => {
+            audit::record("auth.totp_disabled", None, None, None);
+            Ok(Json(json!({ "success": true })))
+        }
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": e })),
+        )),
+    }
+}
+
+/// TOTP 登录 (POST /api/auth/totp/login)
+///
+/// Passkey 模式下的备用登录方式；密码模式下 TOTP 作为第二因素随密码一起提交
+pub async fn totp_login(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(req): Json<TotpCodeRequest>,
+) -> Result<(CookieJar, impl IntoResponse), (StatusCode, Json<Value>)> {
+    let webauthn = &state.webauthn_manager;
+
+    if webauthn.get_auth_mode().await != AuthMode::Passkey {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "TOTP login is only available as a passkey fallback" })),
+        ));
+    }
+
+    if let Err(e) = webauthn.verify_totp(&req.code).await {
+        tracing::warn!("Failed TOTP login attempt");
+        AUTH_GUARD.record_failure(&login_ip(), "totp");
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": e })),
+        ));
+    }
+
+    AUTH_GUARD.record_success(&login_ip());
+    let session_token = state.session_manager.create_session(session_client(&headers)).await;
+    let jar = jar.add(session_cookie(&state, &headers, session_token));
+
+    tracing::info!("TOTP authentication successful");
+    Ok((jar, Json(json!({ "success": true }))))
+}
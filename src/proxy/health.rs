@@ -0,0 +1,101 @@
+// 健康检查
+// /healthz 为存活探针（进程能响应即返回 200）；/readyz 为就绪探针，
+// 检查数据库、可用账号与上游连通性，任一项失败返回 503 并给出各项明细
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::proxy::server::AppState;
+use crate::proxy::upstream::client::Reachability;
+
+/// 无需鉴权、不记录请求日志的探针路径
+pub fn is_probe_path(path: &str) -> bool {
+    matches!(path, "/healthz" | "/readyz")
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(flatten)]
+    detail: serde_json::Value,
+}
+
+impl Check {
+    fn new(ok: bool, error: Option<String>, detail: serde_json::Value) -> Self {
+        Self { ok, error, detail }
+    }
+}
+
+/// 存活探针
+///
+/// GET /healthz
+pub async fn liveness_handler() -> Response {
+    Json(json!({ "status": "ok" })).into_response()
+}
+
+fn check_database() -> Check {
+    use crate::modules::storage::{self, Database};
+    let result = storage::ping(Database::Logs).and_then(|_| storage::ping(Database::ApiKeys));
+    Check::new(result.is_ok(), result.err(), json!({}))
+}
+
+fn check_accounts(state: &AppState) -> Check {
+    let (total, valid) = (state.token_manager.len(), state.token_manager.valid_token_count());
+    let error = (valid == 0).then(|| match total {
+        0 => "No accounts loaded".to_string(),
+        _ => "No account has a valid access token".to_string(),
+    });
+    Check::new(valid > 0, error, json!({ "total": total, "valid": valid }))
+}
+
+fn check_upstream(reachability: Reachability) -> Check {
+    let ok = reachability.is_reachable();
+    let error = (!ok).then(|| "Most recent upstream request could not connect".to_string());
+    Check::new(ok, error, serde_json::to_value(reachability).unwrap_or_default())
+}
+
+/// 就绪探针
+///
+/// GET /readyz
+pub async fn readiness_handler(State(state): State<AppState>) -> Response {
+    let database = tokio::task::spawn_blocking(check_database)
+        .await
+        .unwrap_or_else(|e| Check::new(false, Some(e.to_string()), json!({})));
+    let accounts = check_accounts(&state);
+    let upstream = check_upstream(state.upstream.reachability());
+
+    let ready = database.ok && accounts.ok && upstream.ok;
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    let body = json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "checks": {
+            "database": database,
+            "accounts": accounts,
+            "upstream": upstream,
+        }
+    });
+    (status, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upstream_check() {
+        let reachability = |reached, failed| Reachability { last_reached_at: reached, last_failure_at: failed };
+        assert!(check_upstream(reachability(None, None)).ok);
+        assert!(check_upstream(reachability(Some(10), Some(5))).ok);
+        assert!(!check_upstream(reachability(Some(5), Some(10))).ok);
+        let check = check_upstream(reachability(None, Some(10)));
+        assert!(!check.ok);
+        assert_eq!(serde_json::to_value(&check).unwrap()["last_failure_at"], 10);
+    }
+}
@@ -0,0 +1,156 @@
+// IP 访问控制
+// CIDR 解析与匹配，以及在可信反向代理后面时从 X-Forwarded-For / X-Real-IP 还原客户端地址
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// auth 中间件解析出的客户端地址，存入请求扩展，日志、审计与限流统一使用
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// CIDR 网段（单个地址视为 /32 或 /128）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for IpNet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (addr_part, prefix_part) = match s.split_once('/') {
+            Some((a, p)) => (a, Some(p)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr_part
+            .parse()
+            .map_err(|_| format!("Invalid IP address or CIDR: {}", s))?;
+        let addr = addr.to_canonical();
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix_part {
+            Some(p) => p
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max_prefix)
+                .ok_or_else(|| format!("Invalid CIDR prefix: {}", s))?,
+            None => max_prefix,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl IpNet {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_matches(u32::from(net) as u128, u32::from(ip) as u128, self.prefix, 32)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => prefix_matches(u128::from(net), u128::from(ip), self.prefix, 128),
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(net: u128, ip: u128, prefix: u8, bits: u8) -> bool {
+    if prefix == 0 {
+        return true;
+    }
+    let shift = bits - prefix;
+    (net >> shift) == (ip >> shift)
+}
+
+/// 解析 CIDR 列表，任一条目非法即返回错误
+pub fn parse_list(entries: &[String]) -> Result<Vec<IpNet>, String> {
+    entries
+        .iter()
+        .map(|e| e.trim())
+        .filter(|e| !e.is_empty())
+        .map(IpNet::from_str)
+        .collect()
+}
+
+pub fn matches_any(list: &[IpNet], ip: IpAddr) -> bool {
+    list.iter().any(|net| net.contains(ip))
+}
+
+/// 计算客户端地址
+///
+/// 只有当直连对端属于可信代理时才采信转发头：X-Forwarded-For 从右向左跳过可信代理，
+/// 第一个不可信的地址即为客户端；没有 X-Forwarded-For 时使用 X-Real-IP。防止客户端伪造请求头绕过限制
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    forwarded_for: Option<&str>,
+    real_ip: Option<&str>,
+    trusted_proxies: &[IpNet],
+) -> Option<IpAddr> {
+    let peer = peer?.to_canonical();
+    if !matches_any(trusted_proxies, peer) {
+        return Some(peer);
+    }
+    let Some(forwarded_for) = forwarded_for else {
+        let real_ip = real_ip.and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+        return Some(real_ip.map(|ip| ip.to_canonical()).unwrap_or(peer));
+    };
+
+    let mut client = peer;
+    for hop in forwarded_for.rsplit(',') {
+        let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = ip.to_canonical();
+        if !matches_any(trusted_proxies, client) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nets(entries: &[&str]) -> Vec<IpNet> {
+        parse_list(&entries.iter().map(|s| s.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let list = nets(&["10.0.0.0/8", "192.168.1.7", "2001:db8::/32"]);
+        assert!(matches_any(&list, ip("10.20.30.40")));
+        assert!(matches_any(&list, ip("192.168.1.7")));
+        assert!(!matches_any(&list, ip("192.168.1.8")));
+        assert!(matches_any(&list, ip("2001:db8:1::1")));
+        assert!(!matches_any(&list, ip("2001:db9::1")));
+        // IPv4-mapped IPv6 地址按 IPv4 匹配
+        assert!(matches_any(&list, ip("::ffff:10.1.2.3")));
+        assert!(matches_any(&nets(&["0.0.0.0/0"]), ip("8.8.8.8")));
+
+        assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+        assert!("not-an-ip".parse::<IpNet>().is_err());
+    }
+
+    #[test]
+    fn test_client_ip_forwarded_for() {
+        let trusted = nets(&["127.0.0.1", "10.0.0.0/8"]);
+        let xff = Some("203.0.113.9, 198.51.100.2, 10.0.0.5");
+
+        // 不可信对端：忽略 X-Forwarded-For
+        assert_eq!(client_ip(Some(ip("198.51.100.1")), xff, None, &trusted), Some(ip("198.51.100.1")));
+        // 可信对端：跳过可信代理，取第一个不可信地址（伪造的最左侧地址不被采信）
+        assert_eq!(client_ip(Some(ip("127.0.0.1")), xff, None, &trusted), Some(ip("198.51.100.2")));
+        assert_eq!(client_ip(Some(ip("127.0.0.1")), None, None, &trusted), Some(ip("127.0.0.1")));
+        assert_eq!(client_ip(None, xff, None, &trusted), None);
+
+        // 只有 X-Real-IP 时同样只在可信对端采信；两者都有时以 X-Forwarded-For 为准
+        let real_ip = Some("203.0.113.20");
+        assert_eq!(client_ip(Some(ip("10.1.1.1")), None, real_ip, &trusted), Some(ip("203.0.113.20")));
+        assert_eq!(client_ip(Some(ip("198.51.100.1")), None, real_ip, &trusted), Some(ip("198.51.100.1")));
+        assert_eq!(client_ip(Some(ip("127.0.0.1")), xff, real_ip, &trusted), Some(ip("198.51.100.2")));
+        assert_eq!(client_ip(Some(ip("127.0.0.1")), None, Some("garbage"), &trusted), Some(ip("127.0.0.1")));
+    }
+}
@@ -0,0 +1,299 @@
+// 客户端 API Key 限流 (RPM / RPD)
+// 与 rate_limit.rs（上游账号限流跟踪）不同，这里限制的是下游调用方；
+// 启用集群时各实例定期互相推送计数增量（见 cluster.rs），限额按所有实例的合计计算
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+const MINUTE_SECS: i64 = 60;
+const DAY_SECS: i64 = 86_400;
+
+/// 全局 Key 限流器（auth 中间件使用）
+pub static KEY_RATE_LIMITER: Lazy<KeyRateLimiter> = Lazy::new(KeyRateLimiter::new);
+
+/// 单个 Key 的固定窗口计数
+#[derive(Debug, Clone, Default)]
+struct KeyWindow {
+    minute_slot: i64,
+    minute_count: u32,
+    day_slot: i64,
+    day_count: u32,
+}
+
+impl KeyWindow {
+    /// 进入新窗口时清零对应计数
+    fn roll(&mut self, minute_slot: i64, day_slot: i64) {
+        if self.minute_slot != minute_slot {
+            self.minute_slot = minute_slot;
+            self.minute_count = 0;
+        }
+        if self.day_slot != day_slot {
+            self.day_slot = day_slot;
+            self.day_count = 0;
+        }
+    }
+}
+
+/// 推送给其他实例的计数增量（按产生时所在的窗口）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyCounts {
+    pub key_id: String,
+    pub minute_slot: i64,
+    pub minute_count: u32,
+    pub day_slot: i64,
+    pub day_count: u32,
+}
+
+/// 超限信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyRateLimitExceeded {
+    /// 距离窗口重置的秒数（用于 Retry-After）
+    pub retry_after_secs: u64,
+    /// 触发的限制类型: "rpm" | "rpd"
+    pub limit: &'static str,
+}
+
+/// 单个窗口的当前用量
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct WindowUsage {
+    pub limit: u32,
+    pub used: u32,
+    pub remaining: u32,
+    /// 窗口重置时间（Unix 秒）
+    pub resets_at: i64,
+}
+
+/// Key 的限流状态（未配置的限制为 None）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct KeyRateLimitStatus {
+    pub rpm: Option<WindowUsage>,
+    pub rpd: Option<WindowUsage>,
+}
+
+/// Key 限流器：按分钟 / 按 UTC 自然日的固定窗口计数
+pub struct KeyRateLimiter {
+    windows: DashMap<String, KeyWindow>,
+    /// 本实例自上次推送以来新增的计数
+    unsynced: DashMap<String, KeyWindow>,
+}
+
+impl Default for KeyRateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyRateLimiter {
+    pub fn new() -> Self {
+        Self {
+            windows: DashMap::new(),
+            unsynced: DashMap::new(),
+        }
+    }
+
+    /// 检查并记录一次请求
+    ///
+    /// 未超限时计数 +1 并返回 Ok；超限时不计数，返回需要等待的秒数
+    pub fn check(
+        &self,
+        key_id: &str,
+        rpm: Option<u32>,
+        rpd: Option<u32>,
+    ) -> Result<(), KeyRateLimitExceeded> {
+        self.check_at(key_id, rpm, rpd, chrono::Utc::now().timestamp())
+    }
+
+    fn check_at(
+        &self,
+        key_id: &str,
+        rpm: Option<u32>,
+        rpd: Option<u32>,
+        now: i64,
+    ) -> Result<(), KeyRateLimitExceeded> {
+        if rpm.is_none() && rpd.is_none() {
+            return Ok(());
+        }
+
+        let minute_slot = now / MINUTE_SECS;
+        let day_slot = now / DAY_SECS;
+
+        let mut window = self.windows.entry(key_id.to_string()).or_default();
+        window.roll(minute_slot, day_slot);
+
+        if let Some(limit) = rpd {
+            if window.day_count >= limit {
+                return Err(KeyRateLimitExceeded {
+                    retry_after_secs: ((day_slot + 1) * DAY_SECS - now).max(1) as u64,
+                    limit: "rpd",
+                });
+            }
+        }
+        if let Some(limit) = rpm {
+            if window.minute_count >= limit {
+                return Err(KeyRateLimitExceeded {
+                    retry_after_secs: ((minute_slot + 1) * MINUTE_SECS - now).max(1) as u64,
+                    limit: "rpm",
+                });
+            }
+        }
+
+        window.minute_count += 1;
+        window.day_count += 1;
+        drop(window);
+
+        let mut unsynced = self.unsynced.entry(key_id.to_string()).or_default();
+        unsynced.roll(minute_slot, day_slot);
+        unsynced.minute_count += 1;
+        unsynced.day_count += 1;
+        Ok(())
+    }
+
+    /// 取出自上次调用以来本实例新增的计数（由集群同步任务推送给其他实例）
+    pub fn take_unsynced(&self) -> Vec<KeyCounts> {
+        let key_ids: Vec<String> = self.unsynced.iter().map(|entry| entry.key().clone()).collect();
+        key_ids
+            .into_iter()
+            .filter_map(|key_id| self.unsynced.remove(&key_id))
+            .map(|(key_id, window)| KeyCounts {
+                key_id,
+                minute_slot: window.minute_slot,
+                minute_count: window.minute_count,
+                day_slot: window.day_slot,
+                day_count: window.day_count,
+            })
+            .collect()
+    }
+
+    /// 计入其他实例推送的计数；已过期窗口的增量被忽略
+    pub fn apply_peer(&self, counts: &KeyCounts) {
+        self.apply_peer_at(counts, chrono::Utc::now().timestamp())
+    }
+
+    fn apply_peer_at(&self, counts: &KeyCounts, now: i64) {
+        let (minute_slot, day_slot) = (now / MINUTE_SECS, now / DAY_SECS);
+        let mut window = self.windows.entry(counts.key_id.clone()).or_default();
+        window.roll(minute_slot, day_slot);
+        if counts.minute_slot == minute_slot {
+            window.minute_count = window.minute_count.saturating_add(counts.minute_count);
+        }
+        if counts.day_slot == day_slot {
+            window.day_count = window.day_count.saturating_add(counts.day_count);
+        }
+    }
+
+    /// 查询当前窗口用量（只读，不计数）
+    pub fn status(&self, key_id: &str, rpm: Option<u32>, rpd: Option<u32>) -> KeyRateLimitStatus {
+        self.status_at(key_id, rpm, rpd, chrono::Utc::now().timestamp())
+    }
+
+    fn status_at(&self, key_id: &str, rpm: Option<u32>, rpd: Option<u32>, now: i64) -> KeyRateLimitStatus {
+        let minute_slot = now / MINUTE_SECS;
+        let day_slot = now / DAY_SECS;
+        let window = self.windows.get(key_id).map(|w| w.clone()).unwrap_or_default();
+        // 窗口已过期的计数视为 0
+        let minute_used = if window.minute_slot == minute_slot { window.minute_count } else { 0 };
+        let day_used = if window.day_slot == day_slot { window.day_count } else { 0 };
+
+        let usage = |limit: u32, used: u32, resets_at: i64| WindowUsage {
+            limit,
+            used,
+            remaining: limit.saturating_sub(used),
+            resets_at,
+        };
+        KeyRateLimitStatus {
+            rpm: rpm.map(|limit| usage(limit, minute_used, (minute_slot + 1) * MINUTE_SECS)),
+            rpd: rpd.map(|limit| usage(limit, day_used, (day_slot + 1) * DAY_SECS)),
+        }
+    }
+
+    /// 清除某个 Key 的计数（例如重置用量或修改限额后）
+    pub fn reset(&self, key_id: &str) {
+        self.windows.remove(key_id);
+        self.unsynced.remove(key_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlimited_key_always_passes() {
+        let limiter = KeyRateLimiter::new();
+        for _ in 0..100 {
+            assert!(limiter.check_at("k", None, None, 1_000).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_rpm_limit_and_reset() {
+        let limiter = KeyRateLimiter::new();
+        // 窗口从 120 开始，在 180 重置
+        let now = 150;
+        assert!(limiter.check_at("k", Some(2), None, now).is_ok());
+        assert!(limiter.check_at("k", Some(2), None, now).is_ok());
+
+        let err = limiter.check_at("k", Some(2), None, now).unwrap_err();
+        assert_eq!(err.limit, "rpm");
+        assert_eq!(err.retry_after_secs, 30);
+
+        // 下一分钟恢复
+        assert!(limiter.check_at("k", Some(2), None, 180).is_ok());
+    }
+
+    #[test]
+    fn test_rpd_limit_takes_precedence() {
+        let limiter = KeyRateLimiter::new();
+        assert!(limiter.check_at("k", Some(10), Some(1), 0).is_ok());
+
+        let err = limiter.check_at("k", Some(10), Some(1), 3_600).unwrap_err();
+        assert_eq!(err.limit, "rpd");
+        assert_eq!(err.retry_after_secs, (DAY_SECS - 3_600) as u64);
+
+        // 其他 key 不受影响
+        assert!(limiter.check_at("other", Some(10), Some(1), 3_600).is_ok());
+    }
+
+    #[test]
+    fn test_status_reports_current_window() {
+        let limiter = KeyRateLimiter::new();
+        assert!(limiter.check_at("k", Some(5), Some(100), 150).is_ok());
+        assert!(limiter.check_at("k", Some(5), Some(100), 150).is_ok());
+
+        let status = limiter.status_at("k", Some(5), None, 170);
+        assert_eq!(status.rpm, Some(WindowUsage { limit: 5, used: 2, remaining: 3, resets_at: 180 }));
+        assert!(status.rpd.is_none());
+
+        // 新的一分钟：分钟计数归零，日计数保留
+        let status = limiter.status_at("k", Some(5), Some(100), 200);
+        assert_eq!(status.rpm.unwrap().used, 0);
+        assert_eq!(status.rpd.unwrap().used, 2);
+        // 只读查询不计数
+        assert_eq!(limiter.status_at("k", None, Some(100), 200).rpd.unwrap().used, 2);
+    }
+
+    #[test]
+    fn test_peer_counts_share_limits() {
+        let (local, peer) = (KeyRateLimiter::new(), KeyRateLimiter::new());
+        assert!(local.check_at("k", Some(3), Some(10), 150).is_ok());
+        assert!(local.check_at("k", Some(3), Some(10), 150).is_ok());
+        let counts = local.take_unsynced();
+        assert_eq!(counts.len(), 1);
+        assert_eq!((counts[0].minute_count, counts[0].day_count), (2, 2));
+        // 取出后清空，不会重复推送
+        assert!(local.take_unsynced().is_empty());
+
+        // 同一分钟内收到推送：对端只剩 1 次额度
+        peer.apply_peer_at(&counts[0], 170);
+        assert!(peer.check_at("k", Some(3), Some(10), 170).is_ok());
+        assert_eq!(peer.check_at("k", Some(3), Some(10), 170).unwrap_err().limit, "rpm");
+        // 收到推送的计数不会再被推送出去
+        assert_eq!(peer.take_unsynced()[0].day_count, 1);
+
+        // 下一分钟才到达的推送只计入日计数
+        let late = KeyRateLimiter::new();
+        late.apply_peer_at(&counts[0], 190);
+        let status = late.status_at("k", Some(3), Some(10), 190);
+        assert_eq!((status.rpm.unwrap().used, status.rpd.unwrap().used), (0, 2));
+    }
+}
@@ -0,0 +1,174 @@
+// 监听器
+// 主端口之外可额外监听 TCP 地址或 Unix socket（`unix:/path`），所有监听器共享同一套路由与优雅关闭
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
+
+/// 监听地址
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl ListenAddr {
+    /// `unix:/run/anti-proxy.sock` 为 Unix socket，其余按 `host:port` 解析
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        if let Some(path) = value.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err("Unix socket listener needs a path, e.g. unix:/run/anti-proxy.sock".to_string());
+            }
+            return Ok(Self::Unix(PathBuf::from(path)));
+        }
+        if value.rsplit_once(':').is_none_or(|(host, port)| host.is_empty() || port.parse::<u16>().is_err()) {
+            return Err(format!("Invalid listener address '{}', expected host:port or unix:/path", value));
+        }
+        Ok(Self::Tcp(value.to_string()))
+    }
+}
+
+/// Unix socket 权限，八进制字符串（如 `660`、`0o660`）
+pub fn parse_socket_mode(value: &str) -> Result<u32, String> {
+    let digits = value.trim().trim_start_matches("0o");
+    u32::from_str_radix(digits, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+        .ok_or_else(|| format!("Invalid unix_socket_mode '{}', expected an octal mode such as 660", value))
+}
+
+/// 已接受的连接
+pub enum Accepted {
+    Tcp(TcpStream, SocketAddr, Option<TlsAcceptor>),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+}
+
+pub enum Listener {
+    Tcp { listener: TcpListener, tls: Option<TlsAcceptor> },
+    #[cfg(unix)]
+    Unix { listener: tokio::net::UnixListener, path: PathBuf },
+}
+
+impl Listener {
+    /// 绑定监听地址；Unix socket 会先清理残留的 socket 文件，并按 `socket_mode` 设置权限
+    pub async fn bind(addr: &ListenAddr, tls: Option<TlsAcceptor>, socket_mode: Option<u32>) -> Result<Self, String> {
+        match addr {
+            ListenAddr::Tcp(address) => {
+                let listener = TcpListener::bind(address)
+                    .await
+                    .map_err(|e| format!("地址 {} 绑定失败: {}", address, e))?;
+                Ok(Self::Tcp { listener, tls })
+            }
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => {
+                use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+                if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+                    let _ = std::fs::remove_file(path);
+                }
+                let listener = tokio::net::UnixListener::bind(path)
+                    .map_err(|e| format!("Unix socket {:?} 绑定失败: {}", path, e))?;
+                if let Some(mode) = socket_mode {
+                    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+                        .map_err(|e| format!("设置 {:?} 权限失败: {}", path, e))?;
+                }
+                Ok(Self::Unix { listener, path: path.clone() })
+            }
+            #[cfg(not(unix))]
+            ListenAddr::Unix(path) => {
+                let _ = socket_mode;
+                Err(format!("Unix socket {:?} is not supported on this platform", path))
+            }
+        }
+    }
+
+    pub async fn accept(&self) -> std::io::Result<Accepted> {
+        match self {
+            Self::Tcp { listener, tls } => {
+                let (stream, peer) = listener.accept().await?;
+                Ok(Accepted::Tcp(stream, peer, tls.clone()))
+            }
+            #[cfg(unix)]
+            Self::Unix { listener, .. } => {
+                let (stream, _) = listener.accept().await?;
+                Ok(Accepted::Unix(stream))
+            }
+        }
+    }
+
+    /// 用于日志的地址描述
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Tcp { listener, tls } => {
+                let scheme = if tls.is_some() { "https" } else { "http" };
+                match listener.local_addr() {
+                    Ok(addr) => format!("{}://{}", scheme, addr),
+                    Err(_) => scheme.to_string(),
+                }
+            }
+            #[cfg(unix)]
+            Self::Unix { path, .. } => format!("unix:{}", path.display()),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        // 停止监听后删除 socket 文件
+        if let Self::Unix { path, .. } = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// 绑定主端口与 `listeners` 中的额外地址；TLS 只用于主端口
+pub async fn bind_all(
+    host: &str,
+    port: u16,
+    tls: Option<TlsAcceptor>,
+    extra: &[String],
+    socket_mode: Option<&str>,
+) -> Result<Vec<Listener>, String> {
+    let socket_mode = socket_mode.map(parse_socket_mode).transpose()?;
+    let mut listeners = vec![Listener::bind(&ListenAddr::Tcp(format!("{}:{}", host, port)), tls, None).await?];
+    for entry in extra {
+        listeners.push(Listener::bind(&ListenAddr::parse(entry)?, None, socket_mode).await?);
+    }
+    Ok(listeners)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listen_addr() {
+        assert_eq!(ListenAddr::parse("127.0.0.1:8080").unwrap(), ListenAddr::Tcp("127.0.0.1:8080".to_string()));
+        assert_eq!(ListenAddr::parse("[::1]:8080").unwrap(), ListenAddr::Tcp("[::1]:8080".to_string()));
+        assert_eq!(ListenAddr::parse("unix:/run/ap.sock").unwrap(), ListenAddr::Unix(PathBuf::from("/run/ap.sock")));
+        assert!(ListenAddr::parse("unix:").is_err());
+        assert!(ListenAddr::parse("localhost").is_err());
+        assert!(ListenAddr::parse("localhost:http").is_err());
+    }
+
+    #[test]
+    fn test_parse_socket_mode() {
+        assert_eq!(parse_socket_mode("660").unwrap(), 0o660);
+        assert_eq!(parse_socket_mode("0o600").unwrap(), 0o600);
+        assert!(parse_socket_mode("999").is_err());
+        assert!(parse_socket_mode("7777").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_listener_permissions_and_cleanup() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("anti-proxy-test-{}.sock", uuid::Uuid::new_v4()));
+        let listener = Listener::bind(&ListenAddr::Unix(path.clone()), None, Some(0o600)).await.unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        drop(listener);
+        assert!(!path.exists());
+    }
+}
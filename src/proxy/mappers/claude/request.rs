@@ -435,9 +435,9 @@ fn has_valid_signature_for_function_calls(
 
 /// 构建 System Instruction 
 /// [FIX] 参考 CLIProxyAPI 修复方案：使用固定的 Antigravity 身份文本
-fn build_system_instruction(_system: &Option<SystemPrompt>, _model_name: &str) -> Option<Value> {
-    // [CRITICAL FIX] CLIProxyAPI 使用固定的身份文本替换整个 systemInstruction
-    // 这是解决 429 错误的关键！
+/// 客户端传入的 system prompt 作为后续 parts 追加，保证身份文本始终位于首位
+fn build_system_instruction(system: &Option<SystemPrompt>, _model_name: &str) -> Option<Value> {
+    // 固定的身份文本作为第一个 part（解决 429 错误的关键），客户端的 system parts 追加在其后，不会被替换
     let antigravity_identity = r#"<identity>
 You are Antigravity, a powerful agentic AI coding assistant designed by the Google Deepmind team working on Advanced Agentic Coding.
 You are pair programming with a USER to solve their coding task. The task may require creating a new codebase, modifying or debugging an existing codebase, or simply answering a question.
@@ -457,9 +457,26 @@ Call tools as you normally would. The following list provides additional guidanc
 - **Ask for clarification**. If you are unsure about the USER's intent, always ask for clarification rather than making assumptions.
 </communication_style>"#;
 
+    let mut parts = vec![json!({"text": antigravity_identity})];
+
+    // 保留客户端 system prompt（Anthropic 格式: 字符串或 text block 数组）
+    match system {
+        Some(SystemPrompt::String(text)) if !text.trim().is_empty() => {
+            parts.push(json!({"text": text}));
+        }
+        Some(SystemPrompt::Array(blocks)) => {
+            for block in blocks {
+                if block.block_type == "text" && !block.text.trim().is_empty() {
+                    parts.push(json!({"text": block.text}));
+                }
+            }
+        }
+        _ => {}
+    }
+
     Some(json!({
         "role": "user",
-        "parts": [{"text": antigravity_identity}]
+        "parts": parts
     }))
 }
 
@@ -925,6 +942,36 @@ mod tests {
         assert!(body["requestId"].as_str().unwrap().starts_with("agent-"));
     }
 
+    #[test]
+    fn test_system_prompt_preserved_after_identity() {
+        let req = ClaudeRequest {
+            model: "claude-sonnet-4-5".to_string(),
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: MessageContent::String("Hello".to_string()),
+            }],
+            system: Some(SystemPrompt::Array(vec![SystemBlock {
+                block_type: "text".to_string(),
+                text: "You are a helpful reviewer.".to_string(),
+            }])),
+            tools: None,
+            stream: true,
+            max_tokens: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            thinking: None,
+            metadata: None,
+            output_config: None,
+        };
+
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        let parts = body["request"]["systemInstruction"]["parts"].as_array().unwrap();
+        assert_eq!(parts.len(), 2);
+        assert!(parts[0]["text"].as_str().unwrap().contains("<identity>"));
+        assert_eq!(parts[1]["text"], "You are a helpful reviewer.");
+    }
+
     #[test]
     fn test_clean_json_schema() {
         let mut schema = json!({
@@ -87,16 +87,28 @@ pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value
          }
     }
 
-    let final_request = json!({
+    envelope(project_id, inner_request, &config.final_model, &config.request_type)
+}
+
+/// 透传模式：请求体原样放入 v1internal 信封，不做清洗与注入（safetySettings、tools 等字段保持不变）
+pub fn wrap_request_passthrough(body: &Value, project_id: &str, mapped_model: &str) -> Value {
+    let original_model = body.get("model").and_then(|v| v.as_str()).unwrap_or(mapped_model);
+    let final_model_name = if !mapped_model.is_empty() { mapped_model } else { original_model };
+    let tools_val: Option<Vec<Value>> = body.get("tools").and_then(|t| t.as_array()).cloned();
+    let config = crate::proxy::mappers::common_utils::resolve_request_config(original_model, final_model_name, &tools_val);
+
+    envelope(project_id, body.clone(), &config.final_model, &config.request_type)
+}
+
+fn envelope(project_id: &str, inner_request: Value, model: &str, request_type: &str) -> Value {
+    json!({
         "project": project_id,
         "requestId": format!("agent-{}", uuid::Uuid::new_v4()), // 修正为 agent- 前缀
         "request": inner_request,
-        "model": config.final_model,
+        "model": model,
         "userAgent": "antigravity",
-        "requestType": config.request_type
-    });
-
-    final_request
+        "requestType": request_type
+    })
 }
 
 /// 解包响应（提取 response 字段）
@@ -121,6 +133,24 @@ mod tests {
         assert!(result["requestId"].as_str().unwrap().starts_with("agent-"));
     }
 
+    #[test]
+    fn test_wrap_request_passthrough_keeps_body() {
+        let body = json!({
+            "contents": [{"role": "user", "parts": [{"text": "[undefined]"}]}],
+            "safetySettings": [{"category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_NONE"}],
+            "tools": [{"functionDeclarations": [{"name": "web_search", "parameters": {"type": "object", "multipleOf": 2}}]}]
+        });
+
+        let result = wrap_request_passthrough(&body, "test-project", "gemini-2.5-flash");
+        assert_eq!(result["request"], body);
+        assert_eq!(result["project"], "test-project");
+        assert_eq!(result["model"], "gemini-2.5-flash");
+
+        // 翻译模式会清洗同一请求
+        let translated = wrap_request(&body, "test-project", "gemini-2.5-flash");
+        assert_ne!(translated["request"], body);
+    }
+
     #[test]
     fn test_unwrap_response() {
         let wrapped = json!({
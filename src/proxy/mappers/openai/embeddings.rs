@@ -0,0 +1,176 @@
+// OpenAI Embeddings ↔ Gemini batchEmbedContents 转换
+use base64::Engine as _;
+use serde_json::{json, Value};
+
+/// 客户端未指定 Gemini 嵌入模型时使用的默认模型
+pub const DEFAULT_EMBEDDING_MODEL: &str = "gemini-embedding-001";
+
+/// 解析 `input` 字段：支持单个字符串或字符串数组
+///
+/// OpenAI 还允许传入 token id 数组，上游无法识别，直接拒绝
+pub fn parse_embedding_inputs(input: &Value) -> Result<Vec<String>, String> {
+    match input {
+        Value::String(s) => Ok(vec![s.clone()]),
+        Value::Array(items) if !items.is_empty() => items
+            .iter()
+            .map(|item| {
+                item.as_str()
+                    .map(|s| s.to_string())
+                    .ok_or_else(|| "'input' must be a string or an array of strings".to_string())
+            })
+            .collect(),
+        Value::Array(_) => Err("'input' must not be empty".to_string()),
+        _ => Err("'input' must be a string or an array of strings".to_string()),
+    }
+}
+
+/// 映射客户端请求的模型名
+///
+/// 自定义映射优先；Gemini 嵌入模型原样透传；其他（如 text-embedding-3-small）回落到默认模型
+pub fn resolve_embedding_model(
+    model: &str,
+    custom_mapping: &std::collections::HashMap<String, String>,
+) -> String {
+    if let Some(target) = custom_mapping.get(model) {
+        return target.clone();
+    }
+    let lower = model.to_ascii_lowercase();
+    if lower.starts_with("gemini-embedding") || lower == "text-embedding-004" || lower.starts_with("embedding-") {
+        return model.to_string();
+    }
+    DEFAULT_EMBEDDING_MODEL.to_string()
+}
+
+/// 构建 v1internal batchEmbedContents 请求体
+pub fn build_embedding_request(
+    inputs: &[String],
+    project_id: &str,
+    mapped_model: &str,
+    dimensions: Option<u64>,
+) -> Value {
+    let requests: Vec<Value> = inputs
+        .iter()
+        .map(|text| {
+            let mut req = json!({
+                "model": format!("models/{}", mapped_model),
+                "content": { "parts": [{ "text": text }] }
+            });
+            if let Some(dim) = dimensions {
+                req["outputDimensionality"] = json!(dim);
+            }
+            req
+        })
+        .collect();
+
+    json!({
+        "project": project_id,
+        "requestId": format!("embed-{}", uuid::Uuid::new_v4()),
+        "model": mapped_model,
+        "userAgent": "antigravity",
+        "requestType": "embedding",
+        "request": { "requests": requests }
+    })
+}
+
+/// 将上游响应转换为 OpenAI embeddings 响应
+///
+/// 上游不返回 token 用量，prompt_tokens 按文本长度估算
+pub fn transform_embedding_response(
+    gemini_resp: &Value,
+    model: &str,
+    inputs: &[String],
+    base64_format: bool,
+) -> Result<Value, String> {
+    let inner = gemini_resp.get("response").unwrap_or(gemini_resp);
+    let embeddings = inner
+        .get("embeddings")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| "Upstream response missing 'embeddings'".to_string())?;
+
+    let data: Vec<Value> = embeddings
+        .iter()
+        .enumerate()
+        .map(|(index, emb)| {
+            let values: Vec<f32> = emb
+                .get("values")
+                .and_then(|v| v.as_array())
+                .map(|arr| arr.iter().filter_map(|x| x.as_f64()).map(|x| x as f32).collect())
+                .unwrap_or_default();
+            let embedding = if base64_format {
+                let bytes: Vec<u8> = values.iter().flat_map(|x| x.to_le_bytes()).collect();
+                json!(base64::engine::general_purpose::STANDARD.encode(bytes))
+            } else {
+                json!(values)
+            };
+            json!({
+                "object": "embedding",
+                "index": index,
+                "embedding": embedding
+            })
+        })
+        .collect();
+
+    let prompt_tokens: u32 = inputs.iter().map(|s| estimate_tokens(s)).sum();
+
+    Ok(json!({
+        "object": "list",
+        "data": data,
+        "model": model,
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "total_tokens": prompt_tokens
+        }
+    }))
+}
+
+/// 粗略估算 token 数：ASCII 约 4 字符 1 token，其他字符各 1 token
+fn estimate_tokens(text: &str) -> u32 {
+    let ascii = text.chars().filter(|c| c.is_ascii()).count() as u32;
+    let non_ascii = text.chars().count() as u32 - ascii;
+    ascii.div_ceil(4) + non_ascii
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_embedding_inputs() {
+        assert_eq!(parse_embedding_inputs(&json!("hi")).unwrap(), vec!["hi"]);
+        assert_eq!(parse_embedding_inputs(&json!(["a", "b"])).unwrap(), vec!["a", "b"]);
+        assert!(parse_embedding_inputs(&json!([1, 2, 3])).is_err());
+        assert!(parse_embedding_inputs(&json!([])).is_err());
+    }
+
+    #[test]
+    fn test_resolve_embedding_model() {
+        let mut mapping = std::collections::HashMap::new();
+        assert_eq!(resolve_embedding_model("text-embedding-3-small", &mapping), DEFAULT_EMBEDDING_MODEL);
+        assert_eq!(resolve_embedding_model("text-embedding-004", &mapping), "text-embedding-004");
+        mapping.insert("text-embedding-3-small".to_string(), "text-embedding-004".to_string());
+        assert_eq!(resolve_embedding_model("text-embedding-3-small", &mapping), "text-embedding-004");
+    }
+
+    #[test]
+    fn test_transform_embedding_response() {
+        let upstream = json!({
+            "response": {
+                "embeddings": [
+                    { "values": [0.5, -1.0] },
+                    { "values": [0.25, 0.0] }
+                ]
+            }
+        });
+        let inputs = vec!["abcd".to_string(), "efgh".to_string()];
+        let resp = transform_embedding_response(&upstream, "text-embedding-3-small", &inputs, false).unwrap();
+        assert_eq!(resp["data"].as_array().unwrap().len(), 2);
+        assert_eq!(resp["data"][1]["index"], 1);
+        assert_eq!(resp["data"][0]["embedding"], json!([0.5, -1.0]));
+        assert_eq!(resp["usage"]["prompt_tokens"], 2);
+
+        let resp = transform_embedding_response(&upstream, "m", &inputs, true).unwrap();
+        let encoded = resp["data"][0]["embedding"].as_str().unwrap();
+        let bytes = base64::engine::general_purpose::STANDARD.decode(encoded).unwrap();
+        assert_eq!(f32::from_le_bytes(bytes[0..4].try_into().unwrap()), 0.5);
+    }
+}
@@ -0,0 +1,57 @@
+// 结构化输出修复
+// 请求指定 `response_format` 为 JSON 时，上游偶尔会用 Markdown 代码块包裹结果或在前后附带说明文字；
+// 开启 `openai_json_repair` 后，非流式响应的内容会被还原为其中的 JSON
+use serde_json::Value;
+
+fn parses(text: &str) -> bool {
+    serde_json::from_str::<Value>(text).is_ok()
+}
+
+/// 去掉 Markdown 代码块包裹（```json ... ```）
+fn strip_code_fence(text: &str) -> Option<&str> {
+    let rest = text.strip_prefix("```")?;
+    let body = rest.split_once('\n').map(|(_, body)| body)?;
+    Some(body.trim_end().strip_suffix("```").unwrap_or(body).trim())
+}
+
+/// 截取第一个 `{` / `[` 到最后一个对应的 `}` / `]` 之间的内容
+fn extract_json_block(text: &str) -> Option<&str> {
+    let start = text.find(['{', '['])?;
+    let close = if text[start..].starts_with('{') { '}' } else { ']' };
+    let end = text.rfind(close)?;
+    (end > start).then(|| &text[start..=end])
+}
+
+/// 修复模型返回的 JSON：已是合法 JSON 或无法修复时返回 None，否则返回修复后的内容
+pub fn repair_json(content: &str) -> Option<String> {
+    let trimmed = content.trim();
+    if parses(trimmed) {
+        return (trimmed.len() != content.len()).then(|| trimmed.to_string());
+    }
+    let unfenced = strip_code_fence(trimmed).unwrap_or(trimmed);
+    if parses(unfenced) {
+        return Some(unfenced.to_string());
+    }
+    extract_json_block(unfenced)
+        .filter(|block| parses(block))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repair_json() {
+        assert_eq!(repair_json(r#"{"a":1}"#), None);
+        assert_eq!(repair_json("  {\"a\":1}\n").as_deref(), Some(r#"{"a":1}"#));
+        assert_eq!(repair_json("```json\n{\"a\": [1, 2]}\n```").as_deref(), Some(r#"{"a": [1, 2]}"#));
+        assert_eq!(
+            repair_json("Here is the result:\n{\"ok\": true}\nLet me know!").as_deref(),
+            Some(r#"{"ok": true}"#)
+        );
+        assert_eq!(repair_json("```\n[1, 2]\n```").as_deref(), Some("[1, 2]"));
+        assert_eq!(repair_json("not json at all"), None);
+        assert_eq!(repair_json("{\"a\": "), None);
+    }
+}
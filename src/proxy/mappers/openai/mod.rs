@@ -1,10 +1,14 @@
 // OpenAI mapper 模块
 // 负责 OpenAI ↔ Gemini 协议转换
 
+pub mod embeddings;
+pub mod json_output;
 pub mod models;
 pub mod request;
 pub mod response;
 pub mod streaming;
+pub mod strict;
+pub mod thinking;
 
 pub use models::*;
 pub use request::*;
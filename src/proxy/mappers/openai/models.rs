@@ -14,10 +14,30 @@ pub struct OpenAIRequest {
     pub stream: bool,
     #[serde(rename = "max_tokens")]
     pub max_tokens: Option<u32>,
+    /// 新版 SDK 使用的输出上限；与 max_tokens 同时出现时优先
+    #[serde(default)]
+    pub max_completion_tokens: Option<u32>,
     pub temperature: Option<f32>,
     #[serde(rename = "top_p")]
     pub top_p: Option<f32>,
+    #[serde(default)]
+    pub top_k: Option<u32>,
+    #[serde(default)]
+    pub seed: Option<i64>,
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default)]
+    pub presence_penalty: Option<f32>,
     pub stop: Option<Value>,
+    /// 以下参数上游无对应实现，仅用于在响应头中提示
+    #[serde(default)]
+    pub n: Option<u32>,
+    #[serde(default)]
+    pub logit_bias: Option<Value>,
+    #[serde(default)]
+    pub logprobs: Option<bool>,
+    #[serde(default)]
+    pub top_logprobs: Option<u32>,
     pub response_format: Option<ResponseFormat>,
     #[serde(default)]
     pub tools: Option<Vec<Value>>,
@@ -33,6 +53,28 @@ pub struct OpenAIRequest {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResponseFormat {
     pub r#type: String,
+    /// `type` 为 `json_schema` 时的输出约束
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<JsonSchemaFormat>,
+}
+
+impl ResponseFormat {
+    /// 是否要求输出 JSON（`json_object` 或 `json_schema`）
+    pub fn wants_json(&self) -> bool {
+        matches!(self.r#type.as_str(), "json_object" | "json_schema")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonSchemaFormat {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -45,23 +87,96 @@ pub enum OpenAIContent {
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(tag = "type")]
 pub enum OpenAIContentBlock {
-    #[serde(rename = "text")]
+    #[serde(rename = "text", alias = "input_text")]
     Text {
         text: String,
     },
-    #[serde(rename = "image_url")]
+    #[serde(rename = "image_url", alias = "input_image")]
     ImageUrl {
         image_url: OpenAIImageUrl,
     },
+    /// base64 图片块: {"type": "image", "source": {"type": "base64", "media_type": "...", "data": "..."}}
+    #[serde(rename = "image")]
+    Image {
+        source: OpenAIImageSource,
+    },
+    /// 文件块: {"type": "file", "file": {"file_id": "file-..."}}，或直接携带 `file_data` data URL
+    #[serde(rename = "file")]
+    File {
+        file: OpenAIFileRef,
+    },
+    /// 未支持的内容块类型 (如 input_audio)，忽略而不是拒绝整个请求
+    #[serde(other)]
+    Unsupported,
+}
+
+/// 文件块引用：`file_id` 为 /v1/files 上传的文件，请求预处理时被替换为 `file_data`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OpenAIFileRef {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_data: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(from = "OpenAIImageUrlInput")]
 pub struct OpenAIImageUrl {
     pub url: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub detail: Option<String>,
 }
 
+/// image_url 既可以是对象，也可以直接是 URL 字符串 (Responses API 的 input_image)
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OpenAIImageUrlInput {
+    Url(String),
+    Object {
+        url: String,
+        #[serde(default)]
+        detail: Option<String>,
+    },
+}
+
+impl From<OpenAIImageUrlInput> for OpenAIImageUrl {
+    fn from(input: OpenAIImageUrlInput) -> Self {
+        match input {
+            OpenAIImageUrlInput::Url(url) => Self { url, detail: None },
+            OpenAIImageUrlInput::Object { url, detail } => Self { url, detail },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OpenAIImageSource {
+    #[serde(rename = "type", default)]
+    pub source_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl OpenAIImageSource {
+    /// 转换为 image_url 形式的 URL（base64 数据转为 data URL）
+    pub fn to_url(&self) -> Option<String> {
+        match (&self.data, &self.url) {
+            (Some(data), _) => Some(format!(
+                "data:{};base64,{}",
+                self.media_type.as_deref().unwrap_or("application/octet-stream"),
+                data
+            )),
+            (None, Some(url)) => Some(url.clone()),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenAIMessage {
     pub role: String,
@@ -73,6 +188,9 @@ pub struct OpenAIMessage {
     pub tool_call_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// 思考内容（`openai_thinking: reasoning_content` 时输出；请求中带回的值会被忽略）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
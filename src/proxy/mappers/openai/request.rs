@@ -3,6 +3,9 @@ use super::models::*;
 use serde_json::{json, Value};
 use super::streaming::get_thought_signature;
 
+/// 上游 stopSequences 的数量上限
+const MAX_STOP_SEQUENCES: usize = 5;
+
 pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mapped_model: &str) -> Value {
     // 将 OpenAI 工具转为 Value 数组以便探测
     let tools_val = request.tools.as_ref().map(|list| {
@@ -68,8 +71,11 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
 
             let mut parts = Vec::new();
             
+            // 工具结果的内容只放入 functionResponse，不再重复生成 text part
+            let is_tool_result = msg.role == "tool" || msg.role == "function";
+
             // Handle content (multimodal or text)
-            if let Some(content) = &msg.content {
+            if let Some(content) = msg.content.as_ref().filter(|_| !is_tool_result) {
                 match content {
                     OpenAIContent::String(s) => {
                         if !s.is_empty() {
@@ -83,58 +89,16 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
                                     parts.push(json!({"text": text}));
                                 }
                                 OpenAIContentBlock::ImageUrl { image_url } => {
-                                    if image_url.url.starts_with("data:") {
-                                        if let Some(pos) = image_url.url.find(",") {
-                                            let mime_part = &image_url.url[5..pos];
-                                            let mime_type = mime_part.split(';').next().unwrap_or("image/jpeg");
-                                            let data = &image_url.url[pos + 1..];
-                                            
-                                            parts.push(json!({
-                                                "inlineData": { "mimeType": mime_type, "data": data }
-                                            }));
-                                        }
-                                    } else if image_url.url.starts_with("http") {
-                                        parts.push(json!({
-                                            "fileData": { "fileUri": &image_url.url, "mimeType": "image/jpeg" }
-                                        }));
-                                    } else {
-                                        // [NEW] 处理本地文件路径 (file:// 或 Windows/Unix 路径)
-                                        let file_path = if image_url.url.starts_with("file://") {
-                                            // 移除 file:// 前缀
-                                            #[cfg(target_os = "windows")]
-                                            { image_url.url.trim_start_matches("file:///").replace('/', "\\") }
-                                            #[cfg(not(target_os = "windows"))]
-                                            { image_url.url.trim_start_matches("file://").to_string() }
-                                        } else {
-                                            image_url.url.clone()
-                                        };
-                                        
-                                        tracing::debug!("[OpenAI-Request] Reading local image: {}", file_path);
-                                        
-                                        // 读取文件并转换为 base64
-                                        if let Ok(file_bytes) = std::fs::read(&file_path) {
-                                            use base64::Engine as _;
-                                            let b64 = base64::engine::general_purpose::STANDARD.encode(&file_bytes);
-                                            
-                                            // 根据文件扩展名推断 MIME 类型
-                                            let mime_type = if file_path.to_lowercase().ends_with(".png") {
-                                                "image/png"
-                                            } else if file_path.to_lowercase().ends_with(".gif") {
-                                                "image/gif"
-                                            } else if file_path.to_lowercase().ends_with(".webp") {
-                                                "image/webp"
-                                            } else {
-                                                "image/jpeg"
-                                            };
-                                            
-                                            parts.push(json!({
-                                                "inlineData": { "mimeType": mime_type, "data": b64 }
-                                            }));
-                                            tracing::debug!("[OpenAI-Request] Successfully loaded image: {} ({} bytes)", file_path, file_bytes.len());
-                                        } else {
-                                            tracing::debug!("[OpenAI-Request] Failed to read local image: {}", file_path);
-                                        }
-                                    }
+                                    parts.extend(image_url_to_part(&image_url.url));
+                                }
+                                OpenAIContentBlock::Image { source } => {
+                                    parts.extend(source.to_url().and_then(|url| image_url_to_part(&url)));
+                                }
+                                OpenAIContentBlock::File { file } => {
+                                    parts.extend(file_to_part(file));
+                                }
+                                OpenAIContentBlock::Unsupported => {
+                                    tracing::debug!("[OpenAI-Request] Skipping unsupported content block");
                                 }
                             }
                         }
@@ -171,7 +135,7 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
             }
 
             // Handle tool response
-            if msg.role == "tool" || msg.role == "function" {
+            if is_tool_result {
                 let name = msg.name.as_deref().unwrap_or("unknown");
                 let final_name = if name == "local_shell_call" { "shell" } 
                                 else if let Some(id) = &msg.tool_call_id { tool_id_to_name.get(id).map(|s| s.as_str()).unwrap_or(name) }
@@ -183,10 +147,16 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
                     None => "".to_string()
                 };
 
+                // 工具返回 JSON 对象时直接作为 response，其余内容包装为 { "result": ... }
+                let response_val = match serde_json::from_str::<Value>(&content_val) {
+                    Ok(Value::Object(obj)) => Value::Object(obj),
+                    _ => json!({ "result": content_val }),
+                };
+
                 parts.push(json!({
                     "functionResponse": {
                        "name": final_name,
-                       "response": { "result": content_val }
+                       "response": response_val
                     }
                 }));
             }
@@ -213,22 +183,46 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
 
     // 3. 构建请求体
     let mut gen_config = json!({
-        "maxOutputTokens": request.max_tokens.unwrap_or(64000),
+        "maxOutputTokens": request.max_completion_tokens.or(request.max_tokens).unwrap_or(64000),
         "temperature": request.temperature.unwrap_or(1.0),
         "topP": request.top_p.unwrap_or(1.0), 
     });
+    if let Some(top_k) = request.top_k {
+        gen_config["topK"] = json!(top_k);
+    }
+    if let Some(seed) = request.seed {
+        gen_config["seed"] = json!(seed);
+    }
+    // 多数 SDK 默认发送 0，只下发非零值
+    if let Some(penalty) = request.frequency_penalty.filter(|p| *p != 0.0) {
+        gen_config["frequencyPenalty"] = json!(penalty);
+    }
+    if let Some(penalty) = request.presence_penalty.filter(|p| *p != 0.0) {
+        gen_config["presencePenalty"] = json!(penalty);
+    }
 
-    if let Some(stop) = &request.stop {
-        if stop.is_string() { gen_config["stopSequences"] = json!([stop]); }
-        else if stop.is_array() { gen_config["stopSequences"] = stop.clone(); }
+    let mut stop_sequences = stop_sequences(request.stop.as_ref());
+    if !stop_sequences.is_empty() {
+        stop_sequences.truncate(MAX_STOP_SEQUENCES);
+        gen_config["stopSequences"] = json!(stop_sequences);
     }
 
-    if let Some(fmt) = &request.response_format {
-        if fmt.r#type == "json_object" {
-            gen_config["responseMimeType"] = json!("application/json");
+    // 结构化输出：json_object 只要求 JSON，json_schema 同时下发清洗后的 schema
+    if let Some(fmt) = request.response_format.as_ref().filter(|f| f.wants_json()) {
+        gen_config["responseMimeType"] = json!("application/json");
+        if let Some(mut schema) = fmt.json_schema.as_ref().and_then(|s| s.schema.clone()) {
+            crate::proxy::common::json_schema::clean_json_schema(&mut schema);
+            gen_config["responseSchema"] = schema;
         }
     }
 
+    // 需要输出思考内容时请上游返回 thought parts
+    if super::thinking::mode() != crate::proxy::config::ThinkingOutput::Strip
+        && super::thinking::supports_thoughts(mapped_model)
+    {
+        gen_config["thinkingConfig"] = json!({ "includeThoughts": true });
+    }
+
     let mut inner_request = json!({
         "contents": contents,
         "generationConfig": gen_config,
@@ -302,6 +296,9 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
         
         if !function_declarations.is_empty() {
             inner_request["tools"] = json!([{ "functionDeclarations": function_declarations }]);
+            if let Some(tool_config) = request.tool_choice.as_ref().and_then(map_tool_choice) {
+                inner_request["toolConfig"] = tool_config;
+            }
         }
     }
     
@@ -343,6 +340,7 @@ Call tools as you normally would. The following list provides additional guidanc
              if let Some(gen_obj) = gen_config.as_object_mut() {
                  gen_obj.remove("thinkingConfig");
                  gen_obj.remove("responseMimeType"); 
+                 gen_obj.remove("responseSchema");
                  gen_obj.remove("responseModalities");
                  gen_obj.insert("imageConfig".to_string(), image_config);
              }
@@ -359,6 +357,117 @@ Call tools as you normally would. The following list provides additional guidanc
     })
 }
 
+/// 将文件块转换为 Gemini inlineData part；`file_data` 须为 base64 data URL
+fn file_to_part(file: &OpenAIFileRef) -> Option<Value> {
+    let Some(data_url) = file.file_data.as_deref() else {
+        tracing::warn!("[OpenAI-Request] Dropping unresolved file reference: {:?}", file.file_id);
+        return None;
+    };
+    let parsed = data_url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(','))
+        .and_then(|(meta, data)| meta.strip_suffix(";base64").map(|mime| (mime, data)));
+    match parsed {
+        Some((mime_type, data)) if !mime_type.is_empty() => Some(json!({
+            "inlineData": { "mimeType": mime_type, "data": data }
+        })),
+        _ => {
+            tracing::warn!("[OpenAI-Request] Dropping file with invalid file_data: {:?}", file.filename);
+            None
+        }
+    }
+}
+
+/// 将图片 URL 转换为 Gemini part：data URL → inlineData，HTTP URL → fileData，其余视为本地文件路径
+///
+/// HTTP 图片通常已由 handler 预先下载为 data URL（见 inline_remote_images），这里仅作兜底
+fn image_url_to_part(url: &str) -> Option<Value> {
+    use crate::proxy::common::image;
+
+    if url.starts_with("data:") {
+        return match image::parse_data_url(url) {
+            Ok((mime_type, data)) => Some(json!({
+                "inlineData": { "mimeType": mime_type, "data": data }
+            })),
+            Err(e) => {
+                tracing::warn!("[OpenAI-Request] Dropping invalid image: {}", e);
+                None
+            }
+        };
+    }
+
+    if url.starts_with("http://") || url.starts_with("https://") {
+        let path = url.split(['?', '#']).next().unwrap_or(url).to_ascii_lowercase();
+        let mime_type = if path.ends_with(".png") {
+            "image/png"
+        } else if path.ends_with(".gif") {
+            "image/gif"
+        } else if path.ends_with(".webp") {
+            "image/webp"
+        } else {
+            "image/jpeg"
+        };
+        return Some(json!({
+            "fileData": { "fileUri": url, "mimeType": mime_type }
+        }));
+    }
+
+    // [NEW] 处理本地文件路径 (file:// 或 Windows/Unix 路径)
+    let file_path = if url.starts_with("file://") {
+        #[cfg(target_os = "windows")]
+        { url.trim_start_matches("file:///").replace('/', "\\") }
+        #[cfg(not(target_os = "windows"))]
+        { url.trim_start_matches("file://").to_string() }
+    } else {
+        url.to_string()
+    };
+
+    tracing::debug!("[OpenAI-Request] Reading local image: {}", file_path);
+    let file_bytes = match std::fs::read(&file_path) {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            tracing::debug!("[OpenAI-Request] Failed to read local image: {}", file_path);
+            return None;
+        }
+    };
+    if file_bytes.len() > image::MAX_IMAGE_BYTES {
+        tracing::warn!("[OpenAI-Request] Local image too large: {} ({} bytes)", file_path, file_bytes.len());
+        return None;
+    }
+
+    use base64::Engine as _;
+    let b64 = base64::engine::general_purpose::STANDARD.encode(&file_bytes);
+    let mime_type = image::sniff_mime(&file_bytes).unwrap_or("image/jpeg");
+    tracing::debug!("[OpenAI-Request] Successfully loaded image: {} ({} bytes)", file_path, file_bytes.len());
+    Some(json!({
+        "inlineData": { "mimeType": mime_type, "data": b64 }
+    }))
+}
+
+/// 将 OpenAI tool_choice 映射为 Gemini toolConfig.functionCallingConfig
+fn map_tool_choice(choice: &Value) -> Option<Value> {
+    let config = match choice {
+        Value::String(mode) => match mode.as_str() {
+            "none" => json!({ "mode": "NONE" }),
+            "auto" => json!({ "mode": "AUTO" }),
+            "required" | "any" => json!({ "mode": "ANY" }),
+            _ => return None,
+        },
+        // { "type": "function", "function": { "name": "..." } }：强制调用指定函数
+        Value::Object(obj) => {
+            let name = obj
+                .get("function")
+                .and_then(|f| f.get("name"))
+                .or_else(|| obj.get("name"))
+                .and_then(|v| v.as_str())?;
+            let name = if name == "local_shell_call" { "shell" } else { name };
+            json!({ "mode": "ANY", "allowedFunctionNames": [name] })
+        }
+        _ => return None,
+    };
+    Some(json!({ "functionCallingConfig": config }))
+}
+
 fn enforce_uppercase_types(value: &mut Value) {
     if let Value::Object(map) = value {
         if let Some(type_val) = map.get_mut("type") {
@@ -383,6 +492,38 @@ fn enforce_uppercase_types(value: &mut Value) {
     }
 }
 
+/// `stop` 可以是字符串或字符串数组；忽略空串
+fn stop_sequences(stop: Option<&Value>) -> Vec<&str> {
+    let sequences: Vec<&str> = match stop {
+        Some(Value::String(s)) => vec![s.as_str()],
+        Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    sequences.into_iter().filter(|s| !s.is_empty()).collect()
+}
+
+/// 请求中设置了但不会生效的参数（上游不支持或响应中无法体现）
+pub fn unsupported_params(request: &OpenAIRequest) -> Vec<&'static str> {
+    let mut params = Vec::new();
+    if request.n.is_some_and(|n| n > 1) {
+        params.push("n");
+    }
+    if request.logit_bias.as_ref().is_some_and(|b| b.as_object().is_some_and(|m| !m.is_empty())) {
+        params.push("logit_bias");
+    }
+    if request.logprobs == Some(true) {
+        params.push("logprobs");
+    }
+    if request.top_logprobs.is_some_and(|n| n > 0) {
+        params.push("top_logprobs");
+    }
+    // 超出上限的部分被丢弃
+    if stop_sequences(request.stop.as_ref()).len() > MAX_STOP_SEQUENCES {
+        params.push("stop");
+    }
+    params
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -403,12 +544,22 @@ mod tests {
                 tool_calls: None,
                 tool_call_id: None,
                 name: None,
+                reasoning_content: None,
             }],
             stream: false,
             max_tokens: None,
+            max_completion_tokens: None,
             temperature: None,
             top_p: None,
+            top_k: None,
+            seed: None,
+            frequency_penalty: None,
+            presence_penalty: None,
             stop: None,
+            n: None,
+            logit_bias: None,
+            logprobs: None,
+            top_logprobs: None,
             response_format: None,
             tools: None,
             tool_choice: None,
@@ -424,4 +575,208 @@ mod tests {
         assert_eq!(parts[0]["text"].as_str().unwrap(), "What is in this image?");
         assert_eq!(parts[1]["inlineData"]["mimeType"].as_str().unwrap(), "image/png");
     }
+
+    fn tool_message(role: &str, content: Option<&str>) -> OpenAIMessage {
+        OpenAIMessage {
+            role: role.to_string(),
+            content: content.map(|c| OpenAIContent::String(c.to_string())),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            reasoning_content: None,
+        }
+    }
+
+    #[test]
+    fn test_transform_openai_request_tools() {
+        let mut assistant = tool_message("assistant", None);
+        assistant.tool_calls = Some(vec![ToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: ToolFunction {
+                name: "get_weather".to_string(),
+                arguments: r#"{"city":"Paris"}"#.to_string(),
+            },
+        }]);
+        let mut tool_result = tool_message("tool", Some(r#"{"temp": 21}"#));
+        tool_result.tool_call_id = Some("call_1".to_string());
+        let mut text_result = tool_message("tool", Some("sunny"));
+        text_result.tool_call_id = Some("call_1".to_string());
+
+        let req = OpenAIRequest {
+            model: "gpt-4".to_string(),
+            messages: vec![tool_message("user", Some("Weather?")), assistant, tool_result, text_result],
+            stream: false,
+            max_tokens: None,
+            max_completion_tokens: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            seed: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop: None,
+            n: None,
+            logit_bias: None,
+            logprobs: None,
+            top_logprobs: None,
+            response_format: None,
+            tools: Some(vec![json!({
+                "type": "function",
+                "function": {
+                    "name": "get_weather",
+                    "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+                }
+            })]),
+            tool_choice: Some(json!({"type": "function", "function": {"name": "get_weather"}})),
+            parallel_tool_calls: None,
+            instructions: None,
+            input: None,
+            prompt: None,
+        };
+
+        let result = transform_openai_request(&req, "test-v", "gemini-2.5-flash");
+        let inner = &result["request"];
+        let decl = &inner["tools"][0]["functionDeclarations"][0];
+        assert_eq!(decl["name"], "get_weather");
+        assert_eq!(decl["parameters"]["properties"]["city"]["type"], "STRING");
+        assert_eq!(inner["toolConfig"]["functionCallingConfig"]["mode"], "ANY");
+        assert_eq!(inner["toolConfig"]["functionCallingConfig"]["allowedFunctionNames"][0], "get_weather");
+
+        let contents = inner["contents"].as_array().unwrap();
+        assert_eq!(contents[1]["parts"][0]["functionCall"]["args"]["city"], "Paris");
+        // 连续的工具结果合并为同一条 user 消息
+        let responses = contents[2]["parts"].as_array().unwrap();
+        assert_eq!(responses[0]["functionResponse"]["name"], "get_weather");
+        assert_eq!(responses[0]["functionResponse"]["response"]["temp"], 21);
+        assert_eq!(responses[1]["functionResponse"]["response"]["result"], "sunny");
+    }
+
+    #[test]
+    fn test_map_tool_choice() {
+        assert_eq!(map_tool_choice(&json!("none")).unwrap()["functionCallingConfig"]["mode"], "NONE");
+        assert_eq!(map_tool_choice(&json!("required")).unwrap()["functionCallingConfig"]["mode"], "ANY");
+        assert!(map_tool_choice(&json!("bogus")).is_none());
+    }
+
+    #[test]
+    fn test_transform_openai_request_image_variants() {
+        let png = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mP8z8BQDwAEhQGAhKmMIQAAAABJRU5ErkJggg==";
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{
+                "role": "user",
+                "content": [
+                    {"type": "input_text", "text": "Compare"},
+                    {"type": "input_image", "image_url": format!("data:application/octet-stream;base64,{}", png)},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": png}},
+                    {"type": "image_url", "image_url": {"url": "https://example.com/cat.webp?size=large"}},
+                    {"type": "input_audio", "input_audio": {"data": "", "format": "wav"}},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,not-base64!"}}
+                ]
+            }]
+        }))
+        .unwrap();
+
+        let result = transform_openai_request(&req, "test-v", "gemini-2.5-flash");
+        let parts = result["request"]["contents"][0]["parts"].as_array().unwrap();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0]["text"], "Compare");
+        // MIME 类型按文件头识别
+        assert_eq!(parts[1]["inlineData"]["mimeType"], "image/png");
+        assert_eq!(parts[2]["inlineData"]["data"], png);
+        assert_eq!(parts[3]["fileData"]["mimeType"], "image/webp");
+    }
+
+    #[test]
+    fn test_transform_openai_request_generation_params() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hi"}],
+            "max_tokens": 100,
+            "max_completion_tokens": 200,
+            "top_k": 40,
+            "seed": 7,
+            "frequency_penalty": 0.5,
+            "presence_penalty": 0,
+            "stop": ["a", "", "b", "c", "d", "e", "f"],
+            "n": 1,
+            "logit_bias": {}
+        }))
+        .unwrap();
+
+        let result = transform_openai_request(&req, "test-v", "gemini-2.5-flash");
+        let gen_config = &result["request"]["generationConfig"];
+        assert_eq!(gen_config["maxOutputTokens"], 200);
+        assert_eq!(gen_config["topK"], 40);
+        assert_eq!(gen_config["seed"], 7);
+        assert_eq!(gen_config["frequencyPenalty"], 0.5);
+        assert!(gen_config.get("presencePenalty").is_none());
+        assert_eq!(gen_config["stopSequences"], json!(["a", "b", "c", "d", "e"]));
+        assert_eq!(unsupported_params(&req), vec!["stop"]);
+
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hi"}],
+            "stop": "END",
+            "n": 2,
+            "logit_bias": {"50256": -100},
+            "logprobs": true,
+            "top_logprobs": 3
+        }))
+        .unwrap();
+        let result = transform_openai_request(&req, "test-v", "gemini-2.5-flash");
+        assert_eq!(result["request"]["generationConfig"]["stopSequences"], json!(["END"]));
+        assert_eq!(unsupported_params(&req), vec!["n", "logit_bias", "logprobs", "top_logprobs"]);
+    }
+
+    #[test]
+    fn test_transform_openai_request_json_schema() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "List two colors"}],
+            "response_format": {
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "colors",
+                    "strict": true,
+                    "schema": {
+                        "type": "object",
+                        "properties": {"colors": {"type": "array", "items": {"type": "string"}}},
+                        "required": ["colors"],
+                        "additionalProperties": false
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        let result = transform_openai_request(&req, "test-v", "gemini-2.5-flash");
+        let gen_config = &result["request"]["generationConfig"];
+        assert_eq!(gen_config["responseMimeType"], "application/json");
+        assert_eq!(gen_config["responseSchema"]["type"], "object");
+        assert_eq!(gen_config["responseSchema"]["properties"]["colors"]["items"]["type"], "string");
+        assert!(gen_config["responseSchema"].get("additionalProperties").is_none());
+    }
+
+    #[test]
+    fn test_transform_openai_request_file_blocks() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "Summarize"},
+                    {"type": "file", "file": {"file_data": "data:application/pdf;base64,JVBERi0=", "filename": "a.pdf"}},
+                    {"type": "file", "file": {"file_id": "file-unresolved"}}
+                ]
+            }]
+        }))
+        .unwrap();
+
+        let result = transform_openai_request(&req, "test-v", "gemini-2.5-flash");
+        let parts = result["request"]["contents"][0]["parts"].as_array().unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[1]["inlineData"], json!({ "mimeType": "application/pdf", "data": "JVBERi0=" }));
+    }
 }
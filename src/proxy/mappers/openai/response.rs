@@ -1,12 +1,37 @@
 use super::models::*;
 use serde_json::Value;
 
+/// 将 Gemini functionCall 转换为 OpenAI tool_call（arguments 为 JSON 字符串）
+pub fn function_call_to_tool_call(fc: &Value) -> ToolCall {
+    let name = fc.get("name").and_then(|v| v.as_str()).unwrap_or("unknown");
+    let args = match fc.get("args") {
+        Some(Value::Null) | None => "{}".to_string(),
+        Some(v) => v.to_string(),
+    };
+    let id = fc
+        .get("id")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4().simple()));
+
+    ToolCall {
+        id,
+        r#type: "function".to_string(),
+        function: ToolFunction {
+            name: name.to_string(),
+            arguments: args,
+        },
+    }
+}
+
 pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
     // 解包 response 字段
     let raw = gemini_response.get("response").unwrap_or(gemini_response);
 
     // 提取 content 和 tool_calls
     let mut content_out = String::new();
+    let mut reasoning_out = String::new();
+    let mut thinking = super::thinking::ThinkingWriter::new(super::thinking::mode());
     let mut tool_calls = Vec::new();
 
     if let Some(parts) = raw
@@ -17,16 +42,6 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
         .and_then(|p| p.as_array())
     {
         for part in parts {
-            /* 暂时禁用：思维链/推理部分 (Gemini 2.0+) 避免干扰 Codex CLI 等非推理客户端
-            if let Some(thought) = part.get("thought").and_then(|t| t.as_str()) {
-                if !thought.is_empty() {
-                    content_out.push_str("<thought>\n");
-                    content_out.push_str(thought);
-                    content_out.push_str("\n</thought>\n\n");
-                }
-            }
-            */
-
             // 捕获 thoughtSignature (Gemini 3 工具调用必需)
             if let Some(sig) = part
                 .get("thoughtSignature")
@@ -36,32 +51,16 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
                 super::streaming::store_thought_signature(sig);
             }
 
-            // 文本部分
-            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                content_out.push_str(text);
+            // 思考部分按 openai_thinking 配置输出，文本部分放入正文
+            if let Some(thought) = super::thinking::thought_text(part) {
+                thinking.thought(thought, &mut content_out, &mut reasoning_out);
+            } else if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                thinking.text(text, &mut content_out);
             }
 
             // 工具调用部分
             if let Some(fc) = part.get("functionCall") {
-                let name = fc.get("name").and_then(|v| v.as_str()).unwrap_or("unknown");
-                let args = fc
-                    .get("args")
-                    .map(|v| v.to_string())
-                    .unwrap_or_else(|| "{}".to_string());
-                let id = fc
-                    .get("id")
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(|| format!("{}-{}", name, uuid::Uuid::new_v4()));
-
-                tool_calls.push(ToolCall {
-                    id,
-                    r#type: "function".to_string(),
-                    function: ToolFunction {
-                        name: name.to_string(),
-                        arguments: args,
-                    },
-                });
+                tool_calls.push(function_call_to_tool_call(fc));
             }
 
             // 图片处理
@@ -72,7 +71,7 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
                     .unwrap_or("image/png");
                 let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
                 if !data.is_empty() {
-                    content_out.push_str(&format!("![image](data:{};base64,{})", mime_type, data));
+                    thinking.text(&format!("![image](data:{};base64,{})", mime_type, data), &mut content_out);
                 }
             }
         }
@@ -115,10 +114,9 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
             }
         }
 
-        if !grounding_text.is_empty() {
-            content_out.push_str(&grounding_text);
-        }
+        thinking.text(&grounding_text, &mut content_out);
     }
+    thinking.close(&mut content_out);
 
     // 提取 finish_reason
     let finish_reason = raw
@@ -134,6 +132,12 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
             _ => "stop",
         })
         .unwrap_or("stop");
+    // 模型以工具调用结束时 OpenAI 客户端 (LangChain 等) 依赖 finish_reason = "tool_calls"
+    let finish_reason = if !tool_calls.is_empty() && finish_reason == "stop" {
+        "tool_calls"
+    } else {
+        finish_reason
+    };
 
     OpenAIResponse {
         id: raw
@@ -164,6 +168,7 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
                 },
                 tool_call_id: None,
                 name: None,
+                reasoning_content: if reasoning_out.is_empty() { None } else { Some(reasoning_out) },
             },
             finish_reason: Some(finish_reason.to_string()),
         }],
@@ -198,4 +203,50 @@ mod tests {
         assert_eq!(content, "Hello!");
         assert_eq!(result.choices[0].finish_reason, Some("stop".to_string()));
     }
+
+    #[test]
+    fn test_transform_strips_thoughts_by_default() {
+        let gemini_resp = json!({
+            "candidates": [{
+                "content": {
+                    "parts": [{"text": "let me think", "thought": true}, {"text": "Hello!"}]
+                },
+                "finishReason": "STOP"
+            }]
+        });
+
+        let message = &transform_openai_response(&gemini_resp).choices[0].message;
+        assert_eq!(message.content, Some(OpenAIContent::String("Hello!".to_string())));
+        assert!(message.reasoning_content.is_none());
+    }
+
+    #[test]
+    fn test_transform_tool_calls() {
+        let gemini_resp = json!({
+            "candidates": [{
+                "content": {
+                    "parts": [
+                        {"functionCall": {"name": "get_weather", "args": {"city": "Paris"}}},
+                        {"functionCall": {"name": "get_time"}}
+                    ]
+                },
+                "finishReason": "STOP"
+            }]
+        });
+
+        let result = transform_openai_response(&gemini_resp);
+        let choice = &result.choices[0];
+        assert_eq!(choice.finish_reason.as_deref(), Some("tool_calls"));
+        assert!(choice.message.content.is_none());
+
+        let calls = choice.message.tool_calls.as_ref().unwrap();
+        assert_eq!(calls.len(), 2);
+        assert!(calls[0].id.starts_with("call_"));
+        assert_ne!(calls[0].id, calls[1].id);
+        assert_eq!(calls[0].r#type, "function");
+        assert_eq!(calls[0].function.name, "get_weather");
+        let args: Value = serde_json::from_str(&calls[0].function.arguments).unwrap();
+        assert_eq!(args["city"], "Paris");
+        assert_eq!(calls[1].function.arguments, "{}");
+    }
 }
@@ -61,6 +61,10 @@ pub fn create_openai_sse_stream(
         // Prefixed with _ as these are reserved for future usage reporting
         let mut _last_prompt_tokens: u32 = 0;
         let mut _last_completion_tokens: u32 = 0;
+        // 同一流内的 chunk 共用一个 id，LangChain 等客户端依赖它聚合 tool_call delta
+        let stream_id = format!("chatcmpl-{}", Uuid::new_v4());
+        let mut tool_call_index: usize = 0;
+        let mut thinking = super::thinking::ThinkingWriter::new(super::thinking::mode());
 
         while let Some(item) = gemini_stream.next().await {
             match item {
@@ -109,26 +113,42 @@ pub fn create_openai_sse_stream(
                                     }
 
                                     let mut content_out = String::new();
-                                    
+                                    let mut reasoning_out = String::new();
+                                    let mut tool_call_deltas: Vec<Value> = Vec::new();
+
                                     if let Some(parts_list) = parts {
                                         for part in parts_list {
-                                            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                                content_out.push_str(text);
-                                            }
-                                            // Capture thought (Thinking Models)
-                                            if let Some(_thought_text) = part.get("thought").and_then(|t| t.as_str()) {
-                                                 // content_out.push_str(thought_text);
+                                            // 思考部分按 openai_thinking 配置输出 (Thinking Models)
+                                            if let Some(thought) = super::thinking::thought_text(part) {
+                                                thinking.thought(thought, &mut content_out, &mut reasoning_out);
+                                            } else if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                                                thinking.text(text, &mut content_out);
                                             }
                                             // 捕获 thoughtSignature (Gemini 3 工具调用必需)
                                             if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
                                                 store_thought_signature(sig);
                                             }
 
+                                            // 工具调用：Gemini 每次返回完整的 functionCall，作为单个 delta 下发
+                                            if let Some(fc) = part.get("functionCall") {
+                                                let call = super::response::function_call_to_tool_call(fc);
+                                                tool_call_deltas.push(json!({
+                                                    "index": tool_call_index,
+                                                    "id": call.id,
+                                                    "type": "function",
+                                                    "function": {
+                                                        "name": call.function.name,
+                                                        "arguments": call.function.arguments
+                                                    }
+                                                }));
+                                                tool_call_index += 1;
+                                            }
+
                                             if let Some(img) = part.get("inlineData") {
                                                 let mime_type = img.get("mimeType").and_then(|v| v.as_str()).unwrap_or("image/png");
                                                 let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
                                                 if !data.is_empty() {
-                                                    content_out.push_str(&format!("![image](data:{};base64,{})", mime_type, data));
+                                                    thinking.text(&format!("![image](data:{};base64,{})", mime_type, data), &mut content_out);
                                                 }
                                             }
                                         }
@@ -159,14 +179,17 @@ pub fn create_openai_sse_stream(
                                                 grounding_text.push_str(&links.join("\n"));
                                             }
                                         }
-                                        if !grounding_text.is_empty() {
-                                            content_out.push_str(&grounding_text);
-                                        }
+                                        thinking.text(&grounding_text, &mut content_out);
+                                    }
+
+                                    let has_finish = candidate.and_then(|c| c.get("finishReason")).is_some();
+                                    if has_finish {
+                                        thinking.close(&mut content_out);
                                     }
 
-                                    if content_out.is_empty() {
+                                    if content_out.is_empty() && reasoning_out.is_empty() && tool_call_deltas.is_empty() {
                                         // Skip empty chunks if no text/grounding was found
-                                        if candidate.and_then(|c| c.get("finishReason")).is_none() {
+                                        if !has_finish {
                                             continue;
                                         }
                                     }
@@ -179,20 +202,31 @@ pub fn create_openai_sse_stream(
                                             "MAX_TOKENS" => "length",
                                             "SAFETY" => "content_filter",
                                             _ => f,
-                                        });
+                                        })
+                                        .map(|f| if f == "stop" && tool_call_index > 0 { "tool_calls" } else { f });
+
+                                    let mut delta = serde_json::Map::new();
+                                    if !content_out.is_empty() || (tool_call_deltas.is_empty() && reasoning_out.is_empty()) {
+                                        delta.insert("content".to_string(), json!(content_out));
+                                    }
+                                    if !reasoning_out.is_empty() {
+                                        delta.insert("reasoning_content".to_string(), json!(reasoning_out));
+                                    }
+                                    if !tool_call_deltas.is_empty() {
+                                        delta.insert("role".to_string(), json!("assistant"));
+                                        delta.insert("tool_calls".to_string(), Value::Array(tool_call_deltas));
+                                    }
 
                                     // Construct OpenAI SSE chunk
                                     let openai_chunk = json!({
-                                        "id": format!("chatcmpl-{}", Uuid::new_v4()),
+                                        "id": &stream_id,
                                         "object": "chat.completion.chunk",
                                         "created": Utc::now().timestamp(),
                                         "model": model,
                                         "choices": [
                                             {
                                                 "index": 0,
-                                                "delta": {
-                                                    "content": content_out
-                                                },
+                                                "delta": delta,
                                                 "finish_reason": finish_reason
                                             }
                                         ]
@@ -788,3 +822,39 @@ pub fn create_codex_sse_stream(
 
     Box::pin(stream)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_openai_stream_emits_tool_call_deltas() {
+        let chunks: Vec<Result<Bytes, reqwest::Error>> = vec![
+            Ok(Bytes::from("data: {\"response\":{\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Checking\"}]}}]}}\n\n")),
+            Ok(Bytes::from("data: {\"response\":{\"candidates\":[{\"content\":{\"parts\":[{\"functionCall\":{\"name\":\"get_weather\",\"args\":{\"city\":\"Paris\"}}},{\"functionCall\":{\"name\":\"get_time\",\"args\":{}}}]},\"finishReason\":\"STOP\"}]}}\n\n")),
+        ];
+        let upstream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>> =
+            Box::pin(futures::stream::iter(chunks));
+
+        let frames: Vec<Value> = create_openai_sse_stream(upstream, "gemini-2.5-flash".to_string())
+            .map(|item| String::from_utf8(item.unwrap().to_vec()).unwrap())
+            .filter(|frame| futures::future::ready(!frame.contains("[DONE]")))
+            .map(|frame| serde_json::from_str(frame.trim().trim_start_matches("data: ")).unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0]["id"], frames[1]["id"]);
+        assert_eq!(frames[0]["choices"][0]["delta"]["content"], "Checking");
+
+        let choice = &frames[1]["choices"][0];
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert!(choice["delta"].get("content").is_none());
+        let calls = choice["delta"]["tool_calls"].as_array().unwrap();
+        assert_eq!(calls[0]["index"], 0);
+        assert_eq!(calls[1]["index"], 1);
+        assert_eq!(calls[0]["type"], "function");
+        assert_eq!(calls[0]["function"]["name"], "get_weather");
+        assert_eq!(calls[0]["function"]["arguments"], "{\"city\":\"Paris\"}");
+    }
+}
@@ -0,0 +1,287 @@
+// OpenAI 流式响应严格兼容模式
+// 在转换后的 chat.completion.chunk 流之上再整理一遍，使其与 OpenAI 官方流逐项一致：
+// 首个 chunk 只携带 `role`，`finish_reason` 只取官方枚举值并单独放在最后一个空 delta 的 chunk 中，
+// tool_call 的 `index` 按调用顺序从 0 递增，`created` 全流一致，流以且仅以一个 `[DONE]` 结束
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::pin::Pin;
+
+const DONE_FRAME: &str = "data: [DONE]\n\n";
+
+/// 映射为 OpenAI 的 finish_reason 取值（`stop` / `length` / `tool_calls` / `content_filter` / `function_call`）
+pub fn normalize_finish_reason(reason: &str, has_tool_calls: bool) -> &'static str {
+    match reason.to_ascii_lowercase().as_str() {
+        "length" | "max_tokens" => "length",
+        "tool_calls" => "tool_calls",
+        "function_call" => "function_call",
+        "content_filter" | "safety" | "recitation" | "blocklist" | "prohibited_content" | "spii" | "image_safety" => {
+            "content_filter"
+        }
+        _ if has_tool_calls => "tool_calls",
+        _ => "stop",
+    }
+}
+
+/// 整理流的状态
+#[derive(Default)]
+struct StrictState {
+    id: Option<String>,
+    model: Option<String>,
+    created: Option<i64>,
+    role_sent: bool,
+    finished: bool,
+    done: bool,
+    /// tool_call id -> 下发的 index
+    tool_indices: HashMap<String, usize>,
+    /// 最近一个 tool_call 的 index（续传的参数片段没有 id）
+    last_tool_index: Option<usize>,
+}
+
+impl StrictState {
+    fn chunk(&self, delta: Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "system_fingerprint": null,
+            "choices": [{
+                "index": 0,
+                "delta": delta,
+                "logprobs": null,
+                "finish_reason": finish_reason
+            }]
+        })
+    }
+
+    /// 整理一个上游 chunk，返回应下发的 chunk（可能为 0 到 3 个）
+    fn process(&mut self, mut chunk: Value) -> Vec<Value> {
+        if self.finished {
+            return Vec::new();
+        }
+        if self.id.is_none() {
+            self.id = chunk.get("id").and_then(Value::as_str).map(str::to_string);
+            self.model = chunk.get("model").and_then(Value::as_str).map(str::to_string);
+            self.created = chunk.get("created").and_then(Value::as_i64);
+        }
+        let Some(choice) = chunk.get_mut("choices").and_then(|c| c.get_mut(0)) else {
+            return Vec::new();
+        };
+        let finish_reason = choice.get("finish_reason").and_then(Value::as_str).map(str::to_string);
+        let mut delta = match choice.get_mut("delta").map(Value::take) {
+            Some(Value::Object(delta)) => delta,
+            _ => Map::new(),
+        };
+
+        delta.remove("role");
+        if let Some(Value::Array(calls)) = delta.get_mut("tool_calls") {
+            for call in calls.iter_mut() {
+                self.normalize_tool_call(call);
+            }
+        }
+        let has_tool_calls = delta.get("tool_calls").is_some_and(|c| c.as_array().is_some_and(|a| !a.is_empty()));
+        if !has_tool_calls {
+            delta.remove("tool_calls");
+        }
+        let has_content = delta.get("content").and_then(Value::as_str).is_some_and(|c| !c.is_empty());
+        if !has_content {
+            delta.remove("content");
+        }
+        let has_reasoning = delta.get("reasoning_content").and_then(Value::as_str).is_some_and(|c| !c.is_empty());
+        if !has_reasoning {
+            delta.remove("reasoning_content");
+        }
+
+        let mut out = Vec::new();
+        if !self.role_sent {
+            self.role_sent = true;
+            out.push(self.chunk(json!({ "role": "assistant", "content": "" }), None));
+        }
+        if has_content || has_reasoning || has_tool_calls {
+            out.push(self.chunk(Value::Object(delta), None));
+        }
+        if let Some(reason) = finish_reason {
+            out.push(self.finish_chunk(&reason));
+        }
+        out
+    }
+
+    fn normalize_tool_call(&mut self, call: &mut Value) {
+        let Some(call) = call.as_object_mut() else {
+            return;
+        };
+        let index = match call.get("id").and_then(Value::as_str) {
+            Some(id) => {
+                let next = self.tool_indices.len();
+                *self.tool_indices.entry(id.to_string()).or_insert(next)
+            }
+            None => self.last_tool_index.unwrap_or(0),
+        };
+        self.last_tool_index = Some(index);
+        call.insert("index".to_string(), json!(index));
+        if call.contains_key("id") {
+            call.insert("type".to_string(), json!("function"));
+        }
+    }
+
+    fn finish_chunk(&mut self, reason: &str) -> Value {
+        self.finished = true;
+        let reason = normalize_finish_reason(reason, !self.tool_indices.is_empty());
+        self.chunk(json!({}), Some(reason))
+    }
+
+    /// 流结束（或收到 `[DONE]`）时补齐缺失的结束 chunk 与 `[DONE]`
+    fn finish(&mut self) -> Vec<Bytes> {
+        if self.done {
+            return Vec::new();
+        }
+        self.done = true;
+        let mut out = Vec::new();
+        if self.role_sent && !self.finished {
+            out.push(frame(&self.finish_chunk("stop")));
+        }
+        out.push(Bytes::from(DONE_FRAME));
+        out
+    }
+}
+
+fn frame(chunk: &Value) -> Bytes {
+    Bytes::from(format!("data: {}\n\n", chunk))
+}
+
+/// 把 chat.completion.chunk 流整理为严格的 OpenAI 格式；输入的每一项须为完整的 SSE 帧
+pub fn strict_chat_stream(
+    mut stream: Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>>,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
+    Box::pin(async_stream::stream! {
+        let mut state = StrictState::default();
+        while let Some(item) = stream.next().await {
+            let bytes = match item {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    yield Err(e);
+                    continue;
+                }
+            };
+            let text = String::from_utf8_lossy(&bytes);
+            for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                    continue;
+                };
+                if data == "[DONE]" {
+                    for out in state.finish() {
+                        yield Ok(out);
+                    }
+                    continue;
+                }
+                let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+                    continue;
+                };
+                for chunk in state.process(chunk) {
+                    yield Ok(frame(&chunk));
+                }
+            }
+        }
+        for out in state.finish() {
+            yield Ok(out);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upstream(frames: Vec<Value>, done: bool) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
+        let mut items: Vec<Result<Bytes, String>> = frames.iter().map(|f| Ok(frame(f))).collect();
+        if done {
+            items.push(Ok(Bytes::from(DONE_FRAME)));
+        }
+        Box::pin(futures::stream::iter(items))
+    }
+
+    async fn collect(stream: Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>>) -> Vec<String> {
+        strict_chat_stream(stream)
+            .map(|item| String::from_utf8(item.unwrap().to_vec()).unwrap())
+            .collect()
+            .await
+    }
+
+    fn parse(frame: &str) -> Value {
+        serde_json::from_str(frame.trim().trim_start_matches("data: ")).unwrap()
+    }
+
+    fn chunk(created: i64, delta: Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": created,
+            "model": "gemini-2.5-flash",
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }]
+        })
+    }
+
+    #[tokio::test]
+    async fn test_strict_stream_text() {
+        let frames = collect(upstream(
+            vec![
+                chunk(100, json!({ "content": "Hel" }), None),
+                chunk(101, json!({ "content": "lo" }), Some("MAX_TOKENS")),
+            ],
+            true,
+        ))
+        .await;
+
+        assert_eq!(frames.len(), 5);
+        let first = parse(&frames[0]);
+        assert_eq!(first["choices"][0]["delta"], json!({ "role": "assistant", "content": "" }));
+        assert!(first["choices"][0]["finish_reason"].is_null());
+        assert_eq!(parse(&frames[1])["choices"][0]["delta"], json!({ "content": "Hel" }));
+        let second = parse(&frames[2]);
+        assert_eq!(second["created"], 100);
+        assert!(second["choices"][0]["finish_reason"].is_null());
+        let last = parse(&frames[3]);
+        assert_eq!(last["choices"][0]["delta"], json!({}));
+        assert_eq!(last["choices"][0]["finish_reason"], "length");
+        assert_eq!(frames[4], DONE_FRAME);
+    }
+
+    #[tokio::test]
+    async fn test_strict_stream_tool_calls_and_missing_finish() {
+        let call = |id: &str, name: &str| json!({ "index": 7, "id": id, "function": { "name": name, "arguments": "{}" } });
+        let frames = collect(upstream(
+            vec![
+                chunk(100, json!({ "role": "assistant", "content": "", "tool_calls": [call("a", "f"), call("b", "g")] }), None),
+                chunk(100, json!({ "content": "" }), Some("stop")),
+            ],
+            false,
+        ))
+        .await;
+
+        assert_eq!(frames.len(), 4);
+        let delta = &parse(&frames[1])["choices"][0]["delta"];
+        assert!(delta.get("role").is_none());
+        assert!(delta.get("content").is_none());
+        assert_eq!(delta["tool_calls"][0]["index"], 0);
+        assert_eq!(delta["tool_calls"][1]["index"], 1);
+        assert_eq!(delta["tool_calls"][1]["type"], "function");
+        assert_eq!(parse(&frames[2])["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(frames[3], DONE_FRAME);
+
+        // 上游未给出结束原因时补一个 stop
+        let frames = collect(upstream(vec![chunk(1, json!({ "content": "x" }), None)], false)).await;
+        assert_eq!(parse(&frames[2])["choices"][0]["finish_reason"], "stop");
+        assert_eq!(frames.iter().filter(|f| f.as_str() == DONE_FRAME).count(), 1);
+    }
+
+    #[test]
+    fn test_normalize_finish_reason() {
+        assert_eq!(normalize_finish_reason("SAFETY", false), "content_filter");
+        assert_eq!(normalize_finish_reason("RECITATION", false), "content_filter");
+        assert_eq!(normalize_finish_reason("MALFORMED_FUNCTION_CALL", false), "stop");
+        assert_eq!(normalize_finish_reason("stop", true), "tool_calls");
+        assert_eq!(normalize_finish_reason("length", true), "length");
+    }
+}
@@ -0,0 +1,152 @@
+// OpenAI 兼容响应中的思考内容
+// 上游 thought part（`{"text": ..., "thought": true}`）按 `openai_thinking` 配置输出：
+// 丢弃（默认）、放入 `reasoning_content`（DeepSeek 风格），或以 `<think>` 标签包裹后放在正文之前。
+// 流式与非流式共用同一个 ThinkingWriter，保证两条路径的输出一致
+use serde_json::Value;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::proxy::config::ThinkingOutput;
+
+const THINK_OPEN: &str = "<think>\n";
+const THINK_CLOSE: &str = "\n</think>\n\n";
+
+static MODE: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_mode(mode: ThinkingOutput) {
+    let value = match mode {
+        ThinkingOutput::Strip => 0,
+        ThinkingOutput::ReasoningContent => 1,
+        ThinkingOutput::ThinkTags => 2,
+    };
+    MODE.store(value, Ordering::Relaxed);
+}
+
+pub fn mode() -> ThinkingOutput {
+    match MODE.load(Ordering::Relaxed) {
+        1 => ThinkingOutput::ReasoningContent,
+        2 => ThinkingOutput::ThinkTags,
+        _ => ThinkingOutput::Strip,
+    }
+}
+
+/// 上游模型支持返回思考内容（Gemini 2.5 / 3 的文本模型）
+pub fn supports_thoughts(mapped_model: &str) -> bool {
+    (mapped_model.starts_with("gemini-2.5") || mapped_model.starts_with("gemini-3")) && !mapped_model.contains("image")
+}
+
+/// thought part 的文本；普通 part 返回 None
+pub fn thought_text(part: &Value) -> Option<&str> {
+    match part.get("thought") {
+        Some(Value::Bool(true)) => Some(part.get("text").and_then(Value::as_str).unwrap_or("")),
+        Some(Value::String(s)) => Some(s.as_str()),
+        _ => None,
+    }
+}
+
+/// 把思考与正文写入 content / reasoning_content；流式时跨 chunk 保持状态
+pub struct ThinkingWriter {
+    mode: ThinkingOutput,
+    /// `<think>` 标签已打开尚未关闭
+    in_think: bool,
+}
+
+impl ThinkingWriter {
+    pub fn new(mode: ThinkingOutput) -> Self {
+        Self { mode, in_think: false }
+    }
+
+    pub fn thought(&mut self, text: &str, content: &mut String, reasoning: &mut String) {
+        if text.is_empty() {
+            return;
+        }
+        match self.mode {
+            ThinkingOutput::Strip => {}
+            ThinkingOutput::ReasoningContent => reasoning.push_str(text),
+            ThinkingOutput::ThinkTags => {
+                if !self.in_think {
+                    self.in_think = true;
+                    content.push_str(THINK_OPEN);
+                }
+                content.push_str(text);
+            }
+        }
+    }
+
+    /// 正文（含图片、引文）；先关闭未关闭的 `<think>` 标签
+    pub fn text(&mut self, text: &str, content: &mut String) {
+        if text.is_empty() {
+            return;
+        }
+        self.close(content);
+        content.push_str(text);
+    }
+
+    /// 响应结束时关闭 `<think>` 标签
+    pub fn close(&mut self, content: &mut String) {
+        if self.in_think {
+            self.in_think = false;
+            content.push_str(THINK_CLOSE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn write(mode: ThinkingOutput, chunks: &[&[(&str, bool)]]) -> Vec<(String, String)> {
+        let mut writer = ThinkingWriter::new(mode);
+        let mut out: Vec<(String, String)> = chunks
+            .iter()
+            .map(|parts| {
+                let (mut content, mut reasoning) = (String::new(), String::new());
+                for (text, thought) in parts.iter() {
+                    if *thought {
+                        writer.thought(text, &mut content, &mut reasoning);
+                    } else {
+                        writer.text(text, &mut content);
+                    }
+                }
+                (content, reasoning)
+            })
+            .collect();
+        let mut tail = String::new();
+        writer.close(&mut tail);
+        out.push((tail, String::new()));
+        out
+    }
+
+    #[test]
+    fn test_thinking_modes() {
+        let chunks: &[&[(&str, bool)]] = &[&[("plan", true)], &[(" more", true), ("Answer", false)]];
+
+        let stripped = write(ThinkingOutput::Strip, chunks);
+        assert_eq!(stripped[0], (String::new(), String::new()));
+        assert_eq!(stripped[1], ("Answer".to_string(), String::new()));
+
+        let reasoning = write(ThinkingOutput::ReasoningContent, chunks);
+        assert_eq!(reasoning[0], (String::new(), "plan".to_string()));
+        assert_eq!(reasoning[1], ("Answer".to_string(), " more".to_string()));
+
+        let tags = write(ThinkingOutput::ThinkTags, chunks);
+        assert_eq!(tags[0].0, "<think>\nplan");
+        assert_eq!(tags[1].0, " more\n</think>\n\nAnswer");
+        assert_eq!(tags[2].0, "");
+
+        // 只有思考内容时在结束时关闭标签
+        let only_thought = write(ThinkingOutput::ThinkTags, &[&[("hmm", true)]]);
+        assert_eq!(only_thought[1].0, "\n</think>\n\n");
+    }
+
+    #[test]
+    fn test_thought_text_and_models() {
+        assert_eq!(thought_text(&json!({ "text": "x", "thought": true })), Some("x"));
+        assert_eq!(thought_text(&json!({ "text": "x" })), None);
+        assert_eq!(thought_text(&json!({ "text": "x", "thought": false })), None);
+        assert!(supports_thoughts("gemini-2.5-pro"));
+        assert!(supports_thoughts("gemini-3-pro-high"));
+        assert!(!supports_thoughts("gemini-3-pro-image"));
+        assert!(!supports_thoughts("claude-sonnet-4-5-thinking"));
+    }
+}
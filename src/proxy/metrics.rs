@@ -0,0 +1,266 @@
+// Prometheus 指标
+// 进程内累积计数，由 monitor 中间件 / 上游客户端写入，/metrics 端点按 exposition 格式输出
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 延迟直方图桶上界（毫秒）
+const LATENCY_BUCKETS_MS: [u64; 11] = [50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000, 60_000, 120_000];
+
+/// 全局指标注册表
+pub static METRICS: Lazy<ProxyMetrics> = Lazy::new(ProxyMetrics::new);
+
+/// 按 API Key 聚合的 token 计数
+#[derive(Default)]
+struct KeyTokenCounters {
+    requests: AtomicU64,
+    input_tokens: AtomicU64,
+    output_tokens: AtomicU64,
+}
+
+/// 账号健康快照（渲染时由 TokenManager 提供）
+#[derive(Debug, Clone)]
+pub struct AccountHealth {
+    pub email: String,
+    pub rate_limited: bool,
+}
+
+pub struct ProxyMetrics {
+    /// status code -> 请求数
+    requests_by_status: DashMap<u16, AtomicU64>,
+    /// 每个桶的累计计数（非累积，渲染时累加）
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS_MS.len()],
+    latency_overflow: AtomicU64,
+    latency_sum_ms: AtomicU64,
+    latency_count: AtomicU64,
+    /// key name -> token 计数
+    tokens_by_key: DashMap<String, KeyTokenCounters>,
+    /// 失败后切换到下一个端点的次数（按失败端点统计）
+    upstream_fallbacks: DashMap<String, AtomicU64>,
+    /// 发出的对冲请求数 / 对冲请求先于主请求成功的次数
+    upstream_hedges: AtomicU64,
+    upstream_hedge_wins: AtomicU64,
+}
+
+impl Default for ProxyMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProxyMetrics {
+    pub fn new() -> Self {
+        Self {
+            requests_by_status: DashMap::new(),
+            latency_buckets: Default::default(),
+            latency_overflow: AtomicU64::new(0),
+            latency_sum_ms: AtomicU64::new(0),
+            latency_count: AtomicU64::new(0),
+            tokens_by_key: DashMap::new(),
+            upstream_fallbacks: DashMap::new(),
+            upstream_hedges: AtomicU64::new(0),
+            upstream_hedge_wins: AtomicU64::new(0),
+        }
+    }
+
+    /// 记录一次完成的 API 请求
+    pub fn record_request(&self, status: u16, duration_ms: u64) {
+        self.requests_by_status
+            .entry(status)
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+
+        match LATENCY_BUCKETS_MS.iter().position(|le| duration_ms <= *le) {
+            Some(idx) => self.latency_buckets[idx].fetch_add(1, Ordering::Relaxed),
+            None => self.latency_overflow.fetch_add(1, Ordering::Relaxed),
+        };
+        self.latency_sum_ms.fetch_add(duration_ms, Ordering::Relaxed);
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录某个 API Key 的 token 用量
+    pub fn record_key_tokens(&self, key_name: &str, input_tokens: Option<u32>, output_tokens: Option<u32>) {
+        let entry = self.tokens_by_key.entry(key_name.to_string()).or_default();
+        entry.requests.fetch_add(1, Ordering::Relaxed);
+        entry.input_tokens.fetch_add(input_tokens.unwrap_or(0) as u64, Ordering::Relaxed);
+        entry.output_tokens.fetch_add(output_tokens.unwrap_or(0) as u64, Ordering::Relaxed);
+    }
+
+    /// 记录一次上游端点 fallback
+    pub fn record_upstream_fallback(&self, endpoint: &str) {
+        self.upstream_fallbacks
+            .entry(endpoint.to_string())
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录一次对冲请求及其是否胜出
+    pub fn record_upstream_hedge(&self, won: bool) {
+        self.upstream_hedges.fetch_add(1, Ordering::Relaxed);
+        if won {
+            self.upstream_hedge_wins.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 输出 Prometheus text exposition 格式
+    pub fn render(&self, accounts: &[AccountHealth]) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP antiproxy_requests_total Total proxied API requests by HTTP status.\n");
+        out.push_str("# TYPE antiproxy_requests_total counter\n");
+        let mut statuses: Vec<(u16, u64)> = self
+            .requests_by_status
+            .iter()
+            .map(|e| (*e.key(), e.value().load(Ordering::Relaxed)))
+            .collect();
+        statuses.sort_unstable();
+        for (status, count) in statuses {
+            let _ = writeln!(out, "antiproxy_requests_total{{status=\"{}\"}} {}", status, count);
+        }
+
+        out.push_str("# HELP antiproxy_request_duration_seconds Time until response headers are sent.\n");
+        out.push_str("# TYPE antiproxy_request_duration_seconds histogram\n");
+        let mut cumulative = 0u64;
+        for (idx, le) in LATENCY_BUCKETS_MS.iter().enumerate() {
+            cumulative += self.latency_buckets[idx].load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "antiproxy_request_duration_seconds_bucket{{le=\"{}\"}} {}",
+                *le as f64 / 1000.0,
+                cumulative
+            );
+        }
+        cumulative += self.latency_overflow.load(Ordering::Relaxed);
+        let _ = writeln!(out, "antiproxy_request_duration_seconds_bucket{{le=\"+Inf\"}} {}", cumulative);
+        let _ = writeln!(
+            out,
+            "antiproxy_request_duration_seconds_sum {}",
+            self.latency_sum_ms.load(Ordering::Relaxed) as f64 / 1000.0
+        );
+        let _ = writeln!(
+            out,
+            "antiproxy_request_duration_seconds_count {}",
+            self.latency_count.load(Ordering::Relaxed)
+        );
+
+        let mut keys: Vec<_> = self
+            .tokens_by_key
+            .iter()
+            .map(|e| {
+                (
+                    e.key().clone(),
+                    e.value().requests.load(Ordering::Relaxed),
+                    e.value().input_tokens.load(Ordering::Relaxed),
+                    e.value().output_tokens.load(Ordering::Relaxed),
+                )
+            })
+            .collect();
+        keys.sort();
+
+        out.push_str("# HELP antiproxy_key_requests_total Requests attributed to each API key.\n");
+        out.push_str("# TYPE antiproxy_key_requests_total counter\n");
+        for (name, requests, _, _) in &keys {
+            let _ = writeln!(out, "antiproxy_key_requests_total{{key=\"{}\"}} {}", escape_label(name), requests);
+        }
+        out.push_str("# HELP antiproxy_key_tokens_total Tokens consumed per API key.\n");
+        out.push_str("# TYPE antiproxy_key_tokens_total counter\n");
+        for (name, _, input, output) in &keys {
+            let name = escape_label(name);
+            let _ = writeln!(out, "antiproxy_key_tokens_total{{key=\"{}\",direction=\"in\"}} {}", name, input);
+            let _ = writeln!(out, "antiproxy_key_tokens_total{{key=\"{}\",direction=\"out\"}} {}", name, output);
+        }
+
+        out.push_str("# HELP antiproxy_upstream_fallbacks_total Times an upstream endpoint failed and the next one was tried.\n");
+        out.push_str("# TYPE antiproxy_upstream_fallbacks_total counter\n");
+        let mut fallbacks: Vec<(String, u64)> = self
+            .upstream_fallbacks
+            .iter()
+            .map(|e| (e.key().clone(), e.value().load(Ordering::Relaxed)))
+            .collect();
+        fallbacks.sort();
+        for (endpoint, count) in fallbacks {
+            let _ = writeln!(
+                out,
+                "antiproxy_upstream_fallbacks_total{{endpoint=\"{}\"}} {}",
+                escape_label(&endpoint),
+                count
+            );
+        }
+
+        out.push_str("# HELP antiproxy_upstream_hedges_total Duplicate requests sent because the first attempt was slow.\n");
+        out.push_str("# TYPE antiproxy_upstream_hedges_total counter\n");
+        let _ = writeln!(out, "antiproxy_upstream_hedges_total {}", self.upstream_hedges.load(Ordering::Relaxed));
+        out.push_str("# HELP antiproxy_upstream_hedge_wins_total Hedged requests that succeeded before the original attempt.\n");
+        out.push_str("# TYPE antiproxy_upstream_hedge_wins_total counter\n");
+        let _ = writeln!(out, "antiproxy_upstream_hedge_wins_total {}", self.upstream_hedge_wins.load(Ordering::Relaxed));
+
+        let limited = accounts.iter().filter(|a| a.rate_limited).count();
+        out.push_str("# HELP antiproxy_accounts Accounts loaded into the token pool.\n");
+        out.push_str("# TYPE antiproxy_accounts gauge\n");
+        let _ = writeln!(out, "antiproxy_accounts{{state=\"available\"}} {}", accounts.len() - limited);
+        let _ = writeln!(out, "antiproxy_accounts{{state=\"rate_limited\"}} {}", limited);
+        out.push_str("# HELP antiproxy_account_healthy Whether an account is currently usable (1) or rate limited (0).\n");
+        out.push_str("# TYPE antiproxy_account_healthy gauge\n");
+        for account in accounts {
+            let _ = writeln!(
+                out,
+                "antiproxy_account_healthy{{account=\"{}\"}} {}",
+                escape_label(&account.email),
+                if account.rate_limited { 0 } else { 1 }
+            );
+        }
+
+        out
+    }
+}
+
+/// 转义 Prometheus label 值中的 `\`、`"` 与换行
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_histogram_is_cumulative() {
+        let metrics = ProxyMetrics::new();
+        metrics.record_request(200, 40);
+        metrics.record_request(200, 300);
+        metrics.record_request(429, 200_000);
+
+        let text = metrics.render(&[]);
+        assert!(text.contains("antiproxy_requests_total{status=\"200\"} 2"));
+        assert!(text.contains("antiproxy_requests_total{status=\"429\"} 1"));
+        assert!(text.contains("antiproxy_request_duration_seconds_bucket{le=\"0.05\"} 1"));
+        assert!(text.contains("antiproxy_request_duration_seconds_bucket{le=\"0.5\"} 2"));
+        assert!(text.contains("antiproxy_request_duration_seconds_bucket{le=\"+Inf\"} 3"));
+        assert!(text.contains("antiproxy_request_duration_seconds_count 3"));
+    }
+
+    #[test]
+    fn test_render_keys_accounts_and_fallbacks() {
+        let metrics = ProxyMetrics::new();
+        metrics.record_key_tokens("team \"a\"", Some(10), Some(5));
+        metrics.record_key_tokens("team \"a\"", None, Some(1));
+        metrics.record_upstream_fallback("https://daily.example/v1internal");
+        metrics.record_upstream_hedge(true);
+        metrics.record_upstream_hedge(false);
+
+        let accounts = vec![
+            AccountHealth { email: "a@example.com".to_string(), rate_limited: false },
+            AccountHealth { email: "b@example.com".to_string(), rate_limited: true },
+        ];
+        let text = metrics.render(&accounts);
+        assert!(text.contains("antiproxy_key_requests_total{key=\"team \\\"a\\\"\"} 2"));
+        assert!(text.contains("antiproxy_key_tokens_total{key=\"team \\\"a\\\"\",direction=\"in\"} 10"));
+        assert!(text.contains("antiproxy_key_tokens_total{key=\"team \\\"a\\\"\",direction=\"out\"} 6"));
+        assert!(text.contains("antiproxy_upstream_fallbacks_total{endpoint=\"https://daily.example/v1internal\"} 1"));
+        assert!(text.contains("antiproxy_upstream_hedges_total 2"));
+        assert!(text.contains("antiproxy_upstream_hedge_wins_total 1"));
+        assert!(text.contains("antiproxy_accounts{state=\"rate_limited\"} 1"));
+        assert!(text.contains("antiproxy_account_healthy{account=\"b@example.com\"} 0"));
+    }
+}
@@ -4,13 +4,23 @@ use axum::{
     extract::Request,
     http::{header, StatusCode},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
+    Json,
 };
+use serde_json::json;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::modules::api_keys::KeyPriority;
+use crate::proxy::auth_guard::{AUTH_GUARD, UNKNOWN_IP};
+use crate::proxy::common::error::{error_body, error_response};
+use crate::proxy::ip_filter::ClientIp;
+use crate::proxy::session_manager::{normalize_client_session_id, with_client_session_id, SESSION_ID_HEADER};
 use crate::proxy::{ProxyAuthMode, ProxySecurityConfig};
 
+/// Per-key usage endpoint, authenticated by the key itself
+const USAGE_PATH: &str = "/v1/usage";
+
 /// API Key authentication middleware
 /// Supports multi-key authentication: first checks multi-key database, then falls back to single key from config file
 pub async fn auth_middleware(
@@ -20,12 +30,26 @@ pub async fn auth_middleware(
 ) -> Result<Response, StatusCode> {
     let method = request.method().clone();
     let path = request.uri().path().to_string();
+    let security = security.read().await.clone();
+
+    // Resolved once here; later middleware, handlers and the request log read it from the extensions
+    let client_ip = resolve_client_ip(&request, &security);
+    if let Some(ip) = client_ip {
+        request.extensions_mut().insert(ClientIp(ip));
+    }
+    let client_label = client_ip.map(|ip| ip.to_string()).unwrap_or_else(|| "-".to_string());
 
     // Filter heartbeat and health check requests to avoid log noise
-    if !path.contains("event_logging") && path != "/healthz" {
-        tracing::info!("Request: {} {}", method, path);
+    if !path.contains("event_logging") && !crate::proxy::health::is_probe_path(&path) {
+        tracing::info!("Request: {} {} from {}", method, path, client_label);
     } else {
-        tracing::trace!("Heartbeat: {} {}", method, path);
+        tracing::trace!("Heartbeat: {} {} from {}", method, path, client_label);
+    }
+
+    // Global IP allow/deny rules apply to every route, including the web console
+    if !security.is_ip_allowed(client_ip) {
+        tracing::warn!("[Auth] Rejected request from disallowed IP {:?}: {} {}", client_ip, method, path);
+        return Ok(ip_forbidden_response());
     }
 
     // Allow CORS preflight regardless of auth policy.
@@ -33,15 +57,38 @@ pub async fn auth_middleware(
         return Ok(next.run(request).await);
     }
 
+    // IPs locked out after repeated authentication failures can't reach the login endpoints
+    let guard_ip = client_ip.map(|ip| ip.to_string()).unwrap_or_else(|| UNKNOWN_IP.to_string());
+    if path.starts_with("/api/auth/") {
+        if let Err(retry_after) = AUTH_GUARD.check(&guard_ip) {
+            tracing::warn!("[Auth] Rejected {} from locked out IP {}", path, guard_ip);
+            return Ok(auth_locked_response(&path, retry_after));
+        }
+    }
+
     if is_static_asset(&path) {
         return Ok(next.run(request).await);
     }
 
-    if path == "/oauth-callback" || path.starts_with("/api/oauth/") {
+    if path == "/oauth-callback" || path == crate::proxy::middleware::web_auth::OAUTH_CALLBACK_PATH {
+        return Ok(next.run(request).await);
+    }
+
+    // Peer-to-peer cluster notices carry the shared cluster secret instead of an API key
+    if crate::proxy::cluster::is_peer_path(&path) {
+        return Ok(next.run(request).await);
+    }
+
+    // Ollama chat/generate requests are authenticated by the internal router the handler forwards them to
+    if crate::proxy::handlers::ollama::is_forwarded_path(&path) {
+        return Ok(next.run(request).await);
+    }
+
+    // Admin API tokens are verified by web_auth_middleware, not as proxy API keys
+    if path.starts_with("/api/") && crate::proxy::middleware::web_auth::extract_admin_token(&request).is_some() {
         return Ok(next.run(request).await);
     }
 
-    let security = security.read().await.clone();
     let effective_mode = security.effective_auth_mode();
 
     // Extract API key from header (attempt extraction regardless of auth mode for statistics)
@@ -58,17 +105,112 @@ pub async fn auth_middleware(
         })
         .map(|s| s.to_string());
 
+    // Set when the presented key exists but has expired, so the 401 can say why
+    let mut key_expired = false;
+
     // If API key is provided, try to validate and set AuthenticatedKey (for statistics)
     if let Some(ref key_str) = api_key {
         // First try to validate from multi-key database
-        match crate::modules::api_keys::find_by_key(key_str) {
+        let lookup_key = key_str.clone();
+        let lookup = tokio::task::spawn_blocking(move || crate::modules::api_keys::find_by_key(&lookup_key))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+        match lookup {
             Ok(Some(api_key_record)) => {
-                if api_key_record.enabled {
+                if api_key_record.is_expired() {
+                    tracing::warn!("[Auth] API key {} has expired", api_key_record.name);
+                    key_expired = true;
+                } else if api_key_record.enabled {
                     tracing::debug!("[Auth] Found valid API key for tracking: {} (id: {})", api_key_record.name, api_key_record.id);
+
+                    // Per-key IP allowlist
+                    if !api_key_record.is_ip_allowed(client_ip) {
+                        tracing::warn!(
+                            "[Auth] API key {} used from disallowed IP {:?}",
+                            api_key_record.name,
+                            client_ip
+                        );
+                        return Ok(ip_forbidden_response());
+                    }
+
+                    // The usage endpoint only reports on the key, so it must stay reachable
+                    // once the key runs out of budget and must not consume its rate limit
+                    let is_usage_query = path == USAGE_PATH;
+
+                    // Per-key model allowlist (checked before rate limits so rejected requests don't consume quota)
+                    if api_key_record.allowed_models.is_some() && !is_usage_query {
+                        let (model, rebuilt) = extract_requested_model(request).await;
+                        request = rebuilt;
+                        if let Some(model) = model {
+                            if !api_key_record.is_model_allowed(&model) {
+                                tracing::warn!(
+                                    "[Auth] API key {} is not allowed to use model {}",
+                                    api_key_record.name,
+                                    model
+                                );
+                                return Ok(model_forbidden_response(&model));
+                            }
+                        }
+                    }
+
+                    if !is_usage_query {
+                        // Per-key daily token budget, including a pre-flight estimate of this request's input
+                        let estimated_tokens = if api_key_record.daily_token_budget.is_some() {
+                            let (estimate, rebuilt) = estimate_request_tokens(request).await;
+                            request = rebuilt;
+                            estimate
+                        } else {
+                            0
+                        };
+                        let budget_check = match api_key_record.daily_token_budget {
+                            Some(budget) => {
+                                let key_id = api_key_record.id.clone();
+                                crate::modules::storage::blocking(move || {
+                                    crate::modules::quota::check_key_token_budget(&key_id, Some(budget), estimated_tokens)
+                                })
+                                .await
+                            }
+                            None => Ok(()),
+                        };
+                        if let Err(exceeded) = budget_check {
+                            tracing::warn!(
+                                "[Auth] API key {} exhausted its daily token budget ({}/{})",
+                                api_key_record.name,
+                                exceeded.used,
+                                exceeded.budget
+                            );
+                            return Ok(token_budget_exceeded_response(exceeded));
+                        }
+
+                        // Per-key RPM / RPD limits
+                        if let Err(exceeded) = crate::proxy::key_rate_limit::KEY_RATE_LIMITER.check(
+                            &api_key_record.id,
+                            api_key_record.rate_limit_rpm,
+                            api_key_record.rate_limit_rpd,
+                        ) {
+                            tracing::warn!(
+                                "[Auth] API key {} exceeded {} limit, retry after {}s",
+                                api_key_record.name,
+                                exceeded.limit,
+                                exceeded.retry_after_secs
+                            );
+                            return Ok(rate_limited_response(exceeded));
+                        }
+                    }
+
+                    // Per-key request rewriting (system prompt, default parameters, stripped fields).
+                    // Replayed bodies come from the request log, which already holds the rewritten body.
+                    let is_replay = request.extensions().get::<crate::proxy::monitor::ReplayOf>().is_some();
+                    if let Some(transform) = api_key_record.request_transform.as_ref().filter(|_| !is_usage_query && !is_replay) {
+                        request = crate::proxy::request_transform::transform_request(request, transform).await;
+                    }
+
                     request.extensions_mut().insert(AuthenticatedKey {
                         key: key_str.clone(),
                         key_id: api_key_record.id,
                         key_name: api_key_record.name,
+                        pool: api_key_record.pool,
+                        priority: api_key_record.priority,
                     });
                 }
             }
@@ -80,6 +222,8 @@ pub async fn auth_middleware(
                         key: key_str.clone(),
                         key_id: "legacy".to_string(),
                         key_name: "Legacy Config Key".to_string(),
+                        pool: None,
+                        priority: KeyPriority::Normal,
                     });
                 }
             }
@@ -91,27 +235,245 @@ pub async fn auth_middleware(
 
     // If auth mode is Off, allow through (but AuthenticatedKey is already set for statistics)
     if matches!(effective_mode, ProxyAuthMode::Off) {
-        return Ok(next.run(request).await);
+        return Ok(run_with_key_scope(request, next).await);
     }
 
-    if matches!(effective_mode, ProxyAuthMode::AllExceptHealth) && path == "/healthz" {
-        return Ok(next.run(request).await);
+    if matches!(effective_mode, ProxyAuthMode::AllExceptHealth) && crate::proxy::health::is_probe_path(&path) {
+        return Ok(run_with_key_scope(request, next).await);
     }
 
     // Auth mode is not Off, need to validate API key
     let Some(_key_str) = api_key else {
         tracing::warn!("No API key provided in request");
-        return Err(StatusCode::UNAUTHORIZED);
+        return Ok(unauthorized_response("missing_api_key", "No API key provided"));
     };
 
+    // A locked out IP is rejected even when it presents a valid key
+    if let Err(retry_after) = AUTH_GUARD.check(&guard_ip) {
+        tracing::warn!("[Auth] Rejected API key from locked out IP {}", guard_ip);
+        return Ok(auth_locked_response(&path, retry_after));
+    }
+
     // Check if AuthenticatedKey is already set in request extensions
     if request.extensions().get::<AuthenticatedKey>().is_some() {
         // Already validated, allow through
-        return Ok(next.run(request).await);
+        AUTH_GUARD.record_success(&guard_ip);
+        return Ok(run_with_key_scope(request, next).await);
+    }
+
+    if key_expired {
+        return Ok(key_expired_response());
     }
 
     // API key is invalid
-    Err(StatusCode::UNAUTHORIZED)
+    AUTH_GUARD.record_failure(&guard_ip, "api_key");
+    Ok(unauthorized_response("invalid_api_key", "Invalid API key"))
+}
+
+tokio::task_local! {
+    /// ID of the API key that authenticated the current request.
+    /// Lets the token manager apply per-key account affinity without threading the key through every handler.
+    static CURRENT_KEY_ID: String;
+    /// Account pool of that key; requests only rotate within the pool's accounts.
+    static CURRENT_KEY_POOL: Option<String>;
+    /// Priority of that key; the concurrency limiter serves higher tiers first.
+    static CURRENT_KEY_PRIORITY: KeyPriority;
+}
+
+/// Returns the authenticated API key ID for the request being handled, if any
+pub fn current_key_id() -> Option<String> {
+    CURRENT_KEY_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Returns the account pool the current request's API key is bound to, if any
+pub fn current_key_pool() -> Option<String> {
+    CURRENT_KEY_POOL.try_with(|pool| pool.clone()).ok().flatten()
+}
+
+/// Returns the priority of the current request's API key (`normal` without a key)
+pub fn current_key_priority() -> KeyPriority {
+    CURRENT_KEY_PRIORITY.try_with(|priority| *priority).unwrap_or_default()
+}
+
+async fn run_with_key_scope(request: Request, next: Next) -> Response {
+    let session_id = request
+        .headers()
+        .get(SESSION_ID_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .and_then(normalize_client_session_id);
+    if let Some(key) = request.extensions().get::<AuthenticatedKey>() {
+        crate::proxy::otel::record_attribute("antiproxy.api_key.id", key.key_id.as_str());
+        crate::proxy::otel::record_attribute("antiproxy.api_key.name", key.key_name.as_str());
+    }
+    // Batch jobs always queue at low priority, whatever the key's own tier
+    let is_batch = request.extensions().get::<crate::proxy::batches::BatchRequest>().is_some();
+    let fut = async move {
+        match request
+            .extensions()
+            .get::<AuthenticatedKey>()
+            .map(|k| (k.key_id.clone(), k.pool.clone(), k.priority))
+        {
+            Some((key_id, pool, priority)) => {
+                let priority = if is_batch { KeyPriority::Low } else { priority };
+                let scoped = CURRENT_KEY_POOL.scope(pool, CURRENT_KEY_PRIORITY.scope(priority, next.run(request)));
+                CURRENT_KEY_ID.scope(key_id, scoped).await
+            }
+            None if is_batch => CURRENT_KEY_PRIORITY.scope(KeyPriority::Low, next.run(request)).await,
+            None => next.run(request).await,
+        }
+    };
+    with_client_session_id(session_id, fut).await
+}
+
+/// Extracts the requested model from the path (`/v1beta/models/{model}:method`) or the JSON body.
+/// The body is buffered and put back so downstream handlers still see it.
+pub(crate) async fn extract_requested_model(request: Request) -> (Option<String>, Request) {
+    if let Some(rest) = request.uri().path().strip_prefix("/v1beta/models/") {
+        let model = rest.split(':').next().unwrap_or("").to_string();
+        return ((!model.is_empty()).then_some(model), request);
+    }
+
+    if request.method() != axum::http::Method::POST {
+        return (None, request);
+    }
+
+    let (parts, body) = request.into_parts();
+    match axum::body::to_bytes(body, super::body_limit::max_request_body()).await {
+        Ok(bytes) => {
+            let model = serde_json::from_slice::<serde_json::Value>(&bytes)
+                .ok()
+                .and_then(|v| v.get("model").and_then(|m| m.as_str()).map(|s| s.to_string()));
+            (model, Request::from_parts(parts, axum::body::Body::from(bytes)))
+        }
+        Err(_) => (None, Request::from_parts(parts, axum::body::Body::empty())),
+    }
+}
+
+/// Locally estimates the input tokens of a generation request so the daily budget can be checked before
+/// any upstream quota is spent. Token counting endpoints and non-generation routes estimate to 0.
+async fn estimate_request_tokens(request: Request) -> (u64, Request) {
+    let path = request.uri().path();
+    let is_count_request = path.ends_with("countTokens") || path.ends_with("/count_tokens");
+    if request.method() != axum::http::Method::POST
+        || is_count_request
+        || crate::proxy::request_transform::BodyFormat::from_path(path).is_none()
+    {
+        return (0, request);
+    }
+
+    let (parts, body) = request.into_parts();
+    match axum::body::to_bytes(body, super::body_limit::max_request_body()).await {
+        Ok(bytes) => {
+            let estimate = serde_json::from_slice::<serde_json::Value>(&bytes)
+                .map(|v| crate::proxy::common::token_count::estimate_body_tokens(&v) as u64)
+                .unwrap_or(0);
+            (estimate, Request::from_parts(parts, axum::body::Body::from(bytes)))
+        }
+        Err(_) => (0, Request::from_parts(parts, axum::body::Body::empty())),
+    }
+}
+
+/// Client address: the TCP peer, or the X-Forwarded-For / X-Real-IP address when the peer is a trusted proxy.
+/// Reuses the address this middleware already stored in the request extensions.
+pub(crate) fn resolve_client_ip(request: &Request, security: &ProxySecurityConfig) -> Option<std::net::IpAddr> {
+    if let Some(ClientIp(ip)) = request.extensions().get::<ClientIp>() {
+        return Some(*ip);
+    }
+    let peer = request
+        .extensions()
+        .get::<axum::extract::ConnectInfo<std::net::SocketAddr>>()
+        .map(|info| info.0.ip());
+    let header = |name: &str| request.headers().get(name).and_then(|h| h.to_str().ok());
+    crate::proxy::ip_filter::client_ip(peer, header("x-forwarded-for"), header("x-real-ip"), &security.trusted_proxies)
+}
+
+/// Resolved client address of a request, if known
+pub(crate) fn request_client_ip(request: &Request) -> Option<std::net::IpAddr> {
+    request.extensions().get::<ClientIp>().map(|ClientIp(ip)| *ip)
+}
+
+fn unauthorized_response(code: &str, message: &str) -> Response {
+    error_response(StatusCode::UNAUTHORIZED, code, message)
+}
+
+fn ip_forbidden_response() -> Response {
+    error_response(StatusCode::FORBIDDEN, "ip_not_allowed", "Requests from this IP address are not allowed")
+}
+
+/// 429 for an IP locked out by the auth guard; management routes use the Web UI's `{"error": "..."}` shape
+pub(crate) fn auth_locked_response(path: &str, retry_after: u64) -> Response {
+    let message = format!(
+        "Too many failed authentication attempts, retry after {} seconds",
+        retry_after
+    );
+    let mut response = if path.starts_with("/api/") {
+        (StatusCode::TOO_MANY_REQUESTS, Json(json!({ "error": message }))).into_response()
+    } else {
+        error_response(StatusCode::TOO_MANY_REQUESTS, "too_many_auth_failures", message)
+    };
+    if let Ok(value) = header::HeaderValue::from_str(&retry_after.to_string()) {
+        response.headers_mut().insert(header::RETRY_AFTER, value);
+    }
+    response
+}
+
+fn key_expired_response() -> Response {
+    unauthorized_response("api_key_expired", "This API key has expired")
+}
+
+fn model_forbidden_response(model: &str) -> Response {
+    error_response(
+        StatusCode::FORBIDDEN,
+        "model_not_allowed",
+        format!("This API key is not allowed to use model '{}'", model),
+    )
+}
+
+fn rate_limited_response(exceeded: crate::proxy::key_rate_limit::KeyRateLimitExceeded) -> Response {
+    let mut response = error_response(
+        StatusCode::TOO_MANY_REQUESTS,
+        "rate_limit_exceeded",
+        format!(
+            "API key {} limit exceeded, retry after {} seconds",
+            exceeded.limit, exceeded.retry_after_secs
+        ),
+    );
+    if let Ok(value) = header::HeaderValue::from_str(&exceeded.retry_after_secs.to_string()) {
+        response.headers_mut().insert(header::RETRY_AFTER, value);
+    }
+    response
+}
+
+fn token_budget_exceeded_response(exceeded: crate::modules::quota::TokenBudgetExceeded) -> Response {
+    let retry_after = (exceeded.resets_at - chrono::Utc::now().timestamp()).max(1);
+    let resets_at = chrono::DateTime::from_timestamp(exceeded.resets_at, 0)
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_default();
+    let message = if exceeded.used < exceeded.budget {
+        format!(
+            "Request needs about {} input tokens but only {} of the daily token budget remain, resets at {}",
+            exceeded.requested,
+            exceeded.budget - exceeded.used,
+            resets_at
+        )
+    } else {
+        format!(
+            "Daily token budget of {} tokens exhausted ({} used), resets at {}",
+            exceeded.budget, exceeded.used, resets_at
+        )
+    };
+    let mut body = error_body(StatusCode::TOO_MANY_REQUESTS, "token_budget_exceeded", message);
+    if let Some(error) = body["error"].as_object_mut() {
+        error.insert("budget".to_string(), json!(exceeded.budget));
+        error.insert("used".to_string(), json!(exceeded.used));
+        error.insert("requested".to_string(), json!(exceeded.requested));
+        error.insert("resets_at".to_string(), json!(resets_at));
+    }
+    let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response();
+    if let Ok(value) = header::HeaderValue::from_str(&retry_after.to_string()) {
+        response.headers_mut().insert(header::RETRY_AFTER, value);
+    }
+    response
 }
 
 /// Authenticated API Key information (stored in request extensions)
@@ -120,6 +482,10 @@ pub struct AuthenticatedKey {
     pub key: String,
     pub key_id: String,
     pub key_name: String,
+    /// Account pool the key is bound to (None = all accounts)
+    pub pool: Option<String>,
+    /// Queue priority when upstream concurrency is limited
+    pub priority: KeyPriority,
 }
 
 fn is_static_asset(path: &str) -> bool {
@@ -139,11 +505,33 @@ fn is_static_asset(path: &str) -> bool {
 
 #[cfg(test)]
 mod tests {
-    // Removed unused use super::*;
+    use super::*;
 
     #[test]
     fn test_auth_placeholder() {
         // Placeholder test
         assert!(true);
     }
+
+    #[tokio::test]
+    async fn test_extract_requested_model() {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .body(axum::body::Body::from(r#"{"model":"gemini-2.0-flash","messages":[]}"#))
+            .unwrap();
+        let (model, rebuilt) = extract_requested_model(request).await;
+        assert_eq!(model.as_deref(), Some("gemini-2.0-flash"));
+        // body must still be readable downstream
+        let bytes = axum::body::to_bytes(rebuilt.into_body(), usize::MAX).await.unwrap();
+        assert!(bytes.starts_with(b"{\"model\""));
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1beta/models/gemini-2.5-pro:streamGenerateContent")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let (model, _) = extract_requested_model(request).await;
+        assert_eq!(model.as_deref(), Some("gemini-2.5-pro"));
+    }
 }
@@ -0,0 +1,120 @@
+// 路径前缀
+// 配置 `base_path`（如 `/antiproxy`）时，Web UI、管理接口与协议端点都挂在该前缀下，便于与其他应用共用反向代理；
+// 前缀在最外层剥离，内部路由与中间件仍按无前缀的路径匹配。只在启动时读取
+use axum::{
+    extract::Request,
+    http::{StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use std::sync::OnceLock;
+
+static BASE_PATH: OnceLock<String> = OnceLock::new();
+
+/// 规范化路径前缀：空或 `/` 表示不使用前缀，否则以 `/` 开头、不以 `/` 结尾
+pub fn normalize(base_path: &str) -> Result<String, String> {
+    let trimmed = base_path.trim().trim_end_matches('/');
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+    if !trimmed.starts_with('/') {
+        return Err(format!("`{}` must start with `/`", base_path));
+    }
+    if trimmed.contains("//")
+        || !trimmed
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '-' | '_' | '.' | '~'))
+    {
+        return Err(format!("`{}` may only contain letters, digits, `-`, `_`, `.`, `~` and single `/`", base_path));
+    }
+    Ok(trimmed.to_string())
+}
+
+/// 设置路径前缀（启动时调用一次）
+pub fn init(base_path: &str) -> Result<(), String> {
+    let normalized = normalize(base_path)?;
+    BASE_PATH
+        .set(normalized)
+        .map_err(|_| "base_path is already set".to_string())
+}
+
+/// 当前路径前缀，未配置时为空字符串
+pub fn get() -> &'static str {
+    BASE_PATH.get().map(String::as_str).unwrap_or("")
+}
+
+/// 加上路径前缀的站内地址，`path` 以 `/` 开头
+pub fn url(path: &str) -> String {
+    format!("{}{}", get(), path)
+}
+
+#[derive(Debug, PartialEq)]
+enum Routed {
+    /// 去掉前缀后的地址
+    Inner(Uri),
+    /// 访问前缀本身时补上末尾的 `/`，页面中的相对地址才能正确解析
+    Redirect(String),
+    NotFound,
+}
+
+fn route(base: &str, uri: &Uri) -> Routed {
+    let path = uri.path();
+    let query = uri.query().map(|q| format!("?{}", q)).unwrap_or_default();
+    if path == base {
+        return Routed::Redirect(format!("{}/{}", base, query));
+    }
+    let Some(rest) = path.strip_prefix(base).filter(|rest| rest.starts_with('/')) else {
+        return Routed::NotFound;
+    };
+    match format!("{}{}", rest, query).parse() {
+        Ok(inner) => Routed::Inner(inner),
+        Err(_) => Routed::NotFound,
+    }
+}
+
+pub async fn base_path_middleware(mut request: Request, next: Next) -> Response {
+    let base = get();
+    if base.is_empty() {
+        return next.run(request).await;
+    }
+    match route(base, request.uri()) {
+        Routed::Inner(uri) => {
+            *request.uri_mut() = uri;
+            next.run(request).await
+        }
+        Routed::Redirect(location) => Redirect::permanent(&location).into_response(),
+        Routed::NotFound => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_base_path() {
+        assert_eq!(normalize("").unwrap(), "");
+        assert_eq!(normalize("/").unwrap(), "");
+        assert_eq!(normalize("/antiproxy/").unwrap(), "/antiproxy");
+        assert_eq!(normalize("/apps/anti-proxy").unwrap(), "/apps/anti-proxy");
+        assert!(normalize("antiproxy").is_err());
+        assert!(normalize("/a//b").is_err());
+        assert!(normalize("/a?b").is_err());
+    }
+
+    #[test]
+    fn test_route_strips_prefix() {
+        let uri = |s: &str| s.parse::<Uri>().unwrap();
+        assert_eq!(
+            route("/antiproxy", &uri("/antiproxy/v1/models?limit=1")),
+            Routed::Inner(uri("/v1/models?limit=1"))
+        );
+        assert_eq!(route("/antiproxy", &uri("/antiproxy/")), Routed::Inner(uri("/")));
+        assert_eq!(
+            route("/antiproxy", &uri("/antiproxy?x=1")),
+            Routed::Redirect("/antiproxy/?x=1".to_string())
+        );
+        assert_eq!(route("/antiproxy", &uri("/antiproxyx/")), Routed::NotFound);
+        assert_eq!(route("/antiproxy", &uri("/v1/models")), Routed::NotFound);
+    }
+}
@@ -0,0 +1,119 @@
+// 请求体大小限制
+// 声明了 Content-Length 的超限请求直接返回 413；分块上传的请求体在读取时计数，超限后同样改写为 413
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use futures::StreamExt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::proxy::common::error::error_response;
+
+/// 请求体上限（字节），0 表示不限制；启动 / 热重载时由配置写入
+static MAX_REQUEST_BODY_BYTES: AtomicUsize = AtomicUsize::new(crate::proxy::config::DEFAULT_MAX_REQUEST_BODY_BYTES);
+
+pub fn set_max_request_body(bytes: usize) {
+    MAX_REQUEST_BODY_BYTES.store(bytes, Ordering::Relaxed);
+}
+
+/// 当前请求体上限，供需要完整缓冲请求体的中间件使用
+pub fn max_request_body() -> usize {
+    match MAX_REQUEST_BODY_BYTES.load(Ordering::Relaxed) {
+        0 => usize::MAX,
+        bytes => bytes,
+    }
+}
+
+/// 请求声明的 Content-Length
+pub fn content_length(request: &Request) -> Option<u64> {
+    request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+fn too_large_response(limit: usize) -> Response {
+    error_response(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "request_too_large",
+        format!("Request body exceeds the {} byte limit", limit),
+    )
+}
+
+/// 文件上传请求中 multipart 边界与表单字段的余量
+const MULTIPART_OVERHEAD_BYTES: usize = 64 * 1024;
+
+pub async fn body_limit_middleware(request: Request, next: Next) -> Response {
+    let mut limit = max_request_body();
+    // 文件上传按 files.max_file_bytes 限制，不受普通请求体上限约束
+    if request.uri().path() == crate::proxy::handlers::files::FILES_PATH {
+        let max_file = crate::proxy::files::FILES.max_file_bytes();
+        limit = limit.max(max_file.saturating_add(MULTIPART_OVERHEAD_BYTES));
+    }
+    enforce_limit(request, next, limit).await
+}
+
+async fn enforce_limit(request: Request, next: Next, limit: usize) -> Response {
+    match content_length(&request) {
+        Some(length) if length > limit as u64 => return too_large_response(limit),
+        // hyper 保证请求体与 Content-Length 一致，无需再计数
+        Some(_) => return next.run(request).await,
+        None if limit == usize::MAX => return next.run(request).await,
+        None => {}
+    }
+
+    // 分块上传：读取时计数，超限后返回错误并在响应阶段改写为 413
+    let exceeded = Arc::new(AtomicBool::new(false));
+    let flag = exceeded.clone();
+    let (parts, body) = request.into_parts();
+    let mut read = 0usize;
+    let stream = body.into_data_stream().map(move |chunk| {
+        let chunk = chunk.map_err(std::io::Error::other)?;
+        read += chunk.len();
+        if read > limit {
+            flag.store(true, Ordering::Relaxed);
+            return Err(std::io::Error::other(format!("Request body exceeds the {} byte limit", limit)));
+        }
+        Ok(chunk)
+    });
+    let response = next.run(Request::from_parts(parts, Body::from_stream(stream))).await;
+    if exceeded.load(Ordering::Relaxed) {
+        return too_large_response(limit);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+    use tower::ServiceExt;
+
+    async fn echo_len(body: axum::body::Bytes) -> String {
+        body.len().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_body_limit_declared_and_chunked() {
+        let app = Router::new()
+            .route("/", post(echo_len))
+            .layer(axum::extract::DefaultBodyLimit::disable())
+            .layer(axum::middleware::from_fn(|request, next| enforce_limit(request, next, 8)));
+
+        let declared = Request::post("/").header(header::CONTENT_LENGTH, "9").body(Body::from("123456789")).unwrap();
+        assert_eq!(app.clone().oneshot(declared).await.unwrap().status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let chunks = |parts: &'static [&'static str]| {
+            Body::from_stream(futures::stream::iter(parts.iter().map(|p| Ok::<_, std::io::Error>(*p))))
+        };
+        let small = Request::post("/").body(chunks(&["1234", "5678"])).unwrap();
+        assert_eq!(app.clone().oneshot(small).await.unwrap().status(), StatusCode::OK);
+        let large = Request::post("/").body(chunks(&["1234", "5678", "9"])).unwrap();
+        assert_eq!(app.clone().oneshot(large).await.unwrap().status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,226 @@
+// 响应压缩
+// 客户端声明 `Accept-Encoding: gzip` 时，用 modules::gzip 压缩文本 / JSON 响应（含未声明长度的分块响应）；
+// SSE、NDJSON 等逐条推送的响应、已编码、过小或过大的响应原样返回。
+// 只支持 gzip：依赖中没有 brotli 编码器（tower-http 的 CompressionLayer 需要 async-compression），
+// 只接受 br 的客户端拿到未压缩的响应
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use futures::StreamExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// 是否压缩响应；启动 / 热重载时由配置写入
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// 小于该大小的响应压缩收益不大，直接返回
+const MIN_COMPRESS_BYTES: u64 = 1024;
+
+/// 压缩前需要完整缓冲响应，超过该大小的响应原样转发
+const MAX_COMPRESS_BYTES: u64 = 8 * 1024 * 1024;
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Accept-Encoding 是否接受 gzip（`q=0` 表示拒绝）
+fn accepts_gzip(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|item| {
+            let mut params = item.split(';');
+            let coding = params.next().unwrap_or_default().trim();
+            let rejected = params.any(|p| p.trim().strip_prefix("q=").and_then(|q| q.trim().parse::<f32>().ok()) == Some(0.0));
+            (coding.eq_ignore_ascii_case("gzip") || coding == "*") && !rejected
+        })
+}
+
+/// 响应是否适合压缩：文本类内容、未编码、不是分段响应；SSE / NDJSON 需要逐条送达，缓冲会破坏流式输出
+fn compressible(status: StatusCode, headers: &HeaderMap) -> bool {
+    if status == StatusCode::PARTIAL_CONTENT || headers.contains_key(header::CONTENT_ENCODING) {
+        return false;
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    !content_type.contains("text/event-stream")
+        && !content_type.contains("ndjson")
+        && (content_type.starts_with("text/")
+            || content_type.contains("json")
+            || content_type.contains("javascript")
+            || content_type.contains("xml"))
+}
+
+pub async fn compression_middleware(request: Request, next: Next) -> Response {
+    let accepts = ENABLED.load(Ordering::Relaxed) && accepts_gzip(request.headers());
+    let response = next.run(request).await;
+    if !accepts || !compressible(response.status(), response.headers()) {
+        return response;
+    }
+    // 长度已知时直接按大小筛选；分块响应边读边判断
+    let hint = response.body().size_hint();
+    if hint.exact().is_some_and(|size| size < MIN_COMPRESS_BYTES) || hint.lower() > MAX_COMPRESS_BYTES {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match buffer_body(body, MAX_COMPRESS_BYTES as usize).await {
+        Ok(bytes) => bytes,
+        Err(body) => return Response::from_parts(parts, body),
+    };
+    if (bytes.len() as u64) < MIN_COMPRESS_BYTES {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+    let input = bytes.clone();
+    let compressed = match tokio::task::spawn_blocking(move || crate::modules::gzip::compress(&input)).await {
+        Ok(compressed) => compressed,
+        Err(_) => return Response::from_parts(parts, Body::from(bytes)),
+    };
+
+    parts.headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    Response::from_parts(parts, Body::from(compressed))
+}
+
+/// 缓冲响应体：不超过 `cap` 时返回完整内容；超出上限或读取失败时停止缓冲，
+/// 返回已读部分与剩余部分拼接的原样响应体，不丢数据也不吞掉错误
+async fn buffer_body(body: Body, cap: usize) -> Result<Bytes, Body> {
+    let mut stream = body.into_data_stream();
+    let mut chunks = Vec::new();
+    let mut size = 0;
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(chunk) => {
+                size += chunk.len();
+                chunks.push(chunk);
+                if size > cap {
+                    let head = futures::stream::iter(chunks.into_iter().map(Ok));
+                    return Err(Body::from_stream(head.chain(stream)));
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Failed to buffer response for compression: {}", e);
+                let head = futures::stream::iter(chunks.into_iter().map(Ok));
+                return Err(Body::from_stream(head.chain(futures::stream::once(async move { Err(e) }))));
+            }
+        }
+    }
+    Ok(Bytes::from(chunks.concat()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::to_bytes, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_accepts_gzip() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT_ENCODING, value.parse().unwrap());
+            headers
+        };
+        assert!(accepts_gzip(&headers("gzip, deflate, br")));
+        assert!(accepts_gzip(&headers("br;q=1.0, GZIP;q=0.5")));
+        assert!(accepts_gzip(&headers("*")));
+        assert!(!accepts_gzip(&headers("gzip;q=0")));
+        assert!(!accepts_gzip(&headers("identity")));
+        assert!(!accepts_gzip(&HeaderMap::new()));
+    }
+
+    #[tokio::test]
+    async fn test_compresses_text_responses() {
+        let json = format!("{{\"data\":\"{}\"}}", "x".repeat(4096));
+        let expected = json.clone();
+        let app = Router::new()
+            .route("/json", get(move || async move { ([(header::CONTENT_TYPE, "application/json")], json) }))
+            .route("/small", get(|| async { "ok" }))
+            .route("/sse", get(|| async { ([(header::CONTENT_TYPE, "text/event-stream")], "data: x\n\n".repeat(500)) }))
+            .layer(axum::middleware::from_fn(compression_middleware));
+        let call = |path: &str, encoding: &str| {
+            app.clone().oneshot(Request::get(path).header(header::ACCEPT_ENCODING, encoding).body(Body::empty()).unwrap())
+        };
+
+        let response = call("/json", "gzip").await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[header::VARY], "accept-encoding");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.len() < expected.len() / 10);
+        assert_eq!(crate::modules::gzip::decompress(&body, usize::MAX).unwrap(), expected.as_bytes());
+
+        // 未声明 gzip、过小的响应与 SSE 都不压缩
+        assert!(!call("/json", "identity").await.unwrap().headers().contains_key(header::CONTENT_ENCODING));
+        assert!(!call("/small", "gzip").await.unwrap().headers().contains_key(header::CONTENT_ENCODING));
+        assert!(!call("/sse", "gzip").await.unwrap().headers().contains_key(header::CONTENT_ENCODING));
+    }
+
+    fn chunked(chunks: Vec<String>) -> Body {
+        Body::from_stream(futures::stream::iter(chunks.into_iter().map(Ok::<_, std::io::Error>)))
+    }
+
+    #[tokio::test]
+    async fn test_chunked_responses() {
+        let app = Router::new()
+            .route(
+                "/chunked",
+                get(|| async { ([(header::CONTENT_TYPE, "application/json")], chunked(vec!["[".repeat(1000), "]".repeat(1000)])) }),
+            )
+            .route(
+                "/large",
+                get(|| async {
+                    let chunk = "y".repeat(1024 * 1024);
+                    ([(header::CONTENT_TYPE, "text/plain")], chunked(vec![chunk; 9]))
+                }),
+            )
+            .route(
+                "/ndjson",
+                get(|| async { ([(header::CONTENT_TYPE, "application/x-ndjson")], chunked(vec!["{}\n".repeat(1000)])) }),
+            )
+            .route(
+                "/broken",
+                get(|| async {
+                    let chunks = futures::stream::iter(vec![
+                        Ok("z".repeat(2048)),
+                        Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "upstream closed")),
+                    ]);
+                    ([(header::CONTENT_TYPE, "text/plain")], Body::from_stream(chunks))
+                }),
+            )
+            .layer(axum::middleware::from_fn(compression_middleware));
+        let call = |path: &str| {
+            app.clone().oneshot(Request::get(path).header(header::ACCEPT_ENCODING, "gzip").body(Body::empty()).unwrap())
+        };
+
+        // 未声明长度的分块响应同样压缩
+        let response = call("/chunked").await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let expected = format!("{}{}", "[".repeat(1000), "]".repeat(1000));
+        assert_eq!(crate::modules::gzip::decompress(&body, usize::MAX).unwrap(), expected.as_bytes());
+
+        // 超过缓冲上限的响应原样转发，已读部分不丢失
+        let response = call("/large").await.unwrap();
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.len(), 9 * 1024 * 1024);
+        assert!(body.iter().all(|b| *b == b'y'));
+
+        // NDJSON 逐行推送，不缓冲
+        assert!(!call("/ndjson").await.unwrap().headers().contains_key(header::CONTENT_ENCODING));
+
+        // 读取失败时错误照常传给客户端，而不是返回空响应
+        let response = call("/broken").await.unwrap();
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+        assert!(to_bytes(response.into_body(), usize::MAX).await.is_err());
+    }
+}
@@ -1,23 +1,145 @@
 // CORS middleware
-use tower_http::cors::{CorsLayer, Any};
-use axum::http::Method;
-
-/// Create CORS layer
-pub fn cors_layer() -> CorsLayer {
-    CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods([
-            Method::GET,
-            Method::POST,
-            Method::PUT,
-            Method::DELETE,
-            Method::HEAD,
-            Method::OPTIONS,
-            Method::PATCH,
-        ])
-        .allow_headers(Any)
-        .allow_credentials(false)
-        .max_age(std::time::Duration::from_secs(3600))
+// 按 `cors` 配置处理跨域请求：预检请求在鉴权之前直接应答，其余响应补充 CORS 头；配置随热重载生效
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::proxy::config::CorsConfig;
+use crate::proxy::ProxySecurityConfig;
+
+const ALLOWED_METHODS: &str = "GET, POST, PUT, DELETE, HEAD, OPTIONS, PATCH";
+
+/// 解析后的 CORS 策略
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsPolicy {
+    /// None 表示允许任意来源
+    allowed_origins: Option<Vec<String>>,
+    /// None 表示回显预检请求声明的请求头
+    allowed_headers: Option<String>,
+    allow_credentials: bool,
+    max_age_seconds: u64,
+}
+
+impl Default for CorsPolicy {
+    fn default() -> Self {
+        Self::from_config(&CorsConfig::default())
+    }
+}
+
+/// 来源比较忽略大小写与末尾的 `/`
+fn normalize_origin(origin: &str) -> String {
+    origin.trim().trim_end_matches('/').to_ascii_lowercase()
+}
+
+impl CorsPolicy {
+    pub fn from_config(config: &CorsConfig) -> Self {
+        let any_origin = config.allowed_origins.iter().any(|o| o.trim() == "*");
+        let allowed_origins = (!any_origin).then(|| {
+            config
+                .allowed_origins
+                .iter()
+                .map(|o| normalize_origin(o))
+                .filter(|o| !o.is_empty())
+                .collect()
+        });
+        let allowed_headers = (!config.allowed_headers.iter().any(|h| h.trim() == "*")).then(|| {
+            config
+                .allowed_headers
+                .iter()
+                .map(|h| h.trim())
+                .filter(|h| !h.is_empty())
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+        if config.allow_credentials && any_origin {
+            tracing::warn!("[CORS] allow_credentials requires explicit allowed_origins; ignoring it for '*'");
+        }
+        Self {
+            allowed_origins,
+            allowed_headers,
+            allow_credentials: config.allow_credentials && !any_origin,
+            max_age_seconds: config.max_age_seconds,
+        }
+    }
+
+    fn is_origin_allowed(&self, origin: &str) -> bool {
+        match &self.allowed_origins {
+            None => true,
+            Some(list) => list.contains(&normalize_origin(origin)),
+        }
+    }
+
+    /// 为允许的来源写入基础 CORS 头
+    fn apply_origin_headers(&self, origin: &HeaderValue, headers: &mut HeaderMap) {
+        if self.allowed_origins.is_none() {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+        } else {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+            headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        }
+        if self.allow_credentials {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+        }
+    }
+
+    fn preflight_response(&self, origin: &HeaderValue, request_headers: &HeaderMap) -> Response {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        self.apply_origin_headers(origin, headers);
+        headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static(ALLOWED_METHODS));
+        let allow_headers = match &self.allowed_headers {
+            Some(list) => HeaderValue::from_str(list).ok(),
+            None => request_headers.get(header::ACCESS_CONTROL_REQUEST_HEADERS).cloned(),
+        };
+        if let Some(value) = allow_headers {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, value);
+        }
+        if self.allowed_headers.is_none() {
+            headers.append(header::VARY, HeaderValue::from_static("Access-Control-Request-Headers"));
+        }
+        headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(self.max_age_seconds));
+        response
+    }
+}
+
+fn is_preflight(request: &Request) -> bool {
+    request.method() == Method::OPTIONS && request.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+/// CORS 中间件
+pub async fn cors_middleware(
+    State(security): State<Arc<RwLock<ProxySecurityConfig>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(origin) = request.headers().get(header::ORIGIN).cloned() else {
+        return next.run(request).await;
+    };
+    let policy = security.read().await.cors.clone();
+    let allowed = origin.to_str().is_ok_and(|o| policy.is_origin_allowed(o));
+
+    if is_preflight(&request) {
+        if !allowed {
+            tracing::debug!("[CORS] Rejected preflight from origin {:?}", origin);
+            return StatusCode::FORBIDDEN.into_response();
+        }
+        return policy.preflight_response(&origin, request.headers());
+    }
+
+    let mut response = next.run(request).await;
+    if allowed {
+        policy.apply_origin_headers(&origin, response.headers_mut());
+        response.headers_mut().insert(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static(super::request_id::REQUEST_ID_HEADER),
+        );
+    }
+    response
 }
 
 #[cfg(test)]
@@ -25,9 +147,40 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_cors_layer_creation() {
-        let _layer = cors_layer();
-        // Layer created successfully
-        assert!(true);
+    fn test_default_policy_allows_any_origin_without_credentials() {
+        let policy = CorsPolicy::default();
+        assert!(policy.is_origin_allowed("https://anything.example"));
+
+        let mut headers = HeaderMap::new();
+        policy.apply_origin_headers(&HeaderValue::from_static("https://anything.example"), &mut headers);
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+    }
+
+    #[test]
+    fn test_explicit_origins_with_credentials() {
+        let policy = CorsPolicy::from_config(&CorsConfig {
+            allowed_origins: vec!["https://Admin.example.com/".to_string()],
+            allowed_headers: vec!["content-type".to_string(), "authorization".to_string()],
+            allow_credentials: true,
+            max_age_seconds: 600,
+        });
+        assert!(policy.is_origin_allowed("https://admin.example.com"));
+        assert!(!policy.is_origin_allowed("https://evil.example.com"));
+
+        let origin = HeaderValue::from_static("https://admin.example.com");
+        let response = policy.preflight_response(&origin, &HeaderMap::new());
+        let headers = response.headers();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://admin.example.com");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS], "content-type, authorization");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+    }
+
+    #[test]
+    fn test_credentials_ignored_for_wildcard_origin() {
+        let policy = CorsPolicy::from_config(&CorsConfig { allow_credentials: true, ..Default::default() });
+        assert!(!policy.allow_credentials);
     }
 }
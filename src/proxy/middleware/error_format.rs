@@ -0,0 +1,99 @@
+// 错误响应格式统一
+// 协议端点 (/v1*) 上由处理器或中间件返回的空响应体 / 纯文本错误（如 `Err(StatusCode::UNAUTHORIZED)`、
+// `(StatusCode, String)`、请求体解析失败）改写为 OpenAI 错误格式；已是 JSON 或 SSE 的错误响应原样返回
+use axum::{
+    body::to_bytes,
+    extract::Request,
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::proxy::common::error::{default_code, error_response};
+
+/// 读取纯文本错误体的上限，超出时改用状态码描述
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+/// 响应是否已是客户端可解析的格式（JSON / SSE），或带有不能解码的内容编码
+fn is_structured(headers: &HeaderMap) -> bool {
+    if headers.contains_key(header::CONTENT_ENCODING) {
+        return true;
+    }
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.contains("json") || ct.contains("event-stream"))
+}
+
+pub async fn error_format_middleware(request: Request, next: Next) -> Response {
+    let is_protocol_path = request.uri().path().starts_with("/v1");
+    let response = next.run(request).await;
+    let status = response.status();
+    if !is_protocol_path || !(status.is_client_error() || status.is_server_error()) || is_structured(response.headers()) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let text = match to_bytes(body, MAX_ERROR_BODY_BYTES).await {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).trim().to_string(),
+        Err(_) => String::new(),
+    };
+    // 转发的上游错误体可能是 JSON 文本，优先取其中的 error.message
+    let upstream_message = serde_json::from_str::<serde_json::Value>(&text)
+        .ok()
+        .and_then(|v| v["error"]["message"].as_str().map(str::to_string));
+    let message = match upstream_message {
+        Some(message) => message,
+        None if text.is_empty() => status.canonical_reason().unwrap_or("Request failed").to_string(),
+        None => text,
+    };
+
+    // 保留 Retry-After、CORS 等原有响应头，只替换与响应体相关的头
+    let (formatted, body) = error_response(status, default_code(status), message).into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.extend(formatted.headers);
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn body_json(response: Response) -> serde_json::Value {
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_plain_errors_are_formatted() {
+        let app = Router::new()
+            .route("/v1/bare", get(|| async { StatusCode::UNAUTHORIZED }))
+            .route("/v1/text", get(|| async { (StatusCode::SERVICE_UNAVAILABLE, "Token error: no accounts") }))
+            .route("/v1/upstream", get(|| async { (StatusCode::BAD_REQUEST, r#"{"error": {"code": 400, "message": "bad schema"}}"#) }))
+            .route("/v1/json", get(|| async { error_response(StatusCode::FORBIDDEN, "model_not_allowed", "nope") }))
+            .route("/api/bare", get(|| async { StatusCode::UNAUTHORIZED }))
+            .layer(axum::middleware::from_fn(error_format_middleware));
+        let call = |path: &str| app.clone().oneshot(Request::get(path).body(Body::empty()).unwrap());
+
+        let response = call("/v1/bare").await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["type"], "authentication_error");
+        assert_eq!(body["error"]["code"], "invalid_api_key");
+        assert_eq!(body["error"]["message"], "Unauthorized");
+
+        let body = body_json(call("/v1/text").await.unwrap()).await;
+        assert_eq!(body["error"]["type"], "server_error");
+        assert_eq!(body["error"]["message"], "Token error: no accounts");
+
+        let body = body_json(call("/v1/upstream").await.unwrap()).await;
+        assert_eq!(body["error"]["message"], "bad schema");
+        assert_eq!(body["error"]["code"], "invalid_request");
+
+        assert_eq!(body_json(call("/v1/json").await.unwrap()).await["error"]["code"], "model_not_allowed");
+
+        let response = call("/api/bare").await.unwrap();
+        assert!(to_bytes(response.into_body(), usize::MAX).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+// 维护模式
+// 手动暂停 (/api/proxy/pause) 或处于配置的维护窗口内时，模型请求（/v1、/v1beta 与 Ollama 端点）
+// 返回 503 维护响应；管理接口与 Web 控制台不受影响
+use axum::{
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::RwLock;
+
+use crate::proxy::common::error::error_body;
+use crate::proxy::config::{MaintenanceConfig, MaintenanceWindow};
+
+const DEFAULT_MESSAGE: &str = "The proxy is paused for maintenance";
+
+pub static MAINTENANCE: Lazy<Maintenance> = Lazy::new(Maintenance::default);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Window {
+    start: i64,
+    end: i64,
+}
+
+fn parse_time(value: &str) -> Result<i64, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.timestamp())
+        .map_err(|e| format!("invalid time '{}': {}", value, e))
+}
+
+fn parse_window(window: &MaintenanceWindow) -> Result<Window, String> {
+    let (start, end) = (parse_time(&window.start)?, parse_time(&window.end)?);
+    if end <= start {
+        return Err(format!("window {} - {} ends before it starts", window.start, window.end));
+    }
+    Ok(Window { start, end })
+}
+
+pub fn validate(config: &MaintenanceConfig) -> Result<(), String> {
+    config.windows.iter().try_for_each(|w| parse_window(w).map(|_| ()))
+}
+
+/// 手动暂停
+#[derive(Debug, Clone)]
+struct Pause {
+    since: i64,
+    until: Option<i64>,
+    reason: Option<String>,
+}
+
+/// 当前生效的维护状态
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ActiveMaintenance {
+    /// `manual` 或 `scheduled`
+    pub source: &'static str,
+    pub message: String,
+    pub since: i64,
+    pub until: Option<i64>,
+}
+
+#[derive(Default)]
+struct State {
+    windows: Vec<(Window, MaintenanceWindow)>,
+    pause: Option<Pause>,
+    /// 手动恢复的时间；包含该时间的计划窗口不再生效
+    resumed_at: Option<i64>,
+}
+
+#[derive(Default)]
+pub struct Maintenance {
+    state: RwLock<State>,
+}
+
+impl Maintenance {
+    /// 启动 / 热重载时写入计划窗口（调用前应已通过 validate）
+    pub fn configure(&self, config: &MaintenanceConfig) {
+        let windows = config
+            .windows
+            .iter()
+            .filter_map(|w| parse_window(w).ok().map(|parsed| (parsed, w.clone())))
+            .collect();
+        self.state.write().unwrap().windows = windows;
+    }
+
+    pub fn pause(&self, reason: Option<String>, duration_seconds: Option<u64>) -> ActiveMaintenance {
+        let now = Utc::now().timestamp();
+        let until = duration_seconds.map(|secs| now.saturating_add(secs as i64));
+        self.state.write().unwrap().pause = Some(Pause { since: now, until, reason });
+        self.active_at(now).expect("manual pause was just set")
+    }
+
+    /// 结束手动暂停，同时跳过当前所在的计划维护窗口
+    pub fn resume(&self) {
+        let mut state = self.state.write().unwrap();
+        state.pause = None;
+        state.resumed_at = Some(Utc::now().timestamp());
+    }
+
+    pub fn active(&self) -> Option<ActiveMaintenance> {
+        self.active_at(Utc::now().timestamp())
+    }
+
+    fn active_at(&self, now: i64) -> Option<ActiveMaintenance> {
+        let state = self.state.read().unwrap();
+        if let Some(pause) = state.pause.as_ref().filter(|p| p.until.is_none_or(|until| now < until)) {
+            return Some(ActiveMaintenance {
+                source: "manual",
+                message: pause.reason.clone().unwrap_or_else(|| DEFAULT_MESSAGE.to_string()),
+                since: pause.since,
+                until: pause.until,
+            });
+        }
+        let resumed = |w: &Window| state.resumed_at.is_some_and(|t| w.start <= t && t < w.end);
+        state
+            .windows
+            .iter()
+            .find(|(w, _)| w.start <= now && now < w.end && !resumed(w))
+            .map(|(w, raw)| ActiveMaintenance {
+                source: "scheduled",
+                message: raw.message.clone().unwrap_or_else(|| DEFAULT_MESSAGE.to_string()),
+                since: w.start,
+                until: Some(w.end),
+            })
+    }
+
+    /// 管理接口返回的维护状态
+    pub fn status(&self) -> Value {
+        let now = Utc::now().timestamp();
+        let upcoming: Vec<MaintenanceWindow> = {
+            let state = self.state.read().unwrap();
+            state.windows.iter().filter(|(w, _)| now < w.end).map(|(_, raw)| raw.clone()).collect()
+        };
+        json!({
+            "active": self.active_at(now),
+            "upcoming_windows": upcoming,
+        })
+    }
+}
+
+/// 受维护模式影响的路径：模型请求端点，不含管理接口、Web 控制台与探针
+pub fn is_proxy_path(path: &str) -> bool {
+    path.starts_with("/v1/") || path.starts_with("/v1beta/") || crate::proxy::handlers::ollama::is_ollama_path(path)
+}
+
+fn maintenance_response(active: &ActiveMaintenance) -> Response {
+    let mut body = error_body(StatusCode::SERVICE_UNAVAILABLE, "maintenance", active.message.clone());
+    body["error"]["maintenance"] = json!({
+        "source": active.source,
+        "since": active.since,
+        "until": active.until,
+    });
+    let mut response = (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response();
+    if let Some(until) = active.until {
+        let retry_after = (until - Utc::now().timestamp()).max(1);
+        if let Ok(value) = header::HeaderValue::from_str(&retry_after.to_string()) {
+            response.headers_mut().insert(header::RETRY_AFTER, value);
+        }
+    }
+    response
+}
+
+pub async fn maintenance_middleware(request: Request, next: Next) -> Response {
+    if is_proxy_path(request.uri().path()) {
+        if let Some(active) = MAINTENANCE.active() {
+            return maintenance_response(&active);
+        }
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(start: &str, end: &str) -> MaintenanceWindow {
+        MaintenanceWindow { start: start.to_string(), end: end.to_string(), message: Some("upgrade".to_string()) }
+    }
+
+    #[test]
+    fn test_maintenance_windows_and_pause() {
+        let config = MaintenanceConfig {
+            windows: vec![window("2026-01-01T02:00:00Z", "2026-01-01T03:00:00+00:00")],
+        };
+        assert!(validate(&config).is_ok());
+        assert!(validate(&MaintenanceConfig { windows: vec![window("2026-01-01T03:00:00Z", "2026-01-01T02:00:00Z")] }).is_err());
+        assert!(validate(&MaintenanceConfig { windows: vec![window("tomorrow", "2026-01-01T02:00:00Z")] }).is_err());
+
+        let maintenance = Maintenance::default();
+        maintenance.configure(&config);
+        let start = parse_time("2026-01-01T02:00:00Z").unwrap();
+        assert_eq!(maintenance.active_at(start - 1), None);
+        let active = maintenance.active_at(start + 60).unwrap();
+        assert_eq!((active.source, active.message.as_str(), active.until), ("scheduled", "upgrade", Some(start + 3600)));
+        assert_eq!(maintenance.active_at(start + 3600), None);
+
+        // 手动暂停优先于计划窗口，到期自动结束
+        maintenance.state.write().unwrap().pause = Some(Pause { since: start, until: Some(start + 120), reason: None });
+        assert_eq!(maintenance.active_at(start + 60).unwrap().source, "manual");
+        assert_eq!(maintenance.active_at(start + 60).unwrap().message, DEFAULT_MESSAGE);
+        assert_eq!(maintenance.active_at(start + 180).unwrap().source, "scheduled");
+
+        // 在窗口内恢复会跳过该窗口
+        maintenance.state.write().unwrap().pause = None;
+        maintenance.state.write().unwrap().resumed_at = Some(start + 300);
+        assert_eq!(maintenance.active_at(start + 400), None);
+
+        assert!(is_proxy_path("/v1/chat/completions"));
+        assert!(is_proxy_path("/v1beta/models/gemini-2.5-flash:generateContent"));
+        assert!(is_proxy_path("/api/chat"));
+        assert!(!is_proxy_path("/api/proxy/resume"));
+        assert!(!is_proxy_path("/healthz"));
+    }
+}
@@ -1,12 +1,20 @@
 // Middleware 模块 - Axum 中间件
 
 pub mod auth;
+pub mod base_path;
+pub mod body_limit;
+pub mod compression;
 pub mod cors;
+pub mod error_format;
 pub mod logging;
+pub mod maintenance;
 pub mod monitor;
+pub mod otel;
+pub mod request_id;
+pub mod response_cache;
 pub mod web_auth;
 
 pub use auth::auth_middleware;
 pub use auth::AuthenticatedKey;
-pub use cors::cors_layer;
+pub use cors::cors_middleware;
 pub use web_auth::web_auth_middleware;
@@ -6,11 +6,119 @@ use axum::{
 };
 use std::time::Instant;
 use crate::proxy::server::AppState;
-use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::monitor::{parse_tags, ProxyRequestLog, ReplayOf, CLIENT_CANCELLED, TAGS_HEADER};
 use crate::proxy::middleware::AuthenticatedKey;
+use crate::proxy::metrics::METRICS;
+use crate::proxy::pricing::PRICING;
+use crate::modules::alerts::ALERTS;
+use crate::proxy::common::usage::{ResponseUsage, SseUsageParser};
 use serde_json::Value;
 use futures::StreamExt;
 
+/// 请求日志记录的请求体上限，更大的请求体照常转发但不记录
+const MAX_CAPTURED_BODY: usize = 1024 * 1024;
+
+/// 读取请求体用于记录：不超过 `cap` 时完整缓冲；超出后停止缓冲，已读部分与剩余部分按流继续转发
+async fn capture_request_body(body: Body, cap: usize) -> (Option<bytes::Bytes>, Body) {
+    let mut stream = body.into_data_stream();
+    let mut chunks = Vec::new();
+    let mut size = 0;
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(chunk) => {
+                size += chunk.len();
+                chunks.push(chunk);
+                if size > cap {
+                    let head = futures::stream::iter(chunks.into_iter().map(Ok));
+                    return (None, Body::from_stream(head.chain(stream)));
+                }
+            }
+            Err(e) => {
+                // 读取失败（如超出 max_request_body_bytes）同样交给下游处理，不吞掉错误
+                let head = futures::stream::iter(chunks.into_iter().map(Ok));
+                return (None, Body::from_stream(head.chain(futures::stream::once(async move { Err(e) }))));
+            }
+        }
+    }
+    let bytes = bytes::Bytes::from(chunks.concat());
+    (Some(bytes.clone()), Body::from(bytes))
+}
+
+/// 解码后的响应体上限，防止压缩炸弹
+const MAX_DECODED_BODY: usize = 4 * 1024 * 1024;
+
+/// 响应的 Content-Encoding（`identity` 视为未编码）
+///
+/// 编码过的 JSON / 文本响应体解码后再记录与提取用量，客户端收到的仍是原始字节
+fn content_encoding(headers: &axum::http::HeaderMap) -> Option<String> {
+    headers
+        .get(axum::http::header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .filter(|v| !v.is_empty() && v != "identity")
+}
+
+/// 用于记录与提取用量的响应体：按 Content-Encoding 解码（gzip / deflate）
+fn decoded_body(encoding: Option<&str>, bytes: &bytes::Bytes) -> Result<bytes::Bytes, String> {
+    match encoding {
+        None => Ok(bytes.clone()),
+        Some(encoding) => crate::modules::gzip::decode(encoding, bytes, MAX_DECODED_BODY).map(bytes::Bytes::from),
+    }
+}
+
+/// 请求的模型：Gemini 原生路径中的模型名，或请求体的 `model` 字段
+fn requested_model(path: &str, body: Option<&[u8]>) -> Option<String> {
+    if let Some(rest) = path.strip_prefix("/v1beta/models/") {
+        return rest.split(':').next().filter(|m| !m.is_empty()).map(String::from);
+    }
+    serde_json::from_slice::<Value>(body?)
+        .ok()
+        .and_then(|v| v.get("model").and_then(|m| m.as_str()).map(String::from))
+}
+
+/// 记录 API Key 用量（数据库统计 + Prometheus 指标），按请求模型估算费用
+fn record_key_usage(
+    auth_key: &AuthenticatedKey,
+    model: Option<&str>,
+    success: bool,
+    input_tokens: Option<u32>,
+    output_tokens: Option<u32>,
+) {
+    METRICS.record_key_tokens(&auth_key.key_name, input_tokens, output_tokens);
+    let cost = if success { PRICING.estimate(model, input_tokens, output_tokens) } else { None };
+    crate::modules::usage_buffer::USAGE_BUFFER.record(&auth_key.key_id, success, input_tokens, output_tokens, cost);
+}
+
+/// 转发 SSE 流并逐块提取用量，返回客户端是否中途断开
+///
+/// 客户端断开时接收端被丢弃，立即停止读取并释放上游流，从而中止上游请求，不再继续消耗配额
+async fn forward_sse(
+    mut stream: axum::body::BodyDataStream,
+    tx: tokio::sync::mpsc::Sender<Result<bytes::Bytes, axum::Error>>,
+    parser: &mut SseUsageParser,
+) -> bool {
+    loop {
+        let chunk_res = tokio::select! {
+            _ = tx.closed() => return true,
+            next = stream.next() => match next {
+                Some(chunk_res) => chunk_res,
+                None => return false,
+            },
+        };
+        match chunk_res {
+            Ok(chunk) => {
+                parser.feed(&chunk);
+                if tx.send(Ok(chunk)).await.is_err() {
+                    return true;
+                }
+            }
+            Err(e) => {
+                let _ = tx.send(Err(axum::Error::new(e))).await;
+            }
+        }
+    }
+}
+
 pub async fn monitor_middleware(
     State(state): State<AppState>,
     request: Request,
@@ -21,6 +129,37 @@ pub async fn monitor_middleware(
 
     // Check if this is an API path that needs tracking
     let uri = request.uri().to_string();
+
+    // WebSocket 握手不计入统计，连接内的每条消息会单独经过本中间件
+    if uri.starts_with("/v1/realtime") {
+        return next.run(request).await;
+    }
+
+    // Ollama 兼容端点由处理器转发为内部 chat completions 请求，在内部路由中单独统计
+    if crate::proxy::handlers::ollama::is_forwarded_path(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    // 文件上传 / 管理不是模型请求，也不能缓冲记录上传的文件内容
+    if request.uri().path().starts_with(crate::proxy::handlers::files::FILES_PATH) {
+        return next.run(request).await;
+    }
+
+    // 批处理管理请求不是模型请求，其中的每条请求在内部路由中单独统计
+    if request.uri().path().starts_with(crate::proxy::handlers::batches::BATCHES_PATH) {
+        return next.run(request).await;
+    }
+
+    // 用量查询本身不计入 Key 的请求统计
+    if request.uri().path() == "/v1/usage" {
+        return next.run(request).await;
+    }
+
+    // 日志 / 审计导出是大体积流式响应，实时日志是长连接，都不能被缓冲解析
+    if matches!(request.uri().path(), "/api/logs/export" | "/api/logs/stream" | "/api/audit/export") {
+        return next.run(request).await;
+    }
+
     let is_api_request = uri.starts_with("/v1/") && !uri.contains("event_logging");
 
     // Debug log: check if AuthenticatedKey exists
@@ -41,15 +180,33 @@ pub async fn monitor_middleware(
     // So we always need to parse the response for token info when we have an authenticated API key
     let need_token_tracking = is_api_request && authenticated_key.is_some();
 
+    let start = Instant::now();
+
     if !state.monitor.is_enabled() && !need_token_tracking {
         // Monitor disabled and no API key tracking needed - just pass through
-        return next.run(request).await;
+        let response = next.run(request).await;
+        if is_api_request {
+            METRICS.record_request(response.status().as_u16(), start.elapsed().as_millis() as u64);
+            ALERTS.record_request(response.status().as_u16());
+        }
+        return response;
     }
 
     if !state.monitor.is_enabled() && need_token_tracking {
         // Monitor disabled but we need to track API key usage
         // We need to parse the response to extract token info
+        // 请求体用于按模型估算费用，以及上游流未返回用量时估算输入 token
+        let (lite_body, request) = if request.method() == axum::http::Method::POST {
+            let (parts, body) = request.into_parts();
+            let (captured, body) = capture_request_body(body, MAX_CAPTURED_BODY).await;
+            (captured, Request::from_parts(parts, body))
+        } else {
+            (None, request)
+        };
+        let lite_model = requested_model(request.uri().path(), lite_body.as_deref());
         let response = next.run(request).await;
+        METRICS.record_request(response.status().as_u16(), start.elapsed().as_millis() as u64);
+        ALERTS.record_request(response.status().as_u16());
         let auth_key = authenticated_key.unwrap(); // Safe because need_token_tracking requires it
         let success = response.status().is_success();
         let status = response.status().as_u16();
@@ -58,67 +215,46 @@ pub async fn monitor_middleware(
             .and_then(|v| v.to_str().ok())
             .unwrap_or("")
             .to_string();
+        let encoding = content_encoding(response.headers());
 
-        if content_type.contains("text/event-stream") {
+        if encoding.is_some() && content_type.contains("text/event-stream") {
+            // Compressed stream - cannot be scanned chunk by chunk, estimate from the request body
+            let usage = if success { ResponseUsage::default().fill_estimates(lite_body.as_deref()) } else { ResponseUsage::default() };
+            record_key_usage(&auth_key, lite_model.as_deref(), success, usage.input_tokens, usage.output_tokens);
+            return response;
+        } else if content_type.contains("text/event-stream") {
             // For streaming responses, we need to buffer and scan for usage
             let (parts, body) = response.into_parts();
-            let mut stream = body.into_data_stream();
+            let stream = body.into_data_stream();
             let (tx, rx) = tokio::sync::mpsc::channel(64);
 
             let auth_key_clone = auth_key.clone();
 
-            tokio::spawn(async move {
-                let mut last_few_bytes = Vec::new();
-                const TAIL_BUFFER_SIZE: usize = 16384;
-
-                while let Some(chunk_res) = stream.next().await {
-                    if let Ok(chunk) = chunk_res {
-                        last_few_bytes.extend_from_slice(&chunk);
-                        if last_few_bytes.len() > TAIL_BUFFER_SIZE {
-                            let drain_count = last_few_bytes.len() - TAIL_BUFFER_SIZE;
-                            last_few_bytes.drain(0..drain_count);
-                        }
-                        let _ = tx.send(Ok::<_, axum::Error>(chunk)).await;
-                    } else if let Err(e) = chunk_res {
-                        let _ = tx.send(Err(axum::Error::new(e))).await;
-                    }
+            crate::proxy::shutdown::spawn_tracked(async move {
+                let mut parser = SseUsageParser::new();
+                if forward_sse(stream, tx, &mut parser).await {
+                    tracing::info!("[Monitor-Lite-SSE] Client disconnected, upstream stream aborted");
                 }
 
-                // Extract token info from stream tail
-                let mut input_tokens = None;
-                let mut output_tokens = None;
-
-                if let Ok(full_tail) = std::str::from_utf8(&last_few_bytes) {
-                    for line in full_tail.lines().rev() {
-                        if line.starts_with("data: ") && line.contains("\"usage\"") {
-                            let json_str = line.trim_start_matches("data: ").trim();
-                            if let Ok(json) = serde_json::from_str::<Value>(json_str) {
-                                if let Some(usage) = json.get("usage") {
-                                    input_tokens = usage.get("prompt_tokens").or(usage.get("input_tokens")).and_then(|v| v.as_u64()).map(|v| v as u32);
-                                    output_tokens = usage.get("completion_tokens").or(usage.get("output_tokens")).and_then(|v| v.as_u64()).map(|v| v as u32);
-                                    tracing::debug!(
-                                        "[Monitor-Lite] Extracted tokens from SSE: input={:?}, output={:?}",
-                                        input_tokens,
-                                        output_tokens
-                                    );
-                                    break;
-                                }
-                            }
-                        }
-                    }
+                let stream_success = status < 400;
+                let mut usage = parser.finish();
+                if stream_success {
+                    usage = usage.fill_estimates(lite_body.as_deref());
                 }
+                let ResponseUsage { input_tokens, output_tokens, estimated, .. } = usage;
 
                 // Record API key usage
-                let stream_success = status < 400;
                 tracing::info!(
-                    "[Monitor-Lite-SSE] Recording API key usage: key={}..., success={}, input={:?}, output={:?}",
+                    "[Monitor-Lite-SSE] Recording API key usage: key={}..., success={}, input={:?}, output={:?}, estimated={}",
                     &auth_key_clone.key.chars().take(12).collect::<String>(),
                     stream_success,
                     input_tokens,
-                    output_tokens
+                    output_tokens,
+                    estimated
                 );
-                let _ = crate::modules::api_keys::record_usage(
-                    &auth_key_clone.key,
+                record_key_usage(
+                    &auth_key_clone,
+                    lite_model.as_deref(),
                     stream_success,
                     input_tokens,
                     output_tokens,
@@ -131,17 +267,15 @@ pub async fn monitor_middleware(
             let (parts, body) = response.into_parts();
             match axum::body::to_bytes(body, 512 * 1024).await {
                 Ok(bytes) => {
-                    let mut input_tokens = None;
-                    let mut output_tokens = None;
-
-                    if let Ok(s) = std::str::from_utf8(&bytes) {
-                        if let Ok(json) = serde_json::from_str::<Value>(&s) {
-                            if let Some(usage) = json.get("usage") {
-                                input_tokens = usage.get("prompt_tokens").or(usage.get("input_tokens")).and_then(|v| v.as_u64()).map(|v| v as u32);
-                                output_tokens = usage.get("completion_tokens").or(usage.get("output_tokens")).and_then(|v| v.as_u64()).map(|v| v as u32);
-                            }
+                    let ResponseUsage { input_tokens, output_tokens, .. } = match decoded_body(encoding.as_deref(), &bytes) {
+                        Ok(decoded) => serde_json::from_slice::<Value>(&decoded)
+                            .map(|json| ResponseUsage::from_json(&json))
+                            .unwrap_or_default(),
+                        Err(e) => {
+                            tracing::debug!("[Monitor-Lite-JSON] Failed to decode response body: {}", e);
+                            if success { ResponseUsage::default().fill_estimates(lite_body.as_deref()) } else { ResponseUsage::default() }
                         }
-                    }
+                    };
 
                     tracing::info!(
                         "[Monitor-Lite-JSON] Recording API key usage: key={}..., success={}, input={:?}, output={:?}",
@@ -150,12 +284,12 @@ pub async fn monitor_middleware(
                         input_tokens,
                         output_tokens
                     );
-                    let _ = crate::modules::api_keys::record_usage(&auth_key.key, success, input_tokens, output_tokens);
+                    record_key_usage(&auth_key, lite_model.as_deref(), success, input_tokens, output_tokens);
 
                     return Response::from_parts(parts, Body::from(bytes));
                 }
                 Err(_) => {
-                    let _ = crate::modules::api_keys::record_usage(&auth_key.key, false, None, None);
+                    record_key_usage(&auth_key, lite_model.as_deref(), false, None, None);
                     return Response::from_parts(parts, Body::empty());
                 }
             }
@@ -166,13 +300,19 @@ pub async fn monitor_middleware(
                 &auth_key.key.chars().take(12).collect::<String>(),
                 success
             );
-            let _ = crate::modules::api_keys::record_usage(&auth_key.key, success, None, None);
+            record_key_usage(&auth_key, lite_model.as_deref(), success, None, None);
             return response;
         }
     }
 
-    let start = Instant::now();
     let method = request.method().to_string();
+    let replay = request.extensions().get::<ReplayOf>().cloned();
+    let client_ip = super::auth::request_client_ip(&request);
+    let tags = request
+        .headers()
+        .get(TAGS_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_tags);
 
     if uri.contains("event_logging") {
         return next.run(request).await;
@@ -189,9 +329,15 @@ pub async fn monitor_middleware(
 
     let request_body_str;
     let request = if method == "POST" {
+        let declared = crate::proxy::middleware::body_limit::content_length(&request);
         let (parts, body) = request.into_parts();
-        match axum::body::to_bytes(body, 1024 * 1024).await {
-            Ok(bytes) => {
+        let (captured, body) = match declared {
+            // 超出记录上限的请求体直接按流转发，不做缓冲
+            Some(length) if length > MAX_CAPTURED_BODY as u64 => (None, body),
+            _ => capture_request_body(body, MAX_CAPTURED_BODY).await,
+        };
+        match captured {
+            Some(bytes) => {
                 if model.is_none() {
                     model = serde_json::from_slice::<Value>(&bytes).ok().and_then(|v|
                         v.get("model").and_then(|m| m.as_str()).map(|s| s.to_string())
@@ -202,31 +348,35 @@ pub async fn monitor_middleware(
                 } else {
                     Some("[Binary Request Data]".to_string())
                 };
-                Request::from_parts(parts, Body::from(bytes))
             }
-            Err(_) => {
-                request_body_str = None;
-                Request::from_parts(parts, Body::empty())
+            None => {
+                request_body_str = Some(format!("[Request body not captured: larger than {} bytes]", MAX_CAPTURED_BODY));
             }
         }
+        Request::from_parts(parts, body)
     } else {
         request_body_str = None;
         request
     };
 
-    let response = next.run(request).await;
+    let (response, account_email) = crate::proxy::account_stats::track_served_account(next.run(request)).await;
 
     let duration = start.elapsed().as_millis() as u64;
     let status = response.status().as_u16();
+    if is_api_request {
+        METRICS.record_request(status, duration);
+        ALERTS.record_request(status);
+    }
 
     let content_type = response.headers().get("content-type")
         .and_then(|v| v.to_str().ok())
         .unwrap_or("")
         .to_string();
+    let encoding = content_encoding(response.headers());
 
     let monitor = state.monitor.clone();
     let mut log = ProxyRequestLog {
-        id: uuid::Uuid::new_v4().to_string(),
+        id: replay.as_ref().map(|r| r.log_id.clone()).unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
         timestamp: chrono::Utc::now().timestamp_millis(),
         method,
         url: uri,
@@ -238,73 +388,50 @@ pub async fn monitor_middleware(
         response_body: None,
         input_tokens: None,
         output_tokens: None,
+        key_id: authenticated_key.as_ref().map(|k| k.key_id.clone()),
+        account_email,
+        estimated_cost: None,
+        finish_reason: None,
+        replay_of: replay.map(|r| r.original_id),
+        tags,
+        client_ip: client_ip.map(|ip| ip.to_string()),
+        request_id: super::request_id::current_request_id(),
+        usage_estimated: false,
+        request_body_modified: false,
+        sample_weight: None,
     };
 
-    if content_type.contains("text/event-stream") {
+    if encoding.is_none() && content_type.contains("text/event-stream") {
         log.response_body = Some("[Stream Data]".to_string());
         let (parts, body) = response.into_parts();
-        let mut stream = body.into_data_stream();
+        let stream = body.into_data_stream();
         let (tx, rx) = tokio::sync::mpsc::channel(64);
 
         // Clone API key info for spawned task
         let auth_key_for_spawn = authenticated_key.clone();
 
-        tokio::spawn(async move {
-            let mut last_few_bytes = Vec::new();
-            const TAIL_BUFFER_SIZE: usize = 16384; // Increase buffer size to capture usage data
-
-            while let Some(chunk_res) = stream.next().await {
-                if let Ok(chunk) = chunk_res {
-                    // Always append to buffer, then trim from front if too large
-                    last_few_bytes.extend_from_slice(&chunk);
-                    if last_few_bytes.len() > TAIL_BUFFER_SIZE {
-                        let drain_count = last_few_bytes.len() - TAIL_BUFFER_SIZE;
-                        last_few_bytes.drain(0..drain_count);
-                    }
-                    let _ = tx.send(Ok::<_, axum::Error>(chunk)).await;
-                } else if let Err(e) = chunk_res {
-                    let _ = tx.send(Err(axum::Error::new(e))).await;
-                }
-            }
+        crate::proxy::shutdown::spawn_tracked(async move {
+            let mut parser = SseUsageParser::new();
+            let client_cancelled = forward_sse(stream, tx, &mut parser).await;
 
-            if let Ok(full_tail) = std::str::from_utf8(&last_few_bytes) {
-                tracing::debug!(
-                    "[Monitor] Scanning SSE tail for usage. Tail length: {} bytes",
-                    full_tail.len()
-                );
-                let mut found_usage = false;
-                for line in full_tail.lines().rev() {
-                    if line.starts_with("data: ") && line.contains("\"usage\"") {
-                        let json_str = line.trim_start_matches("data: ").trim();
-                        tracing::debug!("[Monitor] Found data line with usage: {}", &json_str[..json_str.len().min(200)]);
-                        if let Ok(json) = serde_json::from_str::<Value>(json_str) {
-                            if let Some(usage) = json.get("usage") {
-                                tracing::debug!(
-                                    "[Monitor] Found usage in SSE stream: {:?}",
-                                    usage
-                                );
-                                log.input_tokens = usage.get("prompt_tokens").or(usage.get("input_tokens")).and_then(|v| v.as_u64()).map(|v| v as u32);
-                                log.output_tokens = usage.get("completion_tokens").or(usage.get("output_tokens")).and_then(|v| v.as_u64()).map(|v| v as u32);
-                                if log.input_tokens.is_none() && log.output_tokens.is_none() {
-                                    log.output_tokens = usage.get("total_tokens").and_then(|v| v.as_u64()).map(|v| v as u32);
-                                }
-                                tracing::debug!(
-                                    "[Monitor] Extracted tokens: input={:?}, output={:?}",
-                                    log.input_tokens,
-                                    log.output_tokens
-                                );
-                                found_usage = true;
-                                break;
-                            }
-                        } else {
-                            tracing::warn!("[Monitor] Failed to parse JSON from data line");
-                        }
-                    }
-                }
-                if !found_usage {
-                    tracing::debug!("[Monitor] No usage found in SSE tail. Last 500 chars: {}",
-                        &full_tail[full_tail.len().saturating_sub(500)..]);
-                }
+            let mut usage = parser.finish();
+            if log.status < 400 {
+                usage = usage.fill_estimates(log.request_body.as_deref().map(str::as_bytes));
+            }
+            tracing::debug!(
+                "[Monitor] SSE usage: input={:?}, output={:?}, finish_reason={:?}, estimated={}",
+                usage.input_tokens,
+                usage.output_tokens,
+                usage.finish_reason,
+                usage.estimated
+            );
+            log.input_tokens = usage.input_tokens;
+            log.output_tokens = usage.output_tokens;
+            log.finish_reason = usage.finish_reason;
+            log.usage_estimated = usage.estimated;
+            if client_cancelled {
+                tracing::info!("[Monitor] Client disconnected from {}, upstream stream aborted", log.url);
+                log.finish_reason = Some(CLIENT_CANCELLED.to_string());
             }
 
             if log.status >= 400 {
@@ -322,8 +449,9 @@ pub async fn monitor_middleware(
                         log.input_tokens,
                         log.output_tokens
                     );
-                    let _ = crate::modules::api_keys::record_usage(
-                        &auth_key.key,
+                    record_key_usage(
+                        &auth_key,
+                        log.model.as_deref(),
                         success,
                         log.input_tokens,
                         log.output_tokens,
@@ -335,23 +463,59 @@ pub async fn monitor_middleware(
         });
 
         Response::from_parts(parts, Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)))
+    } else if encoding.is_some() && content_type.contains("text/event-stream") {
+        // 压缩的流无法逐块解析，只透传，按请求体估算用量
+        log.response_body = Some(format!("[Stream Data, Content-Encoding: {}]", encoding.as_deref().unwrap_or_default()));
+        if log.status < 400 {
+            let usage = ResponseUsage::default().fill_estimates(log.request_body.as_deref().map(str::as_bytes));
+            log.input_tokens = usage.input_tokens;
+            log.output_tokens = usage.output_tokens;
+            log.usage_estimated = usage.estimated;
+        } else {
+            log.error = Some("Stream Error or Failed".to_string());
+        }
+
+        if is_api_request {
+            if let Some(auth_key) = authenticated_key {
+                record_key_usage(&auth_key, log.model.as_deref(), log.status < 400, log.input_tokens, log.output_tokens);
+            }
+        }
+
+        monitor.log_request(log).await;
+        response
     } else if content_type.contains("application/json") || content_type.contains("text/") {
         let (parts, body) = response.into_parts();
         match axum::body::to_bytes(body, 512 * 1024).await {
             Ok(bytes) => {
-                if let Ok(s) = std::str::from_utf8(&bytes) {
-                    if let Ok(json) = serde_json::from_str::<Value>(&s) {
-                        if let Some(usage) = json.get("usage") {
-                            log.input_tokens = usage.get("prompt_tokens").or(usage.get("input_tokens")).and_then(|v| v.as_u64()).map(|v| v as u32);
-                            log.output_tokens = usage.get("completion_tokens").or(usage.get("output_tokens")).and_then(|v| v.as_u64()).map(|v| v as u32);
-                            if log.input_tokens.is_none() && log.output_tokens.is_none() {
-                                log.output_tokens = usage.get("total_tokens").and_then(|v| v.as_u64()).map(|v| v as u32);
+                match decoded_body(encoding.as_deref(), &bytes) {
+                    Ok(decoded) => {
+                        if let Ok(s) = std::str::from_utf8(&decoded) {
+                            if let Ok(json) = serde_json::from_str::<Value>(s) {
+                                let usage = ResponseUsage::from_json(&json);
+                                log.input_tokens = usage.input_tokens;
+                                log.output_tokens = usage.output_tokens;
+                                log.finish_reason = usage.finish_reason;
                             }
+                            log.response_body = Some(s.to_string());
+                        } else {
+                            log.response_body = Some("[Binary Response Data]".to_string());
+                        }
+                    }
+                    Err(e) => {
+                        // 无法解码的压缩响应（如 br）：按请求体估算用量
+                        log.response_body = Some(format!(
+                            "[{} response, Content-Encoding: {}: {}]",
+                            content_type,
+                            encoding.as_deref().unwrap_or_default(),
+                            e
+                        ));
+                        if log.status < 400 {
+                            let usage = ResponseUsage::default().fill_estimates(log.request_body.as_deref().map(str::as_bytes));
+                            log.input_tokens = usage.input_tokens;
+                            log.output_tokens = usage.output_tokens;
+                            log.usage_estimated = usage.estimated;
                         }
                     }
-                    log.response_body = Some(s.to_string());
-                } else {
-                    log.response_body = Some("[Binary Response Data]".to_string());
                 }
 
                 if log.status >= 400 {
@@ -369,8 +533,9 @@ pub async fn monitor_middleware(
                             log.input_tokens,
                             log.output_tokens
                         );
-                        let _ = crate::modules::api_keys::record_usage(
-                            &auth_key.key,
+                        record_key_usage(
+                            &auth_key,
+                            log.model.as_deref(),
                             success,
                             log.input_tokens,
                             log.output_tokens,
@@ -387,7 +552,7 @@ pub async fn monitor_middleware(
                 // Record API key usage stats (failure case)
                 if is_api_request {
                     if let Some(auth_key) = authenticated_key.clone() {
-                        let _ = crate::modules::api_keys::record_usage(&auth_key.key, false, None, None);
+                        record_key_usage(&auth_key, log.model.as_deref(), false, None, None);
                     }
                 }
 
@@ -396,13 +561,16 @@ pub async fn monitor_middleware(
             }
         }
     } else {
-        log.response_body = Some(format!("[{}]", content_type));
+        log.response_body = Some(match &encoding {
+            Some(encoding) => format!("[{} response, Content-Encoding: {}]", content_type, encoding),
+            None => format!("[{}]", content_type),
+        });
 
         // Record API key usage stats
         if is_api_request {
             if let Some(auth_key) = authenticated_key {
                 let success = log.status < 400;
-                let _ = crate::modules::api_keys::record_usage(&auth_key.key, success, None, None);
+                record_key_usage(&auth_key, log.model.as_deref(), success, None, None);
             }
         }
 
@@ -410,3 +578,85 @@ pub async fn monitor_middleware(
         response
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// 上游流被释放时置位
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward_sse_aborts_upstream_on_client_disconnect() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let flag = DropFlag(dropped.clone());
+        let upstream = async_stream::stream! {
+            let _flag = flag;
+            yield Ok::<_, std::io::Error>(bytes::Bytes::from_static(b"data: {\"usageMetadata\":{\"promptTokenCount\":7}}\n\n"));
+            // 模拟上游持续生成
+            loop {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                yield Ok(bytes::Bytes::from_static(b"data: {}\n\n"));
+            }
+        };
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let task = tokio::spawn(async move {
+            let mut parser = SseUsageParser::new();
+            let cancelled = forward_sse(Body::from_stream(upstream).into_data_stream(), tx, &mut parser).await;
+            (cancelled, parser.finish())
+        });
+
+        assert!(rx.recv().await.is_some());
+        drop(rx);
+        let (cancelled, usage) = tokio::time::timeout(std::time::Duration::from_secs(1), task).await.unwrap().unwrap();
+        assert!(cancelled);
+        assert_eq!(usage.input_tokens, Some(7));
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_forward_sse_completes_normally() {
+        let upstream = futures::stream::iter(vec![Ok::<_, std::io::Error>(bytes::Bytes::from_static(b"data: [DONE]\n\n"))]);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(4);
+        let mut parser = SseUsageParser::new();
+        assert!(!forward_sse(Body::from_stream(upstream).into_data_stream(), tx, &mut parser).await);
+        assert!(rx.recv().await.is_some());
+    }
+
+    #[test]
+    fn test_content_encoding() {
+        let mut headers = axum::http::HeaderMap::new();
+        assert_eq!(content_encoding(&headers), None);
+        headers.insert(axum::http::header::CONTENT_ENCODING, "identity".parse().unwrap());
+        assert_eq!(content_encoding(&headers), None);
+        headers.insert(axum::http::header::CONTENT_ENCODING, "GZIP".parse().unwrap());
+        assert_eq!(content_encoding(&headers).as_deref(), Some("gzip"));
+
+        let json = br#"{"usageMetadata":{"promptTokenCount":3,"candidatesTokenCount":4}}"#;
+        let compressed = bytes::Bytes::from(crate::modules::gzip::compress(json));
+        assert_eq!(decoded_body(Some("gzip"), &compressed).unwrap(), &json[..]);
+        assert_eq!(decoded_body(None, &compressed).unwrap(), compressed);
+        assert!(decoded_body(Some("br"), &compressed).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_capture_request_body_streams_oversized() {
+        let chunks = || futures::stream::iter(["ab", "cd", "ef"].map(|c| Ok::<_, std::io::Error>(bytes::Bytes::from_static(c.as_bytes()))));
+
+        let (captured, body) = capture_request_body(Body::from_stream(chunks()), 6).await;
+        assert_eq!(captured.as_deref(), Some(&b"abcdef"[..]));
+        assert_eq!(axum::body::to_bytes(body, usize::MAX).await.unwrap(), "abcdef");
+
+        let (captured, body) = capture_request_body(Body::from_stream(chunks()), 3).await;
+        assert!(captured.is_none());
+        assert_eq!(axum::body::to_bytes(body, usize::MAX).await.unwrap(), "abcdef");
+    }
+}
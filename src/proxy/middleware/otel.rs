@@ -0,0 +1,68 @@
+// OpenTelemetry 追踪中间件
+// otel_middleware 位于最外层，为每个请求创建 server span（流式响应在流结束时才结束 span）；
+// handler_span_middleware 作为 route_layer 包裹路由处理函数，记录匹配的路由
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use futures::StreamExt;
+
+use crate::proxy::otel::{self, SpanKind, TRACEPARENT_HEADER, TRACER};
+
+pub async fn otel_middleware(request: Request, next: Next) -> Response {
+    if !TRACER.is_enabled() {
+        return next.run(request).await;
+    }
+
+    let traceparent = request.headers().get(TRACEPARENT_HEADER).and_then(|v| v.to_str().ok());
+    let mut span = TRACER.start_server_span(request.method().as_str(), traceparent);
+    span.set_attribute("http.request.method", request.method().as_str());
+    span.set_attribute("url.path", request.uri().path());
+    if let Some(user_agent) = request.headers().get(header::USER_AGENT).and_then(|v| v.to_str().ok()) {
+        span.set_attribute("user_agent.original", user_agent);
+    }
+
+    let response = span.in_scope(next.run(request)).await;
+    let status = response.status();
+    span.set_attribute("http.response.status_code", status.as_u16());
+    if status.is_server_error() {
+        span.set_error(format!("HTTP {}", status.as_u16()));
+    }
+
+    let is_stream = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.contains("text/event-stream"));
+    if !is_stream {
+        return response;
+    }
+
+    // span 随响应流一起释放：流结束或客户端断开时才结束
+    let (parts, body) = response.into_parts();
+    let stream = body.into_data_stream().map(move |chunk| {
+        let _ = &span;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+pub async fn handler_span_middleware(request: Request, next: Next) -> Response {
+    let route = request.extensions().get::<MatchedPath>().map(|p| p.as_str().to_string());
+    if let Some(route) = &route {
+        otel::record_attribute("http.route", route.as_str());
+    }
+
+    let name = format!("handler {}", route.as_deref().unwrap_or_else(|| request.uri().path()));
+    let Some(mut span) = TRACER.start_child_span(name, SpanKind::Internal) else {
+        return next.run(request).await;
+    };
+    let response = span.in_scope(next.run(request)).await;
+    if response.status().is_server_error() {
+        span.set_error(format!("HTTP {}", response.status().as_u16()));
+    }
+    response
+}
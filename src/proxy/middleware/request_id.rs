@@ -0,0 +1,82 @@
+// 请求 ID
+// 每个请求分配一个 ID（客户端已通过 `x-request-id` 指定时沿用），写入响应头、tracing span 与请求日志，
+// 并随上游请求一起发送，便于在客户端、代理日志与上游之间关联同一次调用
+use axum::{
+    extract::Request,
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+/// 请求 ID 请求头 / 响应头
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// 客户端指定的请求 ID 最大长度
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    /// 当前请求的 ID（request_id_middleware 设置作用域）
+    static CURRENT_REQUEST_ID: String;
+}
+
+/// 当前请求的 ID
+pub fn current_request_id() -> Option<String> {
+    CURRENT_REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// 客户端指定的请求 ID：非空、不超过 128 个可见 ASCII 字符，否则忽略
+fn accept_client_id(raw: &str) -> Option<String> {
+    let id = raw.trim();
+    let valid = !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.chars().all(|c| c.is_ascii_graphic());
+    valid.then(|| id.to_string())
+}
+
+pub async fn request_id_middleware(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(accept_client_id)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    crate::proxy::otel::record_attribute("antiproxy.request_id", request_id.as_str());
+
+    let span = tracing::info_span!("request", id = %request_id);
+    let mut response = CURRENT_REQUEST_ID
+        .scope(request_id.clone(), next.run(request))
+        .instrument(span)
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_request_id_generated_or_honored() {
+        let app = Router::new()
+            .route("/", get(|| async { current_request_id().unwrap_or_default() }))
+            .layer(axum::middleware::from_fn(request_id_middleware));
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/").header(REQUEST_ID_HEADER, "client-42").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "client-42");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "client-42");
+
+        let response = app
+            .oneshot(Request::get("/").header(REQUEST_ID_HEADER, "has space").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let generated = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(generated).is_ok());
+    }
+}
@@ -0,0 +1,80 @@
+// 响应缓存中间件
+// 位于 monitor 中间件内层：命中缓存的请求仍会被记录日志与用量，但不会调用上游
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+
+use crate::proxy::middleware::AuthenticatedKey;
+use crate::proxy::response_cache::{self, RESPONSE_CACHE};
+
+const CACHE_HEADER: &str = "x-cache";
+
+/// 支持缓存的生成类端点
+fn is_cacheable_path(path: &str) -> bool {
+    matches!(path, "/v1/chat/completions" | "/v1/completions" | "/v1/responses" | "/v1/messages")
+        || (path.starts_with("/v1beta/models/") && path.ends_with(":generateContent"))
+}
+
+pub async fn response_cache_middleware(request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    if !RESPONSE_CACHE.is_enabled() || request.method() != Method::POST || !is_cacheable_path(&path) {
+        return next.run(request).await;
+    }
+
+    let key_id = request
+        .extensions()
+        .get::<AuthenticatedKey>()
+        .map(|k| k.key_id.clone())
+        .unwrap_or_default();
+
+    let (parts, body) = request.into_parts();
+    let bytes = match to_bytes(body, super::body_limit::max_request_body()).await {
+        Ok(b) => b,
+        Err(e) => return (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response(),
+    };
+
+    let cache_key = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(json) if response_cache::is_cacheable(&path, &json) => Some(response_cache::cache_key(&key_id, &path, &json)),
+        _ => None,
+    };
+    let request = Request::from_parts(parts, Body::from(bytes));
+    let Some(cache_key) = cache_key else {
+        return next.run(request).await;
+    };
+
+    if let Some(cached) = RESPONSE_CACHE.get(&cache_key) {
+        tracing::debug!("[Cache] Hit for {}", path);
+        let mut response = Response::new(Body::from(cached.body));
+        if let Some(ct) = cached.content_type.and_then(|v| HeaderValue::from_str(&v).ok()) {
+            response.headers_mut().insert(header::CONTENT_TYPE, ct);
+        }
+        response.headers_mut().insert(CACHE_HEADER, HeaderValue::from_static("HIT"));
+        return response;
+    }
+
+    let response = next.run(request).await;
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    if response.status() != StatusCode::OK
+        || content_type.as_deref().is_some_and(|ct| ct.starts_with("text/event-stream"))
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(b) => b,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+    RESPONSE_CACHE.insert(cache_key, content_type, bytes.clone());
+    parts.headers.insert(CACHE_HEADER, HeaderValue::from_static("MISS"));
+    Response::from_parts(parts, Body::from(bytes))
+}
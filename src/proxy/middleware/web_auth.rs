@@ -1,17 +1,20 @@
 //! Web UI authentication middleware
 //!
-//! Protects Web UI routes, requires Passkey authentication to access
+//! Protects Web UI routes, requires Passkey authentication to access,
+//! and enforces the admin role required by each `/api/` route
 
 use axum::{
     extract::{Request, State},
-    http::{header, StatusCode},
+    http::{header, Method, StatusCode},
     middleware::Next,
     response::{IntoResponse, Redirect, Response},
 };
 
+use crate::modules::webauthn::{AdminRole, SessionIdentity, ADMIN_TOKEN_PREFIX};
 use crate::proxy::server::AppState;
 
-const SESSION_COOKIE_NAME: &str = "antiproxy_session";
+/// The only OAuth management route reachable without a session
+pub(crate) const OAUTH_CALLBACK_PATH: &str = "/api/oauth/callback";
 
 /// Path prefixes that need protection
 fn is_protected_path(path: &str) -> bool {
@@ -21,8 +24,17 @@ fn is_protected_path(path: &str) -> bool {
         if path.starts_with("/api/auth/") {
             return false;
         }
-        // OAuth callback doesn't need protection (need to add account before setting up passkey)
-        if path.starts_with("/api/oauth/") {
+        // First-run setup steps taken before any admin login exists
+        if crate::proxy::handlers::setup::is_open_path(path) {
+            return false;
+        }
+        // The OAuth callback completes a flow an owner already started; starting a flow
+        // (prepare / device code) needs an owner session, first-run adds go through the setup wizard
+        if path == OAUTH_CALLBACK_PATH {
+            return false;
+        }
+        // Ollama-compatible protocol endpoints use API key auth
+        if crate::proxy::handlers::ollama::is_ollama_path(path) {
             return false;
         }
         return true;
@@ -43,8 +55,18 @@ fn is_protected_path(path: &str) -> bool {
         return false;
     }
 
-    // Health check
-    if path == "/healthz" {
+    // Liveness / readiness probes
+    if crate::proxy::health::is_probe_path(path) {
+        return false;
+    }
+
+    // Prometheus scrape endpoint (protected by API Key auth instead)
+    if path == "/metrics" {
+        return false;
+    }
+
+    // Cluster peer notices (protected by the shared cluster secret instead)
+    if crate::proxy::cluster::is_peer_path(path) {
         return false;
     }
 
@@ -57,6 +79,38 @@ fn is_protected_path(path: &str) -> bool {
     true
 }
 
+/// Read-only stats routes open to viewers
+const VIEWER_READ_PATHS: [&str; 7] = [
+    "/api/stats/usage",
+    "/api/stats/concurrency",
+    "/api/keys/usage",
+    "/api/models",
+    "/api/upstream/endpoints",
+    "/api/upstream/shadow",
+    "/api/proxy/maintenance",
+];
+
+/// Minimum role for a protected path
+///
+/// Viewers only get the stats routes above. Operators also manage API keys and read logs.
+/// Everything else (accounts, configuration, admin management, the audit log) needs an owner.
+pub(crate) fn required_role(method: &Method, path: &str) -> AdminRole {
+    if !path.starts_with("/api/") {
+        return AdminRole::Viewer;
+    }
+    let read_only = method == Method::GET || method == Method::HEAD;
+    if read_only && VIEWER_READ_PATHS.contains(&path) {
+        return AdminRole::Viewer;
+    }
+    if path == "/api/keys" || path.starts_with("/api/keys/") {
+        return AdminRole::Operator;
+    }
+    if path.starts_with("/api/logs") && read_only {
+        return AdminRole::Operator;
+    }
+    AdminRole::Owner
+}
+
 /// Check if the path is a static asset
 fn is_static_asset(path: &str) -> bool {
     // HTML files are not static assets - they need authentication protection
@@ -79,13 +133,13 @@ fn is_static_asset(path: &str) -> bool {
 }
 
 /// Extract session token from Cookie
-fn extract_session_token(request: &Request) -> Option<String> {
+fn extract_session_token(request: &Request, cookie_name: &str) -> Option<String> {
     let cookie_header = request.headers().get(header::COOKIE)?;
     let cookie_str = cookie_header.to_str().ok()?;
 
     for cookie in cookie_str.split(';') {
         let cookie = cookie.trim();
-        if let Some(value) = cookie.strip_prefix(&format!("{}=", SESSION_COOKIE_NAME)) {
+        if let Some(value) = cookie.strip_prefix(cookie_name).and_then(|c| c.strip_prefix('=')) {
             return Some(value.to_string());
         }
     }
@@ -93,6 +147,79 @@ fn extract_session_token(request: &Request) -> Option<String> {
     None
 }
 
+/// Extract an admin API token from `Authorization: Bearer apt_...`
+pub(crate) fn extract_admin_token(request: &Request) -> Option<String> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .filter(|token| token.starts_with(ADMIN_TOKEN_PREFIX))
+        .map(|token| token.to_string())
+}
+
+fn forbidden_response(required: AdminRole) -> Response {
+    (
+        StatusCode::FORBIDDEN,
+        axum::Json(serde_json::json!({
+            "error": format!("This action requires the {} role", required.as_str())
+        })),
+    )
+        .into_response()
+}
+
+fn unauthorized_response() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [("Content-Type", "application/json")],
+        r#"{"error": "Authentication required"}"#,
+    )
+        .into_response()
+}
+
+/// Current role of a valid session; None when its identity has been removed
+pub(crate) async fn session_role(state: &AppState, token: &str) -> Option<AdminRole> {
+    if !state.session_manager.validate_session(token).await {
+        return None;
+    }
+    let identity = state.session_manager.session_identity(token).await?;
+    state.webauthn_manager.identity_role(&identity).await
+}
+
+/// Audit actor for a valid session: `password`, `passkey:<name>` or `user:<name>` (admin API tokens use `token:<name>`)
+async fn session_actor(state: &AppState, token: &str) -> String {
+    identity_actor(state, state.session_manager.session_identity(token).await).await
+}
+
+/// Audit actor name of a login identity
+pub(crate) async fn identity_actor(state: &AppState, identity: Option<SessionIdentity>) -> String {
+    match identity {
+        Some(SessionIdentity::Passkey(credential_id)) => {
+            let name = state
+                .webauthn_manager
+                .list_credentials()
+                .await
+                .into_iter()
+                .find(|c| c.credential_id == credential_id)
+                .map(|c| c.user_name)
+                .unwrap_or(credential_id);
+            format!("passkey:{}", name)
+        }
+        Some(SessionIdentity::User(name)) => format!("user:{}", name),
+        _ => "password".to_string(),
+    }
+}
+
+/// Runs the request with the audit actor and client IP in scope
+async fn run_as_actor(state: &AppState, actor: String, request: Request, next: Next) -> Response {
+    let client_ip = {
+        let security = state.security.read().await;
+        crate::proxy::middleware::auth::resolve_client_ip(&request, &security).map(|ip| ip.to_string())
+    };
+    crate::modules::audit::with_actor(actor, client_ip, next.run(request)).await
+}
+
 /// Web UI authentication middleware
 pub async fn web_auth_middleware(
     State(state): State<AppState>,
@@ -103,9 +230,55 @@ pub async fn web_auth_middleware(
 
     tracing::debug!("web_auth_middleware: checking path = {}", path);
 
+    // Admin API tokens are only accepted on /api/*; an invalid token is rejected even on open auth routes
+    if path.starts_with("/api/") {
+        if let Some(token) = extract_admin_token(&request) {
+            use crate::proxy::auth_guard::{AUTH_GUARD, UNKNOWN_IP};
+            let client_ip = {
+                let security = state.security.read().await;
+                crate::proxy::middleware::auth::resolve_client_ip(&request, &security)
+                    .map(|ip| ip.to_string())
+                    .unwrap_or_else(|| UNKNOWN_IP.to_string())
+            };
+            if let Err(retry_after) = AUTH_GUARD.check(&client_ip) {
+                tracing::warn!("web_auth_middleware: admin API token from locked out IP {}", client_ip);
+                return crate::proxy::middleware::auth::auth_locked_response(&path, retry_after);
+            }
+            let Some(info) = state.webauthn_manager.verify_admin_token(&token).await else {
+                tracing::warn!("web_auth_middleware: invalid admin API token for {}", path);
+                AUTH_GUARD.record_failure(&client_ip, "admin_token");
+                return unauthorized_response();
+            };
+            AUTH_GUARD.record_success(&client_ip);
+            let required = required_role(request.method(), &path);
+            if info.role < required {
+                tracing::warn!(
+                    "web_auth_middleware: {} {} requires {}, token {} is {}",
+                    request.method(),
+                    path,
+                    required.as_str(),
+                    info.name,
+                    info.role.as_str()
+                );
+                return forbidden_response(required);
+            }
+            return run_as_actor(&state, format!("token:{}", info.name), request, next).await;
+        }
+    }
+
     // Check if protection is needed
     if !is_protected_path(&path) {
         tracing::debug!("web_auth_middleware: path {} is not protected, allowing", path);
+        // Auth APIs (passkey registration, password change...) are still audited, with the session's actor if any
+        if path.starts_with("/api/") {
+            let actor = match extract_session_token(&request, &state.session_manager.config().cookie_name) {
+                Some(token) if session_role(&state, &token).await.is_some() => {
+                    session_actor(&state, &token).await
+                }
+                _ => crate::modules::audit::ANONYMOUS_ACTOR.to_string(),
+            };
+            return run_as_actor(&state, actor, request, next).await;
+        }
         return next.run(request).await;
     }
 
@@ -114,12 +287,24 @@ pub async fn web_auth_middleware(
     let session_manager = &state.session_manager;
 
     // Check session
-    if let Some(token) = extract_session_token(&request) {
-        if session_manager.validate_session(&token).await {
+    if let Some(token) = extract_session_token(&request, &session_manager.config().cookie_name) {
+        if let Some(role) = session_role(&state, &token).await {
             // Session is valid, refresh and continue
             session_manager.refresh_session(&token).await;
-            tracing::debug!("web_auth_middleware: valid session for {}", path);
-            return next.run(request).await;
+            tracing::debug!("web_auth_middleware: valid {} session for {}", role.as_str(), path);
+            let required = required_role(request.method(), &path);
+            if role < required {
+                tracing::warn!(
+                    "web_auth_middleware: {} {} requires {}, session is {}",
+                    request.method(),
+                    path,
+                    required.as_str(),
+                    role.as_str()
+                );
+                return forbidden_response(required);
+            }
+            let actor = session_actor(&state, &token).await;
+            return run_as_actor(&state, actor, request, next).await;
         }
         tracing::debug!("web_auth_middleware: invalid session token for {}", path);
     } else {
@@ -130,14 +315,33 @@ pub async fn web_auth_middleware(
     tracing::info!("web_auth_middleware: unauthenticated access to {}, redirecting to login", path);
     // API requests return 401
     if path.starts_with("/api/") {
-        return (
-            StatusCode::UNAUTHORIZED,
-            [("Content-Type", "application/json")],
-            r#"{"error": "Authentication required"}"#,
-        )
-            .into_response();
+        return unauthorized_response();
     }
 
     // Web pages redirect to login page
-    Redirect::to("/login.html").into_response()
+    Redirect::to(&crate::proxy::middleware::base_path::url("/login.html")).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_role() {
+        assert_eq!(required_role(&Method::GET, "/api/stats/usage"), AdminRole::Viewer);
+        assert_eq!(required_role(&Method::GET, "/api/keys/usage"), AdminRole::Viewer);
+        assert_eq!(required_role(&Method::GET, "/index.html"), AdminRole::Viewer);
+        assert_eq!(required_role(&Method::GET, "/api/keys"), AdminRole::Operator);
+        assert_eq!(required_role(&Method::POST, "/api/keys/k1/regenerate"), AdminRole::Operator);
+        assert_eq!(required_role(&Method::GET, "/api/logs/export"), AdminRole::Operator);
+        assert_eq!(required_role(&Method::PUT, "/api/pricing"), AdminRole::Owner);
+        assert_eq!(required_role(&Method::GET, "/api/audit"), AdminRole::Owner);
+        assert_eq!(required_role(&Method::GET, "/api/admins"), AdminRole::Owner);
+        // Account reads expose upstream credentials, config reads are not stats
+        assert_eq!(required_role(&Method::GET, "/api/accounts"), AdminRole::Owner);
+        assert_eq!(required_role(&Method::GET, "/api/accounts/current"), AdminRole::Owner);
+        assert_eq!(required_role(&Method::GET, "/api/accounts/a1"), AdminRole::Owner);
+        assert_eq!(required_role(&Method::GET, "/api/pricing"), AdminRole::Owner);
+        assert_eq!(required_role(&Method::POST, "/api/stats/usage"), AdminRole::Owner);
+    }
 }
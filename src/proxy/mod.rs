@@ -14,9 +14,30 @@ pub mod middleware;        // Axum 中间件
 pub mod upstream;          // 上游客户端
 pub mod common;            // 公共工具
 pub mod monitor;           // 监控
+pub mod redaction;         // 请求日志脱敏
+pub mod metrics;           // Prometheus 指标
 pub mod rate_limit;        // 限流跟踪
+pub mod account_stats;     // 账号请求结果统计
+pub mod key_rate_limit;    // API Key 限流 (RPM/RPD)
+pub mod auth_guard;        // 认证失败锁定（暴力破解防护）
+pub mod concurrency;       // 上游并发限制与排队
+pub mod model_registry;    // 上游模型列表缓存 (/v1/models)
+pub mod ip_filter;         // IP 访问控制 (CIDR)
 pub mod sticky_config;     // 粘性调度配置
 pub mod session_manager;   // 会话指纹管理
+pub mod reload;            // 配置热重载
+pub mod response_cache;    // 非流式响应缓存
+pub mod files;             // 上传文件存储 (/v1/files)
+pub mod batches;           // 批处理任务存储 (/v1/batches)
+pub mod request_transform; // 按 API Key 改写请求体
+pub mod shutdown;          // 优雅关闭与后台任务排空
+pub mod pricing;           // 按模型计价的费用估算
+pub mod otel;              // OpenTelemetry 追踪导出
+pub mod tls;               // HTTPS 终止
+pub mod acme;              // ACME 证书自动签发
+pub mod listener;          // TCP / Unix socket 监听器
+pub mod cluster;           // 多实例协调（账号分片与冷却同步）
+pub mod health;            // 存活 / 就绪探针
 
 
 pub use config::ProxyConfig;
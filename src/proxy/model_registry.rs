@@ -0,0 +1,231 @@
+// 上游模型注册表
+//
+// 按账号缓存 fetchAvailableModels 返回的模型列表，合并后并入 /v1/models；
+// 缓存写入数据目录下的 models_cache.json，重启后无需等待下次刷新
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::proxy::config::ModelRegistryConfig;
+use crate::proxy::token_manager::TokenManager;
+use crate::proxy::upstream::client::UpstreamClient;
+
+const CACHE_FILE: &str = "models_cache.json";
+/// 定时刷新的检查间隔
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+pub static MODEL_REGISTRY: Lazy<ModelRegistry> = Lazy::new(ModelRegistry::load);
+
+/// 单个账号最近一次获取到的模型列表
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountModels {
+    pub email: String,
+    pub models: Vec<String>,
+    /// 最近一次成功获取的时间 (Unix 秒)
+    pub fetched_at: Option<i64>,
+    /// 最近一次获取失败的原因（失败时保留上次的模型列表）
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Cache {
+    /// 最近一次全量刷新的时间 (Unix 秒)
+    refreshed_at: Option<i64>,
+    accounts: BTreeMap<String, AccountModels>,
+}
+
+/// GET/POST /api/models/refresh 的返回
+#[derive(Debug, Clone, Serialize)]
+pub struct RegistryStatus {
+    pub refreshed_at: Option<i64>,
+    pub refresh_interval_minutes: u64,
+    /// 所有账号模型的并集
+    pub models: Vec<String>,
+    pub accounts: BTreeMap<String, AccountModels>,
+}
+
+pub struct ModelRegistry {
+    cache: Mutex<Cache>,
+    refresh_interval_minutes: AtomicU64,
+    /// 手动刷新与定时刷新串行执行
+    refresh_lock: tokio::sync::Mutex<()>,
+}
+
+/// fetchAvailableModels 响应中的模型名（与配额解析一致，只保留 Gemini / Claude）
+fn parse_model_ids(resp: &Value) -> Vec<String> {
+    let mut models: Vec<String> = resp
+        .get("models")
+        .and_then(|m| m.as_object())
+        .map(|models| {
+            models
+                .keys()
+                .filter(|name| name.contains("gemini") || name.contains("claude"))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+    models.sort();
+    models
+}
+
+impl ModelRegistry {
+    fn new(cache: Cache) -> Self {
+        Self {
+            cache: Mutex::new(cache),
+            refresh_interval_minutes: AtomicU64::new(ModelRegistryConfig::default().refresh_interval_minutes),
+            refresh_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    fn load() -> Self {
+        let cache = crate::modules::account::get_data_dir()
+            .ok()
+            .and_then(|dir| std::fs::read_to_string(dir.join(CACHE_FILE)).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self::new(cache)
+    }
+
+    fn save(&self) {
+        let cache = self.cache.lock().unwrap().clone();
+        let result = crate::modules::account::get_data_dir().and_then(|dir| {
+            let content = serde_json::to_string_pretty(&cache).map_err(|e| e.to_string())?;
+            std::fs::write(dir.join(CACHE_FILE), content).map_err(|e| e.to_string())
+        });
+        if let Err(e) = result {
+            tracing::warn!("保存模型列表缓存失败: {}", e);
+        }
+    }
+
+    pub fn configure(&self, config: &ModelRegistryConfig) {
+        self.refresh_interval_minutes.store(config.refresh_interval_minutes, Ordering::Relaxed);
+    }
+
+    /// 记录一个账号的 fetchAvailableModels 结果
+    pub fn record(&self, account_id: &str, email: &str, result: Result<&Value, &str>) {
+        let mut cache = self.cache.lock().unwrap();
+        let entry = cache.accounts.entry(account_id.to_string()).or_default();
+        entry.email = email.to_string();
+        match result {
+            Ok(resp) => {
+                entry.models = parse_model_ids(resp);
+                entry.fetched_at = Some(chrono::Utc::now().timestamp());
+                entry.error = None;
+            }
+            Err(e) => entry.error = Some(e.to_string()),
+        }
+    }
+
+    /// 所有账号模型的并集
+    pub fn models(&self) -> Vec<String> {
+        let cache = self.cache.lock().unwrap();
+        let merged: BTreeSet<&String> = cache.accounts.values().flat_map(|a| &a.models).collect();
+        merged.into_iter().cloned().collect()
+    }
+
+    pub fn status(&self) -> RegistryStatus {
+        let models = self.models();
+        let cache = self.cache.lock().unwrap();
+        RegistryStatus {
+            refreshed_at: cache.refreshed_at,
+            refresh_interval_minutes: self.refresh_interval_minutes.load(Ordering::Relaxed),
+            models,
+            accounts: cache.accounts.clone(),
+        }
+    }
+
+    /// 用所有账号重新获取模型列表（复用健康探测，同时刷新配额快照），并移除已删除账号的缓存
+    pub async fn refresh(&self, token_manager: &TokenManager, upstream: &UpstreamClient) -> RegistryStatus {
+        let _guard = self.refresh_lock.lock().await;
+        let reports = token_manager.probe_health(upstream).await;
+        {
+            let mut cache = self.cache.lock().unwrap();
+            cache.accounts.retain(|id, _| reports.iter().any(|r| &r.account_id == id));
+            cache.refreshed_at = Some(chrono::Utc::now().timestamp());
+        }
+        self.save();
+        let status = self.status();
+        tracing::info!(
+            "[Models] Refreshed model list from {} accounts: {} models",
+            status.accounts.len(),
+            status.models.len()
+        );
+        status
+    }
+
+    fn is_due(&self, now: i64) -> bool {
+        let interval = self.refresh_interval_minutes.load(Ordering::Relaxed);
+        if interval == 0 {
+            return false;
+        }
+        let refreshed_at = self.cache.lock().unwrap().refreshed_at;
+        refreshed_at.is_none_or(|at| now - at >= (interval * 60) as i64)
+    }
+
+    /// 启动定时刷新任务
+    pub fn spawn_refresher(&'static self, token_manager: std::sync::Arc<TokenManager>, upstream: std::sync::Arc<UpstreamClient>) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(SCHEDULE_CHECK_INTERVAL).await;
+                if self.is_due(chrono::Utc::now().timestamp()) {
+                    self.refresh(&token_manager, &upstream).await;
+                }
+            }
+        });
+    }
+}
+
+/// 当前请求的 API Key 可见的模型：内置 / 映射模型加上游模型，按 Key 的模型白名单过滤
+pub async fn visible_models(mut models: Vec<String>) -> Vec<String> {
+    models.extend(MODEL_REGISTRY.models());
+    models.sort();
+    models.dedup();
+
+    let key = match crate::proxy::middleware::auth::current_key_id() {
+        Some(id) => crate::modules::storage::blocking(move || crate::modules::api_keys::get_api_key(&id))
+            .await
+            .ok()
+            .flatten(),
+        None => None,
+    };
+    match key {
+        Some(key) => models.into_iter().filter(|m| key.is_model_allowed(m)).collect(),
+        None => models,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_record_and_merge() {
+        let registry = ModelRegistry::new(Cache::default());
+        let resp = |names: &[&str]| {
+            let models: serde_json::Map<String, Value> = names.iter().map(|n| (n.to_string(), json!({}))).collect();
+            json!({ "models": models })
+        };
+        registry.record("a1", "a@example.com", Ok(&resp(&["gemini-2.5-pro", "claude-sonnet-4-5", "chat_20706"])));
+        registry.record("a2", "b@example.com", Ok(&resp(&["gemini-2.5-pro", "gemini-3-pro-high"])));
+        assert_eq!(registry.models(), vec!["claude-sonnet-4-5", "gemini-2.5-pro", "gemini-3-pro-high"]);
+
+        // 获取失败时保留上次的模型列表
+        registry.record("a2", "b@example.com", Err("Upstream error: 500"));
+        let status = registry.status();
+        assert_eq!(status.accounts["a2"].models.len(), 2);
+        assert_eq!(status.accounts["a2"].error.as_deref(), Some("Upstream error: 500"));
+
+        assert!(registry.is_due(0));
+        registry.cache.lock().unwrap().refreshed_at = Some(1_000);
+        assert!(!registry.is_due(1_000 + 59 * 60));
+        assert!(registry.is_due(1_000 + 60 * 60));
+        registry.configure(&ModelRegistryConfig { refresh_interval_minutes: 0 });
+        assert!(!registry.is_due(i64::MAX));
+    }
+}
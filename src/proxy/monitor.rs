@@ -1,9 +1,95 @@
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
-use std::sync::atomic::{AtomicBool, Ordering};
-use tokio::sync::RwLock;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use tokio::sync::{broadcast, watch, RwLock};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::proxy::config::{LogRedactionConfig, MonitorConfig};
+use crate::proxy::redaction::{truncate, Redactor};
+
+/// 客户端在流式响应结束前断开时记录的结束原因
+pub const CLIENT_CANCELLED: &str = "client_cancelled";
+
+/// 客户端为请求附加成本归属标签的请求头，如 `project=alpha,team=ml`
+pub const TAGS_HEADER: &str = "x-antiproxy-tags";
+
+/// 每个请求最多保留的标签数
+const MAX_TAGS: usize = 16;
+/// 标签名最大长度
+const MAX_TAG_KEY_LEN: usize = 64;
+/// 标签值最大长度
+const MAX_TAG_VALUE_LEN: usize = 128;
+
+/// 错误请求始终记录，成功请求按 1/N 采样
+fn sampled(counter: &AtomicU64, status: u16, sample_rate: u64) -> bool {
+    status >= 400 || sample_rate <= 1 || counter.fetch_add(1, Ordering::Relaxed).is_multiple_of(sample_rate)
+}
+
+fn log_save_result(result: Result<Result<(), String>, tokio::task::JoinError>) {
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => tracing::error!("Failed to save proxy log to DB: {}", e),
+        Err(e) => tracing::error!("Proxy log writer task failed: {}", e),
+    }
+}
+
+/// 日志中请求/响应体与错误信息占用的字节数
+fn body_bytes(log: &ProxyRequestLog) -> usize {
+    [&log.request_body, &log.response_body, &log.error]
+        .iter()
+        .map(|b| b.as_ref().map_or(0, |b| b.len()))
+        .sum()
+}
+
+/// 从最旧的日志开始丢弃请求/响应体，直到占用不超过水位线，返回剩余占用
+fn spill_bodies(logs: &mut VecDeque<ProxyRequestLog>, mut used: usize, watermark: usize) -> usize {
+    for log in logs.iter_mut().rev() {
+        if used <= watermark {
+            break;
+        }
+        let freed = log.request_body.take().map_or(0, |b| b.len()) + log.response_body.take().map_or(0, |b| b.len());
+        used -= freed;
+    }
+    used
+}
+
+/// 标签名是否合法（小写字母、数字、`_`、`-`）
+pub fn is_valid_tag_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= MAX_TAG_KEY_LEN
+        && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+/// 解析标签请求头：逗号分隔的 `key=value`，标签名转为小写且只允许 `[a-z0-9_-]`；
+/// 格式不合法或超长的项被忽略，重复的标签名以最后一次为准，没有有效标签时返回 None
+pub fn parse_tags(header: &str) -> Option<BTreeMap<String, String>> {
+    let mut tags = BTreeMap::new();
+    for pair in header.split(',') {
+        let Some((key, value)) = pair.split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim().to_lowercase(), value.trim());
+        if !is_valid_tag_key(&key) || value.is_empty() || value.len() > MAX_TAG_VALUE_LEN {
+            continue;
+        }
+        if tags.len() >= MAX_TAGS && !tags.contains_key(&key) {
+            continue;
+        }
+        tags.insert(key, value.to_string());
+    }
+    (!tags.is_empty()).then_some(tags)
+}
+
+/// 重放请求的标记（请求扩展，仅由 `POST /api/logs/:id/replay` 在进程内设置）
+#[derive(Debug, Clone)]
+pub struct ReplayOf {
+    /// 被重放的日志 ID
+    pub original_id: String,
+    /// 新日志使用的 ID，便于重放接口返回关联
+    pub log_id: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ProxyRequestLog {
     pub id: String,
     pub timestamp: i64,
@@ -17,6 +103,39 @@ pub struct ProxyRequestLog {
     pub response_body: Option<String>,
     pub input_tokens: Option<u32>,
     pub output_tokens: Option<u32>,
+    /// 发起请求的 API Key ID
+    #[serde(default)]
+    pub key_id: Option<String>,
+    /// 处理该请求的上游账号邮箱
+    #[serde(default)]
+    pub account_email: Option<String>,
+    /// 按价格表估算的费用（模型未配置价格时为空）
+    #[serde(default)]
+    pub estimated_cost: Option<f64>,
+    /// 结束原因：上游返回的值（`stop` / `end_turn` / `length` 等），客户端中途断开时为 `client_cancelled`
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+    /// 由重放产生时，被重放的原始日志 ID
+    #[serde(default)]
+    pub replay_of: Option<String>,
+    /// 客户端通过 `x-antiproxy-tags` 请求头附加的标签
+    #[serde(default)]
+    pub tags: Option<BTreeMap<String, String>>,
+    /// 客户端地址（经可信代理时为 X-Forwarded-For / X-Real-IP 还原的地址）
+    #[serde(default)]
+    pub client_ip: Option<String>,
+    /// 请求 ID，与响应头 `x-request-id` 及服务端日志中的 `request{id=...}` 一致
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// 上游流未返回用量，token 数为本地估算值
+    #[serde(default)]
+    pub usage_estimated: bool,
+    /// 记录的请求体经过脱敏或截断，与实际发送的内容不同（不能重放）
+    #[serde(default)]
+    pub request_body_modified: bool,
+    /// 采样权重：按 1/N 采样保留的成功请求代表 N 个请求，统计与聚合按该值加权；为空表示 1
+    #[serde(default)]
+    pub sample_weight: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -26,11 +145,79 @@ pub struct ProxyStats {
     pub error_count: u64,
 }
 
+/// 实时推送的日志摘要（不含请求 / 响应体）
+#[derive(Debug, Clone, Serialize)]
+pub struct LogSummary {
+    pub id: String,
+    pub timestamp: i64,
+    pub method: String,
+    pub url: String,
+    pub status: u16,
+    pub duration: u64,
+    pub model: Option<String>,
+    pub error: Option<String>,
+    pub input_tokens: Option<u32>,
+    pub output_tokens: Option<u32>,
+    pub key_id: Option<String>,
+    pub account_email: Option<String>,
+    pub estimated_cost: Option<f64>,
+    pub finish_reason: Option<String>,
+    pub tags: Option<BTreeMap<String, String>>,
+    pub client_ip: Option<String>,
+    pub request_id: Option<String>,
+    pub usage_estimated: bool,
+}
+
+impl From<&ProxyRequestLog> for LogSummary {
+    fn from(log: &ProxyRequestLog) -> Self {
+        Self {
+            id: log.id.clone(),
+            timestamp: log.timestamp,
+            method: log.method.clone(),
+            url: log.url.clone(),
+            status: log.status,
+            duration: log.duration,
+            model: log.model.clone(),
+            error: log.error.clone(),
+            input_tokens: log.input_tokens,
+            output_tokens: log.output_tokens,
+            key_id: log.key_id.clone(),
+            account_email: log.account_email.clone(),
+            estimated_cost: log.estimated_cost,
+            finish_reason: log.finish_reason.clone(),
+            tags: log.tags.clone(),
+            client_ip: log.client_ip.clone(),
+            request_id: log.request_id.clone(),
+            usage_estimated: log.usage_estimated,
+        }
+    }
+}
+
+/// 保留策略清理间隔
+const RETENTION_INTERVAL_SECS: u64 = 3600;
+
+/// 实时推送通道容量；订阅者落后超过该条数时丢弃最旧的摘要
+const LIVE_TAIL_CAPACITY: usize = 256;
+
 pub struct ProxyMonitor {
     pub logs: RwLock<VecDeque<ProxyRequestLog>>,
     pub stats: RwLock<ProxyStats>,
     pub max_logs: usize,
     pub enabled: AtomicBool,
+    /// 写入前的脱敏规则（支持热重载）
+    redactor: std::sync::RwLock<Redactor>,
+    /// 采样与请求/响应体记录设置（支持热重载）
+    capture: std::sync::RwLock<MonitorConfig>,
+    /// 成功请求计数，用于 1/N 采样
+    sample_counter: AtomicU64,
+    /// 内存中日志的请求/响应体字节数（持有 logs 写锁时更新）
+    memory_bytes: AtomicUsize,
+    /// 已排队但尚未写入数据库的日志字节数
+    pending_bytes: Arc<AtomicUsize>,
+    /// 新日志摘要的广播通道（/api/logs/stream 订阅）
+    live: broadcast::Sender<LogSummary>,
+    /// 关闭时置为 true，结束所有实时推送连接，避免阻塞优雅关闭
+    tails_closed: watch::Sender<bool>,
 }
 
 impl ProxyMonitor {
@@ -45,9 +232,42 @@ impl ProxyMonitor {
             stats: RwLock::new(ProxyStats::default()),
             max_logs,
             enabled: AtomicBool::new(true), // Default to enabled
+            redactor: std::sync::RwLock::new(Redactor::default()),
+            capture: std::sync::RwLock::new(MonitorConfig::default()),
+            sample_counter: AtomicU64::new(0),
+            memory_bytes: AtomicUsize::new(0),
+            pending_bytes: Arc::new(AtomicUsize::new(0)),
+            live: broadcast::channel(LIVE_TAIL_CAPACITY).0,
+            tails_closed: watch::channel(false).0,
         }
     }
 
+    /// 订阅新日志摘要，同时返回内存中已有的日志（从新到旧）；
+    /// 两者在同一把锁下获取，既不重复也不遗漏
+    pub async fn subscribe(&self) -> (Vec<LogSummary>, broadcast::Receiver<LogSummary>) {
+        let logs = self.logs.read().await;
+        let receiver = self.live.subscribe();
+        (logs.iter().map(LogSummary::from).collect(), receiver)
+    }
+
+    /// 实时推送连接的关闭信号
+    pub fn tails_closed(&self) -> watch::Receiver<bool> {
+        self.tails_closed.subscribe()
+    }
+
+    /// 结束所有实时推送连接（服务器停止时调用）
+    pub fn close_tails(&self) {
+        self.tails_closed.send_replace(true);
+    }
+
+    pub fn set_redaction(&self, config: &LogRedactionConfig) {
+        *self.redactor.write().unwrap_or_else(|e| e.into_inner()) = Redactor::from_config(config);
+    }
+
+    pub fn set_capture(&self, config: &MonitorConfig) {
+        *self.capture.write().unwrap_or_else(|e| e.into_inner()) = config.clone();
+    }
+
     pub fn set_enabled(&self, enabled: bool) {
         self.enabled.store(enabled, Ordering::Relaxed);
     }
@@ -56,10 +276,24 @@ impl ProxyMonitor {
         self.enabled.load(Ordering::Relaxed)
     }
 
-    pub async fn log_request(&self, log: ProxyRequestLog) {
+    pub async fn log_request(&self, mut log: ProxyRequestLog) {
         if !self.is_enabled() {
             return;
         }
+        let capture = self.capture.read().unwrap_or_else(|e| e.into_inner()).clone();
+        if !capture.capture_request_body {
+            log.request_body = None;
+        }
+        if !capture.capture_response_body {
+            log.response_body = None;
+        }
+        self.redactor.read().unwrap_or_else(|e| e.into_inner()).apply(&mut log);
+        log.request_body_modified |= log.request_body.as_ref().is_some_and(|b| b.len() > capture.max_body_bytes);
+        log.request_body = log.request_body.map(|b| truncate(b, capture.max_body_bytes));
+        log.response_body = log.response_body.map(|b| truncate(b, capture.max_body_bytes));
+        if log.status < 400 {
+            log.estimated_cost = crate::proxy::pricing::PRICING.estimate(log.model.as_deref(), log.input_tokens, log.output_tokens);
+        }
         tracing::info!("[Monitor] Logging request: {} {}", log.method, log.url);
         // Update stats
         {
@@ -71,29 +305,91 @@ impl ProxyMonitor {
                 stats.error_count += 1;
             }
         }
+        // 统计包含未被采样的请求
+        if !sampled(&self.sample_counter, log.status, capture.sample_rate) {
+            return;
+        }
+        if log.status < 400 && capture.sample_rate > 1 {
+            log.sample_weight = Some(capture.sample_rate);
+        }
 
         // Add log to memory
+        let watermark = capture.memory_watermark_bytes;
+        let size = body_bytes(&log);
         {
             let mut logs = self.logs.write().await;
+            let mut used = self.memory_bytes.load(Ordering::Relaxed) + size;
             if logs.len() >= self.max_logs {
-                logs.pop_back();
+                used -= logs.pop_back().map_or(0, |old| body_bytes(&old));
             }
             logs.push_front(log.clone());
+            if watermark > 0 && used > watermark {
+                // 日志已写入（或即将写入）数据库，内存中只保留摘要
+                used = spill_bodies(&mut logs, used, watermark);
+                tracing::debug!("[Monitor] Spilled request log bodies above memory watermark ({} bytes kept)", used);
+            }
+            self.memory_bytes.store(used, Ordering::Relaxed);
+            // 持有写锁时广播，与 subscribe 的快照保持一致；没有订阅者时发送失败可忽略
+            let _ = self.live.send(LogSummary::from(&log));
         }
 
         // Save to DB
-        let log_to_save = log.clone();
+        // SQLite 写入是阻塞调用，放到阻塞线程池，避免占用异步工作线程
+        let write = move || crate::modules::proxy_db::save_log(&log);
+        if watermark > 0 && self.pending_bytes.load(Ordering::Relaxed) + size > watermark {
+            // 写入积压超过水位线：同步等待，让请求方承受背压，而不是继续在内存中排队
+            log_save_result(tokio::task::spawn_blocking(write).await);
+        } else {
+            self.pending_bytes.fetch_add(size, Ordering::Relaxed);
+            let pending = Arc::clone(&self.pending_bytes);
+            crate::proxy::shutdown::spawn_tracked(async move {
+                let result = tokio::task::spawn_blocking(write).await;
+                pending.fetch_sub(size, Ordering::Relaxed);
+                log_save_result(result);
+            });
+        }
+    }
+
+    /// 启动后台任务，按保留策略定期清理 SQLite 中的旧日志；配置了归档时先上传再删除
+    pub fn spawn_retention_task(
+        &self,
+        retention: crate::proxy::config::LogRetentionConfig,
+        archive: crate::proxy::config::LogArchiveConfig,
+    ) {
+        if retention.max_days == 0 && retention.max_rows == 0 {
+            return;
+        }
+        let archive = archive.s3.map(crate::modules::s3::S3Client::new);
         tokio::spawn(async move {
-            if let Err(e) = crate::modules::proxy_db::save_log(&log_to_save) {
-                tracing::error!("Failed to save proxy log to DB: {}", e);
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(RETENTION_INTERVAL_SECS));
+            loop {
+                interval.tick().await;
+                if let Some(client) = &archive {
+                    match crate::modules::log_archive::archive_expired(client, &retention).await {
+                        Ok(0) => {}
+                        Ok(archived) => tracing::info!("[Monitor] Archived and pruned {} old request logs", archived),
+                        Err(e) => tracing::error!("Failed to archive proxy logs: {}", e),
+                    }
+                    continue;
+                }
+                let result = tokio::task::spawn_blocking({
+                    let retention = retention.clone();
+                    move || crate::modules::proxy_db::prune_logs(retention.max_days, retention.max_rows)
+                })
+                .await;
+                match result {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(deleted)) => tracing::info!("[Monitor] Pruned {} old request logs", deleted),
+                    Ok(Err(e)) => tracing::error!("Failed to prune proxy logs: {}", e),
+                    Err(e) => tracing::error!("Proxy log retention task failed: {}", e),
+                }
             }
         });
-
     }
 
     pub async fn get_logs(&self, limit: usize) -> Vec<ProxyRequestLog> {
         // Try to get from DB first for true history
-        match crate::modules::proxy_db::get_logs(limit) {
+        match crate::modules::storage::blocking(move || crate::modules::proxy_db::get_logs(limit)).await {
             Ok(logs) => logs,
             Err(e) => {
                 tracing::error!("Failed to get logs from DB: {}", e);
@@ -105,7 +401,7 @@ impl ProxyMonitor {
     }
 
     pub async fn get_stats(&self) -> ProxyStats {
-        match crate::modules::proxy_db::get_stats() {
+        match crate::modules::storage::blocking(crate::modules::proxy_db::get_stats).await {
             Ok(stats) => stats,
             Err(e) => {
                 tracing::error!("Failed to get stats from DB: {}", e);
@@ -117,11 +413,63 @@ impl ProxyMonitor {
     pub async fn clear(&self) {
         let mut logs = self.logs.write().await;
         logs.clear();
+        self.memory_bytes.store(0, Ordering::Relaxed);
         let mut stats = self.stats.write().await;
         *stats = ProxyStats::default();
 
-        if let Err(e) = crate::modules::proxy_db::clear_logs() {
+        if let Err(e) = crate::modules::storage::blocking(crate::modules::proxy_db::clear_logs).await {
             tracing::error!("Failed to clear logs in DB: {}", e);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tags() {
+        let tags = parse_tags(" Project=alpha, team = ml ,bad key=x,empty=,noequals,team=infra").unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags["project"], "alpha");
+        assert_eq!(tags["team"], "infra");
+
+        assert!(parse_tags("").is_none());
+        assert!(parse_tags("a b=c").is_none());
+        assert!(parse_tags(&format!("k={}", "v".repeat(MAX_TAG_VALUE_LEN + 1))).is_none());
+
+        let many: Vec<String> = (0..MAX_TAGS + 4).map(|i| format!("t{}=v", i)).collect();
+        assert_eq!(parse_tags(&many.join(",")).unwrap().len(), MAX_TAGS);
+    }
+
+    #[test]
+    fn test_sampling() {
+        let counter = AtomicU64::new(0);
+        let kept = (0..10).filter(|_| sampled(&counter, 200, 5)).count();
+        assert_eq!(kept, 2);
+        assert!((0..3).all(|_| sampled(&counter, 502, 5)));
+        assert!((0..3).all(|_| sampled(&counter, 200, 0)));
+    }
+
+    #[test]
+    fn test_spill_bodies() {
+        let log = |id: &str| ProxyRequestLog {
+            id: id.to_string(),
+            request_body: Some("x".repeat(100)),
+            response_body: Some("y".repeat(100)),
+            error: Some("e".repeat(10)),
+            ..Default::default()
+        };
+        // 新日志在前
+        let mut logs: VecDeque<_> = ["c", "b", "a"].into_iter().map(log).collect();
+        let used = logs.iter().map(body_bytes).sum();
+        assert_eq!(used, 630);
+
+        assert_eq!(spill_bodies(&mut logs, used, 500), 430);
+        assert!(logs[2].request_body.is_none() && logs[2].error.is_some());
+        assert!(logs[1].request_body.is_some());
+
+        assert_eq!(spill_bodies(&mut logs, 430, 0), 30);
+        assert!(logs.iter().all(|l| l.response_body.is_none()));
+    }
+}
@@ -0,0 +1,514 @@
+// OpenTelemetry 追踪导出
+// 为每个请求生成 server span（中间件）→ handler span → 上游调用 client span，
+// 沿用入站 `traceparent`（W3C Trace Context），按 OTLP/HTTP JSON 批量导出到配置的 Collector
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+use crate::proxy::config::OtelConfig;
+
+/// 全局追踪器（启动 / 热重载时由配置写入）
+pub static TRACER: Lazy<Tracer> = Lazy::new(Tracer::default);
+
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// 待导出队列长度，超出时丢弃新 span
+const QUEUE_CAPACITY: usize = 4096;
+/// 单次导出的最大 span 数
+const MAX_BATCH_SIZE: usize = 512;
+/// 攒批的最长等待时间
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+/// 单次导出超时
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// W3C Trace Context
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpanContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub sampled: bool,
+}
+
+impl SpanContext {
+    /// 解析 `traceparent`：`00-<32 hex trace id>-<16 hex span id>-<2 hex flags>`
+    pub fn from_traceparent(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let flags = parts.next()?;
+        // 版本 00 必须恰好 4 段；未知版本允许携带后续字段
+        if version.len() != 2 || version == "ff" || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+        let trace_id: [u8; 16] = decode_hex(trace_id)?.try_into().ok()?;
+        let span_id: [u8; 8] = decode_hex(span_id)?.try_into().ok()?;
+        let flags = decode_hex(flags)?;
+        if flags.len() != 1 || trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+        Some(Self {
+            trace_id,
+            span_id,
+            sampled: flags[0] & 0x01 == 1,
+        })
+    }
+
+    pub fn to_traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            encode_hex(&self.trace_id),
+            encode_hex(&self.span_id),
+            self.sampled as u8
+        )
+    }
+
+    pub fn trace_id_hex(&self) -> String {
+        encode_hex(&self.trace_id)
+    }
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) || !value.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn random_nonzero<const N: usize>() -> [u8; N] {
+    loop {
+        let id: [u8; N] = std::array::from_fn(|_| rand::random());
+        if id != [0; N] {
+            return id;
+        }
+    }
+}
+
+fn now_unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// 按 trace id 做确定性采样，同一 trace 在各服务得到相同结果
+fn sample_by_ratio(trace_id: &[u8; 16], ratio: f64) -> bool {
+    if ratio >= 1.0 {
+        return true;
+    }
+    if ratio <= 0.0 || ratio.is_nan() {
+        return false;
+    }
+    let mut low = [0u8; 8];
+    low.copy_from_slice(&trace_id[8..]);
+    (u64::from_be_bytes(low) as f64) < ratio * u64::MAX as f64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    Internal = 1,
+    Server = 2,
+    Client = 3,
+}
+
+/// span 属性值
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttrValue {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+}
+
+impl From<&str> for AttrValue {
+    fn from(v: &str) -> Self {
+        Self::Str(v.to_string())
+    }
+}
+
+impl From<String> for AttrValue {
+    fn from(v: String) -> Self {
+        Self::Str(v)
+    }
+}
+
+impl From<i64> for AttrValue {
+    fn from(v: i64) -> Self {
+        Self::Int(v)
+    }
+}
+
+impl From<u16> for AttrValue {
+    fn from(v: u16) -> Self {
+        Self::Int(v as i64)
+    }
+}
+
+impl From<bool> for AttrValue {
+    fn from(v: bool) -> Self {
+        Self::Bool(v)
+    }
+}
+
+type Attributes = Arc<Mutex<Vec<(String, AttrValue)>>>;
+
+#[derive(Debug)]
+struct SpanData {
+    context: SpanContext,
+    parent_span_id: Option<[u8; 8]>,
+    name: String,
+    kind: SpanKind,
+    start_ns: u64,
+    attributes: Attributes,
+    error: Option<String>,
+}
+
+/// 进行中的 span，drop 时结束并提交导出（未采样时只传播上下文）
+pub struct Span {
+    context: SpanContext,
+    data: Option<SpanData>,
+}
+
+impl Span {
+    pub fn context(&self) -> SpanContext {
+        self.context
+    }
+
+    pub fn set_attribute(&self, key: &str, value: impl Into<AttrValue>) {
+        if let Some(data) = &self.data {
+            push_attribute(&data.attributes, key, value.into());
+        }
+    }
+
+    /// 标记为失败（status = ERROR）
+    pub fn set_error(&mut self, message: impl Into<String>) {
+        if let Some(data) = &mut self.data {
+            data.error = Some(message.into());
+        }
+    }
+
+    /// 在该 span 的作用域内执行，内部创建的 span 以其为父节点，`record_attribute` 写入该 span
+    pub async fn in_scope<F: std::future::Future>(&self, fut: F) -> F::Output {
+        let scope = TraceScope {
+            context: self.context,
+            attributes: self.data.as_ref().map(|d| d.attributes.clone()),
+        };
+        CURRENT_SCOPE.scope(scope, fut).await
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(mut data) = self.data.take() {
+            // server span 以 `{method} {http.route}` 命名（路由在 handler 层才能确定）
+            if data.kind == SpanKind::Server {
+                let attrs = data.attributes.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some((_, AttrValue::Str(route))) = attrs.iter().find(|(k, _)| k == "http.route") {
+                    let name = format!("{} {}", data.name, route);
+                    drop(attrs);
+                    data.name = name;
+                }
+            }
+            TRACER.export(data, now_unix_nanos());
+        }
+    }
+}
+
+fn push_attribute(attributes: &Attributes, key: &str, value: AttrValue) {
+    let mut attrs = attributes.lock().unwrap_or_else(|e| e.into_inner());
+    match attrs.iter_mut().find(|(k, _)| k == key) {
+        Some(entry) => entry.1 = value,
+        None => attrs.push((key.to_string(), value)),
+    }
+}
+
+#[derive(Clone)]
+struct TraceScope {
+    context: SpanContext,
+    attributes: Option<Attributes>,
+}
+
+tokio::task_local! {
+    /// 当前请求的 span 上下文
+    static CURRENT_SCOPE: TraceScope;
+}
+
+/// 当前作用域的 span 上下文
+pub fn current_context() -> Option<SpanContext> {
+    CURRENT_SCOPE.try_with(|s| s.context).ok()
+}
+
+/// 为当前作用域的 span 添加属性（如中间件识别出的 API Key）
+pub fn record_attribute(key: &str, value: impl Into<AttrValue>) {
+    let _ = CURRENT_SCOPE.try_with(|s| {
+        if let Some(attributes) = &s.attributes {
+            push_attribute(attributes, key, value.into());
+        }
+    });
+}
+
+struct Exporter {
+    config: OtelConfig,
+    sender: mpsc::Sender<(SpanData, u64)>,
+}
+
+#[derive(Default)]
+pub struct Tracer {
+    exporter: RwLock<Option<Exporter>>,
+}
+
+impl Tracer {
+    /// 应用配置；导出地址等变化时替换后台导出任务（旧任务导出剩余 span 后退出）
+    pub fn configure(&self, config: &OtelConfig) {
+        let mut exporter = self.exporter.write().unwrap_or_else(|e| e.into_inner());
+        if !config.enabled {
+            if exporter.take().is_some() {
+                tracing::info!("[OTel] Trace export disabled");
+            }
+            return;
+        }
+        if exporter.as_ref().is_some_and(|e| &e.config == config) {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(run_exporter(config.clone(), receiver));
+        tracing::info!("[OTel] Exporting traces to {}", traces_url(&config.endpoint));
+        *exporter = Some(Exporter {
+            config: config.clone(),
+            sender,
+        });
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.exporter.read().unwrap_or_else(|e| e.into_inner()).is_some()
+    }
+
+    fn sample_ratio(&self) -> Option<f64> {
+        let exporter = self.exporter.read().unwrap_or_else(|e| e.into_inner());
+        exporter.as_ref().map(|e| e.config.sample_ratio)
+    }
+
+    /// 创建入站请求的 server span；有合法 `traceparent` 时作为其子节点并沿用采样决定
+    pub fn start_server_span(&self, name: impl Into<String>, traceparent: Option<&str>) -> Span {
+        let parent = traceparent.and_then(SpanContext::from_traceparent);
+        let ratio = self.sample_ratio();
+        let trace_id = parent.map(|p| p.trace_id).unwrap_or_else(random_nonzero::<16>);
+        let sampled = match (ratio, parent) {
+            (None, _) => false,
+            (Some(_), Some(parent)) => parent.sampled,
+            (Some(ratio), None) => sample_by_ratio(&trace_id, ratio),
+        };
+        let context = SpanContext {
+            trace_id,
+            span_id: random_nonzero::<8>(),
+            sampled,
+        };
+        self.new_span(context, parent.map(|p| p.span_id), name.into(), SpanKind::Server)
+    }
+
+    /// 在当前作用域下创建子 span；没有父 span 时不记录
+    pub fn start_child_span(&self, name: impl Into<String>, kind: SpanKind) -> Option<Span> {
+        let parent = current_context()?;
+        let context = SpanContext {
+            span_id: random_nonzero::<8>(),
+            ..parent
+        };
+        Some(self.new_span(context, Some(parent.span_id), name.into(), kind))
+    }
+
+    fn new_span(&self, context: SpanContext, parent_span_id: Option<[u8; 8]>, name: String, kind: SpanKind) -> Span {
+        let data = (context.sampled && self.is_enabled()).then(|| SpanData {
+            context,
+            parent_span_id,
+            name,
+            kind,
+            start_ns: now_unix_nanos(),
+            attributes: Arc::new(Mutex::new(Vec::new())),
+            error: None,
+        });
+        Span { context, data }
+    }
+
+    fn export(&self, data: SpanData, end_ns: u64) {
+        let exporter = self.exporter.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(exporter) = exporter.as_ref() {
+            if exporter.sender.try_send((data, end_ns)).is_err() {
+                tracing::debug!("[OTel] Export queue full, dropping span");
+            }
+        }
+    }
+}
+
+fn traces_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/v1/traces") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/traces", endpoint)
+    }
+}
+
+async fn run_exporter(config: OtelConfig, mut receiver: mpsc::Receiver<(SpanData, u64)>) {
+    let client = match reqwest::Client::builder().timeout(EXPORT_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("[OTel] Failed to build exporter client: {}", e);
+            return;
+        }
+    };
+    let url = traces_url(&config.endpoint);
+
+    let mut batch = Vec::with_capacity(MAX_BATCH_SIZE);
+    loop {
+        // 等待第一个 span，随后在间隔内继续攒批
+        match receiver.recv().await {
+            Some(span) => batch.push(span),
+            None => break,
+        }
+        let deadline = tokio::time::Instant::now() + EXPORT_INTERVAL;
+        while batch.len() < MAX_BATCH_SIZE {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(span)) => batch.push(span),
+                _ => break,
+            }
+        }
+        send_batch(&client, &url, &config, std::mem::take(&mut batch)).await;
+    }
+}
+
+async fn send_batch(client: &reqwest::Client, url: &str, config: &OtelConfig, batch: Vec<(SpanData, u64)>) {
+    let count = batch.len();
+    let payload = build_payload(&config.service_name, &batch);
+    let mut request = client.post(url).json(&payload);
+    for (name, value) in &config.headers {
+        request = request.header(name, value);
+    }
+    match request.send().await {
+        Ok(resp) if resp.status().is_success() => {
+            tracing::debug!("[OTel] Exported {} spans", count);
+        }
+        Ok(resp) => tracing::warn!("[OTel] Collector rejected {} spans: HTTP {}", count, resp.status()),
+        Err(e) => tracing::warn!("[OTel] Failed to export {} spans: {}", count, e),
+    }
+}
+
+fn attr_json(key: &str, value: &AttrValue) -> Value {
+    let value = match value {
+        AttrValue::Str(v) => json!({ "stringValue": v }),
+        AttrValue::Int(v) => json!({ "intValue": v.to_string() }),
+        AttrValue::Bool(v) => json!({ "boolValue": v }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+/// 构建 OTLP/HTTP JSON (ExportTraceServiceRequest)
+fn build_payload(service_name: &str, batch: &[(SpanData, u64)]) -> Value {
+    let spans: Vec<Value> = batch
+        .iter()
+        .map(|(span, end_ns)| {
+            let attributes: Vec<Value> = span
+                .attributes
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .iter()
+                .map(|(k, v)| attr_json(k, v))
+                .collect();
+            let status = match &span.error {
+                Some(message) => json!({ "code": 2, "message": message }),
+                None => json!({ "code": 0 }),
+            };
+            let mut value = json!({
+                "traceId": encode_hex(&span.context.trace_id),
+                "spanId": encode_hex(&span.context.span_id),
+                "name": span.name,
+                "kind": span.kind as u8,
+                "startTimeUnixNano": span.start_ns.to_string(),
+                "endTimeUnixNano": end_ns.to_string(),
+                "attributes": attributes,
+                "status": status,
+            });
+            if let Some(parent) = span.parent_span_id {
+                value["parentSpanId"] = json!(encode_hex(&parent));
+            }
+            value
+        })
+        .collect();
+
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attr_json("service.name", &AttrValue::from(service_name))]
+            },
+            "scopeSpans": [{
+                "scope": { "name": "anti-proxy", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }]
+        }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traceparent_roundtrip() {
+        let header = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let ctx = SpanContext::from_traceparent(header).unwrap();
+        assert!(ctx.sampled);
+        assert_eq!(ctx.trace_id_hex(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(ctx.to_traceparent(), header);
+
+        assert!(SpanContext::from_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none());
+        assert!(SpanContext::from_traceparent("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01").is_none());
+        assert!(SpanContext::from_traceparent("ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").is_none());
+        assert!(SpanContext::from_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7").is_none());
+        assert!(!SpanContext::from_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00").unwrap().sampled);
+    }
+
+    #[test]
+    fn test_sample_by_ratio() {
+        let mut low = [0u8; 16];
+        low[8] = 0x10;
+        let mut high = [0u8; 16];
+        high[8] = 0xf0;
+        assert!(sample_by_ratio(&high, 1.0));
+        assert!(!sample_by_ratio(&low, 0.0));
+        assert!(sample_by_ratio(&low, 0.5));
+        assert!(!sample_by_ratio(&high, 0.5));
+    }
+
+    #[test]
+    fn test_build_payload() {
+        let attributes: Attributes = Arc::new(Mutex::new(Vec::new()));
+        push_attribute(&attributes, "http.response.status_code", AttrValue::Int(200));
+        push_attribute(&attributes, "http.response.status_code", AttrValue::Int(502));
+        let span = SpanData {
+            context: SpanContext { trace_id: [1; 16], span_id: [2; 8], sampled: true },
+            parent_span_id: Some([3; 8]),
+            name: "POST /v1/messages".to_string(),
+            kind: SpanKind::Server,
+            start_ns: 10,
+            attributes,
+            error: Some("upstream failed".to_string()),
+        };
+        let payload = build_payload("anti-proxy", &[(span, 20)]);
+        let out = &payload["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(out["traceId"], "01010101010101010101010101010101");
+        assert_eq!(out["parentSpanId"], "0303030303030303");
+        assert_eq!(out["kind"], 2);
+        assert_eq!(out["endTimeUnixNano"], "20");
+        assert_eq!(out["status"]["code"], 2);
+        assert_eq!(out["attributes"].as_array().unwrap().len(), 1);
+        assert_eq!(out["attributes"][0]["value"]["intValue"], "502");
+        assert_eq!(traces_url("http://collector:4318/"), "http://collector:4318/v1/traces");
+    }
+}
@@ -0,0 +1,117 @@
+// 按模型计价的费用估算
+// 价格表按每 1K 输入 / 输出 token 计（货币单位由使用者自定），用于日志、API Key 用量与统计接口中的估算费用
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::proxy::config::ModelPricing;
+
+/// 全局价格表（启动 / 热重载 / PUT /api/pricing 时更新）
+pub static PRICING: Lazy<PricingTable> = Lazy::new(PricingTable::default);
+
+#[derive(Default)]
+pub struct PricingTable {
+    prices: RwLock<HashMap<String, ModelPricing>>,
+}
+
+impl PricingTable {
+    pub fn configure(&self, prices: &HashMap<String, ModelPricing>) {
+        *self.prices.write().unwrap_or_else(|e| e.into_inner()) = prices.clone();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prices.read().unwrap_or_else(|e| e.into_inner()).is_empty()
+    }
+
+    /// 估算一次请求的费用；模型未配置价格或没有 token 数时返回 None
+    pub fn estimate(&self, model: Option<&str>, input_tokens: Option<u32>, output_tokens: Option<u32>) -> Option<f64> {
+        if input_tokens.is_none() && output_tokens.is_none() {
+            return None;
+        }
+        let prices = self.prices.read().unwrap_or_else(|e| e.into_inner());
+        let price = lookup(&prices, model?)?;
+        Some(price.cost(input_tokens.unwrap_or(0), output_tokens.unwrap_or(0)))
+    }
+}
+
+impl ModelPricing {
+    pub fn cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        (input_tokens as f64 * self.input_per_1k + output_tokens as f64 * self.output_per_1k) / 1000.0
+    }
+}
+
+fn lookup<'a>(prices: &'a HashMap<String, ModelPricing>, model: &str) -> Option<&'a ModelPricing> {
+    lookup_pattern(prices, model).map(|(_, price)| price)
+}
+
+/// 按模型名查找配置项，返回命中的模式与值
+///
+/// 精确匹配优先，其次是最长的 `前缀*` 通配，最后是 `*` 兜底
+pub fn lookup_pattern<'a, T>(table: &'a HashMap<String, T>, model: &str) -> Option<(&'a str, &'a T)> {
+    if let Some((pattern, value)) = table.get_key_value(model) {
+        return Some((pattern, value));
+    }
+    table
+        .iter()
+        .filter_map(|(pattern, value)| {
+            let prefix = pattern.strip_suffix('*')?;
+            model.starts_with(prefix).then_some((prefix.len(), pattern.as_str(), value))
+        })
+        .max_by_key(|(len, _, _)| *len)
+        .map(|(_, pattern, value)| (pattern, value))
+}
+
+/// 校验价格表：模型名非空，价格为非负有限数
+pub fn validate(prices: &HashMap<String, ModelPricing>) -> Result<(), String> {
+    for (model, price) in prices {
+        if model.trim().is_empty() {
+            return Err("Model name must not be empty".to_string());
+        }
+        for (field, value) in [("input_per_1k", price.input_per_1k), ("output_per_1k", price.output_per_1k)] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(format!("{} for '{}' must be a non-negative number", field, model));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(input: f64, output: f64) -> ModelPricing {
+        ModelPricing { input_per_1k: input, output_per_1k: output }
+    }
+
+    #[test]
+    fn test_lookup_prefers_exact_then_longest_prefix() {
+        let prices = HashMap::from([
+            ("gemini-2.5-pro".to_string(), price(1.0, 2.0)),
+            ("gemini-*".to_string(), price(0.1, 0.2)),
+            ("gemini-2.5-*".to_string(), price(0.5, 1.0)),
+            ("*".to_string(), price(9.0, 9.0)),
+        ]);
+        assert_eq!(lookup(&prices, "gemini-2.5-pro").unwrap().input_per_1k, 1.0);
+        assert_eq!(lookup(&prices, "gemini-2.5-flash").unwrap().input_per_1k, 0.5);
+        assert_eq!(lookup(&prices, "gemini-3-pro").unwrap().input_per_1k, 0.1);
+        assert_eq!(lookup(&prices, "claude-sonnet-4-5").unwrap().input_per_1k, 9.0);
+    }
+
+    #[test]
+    fn test_estimate() {
+        let table = PricingTable::default();
+        table.configure(&HashMap::from([("m".to_string(), price(3.0, 15.0))]));
+        assert_eq!(table.estimate(Some("m"), Some(1000), Some(500)), Some(10.5));
+        assert_eq!(table.estimate(Some("other"), Some(1000), Some(500)), None);
+        assert_eq!(table.estimate(None, Some(1000), None), None);
+        assert_eq!(table.estimate(Some("m"), None, None), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&HashMap::from([("m".to_string(), price(0.0, 1.0))])).is_ok());
+        assert!(validate(&HashMap::from([("m".to_string(), price(-1.0, 1.0))])).is_err());
+        assert!(validate(&HashMap::from([(" ".to_string(), price(1.0, 1.0))])).is_err());
+    }
+}
@@ -26,7 +26,10 @@ pub async fn fetch_project_id(access_token: &str) -> Result<String, String> {
     if !response.status().is_success() {
         let status = response.status();
         let body = response.text().await.unwrap_or_default();
-        return Err(format!("loadCodeAssist 返回错误 {}: {}", status, body));
+        return Err(format!(
+            "loadCodeAssist 返回错误 {}",
+            crate::proxy::upstream::errors::describe(status.as_u16(), &body)
+        ));
     }
     
     let data: Value = response.json()
@@ -45,6 +48,17 @@ pub async fn fetch_project_id(access_token: &str) -> Result<String, String> {
     Ok(mock_id)
 }
 
+/// 添加账号时自动获取 project_id；失败时只记录警告，首次使用该账号时会再次尝试
+pub async fn discover_project_id(access_token: &str, email: &str) -> Option<String> {
+    match fetch_project_id(access_token).await {
+        Ok(project_id) => Some(project_id),
+        Err(e) => {
+            tracing::warn!("Failed to discover the Cloud Code project for {}: {}", email, e);
+            None
+        }
+    }
+}
+
 /// 生成随机 project_id（当无法从 API 获取时使用）
 /// 格式：{形容词}-{名词}-{5位随机字符}
 pub fn generate_mock_project_id() -> String {
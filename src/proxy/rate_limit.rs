@@ -15,6 +15,26 @@ pub enum RateLimitReason {
     Unknown,
 }
 
+impl RateLimitReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::QuotaExhausted => "quota_exhausted",
+            Self::RateLimitExceeded => "rate_limit_exceeded",
+            Self::ServerError => "server_error",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "quota_exhausted" => Self::QuotaExhausted,
+            "rate_limit_exceeded" => Self::RateLimitExceeded,
+            "server_error" => Self::ServerError,
+            _ => Self::Unknown,
+        }
+    }
+}
+
 /// 限流信息
 #[derive(Debug, Clone)]
 pub struct RateLimitInfo {
@@ -66,6 +86,7 @@ impl RateLimitTracker {
     /// * `status` - HTTP 状态码
     /// * `retry_after_header` - Retry-After header 值
     /// * `body` - 错误响应 body
+    /// * `quota_reset_hint` - 配额刷新时记录的距离重置的秒数，仅在 QUOTA_EXHAUSTED 且无其他提示时使用
     pub fn parse_from_error(
         &self,
         quota_group: &str,
@@ -73,6 +94,7 @@ impl RateLimitTracker {
         status: u16,
         retry_after_header: Option<&str>,
         body: &str,
+        quota_reset_hint: Option<u64>,
     ) -> Option<RateLimitInfo> {
         // 支持 429 (限流) 以及 500/503/529 (后端故障软避让)
         if status != 429 && status != 500 && status != 503 && status != 529 {
@@ -100,6 +122,11 @@ impl RateLimitTracker {
             retry_after_sec = self.parse_retry_time_from_body(body);
         }
         
+        // 3.5 配额耗尽时使用配额接口报告的重置时间
+        if retry_after_sec.is_none() && reason == RateLimitReason::QuotaExhausted {
+            retry_after_sec = quota_reset_hint.filter(|s| *s > 0);
+        }
+        
         // 4. 处理默认值与软避让逻辑（根据限流类型设置不同默认值）
         let retry_sec = match retry_after_sec {
             Some(s) => {
@@ -340,6 +367,20 @@ impl RateLimitTracker {
         }
     }
     
+    /// 标记账号冷却至指定时间（如配额重置时间）；已有更晚的记录时保持不变
+    pub fn set_cooldown(&self, quota_group: &str, account_id: &str, reset_time: SystemTime, reason: RateLimitReason) {
+        let now = SystemTime::now();
+        if reset_time <= now {
+            return;
+        }
+        let key = self.make_key(quota_group, account_id);
+        if self.limits.get(&key).is_some_and(|info| info.reset_time >= reset_time) {
+            return;
+        }
+        let retry_after_sec = reset_time.duration_since(now).map(|d| d.as_secs()).unwrap_or(0);
+        self.limits.insert(key, RateLimitInfo { reset_time, retry_after_sec, detected_at: now, reason });
+    }
+    
     /// 清除过期的限流记录
     #[allow(dead_code)]
     pub fn cleanup_expired(&self) -> usize {
@@ -426,7 +467,7 @@ mod tests {
     #[test]
     fn test_get_remaining_wait() {
         let tracker = RateLimitTracker::new();
-        tracker.parse_from_error("gemini", "acc1", 429, Some("30"), "");
+        tracker.parse_from_error("gemini", "acc1", 429, Some("30"), "", None);
         let wait = tracker.get_remaining_wait("gemini", "acc1");
         assert!(wait > 25 && wait <= 30);
     }
@@ -435,9 +476,29 @@ mod tests {
     fn test_safety_buffer() {
         let tracker = RateLimitTracker::new();
         // 如果 API 返回 1s，我们强制设为 2s
-        tracker.parse_from_error("gemini", "acc1", 429, Some("1"), "");
+        tracker.parse_from_error("gemini", "acc1", 429, Some("1"), "", None);
         let wait = tracker.get_remaining_wait("gemini", "acc1");
         // 由于时间流逝，剩余时间可能是 1s 或 2s
         assert!(wait >= 1 && wait <= 2);
     }
+
+    #[test]
+    fn test_quota_reset_hint_and_cooldown() {
+        let tracker = RateLimitTracker::new();
+        let body = r#"{"error":{"details":[{"reason":"QUOTA_EXHAUSTED"}]}}"#;
+        tracker.parse_from_error("claude", "acc1", 429, None, body, Some(600));
+        let wait = tracker.get_remaining_wait("claude", "acc1");
+        assert!(wait > 590 && wait <= 600);
+
+        // 明确的 Retry-After 优先于配额提示
+        tracker.parse_from_error("claude", "acc2", 429, Some("30"), body, Some(600));
+        assert!(tracker.get_remaining_wait("claude", "acc2") <= 30);
+
+        // 冷却只会延长，不会缩短
+        let soon = SystemTime::now() + Duration::from_secs(60);
+        tracker.set_cooldown("claude", "acc1", soon, RateLimitReason::QuotaExhausted);
+        assert!(tracker.get_remaining_wait("claude", "acc1") > 590);
+        tracker.set_cooldown("gemini", "acc1", soon, RateLimitReason::QuotaExhausted);
+        assert!(tracker.is_rate_limited("gemini", "acc1"));
+    }
 }
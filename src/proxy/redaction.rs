@@ -0,0 +1,305 @@
+// 请求日志脱敏
+// 在 ProxyRequestLog 写入内存 / SQLite 之前按规则清除敏感字段，并按存储策略截断或丢弃请求/响应体
+use regex::Regex;
+use serde_json::Value;
+
+use crate::proxy::config::{BodyStorage, LogRedactionConfig};
+use crate::proxy::monitor::ProxyRequestLog;
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    /// 对象键，`*` 匹配任意键
+    Key(String),
+    /// 数组中的每个元素
+    Each,
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    JsonPath(Vec<PathSegment>),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    matcher: Matcher,
+    replacement: String,
+}
+
+/// 编译后的脱敏规则
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    rules: Vec<CompiledRule>,
+    store_bodies: BodyStorage,
+    max_body_bytes: usize,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::from_config(&LogRedactionConfig::default())
+    }
+}
+
+/// 解析 `messages[].content` 形式的路径
+fn parse_path(path: &str) -> Result<Vec<PathSegment>, String> {
+    let mut segments = Vec::new();
+    for part in path.split('.') {
+        let (key, each_count) = match part.find("[]") {
+            Some(pos) => {
+                let suffix = &part[pos..];
+                if suffix.len() % 2 != 0 || !suffix.as_bytes().chunks(2).all(|c| c == b"[]") {
+                    return Err(format!("Invalid JSON path: {}", path));
+                }
+                (&part[..pos], suffix.len() / 2)
+            }
+            None => (part, 0),
+        };
+        if (key.is_empty() && each_count == 0) || key.contains(['[', ']']) {
+            return Err(format!("Invalid JSON path: {}", path));
+        }
+        if !key.is_empty() {
+            segments.push(PathSegment::Key(key.to_string()));
+        }
+        segments.extend(std::iter::repeat_n(PathSegment::Each, each_count));
+    }
+    Ok(segments)
+}
+
+/// 替换路径命中的所有值，返回是否有修改
+fn redact_path(value: &mut Value, segments: &[PathSegment], replacement: &str) -> bool {
+    let Some((first, rest)) = segments.split_first() else {
+        *value = Value::String(replacement.to_string());
+        return true;
+    };
+    // 不能用 any()：需要处理所有元素而不是在首次命中后停止
+    let redact_all = |children: &mut dyn Iterator<Item = &mut Value>| {
+        let mut changed = false;
+        for child in children {
+            changed |= redact_path(child, rest, replacement);
+        }
+        changed
+    };
+    match (first, value) {
+        (PathSegment::Each, Value::Array(items)) => redact_all(&mut items.iter_mut()),
+        (PathSegment::Key(key), Value::Object(map)) if key == "*" => redact_all(&mut map.values_mut()),
+        (PathSegment::Key(key), Value::Object(map)) => match map.get_mut(key) {
+            Some(child) => redact_path(child, rest, replacement),
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// 按字节截断（保证落在字符边界）
+pub(crate) fn truncate(text: String, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...[truncated {} bytes]", &text[..end], text.len() - end)
+}
+
+impl Redactor {
+    /// 编译配置中的规则，非法规则记录警告后跳过
+    pub fn from_config(config: &LogRedactionConfig) -> Self {
+        let mut rules = Vec::new();
+        for rule in &config.rules {
+            let matcher = match (&rule.json_path, &rule.regex) {
+                (Some(path), None) => parse_path(path.trim()).map(Matcher::JsonPath),
+                (None, Some(pattern)) => Regex::new(pattern)
+                    .map(Matcher::Regex)
+                    .map_err(|e| format!("Invalid redaction regex '{}': {}", pattern, e)),
+                _ => Err("Redaction rule needs exactly one of json_path or regex".to_string()),
+            };
+            match matcher {
+                Ok(matcher) => rules.push(CompiledRule {
+                    matcher,
+                    replacement: rule.replacement.clone(),
+                }),
+                Err(e) => tracing::warn!("Ignoring log redaction rule: {}", e),
+            }
+        }
+        Self {
+            rules,
+            store_bodies: config.store_bodies,
+            max_body_bytes: config.max_body_bytes,
+        }
+    }
+
+    /// 对文本应用规则：能解析为 JSON 时先应用 json_path 规则，再对整体应用 regex 规则
+    pub fn redact_text(&self, text: String) -> String {
+        self.redact(text).0
+    }
+
+    /// 同 redact_text，同时返回文本是否被改动
+    fn redact(&self, text: String) -> (String, bool) {
+        if self.rules.is_empty() {
+            return (text, false);
+        }
+
+        let mut text = text;
+        let mut modified = false;
+        let has_path_rules = self.rules.iter().any(|r| matches!(r.matcher, Matcher::JsonPath(_)));
+        if has_path_rules {
+            if let Ok(mut json) = serde_json::from_str::<Value>(&text) {
+                let mut changed = false;
+                for rule in &self.rules {
+                    if let Matcher::JsonPath(segments) = &rule.matcher {
+                        changed |= redact_path(&mut json, segments, &rule.replacement);
+                    }
+                }
+                if changed {
+                    text = json.to_string();
+                    modified = true;
+                }
+            }
+        }
+
+        for rule in &self.rules {
+            if let Matcher::Regex(re) = &rule.matcher {
+                if let std::borrow::Cow::Owned(replaced) = re.replace_all(&text, rule.replacement.as_str()) {
+                    text = replaced;
+                    modified = true;
+                }
+            }
+        }
+        (text, modified)
+    }
+
+    /// 按存储策略处理请求/响应体，返回处理后的内容及是否与原文不同
+    fn apply_body(&self, body: Option<String>) -> (Option<String>, bool) {
+        let Some(body) = body else {
+            return (None, false);
+        };
+        match self.store_bodies {
+            BodyStorage::None => (None, false),
+            BodyStorage::Truncated => {
+                let (text, modified) = self.redact(body);
+                let truncated = text.len() > self.max_body_bytes;
+                (Some(truncate(text, self.max_body_bytes)), modified || truncated)
+            }
+            BodyStorage::Full => {
+                let (text, modified) = self.redact(body);
+                (Some(text), modified)
+            }
+        }
+    }
+
+    /// 处理一条请求日志；错误信息只做规则替换，不受存储策略影响
+    pub fn apply(&self, log: &mut ProxyRequestLog) {
+        let (request_body, modified) = self.apply_body(log.request_body.take());
+        log.request_body = request_body;
+        log.request_body_modified |= modified;
+        log.response_body = self.apply_body(log.response_body.take()).0;
+        log.error = log.error.take().map(|e| self.redact_text(e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::config::RedactionRule;
+
+    fn redactor(store_bodies: BodyStorage, rules: Vec<RedactionRule>) -> Redactor {
+        Redactor::from_config(&LogRedactionConfig {
+            store_bodies,
+            max_body_bytes: 16,
+            rules,
+        })
+    }
+
+    fn rule(json_path: Option<&str>, regex: Option<&str>) -> RedactionRule {
+        RedactionRule {
+            json_path: json_path.map(String::from),
+            regex: regex.map(String::from),
+            replacement: "[REDACTED]".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("messages[].content").unwrap(),
+            vec![PathSegment::Key("messages".into()), PathSegment::Each, PathSegment::Key("content".into())]
+        );
+        assert_eq!(parse_path("[][]").unwrap(), vec![PathSegment::Each, PathSegment::Each]);
+        assert!(parse_path("messages..content").is_err());
+        assert!(parse_path("messages[0]").is_err());
+    }
+
+    #[test]
+    fn test_json_path_and_regex_rules() {
+        let r = redactor(
+            BodyStorage::Full,
+            vec![
+                rule(Some("messages[].content"), None),
+                rule(Some("metadata.*"), None),
+                rule(None, Some(r"(?i)bearer\s+[A-Za-z0-9._\-]+")),
+                // 非法规则被跳过
+                rule(None, Some("(")),
+                rule(Some("a"), Some("b")),
+            ],
+        );
+        assert_eq!(r.rules.len(), 3);
+
+        let body = r#"{"messages":[{"role":"user","content":"my ssn"},{"role":"assistant"}],"metadata":{"a":1,"b":"x"},"model":"m"}"#;
+        let redacted: Value = serde_json::from_str(&r.redact_text(body.to_string())).unwrap();
+        assert_eq!(redacted["messages"][0]["content"], "[REDACTED]");
+        assert_eq!(redacted["messages"][0]["role"], "user");
+        assert!(redacted["messages"][1].get("content").is_none());
+        assert_eq!(redacted["metadata"]["a"], "[REDACTED]");
+        assert_eq!(redacted["model"], "m");
+
+        assert_eq!(r.redact_text("Authorization: Bearer sk-abc.123".to_string()), "Authorization: [REDACTED]");
+    }
+
+    #[test]
+    fn test_body_storage_modes() {
+        let mut log = ProxyRequestLog {
+            id: "1".into(),
+            timestamp: 0,
+            method: "POST".into(),
+            url: "/v1/chat/completions".into(),
+            status: 500,
+            duration: 0,
+            model: None,
+            error: Some("token sk-secret".into()),
+            request_body: Some("é".repeat(20)),
+            response_body: Some("short".into()),
+            input_tokens: None,
+            output_tokens: None,
+            key_id: None,
+            account_email: None,
+            estimated_cost: None,
+            finish_reason: None,
+            replay_of: None,
+            tags: None,
+            client_ip: None,
+            request_id: None,
+            usage_estimated: false,
+            request_body_modified: false,
+            sample_weight: None,
+        };
+
+        let mut truncated = log.clone();
+        redactor(BodyStorage::Truncated, vec![]).apply(&mut truncated);
+        assert_eq!(truncated.request_body.as_deref(), Some("éééééééé...[truncated 24 bytes]"));
+        assert_eq!(truncated.response_body.as_deref(), Some("short"));
+        assert!(truncated.request_body_modified);
+
+        // 规则未命中的请求体保持原样，可以重放
+        let mut untouched = log.clone();
+        redactor(BodyStorage::Full, vec![rule(Some("messages[].content"), None)]).apply(&mut untouched);
+        assert!(!untouched.request_body_modified);
+        let mut redacted = ProxyRequestLog { request_body: Some(r#"{"messages":[{"content":"x"}]}"#.into()), ..log.clone() };
+        redactor(BodyStorage::Full, vec![rule(Some("messages[].content"), None)]).apply(&mut redacted);
+        assert!(redacted.request_body_modified);
+
+        redactor(BodyStorage::None, vec![rule(None, Some(r"sk-\w+"))]).apply(&mut log);
+        assert!(log.request_body.is_none() && log.response_body.is_none());
+        assert_eq!(log.error.as_deref(), Some("token [REDACTED]"));
+    }
+}
@@ -0,0 +1,159 @@
+// 配置热重载
+// 由 POST /api/config/reload 或 SIGHUP 触发：重新读取配置文件并应用可热更新的部分，
+// 不重启服务器，进行中的 SSE 流不受影响
+use crate::modules;
+use crate::proxy::server::AppState;
+use crate::proxy::{ProxyConfig, ProxySecurityConfig};
+
+/// 需要重启才能生效的配置项
+const RESTART_REQUIRED: &str = "port / allow_lan_access bind address / base_path / log_retention / log_archive / storage";
+
+/// 重新加载配置文件并应用到运行中的服务
+pub async fn reload_config(state: &AppState) -> Result<ProxyConfig, String> {
+    let mut config = modules::config::load_web_config()?;
+    if !modules::config::allow_invalid_config() {
+        let problems = modules::config::check_web_config()?;
+        if !problems.is_empty() {
+            return Err(format!("配置有问题，未重载: {}", problems.join("; ")));
+        }
+    }
+    modules::config::apply_env_overrides(&mut config);
+    apply_config(state, &config).await;
+
+    if config.port != state.bind_port {
+        tracing::warn!(
+            "配置中的端口 {} 与当前监听端口 {} 不一致，需重启后生效 ({})",
+            config.port,
+            state.bind_port,
+            RESTART_REQUIRED
+        );
+    }
+    let base_path = crate::proxy::middleware::base_path::normalize(&config.base_path);
+    if base_path.is_ok_and(|base| base != crate::proxy::middleware::base_path::get()) {
+        tracing::warn!("配置中的 base_path 已修改，需重启后生效");
+    }
+    tracing::info!("配置已热重载");
+    Ok(config)
+}
+
+/// 将配置应用到各个运行时状态
+async fn apply_config(state: &AppState, config: &ProxyConfig) {
+    // 安全策略 (auth_mode / api_key)
+    let security = ProxySecurityConfig::from_proxy_config(config);
+    state.session_manager.configure(&security.session);
+    *state.security.write().await = security;
+
+    // 上游代理 / User-Agent
+    *state.upstream_proxy.write().await = config.upstream_proxy.clone();
+    state.upstream.update_proxy_config(&config.upstream_proxy).await;
+    state.upstream.update_retry_config(config.retry.clone()).await;
+    state.upstream.update_timeouts(config.upstream_timeouts.clone()).await;
+    if let Err(e) = state.upstream.update_endpoints(&config.upstream_endpoints, false).await {
+        tracing::warn!("upstream_endpoints 配置无效，保留当前端点: {}", e);
+    }
+
+    // 模型映射
+    *state.anthropic_mapping.write().await = config.anthropic_mapping.clone();
+    *state.openai_mapping.write().await = config.openai_mapping.clone();
+    *state.custom_mapping.write().await = config.custom_mapping.clone();
+
+    // 调度策略
+    state.token_manager.update_sticky_config(config.scheduling.clone()).await;
+    // 账号可能已由命令行添加 / 删除
+    if let Err(e) = state.token_manager.load_accounts().await {
+        tracing::warn!("重新加载账号失败: {}", e);
+    }
+
+    // 日志
+    state.monitor.set_enabled(config.enable_logging);
+    state.monitor.set_redaction(&config.log_redaction);
+    state.monitor.set_capture(&config.monitor);
+    if let Err(e) = modules::logger::set_log_level(&config.log_level) {
+        tracing::warn!("{}", e);
+    }
+
+    crate::proxy::response_cache::RESPONSE_CACHE.configure(&config.response_cache);
+    match crate::proxy::files::validate(&config.files) {
+        Ok(()) => crate::proxy::files::FILES.configure(&config.files),
+        Err(e) => tracing::warn!("files 配置无效，保留当前文件配置: {}", e),
+    }
+    match crate::proxy::batches::validate(&config.batch) {
+        Ok(()) => crate::proxy::batches::BATCHES.configure(&config.batch),
+        Err(e) => tracing::warn!("batch 配置无效，保留当前批处理配置: {}", e),
+    }
+    match crate::proxy::middleware::maintenance::validate(&config.maintenance) {
+        Ok(()) => crate::proxy::middleware::maintenance::MAINTENANCE.configure(&config.maintenance),
+        Err(e) => tracing::warn!("maintenance 配置无效，保留当前维护窗口: {}", e),
+    }
+    match modules::backup::validate(&config.backup) {
+        Ok(()) => modules::backup::BACKUPS.configure(&config.backup),
+        Err(e) => tracing::warn!("backup 配置无效，保留当前备份配置: {}", e),
+    }
+    match crate::proxy::concurrency::validate(&config.concurrency) {
+        Ok(()) => crate::proxy::concurrency::CONCURRENCY.configure(&config.concurrency),
+        Err(e) => tracing::warn!("concurrency 配置无效，保留当前并发限制: {}", e),
+    }
+    crate::proxy::model_registry::MODEL_REGISTRY.configure(&config.model_registry);
+    match crate::proxy::upstream::shadow::validate(&config.shadow) {
+        Ok(()) => crate::proxy::upstream::shadow::SHADOW.configure(&config.shadow),
+        Err(e) => tracing::warn!("shadow 配置无效，保留当前影子流量设置: {}", e),
+    }
+    modules::alerts::ALERTS.configure(&config.alerts);
+    modules::usage_buffer::USAGE_BUFFER.configure(&config.usage_flush);
+    modules::api_keys::set_deleted_key_retention_days(config.deleted_key_retention_days);
+    match crate::proxy::pricing::validate(&config.pricing) {
+        Ok(()) => crate::proxy::pricing::PRICING.configure(&config.pricing),
+        Err(e) => tracing::warn!("pricing 配置无效，保留当前价格表: {}", e),
+    }
+    match crate::proxy::auth_guard::validate(&config.auth_lockout) {
+        Ok(()) => crate::proxy::auth_guard::AUTH_GUARD.configure(&config.auth_lockout),
+        Err(e) => tracing::warn!("auth_lockout 配置无效，保留当前锁定策略: {}", e),
+    }
+    crate::proxy::otel::TRACER.configure(&config.otel);
+    crate::proxy::cluster::CLUSTER.configure(&config.cluster);
+    match crate::proxy::upstream::profiles::validate(&config.client_profiles) {
+        Ok(()) => crate::proxy::upstream::profiles::CLIENT_PROFILES.configure(&config.client_profiles),
+        Err(e) => tracing::warn!("client_profiles 配置无效，保留当前客户端 profile: {}", e),
+    }
+    match crate::modules::oauth::validate(&config.oauth_clients) {
+        Ok(()) => crate::modules::oauth::OAUTH_CLIENTS.configure(&config.oauth_clients),
+        Err(e) => tracing::warn!("oauth_clients 配置无效，保留当前 OAuth 客户端: {}", e),
+    }
+    crate::proxy::tls::reload(&config.tls);
+    crate::proxy::handlers::gemini::set_passthrough(config.gemini_passthrough);
+    crate::proxy::handlers::openai::set_strict_stream(config.openai_strict_stream);
+    crate::proxy::handlers::openai::set_json_repair(config.openai_json_repair);
+    crate::proxy::mappers::openai::thinking::set_mode(config.openai_thinking);
+    crate::proxy::common::sse::set_keepalive_interval(config.sse_keepalive_seconds);
+    crate::proxy::middleware::body_limit::set_max_request_body(config.max_request_body_bytes);
+    crate::proxy::middleware::compression::set_enabled(config.compress_responses);
+
+    if let Err(e) = modules::quota::set_budget_timezone(&config.token_budget_timezone) {
+        tracing::warn!("{}", e);
+    }
+}
+
+/// 监听 SIGHUP 并触发热重载（仅 Unix）
+#[cfg(unix)]
+pub fn spawn_sighup_listener(state: AppState) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::warn!("无法监听 SIGHUP: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            tracing::info!("收到 SIGHUP，重新加载配置");
+            if let Err(e) = reload_config(&state).await {
+                tracing::error!("配置热重载失败: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_sighup_listener(_state: AppState) {}
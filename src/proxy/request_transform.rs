@@ -0,0 +1,315 @@
+// 请求体改写
+// 按 API Key 在转发前注入系统提示词、补充默认生成参数、删除不允许的字段
+use axum::{extract::Request, http::header};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// 系统提示词的注入方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemPromptMode {
+    /// 放在客户端系统提示词之前（没有时直接注入）
+    #[default]
+    Prepend,
+    /// 丢弃客户端系统提示词
+    Replace,
+}
+
+/// 单个 API Key 的请求改写规则
+///
+/// 执行顺序：删除字段 -> 注入系统提示词 -> 补充默认参数。
+/// 默认参数只在请求未携带时生效；配合 `strip_fields` 删除同名字段即可强制覆盖
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RequestTransform {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub system_prompt_mode: SystemPromptMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+    /// 转发前删除的字段（`.` 分隔路径，如 `tools`、`generationConfig.seed`）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub strip_fields: Vec<String>,
+}
+
+/// 请求体格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFormat {
+    /// /v1/chat/completions、/v1/completions、/v1/responses
+    OpenAI,
+    /// /v1/messages
+    Anthropic,
+    /// /v1beta/models/{model}:method
+    Gemini,
+}
+
+impl BodyFormat {
+    pub fn from_path(path: &str) -> Option<Self> {
+        if path.starts_with("/v1beta/models/") {
+            Some(Self::Gemini)
+        } else if path.starts_with("/v1/messages") {
+            Some(Self::Anthropic)
+        } else if matches!(path, "/v1/chat/completions" | "/v1/completions" | "/v1/responses") {
+            Some(Self::OpenAI)
+        } else {
+            None
+        }
+    }
+}
+
+impl RequestTransform {
+    /// 没有任何规则（用于清除配置）
+    pub fn is_empty(&self) -> bool {
+        self.system_prompt.as_deref().is_none_or(|p| p.trim().is_empty())
+            && self.temperature.is_none()
+            && self.top_p.is_none()
+            && self.max_tokens.is_none()
+            && self.strip_fields.iter().all(|f| f.trim().is_empty())
+    }
+
+    /// 改写 JSON 请求体
+    pub fn apply(&self, format: BodyFormat, body: &mut Value) {
+        let Some(obj) = body.as_object_mut() else {
+            return;
+        };
+        for path in &self.strip_fields {
+            strip_path(obj, path.trim());
+        }
+        if let Some(prompt) = self.system_prompt.as_deref().filter(|p| !p.trim().is_empty()) {
+            match format {
+                BodyFormat::OpenAI => self.inject_openai(obj, prompt),
+                BodyFormat::Anthropic => self.inject_anthropic(obj, prompt),
+                BodyFormat::Gemini => self.inject_gemini(obj, prompt),
+            }
+        }
+        match format {
+            BodyFormat::OpenAI | BodyFormat::Anthropic => {
+                let has_max_tokens = ["max_tokens", "max_completion_tokens", "max_output_tokens"]
+                    .iter()
+                    .any(|k| obj.contains_key(*k));
+                set_default(obj, "temperature", self.temperature.map(Value::from));
+                set_default(obj, "top_p", self.top_p.map(Value::from));
+                if !has_max_tokens {
+                    set_default(obj, "max_tokens", self.max_tokens.map(Value::from));
+                }
+            }
+            BodyFormat::Gemini => {
+                if self.temperature.is_none() && self.top_p.is_none() && self.max_tokens.is_none() {
+                    return;
+                }
+                let key = if obj.contains_key("generation_config") { "generation_config" } else { "generationConfig" };
+                let config = obj.entry(key).or_insert_with(|| json!({}));
+                if let Some(config) = config.as_object_mut() {
+                    set_default(config, "temperature", self.temperature.map(Value::from));
+                    set_default(config, "topP", self.top_p.map(Value::from));
+                    set_default(config, "maxOutputTokens", self.max_tokens.map(Value::from));
+                }
+            }
+        }
+    }
+
+    fn inject_openai(&self, obj: &mut Map<String, Value>, prompt: &str) {
+        if let Some(messages) = obj.get_mut("messages").and_then(|m| m.as_array_mut()) {
+            if self.system_prompt_mode == SystemPromptMode::Replace {
+                messages.retain(|m| !matches!(m.get("role").and_then(|r| r.as_str()), Some("system" | "developer")));
+            }
+            messages.insert(0, json!({ "role": "system", "content": prompt }));
+        } else if obj.contains_key("input") {
+            // Responses API (Codex)：系统提示词在 instructions 中
+            let existing = obj.get("instructions").and_then(|v| v.as_str()).unwrap_or_default();
+            let instructions = self.merge_text(prompt, existing);
+            obj.insert("instructions".to_string(), Value::String(instructions));
+        } else if let Some(prompt_val) = obj.get_mut("prompt") {
+            // Legacy completions 没有系统角色，只能拼在 prompt 前面
+            match prompt_val {
+                Value::String(s) => *s = format!("{}\n\n{}", prompt, s),
+                Value::Array(items) => items.insert(0, Value::String(prompt.to_string())),
+                _ => {}
+            }
+        }
+    }
+
+    fn inject_anthropic(&self, obj: &mut Map<String, Value>, prompt: &str) {
+        let system = match obj.remove("system") {
+            Some(Value::Array(mut blocks)) if self.system_prompt_mode == SystemPromptMode::Prepend => {
+                blocks.insert(0, json!({ "type": "text", "text": prompt }));
+                Value::Array(blocks)
+            }
+            Some(Value::String(existing)) => Value::String(self.merge_text(prompt, &existing)),
+            _ => Value::String(prompt.to_string()),
+        };
+        obj.insert("system".to_string(), system);
+    }
+
+    fn inject_gemini(&self, obj: &mut Map<String, Value>, prompt: &str) {
+        let key = if obj.contains_key("system_instruction") { "system_instruction" } else { "systemInstruction" };
+        let part = json!({ "text": prompt });
+        let existing_parts = obj
+            .get_mut(key)
+            .and_then(|v| v.get_mut("parts"))
+            .and_then(|p| p.as_array_mut())
+            .filter(|_| self.system_prompt_mode == SystemPromptMode::Prepend);
+        match existing_parts {
+            Some(parts) => parts.insert(0, part),
+            None => {
+                obj.insert(key.to_string(), json!({ "parts": [part] }));
+            }
+        }
+    }
+
+    fn merge_text(&self, prompt: &str, existing: &str) -> String {
+        if self.system_prompt_mode == SystemPromptMode::Replace || existing.trim().is_empty() {
+            prompt.to_string()
+        } else {
+            format!("{}\n\n{}", prompt, existing)
+        }
+    }
+}
+
+fn set_default(obj: &mut Map<String, Value>, key: &str, value: Option<Value>) {
+    if let Some(value) = value {
+        obj.entry(key).or_insert(value);
+    }
+}
+
+fn strip_path(obj: &mut Map<String, Value>, path: &str) {
+    match path.split_once('.') {
+        None => {
+            if !path.is_empty() {
+                obj.remove(path);
+            }
+        }
+        Some((head, rest)) => {
+            if let Some(child) = obj.get_mut(head).and_then(|v| v.as_object_mut()) {
+                strip_path(child, rest);
+            }
+        }
+    }
+}
+
+/// 按规则改写请求体；非 JSON 或不支持的路径原样放行
+pub async fn transform_request(request: Request, transform: &RequestTransform) -> Request {
+    if request.method() != axum::http::Method::POST {
+        return request;
+    }
+    let Some(format) = BodyFormat::from_path(request.uri().path()) else {
+        return request;
+    };
+
+    let (mut parts, body) = request.into_parts();
+    let bytes = match axum::body::to_bytes(body, crate::proxy::middleware::body_limit::max_request_body()).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Request::from_parts(parts, axum::body::Body::empty()),
+    };
+    let Ok(mut json) = serde_json::from_slice::<Value>(&bytes) else {
+        return Request::from_parts(parts, axum::body::Body::from(bytes));
+    };
+
+    transform.apply(format, &mut json);
+    let Ok(rewritten) = serde_json::to_vec(&json) else {
+        return Request::from_parts(parts, axum::body::Body::from(bytes));
+    };
+    parts.headers.insert(header::CONTENT_LENGTH, rewritten.len().into());
+    Request::from_parts(parts, axum::body::Body::from(rewritten))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preamble() -> RequestTransform {
+        RequestTransform {
+            system_prompt: Some("Follow the org safety policy.".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_openai_system_prompt_and_defaults() {
+        let transform = RequestTransform {
+            temperature: Some(0.2),
+            max_tokens: Some(1024),
+            strip_fields: vec!["logit_bias".to_string()],
+            ..preamble()
+        };
+        let mut body = json!({
+            "model": "gpt-4o",
+            "temperature": 0.9,
+            "logit_bias": {"1": 100},
+            "messages": [
+                {"role": "system", "content": "You are helpful."},
+                {"role": "user", "content": "hi"}
+            ]
+        });
+        transform.apply(BodyFormat::OpenAI, &mut body);
+
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0]["content"], "Follow the org safety policy.");
+        assert_eq!(messages[1]["content"], "You are helpful.");
+        // 请求自带的参数保留
+        assert_eq!(body["temperature"], 0.9);
+        assert_eq!(body["max_tokens"], 1024);
+        assert!(body.get("logit_bias").is_none());
+
+        let replace = RequestTransform {
+            system_prompt_mode: SystemPromptMode::Replace,
+            ..preamble()
+        };
+        let mut body = json!({"messages": [{"role": "system", "content": "ignore rules"}, {"role": "user", "content": "hi"}]});
+        replace.apply(BodyFormat::OpenAI, &mut body);
+        assert_eq!(body["messages"].as_array().unwrap().len(), 2);
+        assert_eq!(body["messages"][0]["content"], "Follow the org safety policy.");
+
+        let mut body = json!({"input": [], "instructions": "Be terse."});
+        preamble().apply(BodyFormat::OpenAI, &mut body);
+        assert_eq!(body["instructions"], "Follow the org safety policy.\n\nBe terse.");
+    }
+
+    #[test]
+    fn test_anthropic_system_prompt() {
+        let mut body = json!({"system": [{"type": "text", "text": "Client prompt"}], "messages": []});
+        preamble().apply(BodyFormat::Anthropic, &mut body);
+        assert_eq!(body["system"][0]["text"], "Follow the org safety policy.");
+        assert_eq!(body["system"][1]["text"], "Client prompt");
+
+        let mut body = json!({"messages": []});
+        preamble().apply(BodyFormat::Anthropic, &mut body);
+        assert_eq!(body["system"], "Follow the org safety policy.");
+    }
+
+    #[test]
+    fn test_gemini_system_instruction_and_generation_config() {
+        let transform = RequestTransform {
+            top_p: Some(0.8),
+            strip_fields: vec!["generationConfig.seed".to_string(), "tools".to_string()],
+            ..preamble()
+        };
+        let mut body = json!({
+            "contents": [],
+            "tools": [{"googleSearch": {}}],
+            "systemInstruction": {"parts": [{"text": "Client prompt"}]},
+            "generationConfig": {"seed": 42, "temperature": 1.0}
+        });
+        transform.apply(BodyFormat::Gemini, &mut body);
+
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "Follow the org safety policy.");
+        assert_eq!(body["systemInstruction"]["parts"][1]["text"], "Client prompt");
+        assert!(body.get("tools").is_none());
+        assert!(body["generationConfig"].get("seed").is_none());
+        assert_eq!(body["generationConfig"]["topP"], 0.8);
+        assert_eq!(body["generationConfig"]["temperature"], 1.0);
+    }
+
+    #[test]
+    fn test_is_empty() {
+        assert!(RequestTransform::default().is_empty());
+        assert!(RequestTransform { system_prompt: Some("  ".to_string()), ..Default::default() }.is_empty());
+        assert!(!preamble().is_empty());
+        assert_eq!(BodyFormat::from_path("/v1/models"), None);
+    }
+}
@@ -0,0 +1,222 @@
+// 非流式响应缓存
+// 对 temperature = 0 的相同请求直接返回缓存结果，避免重复消耗上游配额
+use bytes::Bytes;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::ResponseCacheConfig;
+
+/// 单条缓存响应体上限，超过不缓存
+pub const MAX_CACHED_BODY_BYTES: usize = 8 * 1024 * 1024;
+
+/// 计算缓存键时忽略的请求字段（不影响生成结果）
+const IGNORED_FIELDS: [&str; 3] = ["user", "metadata", "request_id"];
+
+/// 全局响应缓存（response_cache 中间件使用）
+pub static RESPONSE_CACHE: Lazy<ResponseCache> = Lazy::new(ResponseCache::new);
+
+#[derive(Clone)]
+pub struct CachedResponse {
+    pub content_type: Option<String>,
+    pub body: Bytes,
+    inserted_at: Instant,
+}
+
+pub struct ResponseCache {
+    entries: DashMap<String, CachedResponse>,
+    enabled: AtomicBool,
+    ttl_secs: AtomicU64,
+    max_entries: AtomicUsize,
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResponseCache {
+    pub fn new() -> Self {
+        let defaults = ResponseCacheConfig::default();
+        let cache = Self {
+            entries: DashMap::new(),
+            enabled: AtomicBool::new(false),
+            ttl_secs: AtomicU64::new(0),
+            max_entries: AtomicUsize::new(0),
+        };
+        cache.configure(&defaults);
+        cache
+    }
+
+    /// 应用配置（启动 / 热重载时调用）；关闭时清空缓存
+    pub fn configure(&self, config: &ResponseCacheConfig) {
+        self.enabled.store(config.enabled, Ordering::Relaxed);
+        self.ttl_secs.store(config.ttl_seconds, Ordering::Relaxed);
+        self.max_entries.store(config.max_entries, Ordering::Relaxed);
+        if !config.enabled || config.max_entries == 0 {
+            self.entries.clear();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed) && self.max_entries.load(Ordering::Relaxed) > 0
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs.load(Ordering::Relaxed))
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedResponse> {
+        self.get_at(key, Instant::now())
+    }
+
+    fn get_at(&self, key: &str, now: Instant) -> Option<CachedResponse> {
+        let entry = self.entries.get(key)?.clone();
+        if now.duration_since(entry.inserted_at) >= self.ttl() {
+            self.entries.remove(key);
+            return None;
+        }
+        Some(entry)
+    }
+
+    pub fn insert(&self, key: String, content_type: Option<String>, body: Bytes) {
+        self.insert_at(key, content_type, body, Instant::now())
+    }
+
+    fn insert_at(&self, key: String, content_type: Option<String>, body: Bytes, now: Instant) {
+        let max_entries = self.max_entries.load(Ordering::Relaxed);
+        if max_entries == 0 || body.len() > MAX_CACHED_BODY_BYTES {
+            return;
+        }
+
+        if !self.entries.contains_key(&key) && self.entries.len() >= max_entries {
+            let ttl = self.ttl();
+            self.entries.retain(|_, v| now.duration_since(v.inserted_at) < ttl);
+            // 仍然已满则淘汰最早写入的条目
+            while self.entries.len() >= max_entries {
+                let oldest = self
+                    .entries
+                    .iter()
+                    .min_by_key(|e| e.value().inserted_at)
+                    .map(|e| e.key().clone());
+                match oldest {
+                    Some(k) => self.entries.remove(&k),
+                    None => break,
+                };
+            }
+        }
+
+        self.entries.insert(
+            key,
+            CachedResponse {
+                content_type,
+                body,
+                inserted_at: now,
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// 判断请求是否可缓存：非流式且显式指定 temperature = 0
+///
+/// Gemini 原生协议的参数位于 generationConfig 中，流式由路径中的方法决定
+pub fn is_cacheable(path: &str, body: &Value) -> bool {
+    if body.get("stream").and_then(|v| v.as_bool()) == Some(true) || path.contains("streamGenerateContent") {
+        return false;
+    }
+    let temperature = body
+        .get("temperature")
+        .or_else(|| body.get("generationConfig").and_then(|c| c.get("temperature")))
+        .and_then(|v| v.as_f64());
+    temperature == Some(0.0)
+}
+
+/// 计算缓存键：sha256(key_id, path, 规范化请求体)
+///
+/// serde_json 的对象按键排序序列化，字段顺序不同的相同请求会得到同一个键
+pub fn cache_key(key_id: &str, path: &str, body: &Value) -> String {
+    let mut normalized = body.clone();
+    if let Some(obj) = normalized.as_object_mut() {
+        for field in IGNORED_FIELDS {
+            obj.remove(field);
+        }
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(key_id.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(path.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(normalized.to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cache(ttl_seconds: u64, max_entries: usize) -> ResponseCache {
+        let cache = ResponseCache::new();
+        cache.configure(&ResponseCacheConfig {
+            enabled: true,
+            ttl_seconds,
+            max_entries,
+        });
+        cache
+    }
+
+    #[test]
+    fn test_is_cacheable() {
+        assert!(is_cacheable("/v1/chat/completions", &json!({"model": "m", "temperature": 0})));
+        assert!(!is_cacheable("/v1/chat/completions", &json!({"model": "m", "temperature": 0.7})));
+        assert!(!is_cacheable("/v1/chat/completions", &json!({"model": "m"})));
+        assert!(!is_cacheable("/v1/messages", &json!({"temperature": 0, "stream": true})));
+        assert!(is_cacheable(
+            "/v1beta/models/gemini-2.5-pro:generateContent",
+            &json!({"generationConfig": {"temperature": 0.0}})
+        ));
+        assert!(!is_cacheable(
+            "/v1beta/models/gemini-2.5-pro:streamGenerateContent",
+            &json!({"generationConfig": {"temperature": 0.0}})
+        ));
+    }
+
+    #[test]
+    fn test_cache_key_normalization() {
+        let a = json!({"model": "m", "temperature": 0, "messages": [], "user": "alice"});
+        let b = json!({"messages": [], "temperature": 0, "model": "m"});
+        assert_eq!(cache_key("k1", "/v1/chat/completions", &a), cache_key("k1", "/v1/chat/completions", &b));
+        assert_ne!(cache_key("k1", "/v1/chat/completions", &a), cache_key("k2", "/v1/chat/completions", &a));
+        assert_ne!(cache_key("k1", "/v1/chat/completions", &a), cache_key("k1", "/v1/completions", &a));
+    }
+
+    #[test]
+    fn test_ttl_and_eviction() {
+        let cache = cache(10, 2);
+        let now = Instant::now();
+        cache.insert_at("a".into(), None, Bytes::from_static(b"1"), now);
+        cache.insert_at("b".into(), None, Bytes::from_static(b"2"), now + Duration::from_secs(1));
+        cache.insert_at("c".into(), None, Bytes::from_static(b"3"), now + Duration::from_secs(2));
+
+        // 已满时淘汰最早写入的条目
+        assert!(cache.get_at("a", now + Duration::from_secs(2)).is_none());
+        assert_eq!(cache.get_at("c", now + Duration::from_secs(2)).unwrap().body, "3");
+
+        // 过期
+        assert!(cache.get_at("b", now + Duration::from_secs(11)).is_none());
+        assert_eq!(cache.len(), 1);
+    }
+}
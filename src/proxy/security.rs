@@ -1,10 +1,18 @@
-use crate::proxy::config::{ProxyAuthMode, ProxyConfig};
+use crate::proxy::config::{CookieSameSite, ProxyAuthMode, ProxyConfig, SessionConfig};
+use crate::proxy::ip_filter::{self, IpNet};
+use crate::proxy::middleware::cors::CorsPolicy;
+use std::net::IpAddr;
 
 #[derive(Debug, Clone)]
 pub struct ProxySecurityConfig {
     pub auth_mode: ProxyAuthMode,
     pub api_key: String,
     pub allow_lan_access: bool,
+    pub ip_allowlist: Vec<IpNet>,
+    pub ip_denylist: Vec<IpNet>,
+    pub trusted_proxies: Vec<IpNet>,
+    pub cors: CorsPolicy,
+    pub session: SessionConfig,
 }
 
 impl ProxySecurityConfig {
@@ -13,6 +21,17 @@ impl ProxySecurityConfig {
             auth_mode: config.auth_mode.clone(),
             api_key: config.api_key.clone(),
             allow_lan_access: config.allow_lan_access,
+            ip_allowlist: parse_ip_list("ip_allowlist", &config.ip_allowlist),
+            ip_denylist: parse_ip_list("ip_denylist", &config.ip_denylist),
+            trusted_proxies: parse_ip_list("trusted_proxies", &config.trusted_proxies),
+            cors: CorsPolicy::from_config(&config.cors),
+            session: match validate_session(&config.session) {
+                Ok(()) => config.session.clone(),
+                Err(e) => {
+                    tracing::warn!("session 配置无效，使用默认值: {}", e);
+                    SessionConfig::default()
+                }
+            },
         }
     }
 
@@ -28,33 +47,121 @@ impl ProxySecurityConfig {
             ref other => other.clone(),
         }
     }
+
+    /// 全局 IP 规则检查：黑名单优先，白名单为空时不限制
+    ///
+    /// 存在规则但无法确定客户端地址时拒绝
+    pub fn is_ip_allowed(&self, ip: Option<IpAddr>) -> bool {
+        if self.ip_allowlist.is_empty() && self.ip_denylist.is_empty() {
+            return true;
+        }
+        let Some(ip) = ip else {
+            return false;
+        };
+        if ip_filter::matches_any(&self.ip_denylist, ip) {
+            return false;
+        }
+        self.ip_allowlist.is_empty() || ip_filter::matches_any(&self.ip_allowlist, ip)
+    }
+}
+
+/// 校验 session 配置
+pub fn validate_session(config: &SessionConfig) -> Result<(), String> {
+    if config.ttl_hours == 0 {
+        return Err("ttl_hours must be greater than 0".to_string());
+    }
+    if config.cookie_name.is_empty()
+        || !config.cookie_name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(format!("invalid cookie_name `{}`: use letters, digits, `_` or `-`", config.cookie_name));
+    }
+    if !config.cookie_path.starts_with('/')
+        || config.cookie_path.chars().any(|c| c == ';' || c.is_whitespace() || c.is_control())
+    {
+        return Err(format!("invalid cookie_path `{}`: must start with `/`", config.cookie_path));
+    }
+    if config.cookie_same_site == CookieSameSite::None && config.cookie_secure == Some(false) {
+        return Err("cookie_same_site `none` requires cookie_secure".to_string());
+    }
+    Ok(())
+}
+
+/// 逐条解析，非法条目记录警告后跳过
+fn parse_ip_list(field: &str, entries: &[String]) -> Vec<IpNet> {
+    entries
+        .iter()
+        .filter(|e| !e.trim().is_empty())
+        .filter_map(|e| match e.parse::<IpNet>() {
+            Ok(net) => Some(net),
+            Err(err) => {
+                tracing::warn!("Ignoring invalid {} entry: {}", field, err);
+                None
+            }
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn security(auth_mode: ProxyAuthMode, allow_lan_access: bool) -> ProxySecurityConfig {
+        ProxySecurityConfig {
+            auth_mode,
+            api_key: "sk-test".to_string(),
+            allow_lan_access,
+            ip_allowlist: Vec::new(),
+            ip_denylist: Vec::new(),
+            trusted_proxies: Vec::new(),
+            cors: CorsPolicy::default(),
+            session: SessionConfig::default(),
+        }
+    }
+
     #[test]
     fn auto_mode_resolves_off_for_local_only() {
-        let s = ProxySecurityConfig {
-            auth_mode: ProxyAuthMode::Auto,
-            api_key: "sk-test".to_string(),
-            allow_lan_access: false,
-        };
+        let s = security(ProxyAuthMode::Auto, false);
         assert!(matches!(s.effective_auth_mode(), ProxyAuthMode::Off));
     }
 
     #[test]
     fn auto_mode_resolves_all_except_health_for_lan() {
-        let s = ProxySecurityConfig {
-            auth_mode: ProxyAuthMode::Auto,
-            api_key: "sk-test".to_string(),
-            allow_lan_access: true,
-        };
+        let s = security(ProxyAuthMode::Auto, true);
         assert!(matches!(
             s.effective_auth_mode(),
             ProxyAuthMode::AllExceptHealth
         ));
     }
-}
 
+    #[test]
+    fn ip_rules_deny_takes_precedence() {
+        let mut s = security(ProxyAuthMode::Off, true);
+        assert!(s.is_ip_allowed(None));
+
+        s.ip_allowlist = vec!["10.0.0.0/8".parse().unwrap()];
+        s.ip_denylist = vec!["10.0.0.66".parse().unwrap()];
+        assert!(s.is_ip_allowed(Some("10.1.1.1".parse().unwrap())));
+        assert!(!s.is_ip_allowed(Some("10.0.0.66".parse().unwrap())));
+        assert!(!s.is_ip_allowed(Some("8.8.8.8".parse().unwrap())));
+        assert!(!s.is_ip_allowed(None));
+    }
+
+    #[test]
+    fn session_config_validation() {
+        let mut config = SessionConfig::default();
+        assert!(validate_session(&config).is_ok());
+
+        config.cookie_path = "/antiproxy".to_string();
+        config.cookie_same_site = CookieSameSite::None;
+        assert!(validate_session(&config).is_ok());
+        config.cookie_secure = Some(false);
+        assert!(validate_session(&config).is_err());
+
+        config = SessionConfig { cookie_name: "bad name".to_string(), ..Default::default() };
+        assert!(validate_session(&config).is_err());
+        config = SessionConfig { cookie_path: "antiproxy".to_string(), ..Default::default() };
+        assert!(validate_session(&config).is_err());
+        config = SessionConfig { ttl_hours: 0, ..Default::default() };
+        assert!(validate_session(&config).is_err());
+    }
+}
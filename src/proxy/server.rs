@@ -2,12 +2,13 @@ use crate::proxy::TokenManager;
 use axum::{
     extract::DefaultBodyLimit,
     http::StatusCode,
-    response::{IntoResponse, Json, Response},
-    routing::{get, post},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use std::sync::Arc;
 use tokio::sync::oneshot;
+use tower::ServiceExt;
 use tower_http::trace::TraceLayer;
 use tower_http::services::ServeDir;
 use tracing::{debug, error};
@@ -43,6 +44,8 @@ pub struct AppState {
     pub webauthn_manager: Arc<crate::modules::webauthn::WebAuthnManager>,
     /// Session 管理器
     pub session_manager: Arc<crate::modules::webauthn::SessionManager>,
+    /// 反代安全配置（WebSocket 消息复用 auth 中间件时使用）
+    pub security: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
 }
 
 /// Axum 服务器实例
@@ -53,6 +56,7 @@ pub struct AxumServer {
     custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
     proxy_state: Arc<tokio::sync::RwLock<crate::proxy::config::UpstreamProxyConfig>>,
     security_state: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
+    app_state: AppState,
 }
 
 impl AxumServer {
@@ -82,12 +86,18 @@ impl AxumServer {
     pub async fn update_security(&self, config: &crate::proxy::config::ProxyConfig) {
         let mut sec = self.security_state.write().await;
         *sec = crate::proxy::ProxySecurityConfig::from_proxy_config(config);
+        self.app_state.session_manager.configure(&sec.session);
         tracing::info!("反代服务安全配置已热更新");
     }
 
+    /// 应用状态（供 SIGHUP 热重载等后台任务使用）
+    pub fn app_state(&self) -> AppState {
+        self.app_state.clone()
+    }
+
     /// 启动 Axum 服务器
     pub async fn start(
-        host: String,
+        listeners: Vec<crate::proxy::listener::Listener>,
         port: u16,
         token_manager: Arc<TokenManager>,
         anthropic_mapping: std::collections::HashMap<String, String>,
@@ -97,12 +107,15 @@ impl AxumServer {
         upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
         security_config: crate::proxy::ProxySecurityConfig,
         monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
-
+        retry_config: crate::proxy::config::RetryConfig,
+        upstream_timeouts: crate::proxy::config::UpstreamTimeoutConfig,
+        upstream_endpoints: Vec<String>,
     ) -> Result<(Self, tokio::task::JoinHandle<()>), String> {
         let mapping_state = Arc::new(tokio::sync::RwLock::new(anthropic_mapping));
         let openai_mapping_state = Arc::new(tokio::sync::RwLock::new(openai_mapping));
         let custom_mapping_state = Arc::new(tokio::sync::RwLock::new(custom_mapping));
         let proxy_state = Arc::new(tokio::sync::RwLock::new(upstream_proxy.clone()));
+        let session_config = security_config.session.clone();
         let security_state = Arc::new(RwLock::new(security_config));
         let oauth_state = Arc::new(tokio::sync::Mutex::new(OAuthStatus {
             status: "idle".to_string(),
@@ -120,8 +133,9 @@ impl AxumServer {
         webauthn_manager.load_auth_config().await
             .map_err(|e| format!("Failed to load auth config: {}", e))?;
 
-        // 初始化 Session 管理器 (7天有效期)
+        // 初始化 Session 管理器
         let session_manager = Arc::new(crate::modules::webauthn::SessionManager::new(24 * 7));
+        session_manager.configure(&session_config);
 
         let state = AppState {
             token_manager: token_manager.clone(),
@@ -135,14 +149,22 @@ impl AxumServer {
                 std::collections::HashMap::new(),
             )),
             upstream_proxy: proxy_state.clone(),
-            upstream: Arc::new(crate::proxy::upstream::client::UpstreamClient::new(Some(
-                upstream_proxy.clone(),
-            ))),
+            upstream: Arc::new(
+                crate::proxy::upstream::client::UpstreamClient::new(Some(upstream_proxy.clone()))
+                    .with_retry_config(retry_config)
+                    .with_timeouts(upstream_timeouts)
+                    .with_endpoints(&upstream_endpoints),
+            ),
             monitor: monitor.clone(),
             webauthn_manager,
             session_manager,
+            security: security_state.clone(),
         };
 
+        // 继续执行重启前未完成的批处理
+        crate::proxy::handlers::batches::resume(state.clone());
+        crate::proxy::model_registry::MODEL_REGISTRY.spawn_refresher(state.token_manager.clone(), state.upstream.clone());
+
 
         // 构建路由 - 使用新架构的 handlers！
         use crate::proxy::handlers;
@@ -159,28 +181,91 @@ impl AxumServer {
             .route("/api/auth/logout", post(handlers::webauthn::logout))
             .route("/api/auth/credentials", get(handlers::webauthn::list_credentials))
             .route("/api/auth/credentials/delete", post(handlers::webauthn::delete_credential))
+            .route("/api/auth/passkeys", get(handlers::webauthn::list_passkeys))
+            .route(
+                "/api/auth/passkeys/:id",
+                patch(handlers::webauthn::rename_passkey).delete(handlers::webauthn::revoke_passkey),
+            )
+            .route(
+                "/api/auth/sessions",
+                get(handlers::webauthn::list_sessions).delete(handlers::webauthn::revoke_other_sessions),
+            )
+            .route("/api/auth/sessions/:id", delete(handlers::webauthn::revoke_session))
             // Password Authentication APIs
             .route("/api/auth/password/setup", post(handlers::webauthn::setup_password))
             .route("/api/auth/password/login", post(handlers::webauthn::password_login))
             .route("/api/auth/password/change", post(handlers::webauthn::change_password))
             .route("/api/auth/reset", post(handlers::webauthn::reset_auth))
+            .route("/api/auth/totp", delete(handlers::webauthn::totp_disable))
+            .route("/api/auth/totp/enroll", post(handlers::webauthn::totp_enroll))
+            .route("/api/auth/totp/confirm", post(handlers::webauthn::totp_confirm))
+            .route("/api/auth/totp/login", post(handlers::webauthn::totp_login))
+            .route("/api/admins", get(handlers::webauthn::list_admins))
+            .route("/api/admins/users", post(handlers::webauthn::create_admin_user))
+            .route(
+                "/api/admins/users/:name",
+                patch(handlers::webauthn::update_admin_user).delete(handlers::webauthn::delete_admin_user),
+            )
+            .route("/api/admins/passkeys/:id", patch(handlers::webauthn::update_passkey_role))
+            .route("/api/admins/tokens", post(handlers::webauthn::create_admin_token))
+            .route("/api/admins/tokens/:id", delete(handlers::webauthn::delete_admin_token))
+            // First-run setup wizard
+            .route(handlers::setup::STATE_PATH, get(handlers::setup::get_state))
+            .route(handlers::setup::PASSWORD_PATH, post(handlers::setup::setup_password))
+            .route(handlers::setup::PASSKEY_START_PATH, post(handlers::setup::start_passkey))
+            .route(handlers::setup::PASSKEY_FINISH_PATH, post(handlers::setup::finish_passkey))
+            .route("/api/setup/account", post(handlers::setup::add_account))
+            .route("/api/setup/api-key", post(handlers::setup::create_api_key))
             // API Keys Management
             .route("/api/keys", get(handlers::api_keys::list_api_keys).post(handlers::api_keys::create_api_key))
             .route("/api/keys/usage", get(handlers::api_keys::get_total_usage))
+            .route("/api/keys/deleted", get(handlers::api_keys::list_deleted_api_keys))
             .route(
                 "/api/keys/:id",
                 get(handlers::api_keys::get_api_key)
                     .put(handlers::api_keys::update_api_key)
                     .delete(handlers::api_keys::delete_api_key),
             )
+            .route("/api/keys/by-name/:name", put(handlers::api_keys::upsert_api_key_by_name))
+            .route("/api/keys/:id/restore", post(handlers::api_keys::restore_api_key))
             .route("/api/keys/:id/regenerate", post(handlers::api_keys::regenerate_api_key))
             .route("/api/keys/:id/reset_usage", post(handlers::api_keys::reset_api_key_usage))
+            .route(
+                "/api/model-aliases",
+                get(handlers::model_aliases::list_model_aliases).post(handlers::model_aliases::create_model_alias),
+            )
+            .route(
+                "/api/model-aliases/:alias",
+                get(handlers::model_aliases::get_model_alias)
+                    .put(handlers::model_aliases::update_model_alias)
+                    .delete(handlers::model_aliases::delete_model_alias),
+            )
+            .route("/api/models", get(handlers::manage::models_status))
+            .route("/api/models/refresh", post(handlers::manage::refresh_models))
             // Management APIs
             .route("/api/accounts", get(handlers::manage::list_accounts).post(handlers::manage::create_account))
             .route(
                 "/api/accounts/current",
                 get(handlers::manage::get_current_account).put(handlers::manage::set_current_account),
             )
+            .route("/api/accounts/health", get(handlers::manage::accounts_health))
+            .route("/api/accounts/export", post(handlers::manage::export_accounts))
+            .route("/api/accounts/import", post(handlers::manage::import_accounts))
+            .route("/api/stats/concurrency", get(handlers::manage::concurrency_status))
+            .route("/api/alerts/test", post(handlers::manage::test_alerts))
+            .route(
+                "/api/upstream/endpoints",
+                get(handlers::manage::get_upstream_endpoints).put(handlers::manage::update_upstream_endpoints),
+            )
+            .route("/api/upstream/endpoints/pin", post(handlers::manage::pin_upstream_endpoint))
+            .route(
+                "/api/upstream/shadow",
+                get(handlers::manage::shadow_status).delete(handlers::manage::reset_shadow_stats),
+            )
+            .route(
+                "/api/upstream/client-profiles",
+                get(handlers::manage::get_client_profiles).put(handlers::manage::update_client_profiles),
+            )
             .route(
                 "/api/accounts/refresh_quotas",
                 post(handlers::manage::refresh_all_quotas),
@@ -189,6 +274,8 @@ impl AxumServer {
                 "/api/accounts/:id",
                 get(handlers::manage::get_account).delete(handlers::manage::delete_account),
             )
+            .route("/api/accounts/:id/pools", put(handlers::manage::set_account_pools))
+            .route("/api/accounts/:id/client-profile", put(handlers::manage::set_account_client_profile))
             .route(
                 "/api/accounts/:id/refresh_quota",
                 post(handlers::manage::refresh_account_quota),
@@ -196,14 +283,42 @@ impl AxumServer {
             .route("/api/oauth/prepare", get(handlers::manage::prepare_oauth))
             .route("/api/oauth/status", get(handlers::manage::oauth_status))
             .route("/api/oauth/cancel", post(handlers::manage::cancel_oauth))
-            .route("/api/oauth/callback", post(handlers::manage::submit_oauth_callback))
+            .route("/api/oauth/device", post(handlers::manage::start_device_oauth))
+            .route("/api/accounts/:id/reauth", post(handlers::manage::reauth_account))
+            .route("/api/accounts/:id/refresh-project", post(handlers::manage::refresh_account_project))
+            .route(crate::proxy::middleware::web_auth::OAUTH_CALLBACK_PATH, post(handlers::manage::submit_oauth_callback))
             .route("/oauth-callback", get(handlers::manage::oauth_callback))
+            .route("/api/logs", get(handlers::logs::query_logs))
+            .route("/api/logs/export", get(handlers::logs::export_logs))
+            .route("/api/logs/stream", get(handlers::logs::stream_logs))
+            .route("/api/logs/:id/replay", post(handlers::logs::replay_log))
+            .route("/api/stats/usage", get(handlers::logs::usage_stats))
+            .route("/api/audit", get(handlers::audit::list_audit))
+            .route("/api/audit/export", get(handlers::audit::export_audit))
+            .route("/api/config/reload", post(handlers::manage::reload_config))
+            .route("/api/config/validate", post(handlers::manage::validate_config))
+            .route("/api/debug/transform", post(handlers::openai::handle_debug_transform))
+            .route(
+                "/api/pricing",
+                get(handlers::manage::get_pricing).put(handlers::manage::update_pricing),
+            )
+            .route(
+                "/api/logging/level",
+                get(handlers::manage::get_log_level).put(handlers::manage::update_log_level),
+            )
             .route(
                 "/api/proxy/mappings",
                 get(handlers::manage::get_mappings).put(handlers::manage::update_mappings),
             )
+            .route("/api/proxy/maintenance", get(handlers::manage::maintenance_status))
+            .route("/api/proxy/pause", post(handlers::manage::pause_proxy))
+            .route("/api/proxy/resume", post(handlers::manage::resume_proxy))
+            .route("/api/backup", post(handlers::manage::create_backup))
+            .route("/api/restore", post(handlers::manage::restore_backup))
             // OpenAI Protocol
             .route("/v1/models", get(handlers::openai::handle_list_models))
+            .route("/v1/usage", get(handlers::usage::handle_key_usage))
+            .route("/v1/token-count", post(handlers::token_count::handle_token_count))
             .route(
                 "/v1/chat/completions",
                 post(handlers::openai::handle_chat_completions),
@@ -213,6 +328,23 @@ impl AxumServer {
                 post(handlers::openai::handle_completions),
             )
             .route("/v1/responses", post(handlers::openai::handle_completions)) // 兼容 Codex CLI
+            .route("/v1/embeddings", post(handlers::openai::handle_embeddings))
+            .route(
+                handlers::files::FILES_PATH,
+                get(handlers::files::handle_list).post(handlers::files::handle_upload),
+            )
+            .route(
+                "/v1/files/:file_id",
+                get(handlers::files::handle_get).delete(handlers::files::handle_delete),
+            )
+            .route("/v1/files/:file_id/content", get(handlers::files::handle_content))
+            .route(
+                handlers::batches::BATCHES_PATH,
+                get(handlers::batches::handle_list).post(handlers::batches::handle_create),
+            )
+            .route("/v1/batches/:batch_id", get(handlers::batches::handle_get))
+            .route("/v1/batches/:batch_id/cancel", post(handlers::batches::handle_cancel))
+            .route("/v1/realtime", get(handlers::realtime::handle_realtime)) // WebSocket 流式传输
             .route(
                 "/v1/images/generations",
                 post(handlers::openai::handle_images_generations),
@@ -231,6 +363,10 @@ impl AxumServer {
                 "/v1/models/claude",
                 get(handlers::claude::handle_list_models),
             )
+            // Ollama Protocol (兼容层)
+            .route(handlers::ollama::CHAT_PATH, post(handlers::ollama::handle_chat))
+            .route(handlers::ollama::GENERATE_PATH, post(handlers::ollama::handle_generate))
+            .route(handlers::ollama::TAGS_PATH, get(handlers::ollama::handle_tags))
 	            // Gemini Protocol (Native)
 	            .route("/v1beta/models", get(handlers::gemini::handle_list_models))
             // Handle both GET (get info) and POST (generateContent with colon) at the same route
@@ -245,28 +381,51 @@ impl AxumServer {
             .route("/v1/models/detect", post(handlers::common::handle_detect_model))
             .route("/v1/api/event_logging/batch", post(silent_ok_handler))
             .route("/v1/api/event_logging", post(silent_ok_handler))
-            .route("/healthz", get(health_check_handler))
-            .layer(DefaultBodyLimit::max(100 * 1024 * 1024))
+            .route("/healthz", get(crate::proxy::health::liveness_handler))
+            .route("/readyz", get(crate::proxy::health::readiness_handler))
+            .route("/metrics", get(metrics_handler))
+            .route(crate::proxy::cluster::COOLDOWN_PATH, post(crate::proxy::cluster::handle_cooldown))
+            .route(crate::proxy::cluster::SYNC_PATH, post(crate::proxy::cluster::handle_sync))
+            .route_layer(axum::middleware::from_fn(crate::proxy::middleware::otel::handler_span_middleware))
+            .layer(axum::middleware::from_fn(crate::proxy::middleware::response_cache::response_cache_middleware))
+            // 请求体上限由 body_limit_middleware 按配置执行
+            .layer(DefaultBodyLimit::disable())
             .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::web_auth_middleware))
-            .layer(crate::proxy::middleware::cors_layer())
             // monitor_middleware 必须在 auth_middleware 之后执行（即在 layer 中位于其上方）
             // 这样 AuthenticatedKey 才能在 monitor_middleware 中被访问
             .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::monitor::monitor_middleware))
             .layer(TraceLayer::new_for_http())
+            // 维护模式下模型请求在鉴权之后、记录日志之前返回 503
+            .layer(axum::middleware::from_fn(crate::proxy::middleware::maintenance::maintenance_middleware))
             .layer(axum::middleware::from_fn_with_state(
                 security_state.clone(),
                 crate::proxy::middleware::auth_middleware,
             ))
-            .with_state(state)
+            .layer(axum::middleware::from_fn(crate::proxy::middleware::body_limit::body_limit_middleware))
+            // 鉴权 / 请求体上限 / 处理器返回的纯文本错误统一改写为 OpenAI 错误格式
+            .layer(axum::middleware::from_fn(crate::proxy::middleware::error_format::error_format_middleware))
+            // 压缩位于 monitor / 响应缓存之外：日志与缓存保存的都是未压缩的响应体
+            .layer(axum::middleware::from_fn(crate::proxy::middleware::compression::compression_middleware))
+            // CORS 位于鉴权之外：预检请求无需凭据，401 / 403 响应也带 CORS 头，浏览器才能读取错误
+            .layer(axum::middleware::from_fn_with_state(
+                security_state.clone(),
+                crate::proxy::middleware::cors_middleware,
+            ))
+            // 请求 ID 在鉴权与日志之前分配，整条中间件链的日志都带上该 ID
+            .layer(axum::middleware::from_fn(crate::proxy::middleware::request_id::request_id_middleware))
+            // 最外层：server span 覆盖整个中间件链
+            .layer(axum::middleware::from_fn(crate::proxy::middleware::otel::otel_middleware))
+            .with_state(state.clone())
             .fallback_service(ServeDir::new(static_dir).append_index_html_on_directories(true));
+        // 路径前缀需在路由匹配之前剥离，因此套在整个 Router 之外
+        let app = tower::Layer::layer(
+            &axum::middleware::from_fn(crate::proxy::middleware::base_path::base_path_middleware),
+            app,
+        );
 
-        // 绑定地址
-        let addr = format!("{}:{}", host, port);
-        let listener = tokio::net::TcpListener::bind(&addr)
-            .await
-            .map_err(|e| format!("地址 {} 绑定失败: {}", addr, e))?;
-
-        tracing::info!("反代服务器启动在 http://{}", addr);
+        for listener in &listeners {
+            tracing::info!("反代服务器启动在 {}", listener.describe());
+        }
 
         // 创建关闭通道
         let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
@@ -278,64 +437,135 @@ impl AxumServer {
             custom_mapping: custom_mapping_state.clone(),
             proxy_state,
             security_state,
+            app_state: state,
         };
 
         // 在新任务中启动服务器
+        // 收到停止信号后不再接受新连接，并等待已有连接上的在途请求（含 SSE 流）完成后任务才结束
         let handle = tokio::spawn(async move {
-            use hyper::server::conn::http1;
+            use crate::proxy::listener::Accepted;
             use hyper_util::rt::TokioIo;
             use hyper_util::service::TowerToHyperService;
 
+            // 通知各连接进入优雅关闭；JoinSet 用于等待所有连接结束
+            let (drain_tx, drain_rx) = tokio::sync::watch::channel(false);
+            let mut connections = tokio::task::JoinSet::new();
+
             loop {
+                let accept_any = futures::future::select_all(listeners.iter().map(|l| Box::pin(l.accept())));
                 tokio::select! {
-                    res = listener.accept() => {
-                        match res {
-                            Ok((stream, _)) => {
-                                let io = TokioIo::new(stream);
-                                let service = TowerToHyperService::new(app.clone());
+                    (res, _, _) = accept_any => {
+                        let accepted = match res {
+                            Ok(accepted) => accepted,
+                            Err(e) => {
+                                error!("接收连接失败: {:?}", e);
+                                continue;
+                            }
+                        };
+                        let (peer, is_tls) = match &accepted {
+                            Accepted::Tcp(_, peer, tls) => (*peer, tls.is_some()),
+                            // Unix socket 连接视为本机 (127.0.0.1)，IP 规则与 trusted_proxies 按回环地址判断
+                            #[cfg(unix)]
+                            Accepted::Unix(_) => (std::net::SocketAddr::from(([127, 0, 0, 1], 0)), false),
+                        };
+                        // 注入对端地址，供 auth 中间件做 IP 访问控制
+                        let service = TowerToHyperService::new(app.clone().map_request(
+                            move |mut req: axum::http::Request<hyper::body::Incoming>| {
+                                req.extensions_mut().insert(axum::extract::ConnectInfo(peer));
+                                if is_tls {
+                                    // TLS 直连：按 X-Forwarded-Proto 判断 https 的逻辑（如 WebAuthn origin）需要看到真实协议
+                                    req.headers_mut().insert("x-forwarded-proto", axum::http::HeaderValue::from_static("https"));
+                                }
+                                req.map(axum::body::Body::new)
+                            },
+                        ));
+                        let drain_rx = drain_rx.clone();
 
-                                tokio::task::spawn(async move {
-                                    if let Err(err) = http1::Builder::new()
-                                        .serve_connection(io, service)
-                                        .with_upgrades() // 支持 WebSocket (如果以后需要)
-                                        .await
-                                    {
-                                        debug!("连接处理结束或出错: {:?}", err);
+                        match accepted {
+                            Accepted::Tcp(stream, _, Some(acceptor)) => {
+                                connections.spawn(async move {
+                                    // 握手在连接任务中完成，不阻塞 accept 循环
+                                    match acceptor.accept(stream).await {
+                                        Ok(tls_stream) => serve_connection(TokioIo::new(tls_stream), service, drain_rx).await,
+                                        Err(err) => debug!("TLS 握手失败 ({}): {:?}", peer, err),
                                     }
                                 });
                             }
-                            Err(e) => {
-                                error!("接收连接失败: {:?}", e);
+                            Accepted::Tcp(stream, _, None) => {
+                                connections.spawn(serve_connection(TokioIo::new(stream), service, drain_rx));
+                            }
+                            #[cfg(unix)]
+                            Accepted::Unix(stream) => {
+                                connections.spawn(serve_connection(TokioIo::new(stream), service, drain_rx));
                             }
                         }
                     }
+                    // 回收已结束的连接任务
+                    Some(_) = connections.join_next(), if !connections.is_empty() => {}
                     _ = &mut shutdown_rx => {
                         tracing::info!("反代服务器停止监听");
                         break;
                     }
                 }
             }
+
+            drop(listeners);
+            let _ = drain_tx.send(true);
+            if !connections.is_empty() {
+                tracing::info!("等待 {} 个在途连接结束", connections.len());
+            }
+            while connections.join_next().await.is_some() {}
+            tracing::info!("所有在途连接已结束");
         });
 
         Ok((server_instance, handle))
     }
 
-    /// 停止服务器
+    /// 停止服务器（停止接受新连接，在途请求继续处理；等待 start 返回的 handle 即可等到排空完成）
     pub fn stop(mut self) {
+        // 实时日志是不会自行结束的长连接，先结束它们，排空才不会等到超时
+        self.app_state.monitor.close_tails();
         if let Some(tx) = self.shutdown_tx.take() {
             let _ = tx.send(());
         }
     }
 }
 
+/// 在单个连接上提供服务；收到排空信号后优雅关闭
+async fn serve_connection<I, S>(io: I, service: S, mut drain_rx: tokio::sync::watch::Receiver<bool>)
+where
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+    S: hyper::service::Service<axum::http::Request<hyper::body::Incoming>, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let conn = hyper::server::conn::http1::Builder::new()
+        .serve_connection(io, service)
+        .with_upgrades(); // 支持 WebSocket (如果以后需要)
+    tokio::pin!(conn);
+    let result = tokio::select! {
+        res = conn.as_mut() => res,
+        _ = drain_rx.changed() => {
+            // 空闲连接立即关闭，正在处理的请求（含流式响应）继续直到完成
+            conn.as_mut().graceful_shutdown();
+            conn.await
+        }
+    };
+    if let Err(err) = result {
+        debug!("连接处理结束或出错: {:?}", err);
+    }
+}
+
 // ===== API 处理器 (旧代码已移除，由 src/proxy/handlers/* 接管) =====
 
-/// 健康检查处理器
-async fn health_check_handler() -> Response {
-    Json(serde_json::json!({
-        "status": "ok"
-    }))
-    .into_response()
+/// Prometheus 指标处理器
+async fn metrics_handler(axum::extract::State(state): axum::extract::State<AppState>) -> Response {
+    let body = crate::proxy::metrics::METRICS.render(&state.token_manager.account_health());
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        body,
+    )
+        .into_response()
 }
 
 /// 静默成功处理器 (用于拦截遥测日志等)
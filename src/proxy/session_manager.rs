@@ -2,6 +2,38 @@ use sha2::{Sha256, Digest};
 use crate::proxy::mappers::claude::models::{ClaudeRequest, MessageContent};
 use crate::proxy::mappers::openai::models::{OpenAIRequest, OpenAIContent};
 use serde_json::Value;
+use std::future::Future;
+
+/// 客户端显式指定会话标识的请求头
+pub const SESSION_ID_HEADER: &str = "x-session-id";
+/// 请求头会话标识的最大长度
+const MAX_CLIENT_SESSION_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    /// 当前请求通过 `x-session-id` 请求头指定的会话标识（鉴权中间件设置作用域）
+    static CLIENT_SESSION_ID: String;
+}
+
+/// 规范化请求头中的会话标识；为空或过长时忽略
+pub fn normalize_client_session_id(raw: &str) -> Option<String> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() || trimmed.len() > MAX_CLIENT_SESSION_ID_LEN {
+        return None;
+    }
+    Some(format!("hdr-{}", trimmed))
+}
+
+/// 在请求头会话标识的作用域内执行请求
+pub async fn with_client_session_id<F: Future>(session_id: Option<String>, fut: F) -> F::Output {
+    match session_id {
+        Some(sid) => CLIENT_SESSION_ID.scope(sid, fut).await,
+        None => fut.await,
+    }
+}
+
+fn client_session_id() -> Option<String> {
+    CLIENT_SESSION_ID.try_with(|sid| sid.clone()).ok()
+}
 
 /// 会话管理器工具
 pub struct SessionManager;
@@ -9,6 +41,11 @@ pub struct SessionManager;
 impl SessionManager {
     /// 根据 Claude 请求生成稳定的会话指纹 (Session Fingerprint)
     pub fn extract_session_id(request: &ClaudeRequest) -> String {
+        // 0. 客户端通过 x-session-id 显式指定的会话标识
+        if let Some(sid) = client_session_id() {
+            return sid;
+        }
+
         // 1. 优先使用 metadata 中的 user_id
         if let Some(metadata) = &request.metadata {
             if let Some(user_id) = &metadata.user_id {
@@ -67,6 +104,10 @@ impl SessionManager {
 
     /// 根据 OpenAI 请求生成稳定的会话指纹
     pub fn extract_openai_session_id(request: &OpenAIRequest) -> String {
+        if let Some(sid) = client_session_id() {
+            return sid;
+        }
+
         let mut hasher = Sha256::new();
         hasher.update(request.model.as_bytes());
 
@@ -110,6 +151,10 @@ impl SessionManager {
 
     /// 根据 Gemini 原生请求 (JSON) 生成稳定的会话指纹
     pub fn extract_gemini_session_id(request: &Value, model_name: &str) -> String {
+        if let Some(sid) = client_session_id() {
+            return sid;
+        }
+
         let mut hasher = Sha256::new();
         hasher.update(model_name.as_bytes());
 
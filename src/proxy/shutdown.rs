@@ -0,0 +1,86 @@
+// 优雅关闭
+// 跟踪写日志 / 记录用量的后台任务，进程退出前等待它们完成，避免重启时丢失统计
+use once_cell::sync::Lazy;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// 排空在途流式响应后，等待后台写入完成的最长时间
+pub const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+static BACKGROUND: Lazy<TaskTracker> = Lazy::new(TaskTracker::default);
+
+#[derive(Default)]
+struct TaskTracker {
+    pending: AtomicUsize,
+    idle: Notify,
+}
+
+/// 任务结束（包括 panic）时减少计数
+struct PendingGuard(&'static TaskTracker);
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        if self.0.pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+/// 启动需要在退出前完成的后台任务（监控日志落盘、API Key 用量记录）
+pub fn spawn_tracked<F>(future: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let tracker: &'static TaskTracker = &BACKGROUND;
+    tracker.pending.fetch_add(1, Ordering::AcqRel);
+    let guard = PendingGuard(tracker);
+    tokio::spawn(async move {
+        let _guard = guard;
+        future.await
+    })
+}
+
+/// 当前未完成的后台任务数
+pub fn pending_tasks() -> usize {
+    BACKGROUND.pending.load(Ordering::Acquire)
+}
+
+/// 等待所有后台任务完成，超时返回 false
+pub async fn wait_for_background_tasks(timeout: Duration) -> bool {
+    let tracker: &'static TaskTracker = &BACKGROUND;
+    tokio::time::timeout(timeout, async {
+        loop {
+            let idle = tracker.idle.notified();
+            tokio::pin!(idle);
+            idle.as_mut().enable();
+            if tracker.pending.load(Ordering::Acquire) == 0 {
+                return;
+            }
+            idle.await;
+        }
+    })
+    .await
+    .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_for_background_tasks() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let task = spawn_tracked(async move {
+            let _ = rx.await;
+        });
+        assert!(pending_tasks() >= 1);
+        assert!(!wait_for_background_tasks(Duration::from_millis(20)).await);
+
+        tx.send(()).unwrap();
+        task.await.unwrap();
+        assert!(wait_for_background_tasks(Duration::from_secs(1)).await);
+    }
+}
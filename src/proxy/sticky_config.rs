@@ -17,6 +17,25 @@ impl Default for SchedulingMode {
     }
 }
 
+/// 账号轮换策略：需要选择新账号时（无粘性绑定或强制轮换）如何挑选
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RotationStrategy {
+    /// 轮询 (默认)
+    #[default]
+    RoundRobin,
+    /// 最久未使用的账号优先
+    LeastRecentlyUsed,
+    /// 按剩余配额加权随机
+    QuotaWeighted,
+    /// 同一 API Key 固定使用同一账号（不可用时才切换）
+    StickyPerKey,
+    /// 按会话标识（`x-session-id` 请求头或首条用户消息指纹）哈希到固定账号，重启后映射不变
+    SessionHash,
+    /// 完全随机
+    Random,
+}
+
 /// 粘性会话配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StickySessionConfig {
@@ -24,6 +43,9 @@ pub struct StickySessionConfig {
     pub mode: SchedulingMode,
     /// 缓存优先模式下的最大等待时间 (秒)
     pub max_wait_seconds: u64,
+    /// 账号轮换策略
+    #[serde(default)]
+    pub rotation: RotationStrategy,
 }
 
 impl Default for StickySessionConfig {
@@ -33,6 +55,7 @@ impl Default for StickySessionConfig {
             // 当账号被限流时，会等待（最多 max_wait_seconds）而不是切换账号
             mode: SchedulingMode::CacheFirst,
             max_wait_seconds: 120,  // 最多等待 2 分钟
+            rotation: RotationStrategy::RoundRobin,
         }
     }
 }
@@ -0,0 +1,141 @@
+// HTTPS 终止
+// 证书来自配置的 PEM 文件或 ACME 自动签发；通过可替换的证书解析器实现续期 / 热重载后无需重启即生效
+use once_cell::sync::Lazy;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use std::sync::{Arc, RwLock};
+use tokio_rustls::rustls::{
+    self,
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use tokio_rustls::TlsAcceptor;
+
+use crate::proxy::config::TlsConfig;
+
+/// 当前使用的证书
+pub static CERTS: Lazy<Arc<CertResolver>> = Lazy::new(|| Arc::new(CertResolver::default()));
+
+/// 可在运行时替换证书的解析器；尚无证书时握手失败
+#[derive(Debug, Default)]
+pub struct CertResolver {
+    current: RwLock<Option<Arc<CertifiedKey>>>,
+}
+
+impl CertResolver {
+    pub fn install(&self, key: CertifiedKey) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(key));
+    }
+
+    pub fn has_cert(&self) -> bool {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).is_some()
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// 证书摘要（到期时间与覆盖的域名）
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertInfo {
+    /// 到期时间（秒级时间戳）
+    pub not_after: i64,
+    pub dns_names: Vec<String>,
+}
+
+impl CertInfo {
+    pub fn covers(&self, domains: &[String]) -> bool {
+        domains.iter().all(|d| self.dns_names.iter().any(|n| n.eq_ignore_ascii_case(d)))
+    }
+}
+
+/// 解析叶子证书的到期时间与 SAN 域名
+pub fn cert_info(leaf: &[u8]) -> Result<CertInfo, String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(leaf).map_err(|e| format!("Invalid certificate: {}", e))?;
+    let dns_names = cert
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|san| {
+            san.value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    x509_parser::extensions::GeneralName::DNSName(dns) => Some(dns.to_string()),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(CertInfo { not_after: cert.validity().not_after.timestamp(), dns_names })
+}
+
+/// 从 PEM 证书链与私钥构建证书
+pub fn certified_key_from_pem(cert_pem: &[u8], key_pem: &[u8]) -> Result<(CertifiedKey, CertInfo), String> {
+    let certs = CertificateDer::pem_slice_iter(cert_pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid certificate PEM: {:?}", e))?;
+    let leaf = certs.first().ok_or("Certificate PEM contains no certificate")?;
+    let info = cert_info(leaf)?;
+    let key = PrivateKeyDer::from_pem_slice(key_pem).map_err(|e| format!("Invalid private key PEM: {:?}", e))?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key).map_err(|e| e.to_string())?;
+    Ok((CertifiedKey::new(certs, signing_key), info))
+}
+
+/// 读取配置的证书文件并替换当前证书
+pub fn load_cert_files(config: &TlsConfig) -> Result<CertInfo, String> {
+    let (Some(cert_path), Some(key_path)) = (config.cert_path.as_deref(), config.key_path.as_deref()) else {
+        return Err("tls.cert_path and tls.key_path are required unless tls.acme.domains is set".to_string());
+    };
+    let cert_pem = std::fs::read(cert_path).map_err(|e| format!("Failed to read {}: {}", cert_path, e))?;
+    let key_pem = std::fs::read(key_path).map_err(|e| format!("Failed to read {}: {}", key_path, e))?;
+    let (key, info) = certified_key_from_pem(&cert_pem, &key_pem)?;
+    CERTS.install(key);
+    Ok(info)
+}
+
+fn acceptor() -> Result<TlsAcceptor, String> {
+    let mut server_config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_cert_resolver(CERTS.clone());
+    server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// 启动时初始化 HTTPS：未启用时返回 None
+///
+/// ACME 模式下先加载已保存的证书，再在后台签发 / 续期
+pub async fn init(config: &TlsConfig, host: &str, https_port: u16) -> Result<Option<TlsAcceptor>, String> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    if config.acme.domains.is_empty() {
+        let info = load_cert_files(config)?;
+        tracing::info!("[TLS] Loaded certificate for {:?}, expires at {}", info.dns_names, format_expiry(info.not_after));
+    } else {
+        crate::proxy::acme::start(config.acme.clone(), host, https_port).await?;
+    }
+    acceptor().map(Some)
+}
+
+/// 热重载：重新读取证书文件（如 certbot 续期后）；ACME 证书由后台任务管理
+pub fn reload(config: &TlsConfig) {
+    if !config.enabled || !config.acme.domains.is_empty() || !CERTS.has_cert() {
+        return;
+    }
+    match load_cert_files(config) {
+        Ok(info) => tracing::info!("[TLS] Reloaded certificate, expires at {}", format_expiry(info.not_after)),
+        Err(e) => tracing::error!("[TLS] Failed to reload certificate, keeping the current one: {}", e),
+    }
+}
+
+pub fn format_expiry(not_after: i64) -> String {
+    chrono::DateTime::from_timestamp(not_after, 0)
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_else(|| not_after.to_string())
+}
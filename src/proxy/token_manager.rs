@@ -1,12 +1,34 @@
 // 移除冗余的顶层导入，因为这些在代码中已由 full path 或局部导入处理
 use dashmap::DashMap;
 use std::collections::HashSet;
-use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use crate::proxy::account_stats::AccountStats;
 use crate::proxy::rate_limit::RateLimitTracker;
-use crate::proxy::sticky_config::StickySessionConfig;
+use crate::proxy::sticky_config::{RotationStrategy, StickySessionConfig};
+
+/// access token 提前刷新时间 (秒)
+const TOKEN_REFRESH_AHEAD_SECS: i64 = 300;
+/// 后台刷新任务检查间隔 (秒)
+const REFRESH_CHECK_INTERVAL_SECS: u64 = 60;
+/// 连续刷新失败达到该次数后停用账号（invalid_grant 立即停用）
+const MAX_AUTH_FAILURES: u32 = 3;
+/// 按账号配额跟踪冷却状态的配额组
+const QUOTA_GROUPS: [&str; 2] = ["claude", "gemini"];
+
+tokio::task_local! {
+    /// 指定账号执行（日志重放）：get_token 只返回该账号
+    static PINNED_ACCOUNT: String;
+}
+
+/// 在指定账号上执行 `fut`；`account_id` 为 None 时按正常调度
+pub async fn with_pinned_account<F: std::future::Future>(account_id: Option<String>, fut: F) -> F::Output {
+    match account_id {
+        Some(id) => PINNED_ACCOUNT.scope(id, fut).await,
+        None => fut.await,
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct ProxyToken {
@@ -16,9 +38,38 @@ pub struct ProxyToken {
     pub expires_in: i64,
     pub timestamp: i64,
     pub email: String,
-    pub account_path: PathBuf,  // 账号文件路径，用于更新
     pub project_id: Option<String>,
     pub subscription_tier: Option<String>, // "FREE" | "PRO" | "ULTRA"
+    pub quota_models: Vec<(String, i32)>,  // 模型名 -> 剩余配额百分比 (用于 QuotaWeighted 策略)
+    pub pools: Vec<String>,  // 所属账号池，绑定账号池的 API Key 只在池内轮换
+    pub quota_resets: Vec<(String, i64)>,  // 配额组 -> 配额接口报告的下次重置时间 (Unix 秒)
+    pub client_profile: Option<String>,  // 账号指定的上游客户端 profile
+    pub oauth_client: Option<String>,  // 签发 refresh_token 的 OAuth 客户端
+}
+
+/// 账号健康检查结果 (GET /api/accounts/health)
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AccountHealthReport {
+    pub account_id: String,
+    pub email: String,
+    pub subscription_tier: Option<String>,
+    /// token 可刷新且探测请求成功
+    pub token_valid: bool,
+    pub error: Option<String>,
+    /// access token 过期时间 (Unix 秒)
+    pub token_expires_at: i64,
+    /// 模型名 -> 剩余配额百分比
+    pub remaining_quota: std::collections::BTreeMap<String, i32>,
+    pub rate_limited: bool,
+    /// 最近一小时内记录的上游响应数
+    pub recent_requests: u32,
+    /// 最近一小时内 429 的占比
+    pub recent_429_rate: f64,
+    /// 最近一次成功代理请求的时间 (Unix 秒)
+    pub last_success_at: Option<i64>,
+    /// 冷却结束时间 (Unix 秒)，冷却中的账号不参与轮换
+    pub cooldown_until: Option<i64>,
+    pub probe_latency_ms: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -27,32 +78,50 @@ pub struct SelectedToken {
     pub project_id: String,
     pub email: String,
     pub account_id: String,
+    /// 账号指定的上游客户端 profile
+    pub client_profile: Option<String>,
 }
 
 pub struct TokenManager {
     tokens: Arc<DashMap<String, ProxyToken>>,  // account_id -> ProxyToken
     current_index: Arc<DashMap<String, Arc<AtomicUsize>>>,
     last_used_account: Arc<DashMap<String, Arc<tokio::sync::Mutex<Option<(String, std::time::Instant)>>>>>,
-    data_dir: PathBuf,
     rate_limit_tracker: Arc<RateLimitTracker>,  // 新增: 限流跟踪器
     sticky_config: Arc<tokio::sync::RwLock<StickySessionConfig>>, // 新增：调度配置
     session_accounts: Arc<DashMap<String, String>>, // 新增：会话与账号映射 (SessionID -> AccountID)
     /// 每账号的刷新锁，防止并发刷新同一账号的 token
     refreshing_accounts: Arc<DashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    /// 账号最近一次被选中的时间 (LeastRecentlyUsed 策略)
+    last_selected: Arc<DashMap<String, std::time::Instant>>,
+    /// API Key 与账号映射 (StickyPerKey 策略, scope::KeyID -> AccountID)
+    key_accounts: Arc<DashMap<String, String>>,
+    /// 账号最近的上游响应结果 (用于健康检查)
+    stats: Arc<AccountStats>,
+    /// 账号连续被 OAuth 服务端拒绝刷新的次数（成功刷新后清零）
+    auth_failures: Arc<DashMap<String, u32>>,
+}
+
+impl Default for TokenManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TokenManager {
     /// 创建新的 TokenManager
-    pub fn new(data_dir: PathBuf) -> Self {
+    pub fn new() -> Self {
         Self {
             tokens: Arc::new(DashMap::new()),
             current_index: Arc::new(DashMap::new()),
             last_used_account: Arc::new(DashMap::new()),
-            data_dir,
             rate_limit_tracker: Arc::new(RateLimitTracker::new()),
             sticky_config: Arc::new(tokio::sync::RwLock::new(StickySessionConfig::default())),
             session_accounts: Arc::new(DashMap::new()),
             refreshing_accounts: Arc::new(DashMap::new()),
+            last_selected: Arc::new(DashMap::new()),
+            key_accounts: Arc::new(DashMap::new()),
+            stats: Arc::new(AccountStats::new()),
+            auth_failures: Arc::new(DashMap::new()),
         }
     }
 
@@ -84,47 +153,31 @@ impl TokenManager {
     }
 
     fn scope_group(quota_group: &str, request_type: &str) -> String {
-        if request_type == "image_gen" {
-            format!("{}::image_gen", quota_group)
+        // 图像生成 / 嵌入的配额与对话独立
+        if request_type == "image_gen" || request_type == "embedding" {
+            format!("{}::{}", quota_group, request_type)
         } else {
             quota_group.to_string()
         }
     }
     
-    /// 从主应用账号目录加载所有账号
+    /// 从账号存储加载所有账号
     pub async fn load_accounts(&self) -> Result<usize, String> {
-        let accounts_dir = self.data_dir.join("accounts");
-
-        if !accounts_dir.exists() {
-            return Err(format!("账号目录不存在: {:?}", accounts_dir));
-        }
+        // 使用 spawn_blocking 避免阻塞异步运行时
+        let account_ids = tokio::task::spawn_blocking(crate::modules::account::list_account_ids)
+            .await
+            .map_err(|e| format!("任务执行失败: {}", e))??;
 
-        // Reload should reflect current on-disk state (accounts can be added/removed/disabled).
+        // Reload should reflect current stored state (accounts can be added/removed/disabled).
         self.tokens.clear();
         self.current_index.clear();
         self.last_used_account.clear();
 
-        // 使用 spawn_blocking 避免阻塞异步运行时
-        let accounts_dir_clone = accounts_dir.clone();
-        let entries: Vec<PathBuf> = tokio::task::spawn_blocking(move || {
-            std::fs::read_dir(&accounts_dir_clone)
-                .map(|read_dir| {
-                    read_dir
-                        .filter_map(|e| e.ok())
-                        .map(|e| e.path())
-                        .filter(|p| p.extension().and_then(|s| s.to_str()) == Some("json"))
-                        .collect::<Vec<_>>()
-                })
-        })
-        .await
-        .map_err(|e| format!("任务执行失败: {}", e))?
-        .map_err(|e| format!("读取账号目录失败: {}", e))?;
-
         let mut count = 0;
 
-        for path in entries {
+        for account_id in account_ids {
             // 尝试加载账号
-            match self.load_single_account(&path).await {
+            match self.load_single_account(&account_id).await {
                 Ok(Some(token)) => {
                     let account_id = token.account_id.clone();
                     self.tokens.insert(account_id, token);
@@ -134,7 +187,7 @@ impl TokenManager {
                     // 跳过无效账号
                 },
                 Err(e) => {
-                    tracing::debug!("加载账号失败 {:?}: {}", path, e);
+                    tracing::debug!("加载账号失败 {}: {}", account_id, e);
                 }
             }
         }
@@ -143,15 +196,16 @@ impl TokenManager {
     }
     
     /// 加载单个账号
-    async fn load_single_account(&self, path: &PathBuf) -> Result<Option<ProxyToken>, String> {
+    async fn load_single_account(&self, account_id: &str) -> Result<Option<ProxyToken>, String> {
         // 使用 spawn_blocking 避免在异步上下文中阻塞
-        let path_clone = path.clone();
+        let id = account_id.to_string();
         let content = tokio::task::spawn_blocking(move || {
-            std::fs::read_to_string(&path_clone)
+            crate::modules::account::read_account_json(&id)
         })
         .await
         .map_err(|e| format!("任务执行失败: {}", e))?
-        .map_err(|e| format!("读取文件失败: {}", e))?;
+        .map_err(|e| format!("读取文件失败: {}", e))?
+        .ok_or("账号文件不存在")?;
         
         let account: serde_json::Value = serde_json::from_str(&content)
             .map_err(|e| format!("解析 JSON 失败: {}", e))?;
@@ -162,8 +216,8 @@ impl TokenManager {
             .unwrap_or(false)
         {
             tracing::debug!(
-                "Skipping disabled account file: {:?} (email={})",
-                path,
+                "Skipping disabled account file: {} (email={})",
+                account_id,
                 account.get("email").and_then(|v| v.as_str()).unwrap_or("<unknown>")
             );
             return Ok(None);
@@ -176,8 +230,8 @@ impl TokenManager {
             .unwrap_or(false)
         {
             tracing::debug!(
-                "Skipping proxy-disabled account file: {:?} (email={})",
-                path,
+                "Skipping proxy-disabled account file: {} (email={})",
+                account_id,
                 account.get("email").and_then(|v| v.as_str()).unwrap_or("<unknown>")
             );
             return Ok(None);
@@ -195,12 +249,12 @@ impl TokenManager {
             .ok_or("缺少 token 字段")?;
         
         let access_token = token_obj["access_token"].as_str()
-            .ok_or("缺少 access_token")?
-            .to_string();
+            .ok_or("缺少 access_token")?;
+        let access_token = crate::modules::secret_store::decrypt(access_token)?;
         
         let refresh_token = token_obj["refresh_token"].as_str()
-            .ok_or("缺少 refresh_token")?
-            .to_string();
+            .ok_or("缺少 refresh_token")?;
+        let refresh_token = crate::modules::secret_store::decrypt(refresh_token)?;
         
         let expires_in = token_obj["expires_in"].as_i64()
             .ok_or("缺少 expires_in")?;
@@ -212,12 +266,54 @@ impl TokenManager {
         let project_id = token_obj.get("project_id")
             .and_then(|v| v.as_str())
             .map(|s| s.to_string());
+        let oauth_client = token_obj.get("oauth_client")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
         
         // 【新增】提取订阅等级 (subscription_tier 为 "FREE" | "PRO" | "ULTRA")
         let subscription_tier = account.get("quota")
             .and_then(|q| q.get("subscription_tier"))
             .and_then(|v| v.as_str())
             .map(|s| s.to_string());
+
+        let quota_models = account.get("quota")
+            .and_then(|q| q.get("models"))
+            .and_then(|m| m.as_array())
+            .map(|models| {
+                models.iter().filter_map(|m| {
+                    let name = m.get("name")?.as_str()?.to_string();
+                    let percentage = m.get("percentage")?.as_i64()? as i32;
+                    Some((name, percentage))
+                }).collect()
+            })
+            .unwrap_or_default();
+
+        // 配额重置时间与冷却记录：冷却中的账号在重置前不参与轮换（重启后同样生效）
+        let now = chrono::Utc::now().timestamp();
+        let quota = account.get("quota")
+            .and_then(|q| serde_json::from_value::<crate::models::QuotaData>(q.clone()).ok());
+        let mut quota_resets = Vec::new();
+        if let Some(quota) = &quota {
+            for group in QUOTA_GROUPS {
+                if let Some(reset_at) = crate::modules::quota::group_reset_at(quota, group, now) {
+                    quota_resets.push((group.to_string(), reset_at));
+                }
+            }
+            for (group, until) in crate::modules::quota::active_cooldowns(quota, &QUOTA_GROUPS, now) {
+                tracing::debug!("Account {} is cooling down for {} until {}", email, group, until);
+                self.rate_limit_tracker.set_cooldown(
+                    &group,
+                    &account_id,
+                    std::time::UNIX_EPOCH + std::time::Duration::from_secs(until.max(0) as u64),
+                    crate::proxy::rate_limit::RateLimitReason::QuotaExhausted,
+                );
+            }
+        }
+
+        let pools = account.get("pools")
+            .and_then(|p| serde_json::from_value::<Vec<String>>(p.clone()).ok())
+            .unwrap_or_default();
+        let client_profile = account.get("client_profile").and_then(|p| p.as_str()).map(str::to_string);
         
         Ok(Some(ProxyToken {
             account_id,
@@ -226,9 +322,13 @@ impl TokenManager {
             expires_in,
             timestamp,
             email,
-            account_path: path.clone(),
             project_id,
             subscription_tier,
+            quota_models,
+            pools,
+            quota_resets,
+            client_profile,
+            oauth_client,
         }))
     }
     
@@ -245,12 +345,39 @@ impl TokenManager {
         session_id: Option<&str>,
     ) -> Result<SelectedToken, String> {
         let mut tokens_snapshot: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
-        let total = tokens_snapshot.len();
-        if total == 0 {
+        if tokens_snapshot.is_empty() {
             return Err("Token pool is empty".to_string());
         }
         let scope_group = Self::scope_group(quota_group, request_type);
 
+        // 绑定了账号池的 API Key 只在池内轮换；锁定与会话绑定也按池隔离，避免与其他 Key 互相覆盖
+        let pool = crate::proxy::middleware::auth::current_key_pool();
+        let lock_scope = match &pool {
+            Some(pool) => {
+                retain_pool(&mut tokens_snapshot, pool);
+                if tokens_snapshot.is_empty() {
+                    return Err(format!("No available accounts in pool '{}'", pool));
+                }
+                format!("{}@{}", scope_group, pool)
+            }
+            None => scope_group.clone(),
+        };
+        if let Ok(pinned) = PINNED_ACCOUNT.try_with(|id| id.clone()) {
+            tokens_snapshot.retain(|t| t.account_id == pinned);
+            if tokens_snapshot.is_empty() {
+                return Err(format!("Account {} is not available", pinned));
+            }
+        } else {
+            // 多实例部署：优先使用分给本实例的账号，避免多个实例同时轮到同一账号；本分片账号都在冷却时再借用其他分片
+            let cluster = &crate::proxy::cluster::CLUSTER;
+            if tokens_snapshot.iter().any(|t| {
+                cluster.owns(&t.account_id) && !self.rate_limit_tracker.is_rate_limited(&scope_group, &t.account_id)
+            }) {
+                tokens_snapshot.retain(|t| cluster.owns(&t.account_id));
+            }
+        }
+        let total = tokens_snapshot.len();
+
         // [DEBUG] 追踪账号选择逻辑
         tracing::info!(
             "[TokenManager] get_token called: group={}, type={}, force_rotate={}, session_id={:?}",
@@ -285,7 +412,7 @@ impl TokenManager {
             // 模式 A: 粘性会话处理 (CacheFirst 或 Balance 且有 session_id)
             if !rotate && session_id.is_some() && scheduling.mode != SchedulingMode::PerformanceFirst {
                 let sid = session_id.unwrap();
-                let session_key = Self::session_key(&scope_group, sid);
+                let session_key = Self::session_key(&lock_scope, sid);
 
                 // [DEBUG] 追踪会话绑定查找
                 let bound_account = self.session_accounts.get(&session_key).map(|v| v.clone());
@@ -309,7 +436,7 @@ impl TokenManager {
                                 tracing::debug!("Sticky Session: Successfully recovered and reusing bound account {} for session {}", found.email, sid);
                                 target_token = Some(found.clone());
                                 if request_type != "image_gen" {
-                                    let last_used_lock = self.get_last_used_lock(&scope_group);
+                                    let last_used_lock = self.get_last_used_lock(&lock_scope);
                                     let mut last_used = last_used_lock.lock().await;
                                     *last_used = Some((found.account_id.clone(), std::time::Instant::now()));
                                 }
@@ -328,7 +455,7 @@ impl TokenManager {
                             tracing::debug!("Sticky Session: Successfully reusing bound account {} for session {}", found.email, sid);
                             target_token = Some(found.clone());
                             if request_type != "image_gen" {
-                                let last_used_lock = self.get_last_used_lock(&scope_group);
+                                let last_used_lock = self.get_last_used_lock(&lock_scope);
                                 let mut last_used = last_used_lock.lock().await;
                                 *last_used = Some((found.account_id.clone(), std::time::Instant::now()));
                             }
@@ -339,11 +466,12 @@ impl TokenManager {
 
             // 模式 B: 全局锁定 (针对无 session_id 情况的默认保护)
             if target_token.is_none() && !rotate && request_type != "image_gen" {
-                let last_used_lock = self.get_last_used_lock(&scope_group);
+                let last_used_lock = self.get_last_used_lock(&lock_scope);
                 let mut last_used = last_used_lock.lock().await;
                 
-                // 尝试复用全局锁定账号
-                if let Some((account_id, _last_time)) = &*last_used {
+                // 尝试复用全局锁定账号（会话哈希策略下由会话标识决定账号，不复用）
+                let hashed_session = scheduling.rotation == RotationStrategy::SessionHash && session_id.is_some();
+                if let Some((account_id, _last_time)) = last_used.as_ref().filter(|_| !hashed_session) {
                     if !attempted.contains(account_id) {
                         if self.rate_limit_tracker.is_rate_limited(&scope_group, account_id) {
                             *last_used = None;
@@ -356,53 +484,42 @@ impl TokenManager {
                     }
                 }
                 
-                // 若无锁定，则轮询选择新账号
+                // 若无锁定，则按轮换策略选择新账号
                 if target_token.is_none() {
-                    let start_idx = self.get_group_index(&scope_group).fetch_add(1, Ordering::SeqCst) % total;
-                    for offset in 0..total {
-                        let idx = (start_idx + offset) % total;
-                        let candidate = &tokens_snapshot[idx];
-                        if attempted.contains(&candidate.account_id) {
-                            continue;
-                        }
-
-                        // 【新增】主动避开限流或 5xx 锁定的账号 (来自 PR #28 的高可用思路)
-                        if self.rate_limit_tracker.is_rate_limited(&scope_group, &candidate.account_id) {
-                            continue;
-                        }
-
-                        target_token = Some(candidate.clone());
+                    // 【新增】select_candidate 会主动避开限流或 5xx 锁定的账号 (来自 PR #28 的高可用思路)
+                    if let Some(candidate) = self.select_candidate(
+                        &scope_group,
+                        quota_group,
+                        &tokens_snapshot,
+                        &attempted,
+                        scheduling.rotation,
+                        session_id,
+                    ) {
                         *last_used = Some((candidate.account_id.clone(), std::time::Instant::now()));
                         
                         // 如果是会话首次分配且需要粘性，在此建立绑定
                         if let Some(sid) = session_id {
                             if scheduling.mode != SchedulingMode::PerformanceFirst {
-                                let session_key = Self::session_key(&scope_group, sid);
+                                let session_key = Self::session_key(&lock_scope, sid);
                                 self.session_accounts.insert(session_key, candidate.account_id.clone());
                                 tracing::debug!("Sticky Session: Bound new account {} to session {}", candidate.email, sid);
                             }
                         }
-                        break;
+                        target_token = Some(candidate);
                     }
                 }
             } else if target_token.is_none() {
-                // 模式 C: 纯轮询模式 (Round-robin) 或强制轮换
-                let start_idx = self.get_group_index(&scope_group).fetch_add(1, Ordering::SeqCst) % total;
-                for offset in 0..total {
-                    let idx = (start_idx + offset) % total;
-                    let candidate = &tokens_snapshot[idx];
-                    if attempted.contains(&candidate.account_id) {
-                        continue;
-                    }
-
-                    // 【新增】主动避开限流或 5xx 锁定的账号
-                    if self.rate_limit_tracker.is_rate_limited(&scope_group, &candidate.account_id) {
-                        continue;
-                    }
-
-                    target_token = Some(candidate.clone());
+                // 模式 C: 纯轮询模式 (Round-robin) 或强制轮换，按轮换策略挑选
+                if let Some(candidate) = self.select_candidate(
+                    &scope_group,
+                    quota_group,
+                    &tokens_snapshot,
+                    &attempted,
+                    scheduling.rotation,
+                    session_id,
+                ) {
                     if request_type != "image_gen" {
-                        let last_used_lock = self.get_last_used_lock(&scope_group);
+                        let last_used_lock = self.get_last_used_lock(&lock_scope);
                         let mut last_used = last_used_lock.lock().await;
                         *last_used = Some((candidate.account_id.clone(), std::time::Instant::now()));
                     }
@@ -410,7 +527,7 @@ impl TokenManager {
                     if rotate {
                         tracing::debug!("Force Rotation: Switched to account: {}", candidate.email);
                     }
-                    break;
+                    target_token = Some(candidate);
                 }
             }
             
@@ -426,89 +543,25 @@ impl TokenManager {
                     return Err(format!("All accounts are currently limited or unhealthy. Please wait {}s.", min_wait));
                 }
             };
+            self.last_selected.insert(token.account_id.clone(), std::time::Instant::now());
 
 
             // 3. 检查 token 是否过期（提前5分钟刷新）
-            let now = chrono::Utc::now().timestamp();
-            if now >= token.timestamp - 300 {
-                tracing::debug!("账号 {} 的 token 即将过期，正在刷新...", token.email);
-
-                // 获取或创建该账号的刷新锁
-                let refresh_lock = self.refreshing_accounts
-                    .entry(token.account_id.clone())
-                    .or_insert_with(|| Arc::new(tokio::sync::Mutex::new(())))
-                    .clone();
-
-                // 尝试获取锁，如果已被其他请求持有则等待
-                let _guard = refresh_lock.lock().await;
-
-                // 重新检查 token 是否仍需刷新（可能其他请求已完成刷新）
-                let needs_refresh = if let Some(entry) = self.tokens.get(&token.account_id) {
-                    let current_now = chrono::Utc::now().timestamp();
-                    current_now >= entry.timestamp - 300
-                } else {
-                    true
-                };
-
-                if needs_refresh {
-                    // 调用 OAuth 刷新 token
-                    match crate::modules::oauth::refresh_access_token(&token.refresh_token).await {
-                        Ok(token_response) => {
-                            tracing::debug!("Token 刷新成功！");
-
-                            // 更新本地内存对象供后续使用
-                            token.access_token = token_response.access_token.clone();
-                            token.expires_in = token_response.expires_in;
-                            token.timestamp = now + token_response.expires_in;
-
-                            // 同步更新跨线程共享的 DashMap
-                            if let Some(mut entry) = self.tokens.get_mut(&token.account_id) {
-                                entry.access_token = token.access_token.clone();
-                                entry.expires_in = token.expires_in;
-                                entry.timestamp = token.timestamp;
-                            }
-
-                            // 同步落盘（避免重启后继续使用过期 timestamp 导致频繁刷新）
-                            if let Err(e) = self.save_refreshed_token(&token.account_id, &token_response).await {
-                                tracing::debug!("保存刷新后的 token 失败 ({}): {}", token.email, e);
-                            }
-                        }
-                        Err(e) => {
-                            tracing::error!("Token 刷新失败 ({}): {}，尝试下一个账号", token.email, e);
-                            if e.contains("\"invalid_grant\"") || e.contains("invalid_grant") {
-                                tracing::error!(
-                                    "Disabling account due to invalid_grant ({}): refresh_token likely revoked/expired",
-                                    token.email
-                                );
-                                let _ = self
-                                    .disable_account(&token.account_id, &format!("invalid_grant: {}", e))
-                                    .await;
-                                self.tokens.remove(&token.account_id);
-                            }
-                            // Avoid leaking account emails to API clients; details are still in logs.
-                            last_error = Some(format!("Token refresh failed: {}", e));
-                            attempted.insert(token.account_id.clone());
-
-                            // 如果当前账号被锁定复用，刷新失败后必须解除锁定，避免下一次仍选中同一账号
-                            if request_type != "image_gen" {
-                                let last_used_lock = self.get_last_used_lock(&scope_group);
-                                let mut last_used = last_used_lock.lock().await;
-                                if matches!(&*last_used, Some((id, _)) if id == &token.account_id) {
-                                    *last_used = None;
-                                }
-                            }
-                            continue;
-                        }
-                    }
-                } else {
-                    // 其他请求已完成刷新，从 DashMap 获取最新 token
-                    if let Some(entry) = self.tokens.get(&token.account_id) {
-                        token.access_token = entry.access_token.clone();
-                        token.expires_in = entry.expires_in;
-                        token.timestamp = entry.timestamp;
-                        tracing::debug!("Token 已被其他请求刷新，使用缓存的新 token");
+            if let Err(e) = self.refresh_if_needed(&mut token).await {
+                tracing::error!("Token 刷新失败 ({}): {}，尝试下一个账号", token.email, e);
+                // Avoid leaking account emails to API clients; details are still in logs.
+                last_error = Some(format!("Token refresh failed: {}", e));
+                attempted.insert(token.account_id.clone());
+
+                // 如果当前账号被锁定复用，刷新失败后必须解除锁定，避免下一次仍选中同一账号
+                if request_type != "image_gen" {
+                    let last_used_lock = self.get_last_used_lock(&lock_scope);
+                    let mut last_used = last_used_lock.lock().await;
+                    if matches!(&*last_used, Some((id, _)) if id == &token.account_id) {
+                        *last_used = None;
                     }
                 }
+                continue;
             }
 
             // 4. 确保有 project_id
@@ -526,11 +579,14 @@ impl TokenManager {
                     }
                     Err(e) => {
                         tracing::error!("Failed to fetch project_id for {}: {}", token.email, e);
-                        last_error = Some(format!("Failed to fetch project_id for {}: {}", token.email, e));
+                        last_error = Some(format!(
+                            "Failed to discover the Cloud Code project for {}: {} (retry via POST /api/accounts/{}/refresh-project)",
+                            token.email, e, token.account_id
+                        ));
                         attempted.insert(token.account_id.clone());
 
                         if request_type != "image_gen" {
-                            let last_used_lock = self.get_last_used_lock(&scope_group);
+                            let last_used_lock = self.get_last_used_lock(&lock_scope);
                             let mut last_used = last_used_lock.lock().await;
                             if matches!(&*last_used, Some((id, _)) if id == &token.account_id) {
                                 *last_used = None;
@@ -549,7 +605,7 @@ impl TokenManager {
 
             // 异步更新 current_account_id（不阻塞请求）
             let account_id_for_update = token.account_id.clone();
-            tokio::spawn(async move {
+            tokio::task::spawn_blocking(move || {
                 if let Err(e) = crate::modules::account::set_current_account_id(&account_id_for_update) {
                     tracing::debug!("Failed to update current_account_id: {}", e);
                 }
@@ -560,129 +616,423 @@ impl TokenManager {
                 project_id,
                 email: token.email,
                 account_id: token.account_id,
+                client_profile: token.client_profile,
             });
         }
 
         Err(last_error.unwrap_or_else(|| "All accounts failed".to_string()))
     }
 
-    async fn disable_account(&self, account_id: &str, reason: &str) -> Result<(), String> {
-        let path = if let Some(entry) = self.tokens.get(account_id) {
-            entry.account_path.clone()
-        } else {
-            self.data_dir
-                .join("accounts")
-                .join(format!("{}.json", account_id))
-        };
-
+    /// 读取账号 JSON，修改后写回账号存储
+    async fn update_account_json(
+        account_id: &str,
+        update: impl FnOnce(&mut serde_json::Value) + Send + 'static,
+    ) -> Result<(), String> {
+        let id = account_id.to_string();
         // 使用 spawn_blocking 避免阻塞异步运行时
-        let path_clone = path.clone();
-        let content_str = tokio::task::spawn_blocking(move || {
-            std::fs::read_to_string(&path_clone)
+        tokio::task::spawn_blocking(move || {
+            let content_str = crate::modules::account::read_account_json(&id)
+                .map_err(|e| format!("读取文件失败: {}", e))?
+                .ok_or("账号文件不存在")?;
+            let mut content: serde_json::Value = serde_json::from_str(&content_str)
+                .map_err(|e| format!("解析 JSON 失败: {}", e))?;
+            update(&mut content);
+            let json_str = serde_json::to_string_pretty(&content).unwrap();
+            crate::modules::account::write_account_json(&id, &json_str)
+                .map_err(|e| format!("写入文件失败: {}", e))
         })
         .await
         .map_err(|e| format!("任务执行失败: {}", e))?
-        .map_err(|e| format!("读取文件失败: {}", e))?;
-
-        let mut content: serde_json::Value = serde_json::from_str(&content_str)
-            .map_err(|e| format!("解析 JSON 失败: {}", e))?;
+    }
 
+    async fn disable_account(&self, account_id: &str, reason: &str) -> Result<(), String> {
         let now = chrono::Utc::now().timestamp();
-        content["disabled"] = serde_json::Value::Bool(true);
-        content["disabled_at"] = serde_json::Value::Number(now.into());
-        content["disabled_reason"] = serde_json::Value::String(truncate_reason(reason, 800));
-
-        let json_str = serde_json::to_string_pretty(&content).unwrap();
-        let path_clone = path.clone();
-        tokio::task::spawn_blocking(move || {
-            std::fs::write(&path_clone, json_str)
+        let reason = truncate_reason(reason, 800);
+        Self::update_account_json(account_id, move |content| {
+            content["disabled"] = serde_json::Value::Bool(true);
+            content["disabled_at"] = serde_json::Value::Number(now.into());
+            content["disabled_reason"] = serde_json::Value::String(reason);
         })
-        .await
-        .map_err(|e| format!("任务执行失败: {}", e))?
-        .map_err(|e| format!("写入文件失败: {}", e))?;
+        .await?;
 
-        tracing::warn!("Account disabled: {} ({:?})", account_id, path);
+        tracing::warn!("Account disabled: {}", account_id);
         Ok(())
     }
 
     /// 保存 project_id 到账号文件
     async fn save_project_id(&self, account_id: &str, project_id: &str) -> Result<(), String> {
-        let entry = self.tokens.get(account_id)
-            .ok_or("账号不存在")?;
-
-        let path = entry.account_path.clone();
-        drop(entry); // 释放锁
+        if !self.tokens.contains_key(account_id) {
+            return Err("账号不存在".to_string());
+        }
 
-        // 使用 spawn_blocking 避免阻塞异步运行时
-        let path_clone = path.clone();
-        let content_str = tokio::task::spawn_blocking(move || {
-            std::fs::read_to_string(&path_clone)
+        let project_id = project_id.to_string();
+        Self::update_account_json(account_id, move |content| {
+            content["token"]["project_id"] = serde_json::Value::String(project_id);
         })
-        .await
-        .map_err(|e| format!("任务执行失败: {}", e))?
-        .map_err(|e| format!("读取文件失败: {}", e))?;
+        .await?;
 
-        let mut content: serde_json::Value = serde_json::from_str(&content_str)
-            .map_err(|e| format!("解析 JSON 失败: {}", e))?;
+        tracing::debug!("已保存 project_id 到账号 {}", account_id);
+        Ok(())
+    }
 
-        content["token"]["project_id"] = serde_json::Value::String(project_id.to_string());
+    /// 保存刷新后的 token 到账号文件
+    async fn save_refreshed_token(&self, account_id: &str, token_response: &crate::modules::oauth::TokenResponse) -> Result<(), String> {
+        if !self.tokens.contains_key(account_id) {
+            return Err("账号不存在".to_string());
+        }
 
-        let json_str = serde_json::to_string_pretty(&content).unwrap();
-        let path_clone = path.clone();
-        tokio::task::spawn_blocking(move || {
-            std::fs::write(&path_clone, json_str)
+        let now = chrono::Utc::now().timestamp();
+        let access_token = crate::modules::secret_store::encrypt(&token_response.access_token)?;
+        let expires_in = token_response.expires_in;
+        Self::update_account_json(account_id, move |content| {
+            content["token"]["access_token"] = serde_json::Value::String(access_token);
+            content["token"]["expires_in"] = serde_json::Value::Number(expires_in.into());
+            content["token"]["expiry_timestamp"] = serde_json::Value::Number((now + expires_in).into());
         })
-        .await
-        .map_err(|e| format!("任务执行失败: {}", e))?
-        .map_err(|e| format!("写入文件失败: {}", e))?;
+        .await?;
 
-        tracing::debug!("已保存 project_id 到账号 {}", account_id);
+        tracing::debug!("已保存刷新后的 token 到账号 {}", account_id);
         Ok(())
     }
+    
+    /// token 即将过期时刷新（提前 TOKEN_REFRESH_AHEAD_SECS 秒）
+    ///
+    /// 使用每账号刷新锁，避免请求路径与后台任务并发刷新同一账号；
+    /// invalid_grant 时禁用账号并移出 token 池
+    async fn refresh_if_needed(&self, token: &mut ProxyToken) -> Result<(), String> {
+        let now = chrono::Utc::now().timestamp();
+        if now < token.timestamp - TOKEN_REFRESH_AHEAD_SECS {
+            return Ok(());
+        }
+        tracing::debug!("账号 {} 的 token 即将过期，正在刷新...", token.email);
 
-    /// 保存刷新后的 token 到账号文件
-    async fn save_refreshed_token(&self, account_id: &str, token_response: &crate::modules::oauth::TokenResponse) -> Result<(), String> {
-        let entry = self.tokens.get(account_id)
-            .ok_or("账号不存在")?;
+        // 获取或创建该账号的刷新锁
+        let refresh_lock = self.refreshing_accounts
+            .entry(token.account_id.clone())
+            .or_insert_with(|| Arc::new(tokio::sync::Mutex::new(())))
+            .clone();
 
-        let path = entry.account_path.clone();
-        drop(entry); // 释放锁
+        // 尝试获取锁，如果已被其他请求持有则等待
+        let _guard = refresh_lock.lock().await;
 
-        // 使用 spawn_blocking 避免阻塞异步运行时
-        let path_clone = path.clone();
-        let content_str = tokio::task::spawn_blocking(move || {
-            std::fs::read_to_string(&path_clone)
-        })
-        .await
-        .map_err(|e| format!("任务执行失败: {}", e))?
-        .map_err(|e| format!("读取文件失败: {}", e))?;
+        // 重新检查 token 是否仍需刷新（可能其他请求已完成刷新）
+        let needs_refresh = if let Some(entry) = self.tokens.get(&token.account_id) {
+            let current_now = chrono::Utc::now().timestamp();
+            current_now >= entry.timestamp - TOKEN_REFRESH_AHEAD_SECS
+        } else {
+            true
+        };
 
-        let mut content: serde_json::Value = serde_json::from_str(&content_str)
-            .map_err(|e| format!("解析 JSON 失败: {}", e))?;
+        if !needs_refresh {
+            // 其他请求已完成刷新，从 DashMap 获取最新 token
+            if let Some(entry) = self.tokens.get(&token.account_id) {
+                token.access_token = entry.access_token.clone();
+                token.expires_in = entry.expires_in;
+                token.timestamp = entry.timestamp;
+                tracing::debug!("Token 已被其他请求刷新，使用缓存的新 token");
+            }
+            return Ok(());
+        }
 
+        // 调用 OAuth 刷新 token
+        match crate::modules::oauth::refresh_access_token(&token.refresh_token, token.oauth_client.as_deref()).await {
+            Ok(token_response) => {
+                tracing::debug!("Token 刷新成功！");
+                self.auth_failures.remove(&token.account_id);
+
+                // 更新本地内存对象供后续使用
+                token.access_token = token_response.access_token.clone();
+                token.expires_in = token_response.expires_in;
+                token.timestamp = now + token_response.expires_in;
+
+                // 同步更新跨线程共享的 DashMap
+                if let Some(mut entry) = self.tokens.get_mut(&token.account_id) {
+                    entry.access_token = token.access_token.clone();
+                    entry.expires_in = token.expires_in;
+                    entry.timestamp = token.timestamp;
+                }
+
+                // 同步落盘（避免重启后继续使用过期 timestamp 导致频繁刷新）
+                if let Err(e) = self.save_refreshed_token(&token.account_id, &token_response).await {
+                    tracing::debug!("保存刷新后的 token 失败 ({}): {}", token.email, e);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                // 网络错误不算认证失败，只有 OAuth 服务端拒绝刷新时计数并告警
+                if e.starts_with("刷新请求失败") {
+                    return Err(e);
+                }
+                let failures = {
+                    let mut count = self.auth_failures.entry(token.account_id.clone()).or_insert(0);
+                    *count += 1;
+                    *count
+                };
+                let invalid_grant = e.contains("invalid_grant");
+                let disable_reason = if invalid_grant {
+                    tracing::error!(
+                        "Disabling account due to invalid_grant ({}): refresh_token likely revoked/expired",
+                        token.email
+                    );
+                    Some(format!("invalid_grant: {}", e))
+                } else if failures >= MAX_AUTH_FAILURES {
+                    tracing::error!("Disabling account after {} failed token refreshes ({})", failures, token.email);
+                    Some(format!("token refresh failed {} times in a row: {}", failures, e))
+                } else {
+                    None
+                };
+                if let Some(reason) = &disable_reason {
+                    let _ = self
+                        .disable_account(&token.account_id, &format!("{} (re-authorize via POST /api/accounts/{}/reauth)", reason, token.account_id))
+                        .await;
+                    self.tokens.remove(&token.account_id);
+                    self.auth_failures.remove(&token.account_id);
+                }
+                crate::modules::alerts::notify_account_auth_failure(&token.email, &e, disable_reason.is_some());
+                Err(e)
+            }
+        }
+    }
+
+    /// 启动后台刷新任务：定期检查 token 池，在过期前主动刷新 access token
+    ///
+    /// 避免过期后的第一个请求承担刷新延迟
+    pub fn spawn_refresh_task(self: &Arc<Self>) {
+        let manager = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(REFRESH_CHECK_INTERVAL_SECS));
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                interval.tick().await;
+                let refreshed = manager.refresh_expiring_tokens().await;
+                if refreshed > 0 {
+                    tracing::info!("[TokenManager] Proactively refreshed {} access token(s)", refreshed);
+                }
+            }
+        });
+    }
+
+    /// 刷新所有即将过期的 token，返回成功刷新的数量
+    pub async fn refresh_expiring_tokens(&self) -> usize {
         let now = chrono::Utc::now().timestamp();
+        let expiring: Vec<ProxyToken> = self
+            .tokens
+            .iter()
+            .filter(|e| now >= e.value().timestamp - TOKEN_REFRESH_AHEAD_SECS)
+            .map(|e| e.value().clone())
+            .collect();
+
+        let mut refreshed = 0;
+        for mut token in expiring {
+            match self.refresh_if_needed(&mut token).await {
+                Ok(()) => refreshed += 1,
+                Err(e) => tracing::warn!("[TokenManager] Background refresh failed ({}): {}", token.email, e),
+            }
+        }
+        refreshed
+    }
 
-        content["token"]["access_token"] = serde_json::Value::String(token_response.access_token.clone());
-        content["token"]["expires_in"] = serde_json::Value::Number(token_response.expires_in.into());
-        content["token"]["expiry_timestamp"] = serde_json::Value::Number((now + token_response.expires_in).into());
+    /// 按轮换策略挑选新账号（跳过本轮已尝试及限流中的账号）
+    fn select_candidate(
+        &self,
+        scope_group: &str,
+        quota_group: &str,
+        tokens: &[ProxyToken],
+        attempted: &HashSet<String>,
+        strategy: RotationStrategy,
+        session_id: Option<&str>,
+    ) -> Option<ProxyToken> {
+        let is_available = |t: &ProxyToken| {
+            !attempted.contains(&t.account_id)
+                && !self.rate_limit_tracker.is_rate_limited(scope_group, &t.account_id)
+        };
 
-        let json_str = serde_json::to_string_pretty(&content).unwrap();
-        let path_clone = path.clone();
-        tokio::task::spawn_blocking(move || {
-            std::fs::write(&path_clone, json_str)
-        })
-        .await
-        .map_err(|e| format!("任务执行失败: {}", e))?
-        .map_err(|e| format!("写入文件失败: {}", e))?;
+        match strategy {
+            RotationStrategy::RoundRobin => self.select_round_robin(scope_group, tokens, &is_available),
+            RotationStrategy::LeastRecentlyUsed => tokens
+                .iter()
+                .filter(|t| is_available(t))
+                // 从未使用过的账号 (None) 排在最前
+                .min_by_key(|t| self.last_selected.get(&t.account_id).map(|v| *v))
+                .cloned(),
+            RotationStrategy::QuotaWeighted => {
+                let available: Vec<&ProxyToken> = tokens.iter().filter(|t| is_available(t)).collect();
+                let weights: Vec<u64> = available
+                    .iter()
+                    .map(|t| quota_weight(&t.quota_models, quota_group))
+                    .collect();
+                let total: u64 = weights.iter().sum();
+                if total == 0 {
+                    return self.select_round_robin(scope_group, tokens, &is_available);
+                }
+                let roll = rand::Rng::gen_range(&mut rand::thread_rng(), 0..total);
+                pick_weighted(&weights, roll).map(|idx| available[idx].clone())
+            }
+            RotationStrategy::StickyPerKey => {
+                let Some(key_id) = crate::proxy::middleware::auth::current_key_id() else {
+                    return self.select_round_robin(scope_group, tokens, &is_available);
+                };
+                let binding = format!("{}::{}", scope_group, key_id);
+                if let Some(bound_id) = self.key_accounts.get(&binding).map(|v| v.clone()) {
+                    if let Some(found) = tokens.iter().find(|t| t.account_id == bound_id && is_available(t)) {
+                        return Some(found.clone());
+                    }
+                }
+                let candidate = self.select_round_robin(scope_group, tokens, &is_available)?;
+                tracing::debug!("Sticky Key: Bound account {} to API key {}", candidate.email, key_id);
+                self.key_accounts.insert(binding, candidate.account_id.clone());
+                Some(candidate)
+            }
+            RotationStrategy::SessionHash => {
+                let Some(sid) = session_id else {
+                    return self.select_round_robin(scope_group, tokens, &is_available);
+                };
+                // Rendezvous hashing：账号增减时只有少数会话会换号
+                tokens
+                    .iter()
+                    .filter(|t| is_available(t))
+                    .max_by_key(|t| session_hash_score(sid, &t.account_id))
+                    .cloned()
+            }
+            RotationStrategy::Random => {
+                let available: Vec<&ProxyToken> = tokens.iter().filter(|t| is_available(t)).collect();
+                if available.is_empty() {
+                    return None;
+                }
+                let idx = rand::Rng::gen_range(&mut rand::thread_rng(), 0..available.len());
+                Some(available[idx].clone())
+            }
+        }
+    }
 
-        tracing::debug!("已保存刷新后的 token 到账号 {}", account_id);
-        Ok(())
+    fn select_round_robin(
+        &self,
+        scope_group: &str,
+        tokens: &[ProxyToken],
+        is_available: &dyn Fn(&ProxyToken) -> bool,
+    ) -> Option<ProxyToken> {
+        let total = tokens.len();
+        if total == 0 {
+            return None;
+        }
+        let start_idx = self.get_group_index(scope_group).fetch_add(1, Ordering::SeqCst) % total;
+        (0..total)
+            .map(|offset| &tokens[(start_idx + offset) % total])
+            .find(|t| is_available(t))
+            .cloned()
     }
-    
+
     pub fn len(&self) -> usize {
         self.tokens.len()
     }
+
+    /// access token 尚未过期的账号数（后台任务会在过期前刷新，过期说明刷新失败）
+    pub fn valid_token_count(&self) -> usize {
+        let now = chrono::Utc::now().timestamp();
+        self.tokens.iter().filter(|e| e.value().timestamp > now).count()
+    }
+
+    /// 账号健康快照（用于 /metrics）
+    ///
+    /// 任一配额组处于限流状态即视为不健康
+    pub fn account_health(&self) -> Vec<crate::proxy::metrics::AccountHealth> {
+        let mut accounts: Vec<_> = self
+            .tokens
+            .iter()
+            .map(|entry| {
+                let token = entry.value();
+                let rate_limited = QUOTA_GROUPS
+                    .iter()
+                    .any(|group| self.rate_limit_tracker.is_rate_limited(group, &token.account_id));
+                crate::proxy::metrics::AccountHealth {
+                    email: token.email.clone(),
+                    rate_limited,
+                }
+            })
+            .collect();
+        accounts.sort_by(|a, b| a.email.cmp(&b.email));
+        accounts
+    }
     
+    /// 记录账号的一次成功上游响应
+    pub fn record_success(&self, account_id: &str) {
+        self.stats.record_success(account_id);
+        self.note_served_account(account_id);
+    }
+
+    /// 将账号关联到当前请求的日志记录
+    fn note_served_account(&self, account_id: &str) {
+        if let Some(token) = self.tokens.get(account_id) {
+            crate::proxy::account_stats::note_served_account(&token.email);
+        }
+    }
+
+    /// 主动探测所有账号：刷新 token 并调用 fetchAvailableModels
+    ///
+    /// 探测成功时同步更新账号的配额快照（QuotaWeighted 策略使用）
+    pub async fn probe_health(
+        &self,
+        upstream: &crate::proxy::upstream::client::UpstreamClient,
+    ) -> Vec<AccountHealthReport> {
+        let tokens: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
+        let probes = tokens.into_iter().map(|token| self.probe_account(token, upstream));
+        let mut reports = futures::future::join_all(probes).await;
+        reports.sort_by(|a, b| a.email.cmp(&b.email));
+        reports
+    }
+
+    async fn probe_account(
+        &self,
+        mut token: ProxyToken,
+        upstream: &crate::proxy::upstream::client::UpstreamClient,
+    ) -> AccountHealthReport {
+        let started = std::time::Instant::now();
+        let probe = match self.refresh_if_needed(&mut token).await {
+            Ok(()) => upstream.fetch_available_models(&token.access_token, token.client_profile.as_deref()).await,
+            Err(e) => Err(format!("Token refresh failed: {}", e)),
+        };
+        let probe_latency_ms = started.elapsed().as_millis() as u64;
+
+        crate::proxy::model_registry::MODEL_REGISTRY.record(
+            &token.account_id,
+            &token.email,
+            probe.as_ref().map_err(String::as_str),
+        );
+        let (token_valid, error, quota) = match probe {
+            Ok(resp) => {
+                let quota = crate::proxy::account_stats::parse_quota_models(&resp);
+                if let Some(mut entry) = self.tokens.get_mut(&token.account_id) {
+                    entry.quota_models = quota.clone();
+                }
+                (true, None, quota)
+            }
+            // 探测失败时回落到上次已知的配额
+            Err(e) => (false, Some(e), token.quota_models.clone()),
+        };
+
+        let stats = self.stats.snapshot(&token.account_id);
+        let rate_limited = QUOTA_GROUPS
+            .iter()
+            .any(|group| self.rate_limit_tracker.is_rate_limited(group, &token.account_id));
+        let cooldown_until = self.cooldown_until(&token.account_id);
+
+        AccountHealthReport {
+            account_id: token.account_id,
+            email: token.email,
+            subscription_tier: token.subscription_tier,
+            token_valid,
+            error,
+            token_expires_at: token.timestamp,
+            remaining_quota: quota.into_iter().collect(),
+            rate_limited,
+            recent_requests: stats.requests,
+            recent_429_rate: stats.rate_limited_ratio(),
+            last_success_at: stats.last_success,
+            cooldown_until,
+            probe_latency_ms,
+        }
+    }
+
     // ===== 限流管理方法 =====
     
     /// 标记账号限流(从外部调用,通常在 handler 中)
@@ -695,14 +1045,76 @@ impl TokenManager {
         retry_after_header: Option<&str>,
         error_body: &str,
     ) {
+        self.stats.record_failure(account_id, status);
+        self.note_served_account(account_id);
         let scope_group = Self::scope_group(quota_group, request_type);
-        self.rate_limit_tracker.parse_from_error(
+        let now = chrono::Utc::now().timestamp();
+        let quota_reset_hint = self.tokens.get(account_id).and_then(|token| {
+            token.quota_resets
+                .iter()
+                .find(|(group, _)| group == quota_group)
+                .map(|(_, reset_at)| reset_at - now)
+                .filter(|secs| *secs > 0)
+                .map(|secs| secs as u64)
+        });
+        let Some(info) = self.rate_limit_tracker.parse_from_error(
             &scope_group,
             account_id,
             status,
             retry_after_header,
             error_body,
+            quota_reset_hint,
+        ) else {
+            return;
+        };
+
+        // 429 写入账号文件，重启后仍跳过冷却中的账号；同时通知其他实例
+        if status == 429 {
+            let account_id = account_id.to_string();
+            let until = now + info.retry_after_sec as i64;
+            crate::proxy::cluster::CLUSTER.broadcast_cooldown(crate::proxy::cluster::CooldownNotice {
+                quota_group: scope_group.clone(),
+                account_id: account_id.clone(),
+                until,
+                reason: info.reason.as_str().to_string(),
+            });
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = crate::modules::quota::record_cooldown(&account_id, &scope_group, until, info.reason.as_str()) {
+                    tracing::warn!("Failed to persist cooldown for {}: {}", account_id, e);
+                }
+            });
+        }
+    }
+
+    /// 应用其他实例推送的冷却状态；账号不在本实例时返回 false
+    pub fn apply_peer_cooldown(&self, scope_group: &str, account_id: &str, until: i64, reason: crate::proxy::rate_limit::RateLimitReason) -> bool {
+        if !self.tokens.contains_key(account_id) || until <= chrono::Utc::now().timestamp() {
+            return false;
+        }
+        self.rate_limit_tracker.set_cooldown(
+            scope_group,
+            account_id,
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(until as u64),
+            reason,
         );
+        let (scope_group, account_id) = (scope_group.to_string(), account_id.to_string());
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = crate::modules::quota::record_cooldown(&account_id, &scope_group, until, reason.as_str()) {
+                tracing::warn!("Failed to persist peer cooldown for {}: {}", account_id, e);
+            }
+        });
+        true
+    }
+
+    /// 账号冷却结束时间 (Unix 秒)，取各配额组中最晚的一个
+    fn cooldown_until(&self, account_id: &str) -> Option<i64> {
+        QUOTA_GROUPS
+            .iter()
+            .filter_map(|group| self.rate_limit_tracker.get(group, account_id))
+            .filter(|info| info.reset_time > std::time::SystemTime::now())
+            .filter_map(|info| info.reset_time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64)
+            .max()
     }
     
     /// 检查账号是否在限流中
@@ -761,7 +1173,46 @@ impl TokenManager {
     /// 清除所有会话的粘性映射
     pub fn clear_all_sessions(&self) {
         self.session_accounts.clear();
+        self.key_accounts.clear();
+    }
+}
+
+/// 只保留属于指定账号池的账号
+fn retain_pool(tokens: &mut Vec<ProxyToken>, pool: &str) {
+    tokens.retain(|t| t.pools.iter().any(|p| p == pool));
+}
+
+/// 计算账号在指定配额组下的权重：取该组模型中最高的剩余百分比
+/// 没有配额数据时给予中间权重，避免新账号被饿死
+fn quota_weight(quota_models: &[(String, i32)], quota_group: &str) -> u64 {
+    quota_models
+        .iter()
+        .filter(|(name, _)| name.contains(quota_group))
+        .map(|(_, pct)| (*pct).clamp(0, 100) as u64)
+        .max()
+        .unwrap_or(50)
+}
+
+/// 加权选择：roll 取值范围为 [0, sum(weights))
+fn pick_weighted(weights: &[u64], mut roll: u64) -> Option<usize> {
+    for (idx, weight) in weights.iter().enumerate() {
+        if roll < *weight {
+            return Some(idx);
+        }
+        roll -= weight;
     }
+    None
+}
+
+/// 会话与账号的哈希得分，得分最高的可用账号承接该会话（SHA-256，跨进程 / 重启稳定）
+fn session_hash_score(session_id: &str, account_id: &str) -> u64 {
+    use sha2::{Digest, Sha256};
+    let digest = Sha256::new()
+        .chain_update(session_id.as_bytes())
+        .chain_update([0u8])
+        .chain_update(account_id.as_bytes())
+        .finalize();
+    u64::from_be_bytes(digest[..8].try_into().expect("digest is 32 bytes"))
 }
 
 fn truncate_reason(reason: &str, max_len: usize) -> String {
@@ -772,3 +1223,79 @@ fn truncate_reason(reason: &str, max_len: usize) -> String {
     s.push('…');
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_weight_uses_group_models() {
+        let models = vec![
+            ("claude-sonnet-4-5".to_string(), 20),
+            ("claude-opus-4-5".to_string(), 80),
+            ("gemini-3-pro-high".to_string(), 5),
+        ];
+        assert_eq!(quota_weight(&models, "claude"), 80);
+        assert_eq!(quota_weight(&models, "gemini"), 5);
+        assert_eq!(quota_weight(&[], "claude"), 50);
+    }
+
+    #[test]
+    fn test_pick_weighted() {
+        let weights = [0, 10, 30];
+        assert_eq!(pick_weighted(&weights, 0), Some(1));
+        assert_eq!(pick_weighted(&weights, 9), Some(1));
+        assert_eq!(pick_weighted(&weights, 10), Some(2));
+        assert_eq!(pick_weighted(&weights, 39), Some(2));
+        assert_eq!(pick_weighted(&weights, 40), None);
+    }
+
+    #[test]
+    fn test_session_hash_is_stable_and_spreads() {
+        let accounts = ["acc-a", "acc-b", "acc-c"];
+        fn pick<'a>(sid: &str, accounts: &[&'a str]) -> &'a str {
+            accounts.iter().max_by_key(|a| session_hash_score(sid, a)).unwrap()
+        }
+        assert_eq!(pick("sid-1", &accounts), pick("sid-1", &accounts));
+
+        // 移除未承接该会话的账号不影响映射
+        let chosen = pick("sid-1", &accounts);
+        let others: Vec<&str> = accounts.iter().copied().filter(|a| *a != chosen).collect();
+        let remaining = [chosen, others[0]];
+        assert_eq!(pick("sid-1", &remaining), chosen);
+
+        let spread: HashSet<&str> = (0..50).map(|i| pick(&format!("sid-{}", i), &accounts)).collect();
+        assert_eq!(spread.len(), accounts.len());
+    }
+
+    #[test]
+    fn test_retain_pool() {
+        let token = |id: &str, pools: &[&str]| ProxyToken {
+            account_id: id.to_string(),
+            access_token: String::new(),
+            refresh_token: String::new(),
+            expires_in: 0,
+            timestamp: 0,
+            email: format!("{}@example.com", id),
+            project_id: None,
+            subscription_tier: None,
+            quota_models: Vec::new(),
+            pools: pools.iter().map(|p| p.to_string()).collect(),
+            quota_resets: Vec::new(),
+            client_profile: None,
+            oauth_client: None,
+        };
+        let mut tokens = vec![
+            token("personal", &["me"]),
+            token("shared-1", &["friends"]),
+            token("shared-2", &["friends", "prod"]),
+            token("untagged", &[]),
+        ];
+        retain_pool(&mut tokens, "friends");
+        let ids: Vec<&str> = tokens.iter().map(|t| t.account_id.as_str()).collect();
+        assert_eq!(ids, ["shared-1", "shared-2"]);
+
+        retain_pool(&mut tokens, "experiments");
+        assert!(tokens.is_empty());
+    }
+}
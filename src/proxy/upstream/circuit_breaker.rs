@@ -0,0 +1,211 @@
+// 上游端点熔断器
+// 连续失败 N 次后熔断（Open），冷却期内跳过该端点；冷却结束后放行一个探测请求（HalfOpen）
+// 阈值与冷却时间来自 `upstream_timeouts`，热重载时更新
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::UpstreamTimeoutConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BreakerState {
+    /// 正常，记录连续失败次数
+    Closed { failures: u32 },
+    /// 熔断中，直到指定时间
+    Open { until: Instant },
+    /// 冷却结束，已放行一个探测请求，等待其结果
+    /// 若探测请求被取消（结果一直未上报），超过冷却时间后重新放行
+    HalfOpen { since: Instant },
+}
+
+pub struct CircuitBreaker {
+    states: DashMap<String, BreakerState>,
+    failure_threshold: AtomicU32,
+    cooldown_ms: AtomicU64,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        let config = UpstreamTimeoutConfig::default();
+        Self::new(config.circuit_failure_threshold, config.circuit_cooldown())
+    }
+}
+
+/// 校验熔断参数
+pub fn validate(config: &UpstreamTimeoutConfig) -> Result<(), String> {
+    if config.circuit_failure_threshold == 0 {
+        return Err("circuit_failure_threshold must be greater than 0".to_string());
+    }
+    if config.circuit_cooldown_seconds == 0 {
+        return Err("circuit_cooldown_seconds must be greater than 0".to_string());
+    }
+    Ok(())
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        let breaker = Self {
+            states: DashMap::new(),
+            failure_threshold: AtomicU32::new(0),
+            cooldown_ms: AtomicU64::new(0),
+        };
+        breaker.configure(failure_threshold, cooldown);
+        breaker
+    }
+
+    /// 更新阈值与冷却时间；已熔断的端点保持原定的冷却结束时间
+    pub fn configure(&self, failure_threshold: u32, cooldown: Duration) {
+        self.failure_threshold.store(failure_threshold.max(1), Ordering::Relaxed);
+        self.cooldown_ms.store(cooldown.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn cooldown(&self) -> Duration {
+        Duration::from_millis(self.cooldown_ms.load(Ordering::Relaxed))
+    }
+
+    /// 是否允许向该端点发送请求
+    pub fn allow_request(&self, endpoint: &str) -> bool {
+        self.allow_request_at(endpoint, Instant::now())
+    }
+
+    fn allow_request_at(&self, endpoint: &str, now: Instant) -> bool {
+        let mut state = self
+            .states
+            .entry(endpoint.to_string())
+            .or_insert(BreakerState::Closed { failures: 0 });
+        match *state {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { until } if now >= until => {
+                tracing::info!("Circuit half-open, probing endpoint: {}", endpoint);
+                *state = BreakerState::HalfOpen { since: now };
+                true
+            }
+            BreakerState::HalfOpen { since } if now >= since + self.cooldown() => {
+                *state = BreakerState::HalfOpen { since: now };
+                true
+            }
+            // 熔断中或探测请求尚未返回
+            BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => false,
+        }
+    }
+
+    /// 端点是否处于熔断冷却期（只读，不会触发半开探测）
+    pub fn is_open(&self, endpoint: &str) -> bool {
+        matches!(
+            self.states.get(endpoint).map(|s| *s),
+            Some(BreakerState::Open { until }) if Instant::now() < until
+        )
+    }
+
+    /// 端点正常响应
+    pub fn record_success(&self, endpoint: &str) {
+        if let Some(mut state) = self.states.get_mut(endpoint) {
+            if !matches!(*state, BreakerState::Closed { failures: 0 }) {
+                if !matches!(*state, BreakerState::Closed { .. }) {
+                    tracing::info!("Circuit closed for endpoint: {}", endpoint);
+                }
+                *state = BreakerState::Closed { failures: 0 };
+            }
+        }
+    }
+
+    /// 端点不可用（网络错误 / 5xx / 404 / 408）
+    pub fn record_failure(&self, endpoint: &str) {
+        self.record_failure_at(endpoint, Instant::now())
+    }
+
+    fn record_failure_at(&self, endpoint: &str, now: Instant) {
+        let mut state = self
+            .states
+            .entry(endpoint.to_string())
+            .or_insert(BreakerState::Closed { failures: 0 });
+        let cooldown = self.cooldown();
+        let next = match *state {
+            BreakerState::Closed { failures } if failures + 1 < self.failure_threshold.load(Ordering::Relaxed) => {
+                BreakerState::Closed { failures: failures + 1 }
+            }
+            // 达到阈值，或探测失败，重新熔断
+            _ => {
+                tracing::warn!(
+                    "Circuit opened for endpoint: {} (cooldown {}s)",
+                    endpoint,
+                    cooldown.as_secs()
+                );
+                BreakerState::Open { until: now + cooldown }
+            }
+        };
+        *state = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_threshold() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(10));
+        let now = Instant::now();
+        assert!(breaker.allow_request_at("a", now));
+        breaker.record_failure_at("a", now);
+        assert!(breaker.allow_request_at("a", now));
+        breaker.record_failure_at("a", now);
+        assert!(!breaker.allow_request_at("a", now));
+        // 其他端点不受影响
+        assert!(breaker.allow_request_at("b", now));
+    }
+
+    #[test]
+    fn test_half_open_probe() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(10));
+        let now = Instant::now();
+        breaker.record_failure_at("a", now);
+        assert!(!breaker.allow_request_at("a", now + Duration::from_secs(5)));
+
+        // 冷却结束：只放行一个探测请求
+        let later = now + Duration::from_secs(10);
+        assert!(breaker.allow_request_at("a", later));
+        assert!(!breaker.allow_request_at("a", later));
+
+        // 探测失败重新熔断
+        breaker.record_failure_at("a", later);
+        assert!(!breaker.allow_request_at("a", later + Duration::from_secs(1)));
+
+        // 探测成功关闭熔断
+        let probe = later + Duration::from_secs(10);
+        assert!(breaker.allow_request_at("a", probe));
+        breaker.record_success("a");
+        assert!(breaker.allow_request_at("a", probe));
+        assert!(breaker.allow_request_at("a", probe));
+    }
+
+    #[test]
+    fn test_configure() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(30));
+        let now = Instant::now();
+        breaker.record_failure_at("a", now);
+        // 调低阈值后，已累计的失败次数立即生效
+        breaker.configure(2, Duration::from_secs(5));
+        breaker.record_failure_at("a", now);
+        assert!(!breaker.allow_request_at("a", now));
+        assert!(breaker.allow_request_at("a", now + Duration::from_secs(5)));
+
+        let mut config = UpstreamTimeoutConfig::default();
+        assert!(validate(&config).is_ok());
+        config.circuit_failure_threshold = 0;
+        assert!(validate(&config).is_err());
+        config.circuit_failure_threshold = 1;
+        config.circuit_cooldown_seconds = 0;
+        assert!(validate(&config).is_err());
+    }
+
+    #[test]
+    fn test_success_resets_failure_count() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(10));
+        let now = Instant::now();
+        breaker.record_failure_at("a", now);
+        breaker.record_success("a");
+        breaker.record_failure_at("a", now);
+        assert!(breaker.allow_request_at("a", now));
+    }
+}
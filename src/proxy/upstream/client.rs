@@ -2,39 +2,192 @@
 // High-performance HTTP client wrapper
 
 use reqwest::{header, Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
 use tokio::sync::RwLock;
 use tokio::time::Duration;
 
+use super::circuit_breaker::CircuitBreaker;
+use super::profiles::{apply_body_fields, CLIENT_PROFILES};
+use super::retry::{HedgeBudget, RetryBudget};
+use super::shadow::{ShadowPermit, SHADOW};
+use super::timeout::{guard_stream, timeout_error};
+use crate::proxy::config::{RetryConfig, UpstreamProxyConfig, UpstreamTimeoutConfig};
+
 // Cloud Code v1internal endpoints
 // [FIX] daily 端点优先 - sandbox 端点返回 404 已移除
 const V1_INTERNAL_BASE_URL_DAILY: &str = "https://daily-cloudcode-pa.googleapis.com/v1internal";
 const V1_INTERNAL_BASE_URL_PROD: &str = "https://cloudcode-pa.googleapis.com/v1internal";
+/// 数据目录下保存端点优先级（自动提升后的顺序）的文件
+const ENDPOINT_ORDER_FILE: &str = "upstream_endpoints.json";
 
-pub struct UpstreamClient {
-    http_client: Client,
+/// 默认端点列表（未配置 `upstream_endpoints` 时使用）
+pub fn default_endpoints() -> Vec<String> {
+    vec![
+        V1_INTERNAL_BASE_URL_DAILY.to_string(),
+        V1_INTERNAL_BASE_URL_PROD.to_string(),
+    ]
+}
+
+/// 校验并规范化端点列表：仅允许 http(s)，去掉末尾的 `/`，去重且不能为空
+pub fn normalize_endpoints(urls: &[String]) -> Result<Vec<String>, String> {
+    let mut endpoints: Vec<String> = Vec::with_capacity(urls.len());
+    for raw in urls {
+        let trimmed = raw.trim().trim_end_matches('/');
+        if trimmed.is_empty() {
+            continue;
+        }
+        let parsed = url::Url::parse(trimmed).map_err(|e| format!("Invalid endpoint '{}': {}", raw, e))?;
+        if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+            return Err(format!("Invalid endpoint '{}': must be an http(s) URL", raw));
+        }
+        if parsed.query().is_some() || parsed.fragment().is_some() {
+            return Err(format!("Invalid endpoint '{}': query strings are not allowed", raw));
+        }
+        if !endpoints.iter().any(|e| e == trimmed) {
+            endpoints.push(trimmed.to_string());
+        }
+    }
+    if endpoints.is_empty() {
+        return Err("At least one upstream endpoint is required".to_string());
+    }
+    Ok(endpoints)
+}
+
+/// 持久化的端点优先级
+///
+/// 只有配置的端点列表未变化时才恢复学习到的顺序，配置变化后以配置顺序为准
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PersistedOrder {
+    configured: Vec<String>,
+    order: Vec<String>,
+}
+
+fn restore_order(configured: &[String], persisted: Option<PersistedOrder>) -> Vec<String> {
+    match persisted {
+        Some(p) if p.configured == configured && is_permutation(&p.order, configured) => p.order,
+        _ => configured.to_vec(),
+    }
+}
+
+fn is_permutation(order: &[String], configured: &[String]) -> bool {
+    order.len() == configured.len() && configured.iter().all(|e| order.contains(e))
+}
+
+fn load_persisted_order() -> Option<PersistedOrder> {
+    let path = crate::modules::account::get_data_dir().ok()?.join(ENDPOINT_ORDER_FILE);
+    let content = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn save_persisted_order(order: &PersistedOrder) {
+    let result = crate::modules::account::get_data_dir().and_then(|dir| {
+        let content = serde_json::to_string_pretty(order).map_err(|e| e.to_string())?;
+        std::fs::write(dir.join(ENDPOINT_ORDER_FILE), content).map_err(|e| e.to_string())
+    });
+    if let Err(e) = result {
+        tracing::warn!("保存上游端点顺序失败: {}", e);
+    }
+}
+
+/// 管理接口设置的端点覆盖，仅在运行时生效（重启后清除）
+#[derive(Debug, Clone, Default, PartialEq)]
+struct EndpointOverrides {
+    /// 固定为首选的端点，优先于学习到的顺序
+    pinned: Option<String>,
+    /// 完全不再尝试的端点
+    disabled: Vec<String>,
+}
+
+impl EndpointOverrides {
+    /// 在学习到的顺序上应用覆盖：移除禁用端点，固定端点排到最前
+    fn apply(&self, order: &[String]) -> Vec<String> {
+        let mut effective: Vec<String> = order.iter().filter(|e| !self.disabled.contains(e)).cloned().collect();
+        if let Some(pinned) = &self.pinned {
+            if let Some(pos) = effective.iter().position(|e| e == pinned) {
+                let endpoint = effective.remove(pos);
+                effective.insert(0, endpoint);
+            }
+        }
+        effective
+    }
+
+    /// 去掉已不在配置中的端点；全部被禁用时清空禁用列表
+    fn retain_configured(&mut self, configured: &[String]) {
+        self.pinned = self.pinned.take().filter(|e| configured.contains(e));
+        self.disabled.retain(|e| configured.contains(e));
+        if self.disabled.len() >= configured.len() {
+            self.disabled.clear();
+        }
+    }
+}
+
+/// 单个端点的请求统计（进程启动以来）
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EndpointStats {
+    /// 收到非端点故障的响应（含 429 等业务错误）
+    pub successes: u64,
+    /// 连接失败 / 超时 / 404 / 408 / 5xx
+    pub errors: u64,
+    pub last_success_at: Option<i64>,
+    pub last_error_at: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+/// 单个端点的当前状态
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointStatus {
+    pub url: String,
+    /// 熔断中（冷却期内会被跳过）
+    pub circuit_open: bool,
+    pub stats: EndpointStats,
+}
+
+/// 端点配置与当前生效的优先级
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointsStatus {
+    /// 配置中的端点顺序
+    pub configured: Vec<String>,
+    /// 学习到的顺序（fallback 成功后会被提升）
+    pub learned: Vec<String>,
+    /// 固定为首选的端点
+    pub pinned: Option<String>,
+    /// 已禁用的端点
+    pub disabled: Vec<String>,
+    /// 当前实际尝试顺序（学习到的顺序应用固定 / 禁用之后）
+    pub active: Vec<EndpointStatus>,
+    /// 所有配置端点的请求统计
+    pub stats: BTreeMap<String, EndpointStats>,
+}
+
+/// HTTP client built from the upstream proxy settings and connect timeout (rebuilt on hot reload)
+#[derive(Clone)]
+struct HttpClient {
+    client: Client,
     user_agent: String,
-    // Dynamic endpoint priority list - successful fallback gets promoted
-    endpoints: Arc<RwLock<Vec<String>>>,
+    proxy_config: Option<UpstreamProxyConfig>,
 }
 
-impl UpstreamClient {
-    pub fn new(proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>) -> Self {
+impl HttpClient {
+    fn build(proxy_config: Option<&UpstreamProxyConfig>, connect_timeout: Option<Duration>) -> Self {
         let user_agent = proxy_config
-            .as_ref()
             .map(|c| c.user_agent.clone())
             .filter(|ua| !ua.is_empty())
             .unwrap_or_else(|| "antigravity/1.13.3 darwin/arm64".to_string());
 
+        // 总耗时按请求设置（流式请求不限总时长），见 UpstreamClient::send
         let mut builder = Client::builder()
             // Connection settings (optimize connection reuse, reduce overhead)
-            .connect_timeout(Duration::from_secs(20))
             .pool_max_idle_per_host(16)                  // Max 16 idle connections per host
             .pool_idle_timeout(Duration::from_secs(90))  // Keep idle connections for 90s
             .tcp_keepalive(Duration::from_secs(60))      // TCP keepalive probe at 60s
-            .timeout(Duration::from_secs(600))
             .user_agent(&user_agent);
+        if let Some(timeout) = connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
 
         if let Some(config) = proxy_config {
             if config.enabled && !config.url.is_empty() {
@@ -49,26 +202,333 @@ impl UpstreamClient {
             builder = builder.no_proxy();
         }
 
-        let http_client = builder.build().expect("Failed to create HTTP client");
+        let client = builder.build().expect("Failed to create HTTP client");
+        Self { client, user_agent, proxy_config: proxy_config.cloned() }
+    }
+
+    /// v1internal 请求头；`user_agent` 为客户端 profile 指定的 User-Agent
+    fn headers(&self, access_token: &str, user_agent: Option<&str>) -> Result<header::HeaderMap, String> {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+        headers.insert(
+            header::AUTHORIZATION,
+            header::HeaderValue::from_str(&format!("Bearer {}", access_token))
+                .map_err(|e| e.to_string())?,
+        );
+        // 本构建的 reqwest 不带解压功能，要求上游返回未压缩的响应体，日志与用量提取才能按 JSON / SSE 解析
+        headers.insert(header::ACCEPT_ENCODING, header::HeaderValue::from_static("identity"));
+        headers.insert(
+            header::USER_AGENT,
+            header::HeaderValue::from_str(user_agent.unwrap_or(&self.user_agent))
+                .unwrap_or_else(|_| header::HeaderValue::from_static("antigravity/1.11.9 windows/amd64")),
+        );
+        // 关联上游请求与本地日志
+        let request_id = crate::proxy::middleware::request_id::current_request_id();
+        if let Some(value) = request_id.and_then(|id| header::HeaderValue::from_str(&id).ok()) {
+            headers.insert(crate::proxy::middleware::request_id::REQUEST_ID_HEADER, value);
+        }
+        Ok(headers)
+    }
+}
+
+/// 对冲发送的结果
+struct HedgeOutcome {
+    /// 采用的结果及其对应的端点下标
+    result: Result<Response, String>,
+    idx: usize,
+    /// 已完成但未被采用的失败结果（端点下标），同样要计入熔断与端点统计
+    failed: Option<(usize, Result<Response, String>)>,
+}
+
+/// 对冲发送：主请求 `after` 内未完成且全局预算允许时，向 `hedge_idx` 端点再发一次，采用先成功的结果
+///
+/// 两者都失败时以主请求结果为准，继续原有的 fallback 流程
+async fn hedged<F, Fut>(budget: &HedgeBudget, attempt: F, idx: usize, hedge_idx: usize, after: Duration) -> HedgeOutcome
+where
+    F: Fn(usize) -> Fut,
+    Fut: std::future::Future<Output = Result<Response, String>>,
+{
+    let primary = attempt(idx);
+    tokio::pin!(primary);
+    tokio::select! {
+        result = &mut primary => return HedgeOutcome { result, idx, failed: None },
+        _ = tokio::time::sleep(after) => {}
+    }
+    if !budget.try_withdraw() {
+        return HedgeOutcome { result: primary.await, idx, failed: None };
+    }
+
+    tracing::debug!("No upstream response after {}ms, sending hedged request to endpoint #{}", after.as_millis(), hedge_idx);
+    let hedge = attempt(hedge_idx);
+    tokio::pin!(hedge);
+    let succeeded = |result: &Result<Response, String>| result.as_ref().is_ok_and(|r| r.status().is_success());
+    let (first, hedge_first) = tokio::select! {
+        result = &mut primary => (result, false),
+        result = &mut hedge => (result, true),
+    };
+    if succeeded(&first) {
+        crate::proxy::metrics::METRICS.record_upstream_hedge(hedge_first);
+        let idx = if hedge_first { hedge_idx } else { idx };
+        return HedgeOutcome { result: first, idx, failed: None };
+    }
+    // 先完成的一方失败了，等待另一方
+    if hedge_first {
+        let result = primary.await;
+        crate::proxy::metrics::METRICS.record_upstream_hedge(false);
+        HedgeOutcome { result, idx, failed: Some((hedge_idx, first)) }
+    } else {
+        let result = hedge.await;
+        let won = succeeded(&result);
+        crate::proxy::metrics::METRICS.record_upstream_hedge(won);
+        if won {
+            HedgeOutcome { result, idx: hedge_idx, failed: Some((idx, first)) }
+        } else {
+            HedgeOutcome { result: first, idx, failed: Some((hedge_idx, result)) }
+        }
+    }
+}
+
+/// 不发送请求时预览的上游目标（/api/debug/transform）
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamPreview {
+    pub url: String,
+    /// 请求头，Authorization 中的 token 已打码
+    pub headers: std::collections::BTreeMap<String, String>,
+}
+
+pub struct UpstreamClient {
+    http: RwLock<HttpClient>,
+    // Dynamic endpoint priority list - successful fallback gets promoted
+    endpoints: Arc<RwLock<Vec<String>>>,
+    // Endpoint list as configured (the promotion order is only restored while this is unchanged)
+    configured_endpoints: RwLock<Vec<String>>,
+    // Runtime pin / disable set through the admin API (applied on top of the learned order)
+    overrides: RwLock<EndpointOverrides>,
+    // Per-endpoint success / error counters
+    stats: Mutex<HashMap<String, EndpointStats>>,
+    // Per-endpoint circuit breaker - skips endpoints that keep failing
+    breaker: CircuitBreaker,
+    // Retry policy shared by protocol handlers (attempts / total backoff)
+    retry_config: RwLock<RetryConfig>,
+    // Connect / total / streaming timeouts
+    timeouts: RwLock<UpstreamTimeoutConfig>,
+    // Global budget for hedged (duplicate) requests
+    hedge_budget: HedgeBudget,
+    // Last time any endpoint answered / failed at the network level (Unix secs, 0 = never)
+    last_reached_at: AtomicI64,
+    last_unreachable_at: AtomicI64,
+}
+
+/// 上游连通性（GET /readyz 使用）
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Reachability {
+    /// 最近一次收到上游 HTTP 响应的时间 (Unix 秒)
+    pub last_reached_at: Option<i64>,
+    /// 最近一次连接上游失败的时间 (Unix 秒)
+    pub last_failure_at: Option<i64>,
+}
+
+impl Reachability {
+    /// 最近一次尝试能连上上游即视为可达；尚无请求时不判定为不可达
+    pub fn is_reachable(&self) -> bool {
+        match (self.last_reached_at, self.last_failure_at) {
+            (_, None) => true,
+            (None, Some(_)) => false,
+            (Some(reached), Some(failed)) => reached >= failed,
+        }
+    }
+}
+
+impl UpstreamClient {
+    pub fn new(proxy_config: Option<UpstreamProxyConfig>) -> Self {
+        let timeouts = UpstreamTimeoutConfig::default();
+        let http = RwLock::new(HttpClient::build(proxy_config.as_ref(), timeouts.connect()));
 
         // Initialize with default endpoint priority
         // [FIX] daily 端点优先，避免 429 限流
-        let endpoints = Arc::new(RwLock::new(vec![
-            V1_INTERNAL_BASE_URL_DAILY.to_string(),
-            V1_INTERNAL_BASE_URL_PROD.to_string(),
-        ]));
+        let endpoints = Arc::new(RwLock::new(default_endpoints()));
 
-        Self { http_client, user_agent, endpoints }
+        Self {
+            http,
+            endpoints,
+            configured_endpoints: RwLock::new(default_endpoints()),
+            overrides: RwLock::new(EndpointOverrides::default()),
+            stats: Mutex::new(HashMap::new()),
+            breaker: CircuitBreaker::default(),
+            retry_config: RwLock::new(RetryConfig::default()),
+            timeouts: RwLock::new(timeouts),
+            hedge_budget: HedgeBudget::default(),
+            last_reached_at: AtomicI64::new(0),
+            last_unreachable_at: AtomicI64::new(0),
+        }
     }
 
-    /// Promote a successful fallback endpoint to primary position
-    async fn promote_endpoint(&self, successful_idx: usize) {
-        if successful_idx == 0 {
-            return; // Already primary
+    /// 热更新上游代理 / User-Agent（重建 HTTP client，进行中的请求不受影响）
+    pub async fn update_proxy_config(&self, config: &UpstreamProxyConfig) {
+        let connect = self.timeouts.read().await.connect();
+        *self.http.write().await = HttpClient::build(Some(config), connect);
+    }
+
+    /// 设置重试策略
+    pub fn with_retry_config(mut self, config: RetryConfig) -> Self {
+        self.retry_config = RwLock::new(config);
+        self
+    }
+
+    /// 设置超时
+    pub fn with_timeouts(mut self, config: UpstreamTimeoutConfig) -> Self {
+        self.breaker.configure(config.circuit_failure_threshold, config.circuit_cooldown());
+        let proxy_config = self.http.get_mut().proxy_config.clone();
+        self.http = RwLock::new(HttpClient::build(proxy_config.as_ref(), config.connect()));
+        self.timeouts = RwLock::new(config);
+        self
+    }
+
+    /// 热更新超时与熔断参数（连接超时变化时重建 HTTP client）
+    pub async fn update_timeouts(&self, config: UpstreamTimeoutConfig) {
+        self.breaker.configure(config.circuit_failure_threshold, config.circuit_cooldown());
+        let mut timeouts = self.timeouts.write().await;
+        if timeouts.connect_seconds != config.connect_seconds {
+            let mut http = self.http.write().await;
+            *http = HttpClient::build(http.proxy_config.as_ref(), config.connect());
         }
+        *timeouts = config;
+    }
 
+    /// 设置端点列表，并恢复上次运行学习到的优先级
+    pub fn with_endpoints(mut self, endpoints: &[String]) -> Self {
+        let configured = match normalize_endpoints(endpoints) {
+            Ok(configured) => configured,
+            Err(e) => {
+                tracing::warn!("upstream_endpoints 配置无效，使用默认端点: {}", e);
+                default_endpoints()
+            }
+        };
+        let order = restore_order(&configured, load_persisted_order());
+        if order != configured {
+            tracing::info!("Restored upstream endpoint order: {:?}", order);
+        }
+        self.endpoints = Arc::new(RwLock::new(order));
+        self.configured_endpoints = RwLock::new(configured);
+        self
+    }
+
+    /// 热更新端点列表
+    ///
+    /// 列表未变化时保留当前优先级；`reset_order` 为 true 时强制按配置顺序重新开始
+    pub async fn update_endpoints(&self, endpoints: &[String], reset_order: bool) -> Result<(), String> {
+        let configured = normalize_endpoints(endpoints)?;
+        let mut current = self.configured_endpoints.write().await;
+        if *current == configured && !reset_order {
+            return Ok(());
+        }
+        let mut order = self.endpoints.write().await;
+        *order = configured.clone();
+        *current = configured.clone();
+        self.overrides.write().await.retain_configured(&configured);
+        save_persisted_order(&PersistedOrder {
+            configured: configured.clone(),
+            order: configured,
+        });
+        tracing::info!("Upstream endpoints updated: {:?}", *order);
+        Ok(())
+    }
+
+    /// 当前端点配置与优先级
+    pub async fn endpoints_status(&self) -> EndpointsStatus {
+        let configured = self.configured_endpoints.read().await.clone();
+        let learned = self.endpoints.read().await.clone();
+        let overrides = self.overrides.read().await.clone();
+        let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let stats_for = |url: &String| stats.get(url).cloned().unwrap_or_default();
+        let active = overrides
+            .apply(&learned)
+            .iter()
+            .map(|url| EndpointStatus {
+                url: url.clone(),
+                circuit_open: self.breaker.is_open(url),
+                stats: stats_for(url),
+            })
+            .collect();
+        let stats = configured.iter().map(|url| (url.clone(), stats_for(url))).collect();
+        EndpointsStatus {
+            configured,
+            learned,
+            pinned: overrides.pinned,
+            disabled: overrides.disabled,
+            active,
+            stats,
+        }
+    }
+
+    /// 固定首选端点 / 禁用端点（替换当前设置，仅运行时生效）
+    ///
+    /// 端点必须在配置列表中，且至少保留一个可用端点
+    pub async fn set_endpoint_overrides(&self, pinned: Option<&str>, disabled: &[String]) -> Result<(), String> {
+        let configured = self.configured_endpoints.read().await.clone();
+        let resolve = |raw: &str| -> Result<String, String> {
+            let url = normalize_endpoints(&[raw.to_string()])?.remove(0);
+            if configured.contains(&url) {
+                Ok(url)
+            } else {
+                Err(format!("Endpoint '{}' is not in upstream_endpoints", raw))
+            }
+        };
+
+        let pinned = pinned.map(resolve).transpose()?;
+        let mut disabled_urls: Vec<String> = Vec::with_capacity(disabled.len());
+        for raw in disabled {
+            let url = resolve(raw)?;
+            if !disabled_urls.contains(&url) {
+                disabled_urls.push(url);
+            }
+        }
+        if pinned.as_ref().is_some_and(|p| disabled_urls.contains(p)) {
+            return Err("The pinned endpoint cannot also be disabled".to_string());
+        }
+        if disabled_urls.len() >= configured.len() {
+            return Err("At least one upstream endpoint must stay enabled".to_string());
+        }
+
+        let overrides = EndpointOverrides { pinned, disabled: disabled_urls };
+        tracing::info!(
+            "Upstream endpoint overrides updated | Pinned: {:?} | Disabled: {:?}",
+            overrides.pinned,
+            overrides.disabled
+        );
+        *self.overrides.write().await = overrides;
+        Ok(())
+    }
+
+    /// 当前实际尝试顺序
+    async fn active_endpoints(&self) -> Vec<String> {
+        let learned = self.endpoints.read().await;
+        self.overrides.read().await.apply(&learned)
+    }
+
+    /// 热更新重试策略
+    pub async fn update_retry_config(&self, config: RetryConfig) {
+        *self.retry_config.write().await = config;
+    }
+
+    /// 为一个客户端请求创建重试预算（尝试次数不超过账号池大小）
+    pub async fn retry_budget(&self, pool_size: usize) -> RetryBudget {
+        RetryBudget::new(&*self.retry_config.read().await, pool_size)
+    }
+
+    /// Promote a successful fallback endpoint to primary position
+    ///
+    /// 作用于学习到的顺序；固定的端点仍排在最前
+    async fn promote_endpoint(&self, endpoint: &str) {
+        let configured = self.configured_endpoints.read().await.clone();
         let mut endpoints = self.endpoints.write().await;
-        if successful_idx < endpoints.len() {
+        let Some(successful_idx) = endpoints.iter().position(|e| e == endpoint) else {
+            return;
+        };
+        if successful_idx > 0 {
             let endpoint = endpoints.remove(successful_idx);
             endpoints.insert(0, endpoint.clone());
             tracing::info!(
@@ -76,9 +536,44 @@ impl UpstreamClient {
                 endpoint,
                 successful_idx
             );
+            // 持久化学习到的顺序，重启后继续使用
+            save_persisted_order(&PersistedOrder {
+                configured,
+                order: endpoints.clone(),
+            });
         }
     }
 
+    /// 预览 call_v1_internal 将使用的端点与请求头，不发送请求也不改变熔断状态
+    pub async fn preview_v1_internal(
+        &self,
+        method: &str,
+        access_token: &str,
+        query_string: Option<&str>,
+        client_profile: Option<&str>,
+    ) -> Result<UpstreamPreview, String> {
+        let profile = CLIENT_PROFILES.resolve(client_profile);
+        let user_agent = profile.as_ref().and_then(|p| p.user_agent.as_deref());
+        let headers = self.http.read().await.headers(access_token, user_agent)?;
+        let endpoints = self.active_endpoints().await;
+        // 与 call_v1_internal 一致：跳过熔断中的端点，最后一个端点始终尝试
+        let base_url = endpoints
+            .iter()
+            .enumerate()
+            .find(|(idx, base_url)| idx + 1 == endpoints.len() || !self.breaker.is_open(base_url))
+            .map(|(_, base_url)| base_url.clone())
+            .ok_or("No upstream endpoints configured")?;
+        let headers = headers
+            .iter()
+            .map(|(name, value)| {
+                let value = value.to_str().unwrap_or_default();
+                let value = if name == header::AUTHORIZATION { mask_bearer(value) } else { value.to_string() };
+                (name.to_string(), value)
+            })
+            .collect();
+        Ok(UpstreamPreview { url: Self::build_url(&base_url, method, query_string), headers })
+    }
+
     /// 构建 v1internal URL
     /// 
     /// 构建 API 请求地址
@@ -104,56 +599,246 @@ impl UpstreamClient {
             || status.is_server_error()
     }
 
+    /// 判断响应是否说明端点本身不可用（计入熔断）
+    ///
+    /// 429 是账号级限流，不代表端点故障
+    fn is_endpoint_failure(status: StatusCode) -> bool {
+        status == StatusCode::REQUEST_TIMEOUT
+            || status == StatusCode::NOT_FOUND
+            || status.is_server_error()
+    }
+
+    /// 记录端点响应结果到熔断器
+    fn record_endpoint_result(&self, base_url: &str, status: StatusCode) {
+        self.last_reached_at.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+        if Self::is_endpoint_failure(status) {
+            self.breaker.record_failure(base_url);
+            self.record_stats(base_url, Some(format!("HTTP {}", status.as_u16())));
+        } else {
+            self.breaker.record_success(base_url);
+            self.record_stats(base_url, None);
+        }
+    }
+
+    /// 请求未能到达上游（连接失败 / 超时）
+    fn record_unreachable(&self, base_url: &str, error: &str) {
+        self.last_unreachable_at.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+        self.breaker.record_failure(base_url);
+        self.record_stats(base_url, Some(error.to_string()));
+    }
+
+    fn record_stats(&self, base_url: &str, error: Option<String>) {
+        let now = chrono::Utc::now().timestamp();
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = stats.entry(base_url.to_string()).or_default();
+        match error {
+            Some(error) => {
+                entry.errors += 1;
+                entry.last_error_at = Some(now);
+                entry.last_error = Some(error);
+            }
+            None => {
+                entry.successes += 1;
+                entry.last_success_at = Some(now);
+            }
+        }
+    }
+
+    /// 最近的上游连通性
+    pub fn reachability(&self) -> Reachability {
+        let at = |value: &AtomicI64| Some(value.load(Ordering::Relaxed)).filter(|ts| *ts > 0);
+        Reachability {
+            last_reached_at: at(&self.last_reached_at),
+            last_failure_at: at(&self.last_unreachable_at),
+        }
+    }
+
+    /// 流式方法（`streamGenerateContent` / `alt=sse`）不限总时长，改为限制首字节与空闲间隔
+    fn is_streaming(method: &str, query_string: Option<&str>) -> bool {
+        method.starts_with("stream") || query_string.is_some_and(|qs| qs.contains("alt=sse"))
+    }
+
+    /// 向单个端点发送请求并套用超时；超时错误以 `Upstream timeout` 开头
+    async fn send(
+        http: &HttpClient,
+        base_url: &str,
+        url: &str,
+        headers: header::HeaderMap,
+        body: &Value,
+        timeouts: &UpstreamTimeoutConfig,
+        streaming: bool,
+    ) -> Result<Response, String> {
+        let mut request = http.client.post(url).headers(headers).json(body);
+        if let Some(timeout) = timeouts.request().filter(|_| !streaming) {
+            request = request.timeout(timeout);
+        }
+        let started = std::time::Instant::now();
+        let first_byte = timeouts.stream_first_byte().filter(|_| streaming);
+        let result = match first_byte {
+            Some(limit) => tokio::time::timeout(limit, request.send()).await.map_err(|_| {
+                timeout_error(base_url, format!("no response within {}s", limit.as_secs()))
+            })?,
+            None => request.send().await,
+        };
+        let response = result.map_err(|e| {
+            if e.is_timeout() {
+                timeout_error(base_url, e)
+            } else {
+                format!("HTTP request failed at {}: {}", base_url, e)
+            }
+        })?;
+        if !streaming || !response.status().is_success() {
+            return Ok(response);
+        }
+        let remaining = first_byte.map(|limit| limit.saturating_sub(started.elapsed()));
+        guard_stream(response, remaining, timeouts.stream_idle())
+            .await
+            .map_err(|e| timeout_error(base_url, e))
+    }
+
+    /// 后台发送影子请求：丢弃响应，只记录状态码与延迟
+    fn spawn_shadow(
+        http: HttpClient,
+        base_url: String,
+        url: String,
+        headers: header::HeaderMap,
+        body: Value,
+        timeouts: UpstreamTimeoutConfig,
+        permit: ShadowPermit,
+    ) {
+        tokio::spawn(async move {
+            let _permit = permit;
+            let started = std::time::Instant::now();
+            match Self::send(&http, &base_url, &url, headers, &body, &timeouts, false).await {
+                Ok(resp) => {
+                    SHADOW.record_shadow(&base_url, Some(resp.status().as_u16()), started.elapsed());
+                    let _ = resp.bytes().await;
+                }
+                Err(e) => {
+                    tracing::debug!("[Shadow] {}", e);
+                    SHADOW.record_shadow(&base_url, None, started.elapsed());
+                }
+            }
+        });
+    }
+
     /// 调用 v1internal API（基础方法）
     ///
+    /// 每次尝试一个端点都会在当前 trace 下记录一个 client span
+    ///
     /// 发起基础网络请求，支持多端点自动 Fallback
     /// 当 fallback 端点成功时，会自动将其提升为主端点
+    ///
+    /// `client_profile` 为账号指定的客户端 profile，决定 User-Agent 与注入请求体的客户端字段
     pub async fn call_v1_internal(
         &self,
         method: &str,
         access_token: &str,
-        body: Value,
+        mut body: Value,
         query_string: Option<&str>,
+        client_profile: Option<&str>,
     ) -> Result<Response, String> {
+        let http = self.http.read().await.clone();
+        let timeouts = self.timeouts.read().await.clone();
+        let streaming = Self::is_streaming(method, query_string);
+        // 非流式请求可对冲：每个请求向全局预算存入令牌
+        let hedge_after = {
+            let retry = self.retry_config.read().await;
+            (!streaming && retry.hedge_after_ms > 0).then(|| {
+                self.hedge_budget.deposit(retry.hedge_budget_percent);
+                Duration::from_millis(retry.hedge_after_ms)
+            })
+        };
+
         // 构建 Headers (所有端点复用)
-        let mut headers = header::HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static("application/json"),
-        );
-        headers.insert(
-            header::AUTHORIZATION,
-            header::HeaderValue::from_str(&format!("Bearer {}", access_token))
-                .map_err(|e| e.to_string())?,
-        );
-        headers.insert(
-            header::USER_AGENT,
-            header::HeaderValue::from_str(&self.user_agent)
-                .unwrap_or_else(|_| header::HeaderValue::from_static("antigravity/1.11.9 windows/amd64")),
-        );
+        let profile = CLIENT_PROFILES.resolve(client_profile);
+        let headers = http.headers(access_token, profile.as_ref().and_then(|p| p.user_agent.as_deref()))?;
+        if let Some(profile) = &profile {
+            apply_body_fields(&mut body, profile);
+        }
 
         let mut last_err: Option<String> = None;
 
-        // Read current endpoint priority (dynamic, may have been promoted)
-        let endpoints = self.endpoints.read().await.clone();
+        // Read current endpoint priority (dynamic, may have been promoted / pinned)
+        let endpoints = self.active_endpoints().await;
         let endpoint_count = endpoints.len();
 
+        // 影子流量：抽中的请求同时发往影子端点，不影响熔断与端点统计
+        let shadowed = match SHADOW.sample(method, streaming, &endpoints) {
+            Some((target, permit)) => {
+                let url = Self::build_url(&target, method, query_string);
+                Self::spawn_shadow(http.clone(), target, url, headers.clone(), body.clone(), timeouts.clone(), permit);
+                true
+            }
+            None => false,
+        };
+
+        let (http_ref, headers_ref, body_ref, timeouts_ref, endpoints_ref) = (&http, &headers, &body, &timeouts, &endpoints);
+        let attempt = move |idx: usize| async move {
+            let base_url = &endpoints_ref[idx];
+            let url = Self::build_url(base_url, method, query_string);
+            Self::send(http_ref, base_url, &url, headers_ref.clone(), body_ref, timeouts_ref, streaming).await
+        };
+
+        // 已作为对冲目标发送过的端点，fallback 时不再重复发送
+        let mut tried: Vec<usize> = Vec::new();
+
         // 遍历所有端点，失败时自动切换
         for (idx, base_url) in endpoints.iter().enumerate() {
+            if tried.contains(&idx) {
+                continue;
+            }
             let url = Self::build_url(base_url, method, query_string);
-            let has_next = idx + 1 < endpoint_count;
+            let has_next = (idx + 1..endpoint_count).any(|next| !tried.contains(&next));
 
-            let response = self
-                .http_client
-                .post(&url)
-                .headers(headers.clone())
-                .json(&body)
-                .send()
-                .await;
+            // 熔断中的端点直接跳过（最后一个端点始终尝试，避免全部熔断时无路可走）
+            if has_next && !self.breaker.allow_request(base_url) {
+                tracing::debug!("Skipping endpoint with open circuit: {}", base_url);
+                last_err = Some(format!("Circuit open for {}", base_url));
+                continue;
+            }
+
+            let mut span = upstream_span(method, &url, idx, &body);
+            let started = std::time::Instant::now();
+            // 对冲请求先成功时，后续按对冲端点记录结果并提升优先级
+            let (response, idx) = match hedge_after {
+                Some(after) => {
+                    let hedge_idx = (idx + 1..endpoint_count)
+                        .find(|&next| !tried.contains(&next) && !self.breaker.is_open(&endpoints[next]))
+                        .unwrap_or(idx);
+                    let outcome = hedged(&self.hedge_budget, attempt, idx, hedge_idx, after).await;
+                    if hedge_idx != idx {
+                        tried.push(hedge_idx);
+                    }
+                    if let Some((failed_idx, failed)) = outcome.failed {
+                        let failed_url = &endpoints[failed_idx];
+                        match failed {
+                            Ok(resp) => self.record_endpoint_result(failed_url, resp.status()),
+                            Err(msg) => self.record_unreachable(failed_url, &msg),
+                        }
+                    }
+                    (outcome.result, outcome.idx)
+                }
+                None => (attempt(idx).await, idx),
+            };
+            let base_url = &endpoints[idx];
+            // 对冲目标已尝试过，不再算作可切换的下一个端点
+            let has_next = has_next && (idx + 1..endpoint_count).any(|next| !tried.contains(&next));
 
             match response {
                 Ok(resp) => {
                     let status = resp.status();
+                    if let Some(span) = span.as_mut() {
+                        span.set_attribute("http.response.status_code", status.as_u16());
+                        if !status.is_success() {
+                            span.set_error(format!("HTTP {}", status.as_u16()));
+                        }
+                    }
+                    drop(span);
+                    self.record_endpoint_result(base_url, status);
+                    if shadowed {
+                        SHADOW.record_primary(base_url, Some(status.as_u16()), started.elapsed());
+                    }
                     if status.is_success() {
                         if idx > 0 {
                             tracing::info!(
@@ -164,7 +849,7 @@ impl UpstreamClient {
                                 endpoint_count
                             );
                             // Promote successful fallback to primary position
-                            self.promote_endpoint(idx).await;
+                            self.promote_endpoint(base_url).await;
                         } else {
                             tracing::debug!("✓ Upstream request succeeded | Endpoint: {} | Status: {}", base_url, status);
                         }
@@ -180,14 +865,22 @@ impl UpstreamClient {
                             method
                         );
                         last_err = Some(format!("Upstream {} returned {}", base_url, status));
+                        crate::proxy::metrics::METRICS.record_upstream_fallback(base_url);
                         continue;
                     }
 
                     // 不可重试的错误或已是最后一个端点，直接返回
                     return Ok(resp);
                 }
-                Err(e) => {
-                    let msg = format!("HTTP request failed at {}: {}", base_url, e);
+                Err(msg) => {
+                    if let Some(span) = span.as_mut() {
+                        span.set_error(msg.clone());
+                    }
+                    drop(span);
+                    self.record_unreachable(base_url, &msg);
+                    if shadowed {
+                        SHADOW.record_primary(base_url, None, started.elapsed());
+                    }
                     tracing::debug!("{}", msg);
                     last_err = Some(msg);
 
@@ -195,12 +888,15 @@ impl UpstreamClient {
                     if !has_next {
                         break;
                     }
+                    crate::proxy::metrics::METRICS.record_upstream_fallback(base_url);
                     continue;
                 }
             }
         }
 
-        Err(last_err.unwrap_or_else(|| "All endpoints failed".to_string()))
+        let error = last_err.unwrap_or_else(|| "All endpoints failed".to_string());
+        crate::modules::alerts::notify_endpoints_down(&error);
+        Err(error)
     }
 
     /// 调用 v1internal API（带 429 重试,支持闭包）
@@ -226,44 +922,37 @@ impl UpstreamClient {
     ///
     /// 获取远端模型列表，支持多端点自动 Fallback
     /// 当 fallback 端点成功时，会自动将其提升为主端点
-    pub async fn fetch_available_models(&self, access_token: &str) -> Result<Value, String> {
-        let mut headers = header::HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static("application/json"),
-        );
-        headers.insert(
-            header::AUTHORIZATION,
-            header::HeaderValue::from_str(&format!("Bearer {}", access_token))
-                .map_err(|e| e.to_string())?,
-        );
-        headers.insert(
-            header::USER_AGENT,
-            header::HeaderValue::from_str(&self.user_agent)
-                .unwrap_or_else(|_| header::HeaderValue::from_static("antigravity/1.11.9 windows/amd64")),
-        );
+    ///
+    /// 请求体为空对象，客户端 profile 只替换 User-Agent
+    pub async fn fetch_available_models(&self, access_token: &str, client_profile: Option<&str>) -> Result<Value, String> {
+        let http = self.http.read().await.clone();
+        let timeouts = self.timeouts.read().await.clone();
+
+        let profile = CLIENT_PROFILES.resolve(client_profile);
+        let headers = http.headers(access_token, profile.as_ref().and_then(|p| p.user_agent.as_deref()))?;
 
         let mut last_err: Option<String> = None;
 
-        // Read current endpoint priority (dynamic, may have been promoted)
-        let endpoints = self.endpoints.read().await.clone();
+        // Read current endpoint priority (dynamic, may have been promoted / pinned)
+        let endpoints = self.active_endpoints().await;
         let endpoint_count = endpoints.len();
 
         // 遍历所有端点，失败时自动切换
         for (idx, base_url) in endpoints.iter().enumerate() {
             let url = Self::build_url(base_url, "fetchAvailableModels", None);
 
-            let response = self
-                .http_client
-                .post(&url)
-                .headers(headers.clone())
-                .json(&serde_json::json!({}))
-                .send()
-                .await;
+            if idx + 1 < endpoint_count && !self.breaker.allow_request(base_url) {
+                tracing::debug!("Skipping endpoint with open circuit: {}", base_url);
+                last_err = Some(format!("Circuit open for {}", base_url));
+                continue;
+            }
+
+            let response = Self::send(&http, base_url, &url, headers.clone(), &serde_json::json!({}), &timeouts, false).await;
 
             match response {
                 Ok(resp) => {
                     let status = resp.status();
+                    self.record_endpoint_result(base_url, status);
                     if status.is_success() {
                         if idx > 0 {
                             tracing::info!(
@@ -272,7 +961,7 @@ impl UpstreamClient {
                                 status
                             );
                             // Promote successful fallback to primary position
-                            self.promote_endpoint(idx).await;
+                            self.promote_endpoint(base_url).await;
                         } else {
                             tracing::debug!("✓ fetchAvailableModels succeeded | Endpoint: {}", base_url);
                         }
@@ -292,14 +981,15 @@ impl UpstreamClient {
                             base_url
                         );
                         last_err = Some(format!("Upstream error: {}", status));
+                        crate::proxy::metrics::METRICS.record_upstream_fallback(base_url);
                         continue;
                     }
 
                     // 不可重试的错误或已是最后一个端点
                     return Err(format!("Upstream error: {}", status));
                 }
-                Err(e) => {
-                    let msg = format!("Request failed at {}: {}", base_url, e);
+                Err(msg) => {
+                    self.record_unreachable(base_url, &msg);
                     tracing::debug!("{}", msg);
                     last_err = Some(msg);
 
@@ -307,6 +997,7 @@ impl UpstreamClient {
                     if idx + 1 >= endpoint_count {
                         break;
                     }
+                    crate::proxy::metrics::METRICS.record_upstream_fallback(base_url);
                     continue;
                 }
             }
@@ -316,6 +1007,34 @@ impl UpstreamClient {
     }
 }
 
+/// `Bearer ya29.abc...` -> `Bearer ya29.a…wxyz`，只保留首尾少量字符
+fn mask_bearer(value: &str) -> String {
+    let token = value.strip_prefix("Bearer ").unwrap_or(value);
+    let masked = if token.chars().count() > 12 {
+        let head: String = token.chars().take(6).collect();
+        let tail: String = token.chars().skip(token.chars().count() - 4).collect();
+        format!("{}…{}", head, tail)
+    } else {
+        "***".to_string()
+    };
+    format!("Bearer {}", masked)
+}
+
+/// 上游调用的 client span（span 在收到响应头时结束）
+fn upstream_span(method: &str, url: &str, attempt: usize, body: &Value) -> Option<crate::proxy::otel::Span> {
+    let span = crate::proxy::otel::TRACER.start_child_span(
+        format!("upstream {}", method),
+        crate::proxy::otel::SpanKind::Client,
+    )?;
+    span.set_attribute("http.request.method", "POST");
+    span.set_attribute("url.full", url);
+    span.set_attribute("antiproxy.upstream.attempt", attempt as i64 + 1);
+    if let Some(model) = body.get("model").and_then(|m| m.as_str()) {
+        span.set_attribute("gen_ai.request.model", model);
+    }
+    Some(span)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -337,4 +1056,126 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_normalize_endpoints() {
+        let endpoints = normalize_endpoints(&[
+            " https://mirror.example.com/v1internal/ ".to_string(),
+            "".to_string(),
+            "https://mirror.example.com/v1internal".to_string(),
+            "http://127.0.0.1:9000/v1internal".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(
+            endpoints,
+            vec!["https://mirror.example.com/v1internal", "http://127.0.0.1:9000/v1internal"]
+        );
+
+        assert!(normalize_endpoints(&[]).is_err());
+        assert!(normalize_endpoints(&["ftp://example.com".to_string()]).is_err());
+        assert!(normalize_endpoints(&["not a url".to_string()]).is_err());
+        assert!(normalize_endpoints(&["https://example.com/v1internal?x=1".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_restore_order() {
+        let configured = default_endpoints();
+        let promoted: Vec<String> = configured.iter().rev().cloned().collect();
+        let persisted = PersistedOrder {
+            configured: configured.clone(),
+            order: promoted.clone(),
+        };
+        assert_eq!(restore_order(&configured, Some(persisted.clone())), promoted);
+
+        // 配置变化后以配置顺序为准
+        let changed = vec![configured[1].clone(), "https://mirror.example.com/v1internal".to_string()];
+        assert_eq!(restore_order(&changed, Some(persisted)), changed);
+        assert_eq!(restore_order(&configured, None), configured);
+    }
+    #[test]
+    fn test_endpoint_overrides() {
+        let endpoints = vec![
+            "https://a.example.com/v1internal".to_string(),
+            "https://b.example.com/v1internal".to_string(),
+            "https://c.example.com/v1internal".to_string(),
+        ];
+        let mut overrides = EndpointOverrides {
+            pinned: Some(endpoints[2].clone()),
+            disabled: vec![endpoints[0].clone()],
+        };
+        assert_eq!(overrides.apply(&endpoints), vec![endpoints[2].clone(), endpoints[1].clone()]);
+        assert_eq!(EndpointOverrides::default().apply(&endpoints), endpoints);
+
+        // 配置变化后去掉不存在的端点
+        overrides.retain_configured(&endpoints[..2]);
+        assert_eq!(overrides.pinned, None);
+        assert_eq!(overrides.disabled, vec![endpoints[0].clone()]);
+        overrides.retain_configured(&endpoints[..1]);
+        assert!(overrides.disabled.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_set_endpoint_overrides() {
+        let client = UpstreamClient::new(None);
+        let [daily, prod] = [default_endpoints()[0].clone(), default_endpoints()[1].clone()];
+        client.set_endpoint_overrides(Some(&format!("{}/", prod)), &[]).await.unwrap();
+        assert_eq!(client.active_endpoints().await, vec![prod.clone(), daily.clone()]);
+
+        client.set_endpoint_overrides(None, std::slice::from_ref(&prod)).await.unwrap();
+        let status = client.endpoints_status().await;
+        assert_eq!(status.active.len(), 1);
+        assert_eq!(status.learned, vec![daily.clone(), prod.clone()]);
+
+        assert!(client.set_endpoint_overrides(Some("https://other.example.com"), &[]).await.is_err());
+        assert!(client.set_endpoint_overrides(Some(&daily), std::slice::from_ref(&daily)).await.is_err());
+        assert!(client.set_endpoint_overrides(None, &[daily, prod]).await.is_err());
+    }
+
+    #[test]
+    fn test_mask_bearer() {
+        assert_eq!(mask_bearer("Bearer ya29.a0AfB_secret_token_1234"), "Bearer ya29.a…1234");
+        assert_eq!(mask_bearer("Bearer short"), "Bearer ***");
+    }
+
+    #[tokio::test]
+    async fn test_hedged_takes_first_success() {
+        // 端点下标 -> (延迟毫秒, 状态码)
+        let attempt = |plan: &'static [(u64, u16)]| {
+            move |idx: usize| async move {
+                let (delay, status) = plan[idx];
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                let response = axum::http::Response::builder().status(status).body(String::new()).unwrap();
+                Ok::<_, String>(Response::from(response))
+            }
+        };
+        let status = |outcome: &HedgeOutcome| outcome.result.as_ref().unwrap().status();
+        let failed = |outcome: &HedgeOutcome| {
+            outcome.failed.as_ref().map(|(idx, result)| (*idx, result.as_ref().unwrap().status().as_u16()))
+        };
+        let after = Duration::from_millis(20);
+        let budget = HedgeBudget::default();
+
+        // 主请求及时完成，不发对冲
+        let outcome = hedged(&budget, attempt(&[(0, 200), (0, 200)]), 0, 1, after).await;
+        assert_eq!((status(&outcome), outcome.idx, failed(&outcome)), (StatusCode::OK, 0, None));
+
+        // 主请求慢，对冲先成功
+        let outcome = hedged(&budget, attempt(&[(300, 200), (0, 200)]), 0, 1, after).await;
+        assert_eq!((status(&outcome), outcome.idx, failed(&outcome)), (StatusCode::OK, 1, None));
+
+        // 对冲先失败，等待主请求；对冲的失败同样返回
+        let outcome = hedged(&budget, attempt(&[(60, 200), (0, 503)]), 0, 1, after).await;
+        assert_eq!((status(&outcome), outcome.idx, failed(&outcome)), (StatusCode::OK, 0, Some((1, 503))));
+
+        // 两者都失败：采用主请求结果，对冲结果作为失败返回
+        let outcome = hedged(&budget, attempt(&[(60, 502), (0, 503)]), 0, 1, after).await;
+        assert_eq!(
+            (status(&outcome), outcome.idx, failed(&outcome)),
+            (StatusCode::BAD_GATEWAY, 0, Some((1, 503)))
+        );
+
+        // 预算耗尽后只等待主请求
+        while budget.try_withdraw() {}
+        let outcome = hedged(&budget, attempt(&[(60, 200), (0, 200)]), 0, 1, after).await;
+        assert_eq!((status(&outcome), outcome.idx, failed(&outcome)), (StatusCode::OK, 0, None));
+    }
 }
@@ -0,0 +1,137 @@
+// 上游错误透传
+// 上游返回 4xx/5xx 时把其中的错误信息按客户端协议的格式返回，
+// 并去掉端点 URL、项目 ID、账号邮箱等内部信息；完整错误体仍只记录在服务端日志
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{json, Value};
+
+/// 返回给客户端的错误信息最大长度（字符）
+const MAX_MESSAGE_CHARS: usize = 2000;
+
+const REDACTED: &str = "[redacted]";
+
+static URL_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"https?://[^\s"'<>]+"#).expect("valid regex"));
+/// `projects/<id>`、`project_number:<n>`、`project <id>`；项目 ID 需带连字符或为纯数字，避免误伤普通单词
+static PROJECT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)\b(projects/|project_number:|project[ :]+)(['"]?)([a-z0-9]+(?:-[a-z0-9]+)+|\d+)"#).expect("valid regex")
+});
+static EMAIL_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"[\w.+-]+@[\w-]+(?:\.[\w-]+)+").expect("valid regex"));
+
+/// 去掉错误信息中的端点 URL、项目 ID 与邮箱
+pub fn sanitize(message: &str) -> String {
+    let message = URL_RE.replace_all(message, REDACTED);
+    let message = PROJECT_RE.replace_all(&message, format!("${{1}}${{2}}{}", REDACTED).as_str());
+    let message = EMAIL_RE.replace_all(&message, REDACTED);
+    let message = message.trim();
+    match message.char_indices().nth(MAX_MESSAGE_CHARS) {
+        Some((idx, _)) => format!("{}...", &message[..idx]),
+        None => message.to_string(),
+    }
+}
+
+fn parse_error(body: &str) -> Option<Value> {
+    let value: Value = serde_json::from_str(body).ok()?;
+    // 流式接口的错误体可能是数组
+    let value = match value {
+        Value::Array(items) => items.into_iter().next()?,
+        other => other,
+    };
+    value.get("error").cloned()
+}
+
+/// 上游错误体中可展示给客户端的信息（Google 错误格式取 error.message，否则取原文），已脱敏
+pub fn upstream_message(body: &str) -> String {
+    let message = parse_error(body)
+        .and_then(|error| error.get("message").and_then(|m| m.as_str()).map(str::to_string))
+        .unwrap_or_else(|| body.to_string());
+    let message = sanitize(&message);
+    if message.is_empty() {
+        "Upstream request failed".to_string()
+    } else {
+        message
+    }
+}
+
+/// Google 错误格式中的状态（如 `INVALID_ARGUMENT`）
+fn upstream_status(body: &str) -> Option<String> {
+    parse_error(body)?.get("status")?.as_str().map(str::to_string)
+}
+
+/// 记录在重试错误 / 最终错误中的上游错误描述
+pub fn describe(status: u16, body: &str) -> String {
+    format!("HTTP {}: {}", status, upstream_message(body))
+}
+
+/// Gemini 原生接口的错误响应：`{"error": {"code", "message", "status"}}`
+pub fn gemini_error(status: StatusCode, body: &str) -> Response {
+    let google_status = upstream_status(body).unwrap_or_else(|| match status {
+        StatusCode::BAD_REQUEST => "INVALID_ARGUMENT".to_string(),
+        StatusCode::UNAUTHORIZED => "UNAUTHENTICATED".to_string(),
+        StatusCode::FORBIDDEN => "PERMISSION_DENIED".to_string(),
+        StatusCode::NOT_FOUND => "NOT_FOUND".to_string(),
+        StatusCode::TOO_MANY_REQUESTS => "RESOURCE_EXHAUSTED".to_string(),
+        StatusCode::SERVICE_UNAVAILABLE => "UNAVAILABLE".to_string(),
+        _ => "INTERNAL".to_string(),
+    });
+    (
+        status,
+        Json(json!({
+            "error": {
+                "code": status.as_u16(),
+                "message": upstream_message(body),
+                "status": google_status
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// Anthropic 接口的错误响应：`{"type": "error", "error": {"type", "message"}}`
+pub fn claude_error(status: StatusCode, body: &str) -> Response {
+    let error_type = match status.as_u16() {
+        400 | 422 => "invalid_request_error",
+        401 => "authentication_error",
+        403 => "permission_error",
+        404 => "not_found_error",
+        413 => "request_too_large",
+        429 => "rate_limit_error",
+        503 | 529 => "overloaded_error",
+        _ => "api_error",
+    };
+    (
+        status,
+        Json(json!({
+            "type": "error",
+            "error": {
+                "type": error_type,
+                "message": upstream_message(body)
+            }
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_upstream_errors() {
+        let body = r#"{"error": {"code": 403, "status": "PERMISSION_DENIED",
+            "message": "Permission denied on resource project bamboo-precept-lgxtn (user someone@gmail.com) at https://daily-cloudcode-pa.googleapis.com/v1internal:generateContent"}}"#;
+        let message = upstream_message(body);
+        assert_eq!(message, "Permission denied on resource project [redacted] (user [redacted]) at [redacted]");
+        assert_eq!(upstream_status(body).as_deref(), Some("PERMISSION_DENIED"));
+
+        assert_eq!(sanitize("consumer 'projects/123456' has no quota"), "consumer 'projects/[redacted]' has no quota");
+        assert_eq!(sanitize("The project has been suspended"), "The project has been suspended");
+        assert_eq!(upstream_message("plain text failure"), "plain text failure");
+        assert_eq!(upstream_message(r#"[{"error": {"message": "bad"}}]"#), "bad");
+        assert_eq!(describe(429, ""), "HTTP 429: Upstream request failed");
+    }
+}
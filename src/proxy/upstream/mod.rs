@@ -2,5 +2,10 @@
 // 对应上游通讯接口
 
 pub mod client;
+pub mod circuit_breaker;
+pub mod errors;
 pub mod retry;
+pub mod shadow;
+pub mod timeout;
+pub mod profiles;
 pub mod models;
@@ -0,0 +1,129 @@
+// 上游客户端 profile
+// 每个 profile 包含 User-Agent 与合并到 v1internal 请求体顶层的客户端字段；
+// 账号指定的 profile 优先，其次为默认 profile，都没有时沿用 upstream_proxy.user_agent 与原始请求体
+use once_cell::sync::Lazy;
+use reqwest::header::HeaderValue;
+use serde_json::Value;
+use std::sync::RwLock;
+
+use crate::proxy::config::{ClientProfile, ClientProfilesConfig};
+
+/// 全局 profile 配置（启动 / 热重载 / 管理接口更新时写入）
+pub static CLIENT_PROFILES: Lazy<ClientProfiles> = Lazy::new(ClientProfiles::default);
+
+/// 由代理构造、不允许 profile 覆盖的请求体字段
+const PROTECTED_FIELDS: [&str; 3] = ["project", "request", "model"];
+
+/// 校验 profile 配置
+pub fn validate(config: &ClientProfilesConfig) -> Result<(), String> {
+    for (name, profile) in &config.profiles {
+        if name.trim().is_empty() {
+            return Err("Invalid client profile: name must not be empty".to_string());
+        }
+        if let Some(user_agent) = &profile.user_agent {
+            HeaderValue::from_str(user_agent)
+                .map_err(|_| format!("Invalid client profile {}: user_agent is not a valid header value", name))?;
+        }
+        if let Some(field) = PROTECTED_FIELDS.iter().find(|f| profile.body_fields.contains_key(**f)) {
+            return Err(format!("Invalid client profile {}: body field `{}` cannot be overridden", name, field));
+        }
+    }
+    if let Some(default) = config.default.as_ref().filter(|d| !config.profiles.contains_key(*d)) {
+        return Err(format!("Invalid client profiles: default profile `{}` is not defined", default));
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct ClientProfiles {
+    config: RwLock<ClientProfilesConfig>,
+}
+
+impl ClientProfiles {
+    pub fn configure(&self, config: &ClientProfilesConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config.clone();
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.config.read().unwrap_or_else(|e| e.into_inner()).profiles.contains_key(name)
+    }
+
+    /// 账号使用的 profile；账号指定的 profile 已被删除时回退到默认 profile
+    pub fn resolve(&self, account_profile: Option<&str>) -> Option<ClientProfile> {
+        let config = self.config.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(name) = account_profile {
+            match config.profiles.get(name) {
+                Some(profile) => return Some(profile.clone()),
+                None => tracing::warn!("Client profile `{}` is not defined, using the default profile", name),
+            }
+        }
+        config.default.as_ref().and_then(|name| config.profiles.get(name)).cloned()
+    }
+}
+
+/// 把 profile 的客户端字段合并到 v1internal 请求体顶层
+pub fn apply_body_fields(body: &mut Value, profile: &ClientProfile) {
+    let Some(object) = body.as_object_mut() else {
+        return;
+    };
+    for (key, value) in &profile.body_fields {
+        if !PROTECTED_FIELDS.contains(&key.as_str()) {
+            object.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn profile(user_agent: &str, fields: Value) -> ClientProfile {
+        ClientProfile {
+            user_agent: Some(user_agent.to_string()),
+            body_fields: fields.as_object().cloned().unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_and_apply_profiles() {
+        let profiles = ClientProfiles::default();
+        assert!(profiles.resolve(None).is_none());
+
+        let config = ClientProfilesConfig {
+            default: Some("desktop".to_string()),
+            profiles: [
+                ("desktop".to_string(), profile("antigravity/1.13.3 darwin/arm64", json!({}))),
+                ("legacy".to_string(), profile("antigravity/1.11.9 windows/amd64", json!({"userAgent": "vscode"}))),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        assert!(validate(&config).is_ok());
+        profiles.configure(&config);
+        assert_eq!(profiles.resolve(None).unwrap().user_agent.as_deref(), Some("antigravity/1.13.3 darwin/arm64"));
+        assert_eq!(profiles.resolve(Some("missing")).unwrap().user_agent.as_deref(), Some("antigravity/1.13.3 darwin/arm64"));
+
+        let legacy = profiles.resolve(Some("legacy")).unwrap();
+        let mut body = json!({"project": "p", "model": "m", "userAgent": "antigravity", "requestType": "agent"});
+        apply_body_fields(&mut body, &legacy);
+        assert_eq!(body["userAgent"], "vscode");
+        assert_eq!(body["requestType"], "agent");
+    }
+
+    #[test]
+    fn test_validate_profiles() {
+        let mut config = ClientProfilesConfig {
+            default: Some("missing".to_string()),
+            ..Default::default()
+        };
+        assert!(validate(&config).is_err());
+
+        config.default = None;
+        config.profiles.insert("bad".to_string(), profile("ok", json!({"model": "x"})));
+        assert!(validate(&config).unwrap_err().contains("model"));
+
+        config.profiles.insert("bad".to_string(), profile("line\nbreak", json!({})));
+        assert!(validate(&config).is_err());
+    }
+}
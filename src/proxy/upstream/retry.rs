@@ -1,8 +1,21 @@
 // 429 重试策略
-// Duration 解析
+// Duration 解析 / Retry-After 解析 / 带抖动的指数退避与重试预算 / 对冲请求预算
 
 use regex::Regex;
 use once_cell::sync::Lazy;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::proxy::config::RetryConfig;
+
+/// 指数退避基数（毫秒）
+const BACKOFF_BASE_MS: u64 = 1_000;
+/// 单次指数退避上限（毫秒）
+const BACKOFF_MAX_MS: u64 = 8_000;
+/// 抖动比例（±20%），避免多个请求同时重试
+const JITTER_FACTOR: f64 = 0.2;
+/// 对冲预算的令牌桶容量（允许的突发对冲数）
+const HEDGE_BURST: f64 = 10.0;
 
 static DURATION_RE: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"([\d.]+)\s*(ms|s|m|h)").unwrap()
@@ -66,6 +79,132 @@ pub fn parse_retry_delay(error_text: &str) -> Option<u64> {
     None
 }
 
+/// 解析 HTTP Retry-After 头（秒数或 HTTP-date），返回毫秒
+pub fn parse_retry_after_header(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs.saturating_mul(1000));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let ms = (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).num_milliseconds();
+    Some(ms.max(0) as u64)
+}
+
+/// 服务端给出的重试等待提示：优先 Retry-After 头，其次响应体中的 retryDelay
+pub fn retry_hint_ms(retry_after: Option<&str>, error_text: &str) -> Option<u64> {
+    retry_after
+        .and_then(parse_retry_after_header)
+        .or_else(|| parse_retry_delay(error_text))
+}
+
+/// 指数退避：1s, 2s, 4s, 8s...（上限 BACKOFF_MAX_MS）
+pub fn backoff_delay_ms(attempt: usize) -> u64 {
+    let factor = 1u64 << attempt.min(16);
+    BACKOFF_BASE_MS.saturating_mul(factor).min(BACKOFF_MAX_MS)
+}
+
+/// 对延迟施加 ±JITTER_FACTOR 的随机抖动（e.g., 1000ms -> 800-1200ms）
+pub fn apply_jitter(delay_ms: u64) -> u64 {
+    use rand::Rng;
+    let jitter_range = (delay_ms as f64 * JITTER_FACTOR) as i64;
+    let jitter: i64 = rand::thread_rng().gen_range(-jitter_range..=jitter_range);
+    ((delay_ms as i64) + jitter).max(1) as u64
+}
+
+/// 单个客户端请求的重试预算
+///
+/// 限制总尝试次数与累计等待时间；各协议 handler 在每次失败后向其申请退避时间，
+/// 并在下一次尝试时轮换账号
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    max_attempts: usize,
+    max_total_delay_ms: u64,
+    spent_ms: u64,
+}
+
+impl RetryBudget {
+    /// 尝试次数不超过账号池大小（每次尝试都可以换一个账号）
+    pub fn new(config: &RetryConfig, pool_size: usize) -> Self {
+        Self {
+            max_attempts: config.max_attempts.min(pool_size).max(1),
+            max_total_delay_ms: config.max_total_delay_ms,
+            spent_ms: 0,
+        }
+    }
+
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    /// 申请一次退避等待
+    ///
+    /// 超出剩余预算时截断为剩余值；预算已用尽返回 None（调用方应停止重试）
+    pub fn reserve(&mut self, delay_ms: u64) -> Option<Duration> {
+        let remaining = self.max_total_delay_ms.saturating_sub(self.spent_ms);
+        if remaining == 0 && delay_ms > 0 {
+            return None;
+        }
+        let actual = delay_ms.min(remaining);
+        self.spent_ms += actual;
+        Some(Duration::from_millis(actual))
+    }
+
+    /// 计算 429 / 5xx 后的退避等待：优先服务端提示，否则带抖动的指数退避
+    pub fn next_delay(
+        &mut self,
+        attempt: usize,
+        retry_after: Option<&str>,
+        error_text: &str,
+    ) -> Option<Duration> {
+        self.backoff(attempt, retry_hint_ms(retry_after, error_text))
+    }
+
+    /// 同 next_delay，服务端提示已由调用方解析
+    pub fn backoff(&mut self, attempt: usize, hint_ms: Option<u64>) -> Option<Duration> {
+        let delay_ms = match hint_ms {
+            // 服务端提示是下限，只向上抖动
+            Some(hint) => hint.saturating_add(apply_jitter(200)),
+            None => apply_jitter(backoff_delay_ms(attempt)),
+        };
+        self.reserve(delay_ms)
+    }
+}
+
+/// 对冲请求的全局预算（令牌桶）
+///
+/// 每个可对冲的请求存入 `percent / 100` 个令牌，发出一次对冲取出 1 个，
+/// 长期来看对冲请求不超过请求总数的 `percent`%，上游整体变慢时不会把流量翻倍
+pub struct HedgeBudget {
+    tokens: Mutex<f64>,
+}
+
+impl Default for HedgeBudget {
+    fn default() -> Self {
+        Self { tokens: Mutex::new(HEDGE_BURST) }
+    }
+}
+
+impl HedgeBudget {
+    fn tokens(&self) -> std::sync::MutexGuard<'_, f64> {
+        self.tokens.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn deposit(&self, percent: u32) {
+        let mut tokens = self.tokens();
+        *tokens = (*tokens + percent as f64 / 100.0).min(HEDGE_BURST);
+    }
+
+    /// 取出一个令牌；预算不足返回 false（不发对冲请求）
+    pub fn try_withdraw(&self) -> bool {
+        let mut tokens = self.tokens();
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,4 +230,51 @@ mod tests {
 
         assert_eq!(parse_retry_delay(error_json), Some(1204));
     }
+
+    #[test]
+    fn test_parse_retry_after_header() {
+        assert_eq!(parse_retry_after_header("5"), Some(5000));
+        assert_eq!(parse_retry_after_header("Wed, 21 Oct 2015 07:28:00 GMT"), Some(0));
+        assert_eq!(parse_retry_after_header("soon"), None);
+        assert_eq!(retry_hint_ms(Some("2"), "{}"), Some(2000));
+    }
+
+    #[test]
+    fn test_backoff_delay_is_capped() {
+        assert_eq!(backoff_delay_ms(0), 1_000);
+        assert_eq!(backoff_delay_ms(2), 4_000);
+        assert_eq!(backoff_delay_ms(10), BACKOFF_MAX_MS);
+    }
+
+    #[test]
+    fn test_retry_budget_limits_total_delay() {
+        let config = RetryConfig { max_attempts: 5, max_total_delay_ms: 3_000, ..Default::default() };
+        let mut budget = RetryBudget::new(&config, 2);
+        assert_eq!(budget.max_attempts(), 2);
+
+        assert_eq!(budget.reserve(2_000), Some(Duration::from_millis(2_000)));
+        // 超出部分被截断
+        assert_eq!(budget.reserve(2_000), Some(Duration::from_millis(1_000)));
+        assert_eq!(budget.reserve(1), None);
+        // 无需等待的重试（如 401 换号）不受影响
+        assert_eq!(budget.reserve(0), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_hedge_budget_is_bounded() {
+        let budget = HedgeBudget::default();
+        let burst = (0..20).filter(|_| budget.try_withdraw()).count();
+        assert_eq!(burst, HEDGE_BURST as usize);
+
+        // 10% 的预算：每 10 个请求才攒够一次对冲
+        let mut hedges = 0;
+        for _ in 0..100 {
+            budget.deposit(10);
+            hedges += budget.try_withdraw() as usize;
+        }
+        assert!((9..=10).contains(&hedges));
+
+        budget.deposit(0);
+        assert!(!budget.try_withdraw());
+    }
 }
@@ -0,0 +1,207 @@
+// 影子流量
+// 按比例把非流式 generateContent 请求另外发往影子端点，丢弃影子响应，
+// 分别记录主请求与影子请求在各端点上的状态码与延迟，用于切换端点优先级前量化对比
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use crate::proxy::config::ShadowConfig;
+
+pub static SHADOW: Lazy<Shadow> = Lazy::new(Shadow::default);
+
+/// 只复制该方法的请求
+const SHADOW_METHOD: &str = "generateContent";
+
+pub fn validate(config: &ShadowConfig) -> Result<(), String> {
+    if !config.percent.is_finite() || !(0.0..=100.0).contains(&config.percent) {
+        return Err("percent must be between 0 and 100".to_string());
+    }
+    if let Some(endpoint) = &config.endpoint {
+        super::client::normalize_endpoints(std::slice::from_ref(endpoint)).map_err(|e| format!("endpoint: {}", e))?;
+    }
+    if config.max_in_flight == 0 {
+        return Err("max_in_flight must be greater than 0".to_string());
+    }
+    Ok(())
+}
+
+/// 一组请求的结果统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OutcomeStats {
+    pub requests: u64,
+    /// 2xx
+    pub successes: u64,
+    /// 非 2xx 响应或连接失败
+    pub errors: u64,
+    pub avg_latency_ms: u64,
+    pub max_latency_ms: u64,
+    /// 按状态码计数（连接失败不计入）
+    pub statuses: BTreeMap<u16, u64>,
+    #[serde(skip)]
+    total_latency_ms: u64,
+}
+
+impl OutcomeStats {
+    fn record(&mut self, status: Option<u16>, latency: Duration) {
+        let latency_ms = latency.as_millis() as u64;
+        self.requests += 1;
+        match status {
+            Some(status) if (200..300).contains(&status) => self.successes += 1,
+            _ => self.errors += 1,
+        }
+        if let Some(status) = status {
+            *self.statuses.entry(status).or_default() += 1;
+        }
+        self.total_latency_ms += latency_ms;
+        self.avg_latency_ms = self.total_latency_ms / self.requests;
+        self.max_latency_ms = self.max_latency_ms.max(latency_ms);
+    }
+}
+
+/// 单个端点作为主请求 / 影子请求目标时的统计（只统计被抽中的请求）
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EndpointComparison {
+    pub primary: OutcomeStats,
+    pub shadow: OutcomeStats,
+}
+
+/// GET /api/upstream/shadow 的返回
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowStatus {
+    pub percent: f64,
+    pub endpoint: Option<String>,
+    pub in_flight: usize,
+    /// 因在途影子请求达到上限而未复制的次数
+    pub skipped: u64,
+    pub endpoints: BTreeMap<String, EndpointComparison>,
+}
+
+#[derive(Default)]
+struct Stats {
+    skipped: u64,
+    endpoints: BTreeMap<String, EndpointComparison>,
+}
+
+pub struct Shadow {
+    config: RwLock<ShadowConfig>,
+    in_flight: AtomicUsize,
+    stats: Mutex<Stats>,
+}
+
+impl Default for Shadow {
+    fn default() -> Self {
+        Self {
+            config: RwLock::new(ShadowConfig::default()),
+            in_flight: AtomicUsize::new(0),
+            stats: Mutex::new(Stats::default()),
+        }
+    }
+}
+
+/// 在途影子请求名额，drop 时归还
+pub struct ShadowPermit(&'static Shadow);
+
+impl Drop for ShadowPermit {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Shadow {
+    fn stats(&self) -> std::sync::MutexGuard<'_, Stats> {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn configure(&self, config: &ShadowConfig) {
+        let mut config = config.clone();
+        config.endpoint = config
+            .endpoint
+            .and_then(|e| super::client::normalize_endpoints(&[e]).ok())
+            .map(|mut e| e.remove(0));
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    /// 影子目标：配置的端点，或当前顺序中的第二个端点；与主端点相同时不复制
+    fn target(config: &ShadowConfig, endpoints: &[String]) -> Option<String> {
+        let primary = endpoints.first()?;
+        let target = match &config.endpoint {
+            Some(endpoint) => endpoint,
+            None => endpoints.get(1)?,
+        };
+        (target != primary).then(|| target.clone())
+    }
+
+    /// 为本次请求抽样，抽中时返回影子端点与在途名额
+    pub fn sample(&'static self, method: &str, streaming: bool, endpoints: &[String]) -> Option<(String, ShadowPermit)> {
+        if streaming || method != SHADOW_METHOD {
+            return None;
+        }
+        let config = self.config.read().unwrap_or_else(|e| e.into_inner()).clone();
+        if config.percent <= 0.0 || rand::random::<f64>() * 100.0 >= config.percent {
+            return None;
+        }
+        let target = Self::target(&config, endpoints)?;
+        if self.in_flight.fetch_add(1, Ordering::Relaxed) >= config.max_in_flight {
+            self.in_flight.fetch_sub(1, Ordering::Relaxed);
+            self.stats().skipped += 1;
+            return None;
+        }
+        Some((target, ShadowPermit(self)))
+    }
+
+    /// 记录被抽中请求的主请求结果（`status` 为 None 表示连接失败）
+    pub fn record_primary(&self, endpoint: &str, status: Option<u16>, latency: Duration) {
+        self.stats().endpoints.entry(endpoint.to_string()).or_default().primary.record(status, latency);
+    }
+
+    pub fn record_shadow(&self, endpoint: &str, status: Option<u16>, latency: Duration) {
+        self.stats().endpoints.entry(endpoint.to_string()).or_default().shadow.record(status, latency);
+    }
+
+    pub fn status(&self) -> ShadowStatus {
+        let config = self.config.read().unwrap_or_else(|e| e.into_inner()).clone();
+        let stats = self.stats();
+        ShadowStatus {
+            percent: config.percent,
+            endpoint: config.endpoint,
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            skipped: stats.skipped,
+            endpoints: stats.endpoints.clone(),
+        }
+    }
+
+    /// 清空统计，开始新一轮对比
+    pub fn reset(&self) {
+        *self.stats() = Stats::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shadow_target_and_stats() {
+        let endpoints = vec!["https://a.example.com/v1internal".to_string(), "https://b.example.com/v1internal".to_string()];
+        let mut config = ShadowConfig { percent: 100.0, ..Default::default() };
+        assert_eq!(Shadow::target(&config, &endpoints).as_deref(), Some("https://b.example.com/v1internal"));
+        assert_eq!(Shadow::target(&config, &endpoints[..1]), None);
+        config.endpoint = Some(endpoints[0].clone());
+        assert_eq!(Shadow::target(&config, &endpoints), None);
+
+        assert!(validate(&ShadowConfig { percent: 101.0, ..Default::default() }).is_err());
+        assert!(validate(&ShadowConfig { endpoint: Some("ftp://x".to_string()), ..Default::default() }).is_err());
+        assert!(validate(&ShadowConfig::default()).is_ok());
+
+        let mut stats = OutcomeStats::default();
+        stats.record(Some(200), Duration::from_millis(100));
+        stats.record(Some(503), Duration::from_millis(300));
+        stats.record(None, Duration::from_millis(50));
+        assert_eq!((stats.requests, stats.successes, stats.errors), (3, 1, 2));
+        assert_eq!((stats.avg_latency_ms, stats.max_latency_ms), (150, 300));
+        assert_eq!(stats.statuses.get(&503), Some(&1));
+    }
+}
@@ -0,0 +1,129 @@
+// 上游超时
+// 非流式请求限制总耗时；流式请求分别限制首个数据块的等待时间与相邻数据块的间隔，
+// 超时错误带统一前缀，协议处理器据此返回 504
+use axum::{http::StatusCode, response::Response};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::time::Duration;
+
+use super::errors::sanitize;
+use crate::proxy::common::error::error_response;
+
+/// 超时错误的前缀
+pub const TIMEOUT_ERROR_PREFIX: &str = "Upstream timeout";
+
+/// 构造超时错误
+pub fn timeout_error(base_url: &str, detail: impl std::fmt::Display) -> String {
+    format!("{} at {}: {}", TIMEOUT_ERROR_PREFIX, base_url, detail)
+}
+
+pub fn is_timeout_error(error: &str) -> bool {
+    error.starts_with(TIMEOUT_ERROR_PREFIX)
+}
+
+/// 上游超时的 504 响应（去掉错误中的端点地址）
+pub fn timeout_response(message: &str) -> Response {
+    error_response(StatusCode::GATEWAY_TIMEOUT, "upstream_timeout", sanitize(message))
+}
+
+/// 为流式响应套上首字节与空闲间隔限制
+///
+/// 首个数据块在返回前读取：超时时返回错误，调用方仍可返回 504 或切换端点；
+/// 之后两个数据块间隔超过 `idle` 时，流以一个超时错误结束
+pub async fn guard_stream(
+    response: reqwest::Response,
+    first_byte: Option<Duration>,
+    idle: Option<Duration>,
+) -> Result<reqwest::Response, String> {
+    let mut builder = axum::http::Response::builder()
+        .status(response.status())
+        .version(response.version());
+    if let Some(headers) = builder.headers_mut() {
+        *headers = response.headers().clone();
+    }
+
+    let mut stream = Box::pin(response.bytes_stream());
+    let first = match first_byte {
+        Some(limit) => tokio::time::timeout(limit, stream.next())
+            .await
+            .map_err(|_| "no data received before the stream first-byte timeout".to_string())?,
+        None => stream.next().await,
+    };
+    let stream = futures::stream::iter(first).chain(stream);
+    let body = reqwest::Body::wrap_stream(with_idle_timeout(stream, idle));
+    builder.body(body).map(reqwest::Response::from).map_err(|e| e.to_string())
+}
+
+fn with_idle_timeout<S>(stream: S, idle: Option<Duration>) -> impl Stream<Item = Result<Bytes, std::io::Error>> + Send
+where
+    S: Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+{
+    async_stream::stream! {
+        let mut stream = Box::pin(stream);
+        loop {
+            let next = match idle {
+                Some(limit) => match tokio::time::timeout(limit, stream.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        tracing::warn!("Upstream stream idle for {}s, aborting", limit.as_secs());
+                        yield Err(std::io::Error::new(
+                            std::io::ErrorKind::TimedOut,
+                            format!("{}: no data for {}s", TIMEOUT_ERROR_PREFIX, limit.as_secs()),
+                        ));
+                        break;
+                    }
+                },
+                None => stream.next().await,
+            };
+            match next {
+                Some(item) => yield item.map_err(std::io::Error::other),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunked(delays_ms: &'static [u64]) -> reqwest::Response {
+        let stream = async_stream::stream! {
+            for (i, delay) in delays_ms.iter().enumerate() {
+                tokio::time::sleep(Duration::from_millis(*delay)).await;
+                yield Ok::<_, std::io::Error>(Bytes::from(format!("data: {}\n\n", i)));
+            }
+        };
+        let response = axum::http::Response::builder()
+            .status(200)
+            .header("content-type", "text/event-stream")
+            .body(reqwest::Body::wrap_stream(stream))
+            .unwrap();
+        reqwest::Response::from(response)
+    }
+
+    #[tokio::test]
+    async fn test_guard_stream_timeouts() {
+        let ms = Duration::from_millis;
+
+        let response = guard_stream(chunked(&[200]), Some(ms(50)), None).await;
+        assert!(response.is_err());
+
+        let response = guard_stream(chunked(&[0, 10, 10]), Some(ms(50)), Some(ms(50))).await.unwrap();
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        assert_eq!(response.bytes().await.unwrap(), "data: 0\n\ndata: 1\n\ndata: 2\n\n");
+
+        let response = guard_stream(chunked(&[0, 200]), None, Some(ms(50))).await.unwrap();
+        let chunks: Vec<_> = response.bytes_stream().collect().await;
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].is_ok());
+        assert!(chunks[1].is_err());
+    }
+
+    #[test]
+    fn test_timeout_error_prefix() {
+        let error = timeout_error("https://example.com/v1internal", "operation timed out");
+        assert!(is_timeout_error(&error));
+        assert!(!is_timeout_error("HTTP request failed at https://example.com: refused"));
+    }
+}
@@ -0,0 +1,33 @@
+// CSV 输出工具（RFC 4180 转义）
+
+/// 按需给字段加引号：包含逗号、引号或换行时整体加引号，内部引号双写
+pub fn escape_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// 追加一行（含换行符）
+pub fn push_row<S: AsRef<str>>(out: &mut String, fields: &[S]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(&escape_field(field.as_ref()));
+    }
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_row_escapes() {
+        let mut out = String::new();
+        push_row(&mut out, &["a", "b,c", "say \"hi\"", "line\nbreak"]);
+        assert_eq!(out, "a,\"b,c\",\"say \"\"hi\"\"\",\"line\nbreak\"\n");
+    }
+}
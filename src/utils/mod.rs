@@ -1 +1,2 @@
+pub mod csv;
 pub mod http;
@@ -17,6 +17,12 @@ const state = {
   // API Keys state
   apiKeys: [],
   apiKeysTotalUsage: null,
+  // Web auth state
+  authMode: null,
+  passkeys: [],
+  sessions: [],
+  adminTokens: [],
+  totpEnabled: false,
   // Theme state
   theme: "system", // "light", "dark", or "system"
 };
@@ -171,10 +177,14 @@ const elements = {
   accountsList: document.getElementById("accountsList"),
   refreshAllBtn: document.getElementById("refreshAllBtn"),
   refreshAllAccountsBtn: document.getElementById("refreshAllAccountsBtn"),
+  exportAccountsBtn: document.getElementById("exportAccountsBtn"),
+  importAccountsBtn: document.getElementById("importAccountsBtn"),
+  importAccountsFile: document.getElementById("importAccountsFile"),
   refreshTokenInput: document.getElementById("refreshTokenInput"),
   addAccountBtn: document.getElementById("addAccountBtn"),
   toast: document.getElementById("toast"),
   oauthStartBtn: document.getElementById("oauthStartBtn"),
+  oauthDeviceBtn: document.getElementById("oauthDeviceBtn"),
   oauthLinkBox: document.getElementById("oauthLinkBox"),
   oauthLinkText: document.getElementById("oauthLinkText"),
   oauthOpenBtn: document.getElementById("oauthOpenBtn"),
@@ -199,6 +209,23 @@ const elements = {
   apiKeysList: document.getElementById("apiKeysList"),
   apiKeysUsageSummary: document.getElementById("apiKeysUsageSummary"),
   // Settings elements
+  passkeysPanel: document.getElementById("passkeysPanel"),
+  passkeyList: document.getElementById("passkeyList"),
+  addPasskeyBtn: document.getElementById("addPasskeyBtn"),
+  sessionsPanel: document.getElementById("sessionsPanel"),
+  sessionList: document.getElementById("sessionList"),
+  revokeOtherSessionsBtn: document.getElementById("revokeOtherSessionsBtn"),
+  adminTokensPanel: document.getElementById("adminTokensPanel"),
+  adminTokenList: document.getElementById("adminTokenList"),
+  createAdminTokenBtn: document.getElementById("createAdminTokenBtn"),
+  totpPanel: document.getElementById("totpPanel"),
+  totpStatusText: document.getElementById("totpStatusText"),
+  totpToggleBtn: document.getElementById("totpToggleBtn"),
+  totpEnrollBox: document.getElementById("totpEnrollBox"),
+  totpQr: document.getElementById("totpQr"),
+  totpSecret: document.getElementById("totpSecret"),
+  totpConfirmCode: document.getElementById("totpConfirmCode"),
+  totpConfirmBtn: document.getElementById("totpConfirmBtn"),
   resetAuthBtn: document.getElementById("resetAuthBtn"),
 };
 
@@ -227,8 +254,8 @@ function showToast(message) {
 }
 
 function getBaseUrl() {
-  const { protocol, hostname, port } = window.location;
-  return `${protocol}//${hostname}${port ? `:${port}` : ""}`;
+  // Directory of the page, which includes the base_path prefix if one is configured
+  return new URL(".", window.location.href).href.replace(/\/$/, "");
 }
 
 function getOpenAiBaseUrl() {
@@ -281,6 +308,9 @@ function updateOAuthUI(status, message, authUrl) {
     elements.oauthStartBtn.disabled = isBusy;
     elements.oauthStartBtn.textContent = isBusy ? "Waiting for OAuth..." : "Start OAuth Login";
   }
+  if (elements.oauthDeviceBtn) {
+    elements.oauthDeviceBtn.disabled = isBusy;
+  }
 
   if (elements.oauthLinkBox) {
     if (state.oauth.authUrl && !isSuccess) {
@@ -363,6 +393,24 @@ async function startOAuthLogin() {
   }
 }
 
+async function startDeviceLogin() {
+  updateOAuthUI("loading", "Requesting device code...");
+  try {
+    const data = await apiFetch("/api/oauth/device", { method: "POST" });
+    if (!data || !data.user_code) {
+      throw new Error("Device code missing");
+    }
+    updateOAuthUI(
+      "waiting",
+      `Enter code ${data.user_code} at ${data.verification_url}`,
+      data.verification_url
+    );
+    startOAuthPolling();
+  } catch (err) {
+    updateOAuthUI("error", err.message || "Device authorization failed");
+  }
+}
+
 async function submitOAuthCallback() {
   if (!elements.oauthCallbackInput) return;
   const callbackUrl = elements.oauthCallbackInput.value.trim();
@@ -395,14 +443,15 @@ async function apiFetch(path, options = {}) {
     headers["Content-Type"] = "application/json";
   }
 
-  const response = await fetch(path, {
+  // Relative to the page, so the UI also works under a base_path
+  const response = await fetch(path.replace(/^\//, ""), {
     ...options,
     headers,
   });
 
   // Handle 401 Unauthorized - redirect to login
   if (response.status === 401) {
-    window.location.href = "/login.html";
+    window.location.href = "login.html";
     throw new Error("Authentication required");
   }
 
@@ -722,6 +771,8 @@ function renderAccounts() {
           <div class="table-cell table-actions">
             <button class="secondary small" data-action="set-current" data-id="${escapeHtml(account.id)}">Set</button>
             <button class="ghost small" data-action="refresh-quota" data-id="${escapeHtml(account.id)}">Refresh</button>
+            ${account.token && !account.token.project_id ? `<button class="secondary small" data-action="refresh-project" data-id="${escapeHtml(account.id)}" title="Discover the Cloud Code project ID">Project</button>` : ""}
+            ${account.disabled ? `<button class="primary small" data-action="reauth" data-id="${escapeHtml(account.id)}" title="${escapeHtml(account.disabled_reason || "")}">Re-auth</button>` : ""}
             <button class="danger small" data-action="delete" data-id="${escapeHtml(account.id)}">Del</button>
           </div>
         </div>
@@ -1128,6 +1179,30 @@ async function handleDelete(accountId) {
   }
 }
 
+async function handleReauth(accountId) {
+  try {
+    const data = await apiFetch(`/api/accounts/${accountId}/reauth`, { method: "POST" });
+    if (!data || !data.auth_url) {
+      throw new Error("OAuth URL missing");
+    }
+    updateOAuthUI("waiting", `Sign in as ${data.email} to re-authorize the account`, data.auth_url);
+    openOAuthLink();
+    startOAuthPolling();
+  } catch (err) {
+    showToast(`Re-auth failed: ${err.message}`);
+  }
+}
+
+async function handleRefreshProject(accountId) {
+  try {
+    const data = await apiFetch(`/api/accounts/${accountId}/refresh-project`, { method: "POST" });
+    showToast(`Project: ${data.project_id}`);
+    await loadAccounts();
+  } catch (err) {
+    showToast(`Project discovery failed: ${err.message}`);
+  }
+}
+
 async function handleRefreshQuota(accountId) {
   try {
     await apiFetch(`/api/accounts/${accountId}/refresh_quota`, { method: "POST" });
@@ -1154,6 +1229,45 @@ async function handleRefreshAll() {
   }
 }
 
+async function handleExportAccounts() {
+  const passphrase = window.prompt("Passphrase to encrypt the export (at least 8 characters)", "");
+  if (!passphrase) return;
+  try {
+    const archive = await apiFetch("/api/accounts/export", {
+      method: "POST",
+      body: JSON.stringify({ passphrase }),
+    });
+    const blob = new Blob([JSON.stringify(archive, null, 2)], { type: "application/json" });
+    const link = document.createElement("a");
+    link.href = URL.createObjectURL(blob);
+    link.download = `antiproxy-accounts-${new Date().toISOString().slice(0, 10)}.json`;
+    link.click();
+    URL.revokeObjectURL(link.href);
+    showToast(`Exported ${archive.account_count} accounts`);
+  } catch (err) {
+    showToast(`Export failed: ${err.message}`);
+  }
+}
+
+async function handleImportAccounts(event) {
+  const file = event.target.files && event.target.files[0];
+  event.target.value = "";
+  if (!file) return;
+  const passphrase = window.prompt("Passphrase used for this export", "");
+  if (!passphrase) return;
+  try {
+    const archive = JSON.parse(await file.text());
+    const result = await apiFetch("/api/accounts/import", {
+      method: "POST",
+      body: JSON.stringify({ passphrase, archive }),
+    });
+    showToast(`Imported ${result.created} new, updated ${result.updated} accounts`);
+    await loadAccounts();
+  } catch (err) {
+    showToast(`Import failed: ${err.message}`);
+  }
+}
+
 // ========== API Keys Management ==========
 
 async function loadApiKeys() {
@@ -1486,7 +1600,7 @@ async function handleApiKeyAction(action, keyId) {
       break;
 
     case 'delete':
-      if (!window.confirm(`Delete API key "${key.name}"? This cannot be undone.`)) {
+      if (!window.confirm(`Delete API key "${key.name}"? It stops working immediately and can be restored until it is purged.`)) {
         return;
       }
       try {
@@ -1600,6 +1714,322 @@ function showConfirmModal(title, message, confirmText, onConfirm, isDanger = fal
   });
 }
 
+// ===== Passkey devices =====
+
+function base64UrlEncode(buffer) {
+  const bytes = new Uint8Array(buffer);
+  let str = "";
+  for (const byte of bytes) {
+    str += String.fromCharCode(byte);
+  }
+  return btoa(str).replace(/\+/g, "-").replace(/\//g, "_").replace(/=/g, "");
+}
+
+function base64UrlDecode(str) {
+  str = str.replace(/-/g, "+").replace(/_/g, "/");
+  while (str.length % 4) {
+    str += "=";
+  }
+  const binaryStr = atob(str);
+  const bytes = new Uint8Array(binaryStr.length);
+  for (let i = 0; i < binaryStr.length; i++) {
+    bytes[i] = binaryStr.charCodeAt(i);
+  }
+  return bytes.buffer;
+}
+
+async function loadPasskeys() {
+  if (!elements.passkeysPanel || state.authMode !== "passkey") return;
+  elements.passkeysPanel.hidden = false;
+  try {
+    const data = await apiFetch("/api/auth/passkeys");
+    state.passkeys = data.passkeys || [];
+    renderPasskeys();
+  } catch (err) {
+    console.error("Failed to load passkeys:", err);
+  }
+}
+
+function renderPasskeys() {
+  if (!elements.passkeyList) return;
+  const canRevoke = state.passkeys.length > 1;
+  elements.passkeyList.innerHTML = state.passkeys.map(pk => `
+    <div class="passkey-item">
+      <div class="passkey-info">
+        <strong>${escapeHtml(pk.name)}${pk.current ? ' <span class="muted">(this session)</span>' : ""}</strong>
+        <p>Added ${formatTimestamp(pk.created_at)} · Last used ${formatTimestamp(pk.last_used_at)}</p>
+      </div>
+      <div class="passkey-actions">
+        <button class="ghost" data-passkey-action="rename" data-passkey-id="${escapeHtml(pk.id)}" type="button">Rename</button>
+        <button class="ghost danger" data-passkey-action="revoke" data-passkey-id="${escapeHtml(pk.id)}" type="button" ${canRevoke ? "" : "disabled title=\"Cannot revoke the last passkey\""}>Revoke</button>
+      </div>
+    </div>
+  `).join("");
+}
+
+async function handleAddPasskey() {
+  const name = window.prompt("Device name (e.g. YubiKey, MacBook Pro)", "");
+  if (name === null) return;
+  const deviceName = name.trim() || "New Device";
+
+  try {
+    const { challenge, options } = await apiFetch("/api/auth/register/start", {
+      method: "POST",
+      body: JSON.stringify({ name: deviceName }),
+    });
+
+    options.publicKey.challenge = base64UrlDecode(options.publicKey.challenge);
+    options.publicKey.user.id = base64UrlDecode(options.publicKey.user.id);
+    if (options.publicKey.excludeCredentials) {
+      options.publicKey.excludeCredentials = options.publicKey.excludeCredentials.map(cred => ({
+        ...cred,
+        id: base64UrlDecode(cred.id),
+      }));
+    }
+
+    const credential = await navigator.credentials.create({ publicKey: options.publicKey });
+
+    await apiFetch("/api/auth/register/finish", {
+      method: "POST",
+      body: JSON.stringify({
+        challenge,
+        name: deviceName,
+        response: {
+          id: credential.id,
+          rawId: base64UrlEncode(credential.rawId),
+          type: credential.type,
+          response: {
+            clientDataJSON: base64UrlEncode(credential.response.clientDataJSON),
+            attestationObject: base64UrlEncode(credential.response.attestationObject),
+          },
+        },
+      }),
+    });
+    showToast("Passkey added");
+    await loadPasskeys();
+  } catch (err) {
+    showToast(`Failed to add passkey: ${err.message}`);
+  }
+}
+
+async function handlePasskeyAction(action, passkeyId) {
+  const passkey = state.passkeys.find(pk => pk.id === passkeyId);
+  if (!passkey) return;
+
+  switch (action) {
+    case "rename": {
+      const name = window.prompt("New device name", passkey.name);
+      if (name === null || !name.trim()) return;
+      try {
+        await apiFetch(`/api/auth/passkeys/${encodeURIComponent(passkeyId)}`, {
+          method: "PATCH",
+          body: JSON.stringify({ name: name.trim() }),
+        });
+        await loadPasskeys();
+      } catch (err) {
+        showToast(`Failed: ${err.message}`);
+      }
+      break;
+    }
+
+    case "revoke":
+      if (!window.confirm(`Revoke passkey "${passkey.name}"? Sessions signed in with it will be signed out.`)) {
+        return;
+      }
+      try {
+        await apiFetch(`/api/auth/passkeys/${encodeURIComponent(passkeyId)}`, { method: "DELETE" });
+        showToast("Passkey revoked");
+        if (passkey.current) {
+          window.location.href = "login.html";
+          return;
+        }
+        await loadPasskeys();
+      } catch (err) {
+        showToast(`Failed: ${err.message}`);
+      }
+      break;
+  }
+}
+
+// ===== Sessions =====
+
+async function loadSessions() {
+  if (!elements.sessionsPanel) return;
+  try {
+    const data = await apiFetch("/api/auth/sessions");
+    state.sessions = data.sessions || [];
+    elements.sessionsPanel.hidden = false;
+    renderSessions();
+  } catch (err) {
+    console.error("Failed to load sessions:", err);
+  }
+}
+
+function renderSessions() {
+  if (!elements.sessionList) return;
+  elements.sessionList.innerHTML = state.sessions.map(session => `
+    <div class="passkey-item">
+      <div class="passkey-info">
+        <strong>${escapeHtml(session.user_agent || "Unknown browser")}${session.current ? ' <span class="muted">(this session)</span>' : ""}</strong>
+        <p>${escapeHtml(session.actor)} · ${escapeHtml(session.ip || "unknown IP")} · Signed in ${formatTimestamp(session.created_at)} · Last seen ${formatTimestamp(session.last_seen_at)}</p>
+      </div>
+      <div class="passkey-actions">
+        <button class="ghost danger" data-session-id="${escapeHtml(session.id)}" type="button">Revoke</button>
+      </div>
+    </div>
+  `).join("");
+}
+
+async function handleRevokeSession(sessionId) {
+  const session = state.sessions.find(s => s.id === sessionId);
+  if (!session) return;
+  const prompt = session.current ? "Revoke this session? You will be signed out." : "Revoke this session?";
+  if (!window.confirm(prompt)) return;
+  try {
+    await apiFetch(`/api/auth/sessions/${encodeURIComponent(sessionId)}`, { method: "DELETE" });
+    if (session.current) {
+      window.location.href = "login.html";
+      return;
+    }
+    showToast("Session revoked");
+    await loadSessions();
+  } catch (err) {
+    showToast(`Failed: ${err.message}`);
+  }
+}
+
+async function handleRevokeOtherSessions() {
+  if (!window.confirm("Sign out every other session? This session stays signed in.")) return;
+  try {
+    const data = await apiFetch("/api/auth/sessions", { method: "DELETE" });
+    showToast(`Signed out ${data.revoked_sessions} session(s)`);
+    await loadSessions();
+  } catch (err) {
+    showToast(`Failed: ${err.message}`);
+  }
+}
+
+// ===== Admin API tokens =====
+
+async function loadAdminTokens() {
+  if (!elements.adminTokensPanel) return;
+  try {
+    // Owner only; the panel stays hidden for other roles
+    const data = await apiFetch("/api/admins");
+    state.adminTokens = data.tokens || [];
+    elements.adminTokensPanel.hidden = false;
+    renderAdminTokens();
+  } catch (err) {
+    console.error("Failed to load admin tokens:", err);
+  }
+}
+
+function renderAdminTokens() {
+  if (!elements.adminTokenList) return;
+  if (!state.adminTokens.length) {
+    elements.adminTokenList.innerHTML = '<p class="muted">No tokens yet.</p>';
+    return;
+  }
+  elements.adminTokenList.innerHTML = state.adminTokens.map(token => `
+    <div class="passkey-item">
+      <div class="passkey-info">
+        <strong>${escapeHtml(token.name)} <span class="muted">(${escapeHtml(token.role)})</span></strong>
+        <p>Created ${formatTimestamp(token.created_at)}</p>
+      </div>
+      <div class="passkey-actions">
+        <button class="ghost danger" data-admin-token-id="${escapeHtml(token.id)}" type="button">Revoke</button>
+      </div>
+    </div>
+  `).join("");
+}
+
+async function handleCreateAdminToken() {
+  const name = window.prompt("Token name (e.g. terraform, backup-script)", "");
+  if (name === null || !name.trim()) return;
+  const role = window.prompt("Role: viewer, operator or owner", "operator");
+  if (role === null) return;
+
+  try {
+    const created = await apiFetch("/api/admins/tokens", {
+      method: "POST",
+      body: JSON.stringify({ name: name.trim(), role: role.trim().toLowerCase() }),
+    });
+    window.prompt("Copy the token now. It will not be shown again.", created.token);
+    await loadAdminTokens();
+  } catch (err) {
+    showToast(`Failed to create token: ${err.message}`);
+  }
+}
+
+async function handleRevokeAdminToken(tokenId) {
+  const token = state.adminTokens.find(t => t.id === tokenId);
+  if (!token || !window.confirm(`Revoke token "${token.name}"? Scripts using it will stop working.`)) return;
+  try {
+    await apiFetch(`/api/admins/tokens/${encodeURIComponent(tokenId)}`, { method: "DELETE" });
+    showToast("Token revoked");
+    await loadAdminTokens();
+  } catch (err) {
+    showToast(`Failed: ${err.message}`);
+  }
+}
+
+// ===== TOTP =====
+
+function renderTotpPanel() {
+  if (!elements.totpPanel || !state.authMode || state.authMode === "none") return;
+  elements.totpPanel.hidden = false;
+  elements.totpEnrollBox.hidden = true;
+  const fallbackText = state.authMode === "passkey"
+    ? "Sign in with a code when passkeys are unavailable."
+    : "Require a code in addition to the password.";
+  elements.totpStatusText.textContent = state.totpEnabled
+    ? `Enabled. ${fallbackText}`
+    : `Not enabled. ${fallbackText}`;
+  elements.totpToggleBtn.textContent = state.totpEnabled ? "Disable" : "Enable";
+  elements.totpToggleBtn.className = state.totpEnabled ? "ghost danger" : "primary";
+}
+
+async function handleTotpToggle() {
+  if (state.totpEnabled) {
+    if (!window.confirm("Disable authenticator app login?")) return;
+    try {
+      await apiFetch("/api/auth/totp", { method: "DELETE" });
+      state.totpEnabled = false;
+      showToast("Authenticator app disabled");
+      renderTotpPanel();
+    } catch (err) {
+      showToast(`Failed: ${err.message}`);
+    }
+    return;
+  }
+
+  try {
+    const enrollment = await apiFetch("/api/auth/totp/enroll", { method: "POST" });
+    elements.totpQr.innerHTML = enrollment.qr_svg;
+    elements.totpSecret.textContent = enrollment.secret;
+    elements.totpConfirmCode.value = "";
+    elements.totpEnrollBox.hidden = false;
+  } catch (err) {
+    showToast(`Failed: ${err.message}`);
+  }
+}
+
+async function handleTotpConfirm() {
+  const code = elements.totpConfirmCode.value.trim();
+  if (!code) return;
+  try {
+    await apiFetch("/api/auth/totp/confirm", {
+      method: "POST",
+      body: JSON.stringify({ code }),
+    });
+    state.totpEnabled = true;
+    showToast("Authenticator app enabled");
+    renderTotpPanel();
+  } catch (err) {
+    showToast(`Failed: ${err.message}`);
+  }
+}
+
 async function handleResetAuth() {
   showConfirmModal(
     "Reset Authentication",
@@ -1617,7 +2047,7 @@ async function handleResetAuth() {
             showToast("Authentication reset successfully");
             // Redirect to login page after reset
             setTimeout(() => {
-              window.location.href = "/login.html";
+              window.location.href = "login.html";
             }, 1000);
           } catch (err) {
             showToast(`Reset failed: ${err.message}`);
@@ -1635,10 +2065,20 @@ function bindEvents() {
   if (elements.refreshAllAccountsBtn) {
     elements.refreshAllAccountsBtn.addEventListener("click", handleRefreshAll);
   }
+  if (elements.exportAccountsBtn) {
+    elements.exportAccountsBtn.addEventListener("click", handleExportAccounts);
+  }
+  if (elements.importAccountsBtn && elements.importAccountsFile) {
+    elements.importAccountsBtn.addEventListener("click", () => elements.importAccountsFile.click());
+    elements.importAccountsFile.addEventListener("change", handleImportAccounts);
+  }
   elements.addAccountBtn.addEventListener("click", handleAddAccount);
   if (elements.oauthStartBtn) {
     elements.oauthStartBtn.addEventListener("click", startOAuthLogin);
   }
+  if (elements.oauthDeviceBtn) {
+    elements.oauthDeviceBtn.addEventListener("click", startDeviceLogin);
+  }
   if (elements.oauthOpenBtn) {
     elements.oauthOpenBtn.addEventListener("click", openOAuthLink);
   }
@@ -1682,6 +2122,45 @@ function bindEvents() {
   if (elements.resetAuthBtn) {
     elements.resetAuthBtn.addEventListener("click", handleResetAuth);
   }
+  if (elements.totpToggleBtn) {
+    elements.totpToggleBtn.addEventListener("click", handleTotpToggle);
+  }
+  if (elements.totpConfirmBtn) {
+    elements.totpConfirmBtn.addEventListener("click", handleTotpConfirm);
+  }
+  if (elements.addPasskeyBtn) {
+    elements.addPasskeyBtn.addEventListener("click", handleAddPasskey);
+  }
+  if (elements.createAdminTokenBtn) {
+    elements.createAdminTokenBtn.addEventListener("click", handleCreateAdminToken);
+  }
+  if (elements.adminTokenList) {
+    elements.adminTokenList.addEventListener("click", (event) => {
+      const button = event.target.closest("[data-admin-token-id]");
+      if (button) {
+        handleRevokeAdminToken(button.dataset.adminTokenId);
+      }
+    });
+  }
+  if (elements.sessionList) {
+    elements.sessionList.addEventListener("click", (event) => {
+      const button = event.target.closest("[data-session-id]");
+      if (button) {
+        handleRevokeSession(button.dataset.sessionId);
+      }
+    });
+  }
+  if (elements.revokeOtherSessionsBtn) {
+    elements.revokeOtherSessionsBtn.addEventListener("click", handleRevokeOtherSessions);
+  }
+  if (elements.passkeyList) {
+    elements.passkeyList.addEventListener("click", (event) => {
+      const button = event.target.closest("[data-passkey-action]");
+      if (button) {
+        handlePasskeyAction(button.dataset.passkeyAction, button.dataset.passkeyId);
+      }
+    });
+  }
 
   document.addEventListener("click", (event) => {
     const target = event.target.closest("button[data-action]");
@@ -1696,6 +2175,10 @@ function bindEvents() {
       handleRefreshQuota(accountId);
     } else if (action === "delete") {
       handleDelete(accountId);
+    } else if (action === "reauth") {
+      handleReauth(accountId);
+    } else if (action === "refresh-project") {
+      handleRefreshProject(accountId);
     }
   });
 
@@ -1788,20 +2271,22 @@ function bindEvents() {
 // Check authentication status on page load
 async function checkAuthStatus() {
   try {
-    const response = await fetch("/api/auth/status");
+    const response = await fetch("api/auth/status");
     if (!response.ok) {
-      window.location.href = "/login.html";
+      window.location.href = "login.html";
       return false;
     }
     const data = await response.json();
     if (!data.authenticated) {
-      window.location.href = "/login.html";
+      window.location.href = "login.html";
       return false;
     }
+    state.authMode = data.auth_mode;
+    state.totpEnabled = Boolean(data.totp_enabled);
     return true;
   } catch (err) {
     console.error("Failed to check auth status:", err);
-    window.location.href = "/login.html";
+    window.location.href = "login.html";
     return false;
   }
 }
@@ -1824,5 +2309,9 @@ initTheme();
   loadMappings();
   loadModels();
   loadApiKeys();
+  loadPasskeys();
+  loadSessions();
+  loadAdminTokens();
+  renderTotpPanel();
   fetchOAuthStatus();
 })();